@@ -0,0 +1,41 @@
+-- Bank transfers written as PL/pgSQL stored procedures.
+-- Import with:
+--   FMitF_rs examples/sql/bank.sql --table-node accounts=Bank --table-node audit_log=Audit
+
+CREATE TABLE accounts (
+    id INTEGER PRIMARY KEY,
+    balance INTEGER NOT NULL,
+    frozen BOOLEAN
+);
+
+CREATE TABLE audit_log (
+    account_id INTEGER,
+    seq INTEGER,
+    amount INTEGER,
+    note VARCHAR(64),
+    PRIMARY KEY (account_id, seq)
+);
+
+CREATE OR REPLACE FUNCTION deposit(p_id INT, p_amount INT, p_seq INT) RETURNS VOID AS $$
+BEGIN
+    UPDATE accounts SET balance = balance + p_amount WHERE id = p_id;
+    INSERT INTO audit_log (account_id, seq, amount, note)
+        VALUES (p_id, p_seq, p_amount, 'deposit');
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION withdraw(p_id INT, p_amount INT, p_seq INT) RETURNS INT AS $$
+DECLARE
+    v_balance INT;
+    v_frozen BOOLEAN := FALSE;
+BEGIN
+    SELECT balance, frozen INTO v_balance, v_frozen FROM accounts WHERE id = p_id;
+    IF v_frozen OR v_balance < p_amount THEN
+        RAISE EXCEPTION 'cannot withdraw from account %', p_id;
+    END IF;
+    UPDATE accounts SET balance = v_balance - p_amount WHERE id = p_id;
+    INSERT INTO audit_log (account_id, seq, amount, note)
+        VALUES (p_id, p_seq, -p_amount, 'withdraw');
+    RETURN v_balance - p_amount;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,30 @@
+/* The C interface of FMitF_rs, built and linked with
+ *
+ *     cargo build --release --features ffi
+ *     cc main.c -Iinclude -Ltarget/release -lFMitF_rs
+ *
+ * See src/ffi.rs and the "Embedding" section of the README.
+ */
+#ifndef FMITF_H
+#define FMITF_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Analyzes `len` bytes of UTF-8 TransAct source code. Returns its diagnostics
+ * and SC-graph as a NUL-terminated JSON string, to be released with
+ * fmitf_free, or NULL when `source` is NULL or the analyzer failed. */
+char *fmitf_analyze(const uint8_t *source, size_t len);
+
+/* Releases a string returned by fmitf_analyze; NULL is ignored. */
+void fmitf_free(char *json);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* FMITF_H */
@@ -0,0 +1,182 @@
+//! The `abort_placement` module checks where a function may abort.
+//!
+//! # Overview
+//!
+//! Each hop commits on its own node before the next one starts, so an `abort` can
+//! only roll back the hop it runs in. Which aborts are acceptable is a policy
+//! decision, chosen with `AbortPolicy`:
+//!
+//! - `FirstHop` (the default): an abort may only run before any other hop has
+//!   committed, which is the classic rule for chopped transactions.
+//! - `BeforeWrites`: an abort may also run in a later hop, as long as no earlier
+//!   hop can have written a table. Earlier hops that only read leave nothing to
+//!   roll back.
+//! - `Compensated`: aborts are allowed anywhere; the application is responsible
+//!   for compensating the writes of earlier hops.
+//!
+//! # Flow analysis
+//!
+//! Rather than looking at hop indices alone, the checker follows the control flow
+//! of the function. Hops run in order, and a hop is only entered if the previous
+//! one can fall through to its end. Within a hop, `return`, `abort`, `break` and
+//! `continue` end a path, and the paths through `if` and `while` are joined. An
+//! abort that no path reaches is never reported, and for `BeforeWrites` only the
+//! table writes on paths that reach the abort count.
+
+use crate::ast::*;
+
+/// Which hops of a function may contain a reachable `abort`.
+#[derive(clap::ValueEnum, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AbortPolicy {
+    /// Only the first hop may abort
+    #[default]
+    FirstHop,
+    /// Any hop may abort until an earlier hop may have written a table
+    BeforeWrites,
+    /// Any hop may abort; earlier hops are compensated by the application
+    Compensated,
+}
+
+/// The paths reaching a program point: `None` if there are none, otherwise a
+/// table write that may have happened on one of them.
+type Flow = Option<Option<Span>>;
+
+fn join(a: Flow, b: Flow) -> Flow {
+    match (a, b) {
+        (None, flow) | (flow, None) => flow,
+        (Some(a), Some(b)) => Some(a.or(b)),
+    }
+}
+
+pub struct AbortPlacementChecker<'p> {
+    program: &'p Program,
+    policy: AbortPolicy,
+    // Flows leaving the innermost loop through `break` and `continue`
+    breaks: Flow,
+    continues: Flow,
+    // Reachable aborts of the current hop
+    aborts: Vec<Span>,
+}
+
+impl<'p> AbortPlacementChecker<'p> {
+    /// Creates a new `AbortPlacementChecker` for the given program.
+    pub fn new(program: &'p Program, policy: AbortPolicy) -> Self {
+        Self {
+            program,
+            policy,
+            breaks: None,
+            continues: None,
+            aborts: Vec::new(),
+        }
+    }
+
+    /// Checks the aborts of a function and returns an error for each one the
+    /// policy does not allow.
+    pub fn check(mut self, func: &FunctionDeclaration) -> Vec<SpannedError> {
+        let mut errors = Vec::new();
+        // A write committed by an earlier hop, if one may have happened
+        let mut committed: Option<Span> = None;
+
+        for (hop_index, hop_id) in func.hops.iter().enumerate() {
+            self.aborts.clear();
+            let end = self.check_block(&self.program.hops[*hop_id].statements, Some(None));
+
+            for span in std::mem::take(&mut self.aborts) {
+                let error = match (self.policy, &committed) {
+                    (AbortPolicy::FirstHop, _) if hop_index > 0 => AstError::AbortNotInFirstHop {
+                        function: func.name.to_string(),
+                        hop_index,
+                    },
+                    (AbortPolicy::BeforeWrites, Some(write)) => AstError::AbortAfterWrites {
+                        function: func.name.to_string(),
+                        hop_index,
+                        write: write.clone(),
+                    },
+                    _ => continue,
+                };
+                errors.push(SpannedError {
+                    error,
+                    span: Some(span),
+                });
+            }
+
+            // Later hops only run if this one can fall through
+            match end {
+                Some(write) => committed = committed.or(write),
+                None => break,
+            }
+        }
+        errors
+    }
+
+    /// Follows the paths through a list of statements, starting with `flow`.
+    fn check_block(&mut self, statements: &[StatementId], mut flow: Flow) -> Flow {
+        for stmt_id in statements {
+            flow = self.check_statement(*stmt_id, flow);
+        }
+        flow
+    }
+
+    fn check_statement(&mut self, stmt_id: StatementId, flow: Flow) -> Flow {
+        let stmt = &self.program.statements[stmt_id];
+        // Nothing after an exit runs
+        let write = flow?;
+
+        match &stmt.node {
+            StatementKind::Assignment(_) | StatementKind::MultiAssignment(_) => {
+                Some(write.or_else(|| Some(stmt.span.clone())))
+            }
+            StatementKind::IfStmt(if_stmt) => {
+                let then_flow = self.check_block(&if_stmt.then_branch, Some(write.clone()));
+                let else_flow = match &if_stmt.else_branch {
+                    Some(else_branch) => self.check_block(else_branch, Some(write)),
+                    None => Some(write),
+                };
+                join(then_flow, else_flow)
+            }
+            StatementKind::WhileStmt(while_stmt) => {
+                let breaks = self.breaks.take();
+                let continues = self.continues.take();
+
+                // Iterate to a fixed point; a write seen once stays seen, so the
+                // second pass only adds writes from the end of the body
+                let mut head = Some(write);
+                for _ in 0..2 {
+                    let end = self.check_block(&while_stmt.body, head.clone());
+                    let back = join(end, self.continues.take());
+                    head = join(head, back);
+                }
+                let endless = matches!(
+                    self.program.expressions[while_stmt.condition].node,
+                    ExpressionKind::BoolLit(true)
+                );
+                let exit = if endless { None } else { head };
+                let exit = join(exit, self.breaks.take());
+
+                self.breaks = breaks;
+                self.continues = continues;
+                exit
+            }
+            StatementKind::Break(_) => {
+                self.breaks = join(self.breaks.take(), Some(write));
+                None
+            }
+            StatementKind::Continue(_) => {
+                self.continues = join(self.continues.take(), Some(write));
+                None
+            }
+            StatementKind::Abort(_) => {
+                // Loop bodies are followed more than once
+                if !self.aborts.contains(&stmt.span) {
+                    self.aborts.push(stmt.span.clone());
+                }
+                None
+            }
+            StatementKind::Return(_) => None,
+            StatementKind::VarDecl(_) | StatementKind::VarAssignment(_) | StatementKind::Empty => {
+                Some(write)
+            }
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! The `arithmetic_lints` module warns about arithmetic that can fail at runtime.
+//!
+//! # Overview
+//!
+//! - **DivisionByZero**: the divisor is a literal zero.
+//! - **UnguardedDivision**: the divisor is not a non-zero literal and no enclosing
+//!   `if` or `while` condition, or earlier `if` without `else` that leaves the block,
+//!   shows it is non-zero.
+//! - **PossibleOverflow**: `+`, `-` or `*` on an `int` table field. The verifier models
+//!   `int` as an unbounded Boogie `int`, so it cannot see 64-bit overflow. This lint is
+//!   noisy and only shown by the CLI with `--warn-overflow`.
+//!
+//! A statement with a `// allow(division_by_zero)` or `// allow(overflow)` comment,
+//! on the line before it or at its end, suppresses the lint for itself and the
+//! statements nested in it.
+//!
+//! # Guards
+//!
+//! A condition shows `e` is non-zero when one of its `&&` operands compares `e` with a
+//! literal, as in `e != 0`, `e > 0` or `e < -1` (or the negation of such a condition, in
+//! an `else` branch). A guard holds until a variable or table field it reads is
+//! assigned, and a `while` condition only guards the loop body.
+
+use crate::ast::*;
+
+/// An expression known to be non-zero, with the variables and fields it reads.
+#[derive(Clone)]
+struct Guard {
+    expr: String,
+    vars: Vec<VarId>,
+    fields: Vec<(TableId, FieldId)>,
+}
+
+pub struct ArithmeticChecker<'p> {
+    program: &'p Program,
+    guards: Vec<Guard>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'p> ArithmeticChecker<'p> {
+    /// Creates a new `ArithmeticChecker` for the given program.
+    pub fn new(program: &'p Program) -> Self {
+        Self {
+            program,
+            guards: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Checks the arithmetic in a hop and returns a warning for each problem.
+    pub fn check(mut self, hop: &HopBlock) -> Vec<Diagnostic> {
+        self.check_block(&hop.statements);
+        self.warnings
+    }
+
+    /// Checks a list of statements, keeping the guards added by early exits local to it.
+    fn check_block(&mut self, statements: &[StatementId]) {
+        let guards = self.guards.clone();
+        for stmt_id in statements {
+            self.check_statement(*stmt_id);
+        }
+        self.guards = guards;
+    }
+
+    fn check_statement(&mut self, stmt_id: StatementId) {
+        let stmt = &self.program.statements[stmt_id];
+
+        match &stmt.node {
+            StatementKind::Assignment(assign) => {
+                self.check_expressions(&assign.pk_exprs);
+                self.check_expression(assign.rhs);
+            }
+            StatementKind::MultiAssignment(multi_assign) => {
+                self.check_expressions(&multi_assign.pk_exprs);
+                for pair in &multi_assign.assignments {
+                    self.check_expression(pair.rhs);
+                }
+            }
+            StatementKind::VarAssignment(var_assign) => self.check_expression(var_assign.rhs),
+            StatementKind::VarDecl(var_decl) => {
+                if let Some(init_value) = var_decl.init_value {
+                    self.check_expression(init_value);
+                }
+            }
+            StatementKind::IfStmt(if_stmt) => {
+                self.check_expression(if_stmt.condition);
+
+                let guards = self.guards.clone();
+                self.assume(if_stmt.condition, true);
+                self.check_block(&if_stmt.then_branch);
+                self.guards = guards.clone();
+                self.assume(if_stmt.condition, false);
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    self.check_block(else_branch);
+                }
+                self.guards = guards;
+                self.invalidate(stmt_id);
+
+                // After `if (d == 0) { return; }` the rest of the block knows `d != 0`
+                if if_stmt.else_branch.is_none() && self.leaves_block(&if_stmt.then_branch) {
+                    self.assume(if_stmt.condition, false);
+                }
+            }
+            StatementKind::WhileStmt(while_stmt) => {
+                // The body may change what the condition reads before it runs again
+                self.invalidate(stmt_id);
+                self.check_expression(while_stmt.condition);
+
+                let guards = self.guards.clone();
+                self.assume(while_stmt.condition, true);
+                self.check_block(&while_stmt.body);
+                self.guards = guards;
+            }
+            StatementKind::Return(ret_stmt) => {
+                if let Some(value) = ret_stmt.value {
+                    self.check_expression(value);
+                }
+            }
+            StatementKind::Abort(_)
+            | StatementKind::Break(_)
+            | StatementKind::Continue(_)
+            | StatementKind::Empty => {}
+        }
+
+        // Assignments end the guards that read the assigned variable or field
+        match &stmt.node {
+            StatementKind::Assignment(_)
+            | StatementKind::MultiAssignment(_)
+            | StatementKind::VarAssignment(_) => self.invalidate(stmt_id),
+            _ => {}
+        }
+    }
+
+    fn check_expressions(&mut self, expr_ids: &[ExpressionId]) {
+        for expr_id in expr_ids {
+            self.check_expression(*expr_id);
+        }
+    }
+
+    fn check_expression(&mut self, expr_id: ExpressionId) {
+        let expr = &self.program.expressions[expr_id];
+
+        match &expr.node {
+            ExpressionKind::TableFieldAccess { pk_exprs, .. } => self.check_expressions(pk_exprs),
+            ExpressionKind::UnaryOp { expr, .. } => self.check_expression(*expr),
+            ExpressionKind::BinaryOp {
+                left, op, right, ..
+            } => {
+                self.check_expression(*left);
+                self.check_expression(*right);
+                match op {
+                    BinaryOp::Div => self.check_divisor(*right),
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => {
+                        self.check_overflow([*left, *right], &expr.span)
+                    }
+                    _ => {}
+                }
+            }
+            ExpressionKind::Ident(_)
+            | ExpressionKind::IntLit(_)
+            | ExpressionKind::FloatLit(_)
+            | ExpressionKind::StringLit(_)
+            | ExpressionKind::BoolLit(_) => {}
+        }
+    }
+
+    fn check_divisor(&mut self, divisor: ExpressionId) {
+        let span = self.program.expressions[divisor].span.clone();
+        let warning = match self.literal(divisor) {
+            Some(0.0) => Diagnostic::warning(AstError::DivisionByZero, Some(span)),
+            Some(_) => return,
+            None => {
+                let guarded = self
+                    .canonical(divisor)
+                    .is_some_and(|divisor| self.guards.iter().any(|g| g.expr == divisor.expr));
+                if guarded {
+                    return;
+                }
+                Diagnostic::warning(AstError::UnguardedDivision, Some(span)).with_note(
+                    "Check the divisor first, or add `// allow(division_by_zero)` to the statement",
+                    None,
+                )
+            }
+        };
+        self.warnings.push(warning);
+    }
+
+    /// Warns once if an operand is an `int` table field.
+    fn check_overflow(&mut self, operands: [ExpressionId; 2], span: &Span) {
+        let int_field =
+            operands
+                .iter()
+                .find_map(|operand| match &self.program.expressions[*operand].node {
+                    ExpressionKind::TableFieldAccess {
+                        resolved_table: Some(table),
+                        resolved_field: Some(field),
+                        ..
+                    } if self.program.fields[*field].field_type == TypeName::Int => {
+                        Some((*table, *field))
+                    }
+                    _ => None,
+                });
+        let Some((table, field)) = int_field else {
+            return;
+        };
+
+        let error = AstError::PossibleOverflow {
+            table: self.program.tables[table].name.to_string(),
+            field: self.program.fields[field].field_name.to_string(),
+        };
+        self.warnings
+            .push(Diagnostic::warning(error, Some(span.clone())).with_note(
+            "The verifier models int as unbounded; add `// allow(overflow)` if this is intended",
+            None,
+        ));
+    }
+
+    /// Adds the guards that hold when `condition` evaluates to `holds`.
+    fn assume(&mut self, condition: ExpressionId, holds: bool) {
+        match &self.program.expressions[condition].node {
+            ExpressionKind::UnaryOp {
+                op: UnaryOp::Not,
+                expr,
+                ..
+            } => self.assume(*expr, !holds),
+            ExpressionKind::BinaryOp {
+                left, op, right, ..
+            } => match (op, holds) {
+                (BinaryOp::And, true) | (BinaryOp::Or, false) => {
+                    self.assume(*left, holds);
+                    self.assume(*right, holds);
+                }
+                (BinaryOp::And | BinaryOp::Or, _) => {}
+                _ => {
+                    let op = if holds { op.clone() } else { negated(op) };
+                    let (left, right) = (*left, *right);
+                    let guard = match (self.literal(left), self.literal(right)) {
+                        (None, Some(value)) if excludes_zero(&op, value) => self.canonical(left),
+                        (Some(value), None) if excludes_zero(&mirrored(&op), value) => {
+                            self.canonical(right)
+                        }
+                        _ => None,
+                    };
+                    self.guards.extend(guard);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Drops the guards that read a variable or field the statement assigns.
+    fn invalidate(&mut self, stmt_id: StatementId) {
+        match &self.program.statements[stmt_id].node {
+            StatementKind::Assignment(assign) => {
+                if let (Some(table), Some(field)) = (assign.resolved_table, assign.resolved_field) {
+                    self.guards.retain(|g| !g.fields.contains(&(table, field)));
+                }
+            }
+            StatementKind::MultiAssignment(multi_assign) => {
+                if let Some(table) = multi_assign.resolved_table {
+                    for pair in &multi_assign.assignments {
+                        if let Some(field) = pair.resolved_field {
+                            self.guards.retain(|g| !g.fields.contains(&(table, field)));
+                        }
+                    }
+                }
+            }
+            StatementKind::VarAssignment(var_assign) => {
+                if let Some(var_id) = var_assign.resolved_var {
+                    self.guards.retain(|g| !g.vars.contains(&var_id));
+                }
+            }
+            StatementKind::IfStmt(if_stmt) => {
+                let branches = if_stmt.else_branch.iter().flatten();
+                for stmt_id in if_stmt.then_branch.iter().chain(branches) {
+                    self.invalidate(*stmt_id);
+                }
+            }
+            StatementKind::WhileStmt(while_stmt) => {
+                for stmt_id in &while_stmt.body {
+                    self.invalidate(*stmt_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether a block always leaves the enclosing block through its last statement.
+    fn leaves_block(&self, statements: &[StatementId]) -> bool {
+        statements.last().is_some_and(|stmt_id| {
+            matches!(
+                self.program.statements[*stmt_id].node,
+                StatementKind::Return(_)
+                    | StatementKind::Abort(_)
+                    | StatementKind::Break(_)
+                    | StatementKind::Continue(_)
+            )
+        })
+    }
+
+    /// The value of a numeric literal, possibly negated.
+    fn literal(&self, expr_id: ExpressionId) -> Option<f64> {
+        match &self.program.expressions[expr_id].node {
+            ExpressionKind::IntLit(value) => Some(*value as f64),
+            ExpressionKind::FloatLit(value) => Some(*value),
+            ExpressionKind::UnaryOp {
+                op: UnaryOp::Neg,
+                expr,
+                ..
+            } => self.literal(*expr).map(|value| -value),
+            _ => None,
+        }
+    }
+
+    /// A canonical form of an expression, for comparing divisors with guards.
+    fn canonical(&self, expr_id: ExpressionId) -> Option<Guard> {
+        let mut guard = Guard {
+            expr: String::new(),
+            vars: Vec::new(),
+            fields: Vec::new(),
+        };
+        guard.expr = self.canonical_text(expr_id, &mut guard)?;
+        Some(guard)
+    }
+
+    fn canonical_text(&self, expr_id: ExpressionId, guard: &mut Guard) -> Option<String> {
+        match &self.program.expressions[expr_id].node {
+            ExpressionKind::Ident(_) => {
+                let var_id = *self.program.resolutions.get(&expr_id)?;
+                guard.vars.push(var_id);
+                Some(format!("v{}", var_id.index()))
+            }
+            ExpressionKind::IntLit(value) => Some(value.to_string()),
+            ExpressionKind::FloatLit(value) => Some(format!("{:?}", value)),
+            ExpressionKind::StringLit(value) => Some(format!("{:?}", value)),
+            ExpressionKind::BoolLit(value) => Some(value.to_string()),
+            ExpressionKind::TableFieldAccess {
+                pk_exprs,
+                resolved_table,
+                resolved_pk_fields,
+                resolved_field,
+                ..
+            } => {
+                let (table, field) = ((*resolved_table)?, (*resolved_field)?);
+                guard.fields.push((table, field));
+                let mut key = Vec::new();
+                for (pk_field, pk_expr) in resolved_pk_fields.iter().zip(pk_exprs) {
+                    let text = self.canonical_text(*pk_expr, guard)?;
+                    key.push(((*pk_field)?.index(), text));
+                }
+                key.sort();
+                Some(format!("t{}.f{}{:?}", table.index(), field.index(), key))
+            }
+            ExpressionKind::UnaryOp { op, expr, .. } => {
+                Some(format!("({:?} {})", op, self.canonical_text(*expr, guard)?))
+            }
+            ExpressionKind::BinaryOp {
+                left, op, right, ..
+            } => Some(format!(
+                "({} {:?} {})",
+                self.canonical_text(*left, guard)?,
+                op,
+                self.canonical_text(*right, guard)?
+            )),
+        }
+    }
+}
+
+/// Whether `e op value` rules out `e == 0`.
+fn excludes_zero(op: &BinaryOp, value: f64) -> bool {
+    match op {
+        BinaryOp::Neq => value == 0.0,
+        BinaryOp::Eq => value != 0.0,
+        BinaryOp::Gt => value >= 0.0,
+        BinaryOp::Gte => value > 0.0,
+        BinaryOp::Lt => value <= 0.0,
+        BinaryOp::Lte => value < 0.0,
+        _ => false,
+    }
+}
+
+/// The comparison that holds when `op` does not.
+fn negated(op: &BinaryOp) -> BinaryOp {
+    match op {
+        BinaryOp::Eq => BinaryOp::Neq,
+        BinaryOp::Neq => BinaryOp::Eq,
+        BinaryOp::Lt => BinaryOp::Gte,
+        BinaryOp::Lte => BinaryOp::Gt,
+        BinaryOp::Gt => BinaryOp::Lte,
+        BinaryOp::Gte => BinaryOp::Lt,
+        other => other.clone(),
+    }
+}
+
+/// The comparison with its operands swapped, so `0 < e` becomes `e > 0`.
+fn mirrored(op: &BinaryOp) -> BinaryOp {
+    match op {
+        BinaryOp::Lt => BinaryOp::Gt,
+        BinaryOp::Lte => BinaryOp::Gte,
+        BinaryOp::Gt => BinaryOp::Lt,
+        BinaryOp::Gte => BinaryOp::Lte,
+        other => other.clone(),
+    }
+}
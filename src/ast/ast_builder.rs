@@ -1,7 +1,8 @@
 use id_arena::Arena;
-use pest::iterators::Pair;
-use pest::Parser;
+use pest::iterators::{Pair, Pairs};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
 
 use crate::ast::errors::*;
 use crate::ast::*;
@@ -14,6 +15,26 @@ pub struct TransActParser;
 /// Builds arena-based AST from parsed Pest pairs.
 pub struct AstBuilder {
     program: Program,
+    current_file: Option<Arc<Path>>,
+    nodes_blocks: Vec<Span>,
+    /// Template parameter → argument while a template instance is being built.
+    template_args: HashMap<Symbol, TemplateArg>,
+}
+
+/// A template argument: an identifier or a literal, kept as its grammar rule and text.
+#[derive(Debug, Clone)]
+struct TemplateArg {
+    rule: Rule,
+    text: String,
+}
+
+/// A template declaration waiting to be expanded, with the file it was declared in.
+type PendingTemplate<'a> = (Option<Arc<Path>>, Pair<'a, Rule>);
+
+impl Default for Program {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Program {
@@ -37,6 +58,12 @@ impl Program {
             scopes: Arena::new(),
             resolutions: HashMap::new(),
             var_types: HashMap::new(),
+            source_files: Vec::new(),
+            trivia: TriviaMap::default(),
+            templates: Vec::new(),
+            template_instances: Vec::new(),
+            warnings: Vec::new(),
+            reparsed_elements: 0,
         }
     }
 }
@@ -46,42 +73,87 @@ impl AstBuilder {
     pub fn new() -> Self {
         Self {
             program: Program::new(),
+            current_file: None,
+            nodes_blocks: Vec::new(),
+            template_args: HashMap::new(),
         }
     }
 
-    /// Builds the program from a Pest program pair.
-    pub fn build_program(&mut self, pair: Pair<Rule>) -> Results<Program> {
+    /// Converts a Pest span into a Span tagged with the file being built.
+    fn span(&self, span: pest::Span) -> Span {
+        Span::from_pest_in(span, self.current_file.clone())
+    }
+
+    /// Builds one program from the program pairs of several files.
+    ///
+    /// Each pass runs over all files in order, so declarations of earlier files
+    /// (the imported ones) are visible to later ones.
+    fn build_program_from_pairs(
+        &mut self,
+        files: Vec<(Option<Arc<Path>>, Pair<Rule>)>,
+    ) -> Results<Program> {
         let mut errors = Vec::new();
 
         // First pass: collect nodes
-        for item in pair.clone().into_inner() {
-            if item.as_rule() == Rule::nodes_block {
-                if let Err(mut errs) = self.build_nodes_block(item) {
-                    errors.append(&mut errs);
+        for (file, pair) in &files {
+            self.current_file = file.clone();
+            for item in pair.clone().into_inner() {
+                if item.as_rule() == Rule::nodes_block {
+                    if let Err(mut errs) = self.build_nodes_block(item) {
+                        errors.append(&mut errs);
+                    }
                 }
             }
         }
 
         // Second pass: collect tables
-        for item in pair.clone().into_inner() {
-            if item.as_rule() == Rule::table_declaration {
-                if let Err(mut errs) = self.build_table_declaration(item) {
-                    errors.append(&mut errs);
+        for (file, pair) in &files {
+            self.current_file = file.clone();
+            for item in pair.clone().into_inner() {
+                if item.as_rule() == Rule::table_declaration {
+                    if let Err(mut errs) = self.build_table_declaration(item) {
+                        errors.append(&mut errs);
+                    }
                 }
             }
         }
 
-        // Third pass: parse functions
-        for item in pair.into_inner() {
-            if item.as_rule() == Rule::function_declaration {
-                if let Err(mut errs) = self.build_function_declaration(item) {
+        // Third pass: collect templates, so they can be instantiated from any file
+        let mut templates: HashMap<Symbol, PendingTemplate> = HashMap::new();
+        for (file, pair) in &files {
+            self.current_file = file.clone();
+            for item in pair.clone().into_inner() {
+                if item.as_rule() == Rule::template_declaration {
+                    match self.build_template_declaration(&item) {
+                        Ok(name) => {
+                            templates.insert(name, (file.clone(), item));
+                        }
+                        Err(mut errs) => errors.append(&mut errs),
+                    }
+                }
+            }
+        }
+
+        // Fourth pass: parse functions and expand template instances
+        for (file, pair) in files {
+            self.current_file = file;
+            for item in pair.into_inner() {
+                let result = match item.as_rule() {
+                    Rule::function_declaration => self.build_function_declaration(item).map(|_| ()),
+                    Rule::template_instantiation => {
+                        self.build_template_instantiation(item, &templates)
+                    }
+                    _ => Ok(()),
+                };
+                if let Err(mut errs) = result {
                     errors.append(&mut errs);
                 }
             }
         }
+        self.current_file = None;
 
         if errors.is_empty() {
-            Ok(std::mem::replace(&mut self.program, Program::new()))
+            Ok(std::mem::take(&mut self.program))
         } else {
             Err(errors)
         }
@@ -89,6 +161,8 @@ impl AstBuilder {
 
     /// Builds nodes block from a Pest pair.
     fn build_nodes_block(&mut self, pair: Pair<Rule>) -> Results<()> {
+        let span = self.span(pair.as_span());
+        self.nodes_blocks.push(span);
         for item in pair.into_inner() {
             if item.as_rule() == Rule::node_list {
                 self.build_node_list(item)?;
@@ -101,13 +175,22 @@ impl AstBuilder {
     fn build_node_list(&mut self, pair: Pair<Rule>) -> Results<()> {
         for node_pair in pair.into_inner() {
             if node_pair.as_rule() == Rule::identifier {
-                let name = node_pair.as_str().to_string();
-                let span = Span::from_pest(node_pair.as_span());
+                let name = Symbol::intern(node_pair.as_str());
+                let span = self.span(node_pair.as_span());
 
                 let node = NodeDef {
                     name: name.clone(),
                     span,
                 };
+                if let Some(&previous) = self.program.node_map.get(&name) {
+                    return Err(vec![SpannedError {
+                        error: AstError::DuplicateNode {
+                            name: name.to_string(),
+                            previous: self.program.nodes[previous].span.clone(),
+                        },
+                        span: Some(node.span),
+                    }]);
+                }
                 let node_id = self.program.nodes.alloc(node);
 
                 self.program.node_map.insert(name, node_id);
@@ -119,11 +202,11 @@ impl AstBuilder {
 
     /// Builds a table declaration from a Pest pair.
     fn build_table_declaration(&mut self, pair: Pair<Rule>) -> Results<()> {
-        let span = Span::from_pest(pair.as_span());
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
 
-        let table_name = inner.next().unwrap().as_str().to_string();
-        let node_name = inner.next().unwrap().as_str().to_string();
+        let table_name = Symbol::intern(next_pair(&mut inner, &span)?.as_str());
+        let node_name = Symbol::intern(next_pair(&mut inner, &span)?.as_str());
 
         let node_id = self
             .program
@@ -132,17 +215,31 @@ impl AstBuilder {
             .copied()
             .ok_or_else(|| {
                 vec![SpannedError {
-                    error: AstError::UndeclaredNode(node_name),
+                    error: AstError::UndeclaredNode(node_name.to_string()),
                     span: Some(span.clone()),
                 }]
             })?;
 
-        let mut field_ids = Vec::new();
+        let mut field_ids: Vec<FieldId> = Vec::new();
         let mut primary_key_ids = Vec::new();
 
         for field_pair in inner {
             if field_pair.as_rule() == Rule::field_declaration {
                 let (field_id, is_primary) = self.build_field_declaration(field_pair)?;
+                let field = &self.program.fields[field_id];
+                if let Some(&previous) = field_ids
+                    .iter()
+                    .find(|&&id| self.program.fields[id].field_name == field.field_name)
+                {
+                    return Err(vec![SpannedError {
+                        error: AstError::DuplicateField {
+                            table: table_name.to_string(),
+                            field: field.field_name.to_string(),
+                            previous: self.program.fields[previous].span.clone(),
+                        },
+                        span: Some(field.span.clone()),
+                    }]);
+                }
                 field_ids.push(field_id);
 
                 if is_primary {
@@ -161,6 +258,16 @@ impl AstBuilder {
             }]);
         }
 
+        if let Some(&previous) = self.program.table_map.get(&table_name) {
+            return Err(vec![SpannedError {
+                error: AstError::DuplicateTable {
+                    name: table_name.to_string(),
+                    previous: self.program.tables[previous].span.clone(),
+                },
+                span: Some(span),
+            }]);
+        }
+
         let table = TableDeclaration {
             name: table_name.clone(),
             node: node_id,
@@ -181,22 +288,46 @@ impl AstBuilder {
         &mut self,
         pair: Pair<Rule>,
     ) -> Result<(FieldId, bool), Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
 
-        let first = inner.next().unwrap();
+        let first = next_pair(&mut inner, &span)?;
         let (is_primary, field_type) = if first.as_rule() == Rule::primary_keyword {
-            (true, self.parse_type_name(inner.next().unwrap())?)
+            (true, self.parse_type_name(next_pair(&mut inner, &span)?)?)
         } else {
             (false, self.parse_type_name(first)?)
         };
 
-        let field_name = inner.next().unwrap().as_str().to_string();
+        let field_name = Symbol::intern(next_pair(&mut inner, &span)?.as_str());
+
+        let collation = match inner.next() {
+            Some(clause) => {
+                let name_pair = next_pair(&mut clause.into_inner(), &span)?;
+                let Some(collation) = Collation::from_name(name_pair.as_str()) else {
+                    return Err(vec![SpannedError {
+                        error: AstError::UnknownCollation(name_pair.as_str().to_string()),
+                        span: Some(self.span(name_pair.as_span())),
+                    }]);
+                };
+                if field_type != TypeName::String {
+                    return Err(vec![SpannedError {
+                        error: AstError::CollationOnNonString {
+                            field: field_name.to_string(),
+                            field_type,
+                        },
+                        span: Some(span),
+                    }]);
+                }
+                Some(collation)
+            }
+            None => None,
+        };
 
         let field = FieldDeclaration {
             field_type,
             field_name,
             is_primary,
+            collation,
             span,
         };
 
@@ -205,20 +336,32 @@ impl AstBuilder {
     }
 
     /// Builds function declaration from a Pest pair.
-    fn build_function_declaration(&mut self, pair: Pair<Rule>) -> Results<()> {
-        let span = Span::from_pest(pair.as_span());
+    fn build_function_declaration(&mut self, pair: Pair<Rule>) -> Results<FunctionId> {
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
 
-        let return_type = self.parse_ret_type(inner.next().unwrap())?;
-        let name = inner.next().unwrap().as_str().to_string();
+        let return_type = self.parse_ret_type(next_pair(&mut inner, &span)?)?;
+        let name = Symbol::intern(next_pair(&mut inner, &span)?.as_str());
 
+        self.build_function(return_type, name, inner, span)
+    }
+
+    /// Builds the parameters and hops in `items` into a function named `name`.
+    fn build_function(
+        &mut self,
+        return_type: ReturnType,
+        name: Symbol,
+        items: Pairs<Rule>,
+        span: Span,
+    ) -> Results<FunctionId> {
         let mut parameter_ids = Vec::new();
         let mut hop_ids = Vec::new();
 
-        for item in inner {
+        for item in items {
             match item.as_rule() {
                 Rule::parameter_list => {
                     parameter_ids = self.build_parameter_list(item)?;
+                    self.check_duplicate_parameters(name.clone(), &parameter_ids)?;
                 }
                 Rule::function_body_item => {
                     for hop_item in item.into_inner() {
@@ -232,6 +375,16 @@ impl AstBuilder {
             }
         }
 
+        if let Some(&previous) = self.program.function_map.get(&name) {
+            return Err(vec![SpannedError {
+                error: AstError::DuplicateFunction {
+                    name: name.to_string(),
+                    previous: self.program.functions[previous].span.clone(),
+                },
+                span: Some(span),
+            }]);
+        }
+
         let function = FunctionDeclaration {
             return_type,
             name: name.clone(),
@@ -244,9 +397,144 @@ impl AstBuilder {
         self.program.function_map.insert(name, function_id);
         self.program.root_functions.push(function_id);
 
+        Ok(function_id)
+    }
+
+    /// Records a template declaration and returns its name.
+    ///
+    /// The body is only built when the template is instantiated.
+    fn build_template_declaration(&mut self, pair: &Pair<Rule>) -> Results<Symbol> {
+        let span = self.span(pair.as_span());
+        let mut inner = pair.clone().into_inner();
+
+        let mut name_pair = next_pair(&mut inner, &span)?;
+        if name_pair.as_rule() == Rule::ret_type {
+            name_pair = next_pair(&mut inner, &span)?;
+        }
+        let name = Symbol::intern(name_pair.as_str());
+        let params = next_pair(&mut inner, &span)?
+            .into_inner()
+            .map(|param| Symbol::intern(param.as_str()))
+            .collect();
+
+        if let Some(previous) = self.program.templates.iter().find(|t| t.name == name) {
+            return Err(vec![SpannedError {
+                error: AstError::DuplicateTemplate {
+                    name: name.to_string(),
+                    previous: previous.span.clone(),
+                },
+                span: Some(span),
+            }]);
+        }
+
+        self.program.templates.push(TemplateDeclaration {
+            name: name.clone(),
+            params,
+            span,
+        });
+        Ok(name)
+    }
+
+    /// Expands `instantiate template<args> as name;` into a new function.
+    ///
+    /// The template body is built with each parameter replaced by its argument;
+    /// spans of the expanded function point back into the template.
+    fn build_template_instantiation(
+        &mut self,
+        pair: Pair<Rule>,
+        templates: &HashMap<Symbol, PendingTemplate>,
+    ) -> Results<()> {
+        let span = self.span(pair.as_span());
+        let mut inner = pair.into_inner();
+
+        let template_name = Symbol::intern(next_pair(&mut inner, &span)?.as_str());
+        let args: Vec<TemplateArg> = next_pair(&mut inner, &span)?
+            .into_inner()
+            .map(|arg| {
+                let arg = next_pair(&mut arg.into_inner(), &span)?;
+                Ok(TemplateArg {
+                    rule: arg.as_rule(),
+                    text: arg.as_str().to_string(),
+                })
+            })
+            .collect::<Results<_>>()?;
+        let name = Symbol::intern(next_pair(&mut inner, &span)?.as_str());
+
+        let Some((template_file, template_pair)) = templates.get(&template_name) else {
+            return Err(vec![SpannedError {
+                error: AstError::UndeclaredTemplate(template_name.to_string()),
+                span: Some(span),
+            }]);
+        };
+        let Some(template) = self
+            .program
+            .templates
+            .iter()
+            .find(|t| t.name == template_name)
+        else {
+            return Err(vec![SpannedError {
+                error: AstError::UndeclaredTemplate(template_name.to_string()),
+                span: Some(span),
+            }]);
+        };
+        let params = &template.params;
+        if params.len() != args.len() {
+            return Err(vec![SpannedError {
+                error: AstError::TemplateArgumentCount {
+                    template: template_name.to_string(),
+                    expected: params.len(),
+                    found: args.len(),
+                },
+                span: Some(span),
+            }]);
+        }
+        let bindings = params.iter().cloned().zip(args.iter().cloned()).collect();
+
+        // Build the body in the template's file so spans point at the template
+        let instance_file = std::mem::replace(&mut self.current_file, template_file.clone());
+        self.template_args = bindings;
+        let template_span = self.span(template_pair.as_span());
+        let mut template_inner = template_pair.clone().into_inner();
+        let return_type = next_pair(&mut template_inner, &template_span).and_then(|first| {
+            if first.as_rule() == Rule::ret_type {
+                template_inner.next();
+                self.parse_ret_type(first)
+            } else {
+                Ok(ReturnType::Void)
+            }
+        });
+        template_inner.next(); // template_param_list
+        let result = return_type
+            .and_then(|ret| self.build_function(ret, name, template_inner, template_span));
+        self.template_args.clear();
+        self.current_file = instance_file;
+
+        let function = result?;
+        self.program.template_instances.push(TemplateInstance {
+            template: template_name,
+            args: args.into_iter().map(|arg| arg.text).collect(),
+            function,
+            span,
+        });
         Ok(())
     }
 
+    /// Reads an identifier used as a name, substituting template arguments.
+    fn name(&self, pair: &Pair<Rule>) -> Results<Symbol> {
+        let text = pair.as_str();
+        match self.template_args.get(&Symbol::intern(text)) {
+            None => Ok(Symbol::intern(text)),
+            Some(arg) if arg.rule == Rule::identifier => Ok(Symbol::intern(&arg.text)),
+            Some(arg) => Err(vec![SpannedError {
+                error: AstError::InvalidTemplateArgument {
+                    param: text.to_string(),
+                    arg: arg.text.clone(),
+                },
+                span: Some(self.span(pair.as_span())),
+            }]),
+        }
+    }
+
     /// Builds a list of parameters from a Pest pair.
     fn build_parameter_list(
         &mut self,
@@ -262,18 +550,44 @@ impl AstBuilder {
         Ok(parameter_ids)
     }
 
+    /// Reports the first parameter of `function` whose name is already taken.
+    fn check_duplicate_parameters(
+        &self,
+        function: Symbol,
+        parameter_ids: &[ParameterId],
+    ) -> Results<()> {
+        for (i, &param_id) in parameter_ids.iter().enumerate() {
+            let param = &self.program.parameters[param_id];
+            if let Some(&previous) = parameter_ids[..i]
+                .iter()
+                .find(|&&id| self.program.parameters[id].param_name == param.param_name)
+            {
+                return Err(vec![SpannedError {
+                    error: AstError::DuplicateParameter {
+                        function: function.to_string(),
+                        param: param.param_name.to_string(),
+                        previous: self.program.parameters[previous].span.clone(),
+                    },
+                    span: Some(param.span.clone()),
+                }]);
+            }
+        }
+        Ok(())
+    }
+
     /// Builds a parameter declaration from a Pest pair.
     fn build_parameter_decl(&mut self, pair: Pair<Rule>) -> Result<ParameterId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
 
-        let param_type = self.parse_type_name(inner.next().unwrap())?;
-        let param_name = inner.next().unwrap().as_str().to_string();
+        let param_type = self.parse_type_name(next_pair(&mut inner, &span)?)?;
+        let param_name = self.name(&next_pair(&mut inner, &span)?)?;
 
         let parameter = ParameterDecl {
             param_type,
             param_name,
             span,
+            resolved_var: None,
         };
 
         Ok(self.program.parameters.alloc(parameter))
@@ -281,10 +595,10 @@ impl AstBuilder {
 
     /// Builds hop block from a Pest pair.
     fn build_hop_block(&mut self, pair: Pair<Rule>) -> Result<HopId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
 
-        let node_name = inner.next().unwrap().as_str().to_string();
+        let node_name = self.name(&next_pair(&mut inner, &span)?)?;
 
         let mut statement_ids = Vec::new();
         for item in inner {
@@ -317,8 +631,8 @@ impl AstBuilder {
 
     /// Builds a statement from a Pest pair.
     fn build_statement(&mut self, pair: Pair<Rule>) -> Result<StatementId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
-        let inner = pair.into_inner().next().unwrap();
+        let span = self.span(pair.as_span());
+        let inner = next_pair(&mut pair.into_inner(), &span)?;
 
         let kind = match inner.as_rule() {
             Rule::var_decl_statement => {
@@ -360,15 +674,20 @@ impl AstBuilder {
         &mut self,
         pair: Pair<Rule>,
     ) -> Result<VarDeclStatement, Vec<SpannedError>> {
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
-        let var_type = self.parse_type_name(inner.next().unwrap())?;
-        let var_name = inner.next().unwrap().as_str().to_string();
-        let init_value = self.build_expression(inner.next().unwrap())?;
+        let var_type = self.parse_type_name(next_pair(&mut inner, &span)?)?;
+        let var_name = self.name(&next_pair(&mut inner, &span)?)?;
+        let init_value = match inner.next() {
+            Some(expr) => Some(self.build_expression(expr)?),
+            None => None,
+        };
 
         Ok(VarDeclStatement {
             var_type,
             var_name,
             init_value,
+            resolved_var: None,
         })
     }
 
@@ -377,9 +696,10 @@ impl AstBuilder {
         &mut self,
         pair: Pair<Rule>,
     ) -> Result<VarAssignmentStatement, Vec<SpannedError>> {
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
-        let var_name = inner.next().unwrap().as_str().to_string();
-        let rhs = self.build_expression(inner.next().unwrap())?;
+        let var_name = self.name(&next_pair(&mut inner, &span)?)?;
+        let rhs = self.build_expression(next_pair(&mut inner, &span)?)?;
 
         Ok(VarAssignmentStatement {
             var_name,
@@ -393,16 +713,17 @@ impl AstBuilder {
         &mut self,
         pair: Pair<Rule>,
     ) -> Result<AssignmentStatement, Vec<SpannedError>> {
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
-        let table_name = inner.next().unwrap().as_str().to_string();
+        let table_name = self.name(&next_pair(&mut inner, &span)?)?;
 
         // Parse the primary_key_list
-        let pk_list_pair = inner.next().unwrap();
+        let pk_list_pair = next_pair(&mut inner, &span)?;
         let (pk_fields, pk_exprs) = self.build_primary_key_list(pk_list_pair)?;
         let pk_count = pk_fields.len(); // Calculate length before moving
 
-        let field_name = inner.next().unwrap().as_str().to_string();
-        let rhs = self.build_expression(inner.next().unwrap())?;
+        let field_name = self.name(&next_pair(&mut inner, &span)?)?;
+        let rhs = self.build_expression(next_pair(&mut inner, &span)?)?;
 
         Ok(AssignmentStatement {
             table_name,
@@ -421,16 +742,17 @@ impl AstBuilder {
         &mut self,
         pair: Pair<Rule>,
     ) -> Result<MultiAssignmentStatement, Vec<SpannedError>> {
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
-        let table_name = inner.next().unwrap().as_str().to_string();
+        let table_name = self.name(&next_pair(&mut inner, &span)?)?;
 
         // Parse the primary_key_list
-        let pk_list_pair = inner.next().unwrap();
+        let pk_list_pair = next_pair(&mut inner, &span)?;
         let (pk_fields, pk_exprs) = self.build_primary_key_list(pk_list_pair)?;
         let pk_count = pk_fields.len();
 
         // Parse the multi_assignment_list
-        let multi_assignment_list = inner.next().unwrap();
+        let multi_assignment_list = next_pair(&mut inner, &span)?;
         let assignments = self.build_multi_assignment_list(multi_assignment_list)?;
 
         Ok(MultiAssignmentStatement {
@@ -452,9 +774,10 @@ impl AstBuilder {
 
         for assignment_pair in pair.into_inner() {
             if assignment_pair.as_rule() == Rule::multi_assignment_pair {
+                let span = self.span(assignment_pair.as_span());
                 let mut inner = assignment_pair.into_inner();
-                let field_name = inner.next().unwrap().as_str().to_string();
-                let rhs = self.build_expression(inner.next().unwrap())?;
+                let field_name = self.name(&next_pair(&mut inner, &span)?)?;
+                let rhs = self.build_expression(next_pair(&mut inner, &span)?)?;
 
                 assignments.push(MultiAssignmentPair {
                     field_name,
@@ -471,15 +794,16 @@ impl AstBuilder {
     fn build_primary_key_list(
         &mut self,
         pair: Pair<Rule>,
-    ) -> Result<(Vec<String>, Vec<ExpressionId>), Vec<SpannedError>> {
+    ) -> Result<(Vec<Symbol>, Vec<ExpressionId>), Vec<SpannedError>> {
         let mut pk_fields = Vec::new();
         let mut pk_exprs = Vec::new();
 
         for pk_pair in pair.into_inner() {
             if pk_pair.as_rule() == Rule::primary_key_pair {
+                let span = self.span(pk_pair.as_span());
                 let mut inner = pk_pair.into_inner();
-                let field_name = inner.next().unwrap().as_str().to_string();
-                let expr_id = self.build_expression(inner.next().unwrap())?;
+                let field_name = self.name(&next_pair(&mut inner, &span)?)?;
+                let expr_id = self.build_expression(next_pair(&mut inner, &span)?)?;
                 
                 pk_fields.push(field_name);
                 pk_exprs.push(expr_id);
@@ -491,19 +815,28 @@ impl AstBuilder {
 
     /// Builds an if statement from a Pest pair.
     fn build_if_statement(&mut self, pair: Pair<Rule>) -> Result<IfStatement, Vec<SpannedError>> {
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
-        let condition = self.build_expression(inner.next().unwrap())?;
-        let then_branch = self.build_block(inner.next().unwrap())?;
-        let else_branch = if let Some(else_block) = inner.next() {
-            Some(self.build_block(else_block)?)
+        let condition = self.build_expression(next_pair(&mut inner, &span)?)?;
+        let then_block = next_pair(&mut inner, &span)?;
+        let then_end = then_block.as_span().end();
+        let input = then_block.get_input();
+        let then_branch = self.build_block(then_block)?;
+        let (else_branch, else_span) = if let Some(else_block) = inner.next() {
+            // `else` is the first token after the then-block, past any comments
+            let start = then_end + first_token_offset(&input[then_end..]);
+            let keyword = pest::Span::new(input, start, start + "else".len())
+                .map(|keyword| self.span(keyword));
+            (Some(self.build_block(else_block)?), keyword)
         } else {
-            None
+            (None, None)
         };
 
         Ok(IfStatement {
             condition,
             then_branch,
             else_branch,
+            else_span,
         })
     }
 
@@ -512,9 +845,10 @@ impl AstBuilder {
         &mut self,
         pair: Pair<Rule>,
     ) -> Result<WhileStatement, Vec<SpannedError>> {
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
-        let condition = self.build_expression(inner.next().unwrap())?;
-        let body = self.build_block(inner.next().unwrap())?;
+        let condition = self.build_expression(next_pair(&mut inner, &span)?)?;
+        let body = self.build_block(next_pair(&mut inner, &span)?)?;
 
         Ok(WhileStatement { condition, body })
     }
@@ -536,165 +870,111 @@ impl AstBuilder {
 
     /// Builds an expression from a Pest pair.
     fn build_expression(&mut self, pair: Pair<Rule>) -> Result<ExpressionId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
+        let span = self.span(pair.as_span());
 
         let kind = match pair.as_rule() {
             Rule::expression => {
-                let inner = pair.into_inner().next().unwrap();
+                let inner = next_pair(&mut pair.into_inner(), &span)?;
                 return self.build_expression(inner);
             }
-            Rule::logic_or => return self.build_logic_or(pair),
-            Rule::logic_and => return self.build_logic_and(pair),
-            Rule::equality => return self.build_equality(pair),
-            Rule::comparison => return self.build_comparison(pair),
-            Rule::addition => return self.build_addition(pair),
-            Rule::multiplication => return self.build_multiplication(pair),
+            Rule::logic_or
+            | Rule::logic_and
+            | Rule::equality
+            | Rule::comparison
+            | Rule::addition
+            | Rule::multiplication => return self.build_binary_chain(pair),
             Rule::unary => return self.build_unary(pair),
             Rule::primary => return self.build_primary(pair),
-            Rule::bool_literal => ExpressionKind::BoolLit(pair.as_str() == "true"),
+            Rule::bool_literal
+            | Rule::integer_literal
+            | Rule::float_literal
+            | Rule::string_literal => self.build_literal(pair.as_rule(), pair.as_str(), &span)?,
+            Rule::identifier => match self.template_args.get(&Symbol::intern(pair.as_str())) {
+                Some(arg) => self.build_literal(arg.rule, &arg.text, &span)?,
+                None => ExpressionKind::Ident(Symbol::intern(pair.as_str())),
+            },
+            Rule::table_field_access => return self.build_table_field_access(pair),
+            _ => {
+                return Err(vec![SpannedError {
+                    error: AstError::ParseError(format!(
+                        "Unknown expression rule: {:?}",
+                        pair.as_rule()
+                    )),
+                    span: Some(span),
+                }]);
+            }
+        };
+
+        let expression = Expression { node: kind, span };
+        Ok(self.program.expressions.alloc(expression))
+    }
+
+    /// Builds a literal (or identifier) expression from its grammar rule and text.
+    fn build_literal(&self, rule: Rule, text: &str, span: &Span) -> Results<ExpressionKind> {
+        Ok(match rule {
+            Rule::bool_literal => ExpressionKind::BoolLit(text == "true"),
             Rule::integer_literal => {
-                let value = pair.as_str().parse().map_err(|_| {
+                let value = text.parse().map_err(|_| {
                     vec![SpannedError {
-                        error: AstError::ParseError(format!("Invalid integer: {}", pair.as_str())),
+                        error: AstError::ParseError(format!("Invalid integer: {}", text)),
                         span: Some(span.clone()),
                     }]
                 })?;
                 ExpressionKind::IntLit(value)
             }
             Rule::float_literal => {
-                let value = pair.as_str().parse().map_err(|_| {
+                let value = text.parse().map_err(|_| {
                     vec![SpannedError {
-                        error: AstError::ParseError(format!("Invalid float: {}", pair.as_str())),
+                        error: AstError::ParseError(format!("Invalid float: {}", text)),
                         span: Some(span.clone()),
                     }]
                 })?;
                 ExpressionKind::FloatLit(value)
             }
             Rule::string_literal => {
-                let s = pair.as_str();
-                let content = if s.len() >= 2 {
-                    s[1..s.len() - 1].to_string()
+                let content = if text.len() >= 2 {
+                    text[1..text.len() - 1].to_string()
                 } else {
                     String::new()
                 };
                 ExpressionKind::StringLit(content)
             }
-            Rule::identifier => ExpressionKind::Ident(pair.as_str().to_string()),
-            Rule::table_field_access => return self.build_table_field_access(pair),
-            _ => {
-                return Err(vec![SpannedError {
-                    error: AstError::ParseError(format!(
-                        "Unknown expression rule: {:?}",
-                        pair.as_rule()
-                    )),
-                    span: Some(span),
-                }]);
-            }
-        };
-
-        let expression = Expression { node: kind, span };
-        Ok(self.program.expressions.alloc(expression))
-    }
-
-    fn build_logic_or(&mut self, pair: Pair<Rule>) -> Result<ExpressionId, Vec<SpannedError>> {
-        self.build_binary_expr(pair, BinaryOp::Or)
-    }
-
-    fn build_logic_and(&mut self, pair: Pair<Rule>) -> Result<ExpressionId, Vec<SpannedError>> {
-        self.build_binary_expr(pair, BinaryOp::And)
-    }
-
-    fn build_equality(&mut self, pair: Pair<Rule>) -> Result<ExpressionId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
-        let mut inner = pair.into_inner();
-
-        let mut left = self.build_expression(inner.next().unwrap())?;
-
-        while let Some(op_pair) = inner.next() {
-            let right = self.build_expression(inner.next().unwrap())?;
-            let op = match op_pair.as_str() {
-                "==" => BinaryOp::Eq,
-                "!=" => BinaryOp::Neq,
-                _ => {
-                    return Err(vec![SpannedError {
-                        error: AstError::ParseError(format!(
-                            "Unknown equality op: {}",
-                            op_pair.as_str()
-                        )),
-                        span: Some(span),
-                    }])
-                }
-            };
-
-            let expr = Expression {
-                node: ExpressionKind::BinaryOp { left, op, right, resolved_type: None },
-                span: span.clone(),
-            };
-            left = self.program.expressions.alloc(expr);
-        }
-
-        Ok(left)
-    }
-
-    fn build_comparison(&mut self, pair: Pair<Rule>) -> Result<ExpressionId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
-        let mut inner = pair.into_inner();
-
-        let mut left = self.build_expression(inner.next().unwrap())?;
-
-        while let Some(op_pair) = inner.next() {
-            let right = self.build_expression(inner.next().unwrap())?;
-            let op = match op_pair.as_str() {
-                "<" => BinaryOp::Lt,
-                "<=" => BinaryOp::Lte,
-                ">" => BinaryOp::Gt,
-                ">=" => BinaryOp::Gte,
-                _ => {
-                    return Err(vec![SpannedError {
-                        error: AstError::ParseError(format!(
-                            "Unknown comparison op: {}",
-                            op_pair.as_str()
-                        )),
-                        span: Some(span),
-                    }])
-                }
-            };
-
-            let expr = Expression {
-                node: ExpressionKind::BinaryOp { left, op, right, resolved_type: None },
-                span: span.clone(),
-            };
-            left = self.program.expressions.alloc(expr);
-        }
-
-        Ok(left)
+            _ => ExpressionKind::Ident(Symbol::intern(text)),
+        })
     }
 
-    fn build_addition(&mut self, pair: Pair<Rule>) -> Result<ExpressionId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
+    /// Builds a left-associative chain `operand (op operand)*` for any binary
+    /// precedence level (logic_or, logic_and, equality, comparison, addition,
+    /// multiplication), reading the operator from each operator token.
+    fn build_binary_chain(&mut self, pair: Pair<Rule>) -> Result<ExpressionId, Vec<SpannedError>> {
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
 
-        let mut left = self.build_expression(inner.next().unwrap())?;
+        let first = next_pair(&mut inner, &span)?;
+        let start_span = self.span(first.as_span());
+        let mut left = self.build_expression(first)?;
 
         while let Some(op_pair) = inner.next() {
-            let right = self.build_expression(inner.next().unwrap())?;
-            let op = match op_pair.as_str() {
-                "+" => BinaryOp::Add,
-                "-" => BinaryOp::Sub,
-                _ => {
-                    return Err(vec![SpannedError {
-                        error: AstError::ParseError(format!(
-                            "Unknown addition op: {}",
-                            op_pair.as_str()
-                        )),
-                        span: Some(span),
-                    }])
-                }
+            let op = self.parse_binary_op(&op_pair)?;
+            let right_pair = next_pair(&mut inner, &span)?;
+            let right_span = self.span(right_pair.as_span());
+            let right = self.build_expression(right_pair)?;
+
+            // Each node spans from the start of the chain to the end of its right operand
+            let span = Span {
+                end: right_span.end,
+                end_line: right_span.end_line,
+                end_column: right_span.end_column,
+                ..start_span.clone()
             };
-
             let expr = Expression {
-                node: ExpressionKind::BinaryOp { left, op, right, resolved_type: None },
-                span: span.clone(),
+                node: ExpressionKind::BinaryOp {
+                    left,
+                    op,
+                    right,
+                    resolved_type: None,
+                },
+                span,
             };
             left = self.program.expressions.alloc(expr);
         }
@@ -702,49 +982,36 @@ impl AstBuilder {
         Ok(left)
     }
 
-    fn build_multiplication(
-        &mut self,
-        pair: Pair<Rule>,
-    ) -> Result<ExpressionId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
-        let mut inner = pair.into_inner();
-
-        let mut left = self.build_expression(inner.next().unwrap())?;
-
-        while let Some(op_pair) = inner.next() {
-            let right = self.build_expression(inner.next().unwrap())?;
-            let op = match op_pair.as_str() {
-                "*" => BinaryOp::Mul,
-                "/" => BinaryOp::Div,
-                _ => {
-                    return Err(vec![SpannedError {
-                        error: AstError::ParseError(format!(
-                            "Unknown multiplication op: {}",
-                            op_pair.as_str()
-                        )),
-                        span: Some(span),
-                    }])
-                }
-            };
-
-            let expr = Expression {
-                node: ExpressionKind::BinaryOp { left, op, right, resolved_type: None },
-                span: span.clone(),
-            };
-            left = self.program.expressions.alloc(expr);
+    /// Maps an operator token of a binary precedence level to its `BinaryOp`.
+    fn parse_binary_op(&self, op_pair: &Pair<Rule>) -> Result<BinaryOp, Vec<SpannedError>> {
+        match op_pair.as_str() {
+            "||" => Ok(BinaryOp::Or),
+            "&&" => Ok(BinaryOp::And),
+            "==" => Ok(BinaryOp::Eq),
+            "!=" => Ok(BinaryOp::Neq),
+            "<" => Ok(BinaryOp::Lt),
+            "<=" => Ok(BinaryOp::Lte),
+            ">" => Ok(BinaryOp::Gt),
+            ">=" => Ok(BinaryOp::Gte),
+            "+" => Ok(BinaryOp::Add),
+            "-" => Ok(BinaryOp::Sub),
+            "*" => Ok(BinaryOp::Mul),
+            "/" => Ok(BinaryOp::Div),
+            other => Err(vec![SpannedError {
+                error: AstError::ParseError(format!("Unknown binary operator: {}", other)),
+                span: Some(self.span(op_pair.as_span())),
+            }]),
         }
-
-        Ok(left)
     }
 
     fn build_unary(&mut self, pair: Pair<Rule>) -> Result<ExpressionId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
-        let first = inner.next().unwrap();
+        let first = next_pair(&mut inner, &span)?;
 
         if first.as_rule() == Rule::unary_op {
             let op_str = first.as_str();
-            let operand = self.build_expression(inner.next().unwrap())?;
+            let operand = self.build_expression(next_pair(&mut inner, &span)?)?;
             let op = match op_str {
                 "!" => UnaryOp::Not,
                 "-" => UnaryOp::Neg,
@@ -767,7 +1034,8 @@ impl AstBuilder {
     }
 
     fn build_primary(&mut self, pair: Pair<Rule>) -> Result<ExpressionId, Vec<SpannedError>> {
-        let inner = pair.into_inner().next().unwrap();
+        let span = self.span(pair.as_span());
+        let inner = next_pair(&mut pair.into_inner(), &span)?;
         self.build_expression(inner)
     }
 
@@ -775,17 +1043,17 @@ impl AstBuilder {
         &mut self,
         pair: Pair<Rule>,
     ) -> Result<ExpressionId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
+        let span = self.span(pair.as_span());
         let mut inner = pair.into_inner();
 
-        let table_name = inner.next().unwrap().as_str().to_string();
+        let table_name = self.name(&next_pair(&mut inner, &span)?)?;
 
         // Parse the primary_key_list
-        let pk_list_pair = inner.next().unwrap();
+        let pk_list_pair = next_pair(&mut inner, &span)?;
         let (pk_fields, pk_exprs) = self.build_primary_key_list(pk_list_pair)?;
         let pk_count = pk_fields.len(); // Calculate length before moving
 
-        let field_name = inner.next().unwrap().as_str().to_string();
+        let field_name = self.name(&next_pair(&mut inner, &span)?)?;
 
         let expr = Expression {
             node: ExpressionKind::TableFieldAccess {
@@ -804,35 +1072,6 @@ impl AstBuilder {
         Ok(self.program.expressions.alloc(expr))
     }
 
-    fn build_binary_expr(
-        &mut self,
-        pair: Pair<Rule>,
-        default_op: BinaryOp,
-    ) -> Result<ExpressionId, Vec<SpannedError>> {
-        let span = Span::from_pest(pair.as_span());
-        let mut inner = pair.into_inner();
-
-        let mut left = self.build_expression(inner.next().unwrap())?;
-
-        while inner.peek().is_some() {
-            let _op_pair = inner.next().unwrap(); // Skip operator for now, use default
-            let right = self.build_expression(inner.next().unwrap())?;
-
-            let expr = Expression {
-                node: ExpressionKind::BinaryOp {
-                    left,
-                    op: default_op.clone(),
-                    right,
-                    resolved_type: None,
-                },
-                span: span.clone(),
-            };
-            left = self.program.expressions.alloc(expr);
-        }
-
-        Ok(left)
-    }
-
     /// Parses a return type from a Pest pair.
     fn parse_ret_type(&self, pair: Pair<Rule>) -> Result<ReturnType, Vec<SpannedError>> {
         match pair.as_str() {
@@ -850,34 +1089,52 @@ impl AstBuilder {
             "bool" => Ok(TypeName::Bool),
             _ => Err(vec![SpannedError {
                 error: AstError::ParseError(format!("Unknown type: {}", pair.as_str())),
-                span: Some(Span::from_pest(pair.as_span())),
+                span: Some(self.span(pair.as_span())),
             }]),
         }
     }
 }
 
-pub fn build_program_from_pair(pair: Pair<Rule>) -> Results<Program> {
+/// Builds one function declaration of `file` into an existing program.
+///
+/// The function is appended to `root_functions`; nothing else in the program changes.
+pub(crate) fn build_function_into(
+    program: &mut Program,
+    file: Option<Arc<Path>>,
+    pair: Pair<Rule>,
+) -> Results<FunctionId> {
     let mut builder = AstBuilder::new();
-    builder.build_program(pair)
+    builder.program = std::mem::take(program);
+    builder.current_file = file;
+    let result = builder.build_function_declaration(pair);
+    *program = builder.program;
+    result
 }
 
-/// Parses and builds a program from source code.
-pub fn parse_and_build(source: &str) -> Results<Program> {
-    // Parse using Pest
-    let pairs = TransActParser::parse(Rule::program, source).map_err(|e| {
-        vec![SpannedError {
-            error: AstError::ParseError(e.to_string()),
-            span: None,
-        }]
-    })?;
-
-    let program_pair = pairs.into_iter().next().ok_or_else(|| {
-        vec![SpannedError {
-            error: AstError::ParseError("No program found".to_string()),
-            span: None,
-        }]
-    })?;
-
-    // Build arena-based AST
-    build_program_from_pair(program_pair)
+/// Builds a single program from loaded source files, dependencies first.
+pub fn build_program_from_files(files: Vec<SourceFile>) -> Results<Program> {
+    let sources: Vec<String> = files.iter().map(SourceFile::active_source).collect();
+    let mut pairs = Vec::new();
+    for (file, source) in files.iter().zip(&sources) {
+        let pair = super::loader::parse_program(source, file.path.as_deref())?;
+        pairs.push((file.path.clone(), pair));
+    }
+
+    let mut builder = AstBuilder::new();
+    let mut program = builder.build_program_from_pairs(pairs)?;
+    program.source_files = files;
+    program.trivia = super::trivia::collect_trivia(&program, &builder.nodes_blocks);
+    Ok(program)
+}
+
+/// The offset of the first token of `text`, skipping whitespace and comments.
+fn first_token_offset(text: &str) -> usize {
+    let mut rest = text;
+    loop {
+        let trimmed = rest.trim_start();
+        match trimmed.strip_prefix("//") {
+            Some(comment) => rest = comment.find('\n').map_or("", |n| &comment[n..]),
+            None => return text.len() - trimmed.len(),
+        }
+    }
 }
@@ -0,0 +1,159 @@
+//! The `config` module holds the settings that tune semantic analysis.
+//!
+//! # Overview
+//!
+//! - **AnalysisConfig**: The abort policy, the level of each lint, what
+//!   conflict analysis leaves out (see `sc_graph::ConflictExclusions`), and the
+//!   limits on each verification condition (see `verification::solver`), and
+//!   what records are assumed to start from (see `verification::invariants`).
+//! - **LintLevel**: Whether a lint is dropped, reported as a warning, or rejects
+//!   the program. Lints warn by default, except `overflow`, which is allowed,
+//!   and `uninitialized`, which is denied.
+//!
+//! Every warning belongs to a lint, named as in `// allow(...)` comments (see
+//! `LINTS`). A config can be written in TOML:
+//!
+//! ```
+//! use FMitF_rs::ast::{AbortPolicy, AnalysisConfig, LintLevel};
+//!
+//! let config = AnalysisConfig::from_toml(
+//!     r#"
+//!     abort_policy = "before-writes"
+//!
+//!     [lints]
+//!     overflow = "warn"
+//!     dead_writes = "deny"
+//!
+//!     [verification]
+//!     timeout = 60
+//!     memory = 4096
+//!
+//!     [assumptions]
+//!     initial_state = "assumed"
+//!     invariants = ["Account.balance >= 0"]
+//!     "#,
+//! )
+//! .unwrap();
+//! assert_eq!(config.abort_policy, AbortPolicy::BeforeWrites);
+//! assert_eq!(config.verification.timeout, Some(60));
+//! assert_eq!(config.assumptions.invariants, ["Account.balance >= 0"]);
+//! assert_eq!(config.level("dead_writes"), LintLevel::Deny);
+//! assert_eq!(config.level("unused_variables"), LintLevel::Warn);
+//! // Reads of unassigned locals are rejected unless lowered
+//! assert_eq!(config.level("uninitialized"), LintLevel::Deny);
+//! ```
+
+use crate::ast::{AbortPolicy, Diagnostic};
+use crate::sc_graph::ConflictExclusions;
+use crate::verification::invariants::Assumptions;
+use crate::verification::solver::SolverLimits;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The name of every lint.
+pub const LINTS: &[&str] = &[
+    "unreachable_code",
+    "unused_variables",
+    "adjacent_hops",
+    "idle_hops",
+    "dead_writes",
+    "division_by_zero",
+    "overflow",
+    "uninitialized",
+    "early_return",
+];
+
+/// What to do with the warnings of a lint.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// Drop the warnings
+    Allow,
+    /// Report them as warnings
+    Warn,
+    /// Report them as errors, rejecting the program
+    Deny,
+}
+
+/// Settings for semantic analysis. The default is the standard rule set.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalysisConfig {
+    /// Which hops may abort
+    pub abort_policy: AbortPolicy,
+    /// Levels of lints that differ from their default, by lint name
+    pub lints: HashMap<String, LintLevel>,
+    /// Pairs of functions and tables left out of conflict analysis
+    pub exclusions: ConflictExclusions,
+    /// What a prover may spend on each verification condition
+    pub verification: SolverLimits,
+    /// What records start from, and properties declared of them
+    pub assumptions: Assumptions,
+}
+
+impl AnalysisConfig {
+    /// Parses a config from TOML, rejecting unknown keys and lint names.
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        for name in config.lints.keys() {
+            check_lint_name(name)?;
+        }
+        Ok(config)
+    }
+
+    /// Reads and parses the TOML config file at `path`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+        Self::from_toml(&text).map_err(|e| format!("Invalid config '{}': {}", path.display(), e))
+    }
+
+    /// Sets the level of a lint, or of every lint for `"all"`.
+    pub fn set_level(&mut self, name: &str, level: LintLevel) -> Result<(), String> {
+        if name == "all" {
+            for lint in LINTS {
+                self.lints.insert(lint.to_string(), level);
+            }
+            return Ok(());
+        }
+        check_lint_name(name)?;
+        self.lints.insert(name.to_string(), level);
+        Ok(())
+    }
+
+    /// The level of a lint.
+    pub fn level(&self, name: &str) -> LintLevel {
+        match self.lints.get(name) {
+            Some(level) => *level,
+            None if name == "overflow" => LintLevel::Allow,
+            None if name == "uninitialized" => LintLevel::Deny,
+            None => LintLevel::Warn,
+        }
+    }
+
+    /// Drops the warnings of allowed lints and turns those of denied lints into errors.
+    pub fn apply(&self, warnings: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        warnings
+            .into_iter()
+            .filter_map(|warning| {
+                let Some(name) = warning.error.lint_name() else {
+                    return Some(warning);
+                };
+                match self.level(name) {
+                    LintLevel::Allow => None,
+                    LintLevel::Warn => Some(warning),
+                    LintLevel::Deny => Some(warning.promoted()),
+                }
+            })
+            .collect()
+    }
+}
+
+fn check_lint_name(name: &str) -> Result<(), String> {
+    if LINTS.contains(&name) {
+        Ok(())
+    } else {
+        Err(format!("Unknown lint '{}'", name))
+    }
+}
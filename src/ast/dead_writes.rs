@@ -0,0 +1,296 @@
+//! The `dead_writes` module finds table writes that are overwritten before they are read.
+//!
+//! # Overview
+//!
+//! Within one hop, a write to `T[pk].f` followed by another write to the same row and
+//! field, with no read of `T.f` in between, never has an observable effect. It is
+//! likely a bug, and at least noise for conflict analysis, so the earlier write is
+//! reported as a `DeadWrite` warning.
+//!
+//! # Approximations
+//!
+//! The check is conservative, so it only reports writes that are certainly dead:
+//!
+//! - Rows are compared by their primary key expressions, which must be built from
+//!   variables and literals. A variable assigned between the writes changes the key.
+//! - Any read of `T.f` counts as a read of the pending write, whatever its key.
+//! - Writes inside an `if` or `while` body are only compared with writes in the same
+//!   body. Reads and assignments anywhere in the statement still count.
+//! - `return`, `abort`, `break` and `continue` end the comparison, since the write
+//!   before them may be the last one.
+
+use crate::ast::*;
+
+/// Canonical primary key expressions sorted by key field, and the variables they read.
+type RowKey = (Vec<(FieldId, String)>, Vec<VarId>);
+
+/// A write to a table field that has not been read since.
+struct PendingWrite {
+    table: TableId,
+    field: FieldId,
+    // Canonical primary key expressions, sorted by key field
+    key: Vec<(FieldId, String)>,
+    // Variables the key expressions read
+    vars: Vec<VarId>,
+    span: Span,
+}
+
+pub struct DeadWriteFinder<'p> {
+    program: &'p Program,
+    pending: Vec<PendingWrite>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'p> DeadWriteFinder<'p> {
+    /// Creates a new `DeadWriteFinder` for the given program.
+    pub fn new(program: &'p Program) -> Self {
+        Self {
+            program,
+            pending: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Finds the dead writes in a hop and returns a warning for each.
+    pub fn find(mut self, hop: &HopBlock) -> Vec<Diagnostic> {
+        self.check_block(&hop.statements);
+        self.warnings
+    }
+
+    /// Checks a list of statements that run in sequence.
+    fn check_block(&mut self, statements: &[StatementId]) {
+        for stmt_id in statements {
+            self.check_statement(*stmt_id);
+        }
+    }
+
+    fn check_statement(&mut self, stmt_id: StatementId) {
+        let stmt = &self.program.statements[stmt_id];
+
+        match &stmt.node {
+            StatementKind::Assignment(assign) => {
+                self.read_expressions(&assign.pk_exprs);
+                self.read_expression(assign.rhs);
+                let key = self.canonical_key(&assign.resolved_pk_fields, &assign.pk_exprs);
+                if let (Some(table), Some(field), Some(key)) =
+                    (assign.resolved_table, assign.resolved_field, key)
+                {
+                    self.write(table, field, &key, &stmt.span);
+                }
+            }
+            StatementKind::MultiAssignment(multi_assign) => {
+                self.read_expressions(&multi_assign.pk_exprs);
+                for pair in &multi_assign.assignments {
+                    self.read_expression(pair.rhs);
+                }
+                let key =
+                    self.canonical_key(&multi_assign.resolved_pk_fields, &multi_assign.pk_exprs);
+                if let (Some(table), Some(key)) = (multi_assign.resolved_table, key) {
+                    for field in multi_assign
+                        .assignments
+                        .iter()
+                        .filter_map(|p| p.resolved_field)
+                    {
+                        self.write(table, field, &key, &stmt.span);
+                    }
+                }
+            }
+            StatementKind::VarAssignment(var_assign) => {
+                self.read_expression(var_assign.rhs);
+                if let Some(var_id) = var_assign.resolved_var {
+                    self.pending.retain(|write| !write.vars.contains(&var_id));
+                }
+            }
+            StatementKind::VarDecl(var_decl) => {
+                if let Some(init_value) = var_decl.init_value {
+                    self.read_expression(init_value);
+                }
+            }
+            StatementKind::IfStmt(if_stmt) => {
+                // Account for everything the statement reads or assigns, then
+                // compare the writes of each branch among themselves only
+                self.read_statement(stmt_id);
+                let pending = std::mem::take(&mut self.pending);
+                self.check_block(&if_stmt.then_branch);
+                self.pending.clear();
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    self.check_block(else_branch);
+                }
+                self.pending = pending;
+            }
+            StatementKind::WhileStmt(while_stmt) => {
+                self.read_statement(stmt_id);
+                let pending = std::mem::take(&mut self.pending);
+                self.check_block(&while_stmt.body);
+                self.pending = pending;
+            }
+            StatementKind::Return(ret_stmt) => {
+                if let Some(value) = ret_stmt.value {
+                    self.read_expression(value);
+                }
+                self.pending.clear();
+            }
+            StatementKind::Abort(_) | StatementKind::Break(_) | StatementKind::Continue(_) => {
+                self.pending.clear();
+            }
+            StatementKind::Empty => {}
+        }
+    }
+
+    /// Records a write, reporting an earlier write to the same row and field.
+    fn write(&mut self, table: TableId, field: FieldId, (key, vars): &RowKey, span: &Span) {
+        if let Some(index) = self
+            .pending
+            .iter()
+            .position(|w| w.table == table && w.field == field && w.key == *key)
+        {
+            let earlier = self.pending.remove(index);
+            let error = AstError::DeadWrite {
+                table: self.program.tables[table].name.to_string(),
+                field: self.program.fields[field].field_name.to_string(),
+            };
+            self.warnings.push(
+                Diagnostic::warning(error, Some(earlier.span))
+                    .with_note("Overwritten here", Some(span.clone())),
+            );
+        }
+
+        self.pending.push(PendingWrite {
+            table,
+            field,
+            key: key.clone(),
+            vars: vars.clone(),
+            span: span.clone(),
+        });
+    }
+
+    /// The primary key in a canonical form, with the variables it depends on.
+    /// `None` if a key expression reads a table.
+    fn canonical_key(
+        &self,
+        pk_fields: &[Option<FieldId>],
+        pk_exprs: &[ExpressionId],
+    ) -> Option<RowKey> {
+        let mut vars = Vec::new();
+        let mut key = pk_fields
+            .iter()
+            .zip(pk_exprs)
+            .map(|(field, expr)| Some(((*field)?, self.canonical_expression(*expr, &mut vars)?)))
+            .collect::<Option<Vec<_>>>()?;
+        key.sort_by_key(|(field, _)| field.index());
+        Some((key, vars))
+    }
+
+    fn canonical_expression(&self, expr_id: ExpressionId, vars: &mut Vec<VarId>) -> Option<String> {
+        match &self.program.expressions[expr_id].node {
+            ExpressionKind::Ident(_) => {
+                let var_id = *self.program.resolutions.get(&expr_id)?;
+                vars.push(var_id);
+                Some(format!("v{}", var_id.index()))
+            }
+            ExpressionKind::IntLit(val) => Some(val.to_string()),
+            ExpressionKind::FloatLit(val) => Some(format!("{:?}", val)),
+            ExpressionKind::StringLit(val) => Some(format!("{:?}", val)),
+            ExpressionKind::BoolLit(val) => Some(val.to_string()),
+            ExpressionKind::UnaryOp { op, expr, .. } => Some(format!(
+                "({:?} {})",
+                op,
+                self.canonical_expression(*expr, vars)?
+            )),
+            ExpressionKind::BinaryOp {
+                left, op, right, ..
+            } => Some(format!(
+                "({} {:?} {})",
+                self.canonical_expression(*left, vars)?,
+                op,
+                self.canonical_expression(*right, vars)?
+            )),
+            ExpressionKind::TableFieldAccess { .. } => None,
+        }
+    }
+
+    /// Drops the pending writes a statement may read or re-key, including its bodies.
+    fn read_statement(&mut self, stmt_id: StatementId) {
+        match &self.program.statements[stmt_id].node {
+            StatementKind::Assignment(assign) => {
+                self.read_expressions(&assign.pk_exprs);
+                self.read_expression(assign.rhs);
+            }
+            StatementKind::MultiAssignment(multi_assign) => {
+                self.read_expressions(&multi_assign.pk_exprs);
+                for pair in &multi_assign.assignments {
+                    self.read_expression(pair.rhs);
+                }
+            }
+            StatementKind::VarAssignment(var_assign) => {
+                self.read_expression(var_assign.rhs);
+                if let Some(var_id) = var_assign.resolved_var {
+                    self.pending.retain(|write| !write.vars.contains(&var_id));
+                }
+            }
+            StatementKind::VarDecl(var_decl) => {
+                if let Some(init_value) = var_decl.init_value {
+                    self.read_expression(init_value);
+                }
+            }
+            StatementKind::IfStmt(if_stmt) => {
+                self.read_expression(if_stmt.condition);
+                for stmt_id in if_stmt
+                    .then_branch
+                    .iter()
+                    .chain(if_stmt.else_branch.iter().flatten())
+                {
+                    self.read_statement(*stmt_id);
+                }
+            }
+            StatementKind::WhileStmt(while_stmt) => {
+                self.read_expression(while_stmt.condition);
+                for stmt_id in &while_stmt.body {
+                    self.read_statement(*stmt_id);
+                }
+            }
+            StatementKind::Return(ret_stmt) => {
+                if let Some(value) = ret_stmt.value {
+                    self.read_expression(value);
+                }
+            }
+            StatementKind::Abort(_)
+            | StatementKind::Break(_)
+            | StatementKind::Continue(_)
+            | StatementKind::Empty => {}
+        }
+    }
+
+    fn read_expressions(&mut self, expr_ids: &[ExpressionId]) {
+        for expr_id in expr_ids {
+            self.read_expression(*expr_id);
+        }
+    }
+
+    /// Drops the pending writes to every field the expression reads.
+    fn read_expression(&mut self, expr_id: ExpressionId) {
+        match &self.program.expressions[expr_id].node {
+            ExpressionKind::TableFieldAccess {
+                pk_exprs,
+                resolved_table,
+                resolved_field,
+                ..
+            } => {
+                self.read_expressions(pk_exprs);
+                self.pending.retain(|write| {
+                    Some(write.table) != *resolved_table || Some(write.field) != *resolved_field
+                });
+            }
+            ExpressionKind::UnaryOp { expr, .. } => self.read_expression(*expr),
+            ExpressionKind::BinaryOp { left, right, .. } => {
+                self.read_expression(*left);
+                self.read_expression(*right);
+            }
+            ExpressionKind::Ident(_)
+            | ExpressionKind::IntLit(_)
+            | ExpressionKind::FloatLit(_)
+            | ExpressionKind::StringLit(_)
+            | ExpressionKind::BoolLit(_) => {}
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! The `definite_assignment` module rejects locals that may be read before
+//! they are assigned.
+//!
+//! # Overview
+//!
+//! A local declared without a value, like `int x;`, starts out unassigned. The
+//! checker follows the control flow of the whole function, since a local declared
+//! in one hop stays visible in the hops after it, and reports the first read of
+//! such a local on a path where no assignment reaches it as an
+//! `UninitializedVariable`. Its lint, `uninitialized`, is denied by default, so
+//! the read is an error unless the lint is lowered to a warning (`--warn
+//! uninitialized`); the local then holds the default value of its type at
+//! runtime and in verification.
+//!
+//! # Flow analysis
+//!
+//! Hops run in order, and a hop is only entered if the previous one can fall
+//! through to its end. The set of locals that may still be unassigned is joined
+//! over the branches of an `if`, iterated to a fixed point for a `while`, and
+//! paths ending in `return`, `abort`, `break` or `continue` do not reach the
+//! statements after them.
+
+use crate::ast::*;
+use std::collections::{HashMap, HashSet};
+
+/// The locals that may be unassigned at a program point, or `None` if no path
+/// reaches it.
+type Flow = Option<HashSet<VarId>>;
+
+fn join(a: Flow, b: Flow) -> Flow {
+    match (a, b) {
+        (None, flow) | (flow, None) => flow,
+        (Some(mut a), Some(b)) => {
+            a.extend(b);
+            Some(a)
+        }
+    }
+}
+
+pub struct DefiniteAssignmentChecker<'p> {
+    program: &'p Program,
+    // Hop index of each local declared without a value
+    declared_in: HashMap<VarId, usize>,
+    hop_index: usize,
+    // Flows leaving the innermost loop through `break` and `continue`
+    breaks: Flow,
+    continues: Flow,
+    // Off while a loop body is followed to its fixed point
+    report: bool,
+    reported: HashSet<VarId>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'p> DefiniteAssignmentChecker<'p> {
+    /// Creates a new `DefiniteAssignmentChecker` for the given program.
+    pub fn new(program: &'p Program) -> Self {
+        Self {
+            program,
+            declared_in: HashMap::new(),
+            hop_index: 0,
+            breaks: None,
+            continues: None,
+            report: true,
+            reported: HashSet::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Checks a function and returns a warning for each local that may be read
+    /// before it is assigned.
+    pub fn check(mut self, func: &FunctionDeclaration) -> Vec<Diagnostic> {
+        let mut flow = Some(HashSet::new());
+        for (hop_index, hop_id) in func.hops.iter().enumerate() {
+            self.hop_index = hop_index;
+            flow = self.check_block(&self.program.hops[*hop_id].statements, flow);
+            if flow.is_none() {
+                break;
+            }
+        }
+        self.warnings
+    }
+
+    /// Follows the paths through a list of statements, starting with `flow`.
+    fn check_block(&mut self, statements: &[StatementId], mut flow: Flow) -> Flow {
+        for stmt_id in statements {
+            flow = self.check_statement(*stmt_id, flow);
+        }
+        flow
+    }
+
+    fn check_statement(&mut self, stmt_id: StatementId, flow: Flow) -> Flow {
+        let stmt = &self.program.statements[stmt_id];
+        // Nothing after an exit runs
+        let mut unset = flow?;
+
+        match &stmt.node {
+            StatementKind::VarDecl(var_decl) => {
+                match (var_decl.init_value, var_decl.resolved_var) {
+                    (Some(init_value), _) => self.read_expression(init_value, &unset),
+                    (None, Some(var_id)) => {
+                        unset.insert(var_id);
+                        self.declared_in.insert(var_id, self.hop_index);
+                    }
+                    (None, None) => {}
+                }
+            }
+            StatementKind::VarAssignment(var_assign) => {
+                self.read_expression(var_assign.rhs, &unset);
+                if let Some(var_id) = var_assign.resolved_var {
+                    unset.remove(&var_id);
+                }
+            }
+            StatementKind::Assignment(assign) => {
+                self.read_expressions(&assign.pk_exprs, &unset);
+                self.read_expression(assign.rhs, &unset);
+            }
+            StatementKind::MultiAssignment(multi_assign) => {
+                self.read_expressions(&multi_assign.pk_exprs, &unset);
+                for pair in &multi_assign.assignments {
+                    self.read_expression(pair.rhs, &unset);
+                }
+            }
+            StatementKind::IfStmt(if_stmt) => {
+                self.read_expression(if_stmt.condition, &unset);
+                let then_flow = self.check_block(&if_stmt.then_branch, Some(unset.clone()));
+                let else_flow = match &if_stmt.else_branch {
+                    Some(else_branch) => self.check_block(else_branch, Some(unset)),
+                    None => Some(unset),
+                };
+                return join(then_flow, else_flow);
+            }
+            StatementKind::WhileStmt(while_stmt) => return self.check_while(while_stmt, unset),
+            StatementKind::Return(ret_stmt) => {
+                if let Some(value) = ret_stmt.value {
+                    self.read_expression(value, &unset);
+                }
+                return None;
+            }
+            StatementKind::Break(_) => {
+                self.breaks = join(self.breaks.take(), Some(unset));
+                return None;
+            }
+            StatementKind::Continue(_) => {
+                self.continues = join(self.continues.take(), Some(unset));
+                return None;
+            }
+            StatementKind::Abort(_) => return None,
+            StatementKind::Empty => {}
+        }
+        Some(unset)
+    }
+
+    fn check_while(&mut self, while_stmt: &WhileStatement, unset: HashSet<VarId>) -> Flow {
+        let breaks = self.breaks.take();
+        let continues = self.continues.take();
+        let report = std::mem::replace(&mut self.report, false);
+
+        // The locals unassigned at the loop head, before each test of the condition
+        let mut head = unset;
+        loop {
+            let end = self.check_block(&while_stmt.body, Some(head.clone()));
+            let back = join(end, self.continues.take());
+            self.breaks = None;
+            let next = join(Some(head.clone()), back).unwrap_or_default();
+            if next == head {
+                break;
+            }
+            head = next;
+        }
+
+        // Report the reads once the flow into the body is known
+        self.report = report;
+        self.read_expression(while_stmt.condition, &head);
+        self.check_block(&while_stmt.body, Some(head.clone()));
+        let endless = matches!(
+            self.program.expressions[while_stmt.condition].node,
+            ExpressionKind::BoolLit(true)
+        );
+        let exit = join((!endless).then_some(head), self.breaks.take());
+
+        self.breaks = breaks;
+        self.continues = continues;
+        exit
+    }
+
+    fn read_expressions(&mut self, expr_ids: &[ExpressionId], unset: &HashSet<VarId>) {
+        for expr_id in expr_ids {
+            self.read_expression(*expr_id, unset);
+        }
+    }
+
+    /// Reports the first read of each local in `unset` by the expression.
+    fn read_expression(&mut self, expr_id: ExpressionId, unset: &HashSet<VarId>) {
+        let expr = &self.program.expressions[expr_id];
+        match &expr.node {
+            ExpressionKind::Ident(_) => {
+                let Some(&var_id) = self.program.resolutions.get(&expr_id) else {
+                    return;
+                };
+                if unset.contains(&var_id) && self.report && self.reported.insert(var_id) {
+                    self.warn(var_id, &expr.span);
+                }
+            }
+            ExpressionKind::TableFieldAccess { pk_exprs, .. } => {
+                self.read_expressions(pk_exprs, unset);
+            }
+            ExpressionKind::UnaryOp { expr, .. } => self.read_expression(*expr, unset),
+            ExpressionKind::BinaryOp { left, right, .. } => {
+                self.read_expression(*left, unset);
+                self.read_expression(*right, unset);
+            }
+            ExpressionKind::IntLit(_)
+            | ExpressionKind::FloatLit(_)
+            | ExpressionKind::StringLit(_)
+            | ExpressionKind::BoolLit(_) => {}
+        }
+    }
+
+    fn warn(&mut self, var_id: VarId, span: &Span) {
+        let var = &self.program.variables[var_id];
+        let note = if self.declared_in.get(&var_id) == Some(&self.hop_index) {
+            "Declared here without a value"
+        } else {
+            "Declared here without a value, in an earlier hop; not every path to this read assigns it"
+        };
+        self.warnings.push(
+            Diagnostic::warning(
+                AstError::UninitializedVariable(var.name.to_string()),
+                Some(span.clone()),
+            )
+            .with_note(note, Some(var.defined_at.clone())),
+        );
+    }
+}
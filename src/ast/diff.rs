@@ -0,0 +1,359 @@
+//! The `diff` module compares two programs structurally.
+//! Declarations are matched by name, hops by position and statements with a
+//! longest-common-subsequence diff of their canonical text, so formatting and
+//! comment changes do not show up as differences.
+//!
+//! # Overview
+//!
+//! - **diff_programs**: Computes the changes from one program to another.
+//! - **ProgramDiff**: The list of changes, in declaration order.
+//! - **Change**: One added, removed or changed element with its rendering in both programs.
+
+use serde::Serialize;
+
+use crate::ast::*;
+use crate::pretty::formatter::{format_statement, type_name};
+
+/// How an element differs between the old and the new program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// A single difference between two programs.
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// Location of the element, e.g. `function transfer/hop 1/statement 3`.
+    /// Hop and statement numbers are 1-based and refer to the new program,
+    /// except for removed elements.
+    pub path: String,
+    /// Rendering of the element in the old program, `None` if it was added.
+    pub old: Option<String>,
+    /// Rendering of the element in the new program, `None` if it was removed.
+    pub new: Option<String>,
+    pub old_span: Option<Span>,
+    pub new_span: Option<Span>,
+}
+
+/// The structural differences between two programs.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProgramDiff {
+    pub changes: Vec<Change>,
+}
+
+impl ProgramDiff {
+    /// Returns true if the programs are structurally identical.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    fn added(&mut self, path: String, new: String, span: &Span) {
+        self.changes.push(Change {
+            kind: ChangeKind::Added,
+            path,
+            old: None,
+            new: Some(new),
+            old_span: None,
+            new_span: Some(span.clone()),
+        });
+    }
+
+    fn removed(&mut self, path: String, old: String, span: &Span) {
+        self.changes.push(Change {
+            kind: ChangeKind::Removed,
+            path,
+            old: Some(old),
+            new: None,
+            old_span: Some(span.clone()),
+            new_span: None,
+        });
+    }
+
+    fn changed(&mut self, path: String, old: (String, &Span), new: (String, &Span)) {
+        if old.0 != new.0 {
+            self.changes.push(Change {
+                kind: ChangeKind::Changed,
+                path,
+                old: Some(old.0),
+                new: Some(new.0),
+                old_span: Some(old.1.clone()),
+                new_span: Some(new.1.clone()),
+            });
+        }
+    }
+}
+
+/// Computes the changes that turn `old` into `new`.
+///
+/// Nodes, tables, fields and functions are matched by name. Hops of a function
+/// are compared position by position, and the statements of matching hops with
+/// a sequence diff; a removed statement directly followed by an added one is
+/// reported as a single change.
+///
+/// ```rust
+/// use FMitF_rs::ast::diff::{diff_programs, ChangeKind};
+///
+/// let old = FMitF_rs::parse_and_analyze("nodes { A } table T on A { primary int id; }").unwrap();
+/// let new = FMitF_rs::parse_and_analyze("nodes { A, B } table T on A { primary int id; }").unwrap();
+/// let diff = diff_programs(&old, &new);
+/// assert_eq!(diff.changes.len(), 1);
+/// assert_eq!(diff.changes[0].kind, ChangeKind::Added);
+/// assert_eq!(diff.changes[0].path, "node B");
+/// ```
+pub fn diff_programs(old: &Program, new: &Program) -> ProgramDiff {
+    let mut diff = ProgramDiff::default();
+    diff_nodes(old, new, &mut diff);
+    diff_tables(old, new, &mut diff);
+    diff_functions(old, new, &mut diff);
+    diff
+}
+
+fn diff_nodes(old: &Program, new: &Program, diff: &mut ProgramDiff) {
+    for &id in &old.root_nodes {
+        let node = &old.nodes[id];
+        if !new.node_map.contains_key(&node.name) {
+            diff.removed(
+                format!("node {}", node.name),
+                node.name.to_string(),
+                &node.span,
+            );
+        }
+    }
+    for &id in &new.root_nodes {
+        let node = &new.nodes[id];
+        if !old.node_map.contains_key(&node.name) {
+            diff.added(
+                format!("node {}", node.name),
+                node.name.to_string(),
+                &node.span,
+            );
+        }
+    }
+}
+
+fn table_header(program: &Program, table: &TableDeclaration) -> String {
+    format!("table {} on {}", table.name, program.nodes[table.node].name)
+}
+
+fn field_text(field: &FieldDeclaration) -> String {
+    let primary = if field.is_primary { "primary " } else { "" };
+    let collation = match field.collation {
+        Some(collation) => format!(" collate {}", collation.name()),
+        None => String::new(),
+    };
+    format!(
+        "{}{} {}{};",
+        primary,
+        type_name(&field.field_type),
+        field.field_name,
+        collation
+    )
+}
+
+fn diff_tables(old: &Program, new: &Program, diff: &mut ProgramDiff) {
+    for &id in &old.root_tables {
+        let table = &old.tables[id];
+        if !new.table_map.contains_key(&table.name) {
+            let path = format!("table {}", table.name);
+            diff.removed(path, table_header(old, table), &table.span);
+        }
+    }
+
+    for &new_id in &new.root_tables {
+        let new_table = &new.tables[new_id];
+        let path = format!("table {}", new_table.name);
+        let Some(&old_id) = old.table_map.get(&new_table.name) else {
+            diff.added(path, table_header(new, new_table), &new_table.span);
+            continue;
+        };
+        let old_table = &old.tables[old_id];
+        diff.changed(
+            path.clone(),
+            (table_header(old, old_table), &old_table.span),
+            (table_header(new, new_table), &new_table.span),
+        );
+
+        let find = |program: &Program, table: &TableDeclaration, name: &str| {
+            table
+                .fields
+                .iter()
+                .map(|&f| &program.fields[f])
+                .find(|f| f.field_name == name)
+                .cloned()
+        };
+        for &field_id in &old_table.fields {
+            let field = &old.fields[field_id];
+            if find(new, new_table, &field.field_name).is_none() {
+                let field_path = format!("{}/field {}", path, field.field_name);
+                diff.removed(field_path, field_text(field), &field.span);
+            }
+        }
+        for &field_id in &new_table.fields {
+            let field = &new.fields[field_id];
+            let field_path = format!("{}/field {}", path, field.field_name);
+            match find(old, old_table, &field.field_name) {
+                Some(old_field) => diff.changed(
+                    field_path,
+                    (field_text(&old_field), &old_field.span),
+                    (field_text(field), &field.span),
+                ),
+                None => diff.added(field_path, field_text(field), &field.span),
+            }
+        }
+    }
+}
+
+fn signature(program: &Program, function: &FunctionDeclaration) -> String {
+    let params: Vec<String> = function
+        .parameters
+        .iter()
+        .map(|&p| {
+            let param = &program.parameters[p];
+            format!("{} {}", type_name(&param.param_type), param.param_name)
+        })
+        .collect();
+    let ret = match &function.return_type {
+        ReturnType::Void => "void",
+        ReturnType::Type(ty) => type_name(ty),
+    };
+    format!("{} {}({})", ret, function.name, params.join(", "))
+}
+
+fn diff_functions(old: &Program, new: &Program, diff: &mut ProgramDiff) {
+    for &id in &old.root_functions {
+        let function = &old.functions[id];
+        if !new.function_map.contains_key(&function.name) {
+            let path = format!("function {}", function.name);
+            diff.removed(path, signature(old, function), &function.span);
+        }
+    }
+
+    for &new_id in &new.root_functions {
+        let new_fn = &new.functions[new_id];
+        let path = format!("function {}", new_fn.name);
+        let Some(&old_id) = old.function_map.get(&new_fn.name) else {
+            diff.added(path, signature(new, new_fn), &new_fn.span);
+            continue;
+        };
+        let old_fn = &old.functions[old_id];
+        diff.changed(
+            path.clone(),
+            (signature(old, old_fn), &old_fn.span),
+            (signature(new, new_fn), &new_fn.span),
+        );
+
+        for index in 0..old_fn.hops.len().max(new_fn.hops.len()) {
+            let hop_path = format!("{}/hop {}", path, index + 1);
+            let old_hop = old_fn.hops.get(index).map(|&h| &old.hops[h]);
+            let new_hop = new_fn.hops.get(index).map(|&h| &new.hops[h]);
+            match (old_hop, new_hop) {
+                (Some(old_hop), Some(new_hop)) => {
+                    diff.changed(
+                        hop_path.clone(),
+                        (format!("hop on {}", old_hop.node_name), &old_hop.span),
+                        (format!("hop on {}", new_hop.node_name), &new_hop.span),
+                    );
+                    diff_statements(old, new, old_hop, new_hop, &hop_path, diff);
+                }
+                (Some(old_hop), None) => {
+                    let text = format!("hop on {}", old_hop.node_name);
+                    diff.removed(hop_path, text, &old_hop.span);
+                }
+                (None, Some(new_hop)) => {
+                    let text = format!("hop on {}", new_hop.node_name);
+                    diff.added(hop_path, text, &new_hop.span);
+                }
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+/// An edit in the statement sequence of a hop.
+enum Edit {
+    Keep,
+    Remove(usize),
+    Add(usize),
+}
+
+/// Diffs the statements of two hops with a longest common subsequence of their text.
+fn diff_statements(
+    old: &Program,
+    new: &Program,
+    old_hop: &HopBlock,
+    new_hop: &HopBlock,
+    hop_path: &str,
+    diff: &mut ProgramDiff,
+) {
+    let old_text: Vec<String> = old_hop
+        .statements
+        .iter()
+        .map(|&s| format_statement(old, s))
+        .collect();
+    let new_text: Vec<String> = new_hop
+        .statements
+        .iter()
+        .map(|&s| format_statement(new, s))
+        .collect();
+
+    // lcs[i][j] = length of the LCS of old_text[i..] and new_text[j..]
+    let (n, m) = (old_text.len(), new_text.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_text[i] == new_text[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_text[i] == new_text[j] {
+            edits.push(Edit::Keep);
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            edits.push(Edit::Remove(i));
+            i += 1;
+        } else {
+            edits.push(Edit::Add(j));
+            j += 1;
+        }
+    }
+
+    let mut index = 0;
+    while index < edits.len() {
+        match edits[index] {
+            Edit::Keep => {}
+            // A removal followed by an addition replaces the statement in place
+            Edit::Remove(i) => {
+                let old_span = &old.statements[old_hop.statements[i]].span;
+                if let Some(Edit::Add(j)) = edits.get(index + 1) {
+                    let new_span = &new.statements[new_hop.statements[*j]].span;
+                    diff.changed(
+                        format!("{}/statement {}", hop_path, j + 1),
+                        (old_text[i].clone(), old_span),
+                        (new_text[*j].clone(), new_span),
+                    );
+                    index += 1;
+                } else {
+                    let path = format!("{}/statement {}", hop_path, i + 1);
+                    diff.removed(path, old_text[i].clone(), old_span);
+                }
+            }
+            Edit::Add(j) => {
+                let new_span = &new.statements[new_hop.statements[j]].span;
+                let path = format!("{}/statement {}", hop_path, j + 1);
+                diff.added(path, new_text[j].clone(), new_span);
+            }
+        }
+        index += 1;
+    }
+}
@@ -4,9 +4,14 @@
 //! # Overview
 //!
 //! - **AstError**: Represents various types of errors that can occur during parsing, name resolution,
-//!   and semantic analysis.
+//!   and semantic analysis. Each has a stable code (`AstError::code`), e.g. `E0101`.
 //! - **SpannedError**: Combines an `AstError` with an optional `Span` for detailed error reporting.
 //! - **Results**: A type alias for `Result` with a vector of `SpannedError`.
+//! - **Diagnostic**: An error, warning or note (see `Severity`), with notes attached to it.
+//!   Analyses report warnings as diagnostics on the `Program` instead of failing.
+//!
+//! All three implement `Display`; `AstError` and `SpannedError` implement
+//! `std::error::Error`.
 //!
 //! # Features
 //!
@@ -15,16 +20,36 @@
 //!
 //! # Usage
 //!
-//! Use the `format_errors` function to format a list of errors for display:
+//! Use the `format_errors` function to format a list of errors on one line each,
+//! or `render_errors` and `render_diagnostic` to write them with the source
+//! lines they point at, as the command line prints them:
 //!
 //! ```rust
-//! use crate::ast::errors::{format_errors, SpannedError};
+//! use FMitF_rs::ast::errors::{format_errors, render_errors};
+//!
+//! let source = "nodes { A }\nvoid f() { hop on B { } }";
+//! let errors = FMitF_rs::parse_and_analyze(source).unwrap_err();
+//! assert_eq!(errors[0].error.code(), "E0104");
+//! assert_eq!(
+//!     format_errors(&errors),
+//!     "Error at 2:12: UndeclaredNode[E0104]: Node 'B' is not declared"
+//! );
 //!
-//! let errors: Vec<SpannedError> = vec![]; // Populate with errors
-//! println!("{}", format_errors(&errors));
+//! let mut out = Vec::new();
+//! render_errors(&mut out, &errors, source, false).unwrap();
+//! assert_eq!(
+//!     String::from_utf8(out).unwrap(),
+//!     "UndeclaredNode[E0104]: Node 'B' is not declared at line 2, column 12\n  \
+//!      |\n2 | void f() { hop on B { } }\n  |            ^^^^^^^^^^^^\n"
+//! );
 //! ```
 
-use crate::ast::{Span, TypeName};
+use crate::ast::{Collation, Span, TypeName, LANGUAGE_VERSION};
+use colored::{Color, Colorize};
+use serde::{Deserialize, Serialize};
+use pest::iterators::Pair;
+use pest::RuleType;
+use std::io::{self, Write};
 
 pub type Results<T> = Result<T, Vec<SpannedError>>;
 
@@ -34,7 +59,23 @@ pub struct SpannedError {
     pub span: Option<Span>,
 }
 
-#[derive(Debug, Clone)]
+impl std::fmt::Display for SpannedError {
+    /// One line: `Error at file:line:column: Kind[code]: message`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "Error at {}: {}", span, self.error),
+            None => write!(f, "Error: {}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for SpannedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AstError {
     // Parsing errors
     ParseError(String),
@@ -47,10 +88,79 @@ pub enum AstError {
         field: String,
     },
     UndeclaredNode(String),
-    DuplicateVariable(String),
-    DuplicateFunction(String),
-    DuplicateTable(String),
-    DuplicateNode(String),
+    // Duplicate declarations keep the span of the first one
+    DuplicateVariable {
+        name: String,
+        previous: Span,
+    },
+    DuplicateFunction {
+        name: String,
+        previous: Span,
+    },
+    DuplicateTable {
+        name: String,
+        previous: Span,
+    },
+    DuplicateNode {
+        name: String,
+        previous: Span,
+    },
+    DuplicateField {
+        table: String,
+        field: String,
+        previous: Span,
+    },
+    DuplicateParameter {
+        function: String,
+        param: String,
+        previous: Span,
+    },
+
+    // Scoping errors, with the span of the declaration involved
+    ShadowedParameter {
+        name: String,
+        previous: Span,
+    },
+    ShadowedVariable {
+        name: String,
+        previous: Span,
+    },
+    VariableOutOfScope {
+        name: String,
+        declared: Span,
+    },
+
+    // Module loading errors
+    ImportError(String),
+    CircularImport(String),
+
+    // Conditional compilation errors
+    DirectiveError(String),
+
+    // A `version` pragma this tool cannot read
+    UnsupportedVersion(u32),
+
+    // Incremental reparsing errors
+    InvalidEdit(String),
+
+    // SQL import errors
+    SqlError(String),
+
+    // Template expansion errors
+    UndeclaredTemplate(String),
+    DuplicateTemplate {
+        name: String,
+        previous: Span,
+    },
+    TemplateArgumentCount {
+        template: String,
+        expected: usize,
+        found: usize,
+    },
+    InvalidTemplateArgument {
+        param: String,
+        arg: String,
+    },
 
     // Type checking errors (for later use)
     TypeMismatch {
@@ -68,6 +178,19 @@ pub enum AstError {
     },
     InvalidCondition(TypeName),
 
+    // Collation errors
+    UnknownCollation(String),
+    CollationOnNonString {
+        field: String,
+        field_type: TypeName,
+    },
+    CollationMismatch {
+        left: String,
+        left_collation: Collation,
+        right: String,
+        right_collation: Collation,
+    },
+
     // Control flow errors
     BreakOutsideLoop,
     ContinueOutsideLoop,
@@ -87,17 +210,73 @@ pub enum AstError {
         table: String,
         column: String,
     },
+    DuplicatePrimaryKey {
+        table: String,
+        column: String,
+    },
+    MissingPrimaryKey {
+        table: String,
+        columns: Vec<String>,
+    },
 
     // Hop-specific errors
     AbortNotInFirstHop {
         function: String,
         hop_index: usize,
     },
+    AbortAfterWrites {
+        function: String,
+        hop_index: usize,
+        write: Span,
+    },
+
+    // Warnings
+    UnreachableCode,
+    UnusedVariable(String),
+    UnusedParameter(String),
+    AdjacentHopsOnSameNode(String),
+    EmptyHop {
+        function: String,
+        node: String,
+    },
+    HopWithoutTableAccess {
+        function: String,
+        node: String,
+    },
+    DeadWrite {
+        table: String,
+        field: String,
+    },
+    UninitializedVariable(String),
+    EarlyReturn {
+        function: String,
+        hop_index: usize,
+    },
+    DivisionByZero,
+    UnguardedDivision,
+    PossibleOverflow {
+        table: String,
+        field: String,
+    },
+    // A warning of a custom `AnalysisPass`
+    PassWarning {
+        pass: String,
+        message: String,
+    },
+
+    // Notes attached to other diagnostics
+    Note(String),
 }
 
 impl std::fmt::Display for AstError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}: {}", self.error_type(), self.message())
+        write!(
+            f,
+            "{}[{}]: {}",
+            self.error_type(),
+            self.code(),
+            self.message()
+        )
     }
 }
 
@@ -112,14 +291,32 @@ impl AstError {
             Self::UndeclaredTable(_) => "UndeclaredTable",
             Self::UndeclaredField { .. } => "UndeclaredField",
             Self::UndeclaredNode(_) => "UndeclaredNode",
-            Self::DuplicateVariable(_) => "DuplicateVariable",
-            Self::DuplicateFunction(_) => "DuplicateFunction",
-            Self::DuplicateTable(_) => "DuplicateTable",
-            Self::DuplicateNode(_) => "DuplicateNode",
+            Self::DuplicateVariable { .. } => "DuplicateVariable",
+            Self::DuplicateFunction { .. } => "DuplicateFunction",
+            Self::DuplicateTable { .. } => "DuplicateTable",
+            Self::DuplicateNode { .. } => "DuplicateNode",
+            Self::DuplicateField { .. } => "DuplicateField",
+            Self::DuplicateParameter { .. } => "DuplicateParameter",
+            Self::ShadowedParameter { .. } => "ShadowedParameter",
+            Self::ShadowedVariable { .. } => "ShadowedVariable",
+            Self::VariableOutOfScope { .. } => "VariableOutOfScope",
+            Self::ImportError(_) => "ImportError",
+            Self::CircularImport(_) => "CircularImport",
+            Self::DirectiveError(_) => "DirectiveError",
+            Self::UnsupportedVersion(_) => "UnsupportedVersion",
+            Self::InvalidEdit(_) => "InvalidEdit",
+            Self::SqlError(_) => "SqlError",
+            Self::UndeclaredTemplate(_) => "UndeclaredTemplate",
+            Self::DuplicateTemplate { .. } => "DuplicateTemplate",
+            Self::TemplateArgumentCount { .. } => "TemplateArgumentCount",
+            Self::InvalidTemplateArgument { .. } => "InvalidTemplateArgument",
             Self::TypeMismatch { .. } => "TypeMismatch",
             Self::InvalidUnaryOp { .. } => "InvalidUnaryOp",
             Self::InvalidBinaryOp { .. } => "InvalidBinaryOp",
             Self::InvalidCondition(_) => "InvalidCondition",
+            Self::UnknownCollation(_) => "UnknownCollation",
+            Self::CollationOnNonString { .. } => "CollationOnNonString",
+            Self::CollationMismatch { .. } => "CollationMismatch",
             Self::BreakOutsideLoop => "BreakOutsideLoop",
             Self::ContinueOutsideLoop => "ContinueOutsideLoop",
             Self::MissingReturn(_) => "MissingReturn",
@@ -127,7 +324,87 @@ impl AstError {
             Self::MissingReturnValue => "MissingReturnValue",
             Self::CrossNodeAccess { .. } => "CrossNodeAccess",
             Self::InvalidPrimaryKey { .. } => "InvalidPrimaryKey",
+            Self::DuplicatePrimaryKey { .. } => "DuplicatePrimaryKey",
+            Self::MissingPrimaryKey { .. } => "MissingPrimaryKey",
             Self::AbortNotInFirstHop { .. } => "AbortNotInFirstHop",
+            Self::AbortAfterWrites { .. } => "AbortAfterWrites",
+            Self::UnreachableCode => "UnreachableCode",
+            Self::UnusedVariable(_) => "UnusedVariable",
+            Self::UnusedParameter(_) => "UnusedParameter",
+            Self::AdjacentHopsOnSameNode(_) => "AdjacentHopsOnSameNode",
+            Self::EmptyHop { .. } => "EmptyHop",
+            Self::HopWithoutTableAccess { .. } => "HopWithoutTableAccess",
+            Self::DeadWrite { .. } => "DeadWrite",
+            Self::UninitializedVariable(_) => "UninitializedVariable",
+            Self::EarlyReturn { .. } => "EarlyReturn",
+            Self::DivisionByZero => "DivisionByZero",
+            Self::UnguardedDivision => "UnguardedDivision",
+            Self::PossibleOverflow { .. } => "PossibleOverflow",
+            Self::PassWarning { .. } => "PassWarning",
+            Self::Note(_) => "Note",
+        }
+    }
+
+    /// The stable code of the error, e.g. `E0101`: `E` for errors, `W` for
+    /// warnings and `N` for notes. Codes are never reused for other errors.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ParseError(_) => "E0001",
+            Self::UndeclaredVariable(_) => "E0101",
+            Self::UndeclaredTable(_) => "E0102",
+            Self::UndeclaredField { .. } => "E0103",
+            Self::UndeclaredNode(_) => "E0104",
+            Self::DuplicateVariable { .. } => "E0105",
+            Self::DuplicateFunction { .. } => "E0106",
+            Self::DuplicateTable { .. } => "E0107",
+            Self::DuplicateNode { .. } => "E0108",
+            Self::DuplicateField { .. } => "E0109",
+            Self::DuplicateParameter { .. } => "E0110",
+            Self::ShadowedParameter { .. } => "E0111",
+            Self::ShadowedVariable { .. } => "E0112",
+            Self::VariableOutOfScope { .. } => "E0113",
+            Self::ImportError(_) => "E0002",
+            Self::CircularImport(_) => "E0003",
+            Self::DirectiveError(_) => "E0004",
+            Self::UnsupportedVersion(_) => "E0005",
+            Self::InvalidEdit(_) => "E0006",
+            Self::SqlError(_) => "E0007",
+            Self::UndeclaredTemplate(_) => "E0201",
+            Self::DuplicateTemplate { .. } => "E0202",
+            Self::TemplateArgumentCount { .. } => "E0203",
+            Self::InvalidTemplateArgument { .. } => "E0204",
+            Self::TypeMismatch { .. } => "E0301",
+            Self::InvalidUnaryOp { .. } => "E0302",
+            Self::InvalidBinaryOp { .. } => "E0303",
+            Self::InvalidCondition(_) => "E0304",
+            Self::UnknownCollation(_) => "E0305",
+            Self::CollationOnNonString { .. } => "E0306",
+            Self::CollationMismatch { .. } => "E0307",
+            Self::BreakOutsideLoop => "E0401",
+            Self::ContinueOutsideLoop => "E0402",
+            Self::MissingReturn(_) => "E0403",
+            Self::UnexpectedReturnValue => "E0404",
+            Self::MissingReturnValue => "E0405",
+            Self::CrossNodeAccess { .. } => "E0501",
+            Self::InvalidPrimaryKey { .. } => "E0502",
+            Self::DuplicatePrimaryKey { .. } => "E0503",
+            Self::MissingPrimaryKey { .. } => "E0504",
+            Self::AbortNotInFirstHop { .. } => "E0505",
+            Self::AbortAfterWrites { .. } => "E0506",
+            Self::UnreachableCode => "W0001",
+            Self::UnusedVariable(_) => "W0002",
+            Self::UnusedParameter(_) => "W0003",
+            Self::AdjacentHopsOnSameNode(_) => "W0004",
+            Self::EmptyHop { .. } => "W0005",
+            Self::HopWithoutTableAccess { .. } => "W0006",
+            Self::DeadWrite { .. } => "W0007",
+            Self::UninitializedVariable(_) => "W0008",
+            Self::EarlyReturn { .. } => "W0009",
+            Self::DivisionByZero => "W0010",
+            Self::UnguardedDivision => "W0011",
+            Self::PossibleOverflow { .. } => "W0012",
+            Self::PassWarning { .. } => "W0013",
+            Self::Note(_) => "N0001",
         }
     }
 
@@ -141,10 +418,59 @@ impl AstError {
                 format!("Field '{}' does not exist in table '{}'", field, table)
             }
             Self::UndeclaredNode(name) => format!("Node '{}' is not declared", name),
-            Self::DuplicateVariable(name) => format!("Variable '{}' is already declared", name),
-            Self::DuplicateFunction(name) => format!("Function '{}' is already declared", name),
-            Self::DuplicateTable(name) => format!("Table '{}' is already declared", name),
-            Self::DuplicateNode(name) => format!("Node '{}' is already declared", name),
+            Self::DuplicateVariable { name, .. } => {
+                format!("Variable '{}' is already declared", name)
+            }
+            Self::DuplicateFunction { name, .. } => {
+                format!("Function '{}' is already declared", name)
+            }
+            Self::DuplicateTable { name, .. } => format!("Table '{}' is already declared", name),
+            Self::DuplicateNode { name, .. } => format!("Node '{}' is already declared", name),
+            Self::DuplicateField { table, field, .. } => {
+                format!("Field '{}' is already declared in table '{}'", field, table)
+            }
+            Self::DuplicateParameter {
+                function, param, ..
+            } => format!(
+                "Parameter '{}' is already declared in function '{}'",
+                param, function
+            ),
+            Self::ShadowedParameter { name, .. } => {
+                format!("Local variable '{}' shadows the parameter of the same name", name)
+            }
+            Self::ShadowedVariable { name, .. } => format!(
+                "Variable '{}' shadows a variable declared in an enclosing block",
+                name
+            ),
+            Self::VariableOutOfScope { name, .. } => format!(
+                "Variable '{}' is used outside the block that declares it",
+                name
+            ),
+            Self::ImportError(msg) => msg.clone(),
+            Self::CircularImport(path) => format!("Circular import of '{}'", path),
+            Self::DirectiveError(msg) => msg.clone(),
+            Self::UnsupportedVersion(version) => format!(
+                "The file is written in TransAct version {}, which this tool cannot read (it reads versions up to {})",
+                version, LANGUAGE_VERSION
+            ),
+            Self::InvalidEdit(msg) => msg.clone(),
+            Self::SqlError(msg) => msg.clone(),
+            Self::UndeclaredTemplate(name) => format!("Template '{}' is not declared", name),
+            Self::DuplicateTemplate { name, .. } => {
+                format!("Template '{}' is already declared", name)
+            }
+            Self::TemplateArgumentCount {
+                template,
+                expected,
+                found,
+            } => format!(
+                "Template '{}' expects {} argument(s) but {} were given",
+                template, expected, found
+            ),
+            Self::InvalidTemplateArgument { param, arg } => format!(
+                "Template parameter '{}' is used as a name, but argument '{}' is a literal",
+                param, arg
+            ),
             Self::TypeMismatch { expected, found } => {
                 format!("Expected type {:?} but found {:?}", expected, found)
             }
@@ -158,12 +484,32 @@ impl AstError {
             Self::InvalidCondition(ty) => {
                 format!("Condition must be boolean, found {:?}", ty)
             }
+            Self::UnknownCollation(name) => format!(
+                "Unknown collation '{}', expected 'binary' or 'nocase'",
+                name
+            ),
+            Self::CollationOnNonString { field, field_type } => format!(
+                "Field '{}' has type {:?}; only string fields can have a collation",
+                field, field_type
+            ),
+            Self::CollationMismatch {
+                left,
+                left_collation,
+                right,
+                right_collation,
+            } => format!(
+                "Cannot compare '{}' ({}) with '{}' ({}): the fields have different collations",
+                left,
+                left_collation.name(),
+                right,
+                right_collation.name()
+            ),
             Self::BreakOutsideLoop => "Break statement can only be used inside a loop".to_string(),
             Self::ContinueOutsideLoop => {
                 "Continue statement can only be used inside a loop".to_string()
             }
             Self::MissingReturn(func) => {
-                format!("Function '{}' must have a return statement", func)
+                format!("Function '{}' can reach its end without returning a value", func)
             }
             Self::UnexpectedReturnValue => "Void function cannot return a value".to_string(),
             Self::MissingReturnValue => "Non-void function must return a value".to_string(),
@@ -179,6 +525,19 @@ impl AstError {
                 "Column '{}' is not the primary key of table '{}'",
                 column, table
             ),
+            Self::DuplicatePrimaryKey { table, column } => format!(
+                "Primary key '{}' of table '{}' is given more than once",
+                column, table
+            ),
+            Self::MissingPrimaryKey { table, columns } => format!(
+                "Access to table '{}' is missing primary key {}",
+                table,
+                columns
+                    .iter()
+                    .map(|column| format!("'{}'", column))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             Self::AbortNotInFirstHop {
                 function,
                 hop_index,
@@ -186,21 +545,314 @@ impl AstError {
                 "Abort statement in function '{}' can only be used in the first hop, not hop {}",
                 function, hop_index
             ),
+            Self::AbortAfterWrites {
+                function,
+                hop_index,
+                ..
+            } => format!(
+                "Abort statement in hop {} of function '{}' cannot roll back the table writes of earlier hops",
+                hop_index, function
+            ),
+            Self::UnreachableCode => "Statement is never executed".to_string(),
+            Self::UnusedVariable(name) => format!("Variable '{}' is never read", name),
+            Self::UnusedParameter(name) => format!("Parameter '{}' is never read", name),
+            Self::AdjacentHopsOnSameNode(node) => {
+                format!("Hop runs on node '{}', like the hop before it", node)
+            }
+            Self::EmptyHop { function, node } => {
+                format!("Hop on node '{}' in function '{}' is empty", node, function)
+            }
+            Self::HopWithoutTableAccess { function, node } => format!(
+                "Hop on node '{}' in function '{}' does not access any table",
+                node, function
+            ),
+            Self::DeadWrite { table, field } => format!(
+                "Value written to '{}.{}' is overwritten before it is read",
+                table, field
+            ),
+            Self::UninitializedVariable(name) => {
+                format!("Variable '{}' may be read before it is assigned", name)
+            }
+            Self::EarlyReturn {
+                function,
+                hop_index,
+            } => format!(
+                "Return in hop {} of function '{}' ends the transaction before its last hop",
+                hop_index, function
+            ),
+            Self::DivisionByZero => "Division by zero".to_string(),
+            Self::UnguardedDivision => {
+                "Divisor is not checked to be non-zero before this division".to_string()
+            }
+            Self::PossibleOverflow { table, field } => format!(
+                "Arithmetic on int field '{}.{}' can overflow at runtime",
+                table, field
+            ),
+            Self::PassWarning { pass, message } => format!("{} ({})", message, pass),
+            Self::Note(msg) => msg.clone(),
+        }
+    }
+
+    /// The declaration or earlier statement an error refers back to, with the note shown at it.
+    pub fn previous_declaration(&self) -> Option<(&'static str, &Span)> {
+        match self {
+            Self::DuplicateVariable { previous, .. }
+            | Self::DuplicateFunction { previous, .. }
+            | Self::DuplicateTable { previous, .. }
+            | Self::DuplicateNode { previous, .. }
+            | Self::DuplicateField { previous, .. }
+            | Self::DuplicateParameter { previous, .. }
+            | Self::DuplicateTemplate { previous, .. } => Some(("First declared here", previous)),
+            Self::ShadowedParameter { previous, .. } => Some(("Parameter declared here", previous)),
+            Self::ShadowedVariable { previous, .. } => {
+                Some(("Shadowed variable declared here", previous))
+            }
+            Self::VariableOutOfScope { declared, .. } => {
+                Some(("Declared here, in a block that has already ended", declared))
+            }
+            Self::AbortAfterWrites { write, .. } => {
+                Some(("Written here, in an earlier hop that has committed", write))
+            }
+            _ => None,
+        }
+    }
+
+    /// The lint a warning belongs to, as named in `// allow(...)` comments and
+    /// `AnalysisConfig`. `None` for errors.
+    pub fn lint_name(&self) -> Option<&'static str> {
+        match self {
+            Self::UnreachableCode => Some("unreachable_code"),
+            Self::UnusedVariable(_) | Self::UnusedParameter(_) => Some("unused_variables"),
+            Self::AdjacentHopsOnSameNode(_) => Some("adjacent_hops"),
+            Self::EmptyHop { .. } | Self::HopWithoutTableAccess { .. } => Some("idle_hops"),
+            Self::DeadWrite { .. } => Some("dead_writes"),
+            Self::DivisionByZero | Self::UnguardedDivision => Some("division_by_zero"),
+            Self::PossibleOverflow { .. } => Some("overflow"),
+            Self::UninitializedVariable(_) => Some("uninitialized"),
+            Self::EarlyReturn { .. } => Some("early_return"),
+            _ => None,
+        }
+    }
+}
+
+/// How serious a `Diagnostic` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    /// The program is rejected.
+    Error,
+    /// The program is accepted, but probably not as intended.
+    Warning,
+    /// Extra context for another diagnostic.
+    Note,
+}
+
+impl Severity {
+    /// Lower-case name used when printing.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Note => "note",
         }
     }
 }
 
-/// Formats a list of errors for display.
+/// An error, warning or note, with notes pointing at related code.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub error: AstError,
+    pub span: Option<Span>,
+    pub notes: Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    /// Creates a warning.
+    pub fn warning(error: AstError, span: Option<Span>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            error,
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Attaches a note to this diagnostic.
+    pub fn with_note(mut self, message: impl Into<String>, span: Option<Span>) -> Self {
+        self.notes.push(Self {
+            severity: Severity::Note,
+            error: AstError::Note(message.into()),
+            span,
+            notes: Vec::new(),
+        });
+        self
+    }
+
+    /// Returns this diagnostic as an error, e.g. for `--deny-warnings`.
+    pub fn promoted(mut self) -> Self {
+        if self.severity == Severity::Warning {
+            self.severity = Severity::Error;
+        }
+        self
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    /// One line, without the notes: `warning at line:column: Kind[code]: message`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "{} at {}: {}", self.severity.label(), span, self.error),
+            None => write!(f, "{}: {}", self.severity.label(), self.error),
+        }
+    }
+}
+
+/// Sorts diagnostics by where they are in the sources (file, then offset),
+/// those without a span last; diagnostics at the same place keep their order.
+pub fn sort_diagnostics(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by_key(|diagnostic| {
+        let span = diagnostic.span.as_ref();
+        (
+            span.is_none(),
+            span.and_then(|span| span.file.clone()),
+            span.map(|span| (span.start, span.end)),
+        )
+    });
+}
+
+impl From<SpannedError> for Diagnostic {
+    /// Converts an error, noting the declaration it refers back to.
+    fn from(error: SpannedError) -> Self {
+        let previous = error
+            .error
+            .previous_declaration()
+            .map(|(note, span)| (note, span.clone()));
+        let diagnostic = Self {
+            severity: Severity::Error,
+            error: error.error,
+            span: error.span,
+            notes: Vec::new(),
+        };
+        match previous {
+            Some((note, span)) => diagnostic.with_note(note, Some(span)),
+            None => diagnostic,
+        }
+    }
+}
+
+/// The next child of a parsed rule, or a parse error at `span` when the tree
+/// lacks it, so that a builder out of step with its grammar fails the parse
+/// instead of panicking.
+pub(crate) fn next_pair<'i, R: RuleType>(
+    pairs: &mut impl Iterator<Item = Pair<'i, R>>,
+    span: &Span,
+) -> Results<Pair<'i, R>> {
+    pairs.next().ok_or_else(|| {
+        vec![SpannedError {
+            error: AstError::ParseError("Malformed syntax tree: an element is missing".to_string()),
+            span: Some(span.clone()),
+        }]
+    })
+}
+
+/// Formats a list of errors for display, one line each.
 pub fn format_errors(errors: &[SpannedError]) -> String {
     errors
         .iter()
-        .map(|e| {
-            if let Some(span) = &e.span {
-                format!("Error at {}:{}: {}", span.line, span.column, e.error)
-            } else {
-                format!("Error: {}", e.error)
-            }
-        })
+        .map(SpannedError::to_string)
         .collect::<Vec<_>>()
         .join("\n")
 }
+
+/// Writes errors as the command line prints them (see `render_diagnostic`).
+pub fn render_errors(
+    out: &mut dyn Write,
+    errors: &[SpannedError],
+    source: &str,
+    color: bool,
+) -> io::Result<()> {
+    for error in errors {
+        render_diagnostic(out, &Diagnostic::from(error.clone()), source, color)?;
+    }
+    Ok(())
+}
+
+/// Writes a diagnostic and its notes as the command line prints them: the
+/// message, then the line it points at with the span underlined. Lines are
+/// taken from the diagnostic's file when it names one that can be read, else
+/// from `source`. With `color`, errors are red, warnings yellow and notes cyan,
+/// unless `colored` is told not to color.
+pub fn render_diagnostic(
+    out: &mut dyn Write,
+    diagnostic: &Diagnostic,
+    source: &str,
+    color: bool,
+) -> io::Result<()> {
+    let kind = diagnostic.error.error_type();
+    let code = diagnostic.error.code();
+    let (header, marker, hue) = match diagnostic.severity {
+        Severity::Error => (format!("{}[{}]", kind, code), "^", Color::Red),
+        Severity::Warning => (format!("warning: {}[{}]", kind, code), "^", Color::Yellow),
+        Severity::Note => ("note".to_string(), "-", Color::Cyan),
+    };
+    let paint = |text: String, bold: bool| -> String {
+        match (color, bold) {
+            (false, _) => text,
+            (true, false) => text.color(hue).to_string(),
+            (true, true) => text.color(hue).bold().to_string(),
+        }
+    };
+
+    if let Some(span) = &diagnostic.span {
+        let location = match &span.file {
+            Some(file) => format!(" in {}", file.display()),
+            None => String::new(),
+        };
+        writeln!(
+            out,
+            "{}: {} at line {}, column {}{}",
+            paint(header, true),
+            diagnostic.error.message(),
+            paint(span.line.to_string(), false),
+            paint(span.column.to_string(), false),
+            location
+        )?;
+        // Errors in imported files are shown against that file's source
+        let file_source = span
+            .file
+            .as_ref()
+            .and_then(|file| std::fs::read_to_string(file).ok());
+        let file_source = file_source.as_deref().unwrap_or(source);
+        if let Some(line_content) = file_source.lines().nth(span.line.saturating_sub(1)) {
+            let line_number = span.line.to_string();
+            let gutter = " ".repeat(line_number.len());
+            // Underline the whole span when it stays on one line
+            let width = if span.end_line == span.line {
+                span.end_column.saturating_sub(span.column).max(1)
+            } else {
+                1
+            };
+            writeln!(out, "{} |", gutter)?;
+            writeln!(out, "{} | {}", paint(line_number, false), line_content)?;
+            writeln!(
+                out,
+                "{} | {}{}",
+                gutter,
+                " ".repeat(span.column.saturating_sub(1)),
+                paint(marker.repeat(width), true)
+            )?;
+        }
+    } else {
+        writeln!(
+            out,
+            "{}: {}",
+            paint(header, true),
+            diagnostic.error.message()
+        )?;
+    }
+
+    for note in &diagnostic.notes {
+        render_diagnostic(out, note, source, color)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,564 @@
+//! The `incremental` module updates an analyzed `Program` after a text edit,
+//! rebuilding as little as possible. It is meant for editor integration, where
+//! the program is re-checked on every keystroke.
+//!
+//! # Overview
+//!
+//! - **TextEdit**: A replacement of a byte range in one source file.
+//! - **reparse**: Applies an edit to a program and re-analyzes what it affects.
+//! - **Reparsed**: Whether a single function or the whole program was rebuilt.
+//!
+//! # Strategy
+//!
+//! An edit inside a single function declaration re-parses and re-analyzes only
+//! that function. The new function is built into the existing arenas and takes
+//! the place of the old one in `root_functions`, `function_map` and the trivia
+//! map, its warnings replace the old ones, and every span after the edit is
+//! shifted. The replaced elements stay in the arenas, unreachable from the roots,
+//! until the elements built this way outnumber the rest: the next edit then
+//! rebuilds the program, so memory and the time an edit takes stay bounded over
+//! a long editing session.
+//!
+//! Any other edit (to nodes, tables, templates or `#if` directives, or one that
+//! does not parse as a single function) rebuilds the program from its in-memory
+//! source files. Edits that change imports are rejected; load the program
+//! again instead.
+//!
+//! When the edited source has errors, they are returned and the program is
+//! left as it was.
+
+use pest::iterators::Pair;
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::ast::ast_builder::{build_function_into, build_program_from_files, Rule};
+use crate::ast::trivia::collect_file_trivia;
+use crate::ast::*;
+
+/// Replaces the bytes in `range` of a source file with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    /// Index of the edited file in `Program::source_files`.
+    pub file: usize,
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl TextEdit {
+    /// Returns the smallest edit of `file` that turns `old` into `new`.
+    ///
+    /// Useful when the editor only has the new text, for example to catch up
+    /// after edits that `reparse` rejected.
+    pub fn between(file: usize, old: &str, new: &str) -> Self {
+        let prefix: usize = old
+            .chars()
+            .zip(new.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum();
+        let suffix: usize = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum();
+        TextEdit {
+            file,
+            range: prefix..old.len() - suffix,
+            text: new[prefix..new.len() - suffix].to_string(),
+        }
+    }
+}
+
+/// What `reparse` rebuilt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reparsed {
+    /// Only the function `old`, which has been replaced by `new`.
+    Function { old: FunctionId, new: FunctionId },
+    /// The whole program.
+    Program,
+}
+
+/// Applies `edit` to `program` and re-analyzes the affected declarations.
+///
+/// `defines` and `config` must be the ones the program was originally
+/// analyzed with.
+///
+/// ```rust
+/// use FMitF_rs::ast::incremental::{reparse, Reparsed, TextEdit};
+/// use FMitF_rs::ast::Symbol;
+///
+/// let source = "nodes { A } table T on A { primary int id; int v; }
+/// void f(int x) { hop on A { T[id: x].v = 1; } }
+/// void g(int x) { hop on A { T[id: x].v = 2; } }";
+/// let mut program = FMitF_rs::parse_and_analyze(source).unwrap();
+///
+/// let start = source.find("= 1").unwrap() + 2;
+/// let edit = TextEdit { file: 0, range: start..start + 1, text: "x + 10".to_string() };
+/// let result = reparse(&mut program, &edit, &Default::default(), &Default::default()).unwrap();
+/// assert!(matches!(result, Reparsed::Function { .. }));
+///
+/// // Later declarations move by the length difference of the edit
+/// let g = program.function_map[&Symbol::intern("g")];
+/// assert_eq!(program.functions[g].span.start, source.find("void g").unwrap() + 5);
+///
+/// // Replaced functions are dropped by a rebuild once they pile up
+/// let size = program.memory_stats().total_count();
+/// let mut rebuilds = 0;
+/// for i in 0..100 {
+///     let start = program.source_files[0].source.find("x + ").unwrap() + 4;
+///     let old = if i == 0 { "10" } else { &(i - 1).to_string() };
+///     let range = start..start + old.len();
+///     let edit = TextEdit { file: 0, range, text: i.to_string() };
+///     let result = reparse(&mut program, &edit, &Default::default(), &Default::default());
+///     rebuilds += (result.unwrap() == Reparsed::Program) as usize;
+/// }
+/// assert!(rebuilds > 0);
+/// assert!(program.memory_stats().total_count() <= 3 * size);
+/// ```
+pub fn reparse(
+    program: &mut Program,
+    edit: &TextEdit,
+    defines: &HashSet<String>,
+    config: &AnalysisConfig,
+) -> Results<Reparsed> {
+    let Some(file) = program.source_files.get(edit.file) else {
+        return Err(invalid_edit(format!(
+            "No source file with index {}",
+            edit.file
+        )));
+    };
+    let range = edit.range.clone();
+    if range.start > range.end
+        || range.end > file.source.len()
+        || !file.source.is_char_boundary(range.start)
+        || !file.source.is_char_boundary(range.end)
+    {
+        return Err(invalid_edit(format!(
+            "Edit range {}..{} is outside the source or splits a character",
+            range.start, range.end
+        )));
+    }
+
+    let mut source = file.source.clone();
+    source.replace_range(range.clone(), &edit.text);
+    let delta = edit.text.len() as isize - range.len() as isize;
+    let inactive = preprocessor::inactive_ranges(&source, file.path.as_ref(), defines)?;
+
+    // `#if` sections elsewhere must be unaffected for a function-local rebuild
+    let shifted: Vec<Range<usize>> = file
+        .inactive
+        .iter()
+        .map(|r| match r.start >= range.end {
+            true => shift(r.start, delta)..shift(r.end, delta),
+            false => r.clone(),
+        })
+        .collect();
+    if shifted == inactive && !needs_compaction(program) {
+        if let Some(old) = enclosing_function(program, edit) {
+            let update = FileUpdate {
+                index: edit.file,
+                source: &source,
+                inactive: &inactive,
+                delta,
+            };
+            if let Some(new) = reparse_function(program, old, &update, config)? {
+                return Ok(Reparsed::Function { old, new });
+            }
+        }
+    }
+
+    rebuild(program, edit.file, source, inactive, config)?;
+    Ok(Reparsed::Program)
+}
+
+/// The new contents of an edited file.
+struct FileUpdate<'a> {
+    index: usize,
+    source: &'a str,
+    inactive: &'a [Range<usize>],
+    /// Change in length caused by the edit.
+    delta: isize,
+}
+
+fn invalid_edit(message: String) -> Vec<SpannedError> {
+    vec![SpannedError {
+        error: AstError::InvalidEdit(message),
+        span: None,
+    }]
+}
+
+/// Whether the elements built by reparsing functions outnumber the others.
+fn needs_compaction(program: &Program) -> bool {
+    program.reparsed_elements * 2 > program.memory_stats().total_count()
+}
+
+fn shift(offset: usize, delta: isize) -> usize {
+    offset.saturating_add_signed(delta)
+}
+
+/// Returns the root function whose declaration contains the edited range.
+///
+/// Functions expanded from templates are never rebuilt on their own.
+fn enclosing_function(program: &Program, edit: &TextEdit) -> Option<FunctionId> {
+    let path = &program.source_files[edit.file].path;
+    program.root_functions.iter().copied().find(|&id| {
+        let span = &program.functions[id].span;
+        &span.file == path
+            && span.start <= edit.range.start
+            && edit.range.end <= span.end
+            && !program.template_instances.iter().any(|i| i.function == id)
+    })
+}
+
+/// Rebuilds the function `old_id` from the edited source.
+///
+/// Returns `None` if the edited text is not a single function declaration, in
+/// which case the program is unchanged and must be rebuilt as a whole.
+fn reparse_function(
+    program: &mut Program,
+    old_id: FunctionId,
+    update: &FileUpdate,
+    config: &AnalysisConfig,
+) -> Results<Option<FunctionId>> {
+    let path = program.source_files[update.index].path.clone();
+    let old_span = program.functions[old_id].span.clone();
+    let start = old_span.start;
+    let end = shift(old_span.end, update.delta);
+
+    // Blank everything but the function, keeping offsets and line numbers
+    let function_text =
+        preprocessor::blank_out(update.source, &[0..start, end..update.source.len()]);
+    let inner_inactive: Vec<Range<usize>> = update
+        .inactive
+        .iter()
+        .filter(|r| start <= r.start && r.end <= end)
+        .cloned()
+        .collect();
+    let text = preprocessor::blank_out(&function_text, &inner_inactive);
+
+    let Ok(pair) = loader::parse_program(&text, path.as_deref()) else {
+        return Ok(None);
+    };
+    let mut items = pair.into_inner().filter(|item| item.as_rule() != Rule::EOI);
+    let item = match (items.next(), items.next()) {
+        (Some(item), None) if item.as_rule() == Rule::function_declaration => item,
+        _ => return Ok(None),
+    };
+
+    let existing = ArenaLens::of(program);
+    let elements = program.memory_stats().total_count();
+    let (new_id, warnings) = replace_function(program, old_id, path.clone(), item, config)?;
+    program.reparsed_elements += program.memory_stats().total_count() - elements;
+
+    // Commit: replace the function's warnings, move spans after the edit and
+    // install the new source
+    program.warnings.retain(|warning| {
+        !warning.span.as_ref().is_some_and(|span| {
+            span.file == path && old_span.start <= span.start && span.end <= old_span.end
+        })
+    });
+    let index = LineIndex::new(update.source);
+    shift_spans(program, &existing, &mut |span| {
+        if span.file == path && span.start >= old_span.end {
+            index.relocate(span, update.delta);
+        }
+    });
+
+    let file = &mut program.source_files[update.index];
+    file.source = update.source.to_string();
+    file.inactive = update.inactive.to_vec();
+
+    replace_trivia(
+        program,
+        old_id,
+        new_id,
+        update.index,
+        function_text,
+        inner_inactive,
+    );
+    let warnings = config.apply(semantics_analysis::without_allowed_lints(program, warnings));
+    program.warnings.extend(warnings);
+    sort_diagnostics(&mut program.warnings);
+    Ok(Some(new_id))
+}
+
+/// Builds and analyzes `item` as the replacement of `old_id`, returning it
+/// with its warnings.
+///
+/// On success the new function has the old one's place in `root_functions`;
+/// on failure the old function is restored.
+fn replace_function(
+    program: &mut Program,
+    old_id: FunctionId,
+    file: Option<std::sync::Arc<std::path::Path>>,
+    item: Pair<Rule>,
+    config: &AnalysisConfig,
+) -> Results<(FunctionId, Vec<Diagnostic>)> {
+    let old_name = program.functions[old_id].name.clone();
+    let Some(position) = program.root_functions.iter().position(|&id| id == old_id) else {
+        return Err(invalid_edit(format!(
+            "Function {} is not a root function",
+            old_name
+        )));
+    };
+    program.root_functions.remove(position);
+    program.function_map.remove(&old_name);
+
+    let first_expr = program.expressions.len();
+    let result = build_function_into(program, file, item).and_then(|new_id| {
+        let expr_ids: Vec<ExpressionId> = program
+            .expressions
+            .iter()
+            .skip(first_expr)
+            .map(|(id, _)| id)
+            .collect();
+        name_resolver::resolve_function_names(program, new_id)
+            .and_then(|_| {
+                semantics_analysis::analyze_function_with_types(program, new_id, &expr_ids, config)
+            })
+            .inspect_err(|_| {
+                program.root_functions.pop();
+                program.function_map.remove(&program.functions[new_id].name);
+            })
+            .map(|warnings| (new_id, warnings))
+    });
+
+    match result {
+        Ok((new_id, warnings)) => {
+            program.root_functions.pop();
+            program.root_functions.insert(position, new_id);
+            Ok((new_id, warnings))
+        }
+        Err(errors) => {
+            program.root_functions.insert(position, old_id);
+            program.function_map.insert(old_name, old_id);
+            Err(errors)
+        }
+    }
+}
+
+/// Arena sizes before a function is rebuilt; elements past them belong to
+/// the new function and already have up-to-date spans.
+struct ArenaLens {
+    functions: usize,
+    parameters: usize,
+    hops: usize,
+    statements: usize,
+    expressions: usize,
+    variables: usize,
+}
+
+impl ArenaLens {
+    fn of(program: &Program) -> Self {
+        Self {
+            functions: program.functions.len(),
+            parameters: program.parameters.len(),
+            hops: program.hops.len(),
+            statements: program.statements.len(),
+            expressions: program.expressions.len(),
+            variables: program.variables.len(),
+        }
+    }
+}
+
+/// Applies `fix` to every span that existed before the rebuild.
+fn shift_spans(program: &mut Program, existing: &ArenaLens, fix: &mut dyn FnMut(&mut Span)) {
+    for (_, node) in program.nodes.iter_mut() {
+        fix(&mut node.span);
+    }
+    for (_, table) in program.tables.iter_mut() {
+        fix(&mut table.span);
+    }
+    for (_, field) in program.fields.iter_mut() {
+        fix(&mut field.span);
+    }
+    for (_, function) in program.functions.iter_mut().take(existing.functions) {
+        fix(&mut function.span);
+    }
+    for (_, param) in program.parameters.iter_mut().take(existing.parameters) {
+        fix(&mut param.span);
+    }
+    for (_, hop) in program.hops.iter_mut().take(existing.hops) {
+        fix(&mut hop.span);
+    }
+    for (_, stmt) in program.statements.iter_mut().take(existing.statements) {
+        fix(&mut stmt.span);
+    }
+    for (_, expr) in program.expressions.iter_mut().take(existing.expressions) {
+        fix(&mut expr.span);
+    }
+    for (_, var) in program.variables.iter_mut().take(existing.variables) {
+        fix(&mut var.defined_at);
+    }
+    for template in &mut program.templates {
+        fix(&mut template.span);
+    }
+    for instance in &mut program.template_instances {
+        fix(&mut instance.span);
+    }
+    for warning in &mut program.warnings {
+        let notes = warning.notes.iter_mut().map(|note| &mut note.span);
+        for span in std::iter::once(&mut warning.span).chain(notes).flatten() {
+            fix(span);
+        }
+    }
+
+    let trivia = &mut program.trivia;
+    let items = trivia
+        .leading
+        .values_mut()
+        .chain(trivia.dangling.values_mut());
+    for item in items.flatten().chain(trivia.trailing.values_mut()) {
+        match item {
+            Trivia::Comment { span, .. } | Trivia::Disabled { span, .. } => fix(span),
+            Trivia::BlankLines(_) => {}
+        }
+    }
+}
+
+/// Moves the trivia of the function `old_id` to `new_id`.
+///
+/// Trivia inside the function is collected again from `function_text`, the
+/// edited source with everything but the function blanked out; the leading and
+/// trailing trivia around the declaration are kept.
+fn replace_trivia(
+    program: &mut Program,
+    old_id: FunctionId,
+    new_id: FunctionId,
+    file_index: usize,
+    function_text: String,
+    inactive: Vec<Range<usize>>,
+) {
+    let old_anchors = function_anchors(program, old_id);
+    let new_anchors = function_anchors(program, new_id);
+
+    let trivia = &mut program.trivia;
+    let function_old = SyntaxAnchor::Function(old_id);
+    let leading = trivia.leading.remove(&function_old);
+    let trailing = trivia.trailing.remove(&function_old);
+    for (anchor, _) in &old_anchors {
+        trivia.leading.remove(anchor);
+        trivia.trailing.remove(anchor);
+        trivia.dangling.remove(anchor);
+    }
+
+    let file = SourceFile {
+        path: program.source_files[file_index].path.clone(),
+        source: function_text,
+        version: None,
+        imports: Vec::new(),
+        inactive,
+    };
+    collect_file_trivia(trivia, file_index, &file, &new_anchors);
+
+    let function_new = SyntaxAnchor::Function(new_id);
+    if let Some(leading) = leading {
+        trivia.leading.insert(function_new, leading);
+    }
+    if let Some(trailing) = trailing {
+        trivia.trailing.entry(function_new).or_insert(trailing);
+    }
+}
+
+/// Returns the function, its hops and all their statements with their spans.
+fn function_anchors(program: &Program, function_id: FunctionId) -> Vec<(SyntaxAnchor, Span)> {
+    let function = &program.functions[function_id];
+    let mut anchors = vec![(SyntaxAnchor::Function(function_id), function.span.clone())];
+    let mut statements = Vec::new();
+    for &hop_id in &function.hops {
+        let hop = &program.hops[hop_id];
+        anchors.push((SyntaxAnchor::Hop(hop_id), hop.span.clone()));
+        collect_statements(program, &hop.statements, &mut statements);
+    }
+    anchors.extend(statements.into_iter().map(|id| {
+        (
+            SyntaxAnchor::Statement(id),
+            program.statements[id].span.clone(),
+        )
+    }));
+    anchors
+}
+
+/// Appends `statements` and all statements nested in them to `out`.
+fn collect_statements(program: &Program, statements: &[StatementId], out: &mut Vec<StatementId>) {
+    for &stmt_id in statements {
+        out.push(stmt_id);
+        match &program.statements[stmt_id].node {
+            StatementKind::IfStmt(if_stmt) => {
+                collect_statements(program, &if_stmt.then_branch, out);
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    collect_statements(program, else_branch, out);
+                }
+            }
+            StatementKind::WhileStmt(while_stmt) => {
+                collect_statements(program, &while_stmt.body, out);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Rebuilds the whole program with the edited source of file `file_index`.
+fn rebuild(
+    program: &mut Program,
+    file_index: usize,
+    source: String,
+    inactive: Vec<Range<usize>>,
+    config: &AnalysisConfig,
+) -> Results<()> {
+    let mut files = program.source_files.clone();
+    let file = &mut files[file_index];
+    let active = preprocessor::blank_out(&source, &inactive);
+    let imports: Vec<String> = loader::parse_imports(&active, file.path.clone())
+        .into_iter()
+        .map(|(import, _)| import)
+        .collect();
+    if imports != file.imports {
+        return Err(invalid_edit(
+            "The edit changes imports; load the program again".to_string(),
+        ));
+    }
+    file.version = loader::read_version(&active, file.path.clone())?;
+    file.source = source;
+    file.inactive = inactive;
+
+    let mut rebuilt = build_program_from_files(files)?;
+    name_resolver::resolve_names(&mut rebuilt)?;
+    semantics_analysis::analyze_program_with_types(&mut rebuilt, config)?;
+    *program = rebuilt;
+    Ok(())
+}
+
+/// Start offsets of the lines of a source, for recomputing line and column numbers.
+struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(source: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    /// Returns the 1-based line and column of a byte offset.
+    fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let start = self.line_starts[line - 1];
+        (line, self.source[start..offset].chars().count() + 1)
+    }
+
+    /// Moves `span` by `delta` bytes.
+    fn relocate(&self, span: &mut Span, delta: isize) {
+        span.start = shift(span.start, delta);
+        span.end = shift(span.end, delta);
+        (span.line, span.column) = self.line_col(span.start);
+        (span.end_line, span.end_column) = self.line_col(span.end);
+    }
+}
@@ -0,0 +1,344 @@
+//! The `loader` module assembles multi-file programs.
+//! It follows `import "file.transact";` statements starting from a root source and
+//! returns every reachable file exactly once, dependencies before their importers.
+//!
+//! # Overview
+//!
+//! - **load_source_files**: Collects the root source, the files linked with it,
+//!   and all files they transitively import.
+//!
+//! # Features
+//!
+//! - Import paths are resolved relative to the directory of the importing file.
+//! - Files reached through several import chains are loaded only once.
+//! - Circular imports and unreadable files are reported at the offending import.
+//! - Conditional compilation (`#if FEATURE ... #endif`) is resolved per file before parsing.
+
+use pest::Parser;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::ast::ast_builder::{Rule, TransActParser};
+use crate::ast::preprocessor;
+use crate::ast::*;
+
+struct ModuleLoader<'d> {
+    defines: &'d HashSet<String>,
+    files: Vec<SourceFile>,
+    loaded: HashSet<PathBuf>,
+    in_progress: Vec<PathBuf>,
+}
+
+impl<'d> ModuleLoader<'d> {
+    fn new(defines: &'d HashSet<String>) -> Self {
+        Self {
+            defines,
+            files: Vec::new(),
+            loaded: HashSet::new(),
+            in_progress: Vec::new(),
+        }
+    }
+
+    /// Loads `source` and, recursively, everything it imports.
+    ///
+    /// `path` is the path as written by the user (used in spans), `canonical` the
+    /// resolved path used to detect repeated and circular imports.
+    fn load(
+        &mut self,
+        source: String,
+        path: Option<PathBuf>,
+        canonical: Option<PathBuf>,
+    ) -> Results<()> {
+        let file: Option<Arc<Path>> = path.as_deref().map(Arc::from);
+        let inactive = preprocessor::inactive_ranges(&source, file.as_ref(), self.defines)?;
+        let active = preprocessor::blank_out(&source, &inactive);
+        let version = read_version(&active, file.clone())?;
+        let imports = parse_imports(&active, file.clone());
+
+        if let Some(canonical) = &canonical {
+            self.in_progress.push(canonical.clone());
+        }
+
+        let base_dir = path
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        let mut errors = Vec::new();
+        let mut import_paths = Vec::new();
+        for (import, span) in imports {
+            import_paths.push(import.clone());
+            if let Err(mut errs) = self.load_import(&base_dir.join(&import), span) {
+                errors.append(&mut errs);
+            }
+        }
+
+        if canonical.is_some() {
+            self.in_progress.pop();
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        if let Some(canonical) = canonical {
+            self.loaded.insert(canonical);
+        }
+        self.files.push(SourceFile {
+            path: file,
+            source,
+            version,
+            imports: import_paths,
+            inactive,
+        });
+        Ok(())
+    }
+
+    /// Loads a file linked with the root source, as if the root imported it.
+    fn load_link(&mut self, target: &Path) -> Results<()> {
+        let error = |e: std::io::Error| {
+            vec![SpannedError {
+                error: AstError::ImportError(format!("Cannot read '{}': {}", target.display(), e)),
+                span: None,
+            }]
+        };
+        let canonical = target.canonicalize().map_err(error)?;
+        if self.loaded.contains(&canonical) {
+            return Ok(());
+        }
+        let source = std::fs::read_to_string(&canonical).map_err(error)?;
+        self.load(source, Some(target.to_path_buf()), Some(canonical))
+    }
+
+    fn load_import(&mut self, target: &Path, span: Span) -> Results<()> {
+        let canonical = target.canonicalize().map_err(|e| {
+            vec![SpannedError {
+                error: AstError::ImportError(format!(
+                    "Cannot resolve import '{}': {}",
+                    target.display(),
+                    e
+                )),
+                span: Some(span.clone()),
+            }]
+        })?;
+
+        if self.in_progress.contains(&canonical) {
+            return Err(vec![SpannedError {
+                error: AstError::CircularImport(target.display().to_string()),
+                span: Some(span),
+            }]);
+        }
+        if self.loaded.contains(&canonical) {
+            return Ok(());
+        }
+
+        let source = std::fs::read_to_string(&canonical).map_err(|e| {
+            vec![SpannedError {
+                error: AstError::ImportError(format!("Cannot read '{}': {}", target.display(), e)),
+                span: Some(span),
+            }]
+        })?;
+
+        self.load(source, Some(target.to_path_buf()), Some(canonical))
+    }
+}
+
+/// Reads the `version N;` pragma that may open `source`, before the file is
+/// parsed, so a version this tool cannot read is reported as such rather than
+/// as whatever syntax error it leads to. A malformed pragma is left to the parser.
+pub(crate) fn read_version(source: &str, file: Option<Arc<Path>>) -> Results<Option<u32>> {
+    let mut rest = source.trim_start();
+    while let Some(comment) = rest.strip_prefix("//") {
+        rest = comment
+            .find('\n')
+            .map_or("", |end| &comment[end..])
+            .trim_start();
+    }
+    let start = source.len() - rest.len();
+    let Some(number) = rest.strip_prefix("version").map(str::trim_start) else {
+        return Ok(None);
+    };
+    let digits = number
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(number.len());
+    if digits == 0 {
+        return Ok(None);
+    }
+    match number[..digits].parse::<u32>() {
+        Ok(version) if (1..=LANGUAGE_VERSION).contains(&version) => Ok(Some(version)),
+        version => {
+            let end = source.len() - number.len() + digits;
+            let span =
+                pest::Span::new(source, start, end).map(|span| Span::from_pest_in(span, file));
+            Err(vec![SpannedError {
+                error: AstError::UnsupportedVersion(version.unwrap_or(u32::MAX)),
+                span,
+            }])
+        }
+    }
+}
+
+/// How deeply brackets and prefix operators may nest in a source. The parser
+/// and the analyses recurse once per level, so a deeper source would overflow
+/// the stack of the thread reading it instead of failing to parse.
+pub const MAX_NESTING: usize = 32;
+
+/// What `check_nesting` skips and counts in a language.
+pub(crate) struct Lexical {
+    pub quote: char,
+    pub line_comment: &'static str,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    /// Prefix operators spelled as words, in lower case
+    pub prefix_words: &'static [&'static str],
+}
+
+const TRANSACT: Lexical = Lexical {
+    quote: '"',
+    line_comment: "//",
+    block_comment: None,
+    prefix_words: &[],
+};
+
+/// Reports the first place `source` nests deeper than `MAX_NESTING`, counting
+/// each bracket and each prefix operator before it as a level.
+pub(crate) fn check_nesting(
+    source: &str,
+    lexical: &Lexical,
+    file: Option<Arc<Path>>,
+) -> Results<()> {
+    // The levels each open bracket added, with the prefix operators before it
+    let mut open: Vec<usize> = Vec::new();
+    let mut depth = 0;
+    let mut prefix = 0;
+    let mut i = 0;
+    while let Some(c) = source[i..].chars().next() {
+        let rest = &source[i..];
+        let mut len = c.len_utf8();
+        if rest.starts_with(lexical.line_comment) {
+            i += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        }
+        if let Some((start, end)) = lexical.block_comment {
+            if let Some(comment) = rest.strip_prefix(start) {
+                i += comment
+                    .find(end)
+                    .map_or(rest.len(), |close| start.len() + close + end.len());
+                continue;
+            }
+        }
+        match c {
+            '(' | '[' | '{' => {
+                open.push(1 + prefix);
+                depth += 1 + prefix;
+                prefix = 0;
+            }
+            ')' | ']' | '}' => {
+                depth -= open.pop().unwrap_or(0);
+                prefix = 0;
+            }
+            '!' | '-' if !rest[1..].starts_with('=') => prefix += 1,
+            c if c == lexical.quote => {
+                len = rest[1..].find(c).map_or(rest.len(), |close| close + 2);
+                prefix = 0;
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                len = rest
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                let word = rest[..len].to_lowercase();
+                if lexical.prefix_words.contains(&word.as_str()) {
+                    prefix += 1;
+                } else {
+                    prefix = 0;
+                }
+            }
+            c if c.is_whitespace() => {}
+            _ => prefix = 0,
+        }
+        if depth + prefix > MAX_NESTING {
+            let span =
+                pest::Span::new(source, i, i + len).map(|span| Span::from_pest_in(span, file));
+            return Err(vec![SpannedError {
+                error: AstError::ParseError(format!(
+                    "Nesting is deeper than the {} levels allowed",
+                    MAX_NESTING
+                )),
+                span,
+            }]);
+        }
+        i += len;
+    }
+    Ok(())
+}
+
+/// Returns the path and span of each import statement of `source`.
+///
+/// Only the header of the file is parsed: imports precede every declaration, and
+/// the rest of the file is parsed once, when the program is built, which also
+/// reports its syntax errors.
+pub(crate) fn parse_imports(source: &str, file: Option<Arc<Path>>) -> Vec<(String, Span)> {
+    let Some(header) = TransActParser::parse(Rule::header, source)
+        .ok()
+        .and_then(|mut pairs| pairs.next())
+    else {
+        return Vec::new();
+    };
+
+    header
+        .into_inner()
+        .filter(|item| item.as_rule() == Rule::import_statement)
+        .filter_map(|item| {
+            let span = Span::from_pest_in(item.as_span(), file.clone());
+            let literal = item.into_inner().next()?.as_str();
+            Some((literal[1..literal.len() - 1].to_string(), span))
+        })
+        .collect()
+}
+
+/// Runs the Pest parser on a single file, labelling syntax errors with its path.
+pub(crate) fn parse_program<'a>(
+    source: &'a str,
+    file: Option<&Path>,
+) -> Results<pest::iterators::Pair<'a, Rule>> {
+    check_nesting(source, &TRANSACT, file.map(Arc::from))?;
+    let pairs = TransActParser::parse(Rule::program, source).map_err(|e| {
+        let e = match file {
+            Some(path) => e.with_path(&path.display().to_string()),
+            None => e,
+        };
+        vec![SpannedError {
+            error: AstError::ParseError(e.to_string()),
+            span: None,
+        }]
+    })?;
+
+    pairs.into_iter().next().ok_or_else(|| {
+        vec![SpannedError {
+            error: AstError::ParseError("No program found".to_string()),
+            span: None,
+        }]
+    })
+}
+
+/// Collects the root source and all files it transitively imports, dependencies first.
+/// The `links` are loaded with their imports before the root, as if it imported
+/// them, so the root stays the last file.
+///
+/// Imports in a source without a path are resolved relative to the working directory.
+/// `#if` sections are resolved in every file before its imports are read.
+pub fn load_source_files(
+    source: &str,
+    path: Option<&Path>,
+    links: &[PathBuf],
+    defines: &HashSet<String>,
+) -> Results<Vec<SourceFile>> {
+    let canonical = path.map(|path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+
+    let mut loader = ModuleLoader::new(defines);
+    for link in links {
+        loader.load_link(link)?;
+    }
+    loader.load(source.to_string(), path.map(Path::to_path_buf), canonical)?;
+    Ok(loader.files)
+}
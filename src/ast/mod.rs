@@ -11,10 +11,11 @@
 //! - **Program**: The main structure representing the entire parsed and analyzed program.
 //! - **NodeDef**: Represents a node definition in the AST.
 //! - **TableDeclaration**: Represents a table declaration with fields and primary keys.
-//! - **FieldDeclaration**: Represents a field in a table.
+//! - **FieldDeclaration**: Represents a field in a table, with the collation of string fields.
 //! - **FunctionDeclaration**: Represents a function with parameters and hops.
 //! - **StatementKind**: Represents various types of statements such as assignments, loops, and returns.
 //! - **ExpressionKind**: Represents expressions including literals, identifiers, and operations.
+//! - **Symbol**: An interned identifier, used for every name in the AST.
 //!
 //! The module also includes utility functions for parsing and analyzing the source code.
 //!
@@ -28,42 +29,92 @@
 //!
 //! To parse and analyze a source file, use the `parse_and_analyze` function:
 //!
-//! ```rust
-//! use crate::ast::parse_and_analyze;
+//! ```no_run
+//! use FMitF_rs::ast::parse_and_analyze;
 //!
 //! let source = "..."; // TransAct source code
 //! let program = parse_and_analyze(source).expect("Failed to parse and analyze");
 //! ```
 
 use id_arena::{Arena, Id};
-use std::collections::HashMap;
-
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+mod abort_placement;
+mod arithmetic_lints;
 mod ast_builder;
+pub mod config;
+mod dead_writes;
+mod definite_assignment;
+pub mod diff;
 pub mod errors;
+pub mod incremental;
+mod loader;
 mod name_resolver;
+mod preprocessor;
+pub mod program_builder;
+mod report;
 mod semantics_analysis;
+pub mod serialize;
+pub mod stable_id;
+mod symbol;
+pub mod trivia;
+pub mod visit;
 
 // Re-export only the essential types users need
-pub use errors::{AstError, Results, SpannedError};
+pub use abort_placement::AbortPolicy;
+pub use config::{AnalysisConfig, LintLevel};
+pub use errors::{sort_diagnostics, AstError, Diagnostic, Results, Severity, SpannedError};
+pub use loader::MAX_NESTING;
+pub(crate) use loader::{check_nesting, Lexical};
+pub use program_builder::ProgramBuilder;
+pub use report::{analyze_program_full, AnalysisReport, AnalysisStats, HopEffects};
+pub use semantics_analysis::{analyze_program, analyze_program_with_types};
+pub use symbol::Symbol;
+pub use trivia::{SyntaxAnchor, Trivia, TriviaMap};
 
 /// Represents a span in the source code with start and end positions, line, and column.
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+///
+/// Byte offsets (`start`, `end`) are complemented by 1-based line/column pairs for
+/// both ends, computed once when the span is created. `file` is the path of the
+/// originating source file, or `None` when the program was parsed from an
+/// in-memory string.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
     pub line: usize,
     pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    #[serde(deserialize_with = "serialize::shared_path")]
+    pub file: Option<Arc<Path>>,
 }
 
 impl Span {
     /// Converts a Pest span into a Span.
     pub fn from_pest(span: pest::Span) -> Self {
         let (line, column) = span.start_pos().line_col();
+        let (end_line, end_column) = span.end_pos().line_col();
         Self {
             start: span.start(),
             end: span.end(),
             line,
             column,
+            end_line,
+            end_column,
+            file: None,
+        }
+    }
+
+    /// Converts a Pest span into a Span that records its originating file.
+    pub fn from_pest_in(span: pest::Span, file: Option<Arc<Path>>) -> Self {
+        Self {
+            file,
+            ..Self::from_pest(span)
         }
     }
 }
@@ -76,6 +127,20 @@ impl Default for Span {
             end: 0,
             line: 1,
             column: 1,
+            end_line: 1,
+            end_column: 1,
+            file: None,
+        }
+    }
+}
+
+impl std::fmt::Display for Span {
+    /// Formats the start of the span as `file:line:column`, or `line:column`
+    /// when the span has no file.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}:{}", file.display(), self.line, self.column),
+            None => write!(f, "{}:{}", self.line, self.column),
         }
     }
 }
@@ -92,7 +157,7 @@ pub type ExpressionId = Id<Expression>;
 pub type VarId = Id<VarDecl>;
 pub type ScopeId = Id<Scope>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Spanned<T> {
     pub node: T,
     pub span: Span,
@@ -102,63 +167,226 @@ pub type Expression = Spanned<ExpressionKind>;
 pub type Statement = Spanned<StatementKind>;
 
 /// Main Program structure - this is what users get after processing.
-#[derive(Debug)]
+///
+/// Arena indices are deterministic: files are processed dependencies first and
+/// every arena is filled in source order, so identical input always yields
+/// identical indices. Use
+/// `stable_id::StableIds` for identifiers that survive edits elsewhere in the program.
+///
+/// A program serializes whole, to be read back with `serialize::read_program`;
+/// `to_json` writes the view of it meant for reading.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Program {
     // Arena storage - keep public for read access
+    #[serde(with = "serialize::arena")]
     pub nodes: Arena<NodeDef>,
+    #[serde(with = "serialize::arena")]
     pub tables: Arena<TableDeclaration>,
+    #[serde(with = "serialize::arena")]
     pub fields: Arena<FieldDeclaration>,
+    #[serde(with = "serialize::arena")]
     pub functions: Arena<FunctionDeclaration>,
+    #[serde(with = "serialize::arena")]
     pub hops: Arena<HopBlock>,
+    #[serde(with = "serialize::arena")]
     pub parameters: Arena<ParameterDecl>,
+    #[serde(with = "serialize::arena")]
     pub statements: Arena<Statement>,
+    #[serde(with = "serialize::arena")]
     pub expressions: Arena<Expression>,
+    #[serde(with = "serialize::arena")]
     pub variables: Arena<VarDecl>,
+    #[serde(with = "serialize::arena")]
     pub scopes: Arena<Scope>,
 
     // Root collections - public for iteration
+    #[serde(with = "serialize::ids")]
     pub root_nodes: Vec<NodeId>,
+    #[serde(with = "serialize::ids")]
     pub root_tables: Vec<TableId>,
+    #[serde(with = "serialize::ids")]
     pub root_functions: Vec<FunctionId>,
 
     // Lookup maps - public for convenience
-    pub node_map: HashMap<String, NodeId>,
-    pub table_map: HashMap<String, TableId>,
-    pub function_map: HashMap<String, FunctionId>,
+    #[serde(with = "serialize::sorted_ids")]
+    pub node_map: HashMap<Symbol, NodeId>,
+    #[serde(with = "serialize::sorted_ids")]
+    pub table_map: HashMap<Symbol, TableId>,
+    #[serde(with = "serialize::sorted_ids")]
+    pub function_map: HashMap<Symbol, FunctionId>,
 
     // Resolution results - public for type checking access
+    #[serde(with = "serialize::id_map")]
     pub resolutions: HashMap<ExpressionId, VarId>,
+    #[serde(with = "serialize::id_keys")]
     pub var_types: HashMap<VarId, TypeName>,
+
+    // Source files the program was assembled from, dependencies first
+    pub source_files: Vec<SourceFile>,
+
+    // Comments and blank lines, attached to declarations, hops and statements
+    pub trivia: TriviaMap,
+
+    // Templates and the functions expanded from them
+    pub templates: Vec<TemplateDeclaration>,
+    pub template_instances: Vec<TemplateInstance>,
+
+    // Warnings from analysis; errors are returned instead of stored, except for
+    // warnings of lints denied by the `AnalysisConfig`, which have `Severity::Error`
+    pub warnings: Vec<Diagnostic>,
+
+    // Arena elements built by incremental reparsing since the program was last
+    // built whole; the functions they replaced are still in the arenas
+    #[serde(skip)]
+    pub reparsed_elements: usize,
+}
+
+impl Program {
+    /// The elements of each arena and the bytes they take.
+    pub fn memory_stats(&self) -> crate::memory::MemoryStats {
+        let mut stats = crate::memory::MemoryStats::default();
+        stats.add("nodes", &self.nodes);
+        stats.add("tables", &self.tables);
+        stats.add("fields", &self.fields);
+        stats.add("functions", &self.functions);
+        stats.add("hops", &self.hops);
+        stats.add("parameters", &self.parameters);
+        stats.add("statements", &self.statements);
+        stats.add("expressions", &self.expressions);
+        stats.add("variables", &self.variables);
+        stats.add("scopes", &self.scopes);
+        stats
+    }
+
+    /// The language version the root file declares, if any.
+    pub fn version(&self) -> Option<u32> {
+        self.source_files.last().and_then(|file| file.version)
+    }
+
+    /// The field an expression reads, if it is a resolved table field access.
+    pub fn accessed_field(&self, expr_id: ExpressionId) -> Option<FieldId> {
+        match &self.expressions[expr_id].node {
+            ExpressionKind::TableFieldAccess { resolved_field, .. } => *resolved_field,
+            _ => None,
+        }
+    }
+
+    /// The collation a comparison between two string expressions uses: that of
+    /// the field either side reads, or binary if neither reads a field. Fields of
+    /// different collations are rejected by semantic analysis.
+    pub fn comparison_collation(&self, left: ExpressionId, right: ExpressionId) -> Collation {
+        self.accessed_field(left)
+            .or_else(|| self.accessed_field(right))
+            .map(|field_id| self.fields[field_id].collation())
+            .unwrap_or_default()
+    }
+}
+
+/// The latest version of TransAct this tool reads. A file may declare the
+/// version it is written in with `version N;` before anything else, so that
+/// later versions of the grammar reject it rather than misparse it.
+///
+/// ```
+/// use FMitF_rs::parse_and_analyze;
+///
+/// let program = parse_and_analyze("version 1;\nnodes { A }").unwrap();
+/// assert_eq!(program.version(), Some(1));
+/// let errors = parse_and_analyze("version 2;\nnodes { A } node B;").unwrap_err();
+/// assert_eq!(errors[0].error.error_type(), "UnsupportedVersion");
+/// ```
+pub const LANGUAGE_VERSION: u32 = 1;
+
+/// A source file that contributed declarations to a `Program`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceFile {
+    /// Path of the file, `None` for in-memory sources.
+    pub path: Option<Arc<Path>>,
+    pub source: String,
+    /// The language version its `version N;` pragma declares.
+    pub version: Option<u32>,
+    /// Import paths exactly as written in the file's `import` statements.
+    pub imports: Vec<String>,
+    /// Byte ranges removed by conditional compilation: directive lines and
+    /// disabled `#if` sections.
+    pub inactive: Vec<Range<usize>>,
+}
+
+impl SourceFile {
+    /// Returns the source as seen by the parser, with inactive ranges blanked out.
+    pub fn active_source(&self) -> String {
+        preprocessor::blank_out(&self.source, &self.inactive)
+    }
 }
 
 /// Represents a node definition in the AST.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct NodeDef {
-    pub name: String,
+    pub name: Symbol,
     pub span: Span,
 }
 
 /// Represents a table declaration in the AST.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TableDeclaration {
-    pub name: String,
+    pub name: Symbol,
+    #[serde(with = "serialize::id")]
     pub node: NodeId,
+    #[serde(with = "serialize::ids")]
     pub fields: Vec<FieldId>,
+    #[serde(with = "serialize::ids")]
     pub primary_keys: Vec<FieldId>,
     pub span: Span,
 }
 
 /// Represents a field declaration in the AST.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct FieldDeclaration {
     pub field_type: TypeName,
-    pub field_name: String,
+    pub field_name: Symbol,
     pub is_primary: bool,
+    /// Collation given with `collate`, only allowed on string fields
+    pub collation: Option<Collation>,
     pub span: Span,
 }
 
+impl FieldDeclaration {
+    /// How the values of this field compare.
+    pub fn collation(&self) -> Collation {
+        self.collation.unwrap_or_default()
+    }
+}
+
+/// How string values are ordered and compared for equality.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Collation {
+    /// Byte by byte
+    #[default]
+    Binary,
+    /// Byte by byte after folding to lowercase
+    NoCase,
+}
+
+impl Collation {
+    /// Looks up a collation by the name used in `collate` clauses.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "binary" => Some(Self::Binary),
+            "nocase" => Some(Self::NoCase),
+            _ => None,
+        }
+    }
+
+    /// The name used in `collate` clauses.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Binary => "binary",
+            Self::NoCase => "nocase",
+        }
+    }
+}
+
 /// Represents the type of a field or variable.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TypeName {
     Int,
     Float,
@@ -167,41 +395,69 @@ pub enum TypeName {
 }
 
 /// Represents a function declaration in the AST.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDeclaration {
     pub return_type: ReturnType,
-    pub name: String,
+    pub name: Symbol,
+    #[serde(with = "serialize::ids")]
     pub parameters: Vec<ParameterId>,
+    #[serde(with = "serialize::ids")]
     pub hops: Vec<HopId>,
     pub span: Span,
 }
 
+/// Represents a `template` declaration in the AST.
+///
+/// The body of a template is not built on its own; each instantiation expands
+/// it into a regular function whose spans point back into the template.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateDeclaration {
+    pub name: Symbol,
+    pub params: Vec<Symbol>,
+    pub span: Span,
+}
+
+/// Represents an `instantiate` statement and the function it produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateInstance {
+    pub template: Symbol,
+    /// Template arguments as written in the source.
+    pub args: Vec<String>,
+    #[serde(with = "serialize::id")]
+    pub function: FunctionId,
+    pub span: Span,
+}
+
 /// Represents the return type of a function.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ReturnType {
     Void,
     Type(TypeName),
 }
 
 /// Represents a parameter declaration in the AST.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParameterDecl {
     pub param_type: TypeName,
-    pub param_name: String,
+    pub param_name: Symbol,
     pub span: Span,
+    #[serde(with = "serialize::opt_id")]
+    pub resolved_var: Option<VarId>,
 }
 
 /// Represents a hop block in the AST.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HopBlock {
-    pub node_name: String,
+    pub node_name: Symbol,
+    #[serde(with = "serialize::ids")]
     pub statements: Vec<StatementId>,
     pub span: Span,
+    #[serde(with = "serialize::opt_id")]
     pub resolved_node: Option<NodeId>,
 }
 
 /// Represents a statement in the AST.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum StatementKind {
     Assignment(AssignmentStatement),
     MultiAssignment(MultiAssignmentStatement),
@@ -217,115 +473,149 @@ pub enum StatementKind {
 }
 
 /// Represents an assignment statement in the AST.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssignmentStatement {
-    pub table_name: String,
-    pub pk_fields: Vec<String>,
+    pub table_name: Symbol,
+    pub pk_fields: Vec<Symbol>,
+    #[serde(with = "serialize::ids")]
     pub pk_exprs: Vec<ExpressionId>,
-    pub field_name: String,
+    pub field_name: Symbol,
+    #[serde(with = "serialize::id")]
     pub rhs: ExpressionId,
+    #[serde(with = "serialize::opt_id")]
     pub resolved_table: Option<TableId>,
+    #[serde(with = "serialize::opt_ids")]
     pub resolved_pk_fields: Vec<Option<FieldId>>,
+    #[serde(with = "serialize::opt_id")]
     pub resolved_field: Option<FieldId>,
 }
 
 /// Represents a multi-assignment statement in the AST.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MultiAssignmentStatement {
-    pub table_name: String,
-    pub pk_fields: Vec<String>,
+    pub table_name: Symbol,
+    pub pk_fields: Vec<Symbol>,
+    #[serde(with = "serialize::ids")]
     pub pk_exprs: Vec<ExpressionId>,
     pub assignments: Vec<MultiAssignmentPair>,
+    #[serde(with = "serialize::opt_id")]
     pub resolved_table: Option<TableId>,
+    #[serde(with = "serialize::opt_ids")]
     pub resolved_pk_fields: Vec<Option<FieldId>>,
 }
 
 /// Represents a field:value pair in a multi-assignment.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MultiAssignmentPair {
-    pub field_name: String,
+    pub field_name: Symbol,
+    #[serde(with = "serialize::id")]
     pub rhs: ExpressionId,
+    #[serde(with = "serialize::opt_id")]
     pub resolved_field: Option<FieldId>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VarAssignmentStatement {
-    pub var_name: String,
+    pub var_name: Symbol,
+    #[serde(with = "serialize::id")]
     pub rhs: ExpressionId,
+    #[serde(with = "serialize::opt_id")]
     pub resolved_var: Option<VarId>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IfStatement {
+    #[serde(with = "serialize::id")]
     pub condition: ExpressionId,
+    #[serde(with = "serialize::ids")]
     pub then_branch: Vec<StatementId>,
+    #[serde(with = "serialize::ids_opt")]
     pub else_branch: Option<Vec<StatementId>>,
+    /// The `else` keyword, if written in the source
+    pub else_span: Option<Span>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WhileStatement {
+    #[serde(with = "serialize::id")]
     pub condition: ExpressionId,
+    #[serde(with = "serialize::ids")]
     pub body: Vec<StatementId>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VarDeclStatement {
     pub var_type: TypeName,
-    pub var_name: String,
-    pub init_value: ExpressionId,
+    pub var_name: Symbol,
+    /// `None` for a declaration without a value, like `int x;`.
+    #[serde(with = "serialize::opt_id")]
+    pub init_value: Option<ExpressionId>,
+    #[serde(with = "serialize::opt_id")]
+    pub resolved_var: Option<VarId>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReturnStatement {
+    #[serde(with = "serialize::opt_id")]
     pub value: Option<ExpressionId>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AbortStatement;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BreakStatement;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ContinueStatement;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExpressionKind {
-    Ident(String),
+    Ident(Symbol),
     IntLit(i64),
     FloatLit(f64),
     StringLit(String),
     BoolLit(bool),
     TableFieldAccess {
-        table_name: String,
-        pk_fields: Vec<String>,
+        table_name: Symbol,
+        pk_fields: Vec<Symbol>,
+        #[serde(with = "serialize::ids")]
         pk_exprs: Vec<ExpressionId>,
-        field_name: String,
+        field_name: Symbol,
+        #[serde(with = "serialize::opt_id")]
         resolved_table: Option<TableId>,
+        #[serde(with = "serialize::opt_ids")]
         resolved_pk_fields: Vec<Option<FieldId>>,
+        #[serde(with = "serialize::opt_id")]
         resolved_field: Option<FieldId>,
         resolved_type: Option<TypeName>,
     },
     UnaryOp {
         op: UnaryOp,
+        #[serde(with = "serialize::id")]
         expr: ExpressionId,
         resolved_type: Option<TypeName>,
     },
     BinaryOp {
+        #[serde(with = "serialize::id")]
         left: ExpressionId,
         op: BinaryOp,
+        #[serde(with = "serialize::id")]
         right: ExpressionId,
         resolved_type: Option<TypeName>,
     },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum UnaryOp {
     Not,
     Neg,
+    /// Folds a string to lowercase. Has no syntax; the CFG builder inserts it
+    /// around the operands of `nocase` comparisons.
+    FoldCase,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum BinaryOp {
     Add,
     Sub,
@@ -341,32 +631,133 @@ pub enum BinaryOp {
     Or,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VarDecl {
-    pub name: String,
+    pub name: Symbol,
     pub ty: TypeName,
     pub kind: VarKind,
     pub defined_at: Span,
+    #[serde(with = "serialize::id")]
     pub scope: ScopeId,
 }
 
 /// Represents the kind of a variable (parameter or local).
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum VarKind {
     Parameter,
     Local,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Scope {
+    #[serde(with = "serialize::opt_id")]
     pub parent: Option<ScopeId>,
-    pub variables: HashMap<String, VarId>,
+    #[serde(with = "serialize::sorted_ids")]
+    pub variables: HashMap<Symbol, VarId>,
 }
 
 /// Parses and analyzes the source code to produce a `Program`.
+///
+/// `import` statements are resolved relative to the current working directory.
 pub fn parse_and_analyze(source: &str) -> Results<Program> {
-    let mut program = ast_builder::parse_and_build(source)?;
+    parse_and_analyze_with_path(source, None)
+}
+
+/// Parses and analyzes the source code of the file at `path`, following its imports.
+pub fn parse_and_analyze_with_path(source: &str, path: Option<&Path>) -> Results<Program> {
+    parse_and_analyze_with_defines(source, path, &HashSet::new(), &AnalysisConfig::default())
+}
+
+/// Like `parse_and_analyze_with_path`, keeping the `#if` sections of every
+/// feature in `defines` and analyzing with `config`.
+pub fn parse_and_analyze_with_defines(
+    source: &str,
+    path: Option<&Path>,
+    defines: &HashSet<String>,
+    config: &AnalysisConfig,
+) -> Results<Program> {
+    parse_and_analyze_linked(source, path, &[], defines, config)
+}
+
+/// Like `parse_and_analyze_with_defines`, linking the files in `links` into
+/// the program as if the source imported them, so that transactions maintained
+/// in separate files are analyzed together.
+pub fn parse_and_analyze_linked(
+    source: &str,
+    path: Option<&Path>,
+    links: &[PathBuf],
+    defines: &HashSet<String>,
+    config: &AnalysisConfig,
+) -> Results<Program> {
+    let mut program = parse_and_resolve_linked(source, path, links, defines)?;
+    semantics_analysis::analyze_program_with_types(&mut program, config)?;
+    Ok(program)
+}
+
+/// The files a source imports, directly or through other files, reading only
+/// their `import` statements.
+pub fn imported_files(
+    source: &str,
+    path: &Path,
+    defines: &HashSet<String>,
+) -> Results<Vec<PathBuf>> {
+    let files = loader::load_source_files(source, Some(path), &[], defines)?;
+    // The root is loaded after its imports
+    Ok(files
+        .iter()
+        .rev()
+        .skip(1)
+        .filter_map(|file| file.path.as_deref().map(Path::to_path_buf))
+        .collect())
+}
+
+/// Parses the source code and resolves names, without semantic analysis, for
+/// callers that run it themselves, e.g. with `analyze_program_full`.
+pub fn parse_and_resolve(
+    source: &str,
+    path: Option<&Path>,
+    defines: &HashSet<String>,
+) -> Results<Program> {
+    parse_and_resolve_linked(source, path, &[], defines)
+}
+
+/// Like `parse_and_resolve`, linking the files in `links` as
+/// `parse_and_analyze_linked` does.
+pub fn parse_and_resolve_linked(
+    source: &str,
+    path: Option<&Path>,
+    links: &[PathBuf],
+    defines: &HashSet<String>,
+) -> Results<Program> {
+    build_and_resolve(load_sources(source, path, links, defines)?)
+}
+
+/// The first step of `parse_and_resolve_linked`: the source, the files linked
+/// with it and their imports, each read with its `#if` sections resolved.
+pub fn load_sources(
+    source: &str,
+    path: Option<&Path>,
+    links: &[PathBuf],
+    defines: &HashSet<String>,
+) -> Results<Vec<SourceFile>> {
+    loader::load_source_files(source, path, links, defines)
+}
+
+/// The second step of `parse_and_resolve_linked`: the program of the loaded
+/// files, with its names resolved.
+pub fn build_and_resolve(files: Vec<SourceFile>) -> Results<Program> {
+    let mut program = ast_builder::build_program_from_files(files)?;
     name_resolver::resolve_names(&mut program)?;
-    semantics_analysis::analyze_program_with_types(&mut program)?;
     Ok(program)
 }
+
+/// Reads the file at `path` and parses and analyzes it together with its imports.
+pub fn parse_and_analyze_file(path: &Path) -> Results<Program> {
+    let source = std::fs::read_to_string(path).map_err(|e| {
+        vec![SpannedError {
+            error: AstError::ImportError(format!("Cannot read '{}': {}", path.display(), e)),
+            span: None,
+        }]
+    })?;
+    parse_and_analyze_with_path(&source, Some(path))
+}
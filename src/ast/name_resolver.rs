@@ -12,12 +12,26 @@
 //! - Scope management for variables and functions.
 //! - Error reporting for undeclared identifiers and duplicate declarations.
 //! - Resolution of cross-node references and primary key fields.
+//! - Normalization of primary key arguments, which are named and may be given in any
+//!   order, to the declaration order of the table's primary key.
+//!
+//! # Scoping rules
+//!
+//! - Parameters and the locals declared directly in a hop share the function scope, so a
+//!   local declared in one hop is visible in the hops after it.
+//! - The bodies of `if`, `else` and `while` open a block scope. Locals declared there are
+//!   not visible after the block ends; using one is reported as `VariableOutOfScope`.
+//! - Shadowing is not allowed. A local named like a parameter is a `ShadowedParameter`
+//!   error, and a local named like a variable of an enclosing block is a
+//!   `ShadowedVariable` error. Redeclaring a name in the same scope is a
+//!   `DuplicateVariable` error.
+//! - Sibling blocks are separate, so each branch of an `if` may declare the same name.
 //!
 //! # Usage
 //!
 //! Use the `resolve_names` function to perform name resolution:
 //!
-//! ```rust
+//! ```ignore
 //! use crate::ast::name_resolver::resolve_names;
 //! use crate::ast::Program;
 //!
@@ -35,6 +49,9 @@ pub struct NameResolver<'p> {
     // Symbol table - stack of scopes
     scope_stack: Vec<ScopeId>,
     current_scope: Option<ScopeId>,
+
+    // Locals of block scopes that have ended in the current function
+    ended: HashMap<Symbol, Span>,
 }
 
 impl<'p> NameResolver<'p> {
@@ -45,6 +62,7 @@ impl<'p> NameResolver<'p> {
             errors: Vec::new(),
             scope_stack: Vec::new(),
             current_scope: None,
+            ended: HashMap::new(),
         }
     }
 
@@ -54,7 +72,7 @@ impl<'p> NameResolver<'p> {
     /// including parameters, hops, and statements.
     pub fn resolve(mut self) -> Results<()> {
         // Resolve all functions
-        let function_ids: Vec<_> = self.program.root_functions.iter().copied().collect();
+        let function_ids: Vec<_> = self.program.root_functions.to_vec();
         for func_id in function_ids {
             self.resolve_function(func_id);
         }
@@ -78,16 +96,13 @@ impl<'p> NameResolver<'p> {
         });
 
         self.push_scope(func_scope);
+        self.ended.clear();
 
         // Add parameters to function scope
-        let param_ids: Vec<ParameterId> = self.program.functions[func_id]
-            .parameters
-            .iter()
-            .copied()
-            .collect();
+        let param_ids: Vec<ParameterId> = self.program.functions[func_id].parameters.to_vec();
 
         // Collect parameter data to avoid borrowing issues
-        let params_to_declare: Vec<(String, TypeName, Span)> = param_ids
+        let params_to_declare: Vec<(Symbol, TypeName, Span)> = param_ids
             .iter()
             .map(|&param_id| {
                 let param_decl = &self.program.parameters[param_id];
@@ -99,16 +114,13 @@ impl<'p> NameResolver<'p> {
             })
             .collect();
 
-        for (name, ty, span) in params_to_declare {
-            self.declare_variable(&name, ty, VarKind::Parameter, span, func_scope);
+        for (&param_id, (name, ty, span)) in param_ids.iter().zip(params_to_declare) {
+            let var_id = self.declare_variable(name, ty, VarKind::Parameter, span, func_scope);
+            self.program.parameters[param_id].resolved_var = Some(var_id);
         }
 
         // Resolve each hop (but don't create scope for hops)
-        let hop_ids: Vec<_> = self.program.functions[func_id]
-            .hops
-            .iter()
-            .copied()
-            .collect();
+        let hop_ids: Vec<_> = self.program.functions[func_id].hops.to_vec();
         for hop_id in hop_ids {
             self.resolve_hop(hop_id);
         }
@@ -127,16 +139,12 @@ impl<'p> NameResolver<'p> {
             self.program.hops[hop_id].resolved_node = Some(node_id);
         } else {
             let hop_span = self.program.hops[hop_id].span.clone();
-            self.error_at(&hop_span, AstError::UndeclaredNode(node_name));
+            self.error_at(&hop_span, AstError::UndeclaredNode(node_name.to_string()));
             return;
         }
 
         // Hops do NOT create their own scopes - resolve statements in current function scope
-        let stmt_ids: Vec<_> = self.program.hops[hop_id]
-            .statements
-            .iter()
-            .copied()
-            .collect();
+        let stmt_ids: Vec<_> = self.program.hops[hop_id].statements.to_vec();
         for stmt_id in stmt_ids {
             self.resolve_statement(stmt_id);
         }
@@ -154,29 +162,42 @@ impl<'p> NameResolver<'p> {
         match stmt_kind {
             StatementKind::VarDecl(var_decl) => {
                 // Resolve initializer first
-                self.resolve_expression(var_decl.init_value);
+                if let Some(init_value) = var_decl.init_value {
+                    self.resolve_expression(init_value);
+                }
 
-                // Check for duplicate in the current scope (no shadowing within same scope)
-                if let Some(current_scope_id) = self.current_scope {
-                    let current_scope = &self.program.scopes[current_scope_id];
-                    if current_scope.variables.contains_key(&var_decl.var_name) {
-                        self.error_at(
-                            &stmt_span,
-                            AstError::DuplicateVariable(var_decl.var_name.clone()),
-                        );
-                        return;
-                    }
+                // No shadowing: the name must not be visible yet
+                let current_scope_id = self.current_scope.unwrap();
+                if let Some(existing) = self.lookup_variable(&var_decl.var_name) {
+                    let existing = &self.program.variables[existing];
+                    let name = var_decl.var_name.to_string();
+                    let previous = existing.defined_at.clone();
+                    let error = if matches!(existing.kind, VarKind::Parameter) {
+                        AstError::ShadowedParameter { name, previous }
+                    } else if existing.scope == current_scope_id {
+                        AstError::DuplicateVariable { name, previous }
+                    } else {
+                        AstError::ShadowedVariable { name, previous }
+                    };
+                    self.error_at(&stmt_span, error);
+                    return;
                 }
 
                 // Declare the variable in the current scope
-                let current_scope_id = self.current_scope.unwrap();
-                self.declare_variable(
-                    &var_decl.var_name,
+                let var_id = self.declare_variable(
+                    var_decl.var_name,
                     var_decl.var_type,
                     VarKind::Local,
                     stmt_span.clone(),
                     current_scope_id,
                 );
+
+                // Update the statement with the declared variable
+                if let StatementKind::VarDecl(ref mut var_decl_mut) =
+                    &mut self.program.statements[stmt_id].node
+                {
+                    var_decl_mut.resolved_var = Some(var_id);
+                }
             }
             StatementKind::VarAssignment(var_assign) => {
                 // Resolve RHS expression
@@ -185,10 +206,7 @@ impl<'p> NameResolver<'p> {
                 // Look up the variable
                 let var_id = self.lookup_variable(&var_assign.var_name);
                 if var_id.is_none() {
-                    self.error_at(
-                        &stmt_span,
-                        AstError::UndeclaredVariable(var_assign.var_name.clone()),
-                    );
+                    self.undeclared_variable(var_assign.var_name, &stmt_span);
                 } else {
                     // Update the statement with resolved variable
                     if let StatementKind::VarAssignment(ref mut var_assign_mut) =
@@ -263,8 +281,8 @@ impl<'p> NameResolver<'p> {
                             self.error_at(
                                 &stmt_span,
                                 AstError::UndeclaredField {
-                                    table: assign_copy.table_name.clone(),
-                                    field: field_name,
+                                    table: assign_copy.table_name.to_string(),
+                                    field: field_name.to_string(),
                                 },
                             );
                         }
@@ -273,12 +291,19 @@ impl<'p> NameResolver<'p> {
                             self.error_at(
                                 &stmt_span,
                                 AstError::UndeclaredField {
-                                    table: assign_copy.table_name.clone(),
-                                    field: field_name,
+                                    table: assign_copy.table_name.to_string(),
+                                    field: field_name.to_string(),
                                 },
                             );
                         }
 
+                        sort_keys(
+                            &self.program.tables[table_id].primary_keys,
+                            &mut assign_copy.pk_fields,
+                            &mut assign_copy.pk_exprs,
+                            &mut assign_copy.resolved_pk_fields,
+                        );
+
                         // Update the statement with resolved assignment
                         if let StatementKind::Assignment(ref mut assign_mut) =
                             &mut self.program.statements[stmt_id].node
@@ -289,7 +314,7 @@ impl<'p> NameResolver<'p> {
                     None => {
                         self.error_at(
                             &stmt_span,
-                            AstError::UndeclaredTable(assign_copy.table_name.clone()),
+                            AstError::UndeclaredTable(assign_copy.table_name.to_string()),
                         );
                     }
                 }
@@ -363,8 +388,8 @@ impl<'p> NameResolver<'p> {
                             self.error_at(
                                 &stmt_span,
                                 AstError::UndeclaredField {
-                                    table: multi_assign_copy.table_name.clone(),
-                                    field: field_name,
+                                    table: multi_assign_copy.table_name.to_string(),
+                                    field: field_name.to_string(),
                                 },
                             );
                         }
@@ -373,12 +398,19 @@ impl<'p> NameResolver<'p> {
                             self.error_at(
                                 &stmt_span,
                                 AstError::UndeclaredField {
-                                    table: multi_assign_copy.table_name.clone(),
-                                    field: field_name,
+                                    table: multi_assign_copy.table_name.to_string(),
+                                    field: field_name.to_string(),
                                 },
                             );
                         }
 
+                        sort_keys(
+                            &self.program.tables[table_id].primary_keys,
+                            &mut multi_assign_copy.pk_fields,
+                            &mut multi_assign_copy.pk_exprs,
+                            &mut multi_assign_copy.resolved_pk_fields,
+                        );
+
                         // Update the statement with resolved multi-assignment
                         if let StatementKind::MultiAssignment(ref mut multi_assign_mut) =
                             &mut self.program.statements[stmt_id].node
@@ -389,7 +421,7 @@ impl<'p> NameResolver<'p> {
                     None => {
                         self.error_at(
                             &stmt_span,
-                            AstError::UndeclaredTable(multi_assign_copy.table_name.clone()),
+                            AstError::UndeclaredTable(multi_assign_copy.table_name.to_string()),
                         );
                     }
                 }
@@ -430,12 +462,18 @@ impl<'p> NameResolver<'p> {
         self.push_scope(block_scope);
 
         // Resolve each statement
-        let stmt_ids: Vec<_> = statements.iter().copied().collect();
+        let stmt_ids: Vec<_> = statements.to_vec();
         for stmt_id in stmt_ids {
             self.resolve_statement(stmt_id);
         }
 
         self.pop_scope();
+
+        // Remember the block's locals to explain later uses of them
+        for (name, &var_id) in &self.program.scopes[block_scope].variables {
+            let declared = self.program.variables[var_id].defined_at.clone();
+            self.ended.entry(name.clone()).or_insert(declared);
+        }
     }
 
     /// Resolves names within an expression.
@@ -451,7 +489,7 @@ impl<'p> NameResolver<'p> {
                     // Store the resolution
                     self.program.resolutions.insert(expr_id, var_id);
                 } else {
-                    self.error_at(&expr_span, AstError::UndeclaredVariable(name));
+                    self.undeclared_variable(name, &expr_span);
                 }
             }
             ExpressionKind::TableFieldAccess {
@@ -508,14 +546,16 @@ impl<'p> NameResolver<'p> {
                         self.error_at(
                             &expr_span,
                             AstError::UndeclaredField {
-                                table: table_name.clone(),
-                                field: field_name,
+                                table: table_name.to_string(),
+                                field: field_name.to_string(),
                             },
                         );
                     }
 
                     // Update the expression with resolved IDs
                     if let ExpressionKind::TableFieldAccess {
+                        ref mut pk_fields,
+                        ref mut pk_exprs,
                         ref mut resolved_table,
                         ref mut resolved_pk_fields,
                         ref mut resolved_field,
@@ -525,19 +565,25 @@ impl<'p> NameResolver<'p> {
                         *resolved_table = Some(table_id);
                         *resolved_pk_fields = resolved_pk_field_ids;
                         *resolved_field = field_id;
+                        sort_keys(
+                            &self.program.tables[table_id].primary_keys,
+                            pk_fields,
+                            pk_exprs,
+                            resolved_pk_fields,
+                        );
                     }
 
                     if field_id.is_none() {
                         self.error_at(
                             &expr_span,
                             AstError::UndeclaredField {
-                                table: table_name.clone(),
-                                field: field_name,
+                                table: table_name.to_string(),
+                                field: field_name.to_string(),
                             },
                         );
                     }
                 } else {
-                    self.error_at(&expr_span, AstError::UndeclaredTable(table_name));
+                    self.error_at(&expr_span, AstError::UndeclaredTable(table_name.to_string()));
                 }
             }
             ExpressionKind::UnaryOp { expr, .. } => {
@@ -559,14 +605,14 @@ impl<'p> NameResolver<'p> {
     /// Declares a variable in the current scope.
     fn declare_variable(
         &mut self,
-        name: &str,
+        name: Symbol,
         ty: TypeName,
         kind: VarKind,
         span: Span,
         target_scope_id: ScopeId,
-    ) {
+    ) -> VarId {
         let var_id = self.program.variables.alloc(VarDecl {
-            name: name.to_string(),
+            name: name.clone(),
             ty: ty.clone(),
             kind,
             defined_at: span,
@@ -576,14 +622,15 @@ impl<'p> NameResolver<'p> {
         // Add to target scope
         // The duplicate check should be done before calling this function.
         let scope = &mut self.program.scopes[target_scope_id];
-        scope.variables.insert(name.to_string(), var_id);
+        scope.variables.insert(name, var_id);
 
         // Store type information
         self.program.var_types.insert(var_id, ty);
+        var_id
     }
 
     /// Looks up a variable in the current scope stack.
-    fn lookup_variable(&self, name: &str) -> Option<VarId> {
+    fn lookup_variable(&self, name: &Symbol) -> Option<VarId> {
         // Search through scope stack from current to global
         for &scope_id in self.scope_stack.iter().rev() {
             let scope = &self.program.scopes[scope_id];
@@ -594,6 +641,18 @@ impl<'p> NameResolver<'p> {
         None
     }
 
+    /// Reports a use of a name that is not in scope, noting a block it was declared in.
+    fn undeclared_variable(&mut self, name: Symbol, span: &Span) {
+        let error = match self.ended.get(&name) {
+            Some(declared) => AstError::VariableOutOfScope {
+                name: name.to_string(),
+                declared: declared.clone(),
+            },
+            None => AstError::UndeclaredVariable(name.to_string()),
+        };
+        self.error_at(span, error);
+    }
+
     /// Pushes a new scope onto the stack.
     fn push_scope(&mut self, scope_id: ScopeId) {
         self.current_scope = Some(scope_id);
@@ -620,3 +679,38 @@ pub fn resolve_names(program: &mut Program) -> Results<()> {
     let resolver = NameResolver::new(program);
     resolver.resolve()
 }
+
+/// Resolves the names of a single function, e.g. one that was rebuilt after an edit.
+pub(crate) fn resolve_function_names(program: &mut Program, func_id: FunctionId) -> Results<()> {
+    let mut resolver = NameResolver::new(program);
+    resolver.resolve_function(func_id);
+    if resolver.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+/// Primary keys are named, so puts the fields of a key, their values and
+/// what they resolved to in the declaration order of `primary_keys`. Left as
+/// written unless they name each primary key exactly once.
+fn sort_keys(
+    primary_keys: &[FieldId],
+    pk_fields: &mut Vec<Symbol>,
+    pk_exprs: &mut Vec<ExpressionId>,
+    resolved: &mut Vec<Option<FieldId>>,
+) {
+    if resolved.len() != primary_keys.len() {
+        return;
+    }
+    let Some(order) = primary_keys
+        .iter()
+        .map(|pk| resolved.iter().position(|field| *field == Some(*pk)))
+        .collect::<Option<Vec<usize>>>()
+    else {
+        return;
+    };
+    *pk_fields = order.iter().map(|&i| pk_fields[i].clone()).collect();
+    *pk_exprs = order.iter().map(|&i| pk_exprs[i]).collect();
+    *resolved = order.iter().map(|&i| resolved[i]).collect();
+}
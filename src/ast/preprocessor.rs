@@ -0,0 +1,150 @@
+//! The `preprocessor` module resolves conditional compilation before parsing.
+//! Sections between `#if FEATURE` and `#endif` are kept only when `FEATURE` is
+//! defined (for example with `--define FEATURE` on the command line), so one
+//! source file can describe several deployment variants.
+//!
+//! # Overview
+//!
+//! - **inactive_ranges**: Finds the directive lines and disabled sections of a source.
+//! - **blank_out**: Replaces those ranges with spaces so the parser skips them.
+//!
+//! # Directives
+//!
+//! - `#if FEATURE` / `#if !FEATURE`: Starts a section kept when `FEATURE` is (not) defined.
+//! - `#else`: Switches to the opposite section.
+//! - `#endif`: Ends the innermost section.
+//!
+//! Directives must be on a line of their own and may be nested. Blanking keeps
+//! every byte offset and line number, so spans into the source stay valid.
+
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::ast::*;
+
+/// An open `#if` section.
+struct Section {
+    /// Whether the enclosing sections are active.
+    parent_active: bool,
+    /// Whether the current branch of this section is active.
+    active: bool,
+    /// Whether an `#else` has been seen.
+    seen_else: bool,
+    /// Location of the `#if` directive, for unterminated sections.
+    span: Span,
+}
+
+/// Returns the byte ranges of `source` that are not part of the program:
+/// directive lines and disabled sections. Adjacent ranges are merged, and a
+/// range never includes the newline that ends its last line.
+pub(crate) fn inactive_ranges(
+    source: &str,
+    file: Option<&Arc<Path>>,
+    defines: &HashSet<String>,
+) -> Results<Vec<Range<usize>>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut sections: Vec<Section> = Vec::new();
+    let mut errors = Vec::new();
+    let mut offset = 0;
+
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        let start = offset;
+        let end = start + line.trim_end_matches(['\n', '\r']).len();
+        offset += line.len();
+
+        let active = sections.last().is_none_or(|s| s.parent_active && s.active);
+        let text = line.trim();
+        let span = Span {
+            start,
+            end,
+            line: index + 1,
+            column: 1,
+            end_line: index + 1,
+            end_column: end - start + 1,
+            file: file.cloned(),
+        };
+
+        let inactive = if let Some(directive) = text.strip_prefix('#') {
+            let mut words = directive.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("if"), Some(feature), None) => {
+                    let (negated, feature) = match feature.strip_prefix('!') {
+                        Some(feature) => (true, feature),
+                        None => (false, feature),
+                    };
+                    sections.push(Section {
+                        parent_active: active,
+                        active: defines.contains(feature) != negated,
+                        seen_else: false,
+                        span: span.clone(),
+                    });
+                }
+                (Some("else"), None, None) => match sections.last_mut() {
+                    Some(section) if !section.seen_else => {
+                        section.active = !section.active;
+                        section.seen_else = true;
+                    }
+                    Some(_) => errors.push(directive_error("Duplicate #else", span)),
+                    None => errors.push(directive_error("#else without #if", span)),
+                },
+                (Some("endif"), None, None) => {
+                    if sections.pop().is_none() {
+                        errors.push(directive_error("#endif without #if", span));
+                    }
+                }
+                _ => errors.push(directive_error(
+                    &format!("Unknown directive '{}'", text),
+                    span,
+                )),
+            }
+            true
+        } else {
+            !active
+        };
+
+        if inactive {
+            match ranges.last_mut() {
+                // Extend across the newline that separates the two lines
+                Some(last) if matches!(&source[last.end..start], "\n" | "\r\n") => last.end = end,
+                _ => ranges.push(start..end),
+            }
+        }
+    }
+
+    for section in sections {
+        errors.push(directive_error("#if without matching #endif", section.span));
+    }
+
+    if errors.is_empty() {
+        Ok(ranges)
+    } else {
+        Err(errors)
+    }
+}
+
+fn directive_error(message: &str, span: Span) -> SpannedError {
+    SpannedError {
+        error: AstError::DirectiveError(message.to_string()),
+        span: Some(span),
+    }
+}
+
+/// Replaces every character in `ranges` with spaces, keeping line breaks and byte offsets.
+pub(crate) fn blank_out(source: &str, ranges: &[Range<usize>]) -> String {
+    let mut result = String::with_capacity(source.len());
+    let mut cursor = 0;
+    for range in ranges {
+        result.push_str(&source[cursor..range.start]);
+        for c in source[range.clone()].chars() {
+            match c {
+                '\n' | '\r' => result.push(c),
+                _ => result.extend(std::iter::repeat_n(' ', c.len_utf8())),
+            }
+        }
+        cursor = range.end;
+    }
+    result.push_str(&source[cursor..]);
+    result
+}
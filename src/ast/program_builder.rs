@@ -0,0 +1,604 @@
+//! The `program_builder` module constructs programs in code, without source
+//! text, for tests, fuzzers and tools that synthesize workloads.
+//!
+//! # Overview
+//!
+//! - **ProgramBuilder**: Collects nodes, tables and functions, and builds them
+//!   into a `Program` whose names are resolved and which passed analysis, as
+//!   `parse_and_analyze` returns it.
+//! - **FunctionBuilder**: A function's parameters and hops.
+//! - **Field / Stmt / Expr**: Fields, statements and expressions by value.
+//!
+//! Built elements have default spans, so their errors carry no span.
+//!
+//! ```
+//! use FMitF_rs::ast::program_builder::{Expr, Field, FunctionBuilder, ProgramBuilder, Stmt};
+//! use FMitF_rs::ast::{BinaryOp, TypeName};
+//!
+//! let balance = || Expr::field("Account", [("id", Expr::var("id"))], "balance");
+//! let program = ProgramBuilder::new()
+//!     .node("A")
+//!     .table(
+//!         "Account",
+//!         "A",
+//!         [Field::primary(TypeName::Int, "id"), Field::new(TypeName::Int, "balance")],
+//!     )
+//!     .function(
+//!         FunctionBuilder::new("deposit")
+//!             .param(TypeName::Int, "id")
+//!             .param(TypeName::Int, "amount")
+//!             .hop(
+//!                 "A",
+//!                 [Stmt::assign(
+//!                     "Account",
+//!                     [("id", Expr::var("id"))],
+//!                     "balance",
+//!                     Expr::binary(balance(), BinaryOp::Add, Expr::var("amount")),
+//!                 )],
+//!             ),
+//!     )
+//!     .build()
+//!     .unwrap();
+//! assert!(FMitF_rs::format_program(&program)
+//!     .contains("Account[id: id].balance = Account[id: id].balance + amount;"));
+//!
+//! // Names are resolved as in source
+//! let errors = ProgramBuilder::new()
+//!     .node("A")
+//!     .function(FunctionBuilder::new("f").hop("B", []))
+//!     .build()
+//!     .unwrap_err();
+//! assert!(FMitF_rs::ast::errors::format_errors(&errors).contains("B"));
+//! ```
+
+use super::name_resolver;
+use crate::ast::*;
+
+/// The declarations of a program, built in the order they were added.
+#[derive(Debug, Clone, Default)]
+pub struct ProgramBuilder {
+    nodes: Vec<String>,
+    tables: Vec<(String, String, Vec<Field>)>,
+    functions: Vec<FunctionBuilder>,
+}
+
+/// A table field.
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub ty: TypeName,
+    pub name: String,
+    pub primary: bool,
+    pub collation: Option<Collation>,
+}
+
+/// A function, `void` unless given a return type.
+#[derive(Debug, Clone)]
+pub struct FunctionBuilder {
+    name: String,
+    return_type: ReturnType,
+    params: Vec<(TypeName, String)>,
+    hops: Vec<(String, Vec<Stmt>)>,
+}
+
+/// A statement, as written in source.
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    /// `table[keys].field = value;`
+    Assign {
+        table: String,
+        keys: Vec<(String, Expr)>,
+        field: String,
+        value: Expr,
+    },
+    /// `table[keys].{field: value, ...};`
+    AssignMany {
+        table: String,
+        keys: Vec<(String, Expr)>,
+        fields: Vec<(String, Expr)>,
+    },
+    /// `var = value;`
+    Set(String, Expr),
+    /// `ty var = init;`
+    Var(TypeName, String, Option<Expr>),
+    If(Expr, Vec<Stmt>, Option<Vec<Stmt>>),
+    While(Expr, Vec<Stmt>),
+    Return(Option<Expr>),
+    Abort,
+    Break,
+    Continue,
+}
+
+/// An expression, as written in source.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Var(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    /// `table[keys].field`
+    Field {
+        table: String,
+        keys: Vec<(String, Expr)>,
+        field: String,
+    },
+    Unary(UnaryOp, Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+}
+
+fn pairs<K: Into<String>>(items: impl IntoIterator<Item = (K, Expr)>) -> Vec<(String, Expr)> {
+    items
+        .into_iter()
+        .map(|(name, expr)| (name.into(), expr))
+        .collect()
+}
+
+impl Field {
+    pub fn new(ty: TypeName, name: impl Into<String>) -> Self {
+        Self {
+            ty,
+            name: name.into(),
+            primary: false,
+            collation: None,
+        }
+    }
+
+    /// A field of the table's primary key.
+    pub fn primary(ty: TypeName, name: impl Into<String>) -> Self {
+        Self {
+            primary: true,
+            ..Self::new(ty, name)
+        }
+    }
+
+    /// Compares the (string) field with `collation`.
+    pub fn collate(mut self, collation: Collation) -> Self {
+        self.collation = Some(collation);
+        self
+    }
+}
+
+impl FunctionBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            return_type: ReturnType::Void,
+            params: Vec::new(),
+            hops: Vec::new(),
+        }
+    }
+
+    pub fn returns(mut self, ty: TypeName) -> Self {
+        self.return_type = ReturnType::Type(ty);
+        self
+    }
+
+    pub fn param(mut self, ty: TypeName, name: impl Into<String>) -> Self {
+        self.params.push((ty, name.into()));
+        self
+    }
+
+    /// Adds a hop on `node` running `statements`.
+    pub fn hop(
+        mut self,
+        node: impl Into<String>,
+        statements: impl IntoIterator<Item = Stmt>,
+    ) -> Self {
+        self.hops
+            .push((node.into(), statements.into_iter().collect()));
+        self
+    }
+}
+
+impl Stmt {
+    pub fn assign<K: Into<String>>(
+        table: impl Into<String>,
+        keys: impl IntoIterator<Item = (K, Expr)>,
+        field: impl Into<String>,
+        value: Expr,
+    ) -> Self {
+        Stmt::Assign {
+            table: table.into(),
+            keys: pairs(keys),
+            field: field.into(),
+            value,
+        }
+    }
+
+    pub fn assign_many<K: Into<String>, F: Into<String>>(
+        table: impl Into<String>,
+        keys: impl IntoIterator<Item = (K, Expr)>,
+        fields: impl IntoIterator<Item = (F, Expr)>,
+    ) -> Self {
+        Stmt::AssignMany {
+            table: table.into(),
+            keys: pairs(keys),
+            fields: pairs(fields),
+        }
+    }
+
+    pub fn set(var: impl Into<String>, value: Expr) -> Self {
+        Stmt::Set(var.into(), value)
+    }
+
+    pub fn var(ty: TypeName, name: impl Into<String>, init: Option<Expr>) -> Self {
+        Stmt::Var(ty, name.into(), init)
+    }
+
+    pub fn if_then(condition: Expr, then: impl IntoIterator<Item = Stmt>) -> Self {
+        Stmt::If(condition, then.into_iter().collect(), None)
+    }
+
+    pub fn if_else(
+        condition: Expr,
+        then: impl IntoIterator<Item = Stmt>,
+        otherwise: impl IntoIterator<Item = Stmt>,
+    ) -> Self {
+        Stmt::If(
+            condition,
+            then.into_iter().collect(),
+            Some(otherwise.into_iter().collect()),
+        )
+    }
+
+    pub fn while_loop(condition: Expr, body: impl IntoIterator<Item = Stmt>) -> Self {
+        Stmt::While(condition, body.into_iter().collect())
+    }
+
+    pub fn ret(value: Option<Expr>) -> Self {
+        Stmt::Return(value)
+    }
+}
+
+impl Expr {
+    pub fn var(name: impl Into<String>) -> Self {
+        Expr::Var(name.into())
+    }
+
+    pub fn int(value: i64) -> Self {
+        Expr::Int(value)
+    }
+
+    pub fn float(value: f64) -> Self {
+        Expr::Float(value)
+    }
+
+    pub fn string(value: impl Into<String>) -> Self {
+        Expr::Str(value.into())
+    }
+
+    pub fn bool(value: bool) -> Self {
+        Expr::Bool(value)
+    }
+
+    pub fn field<K: Into<String>>(
+        table: impl Into<String>,
+        keys: impl IntoIterator<Item = (K, Expr)>,
+        field: impl Into<String>,
+    ) -> Self {
+        Expr::Field {
+            table: table.into(),
+            keys: pairs(keys),
+            field: field.into(),
+        }
+    }
+
+    pub fn unary(op: UnaryOp, expr: Expr) -> Self {
+        Expr::Unary(op, Box::new(expr))
+    }
+
+    pub fn binary(left: Expr, op: BinaryOp, right: Expr) -> Self {
+        Expr::Binary(Box::new(left), op, Box::new(right))
+    }
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn node(mut self, name: impl Into<String>) -> Self {
+        self.nodes.push(name.into());
+        self
+    }
+
+    /// Adds a table on `node`, which needs a primary key field.
+    pub fn table(
+        mut self,
+        name: impl Into<String>,
+        node: impl Into<String>,
+        fields: impl IntoIterator<Item = Field>,
+    ) -> Self {
+        self.tables
+            .push((name.into(), node.into(), fields.into_iter().collect()));
+        self
+    }
+
+    pub fn function(mut self, function: FunctionBuilder) -> Self {
+        self.functions.push(function);
+        self
+    }
+
+    /// Builds the program and analyzes it with the default configuration.
+    pub fn build(self) -> Results<Program> {
+        self.build_with(&AnalysisConfig::default())
+    }
+
+    /// Builds the program and analyzes it with `config`.
+    pub fn build_with(self, config: &AnalysisConfig) -> Results<Program> {
+        let mut program = self.declare()?;
+        name_resolver::resolve_names(&mut program)?;
+        analyze_program_with_types(&mut program, config)?;
+        Ok(program)
+    }
+
+    /// Allocates the declarations, reporting what `ast_builder` reports of the
+    /// same declarations in source.
+    fn declare(self) -> Results<Program> {
+        let mut program = Program::new();
+        let error = |error| Err(vec![SpannedError { error, span: None }]);
+
+        for name in self.nodes {
+            let name = Symbol::intern(&name);
+            if program.node_map.contains_key(&name) {
+                return error(AstError::DuplicateNode {
+                    name: name.to_string(),
+                    previous: Span::default(),
+                });
+            }
+            let node = program.nodes.alloc(NodeDef {
+                name: name.clone(),
+                span: Span::default(),
+            });
+            program.node_map.insert(name, node);
+            program.root_nodes.push(node);
+        }
+
+        for (table_name, node_name, fields) in self.tables {
+            let Some(&node) = program.node_map.get(&Symbol::intern(&node_name)) else {
+                return error(AstError::UndeclaredNode(node_name));
+            };
+            let mut field_ids: Vec<FieldId> = Vec::new();
+            for field in fields {
+                let field_name = Symbol::intern(&field.name);
+                if field_ids
+                    .iter()
+                    .any(|&id| program.fields[id].field_name == field_name)
+                {
+                    return error(AstError::DuplicateField {
+                        table: table_name,
+                        field: field.name,
+                        previous: Span::default(),
+                    });
+                }
+                if field.collation.is_some() && field.ty != TypeName::String {
+                    return error(AstError::CollationOnNonString {
+                        field: field.name,
+                        field_type: field.ty,
+                    });
+                }
+                field_ids.push(program.fields.alloc(FieldDeclaration {
+                    field_type: field.ty,
+                    field_name,
+                    is_primary: field.primary,
+                    collation: field.collation,
+                    span: Span::default(),
+                }));
+            }
+            let primary_keys: Vec<FieldId> = field_ids
+                .iter()
+                .copied()
+                .filter(|&id| program.fields[id].is_primary)
+                .collect();
+            if primary_keys.is_empty() {
+                return error(AstError::ParseError(format!(
+                    "Table {} must have at least one primary key",
+                    table_name
+                )));
+            }
+            let name = Symbol::intern(&table_name);
+            if program.table_map.contains_key(&name) {
+                return error(AstError::DuplicateTable {
+                    name: table_name,
+                    previous: Span::default(),
+                });
+            }
+            let table = program.tables.alloc(TableDeclaration {
+                name: name.clone(),
+                node,
+                fields: field_ids,
+                primary_keys,
+                span: Span::default(),
+            });
+            program.table_map.insert(name, table);
+            program.root_tables.push(table);
+        }
+
+        for function in self.functions {
+            let name = Symbol::intern(&function.name);
+            if program.function_map.contains_key(&name) {
+                return error(AstError::DuplicateFunction {
+                    name: function.name,
+                    previous: Span::default(),
+                });
+            }
+            let mut parameters: Vec<ParameterId> = Vec::new();
+            for (param_type, param_name) in function.params {
+                let param_name_symbol = Symbol::intern(&param_name);
+                if parameters
+                    .iter()
+                    .any(|&id| program.parameters[id].param_name == param_name_symbol)
+                {
+                    return error(AstError::DuplicateParameter {
+                        function: function.name,
+                        param: param_name,
+                        previous: Span::default(),
+                    });
+                }
+                parameters.push(program.parameters.alloc(ParameterDecl {
+                    param_type,
+                    param_name: param_name_symbol,
+                    span: Span::default(),
+                    resolved_var: None,
+                }));
+            }
+            let hops = function
+                .hops
+                .into_iter()
+                .map(|(node_name, statements)| {
+                    let statements = block(&mut program, statements);
+                    program.hops.alloc(HopBlock {
+                        node_name: Symbol::intern(&node_name),
+                        statements,
+                        span: Span::default(),
+                        resolved_node: None,
+                    })
+                })
+                .collect();
+            let id = program.functions.alloc(FunctionDeclaration {
+                return_type: function.return_type,
+                name: name.clone(),
+                parameters,
+                hops,
+                span: Span::default(),
+            });
+            program.function_map.insert(name, id);
+            program.root_functions.push(id);
+        }
+
+        Ok(program)
+    }
+}
+
+fn block(program: &mut Program, statements: Vec<Stmt>) -> Vec<StatementId> {
+    statements
+        .into_iter()
+        .map(|stmt| statement(program, stmt))
+        .collect()
+}
+
+fn keys(program: &mut Program, keys: Vec<(String, Expr)>) -> (Vec<Symbol>, Vec<ExpressionId>) {
+    keys.into_iter()
+        .map(|(field, expr)| (Symbol::intern(&field), expression(program, expr)))
+        .unzip()
+}
+
+fn statement(program: &mut Program, stmt: Stmt) -> StatementId {
+    let node = match stmt {
+        Stmt::Assign {
+            table,
+            keys: pk,
+            field,
+            value,
+        } => {
+            let (pk_fields, pk_exprs) = keys(program, pk);
+            let rhs = expression(program, value);
+            StatementKind::Assignment(AssignmentStatement {
+                table_name: Symbol::intern(&table),
+                resolved_pk_fields: vec![None; pk_fields.len()],
+                pk_fields,
+                pk_exprs,
+                field_name: Symbol::intern(&field),
+                rhs,
+                resolved_table: None,
+                resolved_field: None,
+            })
+        }
+        Stmt::AssignMany {
+            table,
+            keys: pk,
+            fields,
+        } => {
+            let (pk_fields, pk_exprs) = keys(program, pk);
+            let assignments = fields
+                .into_iter()
+                .map(|(field, value)| MultiAssignmentPair {
+                    field_name: Symbol::intern(&field),
+                    rhs: expression(program, value),
+                    resolved_field: None,
+                })
+                .collect();
+            StatementKind::MultiAssignment(MultiAssignmentStatement {
+                table_name: Symbol::intern(&table),
+                resolved_pk_fields: vec![None; pk_fields.len()],
+                pk_fields,
+                pk_exprs,
+                assignments,
+                resolved_table: None,
+            })
+        }
+        Stmt::Set(var, value) => StatementKind::VarAssignment(VarAssignmentStatement {
+            var_name: Symbol::intern(&var),
+            rhs: expression(program, value),
+            resolved_var: None,
+        }),
+        Stmt::Var(var_type, var, init) => StatementKind::VarDecl(VarDeclStatement {
+            var_type,
+            var_name: Symbol::intern(&var),
+            init_value: init.map(|init| expression(program, init)),
+            resolved_var: None,
+        }),
+        Stmt::If(condition, then, otherwise) => StatementKind::IfStmt(IfStatement {
+            condition: expression(program, condition),
+            then_branch: block(program, then),
+            else_branch: otherwise.map(|otherwise| block(program, otherwise)),
+            else_span: None,
+        }),
+        Stmt::While(condition, body) => StatementKind::WhileStmt(WhileStatement {
+            condition: expression(program, condition),
+            body: block(program, body),
+        }),
+        Stmt::Return(value) => StatementKind::Return(ReturnStatement {
+            value: value.map(|value| expression(program, value)),
+        }),
+        Stmt::Abort => StatementKind::Abort(AbortStatement),
+        Stmt::Break => StatementKind::Break(BreakStatement),
+        Stmt::Continue => StatementKind::Continue(ContinueStatement),
+    };
+    program.statements.alloc(Statement {
+        node,
+        span: Span::default(),
+    })
+}
+
+fn expression(program: &mut Program, expr: Expr) -> ExpressionId {
+    let node = match expr {
+        Expr::Var(name) => ExpressionKind::Ident(Symbol::intern(&name)),
+        Expr::Int(value) => ExpressionKind::IntLit(value),
+        Expr::Float(value) => ExpressionKind::FloatLit(value),
+        Expr::Str(value) => ExpressionKind::StringLit(value),
+        Expr::Bool(value) => ExpressionKind::BoolLit(value),
+        Expr::Field {
+            table,
+            keys: pk,
+            field,
+        } => {
+            let (pk_fields, pk_exprs) = keys(program, pk);
+            ExpressionKind::TableFieldAccess {
+                table_name: Symbol::intern(&table),
+                resolved_pk_fields: vec![None; pk_fields.len()],
+                pk_fields,
+                pk_exprs,
+                field_name: Symbol::intern(&field),
+                resolved_table: None,
+                resolved_field: None,
+                resolved_type: None,
+            }
+        }
+        Expr::Unary(op, expr) => ExpressionKind::UnaryOp {
+            op,
+            expr: expression(program, *expr),
+            resolved_type: None,
+        },
+        Expr::Binary(left, op, right) => ExpressionKind::BinaryOp {
+            left: expression(program, *left),
+            op,
+            right: expression(program, *right),
+            resolved_type: None,
+        },
+    };
+    program.expressions.alloc(Expression {
+        node,
+        span: Span::default(),
+    })
+}
@@ -0,0 +1,157 @@
+//! The `report` module gathers everything semantic analysis learns about a
+//! program into one `AnalysisReport`.
+//!
+//! # Overview
+//!
+//! - **analyze_program_full**: Runs semantic analysis once and reports errors and
+//!   warnings together, with per-hop effects and statistics.
+//! - **AnalysisReport**: The result, for library users and machine-readable output.
+//! - **HopEffects**: The table fields a hop reads and writes.
+//! - **AnalysisStats**: Counts of the declarations in the program.
+//!
+//! Unlike `analyze_program`, which returns either errors or nothing, the report
+//! keeps the warnings of a program that also has errors. Effects are taken from
+//! name resolution, so they are available for programs with semantic errors.
+//!
+//! ```
+//! use FMitF_rs::ast::{analyze_program_full, parse_and_resolve, AnalysisConfig};
+//! use std::collections::HashSet;
+//!
+//! let program = parse_and_resolve(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void bump(int id) {
+//!         hop on A {
+//!             int unused = 0;
+//!             T[id: id].n = T[id: id].n + true;
+//!         }
+//!     }
+//!     "#,
+//!     None,
+//!     &HashSet::new(),
+//! )
+//! .unwrap();
+//! let report = analyze_program_full(&program, &AnalysisConfig::default());
+//! assert_eq!(report.errors.len(), 1);
+//! assert_eq!(report.warnings.len(), 1);
+//! assert_eq!(report.stats.hops, 1);
+//! let effects = &report.effect_summaries[0];
+//! assert_eq!(effects.reads, effects.writes);
+//! ```
+
+use crate::ast::semantics_analysis::SemanticAnalyzer;
+use crate::ast::visit::{self, Visitor};
+use crate::ast::*;
+
+/// Everything semantic analysis found in a program.
+#[derive(Debug, Clone)]
+pub struct AnalysisReport {
+    pub errors: Vec<SpannedError>,
+    /// Warnings at the levels of the config; those of denied lints have
+    /// `Severity::Error`
+    pub warnings: Vec<Diagnostic>,
+    /// The effects of every hop, by function and then hop order
+    pub effect_summaries: Vec<HopEffects>,
+    pub stats: AnalysisStats,
+}
+
+impl AnalysisReport {
+    /// Whether the program is accepted: no errors and no warnings of denied lints.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+            && self
+                .warnings
+                .iter()
+                .all(|warning| warning.severity != Severity::Error)
+    }
+}
+
+/// The distinct table fields a hop reads and writes, in the order they first
+/// appear.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopEffects {
+    pub function: FunctionId,
+    pub hop: HopId,
+    pub node: Option<NodeId>,
+    pub reads: Vec<FieldId>,
+    pub writes: Vec<FieldId>,
+}
+
+/// Counts of the declarations in a program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnalysisStats {
+    pub nodes: usize,
+    pub tables: usize,
+    pub functions: usize,
+    pub hops: usize,
+    pub statements: usize,
+}
+
+/// Runs semantic analysis on a resolved program and reports everything it found.
+pub fn analyze_program_full(program: &Program, config: &AnalysisConfig) -> AnalysisReport {
+    let (errors, warnings) = SemanticAnalyzer::new(program)
+        .with_config(config)
+        .analyze_all();
+
+    let mut effect_summaries = Vec::new();
+    for &function in &program.root_functions {
+        for &hop in &program.functions[function].hops {
+            let mut effects = HopEffects {
+                function,
+                hop,
+                node: program.hops[hop].resolved_node,
+                reads: Vec::new(),
+                writes: Vec::new(),
+            };
+            visit::walk_hop(&mut effects, program, hop);
+            effect_summaries.push(effects);
+        }
+    }
+
+    let stats = AnalysisStats {
+        nodes: program.root_nodes.len(),
+        tables: program.root_tables.len(),
+        functions: program.root_functions.len(),
+        hops: effect_summaries.len(),
+        statements: program.statements.len(),
+    };
+
+    AnalysisReport {
+        errors,
+        warnings,
+        effect_summaries,
+        stats,
+    }
+}
+
+/// Collects the fields read by each expression and written by each statement.
+impl Visitor for HopEffects {
+    fn visit_statement(&mut self, program: &Program, stmt_id: StatementId) {
+        visit::walk_statement(self, program, stmt_id);
+        match &program.statements[stmt_id].node {
+            StatementKind::Assignment(assign) => add_field(&mut self.writes, assign.resolved_field),
+            StatementKind::MultiAssignment(multi_assign) => {
+                for pair in &multi_assign.assignments {
+                    add_field(&mut self.writes, pair.resolved_field);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expression(&mut self, program: &Program, expr_id: ExpressionId) {
+        visit::walk_expression(self, program, expr_id);
+        if let ExpressionKind::TableFieldAccess { resolved_field, .. } =
+            &program.expressions[expr_id].node
+        {
+            add_field(&mut self.reads, *resolved_field);
+        }
+    }
+}
+
+fn add_field(fields: &mut Vec<FieldId>, field: Option<FieldId>) {
+    if let Some(field) = field.filter(|field| !fields.contains(field)) {
+        fields.push(field);
+    }
+}
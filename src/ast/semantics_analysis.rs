@@ -8,15 +8,43 @@
 //!
 //! # Features
 //!
-//! - Type checking for expressions and assignments.
-//! - Validation of control flow constructs (loops, returns, aborts).
+//! - Type checking for expressions and assignments, including that compared
+//!   string fields have the same collation.
+//! - Validation of control flow constructs (loops, returns, aborts), including that
+//!   a non-void function returns a value on every path and that aborts only run
+//!   where the `AbortPolicy` allows (see `abort_placement`).
 //! - Cross-node access and primary key validation for table operations.
+//! - Warnings for statements that can never run and for variables that are never
+//!   read, stored in `Program::warnings`. Names starting with `_` are exempt from
+//!   the unused check.
+//! - Warnings for consecutive hops on the same node, which could be one hop, for
+//!   hops that are empty or access no table, and for returns before the last hop.
+//! - Warnings for table writes overwritten in the same hop before being read
+//!   (see `dead_writes`), and for divisions that may divide by zero or `int`
+//!   field arithmetic that may overflow (see `arithmetic_lints`). These can be
+//!   suppressed per statement with an `// allow(lint)` comment.
+//!
+//! # Definite assignment
+//!
+//! Name resolution only makes a local visible after its declaration and within
+//! its block, so a read after an `if` that declares it in one branch is reported
+//! as `VariableOutOfScope`. A local declared without a value, like `int x;`, may
+//! still be read before it is assigned, possibly in a later hop; this is found by
+//! a flow analysis over the whole function (see `definite_assignment`) and reported
+//! as an `UninitializedVariable`, an error unless its lint is lowered.
+//!
+//! # Scoping
+//!
+//! Scopes are enforced during name resolution (see `name_resolver`): `if` and
+//! `while` bodies are block scopes, hops share the function scope, and a local may
+//! not shadow a parameter or a variable of an enclosing block. The analyzer relies
+//! on this, since every name then denotes a single variable within a function.
 //!
 //! # Usage
 //!
 //! Use the `analyze_program` function to perform semantic analysis:
 //!
-//! ```rust
+//! ```ignore
 //! use crate::ast::semantics_analysis::analyze_program;
 //! use crate::ast::Program;
 //!
@@ -24,7 +52,12 @@
 //! analyze_program(&program).expect("Semantic analysis failed");
 //! ```
 
+use crate::ast::abort_placement::AbortPlacementChecker;
+use crate::ast::arithmetic_lints::ArithmeticChecker;
+use crate::ast::dead_writes::DeadWriteFinder;
+use crate::ast::definite_assignment::DefiniteAssignmentChecker;
 use crate::ast::*;
+use std::collections::HashSet;
 
 /// The `SemanticAnalyzer` struct performs semantic analysis on a given program.
 ///
@@ -33,14 +66,22 @@ use crate::ast::*;
 pub struct SemanticAnalyzer<'p> {
     program: &'p Program,
     errors: Vec<SpannedError>,
+    warnings: Vec<Diagnostic>,
 
     // Current context
     current_function: Option<FunctionId>,
     current_hop: Option<HopId>,
     return_type: Option<ReturnType>,
-    has_return: bool,
     current_node: Option<NodeId>,
     in_loop: bool,
+    accesses_table: bool,
+
+    // Abort policy and lint levels
+    config: AnalysisConfig,
+
+    // Variables declared and read in the current function
+    declared: Vec<VarId>,
+    read: HashSet<VarId>,
 }
 
 impl<'p> SemanticAnalyzer<'p> {
@@ -49,28 +90,47 @@ impl<'p> SemanticAnalyzer<'p> {
         Self {
             program,
             errors: Vec::new(),
+            warnings: Vec::new(),
             current_function: None,
             current_hop: None,
             return_type: None,
-            has_return: false,
             current_node: None,
             in_loop: false,
+            accesses_table: false,
+            config: AnalysisConfig::default(),
+            declared: Vec::new(),
+            read: HashSet::new(),
         }
     }
 
+    /// Sets the abort policy and lint levels to analyze with.
+    pub fn with_config(mut self, config: &AnalysisConfig) -> Self {
+        self.config = config.clone();
+        self
+    }
+
     /// Run semantic analysis on the program.
     ///
     /// This checks all functions, hops, statements, and expressions for semantic errors.
-    pub fn analyze(mut self) -> Results<()> {
-        self.check_functions();
-
-        if self.errors.is_empty() {
-            Ok(())
+    /// Without errors, the warnings found are returned, at the levels of the config.
+    pub fn analyze(self) -> Results<Vec<Diagnostic>> {
+        let (errors, warnings) = self.analyze_all();
+        if errors.is_empty() {
+            Ok(warnings)
         } else {
-            Err(self.errors)
+            Err(errors)
         }
     }
 
+    /// Run semantic analysis, returning the errors and, even if there are errors,
+    /// the warnings at the levels of the config.
+    pub fn analyze_all(mut self) -> (Vec<SpannedError>, Vec<Diagnostic>) {
+        self.check_functions();
+        let mut warnings = self.config.apply(without_allowed_lints(self.program, self.warnings));
+        sort_diagnostics(&mut warnings);
+        (self.errors, warnings)
+    }
+
     /// Checks all root functions in the program.
     fn check_functions(&mut self) {
         for func_id in &self.program.root_functions {
@@ -84,23 +144,61 @@ impl<'p> SemanticAnalyzer<'p> {
 
         self.current_function = Some(func_id);
         self.return_type = Some(func.return_type.clone());
-        self.has_return = false;
+        self.declared = func
+            .parameters
+            .iter()
+            .filter_map(|&param_id| self.program.parameters[param_id].resolved_var)
+            .collect();
+        self.read.clear();
 
         // Check each hop
-        for (hop_index, hop_id) in func.hops.iter().enumerate() {
-            self.check_hop_block(*hop_id, hop_index, &func.name);
+        for hop_id in &func.hops {
+            self.check_hop_block(*hop_id, &func.name);
         }
-
-        // Check return requirements
-        if matches!(func.return_type, ReturnType::Type(_)) && !self.has_return {
-            self.error_at(&func.span, AstError::MissingReturn(func.name.clone()));
+        let abort_checker = AbortPlacementChecker::new(self.program, self.config.abort_policy);
+        self.errors.extend(abort_checker.check(func));
+        self.warnings.extend(DefiniteAssignmentChecker::new(self.program).check(func));
+
+        // Check return requirements: hops run in order, so the function ends once
+        // any hop leaves it on every path
+        let always_exits = func
+            .hops
+            .iter()
+            .any(|&hop_id| self.always_exits(&self.program.hops[hop_id].statements));
+        if matches!(func.return_type, ReturnType::Type(_)) && !always_exits {
+            self.error_at(&func.span, AstError::MissingReturn(func.name.to_string()));
         }
 
+        self.check_adjacent_hops(&func.hops);
+        self.check_unused_variables();
         self.current_function = None;
     }
 
+    /// Warns when two consecutive hops run on the same node. Splitting them
+    /// gains nothing and adds a piece to the chopping and the SC-graph.
+    fn check_adjacent_hops(&mut self, hops: &[HopId]) {
+        for pair in hops.windows(2) {
+            let (previous, hop) = (&self.program.hops[pair[0]], &self.program.hops[pair[1]]);
+            let Some(node) = hop.resolved_node.filter(|&n| previous.resolved_node == Some(n))
+            else {
+                continue;
+            };
+            let node_name = self.program.nodes[node].name.to_string();
+            self.warnings.push(
+                Diagnostic::warning(
+                    AstError::AdjacentHopsOnSameNode(node_name.clone()),
+                    Some(hop.span.clone()),
+                )
+                .with_note(
+                    format!("Move its statements into this `hop on {}` to merge them", node_name),
+                    Some(previous.span.clone()),
+                ),
+            );
+        }
+    }
+
     /// Checks a hop block, including all statements within it.
-    fn check_hop_block(&mut self, hop_id: HopId, hop_index: usize, function_name: &str) {
+    fn check_hop_block(&mut self, hop_id: HopId, function_name: &str) {
         let hop = &self.program.hops[hop_id];
 
         self.current_hop = Some(hop_id);
@@ -108,60 +206,169 @@ impl<'p> SemanticAnalyzer<'p> {
         self.current_node = hop.resolved_node;
 
         // Check each statement in the hop
-        for stmt_id in &hop.statements {
-            self.check_statement(*stmt_id, hop_index, function_name);
-        }
+        self.accesses_table = false;
+        self.check_block(&hop.statements);
+        self.check_hop_does_work(hop, function_name);
+        self.warnings.extend(DeadWriteFinder::new(self.program).find(hop));
+        self.warnings.extend(ArithmeticChecker::new(self.program).check(hop));
 
         self.current_hop = None;
         self.current_node = None;
     }
 
+    /// Warns about a hop without statements or table accesses. It still becomes
+    /// a piece of the chopping, with S-edges in the SC-graph, but does no work.
+    fn check_hop_does_work(&mut self, hop: &HopBlock, function_name: &str) {
+        if self.accesses_table {
+            return;
+        }
+        let function = function_name.to_string();
+        let node = hop.node_name.to_string();
+        let empty = hop
+            .statements
+            .iter()
+            .all(|stmt_id| matches!(self.program.statements[*stmt_id].node, StatementKind::Empty));
+        let (error, note) = if empty {
+            (AstError::EmptyHop { function, node }, "Remove the hop")
+        } else {
+            (
+                AstError::HopWithoutTableAccess { function, node },
+                "Move its statements into a neighbouring hop",
+            )
+        };
+        self.warnings.push(
+            Diagnostic::warning(error, Some(hop.span.clone())).with_note(note, None),
+        );
+    }
+
+    /// Checks a list of statements, warning about the first one after a
+    /// `return`, `abort`, `break` or `continue`, which can never run.
+    fn check_block(&mut self, statements: &[StatementId]) {
+        let mut exit: Option<&Span> = None;
+        let mut warned = false;
+
+        for stmt_id in statements {
+            let stmt = &self.program.statements[*stmt_id];
+            if let Some(exit_span) = exit.filter(|_| !warned) {
+                if !matches!(stmt.node, StatementKind::Empty) {
+                    self.warnings.push(
+                        Diagnostic::warning(AstError::UnreachableCode, Some(stmt.span.clone()))
+                            .with_note(
+                                "Control never continues past this statement",
+                                Some(exit_span.clone()),
+                            ),
+                    );
+                    warned = true;
+                }
+            }
+
+            self.check_statement(*stmt_id);
+
+            if exit.is_none()
+                && matches!(
+                    stmt.node,
+                    StatementKind::Return(_)
+                        | StatementKind::Abort(_)
+                        | StatementKind::Break(_)
+                        | StatementKind::Continue(_)
+                )
+            {
+                exit = Some(&stmt.span);
+            }
+        }
+    }
+
+    /// Whether every path through `statements` leaves the function with a
+    /// `return` or `abort`. `break` and `continue` only leave the enclosing loop.
+    fn always_exits(&self, statements: &[StatementId]) -> bool {
+        for stmt_id in statements {
+            match &self.program.statements[*stmt_id].node {
+                StatementKind::Return(_) | StatementKind::Abort(_) => return true,
+                StatementKind::Break(_) | StatementKind::Continue(_) => return false,
+                StatementKind::IfStmt(if_stmt) => {
+                    let else_exits = if_stmt
+                        .else_branch
+                        .as_ref()
+                        .is_some_and(|branch| self.always_exits(branch));
+                    if else_exits && self.always_exits(&if_stmt.then_branch) {
+                        return true;
+                    }
+                }
+                // Only `while (true)` without a `break` never falls through
+                StatementKind::WhileStmt(while_stmt) => {
+                    let endless = matches!(
+                        self.program.expressions[while_stmt.condition].node,
+                        ExpressionKind::BoolLit(true)
+                    );
+                    if endless && !self.breaks_loop(&while_stmt.body) {
+                        return true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Whether a loop body contains a `break` for that loop, not a nested one.
+    fn breaks_loop(&self, statements: &[StatementId]) -> bool {
+        statements
+            .iter()
+            .any(|stmt_id| match &self.program.statements[*stmt_id].node {
+                StatementKind::Break(_) => true,
+                StatementKind::IfStmt(if_stmt) => {
+                    self.breaks_loop(&if_stmt.then_branch)
+                        || if_stmt
+                            .else_branch
+                            .as_ref()
+                            .is_some_and(|branch| self.breaks_loop(branch))
+                }
+                _ => false,
+            })
+    }
+
+    /// Warns about the current function's parameters and locals that are never
+    /// read. Assigning to a variable does not count as a use.
+    fn check_unused_variables(&mut self) {
+        for var_id in std::mem::take(&mut self.declared) {
+            let var = &self.program.variables[var_id];
+            if self.read.contains(&var_id) || var.name.starts_with('_') {
+                continue;
+            }
+            let error = match var.kind {
+                VarKind::Parameter => AstError::UnusedParameter(var.name.to_string()),
+                VarKind::Local => AstError::UnusedVariable(var.name.to_string()),
+            };
+            self.warnings.push(
+                Diagnostic::warning(error, Some(var.defined_at.clone()))
+                    .with_note("Prefix the name with '_' if it is unused on purpose", None),
+            );
+        }
+    }
+
     /// Checks a statement for semantic correctness.
     ///
     /// This dispatches to the appropriate check based on statement kind.
-    fn check_statement(&mut self, stmt_id: StatementId, hop_index: usize, function_name: &str) {
+    fn check_statement(&mut self, stmt_id: StatementId) {
         let stmt = &self.program.statements[stmt_id];
 
         match &stmt.node {
             StatementKind::Assignment(a) => self.check_assignment(a, &stmt.span),
             StatementKind::MultiAssignment(a) => self.check_multi_assignment(a, &stmt.span),
             StatementKind::VarAssignment(a) => self.check_var_assignment(a, &stmt.span),
-            StatementKind::IfStmt(i) => {
-                self.check_if_statement(i, &stmt.span, hop_index, function_name)
-            }
-            StatementKind::WhileStmt(w) => {
-                self.check_while_statement(w, &stmt.span, hop_index, function_name)
-            }
+            StatementKind::IfStmt(i) => self.check_if_statement(i, &stmt.span),
+            StatementKind::WhileStmt(w) => self.check_while_statement(w, &stmt.span),
             StatementKind::VarDecl(v) => self.check_var_decl(v, &stmt.span),
             StatementKind::Return(r) => self.check_return_statement(r, &stmt.span),
-            StatementKind::Abort(_) => {
-                self.check_abort_statement(&stmt.span, hop_index, function_name)
-            }
+            // Where aborts may appear is checked per function (see `abort_placement`)
+            StatementKind::Abort(_) => {}
             StatementKind::Break(_) => self.check_break_statement(&stmt.span),
             StatementKind::Continue(_) => self.check_continue_statement(&stmt.span),
             StatementKind::Empty => {}
         }
     }
 
-    fn check_abort_statement(&mut self, span: &Span, hop_index: usize, function_name: &str) {
-        if hop_index != 0 {
-            self.error_at(
-                span,
-                AstError::AbortNotInFirstHop {
-                    function: function_name.to_string(),
-                    hop_index,
-                },
-            );
-        }
-    }
-
-    fn check_if_statement(
-        &mut self,
-        if_stmt: &IfStatement,
-        _span: &Span,
-        hop_index: usize,
-        function_name: &str,
-    ) {
+    fn check_if_statement(&mut self, if_stmt: &IfStatement, _span: &Span) {
         // Check condition
         if let Some(cond_type) = self.check_expression(if_stmt.condition) {
             if cond_type != TypeName::Bool {
@@ -171,25 +378,15 @@ impl<'p> SemanticAnalyzer<'p> {
         }
 
         // Check then branch
-        for stmt_id in &if_stmt.then_branch {
-            self.check_statement(*stmt_id, hop_index, function_name);
-        }
+        self.check_block(&if_stmt.then_branch);
 
         // Check else branch if present
         if let Some(else_branch) = &if_stmt.else_branch {
-            for stmt_id in else_branch {
-                self.check_statement(*stmt_id, hop_index, function_name);
-            }
+            self.check_block(else_branch);
         }
     }
 
-    fn check_while_statement(
-        &mut self,
-        while_stmt: &WhileStatement,
-        _span: &Span,
-        hop_index: usize,
-        function_name: &str,
-    ) {
+    fn check_while_statement(&mut self, while_stmt: &WhileStatement, _span: &Span) {
         // Check condition
         if let Some(cond_type) = self.check_expression(while_stmt.condition) {
             if cond_type != TypeName::Bool {
@@ -203,9 +400,7 @@ impl<'p> SemanticAnalyzer<'p> {
         self.in_loop = true;
 
         // Check body
-        for stmt_id in &while_stmt.body {
-            self.check_statement(*stmt_id, hop_index, function_name);
-        }
+        self.check_block(&while_stmt.body);
 
         // Restore loop context
         self.in_loop = previous_in_loop;
@@ -224,9 +419,11 @@ impl<'p> SemanticAnalyzer<'p> {
     }
 
     fn check_assignment(&mut self, assign: &AssignmentStatement, span: &Span) {
+        self.accesses_table = true;
+
         // Use resolved IDs if available
         let table_id = assign.resolved_table.ok_or_else(|| {
-            self.error_at(span, AstError::UndeclaredTable(assign.table_name.clone()));
+            self.error_at(span, AstError::UndeclaredTable(assign.table_name.to_string()));
         });
 
         if let Ok(table_id) = table_id {
@@ -240,9 +437,9 @@ impl<'p> SemanticAnalyzer<'p> {
                     self.error_at(
                         span,
                         AstError::CrossNodeAccess {
-                            table: table.name.clone(),
-                            table_node: table_node_name.clone(),
-                            current_node: current_node_name.clone(),
+                            table: table.name.to_string(),
+                            table_node: table_node_name.to_string(),
+                            current_node: current_node_name.to_string(),
                         },
                     );
                     return;
@@ -252,56 +449,12 @@ impl<'p> SemanticAnalyzer<'p> {
             // Check that we have all primary key fields resolved
             let all_pk_fields_resolved = assign.resolved_pk_fields.iter().all(|opt| opt.is_some());
 
-            if all_pk_fields_resolved && assign.resolved_field.is_some() {
-                let field_id = assign.resolved_field.unwrap();
-
-                // Validate each primary key field
-                for (i, resolved_pk_field_opt) in assign.resolved_pk_fields.iter().enumerate() {
-                    if let Some(pk_field_id) = resolved_pk_field_opt {
-                        // Check that this field is actually a primary key of this table
-                        if !table.primary_keys.contains(pk_field_id) {
-                            let pk_field = &self.program.fields[*pk_field_id];
-                            self.error_at(
-                                span,
-                                AstError::InvalidPrimaryKey {
-                                    table: table.name.clone(),
-                                    column: pk_field.field_name.clone(),
-                                },
-                            );
-                            return;
-                        }
-
-                        // Check primary key expression type
-                        if i < assign.pk_exprs.len() {
-                            if let Some(pk_type) = self.check_expression(assign.pk_exprs[i]) {
-                                let primary_key_field = &self.program.fields[*pk_field_id];
-                                if !self.types_compatible(&primary_key_field.field_type, &pk_type) {
-                                    self.error_at(
-                                        span,
-                                        AstError::TypeMismatch {
-                                            expected: primary_key_field.field_type.clone(),
-                                            found: pk_type,
-                                        },
-                                    );
-                                }
-                            }
-                        }
-                    }
-                }
+            if let (true, Some(field_id)) = (all_pk_fields_resolved, assign.resolved_field) {
 
-                // Check that all table primary keys are provided
-                if table.primary_keys.len() != assign.resolved_pk_fields.len() {
-                    self.error_at(
-                        span,
-                        AstError::ParseError(format!(
-                            "Table {} requires {} primary key values, but {} were provided",
-                            table.name,
-                            table.primary_keys.len(),
-                            assign.resolved_pk_fields.len()
-                        )),
-                    );
+                if !self.check_primary_key(table_id, &assign.resolved_pk_fields, span) {
                     return;
                 }
+                self.check_primary_key_types(&assign.resolved_pk_fields, &assign.pk_exprs, span);
 
                 // Check RHS type
                 if let Some(rhs_type) = self.check_expression(assign.rhs) {
@@ -321,9 +474,11 @@ impl<'p> SemanticAnalyzer<'p> {
     }
 
     fn check_multi_assignment(&mut self, multi_assign: &MultiAssignmentStatement, span: &Span) {
+        self.accesses_table = true;
+
         // Use resolved IDs if available
         let table_id = multi_assign.resolved_table.ok_or_else(|| {
-            self.error_at(span, AstError::UndeclaredTable(multi_assign.table_name.clone()));
+            self.error_at(span, AstError::UndeclaredTable(multi_assign.table_name.to_string()));
         });
 
         if let Ok(table_id) = table_id {
@@ -337,9 +492,9 @@ impl<'p> SemanticAnalyzer<'p> {
                     self.error_at(
                         span,
                         AstError::CrossNodeAccess {
-                            table: table.name.clone(),
-                            table_node: table_node_name.clone(),
-                            current_node: current_node_name.clone(),
+                            table: table.name.to_string(),
+                            table_node: table_node_name.to_string(),
+                            current_node: current_node_name.to_string(),
                         },
                     );
                     return;
@@ -351,53 +506,14 @@ impl<'p> SemanticAnalyzer<'p> {
             let all_assignment_fields_resolved = multi_assign.assignments.iter().all(|a| a.resolved_field.is_some());
 
             if all_pk_fields_resolved && all_assignment_fields_resolved {
-                // Validate each primary key field
-                for (i, resolved_pk_field_opt) in multi_assign.resolved_pk_fields.iter().enumerate() {
-                    if let Some(pk_field_id) = resolved_pk_field_opt {
-                        // Check that this field is actually a primary key of this table
-                        if !table.primary_keys.contains(pk_field_id) {
-                            let pk_field = &self.program.fields[*pk_field_id];
-                            self.error_at(
-                                span,
-                                AstError::InvalidPrimaryKey {
-                                    table: table.name.clone(),
-                                    column: pk_field.field_name.clone(),
-                                },
-                            );
-                            return;
-                        }
-
-                        // Check primary key expression type
-                        if i < multi_assign.pk_exprs.len() {
-                            if let Some(pk_type) = self.check_expression(multi_assign.pk_exprs[i]) {
-                                let primary_key_field = &self.program.fields[*pk_field_id];
-                                if !self.types_compatible(&primary_key_field.field_type, &pk_type) {
-                                    self.error_at(
-                                        span,
-                                        AstError::TypeMismatch {
-                                            expected: primary_key_field.field_type.clone(),
-                                            found: pk_type,
-                                        },
-                                    );
-                                }
-                            }
-                        }
-                    }
-                }
-
-                // Check that all table primary keys are provided
-                if table.primary_keys.len() != multi_assign.resolved_pk_fields.len() {
-                    self.error_at(
-                        span,
-                        AstError::ParseError(format!(
-                            "Table {} requires {} primary key values, but {} were provided",
-                            table.name,
-                            table.primary_keys.len(),
-                            multi_assign.resolved_pk_fields.len()
-                        )),
-                    );
+                if !self.check_primary_key(table_id, &multi_assign.resolved_pk_fields, span) {
                     return;
                 }
+                self.check_primary_key_types(
+                    &multi_assign.resolved_pk_fields,
+                    &multi_assign.pk_exprs,
+                    span,
+                );
 
                 // Check each assignment field and RHS type
                 for assignment in &multi_assign.assignments {
@@ -420,21 +536,104 @@ impl<'p> SemanticAnalyzer<'p> {
         }
     }
 
-    fn check_var_assignment(&mut self, var_assign: &VarAssignmentStatement, _span: &Span) {
-        // The name resolver should have already checked if the variable exists
-        // We can use the resolutions to get the variable type directly
-        // For now, we'll check the type compatibility
+    /// Checks that a table access names every primary key column of the table
+    /// exactly once, in any order, and no other column.
+    fn check_primary_key(
+        &mut self,
+        table_id: TableId,
+        resolved_pk_fields: &[Option<FieldId>],
+        span: &Span,
+    ) -> bool {
+        let table = &self.program.tables[table_id];
+        let given: Vec<FieldId> = resolved_pk_fields.iter().flatten().copied().collect();
+
+        for (i, pk_field_id) in given.iter().enumerate() {
+            let column = self.program.fields[*pk_field_id].field_name.to_string();
+            let table_name = table.name.to_string();
+            if !table.primary_keys.contains(pk_field_id) {
+                let error = AstError::InvalidPrimaryKey { table: table_name, column };
+                self.error_at(span, error);
+                return false;
+            }
+            if given[..i].contains(pk_field_id) {
+                let error = AstError::DuplicatePrimaryKey { table: table_name, column };
+                self.error_at(span, error);
+                return false;
+            }
+        }
+
+        let missing: Vec<String> = table
+            .primary_keys
+            .iter()
+            .filter(|pk_field_id| !given.contains(pk_field_id))
+            .map(|pk_field_id| self.program.fields[*pk_field_id].field_name.to_string())
+            .collect();
+        if !missing.is_empty() {
+            let error = AstError::MissingPrimaryKey {
+                table: table.name.to_string(),
+                columns: missing,
+            };
+            self.error_at(span, error);
+            return false;
+        }
+        true
+    }
+
+    /// Checks the type of each primary key expression against its column.
+    fn check_primary_key_types(
+        &mut self,
+        resolved_pk_fields: &[Option<FieldId>],
+        pk_exprs: &[ExpressionId],
+        span: &Span,
+    ) {
+        for (pk_field_id, pk_expr) in resolved_pk_fields.iter().zip(pk_exprs) {
+            let Some(pk_field_id) = pk_field_id else {
+                continue;
+            };
+            if let Some(pk_type) = self.check_expression(*pk_expr) {
+                let primary_key_field = &self.program.fields[*pk_field_id];
+                if !self.types_compatible(&primary_key_field.field_type, &pk_type) {
+                    self.error_at(
+                        span,
+                        AstError::TypeMismatch {
+                            expected: primary_key_field.field_type.clone(),
+                            found: pk_type,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_var_assignment(&mut self, var_assign: &VarAssignmentStatement, span: &Span) {
+        let rhs_type = self.check_expression(var_assign.rhs);
 
-        if let Some(_rhs_type) = self.check_expression(var_assign.rhs) {
-            // We would need to look up the variable type from the name resolver's results
-            // For now, we'll skip detailed type checking and let the name resolver handle existence
-            // In a full implementation, we'd look up the resolved variable and check its type
+        // The name resolver reports undeclared variables
+        let Some(var_id) = var_assign.resolved_var else {
+            return;
+        };
+        let var_type = &self.program.variables[var_id].ty;
+        if let Some(rhs_type) = rhs_type {
+            if !self.types_compatible(var_type, &rhs_type) {
+                self.error_at(
+                    span,
+                    AstError::TypeMismatch {
+                        expected: var_type.clone(),
+                        found: rhs_type,
+                    },
+                );
+            }
         }
     }
 
     fn check_var_decl(&mut self, var_decl: &VarDeclStatement, span: &Span) {
+        self.declared.extend(var_decl.resolved_var);
+
         // Check initializer expression type
-        if let Some(init_type) = self.check_expression(var_decl.init_value) {
+        let Some(init_value) = var_decl.init_value else {
+            return;
+        };
+        if let Some(init_type) = self.check_expression(init_value) {
             if !self.types_compatible(&var_decl.var_type, &init_type) {
                 self.error_at(
                     span,
@@ -448,8 +647,6 @@ impl<'p> SemanticAnalyzer<'p> {
     }
 
     fn check_return_statement(&mut self, ret_stmt: &ReturnStatement, span: &Span) {
-        self.has_return = true;
-
         // Clone the return type to avoid borrowing conflicts
         let return_type = self.return_type.clone();
 
@@ -480,6 +677,36 @@ impl<'p> SemanticAnalyzer<'p> {
                 // Should not happen
             }
         }
+        self.check_return_placement(span);
+    }
+
+    /// Warns about a return before the last hop. The hops after it are skipped, but
+    /// the client already holds a result computed from hops that committed on
+    /// their own, with nothing left to undo them.
+    fn check_return_placement(&mut self, span: &Span) {
+        let (Some(func_id), Some(hop_id)) = (self.current_function, self.current_hop) else {
+            return;
+        };
+        let func = &self.program.functions[func_id];
+        let Some(hop_index) = func.hops.iter().position(|&id| id == hop_id) else {
+            return;
+        };
+        let Some(&last_hop) = func.hops.last().filter(|&&last| last != hop_id) else {
+            return;
+        };
+        self.warnings.push(
+            Diagnostic::warning(
+                AstError::EarlyReturn {
+                    function: func.name.to_string(),
+                    hop_index,
+                },
+                Some(span.clone()),
+            )
+            .with_note(
+                "The last hop, skipped on this path",
+                Some(self.program.hops[last_hop].span.clone()),
+            ),
+        );
     }
 
     fn check_expression(&mut self, expr_id: ExpressionId) -> Option<TypeName> {
@@ -489,6 +716,7 @@ impl<'p> SemanticAnalyzer<'p> {
             ExpressionKind::Ident(_name) => {
                 // Use name resolver's resolution to get the variable
                 if let Some(var_id) = self.program.resolutions.get(&expr_id) {
+                    self.read.insert(*var_id);
                     let var = &self.program.variables[*var_id];
                     Some(var.ty.clone())
                 } else {
@@ -510,6 +738,8 @@ impl<'p> SemanticAnalyzer<'p> {
                 field_name,
                 ..
             } => {
+                self.accesses_table = true;
+
                 // Check all primary key expressions
                 for pk_expr in pk_exprs {
                     self.check_expression(*pk_expr);
@@ -518,7 +748,10 @@ impl<'p> SemanticAnalyzer<'p> {
                 let table_id = resolved_table
                     .ok_or_else(|| {
                         let expr_span = expr.span.clone();
-                        self.error_at(&expr_span, AstError::UndeclaredTable(table_name.clone()));
+                        self.error_at(
+                            &expr_span,
+                            AstError::UndeclaredTable(table_name.to_string()),
+                        );
                     })
                     .ok()?;
 
@@ -533,9 +766,9 @@ impl<'p> SemanticAnalyzer<'p> {
                         self.error_at(
                             &expr_span,
                             AstError::CrossNodeAccess {
-                                table: table_obj.name.clone(),
-                                table_node: table_node_name.clone(),
-                                current_node: current_node_name.clone(),
+                                table: table_obj.name.to_string(),
+                                table_node: table_node_name.to_string(),
+                                current_node: current_node_name.to_string(),
                             },
                         );
                         return None;
@@ -550,37 +783,8 @@ impl<'p> SemanticAnalyzer<'p> {
                     return None;
                 }
 
-                // Validate each primary key field
-                for (_, resolved_pk_field_opt) in resolved_pk_fields.iter().enumerate() {
-                    if let Some(pk_field_id) = resolved_pk_field_opt {
-                        // Check that this field is actually a primary key of this table
-                        if !table_obj.primary_keys.contains(pk_field_id) {
-                            let pk_field_obj = &self.program.fields[*pk_field_id];
-                            let expr_span = expr.span.clone();
-                            self.error_at(
-                                &expr_span,
-                                AstError::InvalidPrimaryKey {
-                                    table: table_obj.name.clone(),
-                                    column: pk_field_obj.field_name.clone(),
-                                },
-                            );
-                            return None;
-                        }
-                    }
-                }
-
-                // Check that all table primary keys are provided
-                if table_obj.primary_keys.len() != resolved_pk_fields.len() {
-                    let expr_span = expr.span.clone();
-                    self.error_at(
-                        &expr_span,
-                        AstError::ParseError(format!(
-                            "Table {} requires {} primary key values, but {} were provided",
-                            table_obj.name,
-                            table_obj.primary_keys.len(),
-                            resolved_pk_fields.len()
-                        )),
-                    );
+                let expr_span = expr.span.clone();
+                if !self.check_primary_key(table_id, resolved_pk_fields, &expr_span) {
                     return None;
                 }
 
@@ -591,8 +795,8 @@ impl<'p> SemanticAnalyzer<'p> {
                         self.error_at(
                             &expr_span,
                             AstError::UndeclaredField {
-                                table: table_name.clone(),
-                                field: field_name.clone(),
+                                table: table_name.to_string(),
+                                field: field_name.to_string(),
                             },
                         );
                     })
@@ -606,9 +810,7 @@ impl<'p> SemanticAnalyzer<'p> {
                 expr: inner_expr,
                 ..
             } => {
-                let Some(operand_type) = self.check_expression(*inner_expr) else {
-                    return None;
-                };
+                let operand_type = self.check_expression(*inner_expr)?;
 
                 match op {
                     UnaryOp::Neg => {
@@ -641,17 +843,69 @@ impl<'p> SemanticAnalyzer<'p> {
                             None
                         }
                     }
+                    UnaryOp::FoldCase => {
+                        if operand_type == TypeName::String {
+                            Some(TypeName::String)
+                        } else {
+                            let expr_span = expr.span.clone();
+                            self.error_at(
+                                &expr_span,
+                                AstError::InvalidUnaryOp {
+                                    op: "case folding".to_string(),
+                                    operand: operand_type,
+                                },
+                            );
+                            None
+                        }
+                    }
                 }
             }
             ExpressionKind::BinaryOp { left, op, right, .. } => {
                 let left_type = self.check_expression(*left)?;
                 let right_type = self.check_expression(*right)?;
                 let expr_span = expr.span.clone();
-                self.check_binary_op(op, &left_type, &right_type, &expr_span)
+                let result = self.check_binary_op(op, &left_type, &right_type, &expr_span);
+                if left_type == TypeName::String && right_type == TypeName::String {
+                    self.check_collations(*left, *right, &expr_span);
+                }
+                result
             }
         }
     }
 
+    /// Rejects comparing fields of different collations, which has no single
+    /// meaning.
+    fn check_collations(&mut self, left: ExpressionId, right: ExpressionId, span: &Span) {
+        let field = |expr_id: ExpressionId| match &self.program.expressions[expr_id].node {
+            ExpressionKind::TableFieldAccess {
+                table_name,
+                field_name,
+                resolved_field: Some(field_id),
+                ..
+            } => Some((
+                format!("{}.{}", table_name, field_name),
+                self.program.fields[*field_id].collation(),
+            )),
+            _ => None,
+        };
+        let (Some((left, left_collation)), Some((right, right_collation))) =
+            (field(left), field(right))
+        else {
+            return;
+        };
+        if left_collation != right_collation {
+            self.error_at(
+                span,
+                AstError::CollationMismatch {
+                    left,
+                    left_collation,
+                    right,
+                    right_collation,
+                },
+            );
+        }
+    }
+
     fn check_binary_op(
         &mut self,
         op: &BinaryOp,
@@ -697,9 +951,9 @@ impl<'p> SemanticAnalyzer<'p> {
                 }
             }
             BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte => {
-                if matches!(left, TypeName::Int | TypeName::Float)
-                    && matches!(right, TypeName::Int | TypeName::Float)
-                {
+                let numbers = matches!(left, TypeName::Int | TypeName::Float)
+                    && matches!(right, TypeName::Int | TypeName::Float);
+                if numbers || (left == &TypeName::String && right == &TypeName::String) {
                     Some(TypeName::Bool)
                 } else {
                     self.error_at(
@@ -743,19 +997,17 @@ impl<'p> SemanticAnalyzer<'p> {
     }
 }
 
-/// Public interface for semantic analysis.
+/// Public interface for semantic analysis. Warnings are discarded.
 pub fn analyze_program(program: &Program) -> Results<()> {
     let analyzer = SemanticAnalyzer::new(program);
-    analyzer.analyze()
+    analyzer.analyze().map(|_| ())
 }
 
 /// Analyze program and infer types, updating the AST with resolved types
-pub fn analyze_program_with_types(program: &mut Program) -> Results<()> {
+/// and `Program::warnings`
+pub fn analyze_program_with_types(program: &mut Program, config: &AnalysisConfig) -> Results<()> {
     // First do the regular analysis without mutation
-    {
-        let analyzer = SemanticAnalyzer::new(program);
-        analyzer.analyze()?;
-    }
+    program.warnings = SemanticAnalyzer::new(program).with_config(config).analyze()?;
     
     // Then perform type inference and update the AST
     let mut type_inferrer = TypeInferrer::new(program);
@@ -764,6 +1016,60 @@ pub fn analyze_program_with_types(program: &mut Program) -> Results<()> {
     Ok(())
 }
 
+/// Removes the warnings suppressed by an `// allow(lint)` comment on a statement
+/// that contains them.
+pub(crate) fn without_allowed_lints(program: &Program, warnings: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    let allowed: Vec<(&Span, &str)> = program
+        .trivia
+        .allowed_lints()
+        .into_iter()
+        .filter_map(|(anchor, lint)| match anchor {
+            SyntaxAnchor::Statement(stmt_id) => Some((&program.statements[stmt_id].span, lint)),
+            _ => None,
+        })
+        .collect();
+
+    warnings
+        .into_iter()
+        .filter(|warning| {
+            let (Some(name), Some(span)) = (warning.error.lint_name(), &warning.span) else {
+                return true;
+            };
+            !allowed.iter().any(|(stmt_span, lint)| {
+                *lint == name
+                    && stmt_span.file == span.file
+                    && stmt_span.start <= span.start
+                    && span.end <= stmt_span.end
+            })
+        })
+        .collect()
+}
+
+/// Analyzes a single function and infers the types of `expr_ids`, the
+/// expressions built for it. Returns the function's warnings, before
+/// `// allow` comments and lint levels are applied, since its trivia is not
+/// collected yet.
+pub(crate) fn analyze_function_with_types(
+    program: &mut Program,
+    func_id: FunctionId,
+    expr_ids: &[ExpressionId],
+    config: &AnalysisConfig,
+) -> Results<Vec<Diagnostic>> {
+    let warnings = {
+        let mut analyzer = SemanticAnalyzer::new(program).with_config(config);
+        analyzer.check_function(func_id);
+        if !analyzer.errors.is_empty() {
+            return Err(analyzer.errors);
+        }
+        analyzer.warnings
+    };
+
+    let mut type_inferrer = TypeInferrer::new(program);
+    type_inferrer.infer_types_of(expr_ids);
+
+    Ok(warnings)
+}
+
 /// Type inferrer that updates expression types in the AST
 struct TypeInferrer<'p> {
     program: &'p mut Program,
@@ -817,6 +1123,7 @@ impl<'p> TypeInferrer<'p> {
                 // Otherwise default to Int
                 operand_type.cloned().unwrap_or(TypeName::Int)
             }
+            UnaryOp::FoldCase => TypeName::String,
         }
     }
 
@@ -849,12 +1156,16 @@ impl<'p> TypeInferrer<'p> {
     fn infer_types(&mut self) {
         // We need to collect expression IDs first to avoid borrowing issues
         let expr_ids: Vec<ExpressionId> = self.program.expressions.iter().map(|(id, _)| id).collect();
-        
+        self.infer_types_of(&expr_ids);
+    }
+
+    /// Infers the types of the given expressions.
+    fn infer_types_of(&mut self, expr_ids: &[ExpressionId]) {
         // Make multiple passes to handle dependencies between expressions
         let max_passes = 3;
         for _pass in 0..max_passes {
             let mut changed = false;
-            for expr_id in &expr_ids {
+            for expr_id in expr_ids {
                 let had_type_before = self.get_expression_type(*expr_id).is_some();
                 self.infer_expression_type(*expr_id);
                 let has_type_after = self.get_expression_type(*expr_id).is_some();
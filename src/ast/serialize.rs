@@ -0,0 +1,469 @@
+//! The `serialize` module provides JSON serialization of the AST.
+//! Arena ids are written as their arena index, so a reference such as
+//! `"node": 2` points at element 2 of the program's `nodes` array.
+//!
+//! # Overview
+//!
+//! - **Program::to_json**: Serializes a whole program, arenas included.
+//! - **read_program**: Reads back a program written whole by its `Serialize`
+//!   implementation, comments and warnings included.
+//! - **id / ids / opt_id / ...**: `with` helpers used by the AST types.
+//!
+//! Reading gives every id of a kind the arena the program's items of that kind
+//! are read into, so ids can only be read inside `read_program`, which checks
+//! that each points to an item.
+//!
+//! ```rust
+//! use FMitF_rs::ast::serialize::read_program;
+//! use FMitF_rs::ast::Program;
+//!
+//! let program = FMitF_rs::parse_and_analyze(
+//!     "nodes { A } table T on A { primary int id; int v; }
+//!     // Sets v
+//!     void f(int x) { hop on A { int unused = 1; T[id: x].v = x; } }",
+//! )
+//! .unwrap();
+//! let saved = serde_json::to_string(&program).unwrap();
+//!
+//! let read: Program = read_program(|| serde_json::from_str(&saved)).unwrap();
+//! assert_eq!(read.to_json().unwrap(), program.to_json().unwrap());
+//! assert_eq!(read.warnings.len(), 1);
+//! assert_eq!(FMitF_rs::pretty::format_program(&read), FMitF_rs::pretty::format_program(&program));
+//!
+//! // Outside of `read_program`, or pointing past the arenas, ids are refused
+//! assert!(serde_json::from_str::<Program>(&saved).is_err());
+//! let broken = saved.replace("\"resolved_node\":0", "\"resolved_node\":7");
+//! assert!(read_program(|| serde_json::from_str::<Program>(&broken)).is_err());
+//! ```
+
+use id_arena::{Arena, ArenaBehavior, DefaultArenaBehavior, Id};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::any::{type_name, Any, TypeId};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::ast::*;
+
+/// The arenas of the program being read, by the kind of their items.
+#[derive(Default)]
+struct ReadProgram {
+    arenas: HashMap<TypeId, ReadArena>,
+    /// The path of each file, shared by the spans in it
+    paths: HashMap<PathBuf, Arc<Path>>,
+}
+
+struct ReadArena {
+    /// The kind of its items, for errors
+    name: &'static str,
+    /// The id of the arena
+    id: u32,
+    /// The arena, while ids into it are read before its items
+    arena: Option<Box<dyn Any>>,
+    /// How many items it has, once they are read
+    len: Option<usize>,
+    /// One past the highest index of an id into it
+    end: usize,
+}
+
+thread_local! {
+    static READ_PROGRAM: RefCell<Option<ReadProgram>> = const { RefCell::new(None) };
+}
+
+/// Reads a program written by its `Serialize` implementation, failing when an
+/// id points outside its arenas.
+pub fn read_program<E: serde::de::Error>(
+    read: impl FnOnce() -> Result<Program, E>,
+) -> Result<Program, E> {
+    let outer = READ_PROGRAM.with(|cell| cell.replace(Some(ReadProgram::default())));
+    let result = read();
+    let done = READ_PROGRAM.with(|cell| cell.replace(outer));
+    let program = result?;
+    let mut arenas: Vec<ReadArena> = done
+        .map(|done| done.arenas.into_values().collect())
+        .unwrap_or_default();
+    arenas.sort_by_key(|arena| arena.name);
+    for arena in arenas {
+        match arena.len {
+            None => {
+                return Err(E::custom(format!(
+                    "ids point into the {} arena, which the document does not contain",
+                    arena.name
+                )))
+            }
+            Some(len) if arena.end > len => {
+                return Err(E::custom(format!(
+                    "an id points to item {} of the {} arena, which has {}",
+                    arena.end - 1,
+                    arena.name,
+                    len
+                )))
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(program)
+}
+
+/// Runs `f` on the arena of `T` being read, created if ids into it come first.
+fn with_read_arena<T: 'static, R>(
+    f: impl FnOnce(&mut ReadArena) -> Result<R, String>,
+) -> Result<R, String> {
+    READ_PROGRAM.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let scope = cell
+            .as_mut()
+            .ok_or("a program can only be read inside `read_program`")?;
+        let read = scope.arenas.entry(TypeId::of::<T>()).or_insert_with(|| {
+            let arena = Arena::<T>::new();
+            ReadArena {
+                name: type_name::<T>().rsplit("::").next().unwrap_or_default(),
+                id: DefaultArenaBehavior::<T>::arena_id(arena.next_id()),
+                arena: Some(Box::new(arena)),
+                len: None,
+                end: 0,
+            }
+        });
+        f(read)
+    })
+}
+
+fn read_id<T: 'static>(index: usize) -> Result<Id<T>, String> {
+    with_read_arena::<T, _>(|read| {
+        read.end = read.end.max(index + 1);
+        Ok(DefaultArenaBehavior::<T>::new_id(read.id, index))
+    })
+}
+
+fn read_arena<T: 'static>(items: Vec<T>) -> Result<Arena<T>, String> {
+    let mut arena = with_read_arena::<T, _>(|read| {
+        let arena = read
+            .arena
+            .take()
+            .ok_or_else(|| format!("the {} arena appears twice", read.name))?;
+        read.len = Some(items.len());
+        Ok(arena
+            .downcast::<Arena<T>>()
+            .expect("arenas are kept by the type of their items"))
+    })?;
+    for item in items {
+        arena.alloc(item);
+    }
+    Ok(*arena)
+}
+
+/// An id, written as its index.
+struct IdRef<T>(Id<T>);
+
+impl<T> Serialize for IdRef<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0.index() as u64)
+    }
+}
+
+impl<'de, T: 'static> Deserialize<'de> for IdRef<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let index = usize::deserialize(deserializer)?;
+        read_id(index).map(IdRef).map_err(D::Error::custom)
+    }
+}
+
+pub mod id {
+    use super::*;
+
+    pub fn serialize<T, S: Serializer>(id: &Id<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        IdRef(*id).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Id<T>, D::Error> {
+        Ok(IdRef::deserialize(deserializer)?.0)
+    }
+}
+
+pub mod ids {
+    use super::*;
+
+    pub fn serialize<T, S: Serializer>(ids: &[Id<T>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(ids.iter().map(Id::index))
+    }
+
+    pub fn deserialize<'de, T: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Id<T>>, D::Error> {
+        let ids = Vec::<IdRef<T>>::deserialize(deserializer)?;
+        Ok(ids.into_iter().map(|id| id.0).collect())
+    }
+}
+
+pub mod opt_id {
+    use super::*;
+
+    pub fn serialize<T, S: Serializer>(
+        id: &Option<Id<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        id.map(|id| id.index()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Id<T>>, D::Error> {
+        Ok(Option::<IdRef<T>>::deserialize(deserializer)?.map(|id| id.0))
+    }
+}
+
+pub mod opt_ids {
+    use super::*;
+
+    pub fn serialize<T, S: Serializer>(
+        ids: &[Option<Id<T>>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(ids.iter().map(|id| id.map(|id| id.index())))
+    }
+
+    pub fn deserialize<'de, T: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Option<Id<T>>>, D::Error> {
+        let ids = Vec::<Option<IdRef<T>>>::deserialize(deserializer)?;
+        Ok(ids.into_iter().map(|id| id.map(|id| id.0)).collect())
+    }
+}
+
+pub mod ids_opt {
+    use super::*;
+
+    pub fn serialize<T, S: Serializer>(
+        ids: &Option<Vec<Id<T>>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        ids.as_ref()
+            .map(|ids| ids.iter().map(Id::index).collect::<Vec<_>>())
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<Id<T>>>, D::Error> {
+        let ids = Option::<Vec<IdRef<T>>>::deserialize(deserializer)?;
+        Ok(ids.map(|ids| ids.into_iter().map(|id| id.0).collect()))
+    }
+}
+
+/// A name → id map, with keys in sorted order.
+pub mod sorted_ids {
+    use super::*;
+
+    pub fn serialize<T, S: Serializer>(
+        map: &HashMap<Symbol, Id<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        map.iter()
+            .map(|(name, id)| (name, id.index()))
+            .collect::<BTreeMap<_, _>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Symbol, Id<T>>, D::Error> {
+        let map = HashMap::<Symbol, IdRef<T>>::deserialize(deserializer)?;
+        Ok(map.into_iter().map(|(name, id)| (name, id.0)).collect())
+    }
+}
+
+/// An arena, as the array of its items.
+pub mod arena {
+    use super::*;
+
+    pub fn serialize<T: Serialize, S: Serializer>(
+        arena: &Arena<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(arena.iter().map(|(_, item)| item))
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de> + 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arena<T>, D::Error> {
+        let items = Vec::<T>::deserialize(deserializer)?;
+        read_arena(items).map_err(D::Error::custom)
+    }
+}
+
+/// A map from ids, as a list of `[index, value]` in index order.
+pub mod id_keys {
+    use super::*;
+
+    pub fn serialize<K, V: Serialize, S: Serializer>(
+        map: &HashMap<Id<K>, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<_> = map.iter().map(|(k, v)| (k.index(), v)).collect();
+        entries.sort_by_key(|(index, _)| *index);
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, K: 'static, V: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Id<K>, V>, D::Error> {
+        let entries = Vec::<(IdRef<K>, V)>::deserialize(deserializer)?;
+        Ok(entries.into_iter().map(|(k, v)| (k.0, v)).collect())
+    }
+}
+
+/// A map from ids to ids, as a list of `[index, index]` in key order.
+pub mod id_map {
+    use super::*;
+
+    pub fn serialize<K, V, S: Serializer>(
+        map: &HashMap<Id<K>, Id<V>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let map: HashMap<Id<K>, usize> = map.iter().map(|(&k, v)| (k, v.index())).collect();
+        id_keys::serialize(&map, serializer)
+    }
+
+    pub fn deserialize<'de, K: 'static, V: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Id<K>, Id<V>>, D::Error> {
+        let entries = Vec::<(IdRef<K>, IdRef<V>)>::deserialize(deserializer)?;
+        Ok(entries.into_iter().map(|(k, v)| (k.0, v.0)).collect())
+    }
+}
+
+/// A map whose keys are not strings, as a list of `[key, value]`.
+pub mod entries {
+    use super::*;
+
+    pub fn serialize<K: Serialize, V: Serialize, S: Serializer>(
+        map: &HashMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// The file of a span, read as one path shared by the spans of the file.
+pub fn shared_path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Arc<Path>>, D::Error> {
+    let Some(path) = Option::<PathBuf>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    let shared = READ_PROGRAM.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let scope = cell.as_mut()?;
+        Some(
+            scope
+                .paths
+                .entry(path.clone())
+                .or_insert_with(|| Arc::from(path.as_path()))
+                .clone(),
+        )
+    });
+    Ok(Some(shared.unwrap_or_else(|| Arc::from(path))))
+}
+
+/// Borrowed view of a `Program` with arenas flattened into index-ordered arrays.
+#[derive(Serialize)]
+struct ProgramJson<'a> {
+    files: Vec<Option<&'a Path>>,
+    /// The language version the root file declares
+    version: Option<u32>,
+    nodes: Vec<&'a NodeDef>,
+    tables: Vec<&'a TableDeclaration>,
+    fields: Vec<&'a FieldDeclaration>,
+    functions: Vec<&'a FunctionDeclaration>,
+    hops: Vec<&'a HopBlock>,
+    parameters: Vec<&'a ParameterDecl>,
+    statements: Vec<&'a Statement>,
+    expressions: Vec<&'a Expression>,
+    variables: Vec<&'a VarDecl>,
+    scopes: Vec<&'a Scope>,
+    #[serde(with = "ids")]
+    root_nodes: &'a [NodeId],
+    #[serde(with = "ids")]
+    root_tables: &'a [TableId],
+    #[serde(with = "ids")]
+    root_functions: &'a [FunctionId],
+    templates: &'a [TemplateDeclaration],
+    template_instances: &'a [TemplateInstance],
+    /// Expression index → variable index
+    resolutions: BTreeMap<usize, usize>,
+    /// Variable index → inferred type
+    var_types: BTreeMap<usize, &'a TypeName>,
+}
+
+impl Program {
+    /// Serializes the program to pretty-printed JSON.
+    ///
+    /// Every arena becomes an array ordered by arena index, and every id inside
+    /// the program is emitted as that index. Spans are included on all nodes.
+    ///
+    /// ```rust
+    /// let program = FMitF_rs::parse_and_analyze("nodes { A }").unwrap();
+    /// let json = program.to_json().unwrap();
+    /// assert!(json.contains("\"nodes\""));
+    /// ```
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&self.json_view())
+    }
+
+    /// Serializes the program to a `serde_json::Value`.
+    pub fn to_json_value(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(self.json_view())
+    }
+
+    fn json_view(&self) -> ProgramJson<'_> {
+        ProgramJson {
+            files: self
+                .source_files
+                .iter()
+                .map(|f| f.path.as_deref())
+                .collect(),
+            version: self.version(),
+            nodes: self.nodes.iter().map(|(_, n)| n).collect(),
+            tables: self.tables.iter().map(|(_, t)| t).collect(),
+            fields: self.fields.iter().map(|(_, f)| f).collect(),
+            functions: self.functions.iter().map(|(_, f)| f).collect(),
+            hops: self.hops.iter().map(|(_, h)| h).collect(),
+            parameters: self.parameters.iter().map(|(_, p)| p).collect(),
+            statements: self.statements.iter().map(|(_, s)| s).collect(),
+            expressions: self.expressions.iter().map(|(_, e)| e).collect(),
+            variables: self.variables.iter().map(|(_, v)| v).collect(),
+            scopes: self.scopes.iter().map(|(_, s)| s).collect(),
+            root_nodes: &self.root_nodes,
+            root_tables: &self.root_tables,
+            root_functions: &self.root_functions,
+            templates: &self.templates,
+            template_instances: &self.template_instances,
+            resolutions: self
+                .resolutions
+                .iter()
+                .map(|(expr, var)| (expr.index(), var.index()))
+                .collect(),
+            var_types: self
+                .var_types
+                .iter()
+                .map(|(var, ty)| (var.index(), ty))
+                .collect(),
+        }
+    }
+}
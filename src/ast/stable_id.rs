@@ -0,0 +1,236 @@
+//! The `stable_id` module names AST elements by their position in the source
+//! structure instead of their arena index, e.g. `fn:transfer/hop:1/stmt:3`.
+//!
+//! Arena indices are already deterministic (elements are allocated in source
+//! order, files in import order), but they shift whenever anything earlier in
+//! the program changes. Stable IDs only depend on names and positions inside
+//! the enclosing declaration, so golden files and caches can refer to them.
+//!
+//! # Overview
+//!
+//! - **ElementId**: An arena id of any kind of AST element.
+//! - **StableIds**: The stable ID of every element of a program, in both directions.
+//!
+//! # Format
+//!
+//! - `node:<name>`, `table:<name>`, `table:<name>/field:<name>`
+//! - `fn:<name>`, `fn:<name>/param:<name>`, `fn:<name>/hop:<n>`
+//! - `fn:<name>/hop:<n>/stmt:<m>`: the m-th statement of the hop in source
+//!   order, counting statements nested in `if` and `while` blocks
+//! - `fn:<name>/hop:<n>/stmt:<m>/expr:<k>`: the k-th expression of the statement
+//!   in source order, counting subexpressions
+//!
+//! All numbers are 1-based.
+
+use std::collections::HashMap;
+
+use crate::ast::*;
+
+/// An arena id of any kind of AST element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementId {
+    Node(NodeId),
+    Table(TableId),
+    Field(FieldId),
+    Function(FunctionId),
+    Parameter(ParameterId),
+    Hop(HopId),
+    Statement(StatementId),
+    Expression(ExpressionId),
+}
+
+macro_rules! element_id_from {
+    ($($id:ty => $variant:ident),* $(,)?) => {
+        $(impl From<$id> for ElementId {
+            fn from(id: $id) -> Self {
+                ElementId::$variant(id)
+            }
+        })*
+    };
+}
+
+element_id_from! {
+    NodeId => Node,
+    TableId => Table,
+    FieldId => Field,
+    FunctionId => Function,
+    ParameterId => Parameter,
+    HopId => Hop,
+    StatementId => Statement,
+    ExpressionId => Expression,
+}
+
+/// The stable IDs of all elements of a program.
+///
+/// ```rust
+/// use FMitF_rs::ast::stable_id::StableIds;
+///
+/// let source = "nodes { A } table T on A { primary int id; int v; }
+///     void f(int x) { hop on A { int y = x + 1; T[id: x].v = y; } }";
+/// let program = FMitF_rs::parse_and_analyze(source).unwrap();
+/// let ids = StableIds::new(&program);
+///
+/// let stmt = program.hops[program.functions[program.root_functions[0]].hops[0]].statements[1];
+/// assert_eq!(ids.get(stmt), Some("fn:f/hop:1/stmt:2"));
+/// assert_eq!(ids.lookup("fn:f/hop:1/stmt:2"), Some(stmt.into()));
+///
+/// // Identical input yields identical arenas
+/// let again = FMitF_rs::parse_and_analyze(source).unwrap();
+/// assert_eq!(program.to_json().unwrap(), again.to_json().unwrap());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StableIds {
+    ids: HashMap<ElementId, String>,
+    elements: HashMap<String, ElementId>,
+}
+
+impl StableIds {
+    /// Computes the stable ID of every element reachable from the program's roots.
+    pub fn new(program: &Program) -> Self {
+        let mut ids = Self::default();
+
+        for &node_id in &program.root_nodes {
+            ids.insert(node_id, format!("node:{}", program.nodes[node_id].name));
+        }
+
+        for &table_id in &program.root_tables {
+            let table = &program.tables[table_id];
+            let table_key = format!("table:{}", table.name);
+            for &field_id in &table.fields {
+                let field = &program.fields[field_id];
+                ids.insert(
+                    field_id,
+                    format!("{}/field:{}", table_key, field.field_name),
+                );
+            }
+            ids.insert(table_id, table_key);
+        }
+
+        for &function_id in &program.root_functions {
+            let function = &program.functions[function_id];
+            let function_key = format!("fn:{}", function.name);
+            for &param_id in &function.parameters {
+                let param = &program.parameters[param_id];
+                ids.insert(
+                    param_id,
+                    format!("{}/param:{}", function_key, param.param_name),
+                );
+            }
+            for (index, &hop_id) in function.hops.iter().enumerate() {
+                let hop_key = format!("{}/hop:{}", function_key, index + 1);
+                let mut counter = 0;
+                ids.insert_statements(
+                    program,
+                    &program.hops[hop_id].statements,
+                    &hop_key,
+                    &mut counter,
+                );
+                ids.insert(hop_id, hop_key);
+            }
+            ids.insert(function_id, function_key);
+        }
+
+        ids
+    }
+
+    /// Returns the stable ID of an element, if it is reachable from the program's roots.
+    pub fn get(&self, element: impl Into<ElementId>) -> Option<&str> {
+        self.ids.get(&element.into()).map(String::as_str)
+    }
+
+    /// Returns the element with the given stable ID.
+    pub fn lookup(&self, id: &str) -> Option<ElementId> {
+        self.elements.get(id).copied()
+    }
+
+    fn insert(&mut self, element: impl Into<ElementId>, id: String) {
+        let element = element.into();
+        self.elements.insert(id.clone(), element);
+        self.ids.insert(element, id);
+    }
+
+    fn insert_statements(
+        &mut self,
+        program: &Program,
+        statements: &[StatementId],
+        hop_key: &str,
+        counter: &mut usize,
+    ) {
+        for &stmt_id in statements {
+            *counter += 1;
+            let stmt_key = format!("{}/stmt:{}", hop_key, counter);
+
+            let mut exprs = Vec::new();
+            let mut blocks: Vec<&[StatementId]> = Vec::new();
+            match &program.statements[stmt_id].node {
+                StatementKind::VarDecl(decl) => exprs.extend(decl.init_value),
+                StatementKind::VarAssignment(assign) => exprs.push(assign.rhs),
+                StatementKind::Assignment(assign) => {
+                    exprs.extend(&assign.pk_exprs);
+                    exprs.push(assign.rhs);
+                }
+                StatementKind::MultiAssignment(multi) => {
+                    exprs.extend(&multi.pk_exprs);
+                    exprs.extend(multi.assignments.iter().map(|pair| pair.rhs));
+                }
+                StatementKind::IfStmt(if_stmt) => {
+                    exprs.push(if_stmt.condition);
+                    blocks.push(&if_stmt.then_branch);
+                    if let Some(else_branch) = &if_stmt.else_branch {
+                        blocks.push(else_branch);
+                    }
+                }
+                StatementKind::WhileStmt(while_stmt) => {
+                    exprs.push(while_stmt.condition);
+                    blocks.push(&while_stmt.body);
+                }
+                StatementKind::Return(ret) => exprs.extend(ret.value),
+                StatementKind::Abort(_)
+                | StatementKind::Break(_)
+                | StatementKind::Continue(_)
+                | StatementKind::Empty => {}
+            }
+
+            let mut expr_counter = 0;
+            for expr_id in exprs {
+                self.insert_expression(program, expr_id, &stmt_key, &mut expr_counter);
+            }
+            self.insert(stmt_id, stmt_key);
+
+            for block in blocks {
+                self.insert_statements(program, block, hop_key, counter);
+            }
+        }
+    }
+
+    fn insert_expression(
+        &mut self,
+        program: &Program,
+        expr_id: ExpressionId,
+        stmt_key: &str,
+        counter: &mut usize,
+    ) {
+        *counter += 1;
+        self.insert(expr_id, format!("{}/expr:{}", stmt_key, counter));
+
+        match &program.expressions[expr_id].node {
+            ExpressionKind::TableFieldAccess { pk_exprs, .. } => {
+                for &pk_expr in pk_exprs {
+                    self.insert_expression(program, pk_expr, stmt_key, counter);
+                }
+            }
+            ExpressionKind::UnaryOp { expr, .. } => {
+                self.insert_expression(program, *expr, stmt_key, counter);
+            }
+            ExpressionKind::BinaryOp { left, right, .. } => {
+                self.insert_expression(program, *left, stmt_key, counter);
+                self.insert_expression(program, *right, stmt_key, counter);
+            }
+            ExpressionKind::Ident(_)
+            | ExpressionKind::IntLit(_)
+            | ExpressionKind::FloatLit(_)
+            | ExpressionKind::StringLit(_)
+            | ExpressionKind::BoolLit(_) => {}
+        }
+    }
+}
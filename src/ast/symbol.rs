@@ -0,0 +1,182 @@
+//! The `symbol` module interns identifiers.
+//! Every distinct name is stored once while it is in use, and a `Symbol` is a
+//! cheaply cloned handle to it. Comparing and hashing symbols only looks at
+//! the handle, so maps keyed by names avoid hashing whole strings.
+//!
+//! # Overview
+//!
+//! - **Symbol**: An interned name; derefs to `str` and displays as the name.
+//!
+//! The interner is process-wide (like the one in rustc), so a symbol can be
+//! printed, compared with a string, or serialized without passing a context
+//! around. It only holds names weakly: a name is freed with the last symbol
+//! for it, so a long-lived process such as the language server does not keep
+//! every identifier it has ever seen.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, LazyLock, Mutex, Weak};
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(Default::default);
+
+/// Names in use, bucketed by the hash of their text.
+#[derive(Default)]
+struct Interner {
+    buckets: HashMap<u64, Vec<Weak<str>>>,
+    /// Entries in `buckets`, freed or not.
+    entries: usize,
+    /// Entries left by the last sweep.
+    swept: usize,
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> Arc<str> {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        let bucket = self.buckets.entry(hasher.finish()).or_default();
+        if let Some(interned) = bucket
+            .iter()
+            .find_map(|weak| weak.upgrade().filter(|interned| **interned == *name))
+        {
+            return interned;
+        }
+        let interned: Arc<str> = Arc::from(name);
+        bucket.push(Arc::downgrade(&interned));
+        self.entries += 1;
+        if self.entries > 2 * self.swept.max(1024) {
+            self.sweep();
+        }
+        interned
+    }
+
+    /// Drops the entries of names that are no longer used.
+    fn sweep(&mut self) {
+        self.buckets.retain(|_, bucket| {
+            bucket.retain(|weak| weak.strong_count() > 0);
+            !bucket.is_empty()
+        });
+        self.entries = self.buckets.values().map(Vec::len).sum();
+        self.swept = self.entries;
+    }
+}
+
+/// An interned identifier.
+///
+/// Equality and hashing use the address of the interned string, which is
+/// unique per name among the names in use. Ordering compares the names themselves, so sorting by
+/// symbol is deterministic.
+///
+/// ```rust
+/// use FMitF_rs::ast::Symbol;
+///
+/// let a = Symbol::intern("balance");
+/// assert_eq!(a, Symbol::intern("balance"));
+/// assert_eq!(a, "balance");
+/// assert_eq!(a.len(), 7);
+/// ```
+#[derive(Clone)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    /// Returns the symbol for `name`, interning it on first use.
+    pub fn intern(name: &str) -> Self {
+        let mut interner = INTERNER.lock().unwrap_or_else(|e| e.into_inner());
+        Symbol(interner.intern(name))
+    }
+
+    /// Returns the name this symbol stands for.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(Arc::as_ptr(&self.0), state);
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        &*self.0 == other.as_str()
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.0.to_string()
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Symbol::intern(&String::deserialize(deserializer)?))
+    }
+}
@@ -0,0 +1,426 @@
+//! The `trivia` module keeps the parts of the source that the arena AST drops:
+//! comments and blank lines. It is a concrete-syntax layer that sits next to the
+//! AST and attaches each piece of trivia to a syntax anchor (a declaration, a hop
+//! or a statement), so tools such as the formatter can reproduce it.
+//!
+//! # Overview
+//!
+//! - **SyntaxAnchor**: The AST element a piece of trivia is attached to.
+//! - **Trivia**: A comment, a run of blank lines or a section disabled by `#if`.
+//! - **TriviaMap**: Leading, trailing and dangling trivia for every anchor.
+//!
+//! # Attachment rules
+//!
+//! - A comment on the same line after the end of an anchor (and any `,` or `;` after it)
+//!   is *trailing* trivia of that anchor.
+//! - Other comments and blank lines are *leading* trivia of the anchor that follows them.
+//! - Trivia right before a closing brace (or the end of the file) has no following
+//!   anchor and is *dangling* in the innermost enclosing anchor (or the file).
+//! - So is a comment inside a statement, as in the middle of an expression; it is
+//!   dangling in that statement.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::ast::*;
+
+/// An AST element that trivia can be attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SyntaxAnchor {
+    /// A whole source file, by index into `Program::source_files`.
+    /// Leading trivia is the file header, dangling trivia the file footer.
+    File(usize),
+    /// The `nodes { ... }` block of a source file.
+    NodesBlock(usize),
+    Node(#[serde(with = "serialize::id")] NodeId),
+    Table(#[serde(with = "serialize::id")] TableId),
+    Field(#[serde(with = "serialize::id")] FieldId),
+    Function(#[serde(with = "serialize::id")] FunctionId),
+    Hop(#[serde(with = "serialize::id")] HopId),
+    Statement(#[serde(with = "serialize::id")] StatementId),
+    /// A template declaration, by index into `Program::templates`.
+    Template(usize),
+    /// An `instantiate` statement, by index into `Program::template_instances`.
+    Instance(usize),
+}
+
+/// A piece of source text without meaning to the AST.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trivia {
+    /// A `//` comment; `text` includes the leading slashes.
+    Comment { text: String, span: Span },
+    /// One or more consecutive empty lines.
+    BlankLines(usize),
+    /// Lines removed by conditional compilation (`#if` directives and disabled
+    /// sections), kept verbatim.
+    Disabled { text: String, span: Span },
+}
+
+/// Trivia of a program, keyed by the anchor it is attached to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TriviaMap {
+    #[serde(with = "serialize::entries")]
+    pub leading: HashMap<SyntaxAnchor, Vec<Trivia>>,
+    #[serde(with = "serialize::entries")]
+    pub trailing: HashMap<SyntaxAnchor, Trivia>,
+    #[serde(with = "serialize::entries")]
+    pub dangling: HashMap<SyntaxAnchor, Vec<Trivia>>,
+}
+
+impl TriviaMap {
+    pub fn leading(&self, anchor: SyntaxAnchor) -> &[Trivia] {
+        self.leading.get(&anchor).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn trailing(&self, anchor: SyntaxAnchor) -> Option<&Trivia> {
+        self.trailing.get(&anchor)
+    }
+
+    pub fn dangling(&self, anchor: SyntaxAnchor) -> &[Trivia] {
+        self.dangling.get(&anchor).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Returns true if no trivia has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.leading.is_empty() && self.trailing.is_empty() && self.dangling.is_empty()
+    }
+
+    /// Lints named in `// allow(name, ...)` comments, with the anchor each comment
+    /// leads or trails.
+    pub fn allowed_lints(&self) -> Vec<(SyntaxAnchor, &str)> {
+        self.annotations("allow")
+    }
+
+    /// Properties of records stated in `// assume(prop, ...)` comments, with the
+    /// anchor each comment leads or trails.
+    pub fn assumptions(&self) -> Vec<(SyntaxAnchor, &str)> {
+        self.annotations("assume")
+    }
+
+    /// Properties a function must leave, stated in `// ensures(prop, ...)`
+    /// comments, with the anchor each comment leads or trails.
+    pub fn ensures(&self) -> Vec<(SyntaxAnchor, &str)> {
+        self.annotations("ensures")
+    }
+
+    /// The items of `// keyword(item, ...)` comments, with the anchor each
+    /// comment leads or trails.
+    fn annotations(&self, keyword: &str) -> Vec<(SyntaxAnchor, &str)> {
+        let leading = self
+            .leading
+            .iter()
+            .flat_map(|(anchor, items)| items.iter().map(move |item| (*anchor, item)));
+        let trailing = self.trailing.iter().map(|(anchor, item)| (*anchor, item));
+        leading
+            .chain(trailing)
+            .filter_map(|(anchor, item)| match item {
+                Trivia::Comment { text, .. } => Some((anchor, text)),
+                _ => None,
+            })
+            .filter_map(|(anchor, text)| {
+                let list = text
+                    .trim_start_matches('/')
+                    .trim()
+                    .strip_prefix(keyword)?
+                    .strip_prefix('(')?
+                    .strip_suffix(')')?;
+                Some(list.split(',').map(move |item| (anchor, item.trim())))
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+/// A maximal run of whitespace and comments between two significant characters.
+struct Gap {
+    /// Offset just after the previous significant character, `None` at the start of file.
+    prev: Option<usize>,
+    /// Offset of the next significant character, `None` at the end of file.
+    next: Option<usize>,
+    /// Comments in the gap with their byte ranges.
+    comments: Vec<(usize, usize)>,
+}
+
+/// Splits `source` into gaps of whitespace and comments, skipping string literals.
+///
+/// `inactive` ranges (see `SourceFile::inactive`) are reported like comments.
+fn scan_gaps(source: &str, inactive: &[Range<usize>]) -> Vec<Gap> {
+    let bytes = source.as_bytes();
+    let mut gaps = Vec::new();
+    let mut current = Gap {
+        prev: None,
+        next: None,
+        comments: Vec::new(),
+    };
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(range) = inactive.iter().find(|range| range.start == i) {
+            current.comments.push((range.start, range.end));
+            i = range.end.max(i + 1);
+            continue;
+        }
+        match bytes[i] {
+            b' ' | b'\t' | b'\r' | b'\n' => i += 1,
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                let end = source[i..].find('\n').map_or(bytes.len(), |n| i + n);
+                current.comments.push((i, end));
+                i = end;
+            }
+            c => {
+                current.next = Some(i);
+                gaps.push(current);
+                // Consume the token; strings may contain `//`
+                i += 1;
+                if c == b'"' {
+                    while i < bytes.len() && bytes[i] != b'"' {
+                        i += 1;
+                    }
+                    i += 1;
+                } else {
+                    while i < bytes.len()
+                        && !matches!(bytes[i], b' ' | b'\t' | b'\r' | b'\n' | b'"')
+                        && !(bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'/'))
+                    {
+                        i += 1;
+                    }
+                }
+                current = Gap {
+                    prev: Some(i.min(bytes.len())),
+                    next: None,
+                    comments: Vec::new(),
+                };
+            }
+        }
+    }
+    gaps.push(current);
+    gaps
+}
+
+/// Punctuation that may sit between an anchor and its trailing comment, as in `A, // ...`.
+fn is_separator(c: char) -> bool {
+    matches!(c, ',' | ';' | ' ' | '\t')
+}
+
+fn blank_lines(text: &str) -> usize {
+    text.matches('\n').count().saturating_sub(1)
+}
+
+/// Collects the trivia of every source file of `program`.
+///
+/// `nodes_blocks` are the spans of the `nodes { ... }` blocks, which the arena AST
+/// does not record.
+pub(crate) fn collect_trivia(program: &Program, nodes_blocks: &[Span]) -> TriviaMap {
+    let mut trivia = TriviaMap::default();
+
+    for (file_index, file) in program.source_files.iter().enumerate() {
+        let in_file = |span: &Span| span.file == file.path;
+        let mut anchors: Vec<(SyntaxAnchor, Span)> = Vec::new();
+
+        anchors.extend(
+            nodes_blocks
+                .iter()
+                .filter(|span| in_file(span))
+                .map(|span| (SyntaxAnchor::NodesBlock(file_index), span.clone())),
+        );
+        anchors.extend(
+            program
+                .nodes
+                .iter()
+                .filter(|(_, n)| in_file(&n.span))
+                .map(|(id, n)| (SyntaxAnchor::Node(id), n.span.clone())),
+        );
+        anchors.extend(
+            program
+                .tables
+                .iter()
+                .filter(|(_, t)| in_file(&t.span))
+                .map(|(id, t)| (SyntaxAnchor::Table(id), t.span.clone())),
+        );
+        anchors.extend(
+            program
+                .fields
+                .iter()
+                .filter(|(_, f)| in_file(&f.span))
+                .map(|(id, f)| (SyntaxAnchor::Field(id), f.span.clone())),
+        );
+        anchors.extend(
+            program
+                .functions
+                .iter()
+                .filter(|(_, f)| in_file(&f.span))
+                .map(|(id, f)| (SyntaxAnchor::Function(id), f.span.clone())),
+        );
+        anchors.extend(
+            program
+                .hops
+                .iter()
+                .filter(|(_, h)| in_file(&h.span))
+                .map(|(id, h)| (SyntaxAnchor::Hop(id), h.span.clone())),
+        );
+        anchors.extend(
+            program
+                .statements
+                .iter()
+                .filter(|(_, s)| in_file(&s.span))
+                .map(|(id, s)| (SyntaxAnchor::Statement(id), s.span.clone())),
+        );
+
+        // Functions expanded from a template share the template's spans; the
+        // template itself is the anchor for everything written inside it
+        let templates: Vec<&Span> = program
+            .templates
+            .iter()
+            .map(|t| &t.span)
+            .filter(|span| in_file(span))
+            .collect();
+        anchors.retain(|(_, span)| {
+            !templates
+                .iter()
+                .any(|t| t.start <= span.start && span.end <= t.end)
+        });
+        anchors.extend(
+            program
+                .templates
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| in_file(&t.span))
+                .map(|(i, t)| (SyntaxAnchor::Template(i), t.span.clone())),
+        );
+        anchors.extend(
+            program
+                .template_instances
+                .iter()
+                .enumerate()
+                .filter(|(_, inst)| in_file(&inst.span))
+                .map(|(i, inst)| (SyntaxAnchor::Instance(i), inst.span.clone())),
+        );
+
+        collect_file_trivia(&mut trivia, file_index, file, &anchors);
+    }
+
+    trivia
+}
+
+/// Collects the trivia of one source file and records it in `trivia`.
+///
+/// `anchors` are all anchors of the file with their spans.
+pub(crate) fn collect_file_trivia(
+    trivia: &mut TriviaMap,
+    file_index: usize,
+    file: &SourceFile,
+    anchors: &[(SyntaxAnchor, Span)],
+) {
+    let source = file.source.as_str();
+    for gap in scan_gaps(source, &file.inactive) {
+        let mut items = Vec::new();
+        let mut cursor = gap.prev.unwrap_or(0);
+
+        for (index, &(start, end)) in gap.comments.iter().enumerate() {
+            let before = &source[cursor..start];
+            let (line, column) = line_col(source, start);
+            let (end_line, end_column) = line_col(source, end);
+            let span = Span {
+                start,
+                end,
+                line,
+                column,
+                end_line,
+                end_column,
+                file: file.path.clone(),
+            };
+            let comment = if file.inactive.iter().any(|range| range.start == start) {
+                let text = source[start..end].lines().map(str::trim_end);
+                Trivia::Disabled {
+                    text: text.collect::<Vec<_>>().join("\n"),
+                    span,
+                }
+            } else {
+                Trivia::Comment {
+                    text: source[start..end].trim_end().to_string(),
+                    span,
+                }
+            };
+
+            // A comment on the same line as the previous token trails the anchor ending there
+            let same_line = !before.contains('\n') && matches!(comment, Trivia::Comment { .. });
+            if index == 0 && gap.prev.is_some() && same_line {
+                let prev = gap.prev.unwrap_or(0);
+                let owner = anchors
+                    .iter()
+                    .filter(|(_, span)| {
+                        span.end <= prev && source[span.end..prev].chars().all(is_separator)
+                    })
+                    .max_by_key(|(_, span)| (span.end, span.start));
+                if let Some((anchor, _)) = owner {
+                    trivia.trailing.insert(*anchor, comment);
+                    cursor = end;
+                    continue;
+                }
+            }
+
+            if gap.prev.is_some() && blank_lines(before) > 0 {
+                items.push(Trivia::BlankLines(blank_lines(before)));
+            }
+            items.push(comment);
+            cursor = end;
+        }
+
+        let next = gap.next.unwrap_or(source.len());
+        if gap.prev.is_some() && gap.next.is_some() && blank_lines(&source[cursor..next]) > 0 {
+            items.push(Trivia::BlankLines(blank_lines(&source[cursor..next])));
+        }
+        if items.is_empty() {
+            continue;
+        }
+
+        // Leading trivia of the outermost anchor starting at the next token
+        let following = gap.next.and_then(|next| {
+            anchors
+                .iter()
+                .filter(|(_, span)| span.start == next)
+                .max_by_key(|(_, span)| span.end)
+        });
+        if let Some((anchor, _)) = following {
+            trivia.leading.entry(*anchor).or_default().extend(items);
+            continue;
+        }
+
+        // Otherwise dangling in the innermost anchor that encloses the gap
+        let enclosing = anchors
+            .iter()
+            .filter(|(_, span)| span.start < next && next < span.end)
+            .min_by_key(|(_, span)| span.end - span.start);
+        while matches!(items.last(), Some(Trivia::BlankLines(_))) {
+            items.pop();
+        }
+        if items.is_empty() {
+            continue;
+        }
+        match (enclosing, gap.prev) {
+            (Some((anchor, _)), _) => trivia.dangling.entry(*anchor).or_default().extend(items),
+            (None, None) => trivia
+                .leading
+                .entry(SyntaxAnchor::File(file_index))
+                .or_default()
+                .extend(items),
+            (None, Some(_)) => trivia
+                .dangling
+                .entry(SyntaxAnchor::File(file_index))
+                .or_default()
+                .extend(items),
+        }
+    }
+}
+
+/// Computes the 1-based line and column of a byte offset.
+fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rfind('\n')
+        .map_or(before.chars().count(), |n| before[n + 1..].chars().count())
+        + 1;
+    (line, column)
+}
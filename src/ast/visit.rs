@@ -0,0 +1,258 @@
+//! The `visit` module walks the functions, hops, statements and expressions of
+//! a program, so that lints, metrics and transforms need not each recurse
+//! through the statement and expression arenas themselves.
+//!
+//! # Overview
+//!
+//! - **Visitor**: Visits the elements of a `&Program` by id. Each method walks
+//!   into the element's children by default, so an override that does its work
+//!   and calls the matching `walk_` function keeps the traversal going, and one
+//!   that does not call it skips the children.
+//! - **VisitorMut**: The same over a `&mut Program`, for transforms.
+//! - **walk_program / walk_function / ...**: The default traversals.
+//!
+//! Statements are visited in source order. The expressions of a statement are
+//! visited before the statements nested in it, and the operands of an
+//! expression from left to right.
+//!
+//! ```
+//! use FMitF_rs::ast::visit::{self, Visitor, VisitorMut};
+//! use FMitF_rs::ast::{ExpressionId, ExpressionKind, Program};
+//! use FMitF_rs::parse_and_analyze;
+//!
+//! let mut program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void f(int id) { hop on A { if (T[id: id].n > 1) { T[id: id + 1].n = 2; } } }
+//!     "#,
+//! )
+//! .unwrap();
+//!
+//! // Counts the integer literals
+//! struct Literals(usize);
+//! impl Visitor for Literals {
+//!     fn visit_expression(&mut self, program: &Program, expr: ExpressionId) {
+//!         if let ExpressionKind::IntLit(_) = program.expressions[expr].node {
+//!             self.0 += 1;
+//!         }
+//!         visit::walk_expression(self, program, expr);
+//!     }
+//! }
+//! let mut literals = Literals(0);
+//! visit::walk_program(&mut literals, &program);
+//! assert_eq!(literals.0, 3);
+//!
+//! // Doubles them
+//! struct Double;
+//! impl VisitorMut for Double {
+//!     fn visit_expression_mut(&mut self, program: &mut Program, expr: ExpressionId) {
+//!         if let ExpressionKind::IntLit(value) = &mut program.expressions[expr].node {
+//!             *value *= 2;
+//!         }
+//!         visit::walk_expression_mut(self, program, expr);
+//!     }
+//! }
+//! visit::walk_program_mut(&mut Double, &mut program);
+//! let formatted = FMitF_rs::format_program(&program);
+//! assert!(formatted.contains("T[id: id + 2].n = 4;"), "{}", formatted);
+//! ```
+
+use crate::ast::*;
+
+impl StatementKind {
+    /// The expressions of the statement itself, in source order, without those
+    /// of the statements nested in it.
+    pub fn expressions(&self) -> Vec<ExpressionId> {
+        match self {
+            StatementKind::Assignment(assign) => {
+                let mut exprs = assign.pk_exprs.clone();
+                exprs.push(assign.rhs);
+                exprs
+            }
+            StatementKind::MultiAssignment(multi_assign) => {
+                let mut exprs = multi_assign.pk_exprs.clone();
+                exprs.extend(multi_assign.assignments.iter().map(|pair| pair.rhs));
+                exprs
+            }
+            StatementKind::VarAssignment(var_assign) => vec![var_assign.rhs],
+            StatementKind::VarDecl(var_decl) => var_decl.init_value.into_iter().collect(),
+            StatementKind::IfStmt(if_stmt) => vec![if_stmt.condition],
+            StatementKind::WhileStmt(while_stmt) => vec![while_stmt.condition],
+            StatementKind::Return(ret_stmt) => ret_stmt.value.into_iter().collect(),
+            StatementKind::Abort(_)
+            | StatementKind::Break(_)
+            | StatementKind::Continue(_)
+            | StatementKind::Empty => Vec::new(),
+        }
+    }
+
+    /// The statements nested in it, in source order: the branches of an `if`
+    /// or the body of a `while`.
+    pub fn nested_statements(&self) -> Vec<StatementId> {
+        match self {
+            StatementKind::IfStmt(if_stmt) => if_stmt
+                .then_branch
+                .iter()
+                .chain(if_stmt.else_branch.iter().flatten())
+                .copied()
+                .collect(),
+            StatementKind::WhileStmt(while_stmt) => while_stmt.body.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl ExpressionKind {
+    /// The expressions it is built from, from left to right.
+    pub fn operands(&self) -> Vec<ExpressionId> {
+        match self {
+            ExpressionKind::TableFieldAccess { pk_exprs, .. } => pk_exprs.clone(),
+            ExpressionKind::UnaryOp { expr, .. } => vec![*expr],
+            ExpressionKind::BinaryOp { left, right, .. } => vec![*left, *right],
+            ExpressionKind::Ident(_)
+            | ExpressionKind::IntLit(_)
+            | ExpressionKind::FloatLit(_)
+            | ExpressionKind::StringLit(_)
+            | ExpressionKind::BoolLit(_) => Vec::new(),
+        }
+    }
+}
+
+/// Visits the elements of a program; every method walks into the children of
+/// its element unless overridden.
+pub trait Visitor {
+    fn visit_function(&mut self, program: &Program, function: FunctionId) {
+        walk_function(self, program, function);
+    }
+
+    fn visit_hop(&mut self, program: &Program, hop: HopId) {
+        walk_hop(self, program, hop);
+    }
+
+    fn visit_statement(&mut self, program: &Program, stmt: StatementId) {
+        walk_statement(self, program, stmt);
+    }
+
+    fn visit_expression(&mut self, program: &Program, expr: ExpressionId) {
+        walk_expression(self, program, expr);
+    }
+}
+
+/// Visits the functions of the program, in declaration order.
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for &function in &program.root_functions {
+        visitor.visit_function(program, function);
+    }
+}
+
+/// Visits the hops of a function.
+pub fn walk_function<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    program: &Program,
+    function: FunctionId,
+) {
+    for &hop in &program.functions[function].hops {
+        visitor.visit_hop(program, hop);
+    }
+}
+
+/// Visits the statements of a hop.
+pub fn walk_hop<V: Visitor + ?Sized>(visitor: &mut V, program: &Program, hop: HopId) {
+    for &stmt in &program.hops[hop].statements {
+        visitor.visit_statement(program, stmt);
+    }
+}
+
+/// Visits the expressions of a statement, then the statements nested in it.
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, program: &Program, stmt: StatementId) {
+    let kind = &program.statements[stmt].node;
+    for expr in kind.expressions() {
+        visitor.visit_expression(program, expr);
+    }
+    for nested in kind.nested_statements() {
+        visitor.visit_statement(program, nested);
+    }
+}
+
+/// Visits the operands of an expression.
+pub fn walk_expression<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    program: &Program,
+    expr: ExpressionId,
+) {
+    for operand in program.expressions[expr].node.operands() {
+        visitor.visit_expression(program, operand);
+    }
+}
+
+/// Visits the elements of a program, which it may change; every method walks
+/// into the children of its element, as they are after the method's own
+/// changes, unless overridden.
+pub trait VisitorMut {
+    fn visit_function_mut(&mut self, program: &mut Program, function: FunctionId) {
+        walk_function_mut(self, program, function);
+    }
+
+    fn visit_hop_mut(&mut self, program: &mut Program, hop: HopId) {
+        walk_hop_mut(self, program, hop);
+    }
+
+    fn visit_statement_mut(&mut self, program: &mut Program, stmt: StatementId) {
+        walk_statement_mut(self, program, stmt);
+    }
+
+    fn visit_expression_mut(&mut self, program: &mut Program, expr: ExpressionId) {
+        walk_expression_mut(self, program, expr);
+    }
+}
+
+/// Visits the functions of the program, in declaration order.
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    for function in program.root_functions.clone() {
+        visitor.visit_function_mut(program, function);
+    }
+}
+
+/// Visits the hops of a function.
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    program: &mut Program,
+    function: FunctionId,
+) {
+    for hop in program.functions[function].hops.clone() {
+        visitor.visit_hop_mut(program, hop);
+    }
+}
+
+/// Visits the statements of a hop.
+pub fn walk_hop_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program, hop: HopId) {
+    for stmt in program.hops[hop].statements.clone() {
+        visitor.visit_statement_mut(program, stmt);
+    }
+}
+
+/// Visits the expressions of a statement, then the statements nested in it.
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    program: &mut Program,
+    stmt: StatementId,
+) {
+    for expr in program.statements[stmt].node.expressions() {
+        visitor.visit_expression_mut(program, expr);
+    }
+    for nested in program.statements[stmt].node.nested_statements() {
+        visitor.visit_statement_mut(program, nested);
+    }
+}
+
+/// Visits the operands of an expression.
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    program: &mut Program,
+    expr: ExpressionId,
+) {
+    for operand in program.expressions[expr].node.operands() {
+        visitor.visit_expression_mut(program, operand);
+    }
+}
@@ -1,19 +1,39 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::ast;
+use crate::cfg::serialize::{ast_id_map, named_ids};
 use crate::cfg::*;
 
 /// Builds CFG from an AST Program.
 pub struct CfgBuilder;
 
 /// Context for building CFG - holds the program being constructed
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct CfgCtx {
     pub program: CfgProgram,
 
     // Lookup maps for resolved AST items
-    pub node_map: HashMap<String, NodeId>,
-    pub table_map: HashMap<String, TableId>,
-    pub field_map: HashMap<String, FieldId>,
+    #[serde(with = "named_ids")]
+    pub node_map: HashMap<ast::Symbol, NodeId>,
+    #[serde(with = "named_ids")]
+    pub table_map: HashMap<ast::Symbol, TableId>,
+    #[serde(with = "ast_id_map")]
+    pub field_map: HashMap<ast::FieldId, FieldId>,
+    #[serde(with = "named_ids")]
+    pub function_map: HashMap<ast::Symbol, FunctionId>,
+}
+
+impl Serialize for CfgCtx {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CfgCtx::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CfgCtx {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serialize::read_arenas(|| CfgCtx::deserialize(deserializer))
+    }
 }
 
 /// Helper struct to manage building a single function's CFG.
@@ -25,7 +45,7 @@ struct FunctionContextBuilder<'a> {
     function: FunctionCfg,
 
     // Local mappings for this function
-    var_map: HashMap<String, VarId>,
+    var_map: HashMap<ast::Symbol, VarId>,
 
     // Loop context stack for break/continue
     loop_stack: Vec<LoopContext>,
@@ -41,6 +61,19 @@ struct LoopContext {
     break_target: BasicBlockId,
 }
 
+impl Default for CfgBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CfgCtx {
+    /// The arenas of the program built.
+    pub fn memory_stats(&self) -> crate::memory::MemoryStats {
+        self.program.memory_stats()
+    }
+}
+
 impl CfgBuilder {
     pub fn new() -> Self {
         Self
@@ -61,6 +94,7 @@ impl CfgBuilder {
             node_map: HashMap::new(),
             table_map: HashMap::new(),
             field_map: HashMap::new(),
+            function_map: HashMap::new(),
         };
 
         // Build global items first
@@ -77,7 +111,7 @@ impl CfgBuilder {
             let node_ast = &program.nodes[node_id];
 
             let cfg_node = NodeInfo {
-                name: node_ast.name.clone(),
+                name: node_ast.name.to_string(),
                 tables: Vec::new(), // Will be populated when building tables
             };
 
@@ -107,7 +141,7 @@ impl CfgBuilder {
                 let field_ast = &program.fields[field_ast_id];
 
                 let cfg_field = FieldInfo {
-                    name: field_ast.field_name.clone(),
+                    name: field_ast.field_name.to_string(),
                     ty: field_ast.field_type.clone(),
                     table_id: None, // Will be updated after table_id is allocated
                     is_primary: field_ast.is_primary,
@@ -116,7 +150,7 @@ impl CfgBuilder {
                 let cfg_field_id = ctx.program.fields.alloc(cfg_field);
                 field_ids.push(cfg_field_id);
                 ctx.field_map
-                    .insert(field_ast.field_name.clone(), cfg_field_id);
+                    .insert(field_ast_id, cfg_field_id);
 
                 if field_ast.is_primary {
                     primary_key_ids.push(cfg_field_id); // Add to list instead of overwriting
@@ -128,12 +162,25 @@ impl CfgBuilder {
                 return Err(format!("Table {} has no primary key", table_ast.name));
             }
 
+            let assumptions = program
+                .trivia
+                .assumptions()
+                .into_iter()
+                .filter(|(anchor, _)| match anchor {
+                    ast::SyntaxAnchor::Table(table) => *table == table_id,
+                    ast::SyntaxAnchor::Field(field) => table_ast.fields.contains(field),
+                    _ => false,
+                })
+                .map(|(_, assumption)| assumption.to_string())
+                .collect();
+
             // Build table with composite primary keys
             let cfg_table = TableInfo {
-                name: table_ast.name.clone(),
+                name: table_ast.name.to_string(),
                 node_id: *node_id,
                 fields: field_ids.clone(),
                 primary_keys: primary_key_ids, // Use the list of primary keys
+                assumptions,
             };
 
             let cfg_table_id = ctx.program.tables.alloc(cfg_table);
@@ -160,16 +207,122 @@ impl CfgBuilder {
             let function = builder.build(program, func_ast)?;
 
             let cfg_func_id = ctx.program.functions.alloc(function);
+            ctx.function_map.insert(func_ast.name.clone(), cfg_func_id);
             ctx.program.root_functions.push(cfg_func_id);
         }
         Ok(())
     }
+
+    /// Rebuild the CFGs of the `changed` functions of a program and reuse the
+    /// rest from `ctx`, which must have been built from an earlier version of
+    /// the same program, e.g. one `ast::incremental::reparse` since updated.
+    ///
+    /// Functions are matched by name: a changed function keeps its
+    /// `FunctionId`, a new one is added and a removed one is dropped from
+    /// `root_functions` (the arena cannot free it). A reused function keeps
+    /// the spans of the build it came from, so functions that only moved must
+    /// be listed as changed where spans matter. When nodes or tables changed,
+    /// everything is rebuilt. On error `ctx` is left as it was.
+    ///
+    /// ```rust
+    /// use FMitF_rs::ast::incremental::{reparse, Reparsed, TextEdit};
+    /// use FMitF_rs::ast::Symbol;
+    /// use FMitF_rs::CfgBuilder;
+    /// use std::collections::HashSet;
+    ///
+    /// let source = "nodes { A } table T on A { primary int id; int v; }
+    /// void f(int x) { hop on A { T[id: x].v = 1; } }
+    /// void g(int x) { hop on A { T[id: x].v = 2; } }";
+    /// let mut program = FMitF_rs::parse_and_analyze(source).unwrap();
+    /// let mut ctx = CfgBuilder::build_from_program(&program).unwrap();
+    /// let (f, g) = (ctx.function_map[&Symbol::intern("f")], ctx.function_map[&Symbol::intern("g")]);
+    ///
+    /// let start = source.find("v = 1").unwrap();
+    /// let edit = TextEdit { file: 0, range: start..start + 5, text: "v = T[id: 0].v".to_string() };
+    /// let result = reparse(&mut program, &edit, &Default::default(), &Default::default()).unwrap();
+    /// let Reparsed::Function { new, .. } = result else { unreachable!() };
+    ///
+    /// CfgBuilder::rebuild_functions(&program, &mut ctx, &HashSet::from([new])).unwrap();
+    /// assert_eq!(ctx.program.root_functions, vec![f, g]);
+    /// // `f` now reads before writing
+    /// let f_cfg = &ctx.program.functions[f];
+    /// let entry = f_cfg.hops[f_cfg.hop_order[0]].entry_block.unwrap();
+    /// assert_eq!(f_cfg.blocks[entry].statements.len(), 2);
+    /// ```
+    pub fn rebuild_functions(
+        program: &ast::Program,
+        ctx: &mut CfgCtx,
+        changed: &HashSet<ast::FunctionId>,
+    ) -> Result<(), String> {
+        if !Self::same_schema(program, ctx) {
+            *ctx = Self::build_from_program(program)?;
+            return Ok(());
+        }
+
+        // Build everything first so that a failure leaves `ctx` untouched
+        let mut rebuilt = Vec::new();
+        for &func_id in &program.root_functions {
+            let func_ast = &program.functions[func_id];
+            if changed.contains(&func_id) || !ctx.function_map.contains_key(&func_ast.name) {
+                let builder = FunctionContextBuilder::new(ctx, func_ast)?;
+                rebuilt.push((func_ast.name.clone(), builder.build(program, func_ast)?));
+            }
+        }
+
+        for (name, function) in rebuilt {
+            match ctx.function_map.get(&name) {
+                Some(&cfg_func_id) => ctx.program.functions[cfg_func_id] = function,
+                None => {
+                    let cfg_func_id = ctx.program.functions.alloc(function);
+                    ctx.function_map.insert(name, cfg_func_id);
+                }
+            }
+        }
+        ctx.program.root_functions = program
+            .root_functions
+            .iter()
+            .map(|&func_id| ctx.function_map[&program.functions[func_id].name])
+            .collect();
+        ctx.function_map
+            .retain(|name, _| program.function_map.contains_key(name));
+        Ok(())
+    }
+
+    /// Whether the nodes, tables and fields of `ctx` were built from the ones
+    /// of `program`, so that its functions can be built against them.
+    fn same_schema(program: &ast::Program, ctx: &CfgCtx) -> bool {
+        let nodes_match = program.root_nodes.len() == ctx.program.root_nodes.len()
+            && program.root_nodes.iter().zip(&ctx.program.root_nodes).all(
+                |(&node_id, &cfg_node_id)| {
+                    ctx.node_map.get(&program.nodes[node_id].name) == Some(&cfg_node_id)
+                },
+            );
+        let tables_match = program.root_tables.len() == ctx.program.root_tables.len()
+            && program
+                .root_tables
+                .iter()
+                .zip(&ctx.program.root_tables)
+                .all(|(&table_id, &cfg_table_id)| {
+                    let table_ast = &program.tables[table_id];
+                    let table = &ctx.program.tables[cfg_table_id];
+                    ctx.table_map.get(&table_ast.name) == Some(&cfg_table_id)
+                        && ctx.program.nodes[table.node_id].name
+                            == program.nodes[table_ast.node].name.as_str()
+                        && table_ast.fields.len() == table.fields.len()
+                        && table_ast.fields.iter().zip(&table.fields).all(
+                            |(field_id, cfg_field_id)| {
+                                ctx.field_map.get(field_id) == Some(cfg_field_id)
+                            },
+                        )
+                });
+        nodes_match && tables_match
+    }
 }
 
 impl<'a> FunctionContextBuilder<'a> {
     fn new(ctx: &'a mut CfgCtx, func_ast: &ast::FunctionDeclaration) -> Result<Self, String> {
         let function = FunctionCfg {
-            name: func_ast.name.clone(),
+            name: func_ast.name.to_string(),
             return_type: func_ast.return_type.clone(),
             span: func_ast.span.clone(),
             variables: id_arena::Arena::new(),
@@ -178,6 +331,7 @@ impl<'a> FunctionContextBuilder<'a> {
             blocks: id_arena::Arena::new(),
             entry_hop: None, // Will be set
             hop_order: Vec::new(),
+            ensures: Vec::new(),
         };
 
         Ok(Self {
@@ -201,6 +355,17 @@ impl<'a> FunctionContextBuilder<'a> {
         // Build hops
         self.build_hops(program, func_ast)?;
 
+        self.function.ensures = program
+            .trivia
+            .ensures()
+            .into_iter()
+            .filter(|(anchor, _)| match anchor {
+                ast::SyntaxAnchor::Function(id) => std::ptr::eq(&program.functions[*id], func_ast),
+                _ => false,
+            })
+            .map(|(_, property)| property.to_string())
+            .collect();
+
         Ok(self.function)
     }
 
@@ -213,7 +378,7 @@ impl<'a> FunctionContextBuilder<'a> {
             let param_ast = &program.parameters[param_id];
 
             let var = Variable {
-                name: param_ast.param_name.clone(),
+                name: param_ast.param_name.to_string(),
                 ty: param_ast.param_type.clone(),
                 is_parameter: true,
             };
@@ -276,7 +441,7 @@ impl<'a> FunctionContextBuilder<'a> {
             self.current_hop_id = Some(hop_id);
 
             // Create entry block for this hop
-            let entry_block = self.new_basic_block(hop_id)?;
+            let entry_block = self.new_basic_block(hop_id, SourceOrigin::Synthetic)?;
             self.function.hops[hop_id].entry_block = Some(entry_block); // Assign Some(id)
             self.current_block_id = Some(entry_block);
 
@@ -285,7 +450,7 @@ impl<'a> FunctionContextBuilder<'a> {
                 if self.current_block_id.is_none() {
                     break; // Block was terminated
                 }
-                self.build_statement(program, &program.statements[stmt_id])?;
+                self.build_statement(program, stmt_id)?;
             }
 
             // Handle hop transition
@@ -319,8 +484,10 @@ impl<'a> FunctionContextBuilder<'a> {
     fn build_statement(
         &mut self,
         program: &ast::Program,
-        stmt: &ast::Statement,
+        stmt_id: ast::StatementId,
     ) -> Result<(), String> {
+        let stmt = &program.statements[stmt_id];
+        let origin = SourceOrigin::Statement(stmt_id);
         let current_block = self
             .current_block_id
             .ok_or("No active block for statement")?;
@@ -329,7 +496,7 @@ impl<'a> FunctionContextBuilder<'a> {
             ast::StatementKind::VarDecl(var_decl) => {
                 // Create variable
                 let var = Variable {
-                    name: var_decl.var_name.clone(),
+                    name: var_decl.var_name.to_string(),
                     ty: var_decl.var_type.clone(),
                     is_parameter: false,
                 };
@@ -337,8 +504,12 @@ impl<'a> FunctionContextBuilder<'a> {
                 let var_id = self.function.variables.alloc(var);
                 self.var_map.insert(var_decl.var_name.clone(), var_id);
 
-                // Build initializer
-                let init_operand = self.build_expression(program, var_decl.init_value)?;
+                // Build initializer; a declaration without one starts at the
+                // type's default value
+                let init_operand = match var_decl.init_value {
+                    Some(init_value) => self.build_expression(program, init_value)?,
+                    None => Operand::Const(default_value(&var_decl.var_type)),
+                };
 
                 self.add_statement(
                     current_block,
@@ -346,6 +517,7 @@ impl<'a> FunctionContextBuilder<'a> {
                         var: var_id,
                         rvalue: Rvalue::Use(init_operand),
                         span: stmt.span.clone(),
+                        origin,
                     },
                 );
             }
@@ -368,6 +540,7 @@ impl<'a> FunctionContextBuilder<'a> {
                         var: var_id,
                         rvalue: Rvalue::Use(rhs_operand),
                         span: stmt.span.clone(),
+                        origin,
                     },
                 );
             }
@@ -393,7 +566,7 @@ impl<'a> FunctionContextBuilder<'a> {
                         let pk_field_id = *self
                             .ctx
                             .field_map
-                            .get(&field_ast.field_name)
+                            .get(&pk_field)
                             .ok_or_else(|| {
                                 format!(
                                     "Primary key field {} not found in CFG",
@@ -411,7 +584,7 @@ impl<'a> FunctionContextBuilder<'a> {
                 }
 
                 if pk_field_ids.is_empty() {
-                    return Err(format!("No primary key fields provided"));
+                    return Err("No primary key fields provided".to_string());
                 }
 
                 let field_id = if let Some(resolved_field) = assign.resolved_field {
@@ -419,7 +592,7 @@ impl<'a> FunctionContextBuilder<'a> {
                     *self
                         .ctx
                         .field_map
-                        .get(&field_ast.field_name)
+                        .get(&resolved_field)
                         .ok_or_else(|| format!("Field {} not found in CFG", field_ast.field_name))?
                 } else {
                     return Err(format!("Field {} not resolved", assign.field_name));
@@ -436,6 +609,7 @@ impl<'a> FunctionContextBuilder<'a> {
                         field: field_id,
                         value: value_operand,
                         span: stmt.span.clone(),
+                        origin,
                     },
                 );
             }
@@ -464,7 +638,7 @@ impl<'a> FunctionContextBuilder<'a> {
                         let pk_field_id = *self
                             .ctx
                             .field_map
-                            .get(&field_ast.field_name)
+                            .get(&pk_field)
                             .ok_or_else(|| {
                                 format!(
                                     "Primary key field {} not found in CFG",
@@ -482,7 +656,7 @@ impl<'a> FunctionContextBuilder<'a> {
                 }
 
                 if pk_field_ids.is_empty() {
-                    return Err(format!("No primary key fields provided"));
+                    return Err("No primary key fields provided".to_string());
                 }
 
                 // Generate one TableAssign statement for each field assignment
@@ -492,7 +666,7 @@ impl<'a> FunctionContextBuilder<'a> {
                         *self
                             .ctx
                             .field_map
-                            .get(&field_ast.field_name)
+                            .get(&resolved_field)
                             .ok_or_else(|| format!("Field {} not found in CFG", field_ast.field_name))?
                     } else {
                         return Err(format!("Field {} not resolved", assignment.field_name));
@@ -509,15 +683,16 @@ impl<'a> FunctionContextBuilder<'a> {
                             field: field_id,
                             value: value_operand,
                             span: stmt.span.clone(),
+                            origin,
                         },
                     );
                 }
             }
             ast::StatementKind::IfStmt(if_stmt) => {
-                self.build_if_statement(program, if_stmt)?;
+                self.build_if_statement(program, stmt_id, if_stmt)?;
             }
             ast::StatementKind::WhileStmt(while_stmt) => {
-                self.build_while_statement(program, while_stmt)?;
+                self.build_while_statement(program, stmt_id, while_stmt)?;
             }
             ast::StatementKind::Return(ret_stmt) => {
                 let ret_operand = if let Some(expr_id) = ret_stmt.value {
@@ -526,21 +701,38 @@ impl<'a> FunctionContextBuilder<'a> {
                     None
                 };
 
-                self.set_terminator(current_block, Terminator::Return(ret_operand));
+                self.set_terminator_from(
+                    current_block,
+                    Terminator::Return(ret_operand),
+                    origin,
+                    &stmt.span,
+                );
                 self.current_block_id = None;
             }
             ast::StatementKind::Abort(_) => {
-                self.set_terminator(current_block, Terminator::Abort);
+                self.set_terminator_from(current_block, Terminator::Abort, origin, &stmt.span);
                 self.current_block_id = None;
             }
             ast::StatementKind::Break(_) => {
                 let loop_ctx = self.loop_stack.last().ok_or("Break outside loop")?;
-                self.set_terminator(current_block, Terminator::Goto(loop_ctx.break_target));
+                let target = loop_ctx.break_target;
+                self.set_terminator_from(
+                    current_block,
+                    Terminator::Goto(target),
+                    origin,
+                    &stmt.span,
+                );
                 self.current_block_id = None;
             }
             ast::StatementKind::Continue(_) => {
                 let loop_ctx = self.loop_stack.last().ok_or("Continue outside loop")?;
-                self.set_terminator(current_block, Terminator::Goto(loop_ctx.continue_target));
+                let target = loop_ctx.continue_target;
+                self.set_terminator_from(
+                    current_block,
+                    Terminator::Goto(target),
+                    origin,
+                    &stmt.span,
+                );
                 self.current_block_id = None;
             }
             ast::StatementKind::Empty => {
@@ -554,6 +746,7 @@ impl<'a> FunctionContextBuilder<'a> {
     fn build_if_statement(
         &mut self,
         program: &ast::Program,
+        stmt_id: ast::StatementId,
         if_stmt: &ast::IfStatement,
     ) -> Result<(), String> {
         let current_block = self
@@ -564,23 +757,26 @@ impl<'a> FunctionContextBuilder<'a> {
             .ok_or("No active hop for if statement")?;
 
         let condition = self.build_expression(program, if_stmt.condition)?;
+        let origin = SourceOrigin::Statement(stmt_id);
 
-        let then_block = self.new_basic_block(current_hop)?;
-        let merge_block = self.new_basic_block(current_hop)?;
+        let then_block = self.new_basic_block(current_hop, origin)?;
+        let merge_block = self.new_basic_block(current_hop, origin)?;
 
         let else_block = if if_stmt.else_branch.is_some() {
-            self.new_basic_block(current_hop)?
+            self.new_basic_block(current_hop, origin)?
         } else {
             merge_block
         };
 
-        self.set_terminator(
+        self.set_terminator_from(
             current_block,
             Terminator::Branch {
                 condition,
                 then_block,
                 else_block,
             },
+            SourceOrigin::Expression(if_stmt.condition),
+            &program.expressions[if_stmt.condition].span,
         );
 
         // Build then branch
@@ -589,7 +785,7 @@ impl<'a> FunctionContextBuilder<'a> {
             if self.current_block_id.is_none() {
                 break;
             }
-            self.build_statement(program, &program.statements[stmt_id])?;
+            self.build_statement(program, stmt_id)?;
         }
         if let Some(active_block) = self.current_block_id.take() {
             self.set_terminator(active_block, Terminator::Goto(merge_block));
@@ -602,7 +798,7 @@ impl<'a> FunctionContextBuilder<'a> {
                 if self.current_block_id.is_none() {
                     break;
                 }
-                self.build_statement(program, &program.statements[stmt_id])?;
+                self.build_statement(program, stmt_id)?;
             }
             if let Some(active_block) = self.current_block_id.take() {
                 self.set_terminator(active_block, Terminator::Goto(merge_block));
@@ -616,6 +812,7 @@ impl<'a> FunctionContextBuilder<'a> {
     fn build_while_statement(
         &mut self,
         program: &ast::Program,
+        stmt_id: ast::StatementId,
         while_stmt: &ast::WhileStatement,
     ) -> Result<(), String> {
         let current_block = self
@@ -625,22 +822,25 @@ impl<'a> FunctionContextBuilder<'a> {
             .current_hop_id
             .ok_or("No active hop for while statement")?;
 
-        let header_block = self.new_basic_block(current_hop)?;
-        let body_block = self.new_basic_block(current_hop)?;
-        let exit_block = self.new_basic_block(current_hop)?;
+        let origin = SourceOrigin::Statement(stmt_id);
+        let header_block = self.new_basic_block(current_hop, origin)?;
+        let body_block = self.new_basic_block(current_hop, origin)?;
+        let exit_block = self.new_basic_block(current_hop, origin)?;
 
         self.set_terminator(current_block, Terminator::Goto(header_block));
 
         // Header block
         self.current_block_id = Some(header_block);
         let condition = self.build_expression(program, while_stmt.condition)?;
-        self.set_terminator(
+        self.set_terminator_from(
             header_block,
             Terminator::Branch {
                 condition,
                 then_block: body_block,
                 else_block: exit_block,
             },
+            SourceOrigin::Expression(while_stmt.condition),
+            &program.expressions[while_stmt.condition].span,
         );
 
         // Body block
@@ -654,7 +854,7 @@ impl<'a> FunctionContextBuilder<'a> {
             if self.current_block_id.is_none() {
                 break;
             }
-            self.build_statement(program, &program.statements[stmt_id])?;
+            self.build_statement(program, stmt_id)?;
         }
 
         self.loop_stack.pop();
@@ -722,7 +922,7 @@ impl<'a> FunctionContextBuilder<'a> {
                         let pk_field_id = *self
                             .ctx
                             .field_map
-                            .get(&field_ast.field_name)
+                            .get(&pk_field)
                             .ok_or_else(|| {
                                 format!(
                                     "Primary key field {} not found in CFG",
@@ -740,7 +940,7 @@ impl<'a> FunctionContextBuilder<'a> {
                 }
 
                 if pk_field_ids.is_empty() {
-                    return Err(format!("No primary key fields provided"));
+                    return Err("No primary key fields provided".to_string());
                 }
 
                 let field_id = if let Some(resolved_field) = resolved_field {
@@ -748,7 +948,7 @@ impl<'a> FunctionContextBuilder<'a> {
                     *self
                         .ctx
                         .field_map
-                        .get(&field_ast.field_name)
+                        .get(resolved_field)
                         .ok_or_else(|| format!("Field {} not found in CFG", field_ast.field_name))?
                 } else {
                     return Err(format!("Field {} not resolved", field_name));
@@ -782,6 +982,7 @@ impl<'a> FunctionContextBuilder<'a> {
                             field: field_id,
                         },
                         span: expr.span.clone(),
+                        origin: SourceOrigin::Expression(expr_id),
                     },
                 );
 
@@ -820,14 +1021,32 @@ impl<'a> FunctionContextBuilder<'a> {
                             operand,
                         },
                         span: expr.span.clone(),
+                        origin: SourceOrigin::Expression(expr_id),
                     },
                 );
 
                 Ok(Operand::Var(temp_var_id))
             }
             ast::ExpressionKind::BinaryOp { left, op, right, resolved_type } => {
-                let left_operand = self.build_expression(program, *left)?;
-                let right_operand = self.build_expression(program, *right)?;
+                let mut left_operand = self.build_expression(program, *left)?;
+                let mut right_operand = self.build_expression(program, *right)?;
+
+                // A `nocase` comparison compares the lowercase folds of its operands
+                let comparison = matches!(
+                    op,
+                    BinaryOp::Eq
+                        | BinaryOp::Neq
+                        | BinaryOp::Lt
+                        | BinaryOp::Lte
+                        | BinaryOp::Gt
+                        | BinaryOp::Gte
+                );
+                if comparison
+                    && program.comparison_collation(*left, *right) == ast::Collation::NoCase
+                {
+                    left_operand = self.fold_case(left_operand, expr_id, &expr.span)?;
+                    right_operand = self.fold_case(right_operand, expr_id, &expr.span)?;
+                }
 
                 // Use resolved type from semantic analysis - required for CFG building
                 let result_type = resolved_type.clone().ok_or_else(|| {
@@ -856,6 +1075,7 @@ impl<'a> FunctionContextBuilder<'a> {
                             right: right_operand,
                         },
                         span: expr.span.clone(),
+                        origin: SourceOrigin::Expression(expr_id),
                     },
                 );
 
@@ -864,12 +1084,52 @@ impl<'a> FunctionContextBuilder<'a> {
         }
     }
 
-    fn new_basic_block(&mut self, hop_id: HopId) -> Result<BasicBlockId, String> {
+    /// Emits the lowercase fold of a string operand into a new temporary.
+    fn fold_case(
+        &mut self,
+        operand: Operand,
+        expr_id: ast::ExpressionId,
+        span: &Span,
+    ) -> Result<Operand, String> {
+        let temp_var = Variable {
+            name: format!("_temp_{}", self.function.variables.len()),
+            ty: TypeName::String,
+            is_parameter: false,
+        };
+        let temp_var_id = self.function.variables.alloc(temp_var);
+
+        let current_block = self
+            .current_block_id
+            .ok_or("No active block for case folding")?;
+        self.add_statement(
+            current_block,
+            Statement::Assign {
+                var: temp_var_id,
+                rvalue: Rvalue::UnaryOp {
+                    op: UnaryOp::FoldCase,
+                    operand,
+                },
+                span: span.clone(),
+                origin: SourceOrigin::Expression(expr_id),
+            },
+        );
+
+        Ok(Operand::Var(temp_var_id))
+    }
+
+    fn new_basic_block(
+        &mut self,
+        hop_id: HopId,
+        origin: SourceOrigin,
+    ) -> Result<BasicBlockId, String> {
         let block = BasicBlock {
             hop_id,
             statements: Vec::new(),
             terminator: Terminator::Abort, // Placeholder
-            span: ast::Span::default(),
+            span: self.function.hops[hop_id].span.clone(), // Blocks point at their hop
+            origin,
+            terminator_origin: SourceOrigin::Synthetic,
+            terminator_span: self.function.hops[hop_id].span.clone(),
         };
 
         let block_id = self.function.blocks.alloc(block);
@@ -885,4 +1145,28 @@ impl<'a> FunctionContextBuilder<'a> {
     fn set_terminator(&mut self, block_id: BasicBlockId, terminator: Terminator) {
         self.function.blocks[block_id].terminator = terminator;
     }
+
+    /// Sets a terminator built from the AST element at `span`.
+    fn set_terminator_from(
+        &mut self,
+        block_id: BasicBlockId,
+        terminator: Terminator,
+        origin: SourceOrigin,
+        span: &Span,
+    ) {
+        let block = &mut self.function.blocks[block_id];
+        block.terminator = terminator;
+        block.terminator_origin = origin;
+        block.terminator_span = span.clone();
+    }
+}
+
+/// The value of a variable declared without an initializer.
+fn default_value(ty: &TypeName) -> Constant {
+    match ty {
+        TypeName::Int => Constant::Int(0),
+        TypeName::Float => Constant::Float(ordered_float::OrderedFloat(0.0)),
+        TypeName::String => Constant::String(String::new()),
+        TypeName::Bool => Constant::Bool(false),
+    }
 }
@@ -0,0 +1,191 @@
+//! Dominator and post-dominator trees of function CFGs.
+//!
+//! A block dominates another if every path from the function's entry to the
+//! other block passes through it, and post-dominates it if every path from the
+//! other block to an exit of the function does. Exits are blocks ending in
+//! `Return`, `Abort` or a final `HopExit`. Paths follow the `HopExit` edges
+//! between hops, so a tree covers the whole function. Blocks that the entry
+//! cannot reach, or that cannot reach an exit for post-dominators, are not in
+//! the tree.
+//!
+//! ```
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table T on A { primary int id; int n; }
+//!     table U on B { primary int id; int n; }
+//!     void f(int id) {
+//!         hop on A {
+//!             if (T[id: id].n > 0) {
+//!                 T[id: id].n = 0;
+//!             }
+//!         }
+//!         hop on B {
+//!             U[id: id].n = 1;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let func_id = cfg.root_functions[0];
+//! let func = &cfg.functions[func_id];
+//! let entry = func.hops[func.entry_hop.unwrap()].entry_block.unwrap();
+//! let dominators = cfg.dominators(func_id);
+//! let post_dominators = cfg.post_dominators(func_id);
+//!
+//! // The entry dominates every block, and the second hop post-dominates the entry
+//! let second_hop = func.hops[func.hop_order[1]].entry_block.unwrap();
+//! assert_eq!(dominators.roots(), &[entry]);
+//! assert!(func.blocks.iter().all(|(block, _)| dominators.dominates(entry, block)));
+//! assert!(post_dominators.dominates(second_hop, entry));
+//!
+//! // For debugging, trees print as DOT
+//! let mut dot = Vec::new();
+//! FMitF_rs::pretty::print_dominator_tree_dot(func, &dominators, "Dominators", &mut dot).unwrap();
+//! ```
+
+use crate::cfg::{BasicBlockId, FunctionCfg};
+use petgraph::algo::dominators::simple_fast;
+use petgraph::graph::{DiGraph, NodeIndex};
+use std::collections::HashMap;
+
+/// A dominator or post-dominator tree over the blocks of one function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DominatorTree {
+    /// Blocks without an immediate dominator: the entry block, or for
+    /// post-dominators each block only the virtual exit post-dominates
+    roots: Vec<BasicBlockId>,
+    parents: HashMap<BasicBlockId, BasicBlockId>,
+    children: HashMap<BasicBlockId, Vec<BasicBlockId>>,
+}
+
+impl DominatorTree {
+    /// Computes the dominator tree of a function, rooted at its entry block.
+    pub fn dominators(func: &FunctionCfg) -> Self {
+        let (mut graph, nodes) = block_graph(func);
+        let Some(entry) = func
+            .entry_hop
+            .and_then(|hop_id| func.hops[hop_id].entry_block)
+        else {
+            return Self::from_parents(func, HashMap::new(), Vec::new());
+        };
+        for (block_id, _) in func.blocks.iter() {
+            for succ in func.successors(block_id) {
+                graph.add_edge(nodes[&block_id], nodes[&succ], ());
+            }
+        }
+        Self::from_graph(func, &graph, nodes[&entry])
+    }
+
+    /// Computes the post-dominator tree of a function. The exits hang off a
+    /// virtual exit block, so the result is a forest whose roots are the blocks
+    /// no other block post-dominates.
+    pub fn post_dominators(func: &FunctionCfg) -> Self {
+        let (mut graph, nodes) = block_graph(func);
+        let exit = graph.add_node(None);
+        // Detached blocks reach an exit, but the entry never reaches them
+        for (block_id, _) in func.blocks.iter() {
+            if !func.contains_block(block_id) {
+                continue;
+            }
+            let successors = func.successors(block_id);
+            if successors.is_empty() {
+                graph.add_edge(exit, nodes[&block_id], ());
+            }
+            for succ in successors {
+                graph.add_edge(nodes[&succ], nodes[&block_id], ());
+            }
+        }
+        Self::from_graph(func, &graph, exit)
+    }
+
+    fn from_graph(func: &FunctionCfg, graph: &BlockGraph, root: NodeIndex) -> Self {
+        let dominators = simple_fast(graph, root);
+        let mut parents = HashMap::new();
+        let mut roots = Vec::new();
+        for node in graph.node_indices() {
+            let Some(block_id) = graph[node] else {
+                continue;
+            };
+            match dominators.immediate_dominator(node).map(|idom| graph[idom]) {
+                Some(Some(idom)) => {
+                    parents.insert(block_id, idom);
+                }
+                // Immediately dominated by the virtual exit
+                Some(None) => roots.push(block_id),
+                None if node == root => roots.push(block_id),
+                // Unreachable from the root
+                None => {}
+            }
+        }
+        Self::from_parents(func, parents, roots)
+    }
+
+    fn from_parents(
+        func: &FunctionCfg,
+        parents: HashMap<BasicBlockId, BasicBlockId>,
+        roots: Vec<BasicBlockId>,
+    ) -> Self {
+        let mut children: HashMap<BasicBlockId, Vec<BasicBlockId>> = HashMap::new();
+        // Children in block order, for stable output
+        for (block_id, _) in func.blocks.iter() {
+            if let Some(&parent) = parents.get(&block_id) {
+                children.entry(parent).or_default().push(block_id);
+            }
+        }
+        Self {
+            roots,
+            parents,
+            children,
+        }
+    }
+
+    /// The blocks without an immediate dominator.
+    pub fn roots(&self) -> &[BasicBlockId] {
+        &self.roots
+    }
+
+    /// Whether the block is in the tree.
+    pub fn contains(&self, block: BasicBlockId) -> bool {
+        self.parents.contains_key(&block) || self.roots.contains(&block)
+    }
+
+    /// The immediate dominator (or post-dominator) of a block, `None` for roots
+    /// and blocks not in the tree.
+    pub fn immediate_dominator(&self, block: BasicBlockId) -> Option<BasicBlockId> {
+        self.parents.get(&block).copied()
+    }
+
+    /// The blocks a block immediately dominates.
+    pub fn children(&self, block: BasicBlockId) -> &[BasicBlockId] {
+        self.children.get(&block).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether `a` dominates `b`. Every block in the tree dominates itself.
+    pub fn dominates(&self, a: BasicBlockId, b: BasicBlockId) -> bool {
+        self.dominators_of(b).any(|block| block == a)
+    }
+
+    /// The dominators of a block, from the block itself up to its root. Empty for
+    /// blocks not in the tree.
+    pub fn dominators_of(&self, block: BasicBlockId) -> impl Iterator<Item = BasicBlockId> + '_ {
+        let start = self.contains(block).then_some(block);
+        std::iter::successors(start, |block| self.immediate_dominator(*block))
+    }
+}
+
+/// Block graph with one node per block, plus `None` for a virtual exit.
+type BlockGraph = DiGraph<Option<BasicBlockId>, ()>;
+
+fn block_graph(func: &FunctionCfg) -> (BlockGraph, HashMap<BasicBlockId, NodeIndex>) {
+    let mut graph = DiGraph::new();
+    let nodes = func
+        .blocks
+        .iter()
+        .map(|(block_id, _)| (block_id, graph.add_node(Some(block_id))))
+        .collect();
+    (graph, nodes)
+}
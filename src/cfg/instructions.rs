@@ -0,0 +1,269 @@
+//! Lowering of hops into flat three-address instructions.
+//!
+//! CFG statements already take operands rather than nested expressions, but a
+//! read of a table field hides inside an assignment's rvalue, and control flow
+//! lives in block terminators. Lowering a hop lays its blocks out as one list
+//! of instructions: copies, unary and binary operations on temporaries, explicit
+//! loads and stores of table fields, and labels with the jumps between them.
+//! Blocks are laid out in control-flow order from the hop's entry, and a jump to
+//! the block laid out next becomes a fallthrough.
+//!
+//! ```
+//! use FMitF_rs::cfg::{lower_hop, Instruction};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void f(int id) {
+//!         hop on A {
+//!             if (T[id: id].n > 0) {
+//!                 T[id: id].n = T[id: id].n - 1;
+//!             }
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let func = &cfg.functions[cfg.root_functions[0]];
+//! let instructions = lower_hop(func, func.hop_order[0]);
+//!
+//! let loads = instructions.iter().filter(|i| matches!(i, Instruction::Load { .. }));
+//! let stores = instructions.iter().filter(|i| matches!(i, Instruction::Store { .. }));
+//! assert_eq!((loads.count(), stores.count()), (2, 1));
+//! assert!(matches!(instructions[0], Instruction::Label(_)));
+//!
+//! for instruction in &instructions {
+//!     println!("{}", FMitF_rs::pretty::format_instruction(instruction, func, &cfg));
+//! }
+//! ```
+
+use crate::cfg::{
+    BasicBlockId, BinaryOp, BoundCheck, FieldId, FunctionCfg, HopId, Operand, Rvalue, Statement,
+    TableId, Terminator, UnaryOp, VarId,
+};
+use std::collections::HashSet;
+
+/// A three-address instruction. Operands are variables or constants, and each
+/// instruction defines at most one variable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// `dst = src`
+    Copy {
+        dst: VarId,
+        src: Operand,
+    },
+    /// `dst = op operand`
+    Unary {
+        dst: VarId,
+        op: UnaryOp,
+        operand: Operand,
+    },
+    /// `dst = left op right`
+    Binary {
+        dst: VarId,
+        op: BinaryOp,
+        left: Operand,
+        right: Operand,
+    },
+    /// `dst = table[key].field`
+    Load {
+        dst: VarId,
+        table: TableId,
+        key: Vec<(FieldId, Operand)>,
+        field: FieldId,
+    },
+    /// `table[key].field = value`
+    Store {
+        table: TableId,
+        key: Vec<(FieldId, Operand)>,
+        field: FieldId,
+        value: Operand,
+    },
+    /// The start of a block
+    Label(BasicBlockId),
+    Jump(BasicBlockId),
+    Branch {
+        condition: Operand,
+        then_label: BasicBlockId,
+        else_label: BasicBlockId,
+    },
+    Return(Option<Operand>),
+    Abort,
+    BoundExceeded(BoundCheck),
+    HopExit {
+        next_hop: Option<HopId>,
+    },
+}
+
+impl Instruction {
+    /// The variable the instruction assigns, if any.
+    pub fn defined_var(&self) -> Option<VarId> {
+        match self {
+            Self::Copy { dst, .. }
+            | Self::Unary { dst, .. }
+            | Self::Binary { dst, .. }
+            | Self::Load { dst, .. } => Some(*dst),
+            _ => None,
+        }
+    }
+
+    /// The operands the instruction reads, including primary key operands.
+    pub fn used_operands(&self) -> Vec<&Operand> {
+        match self {
+            Self::Copy { src, .. } => vec![src],
+            Self::Unary { operand, .. } => vec![operand],
+            Self::Binary { left, right, .. } => vec![left, right],
+            Self::Load { key, .. } => key.iter().map(|(_, value)| value).collect(),
+            Self::Store { key, value, .. } => key
+                .iter()
+                .map(|(_, value)| value)
+                .chain(std::iter::once(value))
+                .collect(),
+            Self::Branch { condition, .. } => vec![condition],
+            Self::Return(Some(value)) => vec![value],
+            Self::Label(_)
+            | Self::Jump(_)
+            | Self::Return(None)
+            | Self::Abort
+            | Self::BoundExceeded(_)
+            | Self::HopExit { .. } => Vec::new(),
+        }
+    }
+
+    /// The instruction a statement lowers to.
+    pub fn of_statement(statement: &Statement) -> Self {
+        match statement {
+            Statement::Assign { var, rvalue, .. } => match rvalue {
+                Rvalue::Use(src) => Self::Copy {
+                    dst: *var,
+                    src: src.clone(),
+                },
+                Rvalue::UnaryOp { op, operand } => Self::Unary {
+                    dst: *var,
+                    op: op.clone(),
+                    operand: operand.clone(),
+                },
+                Rvalue::BinaryOp { op, left, right } => Self::Binary {
+                    dst: *var,
+                    op: op.clone(),
+                    left: left.clone(),
+                    right: right.clone(),
+                },
+                Rvalue::TableAccess {
+                    table,
+                    pk_fields,
+                    pk_values,
+                    field,
+                } => Self::Load {
+                    dst: *var,
+                    table: *table,
+                    key: zip_key(pk_fields, pk_values),
+                    field: *field,
+                },
+            },
+            Statement::TableAssign {
+                table,
+                pk_fields,
+                pk_values,
+                field,
+                value,
+                ..
+            } => Self::Store {
+                table: *table,
+                key: zip_key(pk_fields, pk_values),
+                field: *field,
+                value: value.clone(),
+            },
+        }
+    }
+}
+
+fn zip_key(pk_fields: &[FieldId], pk_values: &[Operand]) -> Vec<(FieldId, Operand)> {
+    pk_fields
+        .iter()
+        .copied()
+        .zip(pk_values.iter().cloned())
+        .collect()
+}
+
+/// Lowers the blocks of a hop into instructions.
+pub fn lower_hop(func: &FunctionCfg, hop: HopId) -> Vec<Instruction> {
+    let order = layout(func, hop);
+    let mut instructions = Vec::new();
+    for (position, &block_id) in order.iter().enumerate() {
+        let block = &func.blocks[block_id];
+        let next_block = order.get(position + 1).copied();
+        instructions.push(Instruction::Label(block_id));
+        instructions.extend(block.statements.iter().map(Instruction::of_statement));
+        match &block.terminator {
+            Terminator::Goto(target) if Some(*target) == next_block => {}
+            Terminator::Goto(target) => instructions.push(Instruction::Jump(*target)),
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => instructions.push(Instruction::Branch {
+                condition: condition.clone(),
+                then_label: *then_block,
+                else_label: *else_block,
+            }),
+            Terminator::Return(value) => instructions.push(Instruction::Return(value.clone())),
+            Terminator::Abort => instructions.push(Instruction::Abort),
+            Terminator::BoundExceeded(check) => {
+                instructions.push(Instruction::BoundExceeded(*check))
+            }
+            Terminator::HopExit { next_hop } => instructions.push(Instruction::HopExit {
+                next_hop: *next_hop,
+            }),
+        }
+    }
+    instructions
+}
+
+/// Lowers every hop of a function, in hop order.
+pub fn lower_function(func: &FunctionCfg) -> Vec<(HopId, Vec<Instruction>)> {
+    func.hop_order
+        .iter()
+        .map(|&hop| (hop, lower_hop(func, hop)))
+        .collect()
+}
+
+/// The blocks of a hop in depth-first order from its entry, so a block tends to
+/// follow the block that jumps to it. Blocks of the hop the entry does not
+/// reach follow in block order.
+fn layout(func: &FunctionCfg, hop: HopId) -> Vec<BasicBlockId> {
+    let hop_cfg = &func.hops[hop];
+    let mut order = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack: Vec<BasicBlockId> = hop_cfg.entry_block.into_iter().collect();
+    while let Some(block_id) = stack.pop() {
+        if !seen.insert(block_id) {
+            continue;
+        }
+        order.push(block_id);
+        match &func.blocks[block_id].terminator {
+            Terminator::Goto(target) => stack.push(*target),
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => stack.extend([*else_block, *then_block]),
+            Terminator::Return(_)
+            | Terminator::Abort
+            | Terminator::BoundExceeded(_)
+            | Terminator::HopExit { .. } => {}
+        }
+    }
+    let mut rest: Vec<BasicBlockId> = hop_cfg
+        .blocks
+        .iter()
+        .copied()
+        .filter(|block_id| !seen.contains(block_id))
+        .collect();
+    rest.sort();
+    order.extend(rest);
+    order
+}
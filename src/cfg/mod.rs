@@ -1,9 +1,25 @@
 use id_arena::{Arena, Id};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 pub use crate::ast::{BinaryOp, ReturnType, Span, TypeName, UnaryOp};
 
 mod cfg_builder;
-pub use cfg_builder::CfgBuilder;
+pub use cfg_builder::{CfgBuilder, CfgCtx};
+
+mod dominators;
+pub use dominators::DominatorTree;
+
+mod instructions;
+pub use instructions::{lower_function, lower_hop, Instruction};
+
+pub mod serialize;
+use serialize::{arena, ast_id, float, id, ids, opt_id};
+
+mod paths;
+pub use paths::{HopPath, HopPaths, PathBounds, PathCondition};
+
+mod source_map;
+pub use source_map::{CfgSite, SourceMap};
 
 // Core ID types
 pub type NodeId = Id<NodeInfo>;
@@ -15,43 +31,137 @@ pub type BasicBlockId = Id<BasicBlock>;
 pub type VarId = Id<Variable>;
 
 /// Core CFG Program structure, never clone this structure
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct CfgProgram {
     // Arena for storing various components
+    #[serde(with = "arena")]
     pub nodes: Arena<NodeInfo>,
+    #[serde(with = "arena")]
     pub tables: Arena<TableInfo>,
+    #[serde(with = "arena")]
     pub fields: Arena<FieldInfo>,
+    #[serde(with = "arena")]
     pub functions: Arena<FunctionCfg>,
 
     // Root collections - public for iteration
+    #[serde(with = "ids")]
     pub root_nodes: Vec<NodeId>,
+    #[serde(with = "ids")]
     pub root_tables: Vec<TableId>,
+    #[serde(with = "ids")]
     pub root_functions: Vec<FunctionId>,
 }
 
-#[derive(Debug, Clone)]
+impl Serialize for CfgProgram {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CfgProgram::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CfgProgram {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serialize::read_arenas(|| CfgProgram::deserialize(deserializer))
+    }
+}
+
+impl CfgProgram {
+    /// The elements of each arena and the bytes they take, those of the
+    /// functions' variables, hops and blocks summed over the functions.
+    pub fn memory_stats(&self) -> crate::memory::MemoryStats {
+        let mut stats = crate::memory::MemoryStats::default();
+        stats.add("nodes", &self.nodes);
+        stats.add("tables", &self.tables);
+        stats.add("fields", &self.fields);
+        stats.add("functions", &self.functions);
+        for (_, function) in self.functions.iter() {
+            stats.add("variables", &function.variables);
+            stats.add("hops", &function.hops);
+            stats.add("blocks", &function.blocks);
+        }
+        stats
+    }
+
+    /// The dominator tree of a function's blocks.
+    pub fn dominators(&self, func: FunctionId) -> DominatorTree {
+        DominatorTree::dominators(&self.functions[func])
+    }
+
+    /// The post-dominator tree of a function's blocks.
+    pub fn post_dominators(&self, func: FunctionId) -> DominatorTree {
+        DominatorTree::post_dominators(&self.functions[func])
+    }
+
+    /// The paths through a hop of a function, within the bounds.
+    pub fn hop_paths(&self, func: FunctionId, hop: HopId, bounds: PathBounds) -> HopPaths {
+        HopPaths::enumerate(&self.functions[func], hop, bounds)
+    }
+
+    /// Drops the functions `keep` rejects, and those no longer in
+    /// `root_functions`. The arena cannot free them, so the functions kept are
+    /// moved to a new one and get new `FunctionId`s, in the same order; their
+    /// hops and blocks keep their ids.
+    ///
+    /// ```
+    /// use FMitF_rs::{parse_and_analyze, CfgBuilder};
+    ///
+    /// let program = parse_and_analyze(
+    ///     "nodes { A } table T on A { primary int id; int n; }
+    ///     void f(int id) { hop on A { T[id: id].n = 1; } }
+    ///     void g(int id) { hop on A { T[id: id].n = 2; } }",
+    /// )
+    /// .unwrap();
+    /// let mut cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+    /// cfg.retain_functions(|function| function.name == "g");
+    /// assert_eq!(cfg.functions.len(), 1);
+    /// assert_eq!(cfg.functions[cfg.root_functions[0]].name, "g");
+    /// ```
+    pub fn retain_functions(&mut self, keep: impl Fn(&FunctionCfg) -> bool) {
+        let root_functions = std::mem::take(&mut self.root_functions);
+        let mut functions: Vec<Option<FunctionCfg>> = std::mem::take(&mut self.functions)
+            .into_iter()
+            .map(|(_, function)| Some(function))
+            .collect();
+        for func_id in root_functions {
+            let function = functions[func_id.index()].take().unwrap();
+            if keep(&function) {
+                self.root_functions.push(self.functions.alloc(function));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeInfo {
     pub name: String,
+    #[serde(with = "ids")]
     pub tables: Vec<TableId>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TableInfo {
     pub name: String,
+    #[serde(with = "id")]
     pub node_id: NodeId,
+    #[serde(with = "ids")]
     pub fields: Vec<FieldId>,
+    #[serde(with = "ids")]
     pub primary_keys: Vec<FieldId>, // Changed from single primary_key to multiple primary_keys
+    /// Properties its records are declared to have, from `// assume(...)`
+    /// comments on the table or its fields, as written
+    pub assumptions: Vec<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FieldInfo {
     pub name: String,
     pub ty: TypeName,
+    #[serde(with = "opt_id")]
     pub table_id: Option<TableId>, // Can be None during initial CFG construction, set later
     pub is_primary: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Variable {
     pub name: String,
     pub ty: TypeName,
@@ -59,64 +169,145 @@ pub struct Variable {
 }
 
 /// Function CFG, never clone it
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FunctionCfg {
     pub name: String,
     pub return_type: ReturnType,
     pub span: Span,
 
+    #[serde(with = "arena")]
     pub variables: Arena<Variable>,
+    #[serde(with = "ids")]
     pub parameters: Vec<VarId>,
 
+    #[serde(with = "arena")]
     pub hops: Arena<HopCfg>,
+    #[serde(with = "arena")]
     pub blocks: Arena<BasicBlock>,
 
+    #[serde(with = "opt_id")]
     pub entry_hop: Option<HopId>, // Set after all hops are allocated
+    #[serde(with = "ids")]
     pub hop_order: Vec<HopId>,
+    /// Properties of the records it writes that must hold when it commits,
+    /// from `// ensures(...)` comments on it, as written
+    pub ensures: Vec<String>,
+}
+
+impl FunctionCfg {
+    /// Whether a block is still part of its hop. The arena cannot free blocks, so
+    /// optimizations detach the blocks they prove unreachable from their hop instead.
+    pub fn contains_block(&self, block: BasicBlockId) -> bool {
+        self.hops[self.blocks[block].hop_id].blocks.contains(&block)
+    }
+
+    /// The blocks control can pass to from a block, following `HopExit` into the
+    /// entry block of the next hop.
+    pub fn successors(&self, block: BasicBlockId) -> Vec<BasicBlockId> {
+        match &self.blocks[block].terminator {
+            Terminator::Goto(target) => vec![*target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![*then_block, *else_block],
+            Terminator::HopExit {
+                next_hop: Some(next_hop),
+            } => self.hops[*next_hop].entry_block.into_iter().collect(),
+            Terminator::Return(_)
+            | Terminator::Abort
+            | Terminator::BoundExceeded(_)
+            | Terminator::HopExit { next_hop: None } => Vec::new(),
+        }
+    }
 }
 
 /// Hop - execution on a specific node
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct HopCfg {
+    #[serde(with = "id")]
     pub node_id: NodeId,
+    #[serde(with = "opt_id")]
     pub entry_block: Option<BasicBlockId>, // Set after its basic block is created
+    #[serde(with = "ids")]
     pub blocks: Vec<BasicBlockId>,
     pub span: Span,
 }
 
 /// Basic block
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct BasicBlock {
+    #[serde(with = "id")]
     pub hop_id: HopId,
     pub statements: Vec<Statement>,
     pub terminator: Terminator,
     pub span: Span,
+    /// The `if` or `while` statement the block was created for, or synthetic
+    /// for the entry block of a hop
+    pub origin: SourceOrigin,
+    /// What the terminator was built from: the condition of a branch, or the
+    /// `return`, `abort`, `break` or `continue` statement
+    pub terminator_origin: SourceOrigin,
+    pub terminator_span: Span,
+}
+
+/// The AST element a CFG statement, terminator or block was built from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SourceOrigin {
+    Statement(#[serde(with = "ast_id")] crate::ast::StatementId),
+    Expression(#[serde(with = "ast_id")] crate::ast::ExpressionId),
+    /// Introduced by the builder or a pass, like the jump closing a branch or
+    /// the exit of a hop
+    Synthetic,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Statement {
     Assign {
+        #[serde(with = "id")]
         var: VarId,
         rvalue: Rvalue,
         span: Span,
+        origin: SourceOrigin,
     },
     TableAssign {
+        #[serde(with = "id")]
         table: TableId,
+        #[serde(with = "ids")]
         pk_fields: Vec<FieldId>,
         pk_values: Vec<Operand>,
+        #[serde(with = "id")]
         field: FieldId,
         value: Operand,
         span: Span,
+        origin: SourceOrigin,
     },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+impl Statement {
+    pub fn span(&self) -> &Span {
+        match self {
+            Statement::Assign { span, .. } | Statement::TableAssign { span, .. } => span,
+        }
+    }
+
+    pub fn origin(&self) -> SourceOrigin {
+        match self {
+            Statement::Assign { origin, .. } | Statement::TableAssign { origin, .. } => *origin,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Rvalue {
     Use(Operand),
     TableAccess {
+        #[serde(with = "id")]
         table: TableId,
+        #[serde(with = "ids")]
         pk_fields: Vec<FieldId>,
         pk_values: Vec<Operand>,
+        #[serde(with = "id")]
         field: FieldId,
     },
     UnaryOp {
@@ -130,31 +321,49 @@ pub enum Rvalue {
     },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Operand {
-    Var(VarId),
+    Var(#[serde(with = "id")] VarId),
     Const(Constant),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Constant {
     Int(i64),
-    Float(ordered_float::OrderedFloat<f64>),
+    Float(#[serde(with = "float")] ordered_float::OrderedFloat<f64>),
     Bool(bool),
     String(String),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Terminator {
-    Goto(BasicBlockId),
+    Goto(#[serde(with = "id")] BasicBlockId),
     Branch {
         condition: Operand,
+        #[serde(with = "id")]
         then_block: BasicBlockId,
+        #[serde(with = "id")]
         else_block: BasicBlockId,
     },
     Return(Option<Operand>),
     Abort,
     HopExit {
+        #[serde(with = "opt_id")]
         next_hop: Option<HopId>,
     },
+    /// Ends the paths that follow the back edge of an unrolled loop once more
+    /// than the unroll bound allows
+    BoundExceeded(BoundCheck),
+}
+
+/// How the verifier treats the paths an unrolled loop cuts off
+#[derive(
+    clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub enum BoundCheck {
+    /// Ignore them (`assume false`), checking only runs within the bound
+    #[default]
+    Assume,
+    /// Report them (`assert false`), so a proof also shows the bound is enough
+    Assert,
 }
@@ -0,0 +1,197 @@
+//! Path enumeration within a hop.
+//!
+//! A path runs from the entry block of a hop to a block that leaves it, by
+//! `HopExit`, `Return`, `Abort` or `BoundExceeded`, and records the branch
+//! conditions it takes on the way. By default paths are acyclic: no block appears
+//! twice. `PathBounds` can allow a few trips around each loop, cap the length of
+//! a path, or cap the number of paths, since the count grows exponentially with
+//! the number of branches. Paths a bound cuts off are dropped, and the result
+//! says whether any were.
+//!
+//! ```
+//! use FMitF_rs::cfg::{PathBounds, Terminator};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void f(int id, int k) {
+//!         hop on A {
+//!             int i = 0;
+//!             if (T[id: id].n > k) {
+//!                 T[id: id].n = 0;
+//!             } else {
+//!                 abort;
+//!             }
+//!             while (i < k) {
+//!                 i = i + 1;
+//!             }
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let func_id = cfg.root_functions[0];
+//! let func = &cfg.functions[func_id];
+//! let hop_id = func.hop_order[0];
+//!
+//! // Skip the loop, or abort; running the loop body would revisit its header
+//! let acyclic = cfg.hop_paths(func_id, hop_id, PathBounds::default());
+//! assert_eq!(acyclic.paths.len(), 2);
+//! assert!(acyclic.truncated);
+//! let taken: Vec<bool> = acyclic.paths[0].conditions.iter().map(|cond| cond.taken).collect();
+//! assert_eq!(taken, [true, false]);
+//! assert_eq!(func.blocks[acyclic.paths[1].exit()].terminator, Terminator::Abort);
+//!
+//! // Also up to two iterations of the loop
+//! let unrolled = cfg.hop_paths(func_id, hop_id, PathBounds::default().with_max_visits(3));
+//! assert_eq!(unrolled.paths.len(), 4);
+//!
+//! // Readable output for explanations
+//! let text = FMitF_rs::pretty::format_hop_path(&acyclic.paths[1], func);
+//! assert!(text.ends_with("-> bb3 (abort)"), "{text}");
+//! ```
+
+use crate::cfg::{BasicBlockId, FunctionCfg, HopId, Operand, Terminator};
+
+/// Limits on the paths `HopPaths::enumerate` explores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathBounds {
+    /// How often a block may appear on one path; 1 keeps paths acyclic
+    pub max_visits: usize,
+    /// The most blocks a path may have, if limited
+    pub max_length: Option<usize>,
+    /// The most paths to return, if limited
+    pub max_paths: Option<usize>,
+}
+
+impl Default for PathBounds {
+    fn default() -> Self {
+        Self {
+            max_visits: 1,
+            max_length: None,
+            max_paths: Some(1024),
+        }
+    }
+}
+
+impl PathBounds {
+    pub fn with_max_visits(mut self, max_visits: usize) -> Self {
+        self.max_visits = max_visits;
+        self
+    }
+
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    pub fn with_max_paths(mut self, max_paths: usize) -> Self {
+        self.max_paths = Some(max_paths);
+        self
+    }
+}
+
+/// A branch a path takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathCondition {
+    /// The block ending in the branch
+    pub block: BasicBlockId,
+    pub condition: Operand,
+    /// Whether the path follows the then edge, so the condition holds
+    pub taken: bool,
+}
+
+/// A path through a hop, from its entry block to a block that leaves it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopPath {
+    pub blocks: Vec<BasicBlockId>,
+    /// The branches along the path, in order
+    pub conditions: Vec<PathCondition>,
+}
+
+impl HopPath {
+    /// The block the path leaves the hop from.
+    pub fn exit(&self) -> BasicBlockId {
+        *self.blocks.last().expect("paths have at least one block")
+    }
+}
+
+/// The paths through a hop within some bounds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopPaths {
+    /// Paths in depth-first order, then edges before else edges
+    pub paths: Vec<HopPath>,
+    /// Whether the bounds cut off any path
+    pub truncated: bool,
+}
+
+impl HopPaths {
+    /// Enumerates the paths through a hop of a function.
+    pub fn enumerate(func: &FunctionCfg, hop: HopId, bounds: PathBounds) -> Self {
+        let mut result = Self {
+            paths: Vec::new(),
+            truncated: false,
+        };
+        let Some(entry) = func.hops[hop].entry_block else {
+            return result;
+        };
+
+        let mut stack = vec![HopPath {
+            blocks: vec![entry],
+            conditions: Vec::new(),
+        }];
+        while let Some(path) = stack.pop() {
+            if bounds
+                .max_paths
+                .is_some_and(|max| result.paths.len() >= max)
+            {
+                result.truncated = true;
+                break;
+            }
+            let block_id = path.exit();
+            let mut extend = |path: &HopPath, succ: BasicBlockId, condition: Option<bool>| {
+                let visits = path.blocks.iter().filter(|&&b| b == succ).count();
+                if visits >= bounds.max_visits
+                    || bounds
+                        .max_length
+                        .is_some_and(|max| path.blocks.len() >= max)
+                {
+                    result.truncated = true;
+                    return;
+                }
+                let mut path = path.clone();
+                path.blocks.push(succ);
+                if let (Some(taken), Terminator::Branch { condition, .. }) =
+                    (condition, &func.blocks[block_id].terminator)
+                {
+                    path.conditions.push(PathCondition {
+                        block: block_id,
+                        condition: condition.clone(),
+                        taken,
+                    });
+                }
+                stack.push(path);
+            };
+            match &func.blocks[block_id].terminator {
+                Terminator::Goto(target) => extend(&path, *target, None),
+                // Pushed else first, so the then edge is explored first
+                Terminator::Branch {
+                    then_block,
+                    else_block,
+                    ..
+                } => {
+                    extend(&path, *else_block, Some(false));
+                    extend(&path, *then_block, Some(true));
+                }
+                Terminator::Return(_)
+                | Terminator::Abort
+                | Terminator::BoundExceeded(_)
+                | Terminator::HopExit { .. } => result.paths.push(path),
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,394 @@
+//! Serialization of the CFG and SC-graph, so that they can be saved and read
+//! back without rebuilding them from the source.
+//!
+//! Arena ids only index the arena that made them, so an id is written as
+//! `[arena, index]` and an arena as `{"id": arena, "items": [...]}`. Reading
+//! recreates the arenas and gives every id the arena written with the same
+//! number; `read_arenas` scopes that numbering to one document, and checks
+//! that every id points into an arena it contains. `CfgProgram`, `CfgCtx`
+//! and `SCGraph` each read in a scope of their own, so an SC-graph is read in
+//! one `read_arenas` together with the CFG its hops belong to. Ids into the
+//! AST are written as their index, as `ast::serialize` does, and read back
+//! detached from any arena.
+//!
+//! ```
+//! use FMitF_rs::cfg::{serialize::read_arenas, CfgProgram};
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table T on A { primary int id; int n; }
+//!     table U on B { primary int id; int n; }
+//!     void f(int id) { hop on A { T[id: id].n = T[id: id].n + 1; } hop on B { U[id: id].n = 1; } }
+//!     void g(int id) { hop on A { T[id: id].n = 0; } hop on B { U[id: id].n = 2; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let mut sc_graph = SCGraph::new(&cfg);
+//! sc_graph.eliminate_commuting_edges(&cfg);
+//! let saved = serde_json::to_string(&(&cfg, &sc_graph)).unwrap();
+//!
+//! let (cfg, sc_graph): (CfgProgram, SCGraph) =
+//!     read_arenas(|| serde_json::from_str(&saved)).unwrap();
+//! assert_eq!(cfg.functions[cfg.root_functions[1]].name, "g");
+//! assert_eq!(sc_graph.find_mixed_cycles().len(), 1);
+//! assert_eq!(serde_json::to_string(&(&cfg, &sc_graph)).unwrap().len(), saved.len());
+//!
+//! // Without its CFG, the SC-graph's hops point nowhere
+//! let alone = serde_json::to_string(&sc_graph).unwrap();
+//! assert!(serde_json::from_str::<SCGraph>(&alone).is_err());
+//! ```
+
+use id_arena::{Arena, ArenaBehavior, DefaultArenaBehavior, Id};
+use ordered_float::OrderedFloat;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The arena ids of the document being read.
+#[derive(Default)]
+struct ReadArenas {
+    /// Scopes entered and not yet left
+    depth: usize,
+    /// By the number the document gives each arena
+    arenas: HashMap<u32, ReadArena>,
+}
+
+struct ReadArena {
+    /// The id of the arena created in its place
+    id: u32,
+    /// The arena, while ids into it are read before its items
+    arena: Option<Box<dyn Any>>,
+    /// How many items it has, once they are read
+    len: Option<usize>,
+    /// One past the highest index of an id into it
+    end: usize,
+}
+
+thread_local! {
+    static READ_ARENAS: RefCell<Option<ReadArenas>> = const { RefCell::new(None) };
+}
+
+/// Reads a document of arenas and ids into them, such as a `CfgProgram` and
+/// the `SCGraph` built from it, failing when an id points outside them.
+pub fn read_arenas<R, E: serde::de::Error>(read: impl FnOnce() -> Result<R, E>) -> Result<R, E> {
+    READ_ARENAS.with(|cell| cell.borrow_mut().get_or_insert_with(Default::default).depth += 1);
+    let result = read();
+    let done = READ_ARENAS.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let scope = cell.as_mut().expect("a scope was entered");
+        scope.depth -= 1;
+        if scope.depth == 0 {
+            cell.take()
+        } else {
+            None
+        }
+    });
+    let value = result?;
+    if let Some(done) = done {
+        let mut arenas: Vec<_> = done.arenas.into_iter().collect();
+        arenas.sort_by_key(|(number, _)| *number);
+        for (number, arena) in arenas {
+            match arena.len {
+                None => {
+                    return Err(E::custom(format!(
+                        "ids point into arena {}, which the document does not contain",
+                        number
+                    )))
+                }
+                Some(len) if arena.end > len => {
+                    return Err(E::custom(format!(
+                        "an id points to item {} of arena {}, which has {}",
+                        arena.end - 1,
+                        number,
+                        len
+                    )))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    Ok(value)
+}
+
+fn arena_id<T>(arena: &Arena<T>) -> u32 {
+    DefaultArenaBehavior::<T>::arena_id(arena.next_id())
+}
+
+fn read_id<T: 'static>(number: u32, index: usize) -> Result<Id<T>, String> {
+    READ_ARENAS.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let scope = cell
+            .as_mut()
+            .ok_or("ids can only be read inside `read_arenas`")?;
+        let read = scope.arenas.entry(number).or_insert_with(|| {
+            let arena = Arena::<T>::new();
+            ReadArena {
+                id: arena_id(&arena),
+                arena: Some(Box::new(arena)),
+                len: None,
+                end: 0,
+            }
+        });
+        read.end = read.end.max(index + 1);
+        Ok(DefaultArenaBehavior::<T>::new_id(read.id, index))
+    })
+}
+
+fn read_arena<T: 'static>(number: u32, items: Vec<T>) -> Result<Arena<T>, String> {
+    let mut arena = READ_ARENAS.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let scope = cell
+            .as_mut()
+            .ok_or("arenas can only be read inside `read_arenas`")?;
+        let read = scope.arenas.entry(number).or_insert_with(|| ReadArena {
+            id: 0,
+            arena: Some(Box::new(Arena::<T>::new())),
+            len: None,
+            end: 0,
+        });
+        if read.len.is_some() {
+            return Err(format!("arena {} appears twice", number));
+        }
+        read.len = Some(items.len());
+        let arena = read.arena.take().expect("an arena not yet read is kept");
+        let arena = arena
+            .downcast::<Arena<T>>()
+            .map_err(|_| format!("arena {} holds items of another kind", number))?;
+        read.id = arena_id(&arena);
+        Ok(*arena)
+    })?;
+    for item in items {
+        arena.alloc(item);
+    }
+    Ok(arena)
+}
+
+/// An id, written as `[arena, index]`.
+pub struct IdRef<T>(pub Id<T>);
+
+impl<T> Serialize for IdRef<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (DefaultArenaBehavior::<T>::arena_id(self.0), self.0.index()).serialize(serializer)
+    }
+}
+
+impl<'de, T: 'static> Deserialize<'de> for IdRef<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (number, index) = <(u32, usize)>::deserialize(deserializer)?;
+        read_id(number, index).map(IdRef).map_err(D::Error::custom)
+    }
+}
+
+pub mod id {
+    use super::*;
+
+    pub fn serialize<T, S: Serializer>(id: &Id<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        IdRef(*id).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Id<T>, D::Error> {
+        Ok(IdRef::deserialize(deserializer)?.0)
+    }
+}
+
+pub mod ids {
+    use super::*;
+
+    pub fn serialize<T, S: Serializer>(ids: &[Id<T>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(ids.iter().map(|id| IdRef(*id)))
+    }
+
+    pub fn deserialize<'de, T: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Id<T>>, D::Error> {
+        let ids = Vec::<IdRef<T>>::deserialize(deserializer)?;
+        Ok(ids.into_iter().map(|id| id.0).collect())
+    }
+}
+
+pub mod opt_id {
+    use super::*;
+
+    pub fn serialize<T, S: Serializer>(
+        id: &Option<Id<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        id.map(IdRef).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Id<T>>, D::Error> {
+        Ok(Option::<IdRef<T>>::deserialize(deserializer)?.map(|id| id.0))
+    }
+}
+
+/// An arena, as `{"id": arena, "items": [...]}`.
+pub mod arena {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct ArenaRef<'a, T> {
+        id: u32,
+        items: Vec<&'a T>,
+    }
+
+    #[derive(Deserialize)]
+    struct ArenaItems<T> {
+        id: u32,
+        items: Vec<T>,
+    }
+
+    pub fn serialize<T: Serialize, S: Serializer>(
+        arena: &Arena<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        ArenaRef {
+            id: arena_id(arena),
+            items: arena.iter().map(|(_, item)| item).collect(),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de> + 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arena<T>, D::Error> {
+        let ArenaItems { id, items } = ArenaItems::deserialize(deserializer)?;
+        read_arena(id, items).map_err(D::Error::custom)
+    }
+}
+
+/// An id into the AST, as its index; read back detached from any arena, since
+/// the AST is not part of the document.
+pub mod ast_id {
+    use super::*;
+
+    pub fn serialize<T, S: Serializer>(id: &Id<T>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(id.index() as u64)
+    }
+
+    pub fn deserialize<'de, T, D: Deserializer<'de>>(deserializer: D) -> Result<Id<T>, D::Error> {
+        let index = usize::deserialize(deserializer)?;
+        Ok(DefaultArenaBehavior::<T>::new_id(u32::MAX, index))
+    }
+}
+
+pub mod float {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        value: &OrderedFloat<f64>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.0.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<OrderedFloat<f64>, D::Error> {
+        f64::deserialize(deserializer).map(OrderedFloat)
+    }
+}
+
+/// A map whose keys are not strings, as a list of `[key, value]` in key order.
+pub mod entries {
+    use super::*;
+
+    pub fn serialize<K: Serialize + Ord, V: Serialize, S: Serializer>(
+        map: &HashMap<K, V>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
+/// A map from ids to ids, as a list of `[key, value]` in key order.
+pub mod id_map {
+    use super::*;
+
+    pub fn serialize<K, V, S: Serializer>(
+        map: &HashMap<Id<K>, Id<V>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort();
+        serializer.collect_seq(entries.into_iter().map(|(&k, &v)| (IdRef(k), IdRef(v))))
+    }
+
+    pub fn deserialize<'de, K: 'static, V: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Id<K>, Id<V>>, D::Error> {
+        let entries = Vec::<(IdRef<K>, IdRef<V>)>::deserialize(deserializer)?;
+        Ok(entries.into_iter().map(|(k, v)| (k.0, v.0)).collect())
+    }
+}
+
+/// A map from names to ids, in name order.
+pub mod named_ids {
+    use super::*;
+    use crate::ast::Symbol;
+    use std::collections::BTreeMap;
+
+    pub fn serialize<T, S: Serializer>(
+        map: &HashMap<Symbol, Id<T>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        map.iter()
+            .map(|(name, &id)| (name.as_str(), IdRef(id)))
+            .collect::<BTreeMap<_, _>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Symbol, Id<T>>, D::Error> {
+        let map = HashMap::<Symbol, IdRef<T>>::deserialize(deserializer)?;
+        Ok(map.into_iter().map(|(name, id)| (name, id.0)).collect())
+    }
+}
+
+/// A map from ids into the AST to ids, as a list of `[index, id]`.
+pub mod ast_id_map {
+    use super::*;
+
+    pub fn serialize<K, V, S: Serializer>(
+        map: &HashMap<Id<K>, Id<V>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<_> = map.iter().map(|(k, &v)| (k.index(), IdRef(v))).collect();
+        entries.sort_by_key(|(index, _)| *index);
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, K, V: 'static, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<Id<K>, Id<V>>, D::Error> {
+        let entries = Vec::<(usize, IdRef<V>)>::deserialize(deserializer)?;
+        Ok(entries
+            .into_iter()
+            .map(|(index, v)| (DefaultArenaBehavior::<K>::new_id(u32::MAX, index), v.0))
+            .collect())
+    }
+}
@@ -0,0 +1,160 @@
+//! Mapping between CFG sites and the AST they were built from.
+//!
+//! Every CFG statement records the AST statement or expression it was built
+//! from, and every block records the origin of its terminator. A `CfgSite`
+//! names a statement or terminator, and `CfgProgram::site_origin` and
+//! `CfgProgram::site_span` map it back to the source. `SourceMap` indexes the
+//! other direction: the sites built from an AST statement or expression, which
+//! is how a source line is found in the CFG. Sites follow the convention of
+//! `ReachingDefinitions::reaching`: statement `index` of a block, with an index
+//! past the last statement meaning the terminator.
+//!
+//! Passes copy origins along with the statements they rewrite, so the mapping
+//! stays valid after optimization, though a folded or removed statement no
+//! longer has a site.
+//!
+//! ```
+//! use FMitF_rs::ast::StatementKind;
+//! use FMitF_rs::cfg::{SourceMap, SourceOrigin};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void f(int id) {
+//!         hop on A {
+//!             if (T[id: id].n > 0) {
+//!                 T[id: id].n = 0;
+//!             }
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let source_map = SourceMap::new(&cfg);
+//!
+//! let hop = &program.hops[program.functions[program.root_functions[0]].hops[0]];
+//! let StatementKind::IfStmt(if_stmt) = &program.statements[hop.statements[0]].node else {
+//!     unreachable!()
+//! };
+//!
+//! // The write on line 7 is one CFG statement, which maps back to it
+//! let write = source_map.statement_sites(if_stmt.then_branch[0]);
+//! assert_eq!(write.len(), 1);
+//! assert_eq!(cfg.site_span(write[0]).line, 7);
+//! assert_eq!(cfg.site_origin(write[0]), SourceOrigin::Statement(if_stmt.then_branch[0]));
+//!
+//! // The condition on line 6 is a comparison and the branch it decides
+//! let condition = source_map.expression_sites(if_stmt.condition);
+//! assert_eq!(condition.len(), 2);
+//! assert!(condition[1].is_terminator(&cfg));
+//! assert_eq!(cfg.site_span(condition[1]).line, 6);
+//! ```
+
+use crate::ast::{ExpressionId, StatementId};
+use crate::cfg::serialize::id;
+use crate::cfg::{BasicBlockId, CfgProgram, FunctionId, SourceOrigin, Span};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A statement or terminator of a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CfgSite {
+    #[serde(with = "id")]
+    pub function: FunctionId,
+    #[serde(with = "id")]
+    pub block: BasicBlockId,
+    /// Statement index within the block; the statement count for the terminator
+    pub index: usize,
+}
+
+impl CfgSite {
+    /// Whether the site is the terminator of its block.
+    pub fn is_terminator(&self, cfg: &CfgProgram) -> bool {
+        self.index
+            >= cfg.functions[self.function].blocks[self.block]
+                .statements
+                .len()
+    }
+}
+
+impl CfgProgram {
+    /// The AST element a statement or terminator was built from.
+    pub fn site_origin(&self, site: CfgSite) -> SourceOrigin {
+        let block = &self.functions[site.function].blocks[site.block];
+        match block.statements.get(site.index) {
+            Some(stmt) => stmt.origin(),
+            None => block.terminator_origin,
+        }
+    }
+
+    /// The source span of a statement or terminator. Synthetic terminators
+    /// point at their hop.
+    pub fn site_span(&self, site: CfgSite) -> &Span {
+        let block = &self.functions[site.function].blocks[site.block];
+        match block.statements.get(site.index) {
+            Some(stmt) => stmt.span(),
+            None => &block.terminator_span,
+        }
+    }
+}
+
+/// The CFG sites built from each AST statement and expression.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// Every site of the program, in function, block and index order
+    sites: Vec<CfgSite>,
+    by_statement: HashMap<StatementId, Vec<CfgSite>>,
+    by_expression: HashMap<ExpressionId, Vec<CfgSite>>,
+}
+
+impl SourceMap {
+    /// Indexes the statements and terminators of the blocks still part of a hop.
+    pub fn new(cfg: &CfgProgram) -> Self {
+        let mut map = Self::default();
+        for (function, func) in cfg.functions.iter() {
+            for (block, block_data) in func.blocks.iter() {
+                if !func.contains_block(block) {
+                    continue;
+                }
+                for index in 0..=block_data.statements.len() {
+                    let site = CfgSite {
+                        function,
+                        block,
+                        index,
+                    };
+                    map.sites.push(site);
+                    match cfg.site_origin(site) {
+                        SourceOrigin::Statement(stmt) => {
+                            map.by_statement.entry(stmt).or_default().push(site)
+                        }
+                        SourceOrigin::Expression(expr) => {
+                            map.by_expression.entry(expr).or_default().push(site)
+                        }
+                        SourceOrigin::Synthetic => {}
+                    }
+                }
+            }
+        }
+        map
+    }
+
+    /// Every statement and terminator, in function, block and index order.
+    pub fn sites(&self) -> impl Iterator<Item = CfgSite> + '_ {
+        self.sites.iter().copied()
+    }
+
+    /// The sites built from an AST statement. Control flow statements like `if`
+    /// have none of their own; their conditions are expressions.
+    pub fn statement_sites(&self, stmt: StatementId) -> &[CfgSite] {
+        self.by_statement.get(&stmt).map_or(&[], Vec::as_slice)
+    }
+
+    /// The sites built from an AST expression: the temporaries computing it,
+    /// or the branch it decides.
+    pub fn expression_sites(&self, expr: ExpressionId) -> &[CfgSite] {
+        self.by_expression.get(&expr).map_or(&[], Vec::as_slice)
+    }
+}
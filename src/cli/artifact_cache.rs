@@ -0,0 +1,112 @@
+//! What earlier runs built, kept in a directory (--cache-dir) so a run on an
+//! unchanged program skips the work.
+//!
+//! The analyzed AST of each input, with its warnings and comments, is kept as
+//! JSON under `programs/`, keyed by a hash of the input's source files, its
+//! imports included, and of the settings it is analyzed with; only the files
+//! are read again, and the diagnostics are printed from the program kept. The
+//! optimized CFG and the SC-graph of a program, after the symbolic check of
+//! its C-edges, are kept under `graphs/`, keyed the same way by what they are
+//! built with. Prover answers are kept in `answers.json`, keyed by C-edge as
+//! `--cache` keeps them, so after an edit only the edges of the edited
+//! functions are checked again.
+
+use crate::ast::serialize::read_program;
+use crate::ast::{Program, SourceFile};
+use crate::cfg::serialize::read_arenas;
+use crate::cfg::CfgProgram;
+use crate::sc_graph::SCGraph;
+use crate::verification::cache::hash_text;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// The directory artifacts are kept in when none is named.
+pub const DEFAULT_CACHE_DIR: &str = ".fmitf-cache";
+
+/// The file in the directory prover answers are kept in.
+const ANSWERS_FILE: &str = "answers.json";
+
+/// A directory of artifacts from earlier runs.
+#[derive(Debug, Clone)]
+pub struct ArtifactCache {
+    dir: PathBuf,
+}
+
+impl ArtifactCache {
+    /// The cache in `dir`, created if there is none yet.
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        for kind in ["programs", "graphs"] {
+            let path = dir.join(kind);
+            std::fs::create_dir_all(&path)
+                .map_err(|e| format!("Cannot create '{}': {}", path.display(), e))?;
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// The file prover answers are kept in.
+    pub fn answers_path(&self) -> PathBuf {
+        self.dir.join(ANSWERS_FILE)
+    }
+
+    /// The key of what is built from source files: the files, the settings
+    /// it is built with and the version of this tool.
+    pub fn key(files: &[SourceFile], settings: &str) -> String {
+        let mut text = format!("{}\n{}\n", env!("CARGO_PKG_VERSION"), settings);
+        for file in files {
+            let path = file.path.as_ref().map(|path| path.display().to_string());
+            let _ = writeln!(text, "{:?} {}", path, file.source.len());
+            text.push_str(&file.source);
+        }
+        hash_text(&text)
+    }
+
+    /// The key of the CFG and SC-graph of a program.
+    pub fn graph_key(program: &Program, settings: &str) -> String {
+        Self::key(&program.source_files, settings)
+    }
+
+    fn graph_path(&self, key: &str) -> PathBuf {
+        self.dir.join("graphs").join(format!("{}.json", key))
+    }
+
+    fn program_path(&self, key: &str) -> PathBuf {
+        self.dir.join("programs").join(format!("{}.json", key))
+    }
+
+    /// The analyzed program kept for a key, if any. An unreadable file counts
+    /// as none, to be written again.
+    pub fn load_program(&self, key: &str) -> Option<Program> {
+        let text = std::fs::read_to_string(self.program_path(key)).ok()?;
+        read_program(|| serde_json::from_str(&text)).ok()
+    }
+
+    /// Keeps an analyzed program for a key.
+    pub fn save_program(&self, key: &str, program: &Program) -> Result<(), String> {
+        let path = self.program_path(key);
+        let text = serde_json::to_string(program)
+            .map_err(|e| format!("Cannot serialize the program: {}", e))?;
+        std::fs::write(&path, text).map_err(|e| format!("Cannot write '{}': {}", path.display(), e))
+    }
+
+    /// The CFG and SC-graph kept for a key, if any. An unreadable file counts
+    /// as none, to be written again.
+    pub fn load_graph(&self, key: &str) -> Option<(CfgProgram, SCGraph)> {
+        let text = std::fs::read_to_string(self.graph_path(key)).ok()?;
+        read_arenas(|| serde_json::from_str(&text)).ok()
+    }
+
+    /// Keeps a CFG and its SC-graph for a key.
+    pub fn save_graph(
+        &self,
+        key: &str,
+        cfg: &CfgProgram,
+        sc_graph: &SCGraph,
+    ) -> Result<(), String> {
+        let path = self.graph_path(key);
+        let text = serde_json::to_string(&(cfg, sc_graph))
+            .map_err(|e| format!("Cannot serialize the SC-graph: {}", e))?;
+        std::fs::write(&path, text).map_err(|e| format!("Cannot write '{}': {}", path.display(), e))
+    }
+}
@@ -0,0 +1,434 @@
+// src/cli/batch.rs
+//! Batch mode: several inputs processed at once, each on its own, with a line
+//! per file and a summary of them all.
+//!
+//! Directories stand for the `.transact` files under them, and globs for the
+//! files they match, with `*` and `?` matching within a name and `**` any
+//! number of directories. A file another input imports is analyzed as part
+//! of that input rather than again on its own, so a directory holding a
+//! multi-file project is processed as the project. Files run in parallel;
+//! what each found is printed in the order the inputs were given.
+
+use super::{is_glob, plural, print_diagnostic, Cli, Console, Mode, Pipeline, PipelineStage};
+use crate::ast::{imported_files, Diagnostic, Severity};
+use crate::sc_graph::MixedCycles;
+use crate::sql::import_sql;
+use crate::verification::report::verdict;
+use crate::verification::VerificationResult;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// The extension of the files a directory stands for
+const SOURCE_EXTENSION: &str = "transact";
+
+/// What processing one file found.
+#[derive(Default)]
+struct FileReport {
+    path: PathBuf,
+    /// The source, to show diagnostics against
+    source: String,
+    diagnostics: Vec<Diagnostic>,
+    /// Why processing stopped, if it did
+    failure: Option<String>,
+    functions: usize,
+    /// The C-edges of the SC-graph, and how many were verified
+    c_edges: usize,
+    verified: Option<usize>,
+    /// The mixed cycles left, unless processing stopped before the SC-graph
+    cycles: Option<MixedCycles>,
+    /// What the cycles left make of the chopping
+    verdict: Option<String>,
+    duration: Duration,
+}
+
+impl Pipeline {
+    /// Batch mode: process each input file in parallel, then print a line
+    /// per file and the totals; returns how many files have findings, which
+    /// are warnings in check mode and mixed cycles with --fail-on-cycles, and
+    /// fails when any could not be processed
+    pub fn batch(&self, cli: &Cli) -> Result<usize, String> {
+        let start = Instant::now();
+        let patterns: Vec<PathBuf> = std::iter::once(cli.input.clone())
+            .chain(cli.inputs.iter().cloned())
+            .collect();
+        let files = expand_inputs(&patterns)?;
+        let (roots, covered) = project_roots(&files, &self.ast_stage.defines);
+        for (file, importer) in &covered {
+            debug!(
+                "{} is imported by {}, so it is analyzed with it",
+                file.display(),
+                importer.display()
+            );
+        }
+
+        let reports: Vec<FileReport> = roots.par_iter().map(|file| process(file, cli)).collect();
+        for report in &reports {
+            for diagnostic in &report.diagnostics {
+                print_diagnostic(diagnostic, &report.source);
+            }
+            let (summary, ok) = file_summary(report, &cli.mode);
+            self.console
+                .batch_file(&report.path.display().to_string(), &summary, ok);
+        }
+        print_totals(
+            &reports,
+            covered.len(),
+            start.elapsed(),
+            &cli.mode,
+            &self.console,
+        );
+
+        let failed = reports.iter().filter(|r| r.failure.is_some()).count();
+        if failed > 0 {
+            return Err(format!("{} of {} files failed", failed, reports.len()));
+        }
+        let found = |report: &FileReport| match cli.mode {
+            Mode::Check => !report.diagnostics.is_empty(),
+            _ => {
+                cli.fail_on_cycles
+                    && report
+                        .cycles
+                        .as_ref()
+                        .is_some_and(|cycles| !cycles.cycles.is_empty())
+            }
+        };
+        Ok(reports.iter().filter(|report| found(report)).count())
+    }
+}
+
+/// Runs the pipeline of the mode chosen on one file, quietly
+fn process(path: &Path, cli: &Cli) -> FileReport {
+    let start = Instant::now();
+    let mut report = FileReport {
+        path: path.to_path_buf(),
+        ..Default::default()
+    };
+    if let Err(e) = process_into(&mut report, cli) {
+        report.failure = Some(e);
+    }
+    report.duration = start.elapsed();
+    report
+}
+
+fn process_into(report: &mut FileReport, cli: &Cli) -> Result<(), String> {
+    let mut file_cli = cli.clone();
+    file_cli.input = report.path.clone();
+    file_cli.inputs = Vec::new();
+    file_cli.quiet = true;
+    let mut pipeline = Pipeline::new(&file_cli)?;
+    pipeline.verification_stage.progress = None;
+
+    let source =
+        std::fs::read_to_string(&report.path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let source = if file_cli.is_sql_input() {
+        import_sql(&source, Some(&report.path), &file_cli.table_node_map()?).map_err(|errors| {
+            report.diagnostics = errors.into_iter().map(Diagnostic::from).collect();
+            "SQL import failed".to_string()
+        })?
+    } else {
+        source
+    };
+    report.source = source.clone();
+
+    let program = pipeline.ast_stage.execute(source).map_err(|errors| {
+        let count = plural(errors.len(), "error");
+        report.diagnostics = errors.into_iter().map(Diagnostic::from).collect();
+        count
+    })?;
+    report.diagnostics = program.warnings.clone();
+    let denied = program
+        .warnings
+        .iter()
+        .filter(|warning| warning.severity == Severity::Error)
+        .count();
+    if denied > 0 {
+        return Err(format!("{} of denied lints", plural(denied, "warning")));
+    }
+    report.functions = program.root_functions.len();
+    if cli.mode == Mode::Check {
+        return Ok(());
+    }
+
+    let cfg_program = pipeline.cfg_stage.execute(program)?;
+    let cfg_program = pipeline.optimize_stage.execute(cfg_program)?;
+    let (cfg_program, mut sc_graph) = pipeline.scgraph_stage.execute(cfg_program)?;
+    report.c_edges = sc_graph.stats().2;
+    if cli.mode == Mode::Verify {
+        let manager = pipeline
+            .verification_stage
+            .verify(&cfg_program, &mut sc_graph)?;
+        report.verified = Some(
+            manager
+                .results
+                .values()
+                .filter(|result| matches!(result, VerificationResult::Success))
+                .count(),
+        );
+    }
+    let cycles = sc_graph.find_mixed_cycles_within(&cli.cycle_limits());
+    report.verdict = Some(verdict(cycles.cycles.len(), sc_graph.isolation));
+    report.cycles = Some(cycles);
+    Ok(())
+}
+
+/// The line of a file and whether it passed: `None` for warnings only
+fn file_summary(report: &FileReport, mode: &Mode) -> (String, Option<bool>) {
+    if let Some(failure) = &report.failure {
+        return (format!("failed: {}", failure), Some(false));
+    }
+    let warnings = match report.diagnostics.len() {
+        0 => String::new(),
+        n => format!(", {}", plural(n, "warning")),
+    };
+    let functions = plural(report.functions, "function");
+    let took = format!("{} ms", report.duration.as_millis());
+    let (Some(cycles), Some(verdict)) = (&report.cycles, &report.verdict) else {
+        let ok = report.diagnostics.is_empty().then_some(true);
+        return (format!("{}{} ({})", functions, warnings, took), ok);
+    };
+
+    let edges = match (mode, report.verified) {
+        (Mode::Verify, Some(verified)) => {
+            format!("{}/{} C-edges verified", verified, report.c_edges)
+        }
+        _ => plural(report.c_edges, "C-edge"),
+    };
+    let truncated = if cycles.truncated {
+        " (--max-cycles reached)"
+    } else {
+        ""
+    };
+    let summary = format!(
+        "{}, {}, {}{}{} ({})",
+        functions, edges, verdict, truncated, warnings, took
+    );
+    (summary, Some(cycles.cycles.is_empty()))
+}
+
+fn print_totals(
+    reports: &[FileReport],
+    covered: usize,
+    elapsed: Duration,
+    mode: &Mode,
+    console: &Console,
+) {
+    let failed = reports.iter().filter(|r| r.failure.is_some()).count();
+    let warned = reports
+        .iter()
+        .filter(|r| r.failure.is_none() && !r.diagnostics.is_empty())
+        .count();
+    let mut files = plural(reports.len(), "file");
+    if covered > 0 {
+        files = format!("{} (and {} imported)", files, covered);
+    }
+
+    console.result_summary("Summary");
+    console.result_item("Files", &files, None);
+    console.result_item("Failed", &failed.to_string(), Some(failed == 0));
+    console.result_item("With warnings", &warned.to_string(), None);
+    let cyclic: Vec<usize> = reports
+        .iter()
+        .filter_map(|r| r.cycles.as_ref())
+        .map(|cycles| cycles.cycles.len())
+        .filter(|&count| count > 0)
+        .collect();
+    if *mode != Mode::Check {
+        let total: usize = cyclic.iter().sum();
+        console.result_item(
+            "Mixed cycles",
+            &format!("{} in {}", total, plural(cyclic.len(), "file")),
+            Some(total == 0),
+        );
+    }
+    console.result_item("Time", &format!("{:.2?}", elapsed), None);
+    console.quiet_completion(&format!(
+        "{}: {} failed, {} with warnings, {} with mixed cycles",
+        files,
+        failed,
+        warned,
+        cyclic.len()
+    ));
+}
+
+/// The files the inputs stand for, in order and each once: a file itself,
+/// the `.transact` files under a directory, or the files a glob matches
+pub fn expand_inputs(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let matched = if input.is_dir() {
+            let mut found = Vec::new();
+            source_files_under(input, &mut found)
+                .map_err(|e| format!("Cannot read directory {}: {}", input.display(), e))?;
+            if found.is_empty() {
+                return Err(format!(
+                    "No .{} files in {}",
+                    SOURCE_EXTENSION,
+                    input.display()
+                ));
+            }
+            found
+        } else if is_glob(input) {
+            let found = glob(input);
+            if found.is_empty() {
+                return Err(format!("No files match {}", input.display()));
+            }
+            found
+        } else {
+            vec![input.clone()]
+        };
+        for file in matched {
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// The inputs that no other input imports, and the others with an input
+/// importing them
+fn project_roots(
+    files: &[PathBuf],
+    defines: &HashSet<String>,
+) -> (Vec<PathBuf>, Vec<(PathBuf, PathBuf)>) {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let imports: Vec<HashSet<PathBuf>> = files
+        .par_iter()
+        .map(|file| {
+            // A file that cannot be loaded reports why when processed
+            let source = std::fs::read_to_string(file).unwrap_or_default();
+            imported_files(&source, file, defines)
+                .map(|imported| imported.iter().map(|path| canonical(path)).collect())
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let mut roots = Vec::new();
+    let mut covered = Vec::new();
+    for file in files {
+        let path = canonical(file);
+        let importer = files
+            .iter()
+            .zip(&imports)
+            .find(|(other, imported)| *other != file && imported.contains(&path));
+        match importer {
+            Some((importer, _)) => covered.push((file.clone(), importer.clone())),
+            None => roots.push(file.clone()),
+        }
+    }
+    (roots, covered)
+}
+
+/// Collects the `.transact` files under a directory, sorted by path
+fn source_files_under(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            source_files_under(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == SOURCE_EXTENSION) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The files a glob matches, sorted by path
+fn glob(pattern: &Path) -> Vec<PathBuf> {
+    // Components up to the first wildcard name the directory to search from
+    let mut base = PathBuf::new();
+    let mut rest = Vec::new();
+    for component in pattern.components() {
+        let text = component.as_os_str().to_string_lossy().into_owned();
+        if rest.is_empty() && !is_glob(Path::new(&text)) {
+            base.push(component);
+        } else if !matches!(component, Component::CurDir) {
+            rest.push(text);
+        }
+    }
+    let start = if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        base.clone()
+    };
+
+    let mut files = Vec::new();
+    glob_in(&start, &base, &rest, &mut files);
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Matches the components of a glob against the entries of `dir`, which is
+/// `shown` in the paths returned
+fn glob_in(dir: &Path, shown: &Path, rest: &[String], files: &mut Vec<PathBuf>) {
+    let Some((first, rest)) = rest.split_first() else {
+        if dir.is_file() {
+            files.push(shown.to_path_buf());
+        }
+        return;
+    };
+    if first == "**" {
+        // Any number of directories, none included
+        glob_in(dir, shown, rest, files);
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.filter_map(Result::ok).collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        let shown = shown.join(&name);
+        if first == "**" {
+            if path.is_dir() && !name.starts_with('.') {
+                glob_in(&path, &shown, &rest_with(first, rest), files);
+            }
+        } else if wildcard_match(first, &name) {
+            glob_in(&path, &shown, rest, files);
+        }
+    }
+}
+
+fn rest_with(first: &str, rest: &[String]) -> Vec<String> {
+    std::iter::once(first.to_string())
+        .chain(rest.iter().cloned())
+        .collect()
+}
+
+/// Whether a name matches a pattern in which `*` matches any run of
+/// characters and `?` any one
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and how much of the name it had taken
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
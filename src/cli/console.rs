@@ -0,0 +1,336 @@
+// src/cli/console.rs
+//! Results written to stdout, such as summaries, verdicts and the lines of a
+//! batch or of watch mode, as much of them as -q and -v ask for. Progress,
+//! warnings and errors are `tracing` events instead, written to stderr (see
+//! [`init_logging`](super::init_logging)).
+
+use crate::verification::ledger::Assumption;
+use crate::verification::postconditions::Postcondition;
+use crate::verification::report::FunctionSummary;
+use crate::verification::VerificationResult;
+use colored::*;
+
+/// How much of the results is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only failures and one-line summaries (-q)
+    Quiet,
+    /// The results and their summaries
+    Normal,
+    /// Also the details of each result (-v)
+    Verbose,
+}
+
+pub struct Console {
+    verbosity: Verbosity,
+}
+
+impl Console {
+    pub fn new(verbosity: u8, quiet: bool) -> Self {
+        let verbosity = match verbosity {
+            _ if quiet => Verbosity::Quiet,
+            0 => Verbosity::Normal,
+            _ => Verbosity::Verbose,
+        };
+        Self { verbosity }
+    }
+
+    /// Whether each condition of a replay was proven again: the hops of its
+    /// C-edge, then the prover's answer when it was not
+    pub fn replay_results(&self, results: &[(String, Option<String>)]) {
+        if self.verbosity >= Verbosity::Normal {
+            println!("\n{}", "Replay Results".bright_white().bold());
+            for (hops, failure) in results {
+                match failure {
+                    None => println!(" - {}: {}", hops, "proven again".green()),
+                    Some(msg) => {
+                        println!(" - {}: {}", hops, "not proven".red());
+                        println!("   {}", msg);
+                    }
+                }
+            }
+        }
+    }
+
+    /// What checking the properties functions ensure found, with the
+    /// interleaving that breaks each broken one and where it starts from
+    pub fn postconditions(&self, postconditions: &[Postcondition]) {
+        if postconditions.is_empty() || self.verbosity < Verbosity::Normal {
+            return;
+        }
+        println!("\n{}", "Ensured Properties".bright_white().bold());
+        for postcondition in postconditions {
+            let result = match postcondition.result {
+                "holds" => "holds".green(),
+                "broken" => "broken".red(),
+                result => result.yellow(),
+            };
+            println!(
+                " - {} ensures {}: {}",
+                postcondition.function, postcondition.property, result
+            );
+            if !postcondition.interleaving.is_empty() {
+                println!("   by {}", postcondition.interleaving.join(", "));
+            }
+            if let Some(message) = &postcondition.message {
+                match postcondition.result {
+                    "broken" => println!("   from {}", message),
+                    _ => println!("   {}", message),
+                }
+            }
+        }
+    }
+
+    /// Whether each transaction's C-edges were all removed, and if not the
+    /// transactions it still conflicts with; with --verbose, the edges left
+    pub fn functions(&self, functions: &[FunctionSummary]) {
+        if functions.is_empty() || self.verbosity < Verbosity::Normal {
+            return;
+        }
+        println!("\n{}", "Functions".bright_white().bold());
+        for function in functions {
+            let total = function.removed + function.remaining.len();
+            let edges = if total == 1 { "C-edge" } else { "C-edges" };
+            if total == 0 {
+                println!(" - {}: no C-edges", function.function);
+                continue;
+            }
+            if function.discharged {
+                println!(
+                    " - {}: {}",
+                    function.function,
+                    format!("{} {} removed", total, edges).green()
+                );
+                continue;
+            }
+            println!(
+                " - {}: {}, conflicts with {}",
+                function.function,
+                format!("{} of {} {} left", function.remaining.len(), total, edges).red(),
+                function.conflicts_with.join(", ")
+            );
+            if self.verbosity >= Verbosity::Verbose {
+                for [a, b] in &function.remaining {
+                    println!("   {} -- {}", a, b);
+                }
+            }
+        }
+    }
+
+    /// What the verdict takes for granted, each with what it leaves out
+    pub fn assumptions(&self, ledger: &[Assumption]) {
+        if ledger.is_empty() || self.verbosity < Verbosity::Normal {
+            return;
+        }
+        println!("\n{}", "Assumptions".bright_white().bold());
+        for entry in ledger {
+            println!(" - {}: {}", entry.assumption, entry.consequence);
+        }
+    }
+
+    // Success messages (Normal level)
+    pub fn success(&self, message: &str) {
+        if self.verbosity >= Verbosity::Normal {
+            println!("{} {}", "SUCCESS:".green().bold(), message.green());
+        }
+    }
+
+    pub fn detail_item(&self, label: &str, value: &str) {
+        if self.verbosity >= Verbosity::Verbose {
+            println!("  {}: {}", label.bright_white(), value);
+        }
+    }
+
+    // Results and summaries (Normal level)
+    pub fn result_summary(&self, title: &str) {
+        if self.verbosity >= Verbosity::Normal {
+            println!("\n{}", title.bright_white().bold());
+        }
+    }
+
+    pub fn result_item(&self, label: &str, value: &str, success: Option<bool>) {
+        if self.verbosity >= Verbosity::Normal {
+            let formatted_value = match success {
+                Some(true) => value.green(),
+                Some(false) => value.red(),
+                None => value.normal(),
+            };
+            println!(" - {}: {}", label, formatted_value);
+        }
+    }
+
+    // Quiet-only messages for when normal output is suppressed
+    pub fn quiet_completion(&self, message: &str) {
+        if self.verbosity == Verbosity::Quiet {
+            println!("{}", message);
+        }
+    }
+
+    // Special case: mixed cycles warning (always shown unless truly quiet)
+    pub fn cycles_warning(&self, count: usize) {
+        if self.verbosity >= Verbosity::Normal {
+            println!(
+                "\n{} {} mixed S/C cycles remain after verification.",
+                "Error:".red().bold(),
+                count.to_string().bright_yellow()
+            );
+        }
+    }
+
+    pub fn no_cycles(&self) {
+        if self.verbosity >= Verbosity::Normal {
+            println!(
+                "No mixed S/C cycles detected - system appears {}.",
+                "serializable".green().bold()
+            );
+        }
+    }
+
+    // Special formatting for verification results
+    pub fn verification_result(&self, verified: usize, total: usize, success_rate: f64) {
+        if self.verbosity == Verbosity::Quiet {
+            println!(
+                "Verification: {}/{} C-edges verified ({:.1}%)",
+                verified, total, success_rate
+            );
+        } else if self.verbosity >= Verbosity::Normal {
+            println!("Verification Results");
+            println!(" - Total C-edges analyzed: {}", total);
+            println!(" - Successfully verified: {}", verified);
+            println!(" - Success rate: {:.1}%", success_rate);
+        }
+    }
+
+    /// How many C-edges the prover could not decide, e.g. within the time limit
+    pub fn verification_unknown(&self, unknown: usize) {
+        if self.verbosity >= Verbosity::Normal {
+            println!(" - Unknown: {}", unknown);
+        }
+    }
+
+    /// How many C-edges took their result from the cache, and how many were
+    /// decided in this run
+    pub fn verification_cached(&self, reused: usize, fresh: usize) {
+        if self.verbosity >= Verbosity::Normal {
+            println!(" - Reused from the cache: {}", reused);
+            println!(" - Decided in this run: {}", fresh);
+        }
+    }
+
+    /// The invariants inferred of the tables, listed when verbose
+    pub fn verification_invariants(&self, invariants: &[String]) {
+        if self.verbosity >= Verbosity::Normal {
+            println!(" - Invariants inferred: {}", invariants.len());
+        }
+        if self.verbosity >= Verbosity::Verbose {
+            for invariant in invariants {
+                println!("   {}", invariant);
+            }
+        }
+    }
+
+    // Print detailed verification results
+    pub fn verification_details<F>(&self, get_results: F)
+    where
+        F: Fn() -> Vec<(String, VerificationResult)>,
+    {
+        if self.verbosity >= Verbosity::Verbose {
+            println!("Detailed Results");
+            for (edge_info, result) in get_results() {
+                match result {
+                    VerificationResult::Success => {
+                        println!(" - {}: Verified (commutative), removed", edge_info);
+                    }
+                    VerificationResult::Failure(msg) => {
+                        println!(" - {}: Failed", edge_info);
+                        println!("   {}", msg);
+                    }
+                    VerificationResult::Unknown(msg) => {
+                        println!(" - {}: Unknown", edge_info);
+                        println!("   {}", msg);
+                    }
+                }
+            }
+        }
+    }
+
+    // Handle mixed cycles with appropriate colors
+    pub fn mixed_cycles_status(&self, cycle_count: usize, cycles: Option<Vec<String>>) {
+        if cycle_count > 0 {
+            self.cycles_warning(cycle_count);
+
+            if self.verbosity >= Verbosity::Verbose {
+                if let Some(cycle_strings) = cycles {
+                    println!("   Remaining cycles:");
+                    for (i, cycle_str) in cycle_strings.iter().enumerate() {
+                        println!("     Cycle {}: {}", i + 1, cycle_str);
+                    }
+                }
+            }
+        } else {
+            self.no_cycles();
+        }
+    }
+
+    /// The final verdict on the pruned SC-graph
+    pub fn verdict(&self, verdict: &str, serializable: bool) {
+        if self.verbosity >= Verbosity::Normal {
+            let verdict = if serializable {
+                verdict.green().bold()
+            } else {
+                verdict.red().bold()
+            };
+            println!("Verdict: {}", verdict);
+        }
+    }
+
+    // A simple info message for positive cases
+    pub fn info_positive(&self, message: &str) {
+        if self.verbosity >= Verbosity::Normal {
+            println!("{}", message.bright_white());
+        }
+    }
+
+    /// The line of a file of a batch: green when it passed, red when it
+    /// failed or has mixed cycles, yellow with warnings only
+    pub fn batch_file(&self, path: &str, summary: &str, ok: Option<bool>) {
+        let shown = if ok == Some(true) {
+            Verbosity::Normal
+        } else {
+            Verbosity::Quiet
+        };
+        if self.verbosity >= shown {
+            let path = match ok {
+                Some(true) => path.green().bold(),
+                Some(false) => path.red().bold(),
+                None => path.yellow().bold(),
+            };
+            println!("{}: {}", path, summary);
+        }
+    }
+
+    /// A line of watch mode, stamped with the time of the run it is about
+    pub fn watch_status(&self, message: &str) {
+        println!("{} {}", watch_time().dimmed(), message);
+    }
+
+    /// A run of watch mode that failed, after its errors were printed
+    pub fn watch_failure(&self, message: &str) {
+        println!("{} {}", watch_time().dimmed(), message.red().bold());
+    }
+
+    /// A mixed cycle a run of watch mode fixed or introduced
+    pub fn watch_cycle(&self, fixed: bool, cycle: &str) {
+        if self.verbosity >= Verbosity::Normal {
+            if fixed {
+                println!("  {} {}", "-".green().bold(), cycle.green());
+            } else {
+                println!("  {} {}", "+".red().bold(), cycle.red());
+            }
+        }
+    }
+}
+
+fn watch_time() -> String {
+    chrono::Local::now().format("[%H:%M:%S]").to_string()
+}
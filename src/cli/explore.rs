@@ -0,0 +1,381 @@
+// src/cli/explore.rs
+//! Explore mode: a REPL over the SC-graph of a loaded program, to try out
+//! what removing C-edges would do before proving anything.
+//!
+//! ```
+//! use FMitF_rs::ast::parse_and_analyze;
+//! use FMitF_rs::cli::{ExploreCommand, Explorer};
+//! use FMitF_rs::{CfgBuilder, SCGraph};
+//!
+//! let program =
+//!     parse_and_analyze(include_str!("../../examples/sc_graph_demos/crossed_writers.transact"))
+//!         .unwrap();
+//! let sources = program.source_files.clone();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let mut explorer = Explorer::new(cfg, sc_graph, sources, Default::default());
+//!
+//! let cycles = explorer.run(&ExploreCommand::Cycles).unwrap();
+//! assert!(cycles.starts_with("1 mixed cycle"), "{}", cycles);
+//!
+//! // Either C-edge breaks the cycle
+//! let dropped = explorer.run(&ExploreCommand::Drop { edge: 1 }).unwrap();
+//! assert!(dropped.contains("1 -> 0 mixed cycles"), "{}", dropped);
+//! let restored = explorer.run(&ExploreCommand::Restore { edge: None }).unwrap();
+//! assert!(restored.contains("0 -> 1 mixed cycle"), "{}", restored);
+//!
+//! let hop = explorer.run(&ExploreCommand::Hop { hop: "g:2".to_string() }).unwrap();
+//! assert!(hop.contains("on Node B"), "{}", hop);
+//! ```
+
+use crate::ast::SourceFile;
+use crate::pretty::cfg_printer::format_hop_text;
+use crate::sc_graph::{CycleLimits, Edge, EdgeType, MixedCycles};
+use crate::{CfgProgram, SCGraph};
+#[cfg(feature = "repl")]
+use clap_repl::reedline::{DefaultPrompt, DefaultPromptSegment};
+#[cfg(feature = "repl")]
+use clap_repl::{ClapEditor, ReadCommandOutput};
+use colored::*;
+use std::collections::BTreeSet;
+use std::io::{BufRead, IsTerminal};
+
+/// Explore mode commands
+#[derive(clap::Parser, Debug, Clone)]
+pub enum ExploreCommand {
+    /// List the mixed cycles: cycles
+    Cycles,
+
+    /// List the C-edges, numbered: edges
+    Edges,
+
+    /// Show the conflicting statements of a C-edge: edge 2
+    Edge { edge: usize },
+
+    /// Drop a C-edge as if proven to commute and recount the cycles: drop 2
+    Drop { edge: usize },
+
+    /// Put back a dropped C-edge, or all of them: restore [2]
+    Restore { edge: Option<usize> },
+
+    /// Show the CFG of a hop, as function:position: hop transfer:1
+    Hop { hop: String },
+
+    /// Exit explore mode
+    Exit,
+}
+
+/// A program's SC-graph and the C-edges dropped from it so far.
+pub struct Explorer {
+    cfg: CfgProgram,
+    sc_graph: SCGraph,
+    sources: Vec<SourceFile>,
+    limits: CycleLimits,
+    /// The C-edges of the graph; commands number them from 1
+    c_edges: Vec<Edge>,
+    /// Indices into `c_edges` of the edges dropped
+    dropped: BTreeSet<usize>,
+}
+
+impl Explorer {
+    pub fn new(
+        cfg: CfgProgram,
+        sc_graph: SCGraph,
+        sources: Vec<SourceFile>,
+        limits: CycleLimits,
+    ) -> Self {
+        let c_edges = sc_graph
+            .edges
+            .iter()
+            .filter(|edge| edge.edge_type == EdgeType::C)
+            .cloned()
+            .collect();
+        Self {
+            cfg,
+            sc_graph,
+            sources,
+            limits,
+            c_edges,
+            dropped: BTreeSet::new(),
+        }
+    }
+
+    /// Runs a command, returning what to print.
+    pub fn run(&mut self, command: &ExploreCommand) -> Result<String, String> {
+        match command {
+            ExploreCommand::Cycles => Ok(self.cycles_text()),
+            ExploreCommand::Edges => Ok(self.edges_text()),
+            ExploreCommand::Edge { edge } => self.edge_text(*edge),
+            ExploreCommand::Drop { edge } => {
+                let index = self.c_edge(*edge)?;
+                if self.dropped.contains(&index) {
+                    return Err(format!("C-edge {} is already dropped", edge));
+                }
+                let before = self.cycles();
+                self.dropped.insert(index);
+                Ok(format!(
+                    "Dropped C-edge {} ({}): {}",
+                    edge,
+                    self.describe_edge(&self.c_edges[index]),
+                    self.delta(&before)
+                ))
+            }
+            ExploreCommand::Restore { edge } => {
+                let before = self.cycles();
+                let restored = match edge {
+                    Some(edge) => {
+                        let index = self.c_edge(*edge)?;
+                        if !self.dropped.remove(&index) {
+                            return Err(format!("C-edge {} is not dropped", edge));
+                        }
+                        format!("C-edge {}", edge)
+                    }
+                    None => {
+                        let count = std::mem::take(&mut self.dropped).len();
+                        super::plural(count, "C-edge")
+                    }
+                };
+                Ok(format!("Restored {}: {}", restored, self.delta(&before)))
+            }
+            ExploreCommand::Hop { hop } => self.hop_text(hop),
+            ExploreCommand::Exit => Ok(String::new()),
+        }
+    }
+
+    /// The SC-graph without the dropped C-edges.
+    fn graph(&self) -> SCGraph {
+        let mut graph = self.sc_graph.clone();
+        for &index in &self.dropped {
+            graph.edges.retain(|edge| *edge != self.c_edges[index]);
+        }
+        graph
+    }
+
+    fn cycles(&self) -> MixedCycles {
+        self.graph().find_mixed_cycles_within(&self.limits)
+    }
+
+    /// The index into `c_edges` of the C-edge a command numbers.
+    fn c_edge(&self, number: usize) -> Result<usize, String> {
+        match number {
+            1.. if number <= self.c_edges.len() => Ok(number - 1),
+            _ => Err(format!(
+                "No C-edge {}; the C-edges are numbered 1 to {}",
+                number,
+                self.c_edges.len()
+            )),
+        }
+    }
+
+    fn describe_edge(&self, edge: &Edge) -> String {
+        format!(
+            "{} -- {}",
+            self.sc_graph.hop_name(edge.source, &self.cfg),
+            self.sc_graph.hop_name(edge.target, &self.cfg)
+        )
+    }
+
+    fn count(cycles: &MixedCycles) -> String {
+        let count = super::plural(cycles.cycles.len(), "mixed cycle");
+        match cycles.truncated {
+            true => format!("at least {}", count),
+            false => count,
+        }
+    }
+
+    /// How the cycles changed from `before`, with those broken and made.
+    fn delta(&self, before: &MixedCycles) -> String {
+        let after = self.cycles();
+        let graph = self.graph();
+        let ids = |cycles: &MixedCycles| -> Vec<(String, String)> {
+            cycles
+                .cycles
+                .iter()
+                .map(|cycle| {
+                    (
+                        graph.cycle_id(cycle, &self.cfg),
+                        graph.describe_cycle(cycle, &self.cfg),
+                    )
+                })
+                .collect()
+        };
+        let (old, new) = (ids(before), ids(&after));
+        let mut s = format!(
+            "{} -> {}",
+            before.cycles.len(),
+            super::plural(after.cycles.len(), "mixed cycle")
+        );
+        if after.truncated {
+            s.push_str(" (search stopped at its limit)");
+        }
+        for (id, cycle) in old.iter().filter(|cycle| !new.contains(cycle)) {
+            s.push_str(&format!("\n  - [{}] {}", id, cycle));
+        }
+        for (id, cycle) in new.iter().filter(|cycle| !old.contains(cycle)) {
+            s.push_str(&format!("\n  + [{}] {}", id, cycle));
+        }
+        s
+    }
+
+    fn cycles_text(&self) -> String {
+        let cycles = self.cycles();
+        let graph = self.graph();
+        let mut s = Self::count(&cycles);
+        if !self.dropped.is_empty() {
+            s.push_str(&format!(
+                " ({} dropped)",
+                super::plural(self.dropped.len(), "C-edge")
+            ));
+        }
+        for cycle in &cycles.cycles {
+            s.push_str(&format!(
+                "\n  [{}] {}",
+                graph.cycle_id(cycle, &self.cfg),
+                graph.describe_cycle(cycle, &self.cfg)
+            ));
+        }
+        s
+    }
+
+    fn edges_text(&self) -> String {
+        let mut s = super::plural(self.c_edges.len(), "C-edge");
+        for (index, edge) in self.c_edges.iter().enumerate() {
+            let dropped = match self.dropped.contains(&index) {
+                true => " (dropped)",
+                false => "",
+            };
+            s.push_str(&format!(
+                "\n  {}. {}{}",
+                index + 1,
+                self.describe_edge(edge),
+                dropped
+            ));
+        }
+        s
+    }
+
+    fn edge_text(&self, number: usize) -> Result<String, String> {
+        let edge = &self.c_edges[self.c_edge(number)?];
+        let mut s = format!("C-edge {}: {}", number, self.describe_edge(edge));
+        let Some(justification) = self.sc_graph.justifications.get(edge) else {
+            return Ok(s);
+        };
+        s.push_str(&format!("\n  {}", justification.reason));
+        for conflict in &justification.conflicts {
+            s.push_str(&format!(
+                "\n  {} on {}.{}",
+                conflict.kind,
+                self.cfg.tables[conflict.table].name,
+                self.cfg.fields[conflict.field].name
+            ));
+            for site in [conflict.source, conflict.target] {
+                let span = self.cfg.site_span(site);
+                let line = self
+                    .sources
+                    .iter()
+                    .find(|file| file.path == span.file)
+                    .and_then(|file| file.source.lines().nth(span.line - 1))
+                    .unwrap_or_default();
+                s.push_str(&format!(
+                    "\n    {} at {}: {}",
+                    self.cfg.functions[site.function].name,
+                    span,
+                    line.trim()
+                ));
+            }
+        }
+        Ok(s)
+    }
+
+    fn hop_text(&self, hop: &str) -> Result<String, String> {
+        let invalid = || format!("No hop '{}'; name hops as function:position, e.g. f:1", hop);
+        let (name, position) = hop.rsplit_once(':').ok_or_else(invalid)?;
+        let position: usize = position.parse().map_err(|_| invalid())?;
+        let (function_id, function) = self
+            .cfg
+            .functions
+            .iter()
+            .find(|(_, function)| function.name == name)
+            .ok_or_else(invalid)?;
+        let hop_id = position
+            .checked_sub(1)
+            .and_then(|index| function.hop_order.get(index))
+            .ok_or_else(invalid)?;
+        Ok(format_hop_text(&self.cfg, function_id, *hop_id, true)
+            .trim_end()
+            .to_string())
+    }
+}
+
+/// Runs explore mode on a program until `exit`, reading commands from the
+/// terminal, or one per line when stdin is not one.
+pub fn explore(mut explorer: Explorer) -> Result<(), String> {
+    if !std::io::stdin().is_terminal() {
+        for line in std::io::stdin().lock().lines() {
+            let line = line.map_err(|e| format!("Failed to read a command: {}", e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let words = std::iter::once("").chain(line.split_whitespace());
+            match <ExploreCommand as clap::Parser>::try_parse_from(words) {
+                Ok(ExploreCommand::Exit) => break,
+                Ok(command) => print_result(explorer.run(&command)),
+                Err(e) => {
+                    let _ = e.print();
+                }
+            }
+        }
+        return Ok(());
+    }
+    interact(explorer)
+}
+
+/// Reads commands from the terminal, with line editing.
+#[cfg(feature = "repl")]
+fn interact(mut explorer: Explorer) -> Result<(), String> {
+    println!("{}", "SC-Graph Explorer".bold());
+    println!(
+        "Available commands: {}",
+        "cycles, edges, edge, drop, restore, hop, exit, help".bright_white()
+    );
+    println!("{}", explorer.cycles_text());
+
+    let prompt = DefaultPrompt::new(
+        DefaultPromptSegment::Basic("explore".to_string()),
+        DefaultPromptSegment::Empty,
+    );
+    let mut editor = ClapEditor::<ExploreCommand>::builder()
+        .with_prompt(Box::new(prompt))
+        .build();
+    loop {
+        match editor.read_command() {
+            ReadCommandOutput::Command(ExploreCommand::Exit)
+            | ReadCommandOutput::CtrlC
+            | ReadCommandOutput::CtrlD => break,
+            ReadCommandOutput::Command(command) => print_result(explorer.run(&command)),
+            ReadCommandOutput::EmptyLine => {}
+            ReadCommandOutput::ClapError(err) => {
+                let _ = err.print();
+            }
+            ReadCommandOutput::ShlexError => {
+                eprintln!("ERROR: Invalid input: unmatched quotes");
+            }
+            ReadCommandOutput::ReedlineError(err) => {
+                return Err(format!("Input error: {}", err));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "repl"))]
+fn interact(_explorer: Explorer) -> Result<(), String> {
+    Err("Exploring from a terminal needs the `repl` feature; pipe the commands in".to_string())
+}
+
+fn print_result(result: Result<String, String>) {
+    match result {
+        Ok(text) => println!("{}", text),
+        Err(e) => eprintln!("{} {}", "ERROR:".red().bold(), e.bright_red()),
+    }
+}
@@ -0,0 +1,126 @@
+// src/cli/init.rs
+//! `fmitf init DIR`: a small project for new users to start from, with a
+//! workload, its config and a baseline accepting the cycle it has on purpose.
+//!
+//! ```
+//! use FMitF_rs::ast::AnalysisConfig;
+//! use FMitF_rs::cli::{INIT_CONFIG, INIT_WORKLOAD};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder, SCGraph};
+//!
+//! let program = parse_and_analyze(INIT_WORKLOAD).unwrap();
+//! assert!(program.warnings.is_empty());
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! assert_eq!(SCGraph::new(&cfg).find_mixed_cycles().len(), 1);
+//! assert!(AnalysisConfig::from_toml(INIT_CONFIG).is_ok());
+//! ```
+
+use super::{Pipeline, PipelineStage};
+use crate::parse_and_analyze;
+use crate::sc_graph::{Baseline, DEFAULT_BASELINE_FILE};
+use std::path::Path;
+use tracing::info;
+
+/// The workload `fmitf init` writes, as `bank.transact`.
+pub const INIT_WORKLOAD: &str = r#"// A small banking workload to start from: balances live on one node and an
+// audit log on the other. deposit and withdraw each update an account, then
+// log the amount, so their hops form a mixed cycle: each may run between the
+// two hops of the other. fmitf-baseline.toml accepts it, so that
+// `--baseline` only fails on cycles added later.
+nodes {
+    Bank,
+    Audit
+}
+
+table Account on Bank {
+    primary int id;
+    int balance;
+}
+
+table Log on Audit {
+    primary int entry;
+    int account;
+    int amount;
+}
+
+void deposit(int account, int amount, int entry) {
+    hop on Bank {
+        int balance = Account[id: account].balance;
+        Account[id: account].balance = balance + amount;
+    }
+
+    hop on Audit {
+        Log[entry: entry].account = account;
+        Log[entry: entry].amount = amount;
+    }
+}
+
+void withdraw(int account, int amount, int entry) {
+    hop on Bank {
+        int balance = Account[id: account].balance;
+        if (balance < amount) {
+            abort;
+        }
+        Account[id: account].balance = balance - amount;
+    }
+
+    hop on Audit {
+        Log[entry: entry].account = account;
+        Log[entry: entry].amount = 0 - amount;
+    }
+}
+"#;
+
+/// The config `fmitf init` writes, as `fmitf.toml`.
+pub const INIT_CONFIG: &str = r#"# Settings for analysis and verification: fmitf bank.transact --config fmitf.toml
+
+# Aborts are accepted in a transaction's first hop only
+abort_policy = "first-hop"
+
+[lints]
+dead_writes = "deny"
+
+[verification]
+timeout = 60  # wall-clock seconds per C-edge
+"#;
+
+const WORKLOAD_FILE: &str = "bank.transact";
+const CONFIG_FILE: &str = "fmitf.toml";
+
+impl Pipeline {
+    /// Write the workload, its config and its baseline into `dir`, which is
+    /// created if needed; existing files are left alone
+    pub fn init(&mut self, dir: &Path) -> Result<(), String> {
+        let paths = [WORKLOAD_FILE, CONFIG_FILE, DEFAULT_BASELINE_FILE].map(|file| dir.join(file));
+        if let Some(path) = paths.iter().find(|path| path.exists()) {
+            return Err(format!("'{}' already exists", path.display()));
+        }
+        let baseline = self.init_baseline()?;
+
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Cannot create '{}': {}", dir.display(), e))?;
+        for (path, text) in paths.iter().zip([INIT_WORKLOAD, INIT_CONFIG, &baseline]) {
+            info!(path = %path.display(), "Writing {}", path.display());
+            std::fs::write(path, text)
+                .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))?;
+        }
+        self.console.success(&format!(
+            "Created {}; try: cd {} && fmitf {} --mode scgraph --config {} --baseline",
+            dir.display(),
+            dir.display(),
+            WORKLOAD_FILE,
+            CONFIG_FILE
+        ));
+        Ok(())
+    }
+
+    /// The baseline accepting the workload's cycles, as scgraph mode finds them
+    fn init_baseline(&mut self) -> Result<String, String> {
+        let program = parse_and_analyze(INIT_WORKLOAD)
+            .map_err(|errors| format!("The workload has {} error(s)", errors.len()))?;
+        let cfg_program = self.cfg_stage.execute(program)?;
+        let optimized_cfg = self.optimize_stage.execute(cfg_program)?;
+        let (cfg_program, sc_graph) = self.scgraph_stage.execute(optimized_cfg)?;
+        let cycles = sc_graph.find_mixed_cycles();
+        Ok(Baseline::write(&sc_graph, &cycles, &cfg_program))
+    }
+}
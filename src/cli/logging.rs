@@ -0,0 +1,200 @@
+// src/cli/logging.rs
+//! Progress, warnings and errors are `tracing` events, written to stderr by a
+//! `tracing-subscriber` formatter, while results go to stdout through
+//! [`Console`](super::Console), so that results can be piped or scraped.
+//!
+//! `-q` keeps the `error` level only, the default adds `warn` and `info`, `-v`
+//! `debug` details and `-vv` `trace`, among them the close of each phase's
+//! span (`parse`, `ast`, `analyze`, `cfg`, `optimize`, `scgraph`, `cycles` or
+//! `verify`) with how long it was busy. Text shows each event's message, which
+//! says what its fields hold. With `--log-format json` each event is a line of
+//! JSON as `tracing-subscriber` writes it: its `timestamp`, `level`, `message`
+//! and fields, and the `span` it happened in. Stage ends carry the `stage`,
+//! its `status` and, with `-vv`, `elapsed_ms`.
+//!
+//! ```
+//! use FMitF_rs::cli::level_filter;
+//! use tracing::level_filters::LevelFilter;
+//!
+//! assert_eq!(level_filter(0, false), LevelFilter::INFO);
+//! assert_eq!(level_filter(2, false), LevelFilter::TRACE);
+//! assert_eq!(level_filter(2, true), LevelFilter::ERROR);
+//! ```
+
+use super::{Cli, Mode};
+use clap::ValueEnum;
+use std::fmt;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::{debug, error, info};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::{format, FmtSpan, Writer};
+use tracing_subscriber::fmt::FormatFields;
+
+/// Fields for log collectors that repeat what the message says, left out of text
+const STRUCTURED_FIELDS: [&str; 6] = [
+    "stage",
+    "status",
+    "elapsed_ms",
+    "warnings",
+    "errors",
+    "path",
+];
+
+/// How messages to stderr are written
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LogFormat {
+    /// For people to read
+    #[default]
+    Text,
+    /// A JSON object per line, for log collectors
+    Json,
+}
+
+/// The level of events the -v and -q flags keep
+pub fn level_filter(verbosity: u8, quiet: bool) -> LevelFilter {
+    match verbosity {
+        _ if quiet => LevelFilter::ERROR,
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// Writes the events of the run to stderr, as -v, -q and --log-format ask
+pub fn init_logging(cli: &Cli) {
+    // Fmt mode rewrites files silently, or prints the source, so keep
+    // progress messages out of it
+    let level = level_filter(cli.verbose, cli.quiet || cli.mode == Mode::Fmt);
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .with_span_events(FmtSpan::CLOSE);
+    // Only the first subscriber of a process is kept, e.g. of a test
+    let _ = match cli.log_format {
+        // Leaving the time out of the events only, so spans keep theirs
+        LogFormat::Text => subscriber
+            .event_format(format().without_time().with_target(false))
+            .fmt_fields(TextFields)
+            .with_ansi(colored::control::SHOULD_COLORIZE.should_colorize())
+            .try_init(),
+        LogFormat::Json => subscriber.json().flatten_event(true).try_init(),
+    };
+}
+
+/// Writes the message of an event and the fields it does not repeat
+struct TextFields;
+
+impl<'w> FormatFields<'w> for TextFields {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'w>, fields: R) -> fmt::Result {
+        let mut visitor = TextVisitor {
+            writer,
+            result: Ok(()),
+            first: true,
+        };
+        fields.record(&mut visitor);
+        visitor.result
+    }
+}
+
+struct TextVisitor<'w> {
+    writer: Writer<'w>,
+    result: fmt::Result,
+    first: bool,
+}
+
+impl Visit for TextVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.record_debug(field, &format_args!("{}", value)),
+            _ => self.record_debug(field, &value),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if self.result.is_err() || STRUCTURED_FIELDS.contains(&field.name()) {
+            return;
+        }
+        let separator = if self.first { "" } else { " " };
+        self.first = false;
+        self.result = match field.name() {
+            "message" => write!(self.writer, "{}{:?}", separator, value),
+            name => write!(self.writer, "{}{}={:?}", separator, name, value),
+        };
+    }
+}
+
+/// A stage of the pipeline, logged when it ends with how long it took
+#[must_use = "a stage is logged when it ends"]
+pub struct Stage {
+    name: String,
+    label: String,
+    started: Instant,
+}
+
+impl Stage {
+    pub fn start(number: usize, total: usize, name: &str) -> Self {
+        let label = format!("Stage {}/{} ({})", number, total, name);
+        debug!(stage = name, "{} started", label);
+        Self {
+            name: name.to_string(),
+            label,
+            started: Instant::now(),
+        }
+    }
+
+    /// The time since the start, in milliseconds to the microsecond, kept
+    /// for -vv as the phases' times are
+    fn elapsed_ms(&self) -> Option<f64> {
+        (LevelFilter::current() >= LevelFilter::TRACE)
+            .then(|| (self.started.elapsed().as_secs_f64() * 1e6).round() / 1000.0)
+    }
+
+    pub fn success(self) {
+        info!(
+            stage = %self.name,
+            status = "ok",
+            elapsed_ms = self.elapsed_ms(),
+            "{}: OK",
+            self.label
+        );
+    }
+
+    pub fn warnings(self, count: usize) {
+        info!(
+            stage = %self.name,
+            status = "warnings",
+            elapsed_ms = self.elapsed_ms(),
+            warnings = count,
+            "{}: OK ({} warning{})",
+            self.label,
+            count,
+            if count == 1 { "" } else { "s" }
+        );
+    }
+
+    pub fn error(self, count: usize) {
+        error!(
+            stage = %self.name,
+            status = "error",
+            elapsed_ms = self.elapsed_ms(),
+            errors = count,
+            "{}: {} error{} found",
+            self.label,
+            count,
+            if count == 1 { "" } else { "s" }
+        );
+    }
+
+    pub fn skipped(self, reason: &str) {
+        info!(
+            stage = %self.name,
+            status = "skipped",
+            elapsed_ms = self.elapsed_ms(),
+            "{}: OK ({})",
+            self.label,
+            reason
+        );
+    }
+}
@@ -1,32 +1,98 @@
 // src/cli/mod.rs
-use clap::{Parser, ValueEnum};
-use std::path::PathBuf;
+use crate::ast::{AbortPolicy, AnalysisConfig, LintLevel};
+use crate::cfg::BoundCheck;
+use crate::sc_graph::{CycleLimits, Finding, Isolation, DEFAULT_BASELINE_FILE};
+use crate::verification::cache::DEFAULT_CACHE_FILE;
+use crate::verification::invariants::InitialState;
+use crate::verification::solver::{
+    Encoding, IntEncoding, SolverKind, StringEncoding, VcGranularity,
+};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
-mod logger;
+mod artifact_cache;
+mod batch;
+mod console;
+mod explore;
+mod init;
+mod logging;
 mod output;
 mod pipeline;
+mod progress;
+mod render;
 mod stages;
+mod timings;
 mod traits;
+mod watch;
 
-pub use logger::*;
+pub use artifact_cache::*;
+pub use console::*;
+pub use explore::*;
+pub use init::{INIT_CONFIG, INIT_WORKLOAD};
+pub use logging::*;
 pub use output::*;
 pub use pipeline::*;
+pub use progress::*;
+pub use render::RenderFormat;
 pub use stages::*;
+pub use timings::*;
 pub use traits::*;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "fmitf")]
 #[command(about = "A chopped transaction serializability verification tool")]
 #[command(version = "0.1.0")]
+#[command(subcommand_negates_reqs = true)]
 pub struct Cli {
-    /// Input source file
+    /// Runs something other than the modes on the inputs
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Input source file, or a directory or glob of them to process each, or
+    /// `-` to read the source from stdin; more than one only in check,
+    /// scgraph and verify modes
+    #[arg(value_name = "INPUT", required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// The first of the inputs
+    #[arg(skip)]
     pub input: PathBuf,
 
-    /// Processing mode - each mode includes all previous stages
-    #[arg(short = 'm', long = "mode", default_value = "verify")]
+    /// The inputs after the first, each processed on its own
+    #[arg(skip)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Another workload file to analyze with the input, as if it imported it (repeatable)
+    #[arg(long = "link", value_name = "FILE")]
+    pub links: Vec<PathBuf>,
+
+    /// Processing mode - each mode includes all previous stages. Several, as
+    /// `-m ast,cfg,scgraph`, share one parse and write each to its file in
+    /// the --output directory
+    #[arg(
+        short = 'm',
+        long = "mode",
+        value_name = "MODE",
+        value_delimiter = ',',
+        default_value = "verify"
+    )]
+    pub modes: Vec<Mode>,
+
+    /// The mode run: the last of --mode, in the order of the stages
+    #[arg(skip = Mode::Verify)]
     pub mode: Mode,
 
-    /// Output file for ast/cfg/scgraph modes, or output directory for verify mode
+    /// Run ast, cfg, optimize, scgraph, stats and verify modes at once, into
+    /// the --output directory
+    #[arg(long = "all", conflicts_with = "modes")]
+    pub all: bool,
+
+    /// Output file for ast/cfg/scgraph modes, output directory for verify mode and
+    /// several modes, or the file fmt mode writes instead of rewriting the input
+    /// (`-` for stdout)
     #[arg(short = 'o', long = "output")]
     pub output: Option<PathBuf>,
 
@@ -34,33 +100,398 @@ pub struct Cli {
     #[arg(long = "output-dir")]
     pub output_dir: Option<PathBuf>,
 
-    /// Verbose output
-    #[arg(short = 'v', long = "verbose")]
-    pub verbose: bool,
+    /// Verbose output; -vv also logs how long each phase took
+    #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// How progress, warnings and errors are written to stderr
+    #[arg(long = "log-format", value_name = "FORMAT", default_value = "text")]
+    pub log_format: LogFormat,
 
     /// Show source spans in output
     #[arg(long = "show-spans")]
     pub show_spans: bool,
 
-    /// Generate DOT output (for cfg and scgraph modes)
+    /// Show stable element IDs (e.g. fn:transfer/hop:1/stmt:3) in AST output
+    #[arg(long = "show-ids")]
+    pub show_ids: bool,
+
+    /// Output format (json for ast, cfg, optimize, scgraph, verify, stats and doc
+    /// modes and --differential; dot for cfg, optimize, scgraph and verify modes;
+    /// html for doc mode; default: text)
+    #[arg(
+        long = "format",
+        value_name = "FORMAT",
+        conflicts_with_all = ["dot", "json", "sarif", "graphml"]
+    )]
+    pub format: Option<OutputFormat>,
+
+    /// Generate DOT output, the same as --format dot
     #[arg(long = "dot")]
     pub dot: bool,
 
+    /// Generate JSON output, the same as --format json
+    #[arg(long = "json", conflicts_with_all = ["dot", "graphml"])]
+    pub json: bool,
+
+    /// Report the C-edges that failed verification (verify mode), or the
+    /// errors and warnings of the program (check mode), as SARIF, for code
+    /// scanning in CI
+    #[arg(long = "sarif", conflicts_with_all = ["dot", "json", "graphml"])]
+    pub sarif: bool,
+
+    /// Generate GraphML output (for cfg and optimize modes)
+    #[arg(long = "graphml", conflicts_with = "dot")]
+    pub graphml: bool,
+
+    /// Write the table declarations as SQL CREATE TABLE statements, for
+    /// PostgreSQL (ast mode only)
+    #[arg(long = "sql", conflicts_with_all = ["dot", "json", "graphml"])]
+    pub sql: bool,
+
+    /// Write Rust skeletons of the transactions: a storage trait per node, an
+    /// async function per hop and one running the hops in order (ast mode only)
+    #[arg(long = "rust", conflicts_with_all = ["dot", "json", "graphml", "sql"])]
+    pub rust: bool,
+
+    /// Write a protobuf service per node with an rpc per hop on it, and a
+    /// coordinator service running the transactions (ast mode only)
+    #[arg(long = "proto", conflicts_with_all = ["dot", "json", "graphml", "sql", "rust"])]
+    pub proto: bool,
+
+    /// Also write the DOT output as an image next to its .dot file, laid out
+    /// by Graphviz's dot
+    #[arg(long = "render", value_name = "FORMAT")]
+    pub render: Option<RenderFormat>,
+
+    /// Report how long each phase took and what it allocated, and the size of
+    /// the arenas of the AST and CFG, to stderr as a table or as JSON
+    #[arg(
+        long = "timings",
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        default_missing_value = "text"
+    )]
+    pub timings: Option<TimingsFormat>,
+
     /// Quiet mode - minimal output
     #[arg(short = 'q', long = "quiet")]
     pub quiet: bool,
 
-    /// Verification timeout in seconds (verify mode only)
-    #[arg(long = "timeout", default_value = "30")]
-    pub timeout: u32,
+    /// Wall-clock seconds the prover may spend on each C-edge (verify mode
+    /// only; default: 30)
+    #[arg(long = "timeout", value_name = "SECONDS")]
+    pub timeout: Option<u32>,
+
+    /// Megabytes of memory the prover may use on each C-edge (verify mode only)
+    #[arg(long = "memory-limit", value_name = "MB")]
+    pub memory_limit: Option<u32>,
+
+    /// Seed of the prover's random choices, to repeat a run exactly (verify
+    /// mode only)
+    #[arg(long = "seed", value_name = "N")]
+    pub seed: Option<u32>,
+
+    /// The prover to check C-edges with (verify mode only; default: boogie)
+    #[arg(long = "solver", value_name = "SOLVER")]
+    pub solver: Option<SolverKind>,
+
+    /// Check C-edges with another SMT solver that reads SMT-LIB2 on stdin,
+    /// such as "yices-smt2" (verify mode only)
+    #[arg(long = "smt-solver", value_name = "COMMAND", conflicts_with = "solver")]
+    pub smt_solver: Option<String>,
+
+    /// The Boogie executable, instead of the one on PATH (verify mode with
+    /// Boogie only)
+    #[arg(long = "boogie-path", value_name = "FILE")]
+    pub boogie_path: Option<PathBuf>,
+
+    /// Keep prover answers in this file and reuse them for C-edges whose
+    /// functions did not change (verify mode); in diff mode, list the
+    /// C-edges whose answers no longer apply
+    #[arg(
+        long = "cache",
+        value_name = "FILE",
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_CACHE_FILE
+    )]
+    pub cache: Option<PathBuf>,
+
+    /// Keep the analyzed AST, CFG and SC-graph of each program and the prover
+    /// answers in this directory, and reuse them when a program or its
+    /// C-edges did not change
+    #[arg(
+        long = "cache-dir",
+        value_name = "DIR",
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_CACHE_DIR
+    )]
+    pub cache_dir: Option<PathBuf>,
+
+    /// How many C-edges to verify at once, each with a prover of its own
+    /// (verify mode only)
+    #[arg(long = "jobs", short = 'j', value_name = "N", default_value_t = 1)]
+    pub jobs: usize,
+
+    /// Write a verification condition file per C-edge, or per pair of
+    /// functions for fewer prover runs (verify mode only; default: edge)
+    #[arg(long = "vc-granularity", value_name = "GRANULARITY")]
+    pub vc_granularity: Option<VcGranularity>,
+
+    /// Encode integers as unbounded integers, which provers handle fastest,
+    /// or as 32- or 64-bit bitvectors that wrap on overflow (verify mode
+    /// only; default: int)
+    #[arg(long = "int-encoding", value_name = "ENCODING")]
+    pub int_encoding: Option<IntEncoding>,
+
+    /// Encode strings with the solver's theory of strings, or as values known
+    /// only to be equal or not, which Boogie needs (verify mode only;
+    /// default: theory, or uninterpreted with Boogie)
+    #[arg(long = "string-encoding", value_name = "ENCODING")]
+    pub string_encoding: Option<StringEncoding>,
+
+    /// Infer invariants of the tables, such as bounds and sums of fields, and
+    /// assume them when verifying C-edges (verify mode only)
+    #[arg(long = "infer-invariants")]
+    pub infer_invariants: bool,
+
+    /// Verify from records with any values, or assume the properties declared
+    /// in `// assume(...)` comments and the config without checking them
+    /// (verify mode only; default: havoc)
+    #[arg(long = "initial-state", value_name = "STATE")]
+    pub initial_state: Option<InitialState>,
+
+    /// Keep the condition and proof of each C-edge removed in this directory,
+    /// with a manifest of the prover and its options (verify mode only)
+    #[arg(long = "artifacts", value_name = "DIR")]
+    pub artifacts: Option<PathBuf>,
+
+    /// Check the conditions of the proof artifacts in the input directory
+    /// again with the prover chosen, instead of verifying (verify mode only)
+    #[arg(
+        long = "replay",
+        conflicts_with_all = ["artifacts", "cache", "output", "output_dir", "dot", "json", "sarif", "baseline"]
+    )]
+    pub replay: bool,
+
+    /// Write a test for each C-edge kept on a mixed cycle to this directory,
+    /// with the calls, records and schedule that may reproduce its anomaly, for
+    /// simulate mode's --test (verify mode only)
+    #[arg(long = "test-schedules", value_name = "DIR", conflicts_with = "replay")]
+    pub test_schedules: Option<PathBuf>,
+
+    /// Show each C-edge on stderr as it is decided: a line per edge or a bar
+    /// (verify mode only; default: lines, none with --quiet), or a JSON object
+    /// per event, phases and mixed cycles found included (any mode)
+    #[arg(long = "progress", value_name = "STYLE")]
+    pub progress: Option<ProgressStyle>,
 
     /// Skip optimization passes
     #[arg(long = "no-optimize")]
     pub no_optimize: bool,
 
-    /// Disable colored output
-    #[arg(long = "no-color")]
+    /// Unroll loops so each back edge is taken at most BOUND times, for bounded
+    /// verification of loops without invariants
+    #[arg(long = "unroll", value_name = "BOUND")]
+    pub unroll: Option<usize>,
+
+    /// Whether runs longer than the --unroll bound are ignored or reported (default: assume)
+    #[arg(long = "unroll-check", value_name = "CHECK", requires = "unroll")]
+    pub unroll_check: Option<BoundCheck>,
+
+    /// Print the CFG to stderr after each optimization pass, with its timing
+    #[arg(long = "dump-passes")]
+    pub dump_passes: bool,
+
+    /// Stop looking for mixed cycles once this many are found
+    #[arg(long = "max-cycles", value_name = "N")]
+    pub max_cycles: Option<usize>,
+
+    /// Skip mixed cycles through more than this many hops
+    #[arg(long = "max-cycle-length", value_name = "N")]
+    pub max_cycle_length: Option<usize>,
+
+    /// Check the chopping against serializability, or against snapshot
+    /// isolation, which leaves out cycles that are write skew (scgraph and
+    /// verify modes; default: serializable)
+    #[arg(long = "isolation", value_name = "LEVEL")]
+    pub isolation: Option<Isolation>,
+
+    /// Exit with 1 when mixed cycles remain, after verification in verify and
+    /// report modes (scgraph, verify and report modes)
+    #[arg(long = "fail-on-cycles")]
+    pub fail_on_cycles: bool,
+
+    /// Fail only on mixed cycles this baseline does not accept (scgraph and verify modes)
+    #[arg(
+        long = "baseline",
+        value_name = "FILE",
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_BASELINE_FILE
+    )]
+    pub baseline: Option<PathBuf>,
+
+    /// Write the mixed cycles found to the --baseline file instead of checking them (scgraph mode only)
+    #[arg(long = "update-baseline", requires = "baseline")]
+    pub update_baseline: bool,
+
+    /// TOML file weighting each function, to rank the mixed cycles by (scgraph mode only)
+    #[arg(long = "weights", value_name = "FILE")]
+    pub weights: Option<PathBuf>,
+
+    /// Analyze only this transaction and those it conflicts with; cfg and
+    /// optimize modes show it alone (repeatable)
+    #[arg(long = "function", value_name = "FUNCTION")]
+    pub functions: Vec<String>,
+
+    /// Drop the C-edges between two functions, as "FUNCTION,FUNCTION" (repeatable; scgraph and verify modes)
+    #[arg(long = "ignore-pair", value_name = "PAIR")]
+    pub ignore_pairs: Vec<String>,
+
+    /// Drop the conflicts through a table (repeatable; scgraph and verify modes)
+    #[arg(long = "ignore-table", value_name = "TABLE")]
+    pub ignore_tables: Vec<String>,
+
+    /// Summarize the C-edges between each pair of functions (scgraph mode only)
+    #[arg(long = "pair-report", conflicts_with_all = ["dot", "json"])]
+    pub pair_report: bool,
+
+    /// Summarize the C-edges over each table and field (scgraph mode only)
+    #[arg(long = "hotspot-report", conflicts_with_all = ["dot", "json", "pair_report"])]
+    pub hotspot_report: bool,
+
+    /// List the pairs of hops that may deadlock if each node locked the
+    /// records a hop accesses, with the order they take locks in (scgraph
+    /// mode only)
+    #[arg(
+        long = "deadlock-report",
+        conflicts_with_all = ["dot", "json", "pair_report", "hotspot_report"]
+    )]
+    pub deadlock_report: bool,
+
+    /// Write the chopped workload as a TLA+ specification, with hops as
+    /// atomic steps and a serializability invariant to check with TLC
+    /// (scgraph mode only)
+    #[arg(
+        long = "tla",
+        conflicts_with_all = ["dot", "json", "pair_report", "hotspot_report", "deadlock_report"]
+    )]
+    pub tla: bool,
+
+    /// Write the transactions and their chopping as an Alloy model, to search
+    /// for anomalies and check the C-edges in the Alloy Analyzer (scgraph
+    /// mode only)
+    #[arg(
+        long = "alloy",
+        conflicts_with_all = ["dot", "json", "pair_report", "hotspot_report", "deadlock_report", "tla"]
+    )]
+    pub alloy: bool,
+
+    /// When to color output: `auto` colors it on a terminal unless NO_COLOR
+    /// is set (default: auto)
+    #[arg(long = "color", value_name = "WHEN")]
+    pub color: Option<ColorChoice>,
+
+    /// Disable colored output, the same as --color never
+    #[arg(long = "no-color", conflicts_with = "color")]
     pub no_color: bool,
+
+    /// Print neither colors nor symbols outside ASCII, such as arrows and
+    /// table borders, for logs that scrapers read
+    #[arg(long = "plain")]
+    pub plain: bool,
+
+    /// Transaction to run in simulate mode, as "FUNCTION ARG..." (repeatable, runs in order)
+    #[arg(long = "call", value_name = "CALL")]
+    pub calls: Vec<String>,
+
+    /// Interleave the hops of the --call transactions, as "FUNCTION:HOP ..." (simulate mode only)
+    #[arg(long = "schedule", value_name = "STEPS")]
+    pub schedule: Option<String>,
+
+    /// Run a test that --test-schedules wrote: its records, calls and schedule
+    /// (simulate mode only)
+    #[arg(long = "test", value_name = "FILE", conflicts_with_all = ["calls", "schedule"])]
+    pub test: Option<PathBuf>,
+
+    /// Run the schedule of each mixed cycle on random calls and compare each
+    /// run with the serial runs of its calls, reporting the first that none
+    /// reproduces (simulate mode only)
+    #[arg(
+        long = "differential",
+        value_name = "TRIALS",
+        num_args = 0..=1,
+        default_missing_value = "256",
+        conflicts_with_all = ["calls", "schedule", "test"]
+    )]
+    pub differential: Option<usize>,
+
+    /// New version of the input to compare it against; implies diff mode
+    /// unless --mode is given
+    #[arg(long = "compare", visible_alias = "diff", value_name = "FILE")]
+    pub compare: Option<PathBuf>,
+
+    /// Explain one finding, a mixed cycle as cycle:ID or a C-edge as
+    /// edge:f:1-g:2: its conflicts with their source, why pruning kept it and
+    /// what verification must prove; implies scgraph mode unless --mode is
+    /// given. `fmitf explain FILE FINDING` is the same
+    #[arg(long = "explain", value_name = "FINDING")]
+    pub explain: Option<String>,
+
+    /// Enable a feature for `#if FEATURE ... #endif` sections (repeatable)
+    #[arg(short = 'D', long = "define", value_name = "FEATURE")]
+    pub defines: Vec<String>,
+
+    /// Fail if the input is not already formatted instead of rewriting it,
+    /// writing nothing (fmt mode only)
+    #[arg(long = "check")]
+    pub check: bool,
+
+    /// Run again whenever the input or a file it imports is saved, printing
+    /// what changed, e.g. the mixed cycles fixed and introduced (check,
+    /// scgraph and verify modes)
+    #[arg(
+        long = "watch",
+        conflicts_with_all = [
+            "output", "output_dir", "format", "dot", "json", "sarif", "artifacts", "replay",
+            "test_schedules", "progress", "baseline", "pair_report", "hotspot_report",
+            "deadlock_report", "tla", "alloy", "fail_on_cycles",
+            "functions"
+        ]
+    )]
+    pub watch: bool,
+
+    /// Treat warnings as errors, e.g. to keep CI builds warning-free
+    #[arg(long = "deny-warnings")]
+    pub deny_warnings: bool,
+
+    /// Also warn about int field arithmetic that could overflow at runtime
+    #[arg(long = "warn-overflow")]
+    pub warn_overflow: bool,
+
+    /// Which hops may contain a reachable abort (default: first-hop)
+    #[arg(long = "abort-policy", value_name = "POLICY")]
+    pub abort_policy: Option<AbortPolicy>,
+
+    /// TOML file with the abort policy and lint levels to analyze with
+    #[arg(long = "config", value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Drop the warnings of a lint, or of `all` lints (repeatable)
+    #[arg(long = "allow", value_name = "LINT")]
+    pub allow: Vec<String>,
+
+    /// Report a lint as warnings (repeatable)
+    #[arg(long = "warn", value_name = "LINT")]
+    pub warn: Vec<String>,
+
+    /// Report a lint as errors (repeatable)
+    #[arg(long = "deny", value_name = "LINT")]
+    pub deny: Vec<String>,
+
+    /// Place a table on a node when importing a .sql input, as TABLE=NODE (repeatable)
+    #[arg(long = "table-node", value_name = "TABLE=NODE")]
+    pub table_nodes: Vec<String>,
 }
 
 #[derive(ValueEnum, Clone, PartialEq, Debug)]
@@ -73,14 +504,484 @@ pub enum Mode {
     Optimize,
     /// Start interactive runtime with optimized CFG (includes AST + CFG + Optimize stages)
     Runtime,
+    /// Run the --call transactions one after another (includes AST + CFG + Optimize stages)
+    Simulate,
     /// Build Serializability Conflict Graph (includes AST + CFG + Optimize stages)
     Scgraph,
     /// Run verification and pruning (includes all previous stages)
     Verify,
+    /// Reformat source code into canonical TransAct (includes AST stage)
+    Fmt,
+    /// Compare the input with the --diff file: its declarations, C-edges and
+    /// mixed cycles (includes AST + CFG + Optimize + SCGraph stages)
+    Diff,
+    /// Only parse and analyze, printing diagnostics; exits 0 when clean, 1
+    /// with warnings and 2 with errors (AST stage only)
+    Check,
+    /// Write a self-contained HTML report of the chopping, verified if a
+    /// prover is found (includes all previous stages)
+    Report,
+    /// Explore the SC-graph interactively: list cycles, inspect C-edges, drop
+    /// them hypothetically and show hop CFGs (includes AST + CFG + Optimize +
+    /// SCGraph stages)
+    Explore,
+    /// Print workload metrics: transactions, hops, tables per node, cross-node
+    /// data flow, statements and SC-graph density (includes AST + CFG +
+    /// Optimize + SCGraph stages)
+    Stats,
+    /// Document the workload in Markdown, or HTML with --format html: nodes,
+    /// tables with their columns, and transactions with the tables each hop
+    /// reads and writes (includes AST + CFG stages)
+    Doc,
+}
+
+impl Mode {
+    /// The modes that may run together, in the order of their stages
+    pub const ARTIFACTS: [Mode; 6] = [
+        Mode::Ast,
+        Mode::Cfg,
+        Mode::Optimize,
+        Mode::Scgraph,
+        Mode::Stats,
+        Mode::Verify,
+    ];
+
+    /// Where the mode's stages end among [`Mode::ARTIFACTS`], the others last
+    fn stage_order(&self) -> usize {
+        Self::ARTIFACTS
+            .iter()
+            .position(|mode| mode == self)
+            .unwrap_or(Self::ARTIFACTS.len())
+    }
+
+    /// The name --mode takes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Ast => "ast",
+            Mode::Cfg => "cfg",
+            Mode::Optimize => "optimize",
+            Mode::Runtime => "runtime",
+            Mode::Simulate => "simulate",
+            Mode::Scgraph => "scgraph",
+            Mode::Verify => "verify",
+            Mode::Fmt => "fmt",
+            Mode::Diff => "diff",
+            Mode::Check => "check",
+            Mode::Report => "report",
+            Mode::Explore => "explore",
+            Mode::Stats => "stats",
+            Mode::Doc => "doc",
+        }
+    }
+}
+
+/// What a mode writes its result as
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum OutputFormat {
+    /// For people to read
+    #[default]
+    Text,
+    /// For tools, in the schema doc/json.md describes
+    Json,
+    /// A Graphviz graph
+    Dot,
+    /// A web page
+    Html,
+}
+
+/// Commands run instead of the modes
+#[derive(clap::Subcommand, Clone, PartialEq, Eq, Debug)]
+pub enum Command {
+    /// Run the language server on stdin and stdout
+    Lsp {
+        /// Accepted for editors that start language servers with it; the
+        /// server always uses stdin and stdout
+        #[arg(long = "stdio", hide = true)]
+        stdio: bool,
+    },
+
+    /// Write a small project to start from: fmitf init bank
+    Init {
+        /// The directory to create
+        dir: PathBuf,
+    },
+
+    /// Explain one finding, as --explain does: fmitf explain bank.transact cycle:1
+    Explain {
+        /// The source file
+        file: PathBuf,
+
+        /// A mixed cycle as cycle:ID or a C-edge as edge:f:1-g:2
+        finding: String,
+    },
+}
+
+/// When output is colored
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ColorChoice {
+    /// On a terminal, unless NO_COLOR is set or CLICOLOR_FORCE forces it
+    #[default]
+    Auto,
+    Always,
+    Never,
 }
 
 impl Cli {
+    /// Parses the command line, where `old --diff new` implies diff mode
+    pub fn parse_args() -> Self {
+        let matches = Self::command().get_matches();
+        let mut cli = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        let mut paths = std::mem::take(&mut cli.paths).into_iter();
+        cli.input = paths.next().unwrap_or_default();
+        cli.inputs = paths.collect();
+        if let Some(Command::Explain { file, finding }) = &cli.command {
+            if cli.explain.is_none() && cli.input.as_os_str().is_empty() {
+                cli.input = file.clone();
+                cli.explain = Some(finding.clone());
+            }
+        }
+        if cli.all {
+            cli.modes = Mode::ARTIFACTS.to_vec();
+        }
+        cli.modes.sort_by_key(Mode::stage_order);
+        cli.modes.dedup();
+        cli.mode = cli.modes.last().cloned().unwrap_or(Mode::Verify);
+        let default_mode =
+            matches.value_source("modes") == Some(ValueSource::DefaultValue) && !cli.all;
+        if cli.compare.is_some() && default_mode {
+            cli.mode = Mode::Diff;
+        }
+        if cli.explain.is_some() && default_mode {
+            cli.mode = Mode::Scgraph;
+        }
+        cli
+    }
+
+    /// Whether --mode names several modes, run on one parse
+    pub fn several_modes(&self) -> bool {
+        self.modes.len() > 1
+    }
+
+    /// The options of one of several modes, writing to its file in the
+    /// --output directory: `ast.txt`, `cfg.dot`, or `verify/` for the
+    /// directory of verify mode
+    pub fn for_mode(&self, mode: Mode) -> Cli {
+        let file = match self.output_format() {
+            OutputFormat::Text if mode == Mode::Verify => mode.name().to_string(),
+            OutputFormat::Text => format!("{}.txt", mode.name()),
+            OutputFormat::Json => format!("{}.json", mode.name()),
+            OutputFormat::Dot => format!("{}.dot", mode.name()),
+            OutputFormat::Html => format!("{}.html", mode.name()),
+        };
+        let mut cli = self.clone();
+        cli.output = self.output.as_ref().map(|dir| dir.join(file));
+        cli.modes = vec![mode.clone()];
+        cli.mode = mode;
+        cli
+    }
+
+    /// The options of `mode` when it is one of several before the last,
+    /// whose result is written as the stages pass it
+    pub fn earlier_mode(&self, mode: Mode) -> Option<Cli> {
+        (self.several_modes() && self.modes.contains(&mode) && mode != self.mode)
+            .then(|| self.for_mode(mode))
+    }
+
+    /// When output is colored, after --no-color and --plain
+    pub fn color_choice(&self) -> ColorChoice {
+        if self.no_color || self.plain {
+            ColorChoice::Never
+        } else {
+            self.color.unwrap_or_default()
+        }
+    }
+
+    /// Where verify mode writes its Boogie files: --output-dir, or --output
+    /// unless it names the --dot, --json or --sarif file
+    pub fn boogie_output_dir(&self) -> Option<PathBuf> {
+        if self.mode != Mode::Verify {
+            return None;
+        }
+        match (&self.output_dir, &self.output) {
+            (Some(dir), _) => Some(dir.clone()),
+            (None, Some(dir)) if self.output_format() == OutputFormat::Text && !self.sarif => {
+                Some(dir.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// The output format: --format, or --dot or --json, or else text
+    pub fn output_format(&self) -> OutputFormat {
+        match self.format {
+            Some(format) => format,
+            None if self.dot => OutputFormat::Dot,
+            None if self.json => OutputFormat::Json,
+            None => OutputFormat::Text,
+        }
+    }
+
+    /// Whether the mode verifies C-edges: verify mode, and report mode when a
+    /// prover is found
+    pub fn verifies(&self) -> bool {
+        matches!(self.mode, Mode::Verify | Mode::Report)
+    }
+
+    /// Whether C-edges are checked with Boogie rather than an SMT solver
+    pub fn uses_boogie(&self) -> bool {
+        self.smt_solver.is_none() && self.solver.unwrap_or_default() == SolverKind::Boogie
+    }
+
+    /// How verify mode encodes values: --int-encoding, and --string-encoding,
+    /// by default the theory of strings unless Boogie, which has none, checks
+    pub fn encoding(&self) -> Encoding {
+        let strings = match self.string_encoding {
+            Some(strings) => strings,
+            None if self.uses_boogie() => StringEncoding::Uninterpreted,
+            None => StringEncoding::Theory,
+        };
+        Encoding {
+            ints: self.int_encoding.unwrap_or_default(),
+            strings,
+        }
+    }
+
+    /// Whether several files are processed, each on its own: more than one
+    /// input, a directory or a glob
+    pub fn is_batch(&self) -> bool {
+        !self.replay && (!self.inputs.is_empty() || self.input.is_dir() || is_glob(&self.input))
+    }
+
+    /// Whether details are shown: -v or more
+    pub fn is_verbose(&self) -> bool {
+        self.verbose > 0
+    }
+
+    /// Whether the source is read from stdin: `fmitf -`
+    pub fn is_stdin(&self) -> bool {
+        self.input == Path::new("-")
+    }
+
+    /// The input as spans and messages name it: `<stdin>` for `-`
+    pub fn input_name(&self) -> PathBuf {
+        if self.is_stdin() {
+            PathBuf::from("<stdin>")
+        } else {
+            self.input.clone()
+        }
+    }
+
+    /// The source of the input file, or of stdin for `-`
+    pub fn read_input(&self) -> Result<String, String> {
+        let read = if self.is_stdin() {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source).map(|_| source)
+        } else {
+            std::fs::read_to_string(&self.input)
+        };
+        read.map_err(|e| format!("Failed to read file {:?}: {}", self.input_name(), e))
+    }
+
+    /// Whether the input is a SQL script to import rather than TransAct source
+    pub fn is_sql_input(&self) -> bool {
+        self.input
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("sql"))
+    }
+
+    /// Parse the --table-node mappings into table → node
+    pub fn table_node_map(&self) -> Result<HashMap<String, String>, String> {
+        self.table_nodes
+            .iter()
+            .map(|mapping| match mapping.split_once('=') {
+                Some((table, node)) if !table.is_empty() && !node.is_empty() => {
+                    Ok((table.to_string(), node.to_string()))
+                }
+                _ => Err(format!(
+                    "Invalid --table-node '{}', expected TABLE=NODE",
+                    mapping
+                )),
+            })
+            .collect()
+    }
+
+    /// The limits on mixed cycle enumeration given by --max-cycles and
+    /// --max-cycle-length
+    pub fn cycle_limits(&self) -> CycleLimits {
+        CycleLimits {
+            max_cycles: self.max_cycles,
+            max_length: self.max_cycle_length,
+        }
+    }
+
+    /// The analysis settings: the --config file, then the flags, in the order
+    /// --warn-overflow, --deny-warnings, --allow, --warn, --deny; --ignore-pair
+    /// and --ignore-table add to the exclusions of the file
+    pub fn analysis_config(&self) -> Result<AnalysisConfig, String> {
+        let mut config = match &self.config {
+            Some(path) => AnalysisConfig::load(path)?,
+            None => AnalysisConfig::default(),
+        };
+        if let Some(abort_policy) = self.abort_policy {
+            config.abort_policy = abort_policy;
+        }
+        if self.warn_overflow {
+            config.set_level("overflow", LintLevel::Warn)?;
+        }
+        if self.deny_warnings {
+            config.set_level("all", LintLevel::Deny)?;
+        }
+        let flags = [
+            (&self.allow, LintLevel::Allow),
+            (&self.warn, LintLevel::Warn),
+            (&self.deny, LintLevel::Deny),
+        ];
+        for (names, level) in flags {
+            for name in names {
+                config.set_level(name, level)?;
+            }
+        }
+        for pair in &self.ignore_pairs {
+            config.exclusions.add_pair(pair)?;
+        }
+        config
+            .exclusions
+            .ignore_tables
+            .extend(self.ignore_tables.iter().cloned());
+        let limits = &mut config.verification;
+        limits.timeout = self.timeout.or(limits.timeout);
+        limits.memory = self.memory_limit.or(limits.memory);
+        limits.seed = self.seed.or(limits.seed);
+        if let Some(initial_state) = self.initial_state {
+            config.assumptions.initial_state = initial_state;
+        }
+        Ok(config)
+    }
+
+    /// Check that the mode writes the output format
+    fn validate_format(&self) -> Result<(), String> {
+        match self.output_format() {
+            OutputFormat::Text => {}
+            OutputFormat::Json
+                if !(matches!(
+                    self.mode,
+                    Mode::Ast
+                        | Mode::Cfg
+                        | Mode::Optimize
+                        | Mode::Scgraph
+                        | Mode::Verify
+                        | Mode::Stats
+                        | Mode::Doc
+                ) || self.mode == Mode::Simulate && self.differential.is_some()) =>
+            {
+                return Err(
+                    "JSON output is only valid for ast, cfg, optimize, scgraph, verify, stats and doc modes, and simulate mode with --differential"
+                        .to_string(),
+                );
+            }
+            OutputFormat::Dot
+                if !matches!(
+                    self.mode,
+                    Mode::Cfg | Mode::Optimize | Mode::Scgraph | Mode::Verify
+                ) =>
+            {
+                return Err(
+                    "DOT output is only valid for cfg, optimize, scgraph and verify modes"
+                        .to_string(),
+                );
+            }
+            OutputFormat::Html if self.mode != Mode::Doc => {
+                return Err("HTML output is only valid for doc mode".to_string());
+            }
+            OutputFormat::Json | OutputFormat::Dot | OutputFormat::Html => {}
+        }
+        if self.sarif && !matches!(self.mode, Mode::Verify | Mode::Check) {
+            return Err("--sarif is only valid for verify and check modes".to_string());
+        }
+        if self.graphml && !matches!(self.mode, Mode::Cfg | Mode::Optimize) {
+            return Err("--graphml is only valid for cfg and optimize modes".to_string());
+        }
+        if self.sql && self.mode != Mode::Ast {
+            return Err("--sql is only valid for ast mode".to_string());
+        }
+        if self.rust && self.mode != Mode::Ast {
+            return Err("--rust is only valid for ast mode".to_string());
+        }
+        if self.proto && self.mode != Mode::Ast {
+            return Err("--proto is only valid for ast mode".to_string());
+        }
+        Ok(())
+    }
+
     pub fn validate(&self) -> Result<(), String> {
+        let has_inputs = !self.input.as_os_str().is_empty();
+        match &self.command {
+            Some(Command::Lsp { .. }) => {
+                if has_inputs || self.watch || self.output.is_some() {
+                    return Err(
+                        "The language server takes no inputs, --watch or --output".to_string()
+                    );
+                }
+                return Ok(());
+            }
+            Some(Command::Init { .. }) => {
+                if has_inputs {
+                    return Err("init takes only the directory to create".to_string());
+                }
+                return Ok(());
+            }
+            Some(Command::Explain { file, .. }) if *file != self.input => {
+                return Err(
+                    "explain takes its file after it, and no other input or --explain".to_string(),
+                );
+            }
+            _ => {}
+        }
+        if self.is_stdin() {
+            if !self.inputs.is_empty() {
+                return Err(
+                    "- reads one program from stdin, so it takes no other inputs".to_string(),
+                );
+            }
+            if self.watch || self.replay {
+                return Err("--watch and --replay read files, not stdin".to_string());
+            }
+        }
+        if self.timings.is_some() && (self.watch || self.replay) {
+            return Err("--timings times a single run, not --watch or --replay".to_string());
+        }
+
+        if self.several_modes() {
+            if let Some(mode) = self
+                .modes
+                .iter()
+                .find(|mode| !Mode::ARTIFACTS.contains(mode))
+            {
+                return Err(format!(
+                    "{} mode runs on its own; several modes are any of ast, cfg, optimize, scgraph, stats and verify",
+                    mode.name()
+                ));
+            }
+            if self.output.is_none() || self.output_dir.is_some() {
+                return Err(
+                    "Several modes write each to its file in the --output directory, e.g. -m ast,cfg -o out"
+                        .to_string(),
+                );
+            }
+            if self.watch || self.is_batch() || !self.functions.is_empty() || self.explain.is_some()
+            {
+                return Err(
+                    "--watch, --function, --explain and several inputs take a single mode"
+                        .to_string(),
+                );
+            }
+            // The last mode takes every option; the others only write
+            for mode in &self.modes {
+                self.for_mode(mode.clone()).validate_format()?;
+            }
+            return self.for_mode(self.mode.clone()).validate();
+        }
+
         // For verify mode, handle output options
         if self.mode == Mode::Verify {
             if self.output.is_some() && self.output_dir.is_some() {
@@ -96,15 +997,13 @@ impl Cli {
             }
         }
 
-        // DOT output is only valid for CFG, Optimize, SC-Graph, and Verify modes
-        if self.dot
-            && !matches!(
-                self.mode,
-                Mode::Cfg | Mode::Optimize | Mode::Scgraph | Mode::Verify
-            )
+        self.validate_format()?;
+        if self.render.is_some()
+            && (self.output_format() != OutputFormat::Dot
+                || (self.output.is_none() && self.boogie_output_dir().is_none()))
         {
             return Err(
-                "--dot flag is only valid for cfg, optimize, scgraph, and verify modes".to_string(),
+                "--render needs DOT output written to a file, e.g. --dot -o graph.dot".to_string(),
             );
         }
 
@@ -115,39 +1014,396 @@ impl Cli {
             // We'll allow it but it won't have much effect
         }
 
-        // Timeout is only meaningful for verify mode
-        if self.timeout != 30 && self.mode != Mode::Verify {
-            return Err("--timeout is only valid for verify mode".to_string());
+        // Prover settings are only meaningful for modes that verify
+        if (self.timeout.is_some() || self.memory_limit.is_some() || self.seed.is_some())
+            && !self.verifies()
+        {
+            return Err(
+                "--timeout, --memory-limit and --seed are only valid for verify and report modes"
+                    .to_string(),
+            );
+        }
+
+        if self.smt_solver.is_some() && !self.verifies() {
+            return Err("--smt-solver is only valid for verify and report modes".to_string());
+        }
+
+        if self.boogie_path.is_some() && (!self.verifies() || !self.uses_boogie()) {
+            return Err(
+                "--boogie-path is only valid for verify and report modes with Boogie".to_string(),
+            );
+        }
+
+        if self.cache.is_some() && !self.verifies() && self.mode != Mode::Diff {
+            return Err("--cache is only valid for verify, report and diff modes".to_string());
+        }
+
+        if self.jobs == 0 {
+            return Err("--jobs must be at least 1".to_string());
+        }
+
+        if self.jobs != 1 && !self.verifies() {
+            return Err("--jobs is only valid for verify and report modes".to_string());
+        }
+
+        if self.vc_granularity.is_some() && !self.verifies() {
+            return Err("--vc-granularity is only valid for verify and report modes".to_string());
+        }
+
+        if self.int_encoding.is_some() && !self.verifies() {
+            return Err("--int-encoding is only valid for verify and report modes".to_string());
+        }
+
+        if self.string_encoding.is_some() && !self.verifies() {
+            return Err("--string-encoding is only valid for verify and report modes".to_string());
+        }
+
+        if self.string_encoding == Some(StringEncoding::Theory) && self.uses_boogie() {
+            return Err(
+                "Boogie has no theory of strings; use --string-encoding uninterpreted or an SMT solver"
+                    .to_string(),
+            );
+        }
+
+        if self.infer_invariants && !self.verifies() {
+            return Err("--infer-invariants is only valid for verify and report modes".to_string());
+        }
+
+        if self.initial_state.is_some() && !self.verifies() {
+            return Err("--initial-state is only valid for verify and report modes".to_string());
+        }
+
+        if (self.artifacts.is_some() || self.replay) && self.mode != Mode::Verify {
+            return Err("--artifacts and --replay are only valid for verify mode".to_string());
+        }
+
+        if self.test_schedules.is_some() && self.mode != Mode::Verify {
+            return Err("--test-schedules is only valid for verify mode".to_string());
+        }
+
+        let edges_only = matches!(
+            self.progress,
+            Some(ProgressStyle::Lines | ProgressStyle::Bar)
+        );
+        if edges_only && self.mode != Mode::Verify {
+            return Err(
+                "--progress lines and bar are only valid for verify mode; json is valid for any"
+                    .to_string(),
+            );
+        }
+
+        if self.solver.is_some() && !self.verifies() {
+            return Err("--solver is only valid for verify and report modes".to_string());
         }
 
         // Runtime mode doesn't need output files
-        if self.mode == Mode::Runtime {
-            if self.output.is_some() || self.output_dir.is_some() {
+        if self.mode == Mode::Runtime
+            && (self.output.is_some() || self.output_dir.is_some()) {
                 return Err(
                     "Runtime mode doesn't support output files - it's an interactive REPL"
                         .to_string(),
                 );
             }
+
+        if self.mode == Mode::Explore && (self.output.is_some() || self.output_dir.is_some()) {
+            return Err("Explore mode is interactive, so writes no output files".to_string());
+        }
+
+        if self.mode == Mode::Check && (self.output.is_some() || self.output_dir.is_some()) {
+            return Err("Check mode only prints diagnostics, so writes no output files".to_string());
+        }
+
+        if self.mode == Mode::Simulate && self.output.is_some() && self.differential.is_none() {
+            return Err("Simulate mode only writes an output file with --differential".to_string());
+        }
+
+        match (&self.mode, self.calls.is_empty()) {
+            (Mode::Simulate, true) if self.test.is_none() && self.differential.is_none() => {
+                return Err(
+                    "Simulate mode requires at least one --call, --test or --differential"
+                        .to_string(),
+                );
+            }
+            (mode, false) if *mode != Mode::Simulate => {
+                return Err("--call is only valid for simulate mode".to_string());
+            }
+            _ => {}
+        }
+
+        if self.schedule.is_some() && self.mode != Mode::Simulate {
+            return Err("--schedule is only valid for simulate mode".to_string());
+        }
+
+        if self.test.is_some() && self.mode != Mode::Simulate {
+            return Err("--test is only valid for simulate mode".to_string());
+        }
+
+        if self.differential.is_some() && self.mode != Mode::Simulate {
+            return Err("--differential is only valid for simulate mode".to_string());
         }
 
         // No-optimize flag is only meaningful for modes that include optimization
         if self.no_optimize
             && !matches!(
                 self.mode,
-                Mode::Optimize | Mode::Runtime | Mode::Scgraph | Mode::Verify
+                Mode::Optimize
+                    | Mode::Runtime
+                    | Mode::Simulate
+                    | Mode::Scgraph
+                    | Mode::Explore
+                    | Mode::Verify
+                    | Mode::Report
+                    | Mode::Stats
+            )
+        {
+            return Err(
+                "--no-optimize is only valid for optimize, runtime, simulate, scgraph, explore, verify, report and stats modes"
+                    .to_string(),
+            );
+        }
+
+        if self.unroll.is_some()
+            && !matches!(
+                self.mode,
+                Mode::Optimize
+                    | Mode::Runtime
+                    | Mode::Simulate
+                    | Mode::Scgraph
+                    | Mode::Explore
+                    | Mode::Verify
+                    | Mode::Report
             )
         {
             return Err(
-                "--no-optimize is only valid for optimize, runtime, scgraph, and verify modes"
+                "--unroll is only valid for optimize, runtime, simulate, scgraph, explore, verify and report modes"
                     .to_string(),
             );
         }
 
+        if self.dump_passes
+            && !matches!(
+                self.mode,
+                Mode::Optimize
+                    | Mode::Runtime
+                    | Mode::Simulate
+                    | Mode::Scgraph
+                    | Mode::Explore
+                    | Mode::Verify
+                    | Mode::Report
+            )
+        {
+            return Err(
+                "--dump-passes is only valid for optimize, runtime, simulate, scgraph, explore, verify and report modes"
+                    .to_string(),
+            );
+        }
+
+        if let Some(finding) = &self.explain {
+            if self.mode != Mode::Scgraph {
+                return Err("--explain is only valid for scgraph mode".to_string());
+            }
+            let other = [
+                ("--format", self.output_format() != OutputFormat::Text),
+                ("--baseline", self.baseline.is_some()),
+                ("--fail-on-cycles", self.fail_on_cycles),
+                ("--weights", self.weights.is_some()),
+                ("--pair-report", self.pair_report),
+                ("--hotspot-report", self.hotspot_report),
+                ("--deadlock-report", self.deadlock_report),
+                ("--tla", self.tla),
+                ("--alloy", self.alloy),
+            ];
+            if let Some((flag, _)) = other.iter().find(|(_, given)| *given) {
+                return Err(format!(
+                    "--explain prints one finding, so takes no {}",
+                    flag
+                ));
+            }
+            Finding::parse(finding)?;
+        }
+
+        if self.pair_report && self.mode != Mode::Scgraph {
+            return Err("--pair-report is only valid for scgraph mode".to_string());
+        }
+
+        if self.hotspot_report && self.mode != Mode::Scgraph {
+            return Err("--hotspot-report is only valid for scgraph mode".to_string());
+        }
+
+        if self.deadlock_report && self.mode != Mode::Scgraph {
+            return Err("--deadlock-report is only valid for scgraph mode".to_string());
+        }
+
+        if self.tla && self.mode != Mode::Scgraph {
+            return Err("--tla is only valid for scgraph mode".to_string());
+        }
+
+        if self.alloy && self.mode != Mode::Scgraph {
+            return Err("--alloy is only valid for scgraph mode".to_string());
+        }
+
+        if (self.max_cycles.is_some() || self.max_cycle_length.is_some())
+            && !matches!(
+                self.mode,
+                Mode::Scgraph | Mode::Explore | Mode::Verify | Mode::Report | Mode::Diff
+            )
+        {
+            return Err(
+                "--max-cycles and --max-cycle-length are only valid for scgraph, explore, verify, report and diff modes"
+                    .to_string(),
+            );
+        }
+
+        if self.isolation.is_some()
+            && !matches!(
+                self.mode,
+                Mode::Scgraph | Mode::Explore | Mode::Verify | Mode::Report
+            )
+        {
+            return Err(
+                "--isolation is only valid for scgraph, explore, verify and report modes"
+                    .to_string(),
+            );
+        }
+
+        if self.baseline.is_some() && !matches!(self.mode, Mode::Scgraph | Mode::Verify) {
+            return Err("--baseline is only valid for scgraph and verify modes".to_string());
+        }
+
+        if (!self.ignore_pairs.is_empty() || !self.ignore_tables.is_empty())
+            && !matches!(
+                self.mode,
+                Mode::Scgraph | Mode::Explore | Mode::Verify | Mode::Report | Mode::Stats
+            )
+        {
+            return Err(
+                "--ignore-pair and --ignore-table are only valid for scgraph, explore, verify, report and stats modes"
+                    .to_string(),
+            );
+        }
+
+        if self.weights.is_some() && self.mode != Mode::Scgraph {
+            return Err("--weights is only valid for scgraph mode".to_string());
+        }
+
+        if self.plain && self.color == Some(ColorChoice::Always) {
+            return Err(
+                "--plain prints no colors, so cannot be used with --color always".to_string(),
+            );
+        }
+
+        if self.fail_on_cycles && !matches!(self.mode, Mode::Scgraph | Mode::Verify | Mode::Report)
+        {
+            return Err(
+                "--fail-on-cycles is only valid for scgraph, verify and report modes".to_string(),
+            );
+        }
+
+        if !self.functions.is_empty()
+            && !matches!(
+                self.mode,
+                Mode::Cfg
+                    | Mode::Optimize
+                    | Mode::Scgraph
+                    | Mode::Explore
+                    | Mode::Verify
+                    | Mode::Report
+            )
+        {
+            return Err(
+                "--function is only valid for cfg, optimize, scgraph, explore, verify and report modes"
+                    .to_string(),
+            );
+        }
+
+        if self.update_baseline && self.mode != Mode::Scgraph {
+            return Err("--update-baseline is only valid for scgraph mode".to_string());
+        }
+
+        if self.is_batch() {
+            if !matches!(self.mode, Mode::Check | Mode::Scgraph | Mode::Verify) {
+                return Err(
+                    "Several inputs are only supported in check, scgraph and verify modes"
+                        .to_string(),
+                );
+            }
+            // Each file would write over the others' results
+            let per_file = [
+                ("--output", self.output.is_some()),
+                ("--output-dir", self.output_dir.is_some()),
+                ("--format", self.output_format() != OutputFormat::Text),
+                ("--sarif", self.sarif),
+                ("--watch", self.watch),
+                ("--cache", self.cache.is_some()),
+                ("--artifacts", self.artifacts.is_some()),
+                ("--test-schedules", self.test_schedules.is_some()),
+                ("--progress", self.progress.is_some()),
+                ("--baseline", self.baseline.is_some()),
+                ("--function", !self.functions.is_empty()),
+                ("--weights", self.weights.is_some()),
+                ("--pair-report", self.pair_report),
+                ("--hotspot-report", self.hotspot_report),
+                ("--deadlock-report", self.deadlock_report),
+                ("--tla", self.tla),
+                ("--alloy", self.alloy),
+                ("--timings", self.timings.is_some()),
+            ];
+            if let Some((flag, _)) = per_file.iter().find(|(_, given)| *given) {
+                return Err(format!("{} is not supported with several inputs", flag));
+            }
+        }
+
+        if self.watch && !matches!(self.mode, Mode::Check | Mode::Scgraph | Mode::Verify) {
+            return Err("--watch is only valid for check, scgraph and verify modes".to_string());
+        }
+
+        if self.check && self.mode != Mode::Fmt {
+            return Err("--check is only valid for fmt mode".to_string());
+        }
+
+        if self.check && self.output.is_some() {
+            return Err("--check writes nothing, so it takes no --output".to_string());
+        }
+
+        match (&self.mode, &self.compare) {
+            (Mode::Diff, None) => {
+                return Err("Diff mode requires --diff <FILE>".to_string());
+            }
+            (mode, Some(_)) if *mode != Mode::Diff => {
+                return Err("--diff is only valid for diff mode".to_string());
+            }
+            _ => {}
+        }
+
+        if self.is_sql_input() {
+            if self.table_nodes.is_empty() {
+                return Err("SQL input requires --table-node TABLE=NODE mappings".to_string());
+            }
+            if self.check {
+                return Err("--check is not supported for SQL input".to_string());
+            }
+            if self.watch {
+                return Err("--watch is not supported for SQL input".to_string());
+            }
+            self.table_node_map()?;
+        } else if !self.table_nodes.is_empty() {
+            return Err("--table-node is only valid for .sql input".to_string());
+        }
+
+        if !self.links.is_empty() && matches!(self.mode, Mode::Fmt | Mode::Diff) {
+            return Err("--link is not valid for fmt and diff modes".to_string());
+        }
+
         // Quiet and verbose are mutually exclusive
-        if self.quiet && self.verbose {
+        if self.quiet && self.is_verbose() {
             return Err("Cannot use both --quiet and --verbose flags".to_string());
         }
 
         Ok(())
     }
 }
+
+/// Whether a path has the wildcards of a glob, `*` or `?`
+pub fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?'])
+}
@@ -1,8 +1,11 @@
 // src/cli/output.rs
 use super::{Cli, DirectoryOutput, FileOutput};
+use crate::ast::errors::render_diagnostic;
+use crate::ast::Diagnostic;
 use std::fs;
 use std::io::{stdout, BufWriter, Write};
 use std::path::PathBuf;
+use tracing::info;
 
 pub struct OutputManager;
 
@@ -10,13 +13,12 @@ impl OutputManager {
     /// Get a writer for file output
     pub fn get_file_writer(
         output_path: &Option<PathBuf>,
-        logger: &super::Logger,
     ) -> Result<BufWriter<Box<dyn Write>>, String> {
         match output_path {
             Some(path) => {
                 let file = fs::File::create(path)
                     .map_err(|e| format!("Failed to create file {:?}: {}", path, e))?;
-                logger.file_output(path);
+                info!(path = %path.display(), "Writing {}", path.display());
                 Ok(BufWriter::new(Box::new(file)))
             }
             None => Ok(BufWriter::new(Box::new(stdout()))),
@@ -28,12 +30,14 @@ impl OutputManager {
     where
         S: FileOutput<Data = T>,
     {
-        let logger = super::Logger::new(cli.verbose, cli.quiet);
-        let mut writer = Self::get_file_writer(&cli.output, &logger)?;
+        let mut writer = Self::get_file_writer(&cli.output)?;
         stage.write_output(data, &mut writer, cli)?;
         writer
             .flush()
             .map_err(|e| format!("Failed to flush output: {}", e))?;
+        if let (Some(format), Some(path)) = (cli.render, &cli.output) {
+            format.render(path)?;
+        }
         Ok(())
     }
 
@@ -52,28 +56,21 @@ impl OutputManager {
     }
 }
 
+/// A count with its noun, in the plural unless it is 1: `1 error`, `2 errors`
+pub(crate) fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        n => format!("{} {}s", n, noun),
+    }
+}
+
 /// Error handling utilities
 pub fn print_spanned_error(spanned_error: &crate::AstSpannedError, source_code: &str) {
-    use colored::*;
+    print_diagnostic(&Diagnostic::from(spanned_error.clone()), source_code);
+}
 
-    if let Some(span_value) = &spanned_error.span {
-        eprintln!(
-            "{}: {} at line {}, column {}",
-            spanned_error.error.error_type().red().bold(),
-            spanned_error.error.message(),
-            span_value.line.to_string().red(),
-            span_value.column.to_string().red()
-        );
-        if let Some(line_content) = source_code.lines().nth(span_value.line.saturating_sub(1)) {
-            eprintln!("   |");
-            eprintln!("{} | {}", span_value.line.to_string().red(), line_content);
-            eprintln!("   | {}{}", " ".repeat(span_value.column), "^".red().bold());
-        }
-    } else {
-        eprintln!(
-            "{}: {}",
-            spanned_error.error.error_type().red().bold(),
-            spanned_error.error.message()
-        );
-    }
+/// Prints a diagnostic and its notes to stderr; errors are red, warnings
+/// yellow and notes cyan.
+pub fn print_diagnostic(diagnostic: &Diagnostic, source_code: &str) {
+    let _ = render_diagnostic(&mut std::io::stderr().lock(), diagnostic, source_code, true);
 }
@@ -1,30 +1,154 @@
 // src/cli/pipeline.rs
-use super::{output::*, stages::*, traits::*, Cli, Logger, Mode, StageContext};
+use super::{
+    emit_event, explore::*, memory_json, output::*, print_memory, print_timings, stages::*,
+    timings_json, traits::*, ArtifactCache, Cli, Console, Mode, OutputFormat, Phase, PhaseClock,
+    ProgressStyle, Stage, TimingsFormat,
+};
+use crate::ast::diff::diff_programs;
+use crate::ast::{Diagnostic, Severity};
+use crate::lsp::Server;
+use crate::memory::MemoryStats;
+use crate::passes::registered_passes;
+use crate::pretty::print_explanation;
+use crate::pretty::sarif::diagnostics_log;
+use crate::sc_graph::{Finding, GraphDelta, SCGraph};
+use crate::sql::import_sql;
+use crate::verification::artifacts::replay;
+use crate::verification::cache::VerificationCache;
+use crate::verification::ledger::ledger;
+use crate::verification::report::{function_summaries, VerificationReport};
+use crate::verification::smt::format_model;
+use crate::verification::solver::{SolverAnswer, SolverLimits, DEFAULT_TIMEOUT};
+use crate::{AstProgram, CfgBuilder, CfgProgram};
+use serde_json::json;
+use std::io::Write;
+use std::path::Path;
+use tracing::{debug, error, info, warn};
 
 pub struct Pipeline {
     pub ast_stage: AstStage,
+    pub fmt_stage: FmtStage,
     pub cfg_stage: CfgStage,
     pub optimize_stage: OptimizeStage,
     pub scgraph_stage: ScGraphStage,
     pub verification_stage: VerificationStage,
-    pub logger: Logger,
+    /// What earlier runs built (--cache-dir)
+    pub artifacts: Option<ArtifactCache>,
+    pub console: Console,
+    /// The phases run so far, as --timings reports them
+    pub phases: Vec<Phase>,
+    /// The arenas of what the phases built, as --timings reports them
+    pub memory: Vec<(&'static str, MemoryStats)>,
 }
 
 impl Pipeline {
-    pub fn new(cli: &Cli) -> Self {
-        Self {
-            ast_stage: AstStage,
+    pub fn new(cli: &Cli) -> Result<Self, String> {
+        let config = cli.analysis_config()?;
+        let artifacts = cli
+            .cache_dir
+            .as_deref()
+            .map(ArtifactCache::open)
+            .transpose()?;
+        Ok(Self {
+            ast_stage: AstStage {
+                // Source imported from SQL is generated, so it has no file to import relative to
+                input_path: (!cli.is_sql_input()).then(|| cli.input_name()),
+                links: cli.links.clone(),
+                defines: cli.defines.iter().cloned().collect(),
+                config: config.clone(),
+                artifacts: artifacts.clone(),
+                phases: Vec::new(),
+            },
+            fmt_stage: FmtStage,
             cfg_stage: CfgStage,
             optimize_stage: OptimizeStage {
                 skip_optimization: cli.no_optimize,
+                unroll: cli
+                    .unroll
+                    .map(|bound| (bound, cli.unroll_check.unwrap_or_default())),
+                dump_passes: cli.dump_passes,
+                report: Default::default(),
+            },
+            scgraph_stage: ScGraphStage {
+                exclusions: config.exclusions,
+                isolation: cli.isolation.unwrap_or_default(),
+                functions: cli.functions.clone(),
             },
-            scgraph_stage: ScGraphStage,
             verification_stage: VerificationStage {
-                timeout: cli.timeout,
-                boogie_output_dir: cli.output_dir.clone(), // Only use output_dir for Boogie files
+                limits: SolverLimits {
+                    timeout: config.verification.timeout.or(Some(DEFAULT_TIMEOUT)),
+                    ..config.verification
+                },
+                smt_solver: cli.smt_solver.clone(),
+                solver: cli.solver.unwrap_or_default(),
+                boogie_path: cli.boogie_path.clone(),
+                cache: cli
+                    .cache
+                    .clone()
+                    .or_else(|| artifacts.as_ref().map(ArtifactCache::answers_path)),
+                keep_answers: cli.cache.is_none() && artifacts.is_some(),
+                answers: None,
+                jobs: cli.jobs,
+                granularity: cli.vc_granularity.unwrap_or_default(),
+                encoding: cli.encoding(),
+                infer_invariants: cli.infer_invariants,
+                assumptions: config.assumptions,
+                artifacts: cli.artifacts.clone(),
+                progress: cli
+                    .progress
+                    .or((!cli.quiet).then_some(ProgressStyle::Lines)),
+                boogie_output_dir: cli.boogie_output_dir(),
             },
-            logger: Logger::new(cli.verbose, cli.quiet),
-        }
+            artifacts,
+            // Fmt mode rewrites files silently, or prints the source, so keep
+            // summaries out of it
+            console: Console::new(cli.verbose, cli.quiet || cli.mode == Mode::Fmt),
+            phases: Vec::new(),
+            memory: Vec::new(),
+        })
+    }
+
+    /// What the CFG and SC-graph of a program are built with, besides its
+    /// source, for the key of the artifacts kept of them
+    fn graph_settings(&self) -> String {
+        let mut defines: Vec<&String> = self.ast_stage.defines.iter().collect();
+        defines.sort();
+        format!(
+            "defines {:?}\nabort policy {:?}\nskip optimization {}\nunroll {:?}\nexclusions {:?}\nisolation {:?}\nfunctions {:?}",
+            defines,
+            self.ast_stage.config.abort_policy,
+            self.optimize_stage.skip_optimization,
+            self.optimize_stage.unroll,
+            self.scgraph_stage.exclusions,
+            self.scgraph_stage.isolation,
+            self.scgraph_stage.functions
+        )
+    }
+
+    /// Keeps a phase that ended for --timings
+    fn record(&mut self, phase: Phase) {
+        self.phases.push(phase);
+    }
+
+    /// Reports the phases run with --timings, to stderr
+    pub fn report_timings(&self, cli: &Cli) -> Result<(), String> {
+        let mut stderr = std::io::stderr();
+        let written = match cli.timings {
+            None => return Ok(()),
+            Some(TimingsFormat::Text) => print_timings(&self.phases, &mut stderr).and_then(|()| {
+                if self.memory.is_empty() {
+                    return Ok(());
+                }
+                writeln!(stderr)?;
+                print_memory(&self.memory, &mut stderr)
+            }),
+            Some(TimingsFormat::Json) => {
+                let mut json = timings_json(&self.phases);
+                json["memory"] = memory_json(&self.memory);
+                writeln!(stderr, "{}", json)
+            }
+        };
+        written.map_err(|e| format!("Failed to write the timings: {}", e))
     }
 
     /// Calculate the total number of stages for a given mode
@@ -34,144 +158,703 @@ impl Pipeline {
             Mode::Cfg => 2,
             Mode::Optimize => 3,
             Mode::Runtime => 3, // AST + CFG + Optimize
+            Mode::Simulate => 3,
             Mode::Scgraph => 4, // AST + CFG + Optimize + SCGraph
             Mode::Verify => 5,  // AST + CFG + Optimize + SCGraph + Verification
+            Mode::Fmt => 1,
+            Mode::Diff => 2, // AST + comparing SC-graphs
+            Mode::Check => 1,
+            Mode::Report => 5,
+            Mode::Explore => 4,
+            Mode::Stats => 4,
+            Mode::Doc => 2,
         }
     }
 
+    /// Run the stages of `target_mode` on the source; returns how many
+    /// findings fail the run, such as mixed cycles with --fail-on-cycles.
+    /// With several modes, each earlier one writes its result as the stages
+    /// pass it, and the last runs as it would alone
     pub fn execute(
         &mut self,
         source_code: String,
         target_mode: Mode,
         cli: &Cli,
-    ) -> Result<(), String> {
-        let ctx = StageContext::new(cli).with_source(&source_code);
+    ) -> Result<usize, String> {
+        let last = cli
+            .several_modes()
+            .then(|| cli.for_mode(target_mode.clone()));
+        let (all, cli) = (cli, last.as_ref().unwrap_or(cli));
+        if let (Some(_), Some(dir)) = (&last, &all.output) {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create directory {:?}: {}", dir, e))?;
+            self.verification_stage.boogie_output_dir = cli.boogie_output_dir();
+        }
+        let source_code = if cli.is_sql_input() {
+            self.import_sql(&source_code, cli)?
+        } else {
+            source_code
+        };
         let total_stages = Self::total_stages_for_mode(&target_mode);
+        let ast_program = self.analyze(&source_code, total_stages)?;
+        if let Some(cli) = all.earlier_mode(Mode::Ast) {
+            OutputManager::handle_file_output(&self.ast_stage, &ast_program, &cli)?;
+        }
 
-        // Stage 1: AST
-        self.logger.stage_start(
-            self.ast_stage.stage_number(),
-            total_stages,
-            "Frontend Analysis",
-        );
-
-        let ast_program = self
-            .ast_stage
-            .execute(source_code.clone())
-            .map_err(|errors| {
-                self.logger.stage_error(errors.len());
-                for error in &errors {
-                    if let Some(source) = ctx.source_code {
-                        print_spanned_error(error, source);
-                    }
-                }
-                self.logger.abort_pipeline();
-                "AST stage failed".to_string()
-            })?;
+        if target_mode == Mode::Ast {
+            OutputManager::handle_file_output(&self.ast_stage, &ast_program, cli)?;
+            return Ok(0);
+        }
 
-        self.logger.stage_success();
+        if target_mode == Mode::Check {
+            return Ok(0);
+        }
 
-        if target_mode == Mode::Ast {
-            return OutputManager::handle_file_output(&self.ast_stage, &ast_program, cli);
+        if target_mode == Mode::Fmt {
+            if cli.check {
+                if let Some(message) = self.fmt_stage.check(&ast_program, &source_code, cli) {
+                    error!("{}", message);
+                    return Ok(1);
+                }
+                return Ok(0);
+            }
+            // Imported SQL is shown rather than written over its .sql file
+            let to_stdout = match cli.output.as_deref() {
+                Some(path) => path == Path::new("-"),
+                None => cli.is_sql_input() || cli.is_stdin(),
+            };
+            if to_stdout {
+                self.fmt_stage
+                    .write_output(&ast_program, &mut std::io::stdout(), cli)?;
+                return Ok(0);
+            }
+            let path = cli.output.as_deref().unwrap_or(&cli.input);
+            self.fmt_stage.write_file(&ast_program, path)?;
+            return Ok(0);
         }
 
-        // Stage 2: CFG
-        self.logger.stage_start(
-            self.cfg_stage.stage_number(),
-            total_stages,
-            "Building Control Flow Graph",
-        );
+        if target_mode == Mode::Diff {
+            let other = self.analyze_compared_file(cli)?;
+            let diff = diff_programs(&ast_program, &other);
 
-        let cfg_program = self.cfg_stage.execute(ast_program).map_err(|e| {
-            self.logger.error(&format!("CFG stage failed: {}", e));
-            e
-        })?;
+            // Stage 2: both SC-graphs, built as scgraph mode would
+            let stage = Stage::start(2, total_stages, "Comparing SC-graphs");
+            let old = self.build_sc_graph(ast_program)?;
+            let new = self.build_sc_graph(other)?;
+            let mut delta =
+                GraphDelta::new((&old.0, &old.1), (&new.0, &new.1), &cli.cycle_limits());
+            let cache = match &cli.cache {
+                Some(path) => Some((path, VerificationCache::load(path)?)),
+                None => None,
+            };
+            if cache.is_some() {
+                delta.compare_answers((&old.0, &old.1), (&new.0, &new.1));
+            }
+            stage.success();
+            if let Some((path, cache)) = cache {
+                debug!(
+                    "{} holds {}",
+                    path.display(),
+                    plural(cache.answers.len(), "answer")
+                );
+            }
+
+            OutputManager::handle_file_output(&DiffStage, &(diff, delta), cli)?;
+            return Ok(0);
+        }
 
-        self.logger.stage_success();
+        if target_mode == Mode::Doc {
+            // Stage 2: the CFG, built beside the program so its comments
+            // still describe the declarations
+            let stage = Stage::start(
+                self.cfg_stage.stage_number(),
+                total_stages,
+                "Building Control Flow Graph",
+            );
+            let clock = PhaseClock::start("cfg");
+            let cfg_program = CfgBuilder::build_from_program(&ast_program)
+                .map(|ctx| ctx.program)
+                .map_err(|e| format!("CFG building failed: {}", e))?;
+            self.record(clock.stop());
+            stage.success();
 
-        if target_mode == Mode::Cfg {
-            return OutputManager::handle_file_output(&self.cfg_stage, &cfg_program, cli);
+            OutputManager::handle_file_output(&DocStage, &(ast_program, cfg_program), cli)?;
+            return Ok(0);
         }
 
-        // Stage 3: Optimization
-        self.logger.stage_start(
-            self.optimize_stage.stage_number(),
-            total_stages,
-            "Optimizing Control Flow Graph",
-        );
+        // Explore mode and --explain quote the source of conflicting statements
+        let sources = match target_mode {
+            Mode::Explore => ast_program.source_files.clone(),
+            Mode::Scgraph if cli.explain.is_some() => ast_program.source_files.clone(),
+            _ => Vec::new(),
+        };
 
-        let optimized_cfg = self.optimize_stage.execute(cfg_program)?;
+        // The CFG and SC-graph of a program built before are read back from
+        // --cache-dir; the modes that stop before the SC-graph build their own
+        let builds_graph = !matches!(
+            target_mode,
+            Mode::Cfg | Mode::Optimize | Mode::Runtime | Mode::Simulate
+        ) && all.earlier_mode(Mode::Cfg).is_none()
+            && all.earlier_mode(Mode::Optimize).is_none();
+        let graph_key = match &self.artifacts {
+            Some(_) if builds_graph => Some(ArtifactCache::graph_key(
+                &ast_program,
+                &self.graph_settings(),
+            )),
+            _ => None,
+        };
+        let cached = match (&self.artifacts, &graph_key) {
+            (Some(cache), Some(key)) => cache.load_graph(key),
+            _ => None,
+        };
 
-        if self.optimize_stage.skip_optimization {
-            self.logger.stage_skipped("skipped");
+        let (cfg_program, mut sc_graph) = if let Some(data) = cached {
+            for (number, name) in [
+                (self.cfg_stage.stage_number(), "Building Control Flow Graph"),
+                (
+                    self.optimize_stage.stage_number(),
+                    "Optimizing Control Flow Graph",
+                ),
+                (
+                    self.scgraph_stage.stage_number(),
+                    "Building Serializability Conflict Graph",
+                ),
+            ] {
+                Stage::start(number, total_stages, name).skipped("cached");
+            }
+            self.memory.push(("optimize", data.0.memory_stats()));
+            data
         } else {
-            self.logger.stage_success();
-        }
+            // Stage 2: CFG
+            let stage = Stage::start(
+                self.cfg_stage.stage_number(),
+                total_stages,
+                "Building Control Flow Graph",
+            );
 
-        if target_mode == Mode::Optimize {
-            return OutputManager::handle_file_output(&self.optimize_stage, &optimized_cfg, cli);
-        }
+            let clock = PhaseClock::start("cfg");
+            let cfg_program = CfgStage::build(&ast_program).map_err(|e| {
+                error!("CFG stage failed: {}", e);
+                e
+            })?;
 
-        // Runtime mode: Start REPL with the optimized CFG
-        if target_mode == Mode::Runtime {
-            self.logger
-                .process_start("interactive runtime with optimized CFG");
-            crate::runtime::start_runtime_repl_with_cfg(optimized_cfg)?;
-            return Ok(());
-        }
+            self.record(clock.stop());
 
-        // Stage 4: SC-Graph
-        self.logger.stage_start(
-            self.scgraph_stage.stage_number(),
-            total_stages,
-            "Building Serializability Conflict Graph",
-        );
+            stage.success();
+            self.memory.push(("cfg", cfg_program.memory_stats()));
+
+            // The SC-graph stage keeps the functions conflicting with them too
+            let cfg_program = match target_mode {
+                Mode::Cfg | Mode::Optimize => only_functions(cfg_program, &cli.functions)?,
+                _ => cfg_program,
+            };
 
-        let (cfg_program, sc_graph) = self.scgraph_stage.execute(optimized_cfg)?;
+            if let Some(cli) = all.earlier_mode(Mode::Cfg) {
+                OutputManager::handle_file_output(&self.cfg_stage, &cfg_program, &cli)?;
+            }
+            if target_mode == Mode::Cfg {
+                OutputManager::handle_file_output(&self.cfg_stage, &cfg_program, cli)?;
+                return Ok(0);
+            }
 
-        self.logger.stage_success();
+            // Stage 3: Optimization
+            let stage = Stage::start(
+                self.optimize_stage.stage_number(),
+                total_stages,
+                "Optimizing Control Flow Graph",
+            );
+
+            let clock = PhaseClock::start("optimize");
+            let optimized_cfg = self.optimize_stage.execute(cfg_program)?;
+
+            self.record(clock.stop());
+            if self.optimize_stage.skip_optimization {
+                stage.skipped("skipped");
+            } else {
+                stage.success();
+            }
+            self.memory.push(("optimize", optimized_cfg.memory_stats()));
+            for run in &self.optimize_stage.report.runs {
+                debug!(
+                    "{}: {:.3} ms, {} function(s) changed",
+                    run.name,
+                    run.duration.as_secs_f64() * 1000.0,
+                    run.changed_functions
+                );
+            }
+
+            if let Some(cli) = all.earlier_mode(Mode::Optimize) {
+                OutputManager::handle_file_output(&self.optimize_stage, &optimized_cfg, &cli)?;
+            }
+            if target_mode == Mode::Optimize {
+                OutputManager::handle_file_output(&self.optimize_stage, &optimized_cfg, cli)?;
+                return Ok(0);
+            }
+
+            // Runtime mode: Start REPL with the optimized CFG
+            if target_mode == Mode::Runtime {
+                info!("Starting interactive runtime with optimized CFG");
+                crate::runtime::start_runtime_repl_with_cfg(optimized_cfg)?;
+                return Ok(0);
+            }
+
+            if target_mode == Mode::Simulate {
+                if let Some(path) = &cli.test {
+                    let text = std::fs::read_to_string(path)
+                        .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+                    let test = serde_json::from_str(&text)
+                        .map_err(|e| format!("Invalid test '{}': {}", path.display(), e))?;
+                    crate::runtime::run_test(optimized_cfg, &test)?;
+                    return Ok(0);
+                }
+                if let Some(trials) = cli.differential {
+                    let options = crate::runtime::DifferentialOptions {
+                        trials,
+                        ..Default::default()
+                    };
+                    let check = crate::runtime::check_chopping(optimized_cfg, &options)?;
+                    OutputManager::handle_file_output(&DifferentialStage, &check, cli)?;
+                    return Ok(match check {
+                        crate::runtime::DifferentialCheck::NoDivergence { .. } => 0,
+                        crate::runtime::DifferentialCheck::Diverged(_) => 1,
+                    });
+                }
+                crate::runtime::run_simulation(optimized_cfg, &cli.calls, cli.schedule.as_deref())?;
+                return Ok(0);
+            }
 
+            // Stage 4: SC-Graph
+            let stage = Stage::start(
+                self.scgraph_stage.stage_number(),
+                total_stages,
+                "Building Serializability Conflict Graph",
+            );
+
+            let clock = PhaseClock::start("scgraph");
+            let (cfg_program, sc_graph) = self.scgraph_stage.execute(optimized_cfg)?;
+            if let (Some(cache), Some(key)) = (&self.artifacts, &graph_key) {
+                cache.save_graph(key, &cfg_program, &sc_graph)?;
+            }
+
+            self.record(clock.stop());
+
+            stage.success();
+            (cfg_program, sc_graph)
+        };
+
+        // Registered analysis passes see the program beside its SC-graph
+        let passes = registered_passes();
+        let findings = if passes.is_empty() {
+            Vec::new()
+        } else {
+            sc_graph.prune_with(&passes, &ast_program, &cfg_program);
+            sc_graph.check_with(&passes, &ast_program, &cfg_program)
+        };
+        drop(ast_program);
+        for finding in &findings {
+            print_diagnostic(finding, &source_code);
+        }
+        if cli.timings.is_some() || cli.progress == Some(ProgressStyle::Json) {
+            // The modes search the cycles as they need them; timed and told on
+            // its own here
+            let clock = PhaseClock::start("cycles");
+            let found = sc_graph.find_mixed_cycles_within(&cli.cycle_limits());
+            self.record(clock.stop());
+            for cycle in &found.cycles {
+                emit_event(json!({
+                    "event": "cycle_found",
+                    "id": sc_graph.cycle_id(cycle, &cfg_program),
+                    "hops": sc_graph.cycle_hops(cycle, &cfg_program),
+                }));
+            }
+        }
+        if !cli.functions.is_empty() {
+            let names: Vec<&str> = cfg_program
+                .root_functions
+                .iter()
+                .map(|&func_id| cfg_program.functions[func_id].name.as_str())
+                .collect();
+            debug!(
+                "Analyzing {} (--function and the functions they conflict with)",
+                names.join(", ")
+            );
+        }
+
+        if let (Mode::Scgraph, Some(finding)) = (&target_mode, &cli.explain) {
+            let finding = Finding::parse(finding)?;
+            let explanation = sc_graph.explain(&finding, &cfg_program, &cli.cycle_limits())?;
+            let mut writer = OutputManager::get_file_writer(&cli.output)?;
+            print_explanation(&explanation, &cfg_program, &sources, &mut writer)
+                .and_then(|_| writer.flush())
+                .map_err(|e| format!("Failed to write the explanation: {}", e))?;
+            return Ok(0);
+        }
+
+        let (cfg_program, sc_graph) = match all.earlier_mode(Mode::Scgraph) {
+            Some(cli) => {
+                let data = (cfg_program, sc_graph);
+                OutputManager::handle_file_output(&self.scgraph_stage, &data, &cli)?;
+                data
+            }
+            None => (cfg_program, sc_graph),
+        };
         if target_mode == Mode::Scgraph {
             // For Scgraph mode, we need to output and return early
             // Get summary without consuming the values
             let summary = format!("SC-Graph generated with {} nodes", sc_graph.nodes.len());
-            self.logger.detail(&summary);
-            return OutputManager::handle_file_output(
-                &self.scgraph_stage,
-                &(cfg_program, sc_graph),
-                cli,
-            );
+            debug!("{}", summary);
+            let data = (cfg_program, sc_graph);
+            OutputManager::handle_file_output(&self.scgraph_stage, &data, cli)?;
+            let mut findings = 0;
+            if let Some(path) = &cli.baseline {
+                findings += check_baseline(
+                    &data.1,
+                    &data.0,
+                    &cli.cycle_limits(),
+                    path,
+                    cli.update_baseline,
+                    &self.console,
+                )?;
+            }
+            if cli.fail_on_cycles {
+                findings += remaining_cycles(&data.1, &cli.cycle_limits());
+            }
+            return Ok(findings);
+        }
+
+        if let Some(cli) = all.earlier_mode(Mode::Stats) {
+            let stats = sc_graph.workload_stats(&cfg_program);
+            OutputManager::handle_file_output(&StatsStage, &stats, &cli)?;
+        }
+        if target_mode == Mode::Stats {
+            let stats = sc_graph.workload_stats(&cfg_program);
+            OutputManager::handle_file_output(&StatsStage, &stats, cli)?;
+            return Ok(0);
+        }
+
+        if target_mode == Mode::Explore {
+            info!("Starting interactive exploration of the SC-graph");
+            let explorer = Explorer::new(cfg_program, sc_graph, sources, cli.cycle_limits());
+            explore(explorer)?;
+            return Ok(0);
+        }
+
+        if target_mode == Mode::Report {
+            return self.report(cfg_program, sc_graph, total_stages, cli);
         }
 
         // Stage 5: Verification
-        self.logger.stage_start(
+        let stage = Stage::start(
             self.verification_stage.stage_number(),
             total_stages,
             "Verification & C-edge Pruning",
         );
 
+        let clock = PhaseClock::start("verify");
         let verification_result = self.verification_stage.execute((cfg_program, sc_graph))?;
-        let (_final_cfg, final_scgraph, results) = &verification_result;
+        let (final_cfg, final_scgraph, results) = &verification_result;
+
+        self.record(clock.stop());
 
-        self.logger.stage_success();
+        stage.success();
+        if let Some(dir) = &cli.artifacts {
+            info!(path = %dir.display(), "Proof artifacts saved to {}", dir.display());
+        }
 
         // Handle verification output based on flags
-        if cli.output_dir.is_some() {
+        if cli.boogie_output_dir().is_some() {
             // Directory output mode - save Boogie files and optionally DOT file
             OutputManager::handle_directory_output(
                 &self.verification_stage,
                 &verification_result,
                 cli,
             )?;
-        } else if cli.dot {
-            // DOT output mode - either to file (if --output specified) or console
+        } else if cli.output_format() != OutputFormat::Text || cli.sarif {
+            // DOT, JSON or SARIF output mode - either to file (if --output specified) or console
             OutputManager::handle_file_output(&self.verification_stage, &verification_result, cli)?;
         }
 
-        // Print detailed results and final state
-        print_verification_results(results, &self.logger);
-        check_final_state(final_scgraph, &self.logger);
+        // Print detailed results and final state, which reports hold
+        if cli.output_format() != OutputFormat::Json && !cli.sarif {
+            print_verification_results(results, final_cfg, &self.console);
+            self.console.postconditions(&results.postconditions);
+            self.console
+                .functions(&function_summaries(results, final_cfg, final_scgraph));
+            self.console
+                .assumptions(&ledger(results, final_cfg, final_scgraph));
+            check_final_state(final_scgraph, &cli.cycle_limits(), &self.console);
+        }
+        if let Some(dir) = &cli.test_schedules {
+            write_test_schedules(
+                results,
+                final_cfg,
+                final_scgraph,
+                &cli.cycle_limits(),
+                dir,
+                &self.console,
+            )?;
+        }
+        let mut findings = 0;
+        if let Some(path) = &cli.baseline {
+            findings += check_baseline(
+                final_scgraph,
+                final_cfg,
+                &cli.cycle_limits(),
+                path,
+                false,
+                &self.console,
+            )?;
+        }
+        if cli.fail_on_cycles {
+            findings += remaining_cycles(final_scgraph, &cli.cycle_limits());
+        }
+
+        Ok(findings)
+    }
+
+    /// Report mode: verify the C-edges when a prover is found, then write the
+    /// HTML report; returns the mixed cycles left with --fail-on-cycles
+    fn report(
+        &mut self,
+        cfg_program: CfgProgram,
+        sc_graph: SCGraph,
+        total_stages: usize,
+        cli: &Cli,
+    ) -> Result<usize, String> {
+        let stage = Stage::start(
+            self.verification_stage.stage_number(),
+            total_stages,
+            "Verification & C-edge Pruning",
+        );
+        let data = match self.verification_stage.backend() {
+            Ok(_) => {
+                let clock = PhaseClock::start("verify");
+                let (cfg_program, sc_graph, manager) =
+                    self.verification_stage.execute((cfg_program, sc_graph))?;
+                self.record(clock.stop());
+                stage.success();
+                let report =
+                    VerificationReport::new(&manager, &cfg_program, &sc_graph, &cli.cycle_limits());
+                (cfg_program, sc_graph, Ok(report))
+            }
+            Err(e) => {
+                stage.skipped("skipped");
+                warn!(
+                    "Verification skipped, so the report has none: {}",
+                    e
+                );
+                (cfg_program, sc_graph, Err(e))
+            }
+        };
+        OutputManager::handle_file_output(&ReportStage, &data, cli)?;
+        if !cli.fail_on_cycles {
+            return Ok(0);
+        }
+        Ok(remaining_cycles(&data.1, &cli.cycle_limits()))
+    }
+
+    /// Check the conditions of the proof artifacts in the input directory
+    /// again with the prover chosen; returns how many were not proven
+    pub fn replay(&self, cli: &Cli) -> Result<usize, String> {
+        let mut solver = self.verification_stage.backend()?;
+        let (manifest, answers) = replay(&cli.input, solver.as_mut())?;
+        let results: Vec<_> = answers
+            .iter()
+            .map(|(edge, answer)| {
+                let failure = match answer {
+                    SolverAnswer::Unsat => None,
+                    SolverAnswer::Sat(model) => Some(format!(
+                        "{} found a start state on which the orders end differently: {}",
+                        solver.name(),
+                        format_model(model)
+                    )),
+                    SolverAnswer::Unknown(msg) => Some(msg.clone()),
+                };
+                (edge.hops.join(" -- "), failure)
+            })
+            .collect();
+        self.console.replay_results(&results);
+        let failed = results
+            .iter()
+            .filter(|(_, failure)| failure.is_some())
+            .count();
+        let recorded = match &manifest.version {
+            Some(version) => format!("{} ({})", manifest.prover, version),
+            None => manifest.prover.clone(),
+        };
+        if failed > 0 {
+            error!(
+                "{} of {} conditions proven by {} were not proven again",
+                failed,
+                results.len(),
+                recorded
+            );
+            return Ok(failed);
+        }
+        self.console.success(&format!(
+            "{} conditions proven by {} proven again by {}",
+            results.len(),
+            recorded,
+            solver.name()
+        ));
+        Ok(0)
+    }
+
+    /// Serve the language server protocol on stdin and stdout, analyzing
+    /// documents with the --define, --link and lint options
+    pub fn lsp(&self) -> Result<(), String> {
+        let mut server = Server::new(
+            self.ast_stage.defines.clone(),
+            self.ast_stage.links.clone(),
+            self.ast_stage.config.clone(),
+        );
+        server.run(std::io::stdin().lock(), std::io::stdout().lock())
+    }
 
+    /// Check mode: parse and analyze the input, printing its diagnostics, and
+    /// return how many warnings it has
+    pub fn check(&mut self, source_code: String, cli: &Cli) -> Result<usize, String> {
+        if cli.sarif {
+            return self.check_sarif(source_code, cli);
+        }
+        let source_code = if cli.is_sql_input() {
+            self.import_sql(&source_code, cli)?
+        } else {
+            source_code
+        };
+        let program = self.analyze(&source_code, Self::total_stages_for_mode(&Mode::Check))?;
+        if program.warnings.is_empty() {
+            self.console.success(&format!(
+                "{}: no problems found",
+                cli.input_name().display()
+            ));
+        }
+        Ok(program.warnings.len())
+    }
+
+    /// Check mode with --sarif: the errors and warnings as a SARIF log on
+    /// stdout, in place of the text
+    fn check_sarif(&mut self, source_code: String, cli: &Cli) -> Result<usize, String> {
+        let analyzed = if cli.is_sql_input() {
+            import_sql(&source_code, Some(&cli.input), &cli.table_node_map()?)
+                .and_then(|source| self.ast_stage.execute(source))
+        } else {
+            self.ast_stage.execute(source_code)
+        };
+        let diagnostics: Vec<Diagnostic> = match analyzed {
+            Ok(program) => program.warnings,
+            Err(errors) => errors.into_iter().map(Diagnostic::from).collect(),
+        };
+        let log = serde_json::to_string_pretty(&diagnostics_log(&diagnostics))
+            .map_err(|e| format!("Failed to serialize the SARIF log: {}", e))?;
+        writeln!(std::io::stdout(), "{}", log)
+            .map_err(|e| format!("Failed to write the SARIF log: {}", e))?;
+
+        let errors = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count();
+        match errors {
+            0 => Ok(diagnostics.len()),
+            1 => Err(format!("{}: 1 error", cli.input_name().display())),
+            n => Err(format!("{}: {} errors", cli.input_name().display(), n)),
+        }
+    }
+
+    /// Stage 1: parse and analyze the source, printing its errors and warnings
+    fn analyze(&mut self, source_code: &str, total_stages: usize) -> Result<AstProgram, String> {
+        let stage = Stage::start(
+            self.ast_stage.stage_number(),
+            total_stages,
+            "Frontend Analysis",
+        );
+
+        let ast_program = match self.ast_stage.execute(source_code.to_string()) {
+            Ok(program) => program,
+            Err(errors) => {
+                stage.error(errors.len());
+                for error in &errors {
+                    print_spanned_error(error, source_code);
+                }
+                error!("Aborting pipeline due to errors");
+                return Err("AST stage failed".to_string());
+            }
+        };
+
+        Self::report_warnings(stage, &ast_program, source_code)?;
+        for phase in std::mem::take(&mut self.ast_stage.phases) {
+            self.record(phase);
+        }
+        self.memory.push(("analyze", ast_program.memory_stats()));
+        Ok(ast_program)
+    }
+
+    /// Print the AST stage's warnings; warnings of denied lints fail the stage
+    fn report_warnings(stage: Stage, program: &AstProgram, source: &str) -> Result<(), String> {
+        let warnings: &[Diagnostic] = &program.warnings;
+        if warnings.is_empty() {
+            stage.success();
+            return Ok(());
+        }
+
+        let denied = warnings
+            .iter()
+            .filter(|w| w.severity == Severity::Error)
+            .count();
+        if denied > 0 {
+            stage.error(denied);
+            for warning in warnings {
+                print_diagnostic(warning, source);
+            }
+            error!("Aborting pipeline due to errors");
+            return Err("AST stage failed: warnings of denied lints".to_string());
+        }
+
+        stage.warnings(warnings.len());
+        for warning in warnings {
+            print_diagnostic(warning, source);
+        }
         Ok(())
     }
+
+    /// Build the CFG, optimize it and build its SC-graph, without output
+    fn build_sc_graph(&mut self, ast_program: AstProgram) -> Result<(CfgProgram, SCGraph), String> {
+        let cfg_program = self.cfg_stage.execute(ast_program)?;
+        let optimized_cfg = self.optimize_stage.execute(cfg_program)?;
+        self.scgraph_stage.execute(optimized_cfg)
+    }
+
+    /// Parse and analyze the file given with --diff, with the same settings as the input
+    fn analyze_compared_file(&self, cli: &Cli) -> Result<AstProgram, String> {
+        let path = cli.compare.as_ref().ok_or("Diff mode requires --diff <FILE>")?;
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read file {:?}: {}", path, e))?;
+
+        let mut stage = AstStage {
+            input_path: Some(path.clone()),
+            links: Vec::new(),
+            defines: self.ast_stage.defines.clone(),
+            config: self.ast_stage.config.clone(),
+            artifacts: self.ast_stage.artifacts.clone(),
+            phases: Vec::new(),
+        };
+        stage.execute(source.clone()).map_err(|errors| {
+            for error in &errors {
+                print_spanned_error(error, &source);
+            }
+            format!("Failed to analyze {}", path.display())
+        })
+    }
+
+    /// Convert a SQL input into TransAct source using the --table-node mappings
+    fn import_sql(&self, source: &str, cli: &Cli) -> Result<String, String> {
+        let table_nodes = cli.table_node_map()?;
+        import_sql(source, Some(&cli.input), &table_nodes).map_err(|errors| {
+            error!(errors = errors.len(), "SQL import failed");
+            for error in &errors {
+                print_spanned_error(error, source);
+            }
+            format!("Failed to import {}", cli.input.display())
+        })
+    }
 }
@@ -0,0 +1,135 @@
+// src/cli/progress.rs
+//! Progress of runs on stderr, so reports on stdout stay intact: the C-edges
+//! of verify runs as they are decided, and with `--progress json` in any mode
+//! the phases as they start and finish and the mixed cycles found.
+
+use crate::pretty::arrow;
+use crate::verification::progress::ProgressEvent;
+use clap::ValueEnum;
+use colored::*;
+use serde_json::Value;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether phases and cycles are told as JSON events (--progress json);
+/// process-wide, as phases start deep in the stages
+static JSON_EVENTS: AtomicBool = AtomicBool::new(false);
+
+/// Tell phases and cycles as JSON events on stderr
+pub fn set_json_events(on: bool) {
+    JSON_EVENTS.store(on, Ordering::Relaxed);
+}
+
+/// Writes an event as a line of JSON to stderr, if events are told
+pub fn emit_event(event: Value) {
+    if JSON_EVENTS.load(Ordering::Relaxed) {
+        let _ = writeln!(io::stderr().lock(), "{}", event);
+    }
+}
+
+/// How verify mode shows C-edges as they are decided
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProgressStyle {
+    /// A line per C-edge
+    Lines,
+    /// A bar redrawn in place, or lines when stderr is not a terminal
+    Bar,
+    /// A JSON object per event, phases and cycles included (any mode)
+    Json,
+}
+
+pub struct ProgressPrinter {
+    style: ProgressStyle,
+    total: usize,
+    done: usize,
+    verified: usize,
+}
+
+impl ProgressPrinter {
+    pub fn new(style: ProgressStyle) -> Self {
+        let style = match style {
+            ProgressStyle::Bar if !io::stderr().is_terminal() => ProgressStyle::Lines,
+            style => style,
+        };
+        Self {
+            style,
+            total: 0,
+            done: 0,
+            verified: 0,
+        }
+    }
+
+    pub fn event(&mut self, event: &ProgressEvent) {
+        let mut stderr = io::stderr().lock();
+        if self.style == ProgressStyle::Json {
+            let _ = writeln!(stderr, "{}", event.to_json());
+            return;
+        }
+        match event {
+            ProgressEvent::Started { edges } => {
+                self.total = *edges;
+                // Off the line the stage header left open
+                if *edges > 0 {
+                    let _ = writeln!(stderr);
+                }
+            }
+            ProgressEvent::Decided(edge) => {
+                self.done += 1;
+                if edge.status == "verified" {
+                    self.verified += 1;
+                }
+                let status = match edge.status {
+                    "verified" => edge.status.green(),
+                    "failed" => edge.status.red(),
+                    status => status.yellow(),
+                };
+                let _ = match self.style {
+                    ProgressStyle::Bar => write!(
+                        stderr,
+                        "\r{} {}/{} C-edges, {} verified",
+                        bar(self.done, self.total),
+                        self.done,
+                        self.total,
+                        self.verified
+                    ),
+                    _ => writeln!(
+                        stderr,
+                        " - [{}/{}] Edge {}{}{} ({}, {}): {} by {} in {:.2}s",
+                        self.done,
+                        self.total,
+                        edge.source,
+                        arrow(),
+                        edge.target,
+                        edge.hops[0],
+                        edge.hops[1],
+                        status,
+                        edge.by,
+                        edge.elapsed.as_secs_f64()
+                    ),
+                };
+                let _ = stderr.flush();
+            }
+            ProgressEvent::Finished { elapsed } => {
+                if self.total == 0 {
+                    return;
+                }
+                if self.style == ProgressStyle::Bar {
+                    let _ = writeln!(stderr);
+                }
+                let _ = writeln!(
+                    stderr,
+                    " - {} C-edges decided in {:.2}s",
+                    self.total,
+                    elapsed.as_secs_f64()
+                );
+            }
+        }
+    }
+}
+
+/// A bar of 30 cells, `done` of `total` of them filled.
+fn bar(done: usize, total: usize) -> String {
+    const WIDTH: usize = 30;
+    let filled = (done * WIDTH).checked_div(total).unwrap_or(WIDTH);
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(WIDTH - filled))
+}
@@ -0,0 +1,66 @@
+// src/cli/render.rs
+//! `--render`: DOT output laid out as an image next to its `.dot` file, by
+//! Graphviz's `dot` when it is on `PATH`. No layout engine is built in, so
+//! without Graphviz the run fails and says where to get it.
+//!
+//! ```
+//! use FMitF_rs::cli::RenderFormat;
+//! use std::path::Path;
+//!
+//! let image = RenderFormat::Svg.image_path(Path::new("out/graph.dot"));
+//! assert_eq!(image, Path::new("out/graph.svg"));
+//! ```
+
+use crate::verification::toolchain::find_on_path;
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::info;
+
+/// The image format `--render` writes
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderFormat {
+    Svg,
+    Png,
+}
+
+impl RenderFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            RenderFormat::Svg => "svg",
+            RenderFormat::Png => "png",
+        }
+    }
+
+    /// The image of the DOT file at `dot`: the same path with the extension
+    /// of the format
+    pub fn image_path(self, dot: &Path) -> PathBuf {
+        dot.with_extension(self.extension())
+    }
+
+    /// Lays out the DOT file at `dot` and writes its image next to it
+    pub fn render(self, dot: &Path) -> Result<PathBuf, String> {
+        let graphviz = find_on_path("dot").ok_or_else(|| {
+            "--render needs Graphviz's dot on PATH; install Graphviz (https://graphviz.org) \
+             or render the .dot file elsewhere"
+                .to_string()
+        })?;
+        let image = self.image_path(dot);
+        let output = Command::new(graphviz)
+            .arg(format!("-T{}", self.extension()))
+            .arg(dot)
+            .arg("-o")
+            .arg(&image)
+            .output()
+            .map_err(|e| format!("Failed to run dot: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "dot could not render {}: {}",
+                dot.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        info!(path = %image.display(), "Writing {}", image.display());
+        Ok(image)
+    }
+}
@@ -1,32 +1,60 @@
 // src/cli/stages.rs
-use super::{DirectoryOutput, FileOutput, PipelineStage, StageSummary};
+use super::{
+    plural, ArtifactCache, DirectoryOutput, FileOutput, OutputFormat, Phase, PhaseClock, PipelineStage,
+    ProgressPrinter, ProgressStyle, StageSummary,
+};
 use crate::{
-    ast::parse_and_analyze,
-    optimization::CfgOptimizer,
+    ast::{
+        analyze_program_with_types, build_and_resolve, diff::ProgramDiff, load_sources,
+        AnalysisConfig,
+    },
+    cfg::{BoundCheck, FunctionId},
+    optimization::{CfgOptimizer, LoopUnrollingPass, PassManager, PassReport},
+    runtime::{DifferentialCheck, RunEnd, RuntimeValue, TableSnapshot},
     pretty::{
-        print_cfg, print_program_to_writer, print_sc_graph, CfgFormat, CfgPrintOptions,
-        PrintMode as AstPrintMode, PrintOptions as AstPrintOptions, SCGraphFormat,
+        arrow, format_cfg_text, format_program, format_proto, format_rust_skeleton, format_sql_ddl, print_cfg, print_program, print_program_diff, print_graph_delta, print_sc_graph, print_workload_stats, CfgFormat, CfgPrintOptions,
+        doc_printer::{html_docs, markdown_docs, WorkloadDocs},
+        html_report::html_report, PrintMode as AstPrintMode, PrintOptions as AstPrintOptions, SCGraphFormat,
         SCGraphPrintOptions,
     },
-    sc_graph::SCGraph,
-    verification::{VerificationManager, VerificationResult},
+    sc_graph::{
+        Baseline, ConflictExclusions, CycleLimits, GraphDelta, Isolation, MixedCycles, SCGraph,
+        TransactionWeights, WorkloadStats,
+    },
+    verification::{
+        artifacts::export,
+        cache::VerificationCache,
+        invariants::{declared, Assumptions},
+        output_dir::write_output_dir,
+        report::{verdict, VerificationReport},
+        schedules::test_schedules,
+        solver::{Encoding, SmtBackend, SolverBackend, SolverKind, SolverLimits, VcGranularity},
+        VerificationManager, VerificationResult,
+    },
     AstProgram, AstSpannedError, CfgBuilder, CfgProgram,
 };
+use std::collections::HashSet;
 use std::io::Write;
-use std::path::PathBuf;
-
-// Wrapper functions to handle trait bound conversion
-fn write_ast_program(
-    data: &AstProgram,
-    opts: &AstPrintOptions,
-    writer: &mut dyn Write,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut cursor = std::io::Cursor::new(Vec::new());
-    print_program_to_writer(data, opts, &mut cursor)?;
-    writer.write_all(cursor.get_ref())?;
-    Ok(())
+use std::path::{Path, PathBuf};
+use tracing::{debug, error, info, warn};
+
+/// The CFG output format selected by the flags
+fn cfg_format(cli: &super::Cli) -> CfgFormat {
+    let format = cli.output_format();
+    if format == OutputFormat::Dot {
+        CfgFormat::Dot
+    } else if format == OutputFormat::Json {
+        CfgFormat::Json
+    } else if cli.graphml {
+        CfgFormat::GraphMl
+    } else if cli.is_verbose() {
+        CfgFormat::Text
+    } else {
+        CfgFormat::Summary
+    }
 }
 
+// Wrapper functions to handle trait bound conversion
 fn write_cfg_program(
     data: &CfgProgram,
     opts: &CfgPrintOptions,
@@ -51,7 +79,35 @@ fn write_sc_graph_data(
 }
 
 // AST Stage
-pub struct AstStage;
+pub struct AstStage {
+    /// Path of the root input file, used to resolve `import` statements
+    pub input_path: Option<PathBuf>,
+    /// Files linked with the input, as if it imported them
+    pub links: Vec<PathBuf>,
+    /// Features whose `#if` sections are compiled in
+    pub defines: HashSet<String>,
+    /// Abort policy and lint levels
+    pub config: AnalysisConfig,
+    /// What earlier runs analyzed (--cache-dir)
+    pub artifacts: Option<ArtifactCache>,
+    /// How long loading, building and analysis took in the last run
+    pub phases: Vec<Phase>,
+}
+
+impl AstStage {
+    /// What a program is analyzed with, besides its source, for the key of
+    /// the program kept of it
+    fn settings(&self) -> String {
+        let mut defines: Vec<&String> = self.defines.iter().collect();
+        defines.sort();
+        let mut lints: Vec<_> = self.config.lints.iter().collect();
+        lints.sort_by_key(|(name, _)| *name);
+        format!(
+            "defines {:?}\nabort policy {:?}\nlints {:?}",
+            defines, self.config.abort_policy, lints
+        )
+    }
+}
 
 impl PipelineStage for AstStage {
     type Input = String; // source code
@@ -59,7 +115,37 @@ impl PipelineStage for AstStage {
     type Error = Vec<AstSpannedError>;
 
     fn execute(&mut self, source_code: String) -> Result<Self::Output, Self::Error> {
-        parse_and_analyze(&source_code)
+        self.phases.clear();
+        let clock = PhaseClock::start("parse");
+        let files = load_sources(
+            &source_code,
+            self.input_path.as_deref(),
+            &self.links,
+            &self.defines,
+        )?;
+        self.phases.push(clock.stop());
+        let key = self
+            .artifacts
+            .as_ref()
+            .map(|_| ArtifactCache::key(&files, &self.settings()));
+        if let (Some(cache), Some(key)) = (&self.artifacts, &key) {
+            if let Some(program) = cache.load_program(key) {
+                debug!("Analyzed program read from the cache");
+                return Ok(program);
+            }
+        }
+        let clock = PhaseClock::start("ast");
+        let mut program = build_and_resolve(files)?;
+        self.phases.push(clock.stop());
+        let clock = PhaseClock::start("analyze");
+        analyze_program_with_types(&mut program, &self.config)?;
+        self.phases.push(clock.stop());
+        if let (Some(cache), Some(key)) = (&self.artifacts, &key) {
+            if let Err(e) = cache.save_program(key, &program) {
+                warn!("{}", e);
+            }
+        }
+        Ok(program)
     }
 
     fn name(&self) -> &'static str {
@@ -80,31 +166,310 @@ impl FileOutput for AstStage {
         writer: &mut dyn Write,
         cli: &super::Cli,
     ) -> Result<(), String> {
+        if cli.output_format() == OutputFormat::Json {
+            let json = data
+                .to_json()
+                .map_err(|e| format!("Failed to serialize the AST: {}", e))?;
+            return writeln!(writer, "{}", json)
+                .map_err(|e| format!("Failed to write the AST: {}", e));
+        }
+        if cli.sql {
+            return write!(writer, "{}", format_sql_ddl(data))
+                .map_err(|e| format!("Failed to write the tables: {}", e));
+        }
+        if cli.rust {
+            return write!(writer, "{}", format_rust_skeleton(data))
+                .map_err(|e| format!("Failed to write the skeletons: {}", e));
+        }
+        if cli.proto {
+            return write!(writer, "{}", format_proto(data, &model_module(cli)))
+                .map_err(|e| format!("Failed to write the services: {}", e));
+        }
+
         let opts = AstPrintOptions {
-            mode: if cli.verbose {
+            mode: if cli.is_verbose() {
                 AstPrintMode::Verbose
             } else {
                 AstPrintMode::Summary
             },
             show_spans: cli.show_spans,
+            show_ids: cli.show_ids,
         };
 
-        write_ast_program(data, &opts, writer).map_err(|e| format!("Failed to print AST: {}", e))
+        print_program(data, &opts, writer).map_err(|e| format!("Failed to print AST: {}", e))
+    }
+}
+
+// Fmt Stage
+pub struct FmtStage;
+
+impl FmtStage {
+    /// Compare the canonical formatting with the original source; when they
+    /// differ, names the first line formatting would change
+    pub fn check(&self, data: &AstProgram, source: &str, cli: &super::Cli) -> Option<String> {
+        let formatted = format_program(data);
+        if formatted == source {
+            return None;
+        }
+        let line = source
+            .lines()
+            .zip(formatted.lines())
+            .position(|(old, new)| old != new)
+            .unwrap_or_else(|| source.lines().count().min(formatted.lines().count()));
+        Some(format!(
+            "{} is not formatted (first change at line {})",
+            cli.input_name().display(),
+            line + 1
+        ))
+    }
+
+    /// Write the formatted program to `path`, leaving the file untouched when
+    /// it is formatted already; returns whether it was written
+    pub fn write_file(&self, data: &AstProgram, path: &Path) -> Result<bool, String> {
+        let formatted = format_program(data);
+        if std::fs::read_to_string(path).is_ok_and(|current| current == formatted) {
+            return Ok(false);
+        }
+        std::fs::write(path, formatted)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(true)
+    }
+}
+
+impl FileOutput for FmtStage {
+    type Data = AstProgram;
+
+    fn write_output(
+        &self,
+        data: &Self::Data,
+        writer: &mut dyn Write,
+        _cli: &super::Cli,
+    ) -> Result<(), String> {
+        writer
+            .write_all(format_program(data).as_bytes())
+            .map_err(|e| format!("Failed to write formatted source: {}", e))
+    }
+}
+
+// Diff Stage
+pub struct DiffStage;
+
+impl FileOutput for DiffStage {
+    type Data = (ProgramDiff, GraphDelta);
+
+    fn write_output(
+        &self,
+        data: &Self::Data,
+        writer: &mut dyn Write,
+        _cli: &super::Cli,
+    ) -> Result<(), String> {
+        print_program_diff(&data.0, writer)
+            .and_then(|_| print_graph_delta(&data.1, writer))
+            .map_err(|e| format!("Failed to print diff: {}", e))
+    }
+}
+
+// Stats Stage
+pub struct StatsStage;
+
+impl FileOutput for StatsStage {
+    type Data = WorkloadStats;
+
+    fn write_output(
+        &self,
+        data: &Self::Data,
+        writer: &mut dyn Write,
+        cli: &super::Cli,
+    ) -> Result<(), String> {
+        let written = if cli.output_format() == OutputFormat::Json {
+            serde_json::to_writer_pretty(&mut *writer, data)
+                .map_err(std::io::Error::from)
+                .and_then(|_| writeln!(writer))
+        } else {
+            print_workload_stats(data, writer)
+        };
+        written.map_err(|e| format!("Failed to print stats: {}", e))
+    }
+}
+
+// Differential Stage
+pub struct DifferentialStage;
+
+impl FileOutput for DifferentialStage {
+    type Data = DifferentialCheck;
+
+    fn write_output(
+        &self,
+        data: &Self::Data,
+        writer: &mut dyn Write,
+        cli: &super::Cli,
+    ) -> Result<(), String> {
+        let written = if cli.output_format() == OutputFormat::Json {
+            serde_json::to_writer_pretty(&mut *writer, &differential_json(data))
+                .map_err(std::io::Error::from)
+                .and_then(|_| writeln!(writer))
+        } else {
+            match data {
+                DifferentialCheck::NoDivergence {
+                    trials,
+                    inconclusive,
+                } => writeln!(
+                    writer,
+                    "No divergence from serial runs in {}, {} inconclusive",
+                    plural(*trials, "trial"),
+                    inconclusive
+                ),
+                DifferentialCheck::Diverged(divergence) => {
+                    writeln!(writer, "A chopped run no serial order reproduces:")
+                        .and_then(|_| write!(writer, "{}", divergence))
+                }
+            }
+        };
+        written.map_err(|e| format!("Failed to print the differential check: {}", e))
+    }
+}
+
+/// The trials of a differential check, or its divergence with values as
+/// `--call` takes them
+fn differential_json(check: &DifferentialCheck) -> serde_json::Value {
+    let divergence = match check {
+        DifferentialCheck::NoDivergence {
+            trials,
+            inconclusive,
+        } => {
+            return serde_json::json!({
+                "diverged": false,
+                "trials": trials,
+                "inconclusive": inconclusive,
+            })
+        }
+        DifferentialCheck::Diverged(divergence) => divergence,
+    };
+    let values = |values: &[RuntimeValue]| -> Vec<String> {
+        values.iter().map(ToString::to_string).collect()
+    };
+    let tables = |snapshot: &TableSnapshot| -> Vec<serde_json::Value> {
+        snapshot
+            .iter()
+            .flat_map(|(table, records)| {
+                records.iter().map(move |(key, fields)| {
+                    let fields: serde_json::Map<String, serde_json::Value> = fields
+                        .iter()
+                        .map(|(field, value)| (field.clone(), value.to_string().into()))
+                        .collect();
+                    serde_json::json!({ "table": table, "key": values(key), "fields": fields })
+                })
+            })
+            .collect()
+    };
+    let end = |(outcomes, snapshot): &RunEnd| {
+        let outcomes: Vec<String> = outcomes.iter().map(ToString::to_string).collect();
+        serde_json::json!({ "outcomes": outcomes, "tables": tables(snapshot) })
+    };
+    let calls: Vec<serde_json::Value> = divergence
+        .calls
+        .iter()
+        .map(|(function, args)| serde_json::json!({ "function": function, "args": values(args) }))
+        .collect();
+    let serial: Vec<serde_json::Value> = divergence
+        .serial
+        .iter()
+        .map(|(schedule, run)| {
+            let mut run = end(run);
+            run["order"] = schedule.functions().into();
+            run
+        })
+        .collect();
+    serde_json::json!({
+        "diverged": true,
+        "calls": calls,
+        "schedule": divergence.schedule.to_string(),
+        "initial": tables(&divergence.initial),
+        "chopped": end(&divergence.chopped),
+        "serial": serial,
+    })
+}
+
+// Report Stage
+pub struct ReportStage;
+
+impl FileOutput for ReportStage {
+    /// The SC-graph, pruned if verification ran, and its report or why none ran
+    type Data = (CfgProgram, SCGraph, Result<VerificationReport, String>);
+
+    fn write_output(
+        &self,
+        (cfg_program, sc_graph, verification): &Self::Data,
+        writer: &mut dyn Write,
+        cli: &super::Cli,
+    ) -> Result<(), String> {
+        let title = cli
+            .input_name()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let html = html_report(
+            cfg_program,
+            sc_graph,
+            verification.as_ref().map_err(String::as_str),
+            &cli.cycle_limits(),
+            &title,
+        );
+        writer
+            .write_all(html.as_bytes())
+            .map_err(|e| format!("Failed to write the report: {}", e))
+    }
+}
+
+// Doc Stage
+pub struct DocStage;
+
+impl FileOutput for DocStage {
+    /// The program, for its comments, and its CFG
+    type Data = (AstProgram, CfgProgram);
+
+    fn write_output(
+        &self,
+        (program, cfg_program): &Self::Data,
+        writer: &mut dyn Write,
+        cli: &super::Cli,
+    ) -> Result<(), String> {
+        let title = cli
+            .input_name()
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let docs = WorkloadDocs::new(program, cfg_program, &title);
+        let written = match cli.output_format() {
+            OutputFormat::Json => serde_json::to_writer_pretty(&mut *writer, &docs)
+                .map_err(std::io::Error::from)
+                .and_then(|_| writeln!(writer)),
+            OutputFormat::Html => writer.write_all(html_docs(&docs).as_bytes()),
+            _ => writer.write_all(markdown_docs(&docs).as_bytes()),
+        };
+        written.map_err(|e| format!("Failed to write the documentation: {}", e))
     }
 }
 
 // CFG Stage
 pub struct CfgStage;
 
+impl CfgStage {
+    /// The CFG of a program, leaving the program to the caller
+    pub fn build(ast_program: &AstProgram) -> Result<CfgProgram, String> {
+        CfgBuilder::build_from_program(ast_program)
+            .map(|ctx| ctx.program)
+            .map_err(|e| format!("CFG building failed: {}", e))
+    }
+}
+
 impl PipelineStage for CfgStage {
     type Input = AstProgram;
     type Output = CfgProgram;
     type Error = String;
 
     fn execute(&mut self, ast_program: AstProgram) -> Result<Self::Output, Self::Error> {
-        CfgBuilder::build_from_program(&ast_program)
-            .map(|ctx| ctx.program)
-            .map_err(|e| format!("CFG building failed: {}", e))
+        Self::build(&ast_program)
     }
 
     fn name(&self) -> &'static str {
@@ -126,14 +491,8 @@ impl FileOutput for CfgStage {
         cli: &super::Cli,
     ) -> Result<(), String> {
         let cfg_opts = CfgPrintOptions {
-            format: if cli.dot {
-                CfgFormat::Dot
-            } else if cli.verbose {
-                CfgFormat::Text
-            } else {
-                CfgFormat::Summary
-            },
-            verbose: cli.verbose,
+            format: cfg_format(cli),
+            verbose: cli.is_verbose(),
             quiet: cli.quiet,
             show_spans: cli.show_spans,
         };
@@ -146,6 +505,42 @@ impl FileOutput for CfgStage {
 // Optimization Stage
 pub struct OptimizeStage {
     pub skip_optimization: bool,
+    /// Loop unroll bound and residual check; unrolling runs even without optimization
+    pub unroll: Option<(usize, BoundCheck)>,
+    /// Print the CFG to stderr after each pass
+    pub dump_passes: bool,
+    /// The passes of the last run, with their timing
+    pub report: PassReport,
+}
+
+impl OptimizeStage {
+    fn pass_manager(&self) -> PassManager {
+        let mut manager = PassManager::new();
+        if let Some((bound, check)) = self.unroll {
+            manager = manager.register(Box::new(LoopUnrollingPass::new(bound, check)), &[]);
+        }
+        if !self.skip_optimization {
+            // Unroll first, so the optimizer can fold the copies of each iteration
+            let after: &[&'static str] = if self.unroll.is_some() {
+                &["Loop Unrolling"]
+            } else {
+                &[]
+            };
+            manager = manager.register(Box::new(CfgOptimizer::default_passes()), after);
+        }
+        manager
+    }
+
+    /// Optimizes only the functions given, e.g. the ones rebuilt after an
+    /// edit, whose passes replace the report of the last run
+    pub fn optimize_functions(
+        &mut self,
+        cfg_program: &mut CfgProgram,
+        functions: &HashSet<FunctionId>,
+    ) -> Result<(), String> {
+        self.report = self.pass_manager().run_on(cfg_program, functions)?;
+        Ok(())
+    }
 }
 
 impl PipelineStage for OptimizeStage {
@@ -154,12 +549,24 @@ impl PipelineStage for OptimizeStage {
     type Error = String;
 
     fn execute(&mut self, mut cfg_program: CfgProgram) -> Result<Self::Output, Self::Error> {
-        if self.skip_optimization {
-            return Ok(cfg_program);
-        }
-
-        let optimizer = CfgOptimizer::default_passes();
-        let _opt_result = optimizer.optimize_program(&mut cfg_program);
+        let dump_options = CfgPrintOptions {
+            format: CfgFormat::Text,
+            verbose: true,
+            quiet: false,
+            show_spans: false,
+        };
+        let dump_passes = self.dump_passes;
+        self.report = self
+            .pass_manager()
+            .run_observed(&mut cfg_program, |run, cfg| {
+                if dump_passes {
+                    eprintln!(
+                        "\n=== After {} ({:?}, {} function(s) changed) ===",
+                        run.name, run.duration, run.changed_functions
+                    );
+                    eprintln!("{}", format_cfg_text(cfg, &dump_options));
+                }
+            })?;
 
         Ok(cfg_program)
     }
@@ -183,14 +590,8 @@ impl FileOutput for OptimizeStage {
         cli: &super::Cli,
     ) -> Result<(), String> {
         let cfg_opts = CfgPrintOptions {
-            format: if cli.dot {
-                CfgFormat::Dot
-            } else if cli.verbose {
-                CfgFormat::Text
-            } else {
-                CfgFormat::Summary
-            },
-            verbose: cli.verbose,
+            format: cfg_format(cli),
+            verbose: cli.is_verbose(),
             quiet: cli.quiet,
             show_spans: cli.show_spans,
         };
@@ -201,7 +602,49 @@ impl FileOutput for OptimizeStage {
 }
 
 // SC-Graph Stage
-pub struct ScGraphStage;
+pub struct ScGraphStage {
+    /// Pairs of functions and tables left out of conflict analysis
+    pub exclusions: ConflictExclusions,
+    /// What the mixed cycles are judged against
+    pub isolation: Isolation,
+    /// The functions to analyze with those they conflict with, or all when
+    /// empty (--function)
+    pub functions: Vec<String>,
+}
+
+impl ScGraphStage {
+    /// The SC-graph of the program, with the exclusions and the C-edges
+    /// symbolic execution shows to commute taken out
+    pub fn build(&self, cfg_program: &CfgProgram) -> Result<SCGraph, String> {
+        self.exclusions.check_names(cfg_program)?;
+        Ok(self.build_checked(cfg_program))
+    }
+
+    fn build_checked(&self, cfg_program: &CfgProgram) -> SCGraph {
+        let mut sc_graph = SCGraph::new(cfg_program);
+        sc_graph.isolation = self.isolation;
+        // Excluded edges need no symbolic check
+        sc_graph.exclude(&self.exclusions, cfg_program);
+        sc_graph.eliminate_commuting_edges(cfg_program);
+        sc_graph
+    }
+
+    /// The program with only the --function functions and those they
+    /// conflict with, so that later stages analyze no others
+    fn scope(&self, mut cfg_program: CfgProgram) -> Result<CfgProgram, String> {
+        if self.functions.is_empty() {
+            return Ok(cfg_program);
+        }
+        let selected = named_functions(&cfg_program, &self.functions)?;
+        let scope = SCGraph::conflicting_functions(&cfg_program, &selected, &self.exclusions);
+        let names: HashSet<String> = scope
+            .iter()
+            .map(|&func_id| cfg_program.functions[func_id].name.clone())
+            .collect();
+        cfg_program.retain_functions(|function| names.contains(&function.name));
+        Ok(cfg_program)
+    }
+}
 
 impl PipelineStage for ScGraphStage {
     type Input = CfgProgram;
@@ -209,7 +652,10 @@ impl PipelineStage for ScGraphStage {
     type Error = String;
 
     fn execute(&mut self, cfg_program: CfgProgram) -> Result<Self::Output, Self::Error> {
-        let sc_graph = SCGraph::new(&cfg_program);
+        // Exclusions may name functions out of scope
+        self.exclusions.check_names(&cfg_program)?;
+        let cfg_program = self.scope(cfg_program)?;
+        let sc_graph = self.build_checked(&cfg_program);
         Ok((cfg_program, sc_graph))
     }
 
@@ -232,16 +678,38 @@ impl FileOutput for ScGraphStage {
         cli: &super::Cli,
     ) -> Result<(), String> {
         let (cfg_program, sc_graph) = data;
+        let weights = match &cli.weights {
+            Some(path) => {
+                let weights = TransactionWeights::load(path)?;
+                weights.check_functions(cfg_program)?;
+                Some(weights)
+            }
+            None => None,
+        };
         let sc_opts = SCGraphPrintOptions {
-            format: if cli.dot {
+            format: if cli.output_format() == OutputFormat::Dot {
                 SCGraphFormat::Dot
-            } else if cli.verbose {
+            } else if cli.output_format() == OutputFormat::Json {
+                SCGraphFormat::Json
+            } else if cli.pair_report {
+                SCGraphFormat::PairReport
+            } else if cli.hotspot_report {
+                SCGraphFormat::HotspotReport
+            } else if cli.deadlock_report {
+                SCGraphFormat::DeadlockReport
+            } else if cli.tla {
+                SCGraphFormat::Tla(model_module(cli))
+            } else if cli.alloy {
+                SCGraphFormat::Alloy(model_module(cli))
+            } else if cli.is_verbose() {
                 SCGraphFormat::Text
             } else {
                 SCGraphFormat::Summary
             },
-            verbose: cli.verbose,
+            verbose: cli.is_verbose(),
             show_spans: cli.show_spans,
+            cycle_limits: cli.cycle_limits(),
+            weights,
         };
 
         write_sc_graph_data(sc_graph, cfg_program, &sc_opts, writer)
@@ -249,6 +717,16 @@ impl FileOutput for ScGraphStage {
     }
 }
 
+/// The module of a TLA+ or Alloy model, named as TLC expects after the file
+/// it is written to, or else after the input.
+fn model_module(cli: &super::Cli) -> String {
+    let path = cli.output.as_ref().unwrap_or(&cli.input);
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .filter(|stem| !stem.is_empty())
+        .map_or_else(|| "Workload".to_string(), str::to_string)
+}
+
 impl StageSummary for ScGraphStage {
     type Data = (CfgProgram, SCGraph);
 
@@ -261,31 +739,116 @@ impl StageSummary for ScGraphStage {
 
 // Verification Stage
 pub struct VerificationStage {
-    pub timeout: u32,
+    pub limits: SolverLimits,
+    pub solver: SolverKind,
+    /// The command of an SMT solver to check C-edges with instead
+    pub smt_solver: Option<String>,
+    /// The Boogie executable, if not the one on PATH
+    pub boogie_path: Option<PathBuf>,
+    /// The file prover answers are kept in across runs
+    pub cache: Option<PathBuf>,
+    /// Whether the file keeps the answers a run does not use, as that of a
+    /// --cache-dir, shared by the programs run in it, does
+    pub keep_answers: bool,
+    /// Prover answers kept in memory between runs when no --cache file
+    /// keeps them, as watch mode does
+    pub answers: Option<VerificationCache>,
+    /// How many C-edges are verified at once
+    pub jobs: usize,
+    /// Whether condition files hold a C-edge or a pair of functions
+    pub granularity: VcGranularity,
+    /// How conditions encode integers and strings
+    pub encoding: Encoding,
+    /// Whether invariants of the tables are inferred and assumed
+    pub infer_invariants: bool,
+    /// What records start from, and properties declared of them besides
+    /// those in comments
+    pub assumptions: Assumptions,
+    /// The directory proof artifacts are written to
+    pub artifacts: Option<PathBuf>,
+    /// How C-edges are shown as they are decided, if at all
+    pub progress: Option<ProgressStyle>,
     pub boogie_output_dir: Option<PathBuf>, // Added field to store Boogie output directory
 }
 
-impl PipelineStage for VerificationStage {
-    type Input = (CfgProgram, SCGraph);
-    type Output = (CfgProgram, SCGraph, VerificationManager);
-    type Error = String;
-
-    fn execute(&mut self, input: Self::Input) -> Result<Self::Output, Self::Error> {
-        let (cfg_program, mut sc_graph) = input;
+impl VerificationStage {
+    /// The prover chosen: --smt-solver, or else --solver, with its
+    /// executables found and checked
+    pub fn backend(&self) -> Result<Box<dyn SolverBackend>, String> {
+        match &self.smt_solver {
+            Some(command) => Ok(Box::new(SmtBackend::custom(command, self.limits)?)),
+            None => self
+                .solver
+                .discover(self.limits, self.boogie_path.as_deref()),
+        }
+    }
 
+    /// Verifies the C-edges of the SC-graph, removing the ones proven to
+    /// commute, and returns the manager that ran
+    pub fn verify(
+        &mut self,
+        cfg_program: &CfgProgram,
+        sc_graph: &mut SCGraph,
+    ) -> Result<VerificationManager, String> {
         // Create verification manager using our new verification module
-        let mut verification_manager = VerificationManager::new();
+        let mut verification_manager = VerificationManager::with_solver(self.backend()?);
+        verification_manager.jobs = self.jobs;
+        verification_manager.limits = self.limits;
+        verification_manager.granularity = self.granularity;
+        verification_manager.encoding = self.encoding;
+        verification_manager.infer_invariants = self.infer_invariants;
+        verification_manager.initial_state = self.assumptions.initial_state;
+        verification_manager.assumptions = declared(cfg_program, &self.assumptions.invariants)?;
+        if self.artifacts.is_some() {
+            verification_manager.discharges = Some(Vec::new());
+        }
+        if let Some(style) = self.progress {
+            let mut printer = ProgressPrinter::new(style);
+            verification_manager.progress = Some(Box::new(move |event| printer.event(event)));
+        }
+        let in_memory = self.cache.is_none() && self.answers.is_some();
+        if let Some(path) = &self.cache {
+            let mut cache = VerificationCache::load(path)?;
+            if self.keep_answers {
+                cache.keep_unused();
+            }
+            verification_manager.cache = Some(cache);
+        } else {
+            verification_manager.cache = self.answers.take();
+        }
 
         // Run the commutativity pipeline (this will modify sc_graph by removing successful C-edges)
-        verification_manager.run_commutativity_pipeline(&cfg_program, &mut sc_graph);
+        verification_manager.run_commutativity_pipeline(cfg_program, sc_graph);
+        verification_manager.check_postconditions(cfg_program, sc_graph)?;
 
-        // If Boogie output directory is specified, save the Boogie files
+        if let (Some(path), Some(cache)) = (&self.cache, &verification_manager.cache) {
+            cache.save(path)?;
+        }
+        if in_memory {
+            self.answers = verification_manager.cache.clone();
+        }
+        if let Some(dir) = &self.artifacts {
+            export(&verification_manager, cfg_program, self.limits, dir)?;
+        }
+
+        // With an output directory, lay out what was proven of each C-edge
         if let Some(ref dir) = self.boogie_output_dir {
-            verification_manager
-                .save_boogie_files(dir)
-                .map_err(|e| format!("Failed to save Boogie files: {}", e))?;
+            write_output_dir(&verification_manager, cfg_program, sc_graph, dir)
+                .map_err(|e| format!("Failed to write the verification output: {}", e))?;
         }
 
+        Ok(verification_manager)
+    }
+}
+
+impl PipelineStage for VerificationStage {
+    type Input = (CfgProgram, SCGraph);
+    type Output = (CfgProgram, SCGraph, VerificationManager);
+    type Error = String;
+
+    fn execute(&mut self, input: Self::Input) -> Result<Self::Output, Self::Error> {
+        let (cfg_program, mut sc_graph) = input;
+        let verification_manager = self.verify(&cfg_program, &mut sc_graph)?;
         Ok((cfg_program, sc_graph, verification_manager))
     }
 
@@ -309,29 +872,59 @@ impl FileOutput for VerificationStage {
     ) -> Result<(), String> {
         let (cfg_program, sc_graph, _) = data;
 
-        // Only output DOT file when --dot flag is specified
-        if cli.dot {
+        if let Some(report) = verification_report(data, cli)? {
+            return writeln!(writer, "{}", report)
+                .map_err(|e| format!("Failed to write the report: {}", e));
+        }
+
+        // Only output DOT file when --format dot is specified
+        if cli.output_format() == OutputFormat::Dot {
             let sc_opts = SCGraphPrintOptions {
                 format: SCGraphFormat::Dot,
-                verbose: cli.verbose,
+                verbose: cli.is_verbose(),
                 show_spans: cli.show_spans,
+                cycle_limits: cli.cycle_limits(),
+                weights: None,
             };
 
             write_sc_graph_data(sc_graph, cfg_program, &sc_opts, writer)
                 .map_err(|e| format!("Failed to write SC-Graph DOT: {}", e))
         } else {
-            Err("FileOutput for VerificationStage is only supported with --dot flag".to_string())
+            Err(
+                "FileOutput for VerificationStage needs --format dot, --format json or --sarif"
+                    .to_string(),
+            )
         }
     }
 }
 
+/// The report of a verify run as JSON with --json, or as a SARIF log with
+/// --sarif
+fn verification_report(
+    (cfg_program, sc_graph, manager): &(CfgProgram, SCGraph, VerificationManager),
+    cli: &super::Cli,
+) -> Result<Option<String>, String> {
+    if cli.output_format() != OutputFormat::Json && !cli.sarif {
+        return Ok(None);
+    }
+    let report = VerificationReport::new(manager, cfg_program, sc_graph, &cli.cycle_limits());
+    let json = if cli.sarif {
+        report.to_sarif(cfg_program, sc_graph)
+    } else {
+        report.to_json()
+    };
+    serde_json::to_string_pretty(&json)
+        .map(Some)
+        .map_err(|e| format!("Failed to serialize the report: {}", e))
+}
+
 impl DirectoryOutput for VerificationStage {
     type Data = (CfgProgram, SCGraph, VerificationManager);
 
     fn write_to_directory(
         &self,
         data: &Self::Data,
-        dir: &PathBuf,
+        dir: &Path,
         cli: &super::Cli,
     ) -> Result<(), String> {
         // Ensure the general output directory for this stage exists
@@ -340,13 +933,28 @@ impl DirectoryOutput for VerificationStage {
 
         let (cfg_program, sc_graph, _) = data;
 
-        // If --dot flag is specified with --output-dir, create a.dot in the directory
-        if cli.dot {
+        // With --json or --sarif, the report goes next to the Boogie files
+        if let Some(report) = verification_report(data, cli)? {
+            let name = if cli.sarif {
+                "report.sarif"
+            } else {
+                "report.json"
+            };
+            let report_path = dir.join(name);
+            std::fs::write(&report_path, report)
+                .map_err(|e| format!("Failed to write {:?}: {}", report_path, e))?;
+            info!(path = %report_path.display(), "Writing {}", report_path.display());
+        }
+
+        // With --format dot and --output-dir, create a.dot in the directory
+        if cli.output_format() == OutputFormat::Dot {
             let sc_dot_path = dir.join("a.dot");
             let sc_opts = SCGraphPrintOptions {
                 format: SCGraphFormat::Dot,
-                verbose: cli.verbose,
+                verbose: cli.is_verbose(),
                 show_spans: cli.show_spans,
+                cycle_limits: cli.cycle_limits(),
+                weights: None,
             };
 
             let mut file = std::fs::File::create(&sc_dot_path).map_err(|e| {
@@ -359,16 +967,18 @@ impl DirectoryOutput for VerificationStage {
             write_sc_graph_data(sc_graph, cfg_program, &sc_opts, &mut file)
                 .map_err(|e| format!("Failed to write SC-Graph DOT file: {}", e))?;
 
-            let logger = super::Logger::new(cli.verbose, cli.quiet);
-            logger.file_output(&sc_dot_path);
+            info!(path = %sc_dot_path.display(), "Writing {}", sc_dot_path.display());
+            if let Some(format) = cli.render {
+                format.render(&sc_dot_path)?;
+            }
         }
 
-        // Only output Boogie files when --output-dir is specified (not --output)
-        if cli.output_dir.is_some() {
-            if let Some(boogie_dir) = &self.boogie_output_dir {
-                let logger = super::Logger::new(cli.verbose, cli.quiet);
-                logger.boogie_files_saved(boogie_dir);
-            }
+        if let Some(boogie_dir) = &self.boogie_output_dir {
+            info!(
+                path = %boogie_dir.display(),
+                "Verification output saved to {}",
+                boogie_dir.display()
+            );
         }
 
         Ok(())
@@ -381,19 +991,31 @@ impl StageSummary for VerificationStage {
     fn get_summary(&self, data: &Self::Data) -> String {
         let (_, _, manager) = data;
         let results = &manager.results;
-        format!(
+        let summary = format!(
             "{} C-edges processed, {} successful",
             results.len(),
             results
                 .values()
                 .filter(|r| matches!(r, VerificationResult::Success))
                 .count()
-        )
+        );
+        match &manager.cache {
+            Some(_) => format!(
+                "{}, {} reused from the cache",
+                summary,
+                manager.reused.len()
+            ),
+            None => summary,
+        }
     }
 }
 
 /// Print detailed verification results
-pub fn print_verification_results(manager: &VerificationManager, logger: &super::Logger) {
+pub fn print_verification_results(
+    manager: &VerificationManager,
+    cfg: &CfgProgram,
+    console: &super::Console,
+) {
     let total = manager.results.len();
     let successful = manager
         .results
@@ -402,36 +1024,101 @@ pub fn print_verification_results(manager: &VerificationManager, logger: &super:
         .count();
 
     if total == 0 {
-        logger.info_positive("No C-edges found for verification.");
+        console.info_positive("No C-edges found for verification.");
         return;
     }
 
     let success_rate = (successful as f64 / total as f64) * 100.0;
-    logger.verification_result(successful, total, success_rate);
+    console.verification_result(successful, total, success_rate);
+    let unknown = manager
+        .results
+        .values()
+        .filter(|r| matches!(r, VerificationResult::Unknown(_)))
+        .count();
+    if unknown > 0 {
+        console.verification_unknown(unknown);
+    }
+    if manager.cache.is_some() {
+        console.verification_cached(manager.reused.len(), total - manager.reused.len());
+    }
+    if manager.infer_invariants {
+        console.verification_invariants(
+            &manager
+                .invariants
+                .iter()
+                .map(|invariant| invariant.format(cfg))
+                .collect::<Vec<_>>(),
+        );
+    }
 
-    // Use the logger's verification_details method for verbose output
-    logger.verification_details(|| {
-        manager
-            .results
-            .iter()
+    // Use the console's verification_details method for verbose output
+    console.verification_details(|| {
+        let mut results: Vec<_> = manager.results.iter().collect();
+        results.sort_by_key(|(edge, _)| (edge.source, edge.target));
+        results
+            .into_iter()
             .map(|(edge, result)| {
-                let edge_info = format!("Edge {}→{}", edge.source.index(), edge.target.index());
-                match result {
-                    crate::verification::execution::VerificationResult::Success => {
-                        (edge_info, true, None)
-                    }
-                    crate::verification::execution::VerificationResult::Failure(msg) => {
-                        (edge_info, false, Some(msg.clone()))
+                let mut edge_info = format!(
+                    "Edge {}{}{}",
+                    edge.source.index(),
+                    arrow(),
+                    edge.target.index()
+                );
+                // Tell verdicts of this run from those of an earlier one
+                if manager.cache.is_some() {
+                    if manager.reused.contains(edge) {
+                        edge_info.push_str(" (reused)");
+                    } else {
+                        edge_info.push_str(" (fresh)");
                     }
                 }
+                (edge_info, result.clone())
             })
             .collect()
     });
 }
 
+/// The functions --function names, failing on a name no function has
+fn named_functions(
+    cfg_program: &CfgProgram,
+    names: &[String],
+) -> Result<HashSet<FunctionId>, String> {
+    names
+        .iter()
+        .map(|name| {
+            cfg_program
+                .root_functions
+                .iter()
+                .copied()
+                .find(|&func_id| &cfg_program.functions[func_id].name == name)
+                .ok_or_else(|| format!("No function named '{}' (--function)", name))
+        })
+        .collect()
+}
+
+/// The program with only the functions --function names, which cfg and
+/// optimize modes show; all of it when none are named
+pub fn only_functions(mut cfg_program: CfgProgram, names: &[String]) -> Result<CfgProgram, String> {
+    if names.is_empty() {
+        return Ok(cfg_program);
+    }
+    named_functions(&cfg_program, names)?;
+    cfg_program.retain_functions(|function| names.contains(&function.name));
+    Ok(cfg_program)
+}
+
 /// Check final state after verification
-pub fn check_final_state(sc_graph: &SCGraph, logger: &super::Logger) {
-    let mixed_cycles = sc_graph.find_mixed_cycles();
+pub fn check_final_state(sc_graph: &SCGraph, limits: &CycleLimits, console: &super::Console) {
+    let MixedCycles {
+        cycles: mixed_cycles,
+        truncated,
+    } = sc_graph.find_mixed_cycles_within(limits);
+    if truncated {
+        warn!(
+            "Stopped looking for mixed cycles after {} (--max-cycles)",
+            mixed_cycles.len()
+        );
+    }
 
     if !mixed_cycles.is_empty() {
         let cycle_strings: Vec<String> = mixed_cycles
@@ -446,8 +1133,110 @@ pub fn check_final_state(sc_graph: &SCGraph, logger: &super::Logger) {
             })
             .collect();
 
-        logger.mixed_cycles_status(mixed_cycles.len(), Some(cycle_strings));
+        console.mixed_cycles_status(mixed_cycles.len(), Some(cycle_strings));
+
+        let plan: Vec<String> = sc_graph
+            .c_edge_hitting_set(&mixed_cycles)
+            .iter()
+            .map(|edge| {
+                format!(
+                    "H{} -- H{}",
+                    sc_graph.nodes[edge.source].cfg_hop_id.index(),
+                    sc_graph.nodes[edge.target].cfg_hop_id.index()
+                )
+            })
+            .collect();
+        console.detail_item(
+            &format!("C-edges breaking every cycle ({})", plan.len()),
+            &plan.join(", "),
+        );
     } else {
-        logger.mixed_cycles_status(0, None);
+        console.mixed_cycles_status(0, None);
+    }
+    console.verdict(
+        &verdict(mixed_cycles.len(), sc_graph.isolation),
+        mixed_cycles.is_empty(),
+    );
+}
+
+/// Write a test for each C-edge kept on a mixed cycle left to `dir`, a JSON
+/// file each that simulate mode runs with --test
+pub fn write_test_schedules(
+    manager: &VerificationManager,
+    cfg_program: &CfgProgram,
+    sc_graph: &SCGraph,
+    limits: &CycleLimits,
+    dir: &Path,
+    console: &super::Console,
+) -> Result<(), String> {
+    let MixedCycles { cycles, .. } = sc_graph.find_mixed_cycles_within(limits);
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Cannot create '{}': {}", dir.display(), e))?;
+    let tests = test_schedules(manager, cfg_program, sc_graph, &cycles);
+    for test in &tests {
+        let path = dir.join(test.file_name());
+        let json = serde_json::to_string_pretty(test).map_err(|e| e.to_string())?;
+        std::fs::write(&path, json)
+            .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))?;
+        info!(path = %path.display(), "Writing {}", path.display());
+    }
+    if tests.is_empty() {
+        console.info_positive("No C-edge kept on a mixed cycle has a test schedule.");
+    }
+    Ok(())
+}
+
+/// Name the mixed cycles that the baseline file does not accept and return
+/// how many there are, or with `update`, write every mixed cycle to it
+pub fn check_baseline(
+    sc_graph: &SCGraph,
+    cfg_program: &CfgProgram,
+    limits: &CycleLimits,
+    path: &Path,
+    update: bool,
+    console: &super::Console,
+) -> Result<usize, String> {
+    let MixedCycles { cycles, .. } = sc_graph.find_mixed_cycles_within(limits);
+    if update {
+        std::fs::write(path, Baseline::write(sc_graph, &cycles, cfg_program))
+            .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))?;
+        info!(path = %path.display(), "Writing {}", path.display());
+        return Ok(0);
+    }
+
+    let baseline = Baseline::load(path)?;
+    let new_cycles = sc_graph.new_cycles(&baseline, &cycles, cfg_program);
+    if new_cycles.is_empty() {
+        console.success(&format!(
+            "All {} mixed cycles are in the baseline",
+            cycles.len()
+        ));
+        return Ok(0);
+    }
+    for (id, cycle) in &new_cycles {
+        error!(
+            "New mixed cycle {}: {}",
+            id,
+            sc_graph.describe_cycle(cycle, cfg_program)
+        );
+    }
+    error!(
+        "{} mixed cycle(s) not in the baseline '{}'",
+        new_cycles.len(),
+        path.display()
+    );
+    Ok(new_cycles.len())
+}
+
+/// The mixed cycles left, for --fail-on-cycles, naming how many there are
+/// when there are any
+pub fn remaining_cycles(sc_graph: &SCGraph, limits: &CycleLimits) -> usize {
+    let count = sc_graph.find_mixed_cycles_within(limits).cycles.len();
+    if count > 0 {
+        error!(
+            "{} left (--fail-on-cycles)",
+            plural(count, "mixed cycle")
+        );
     }
+    count
 }
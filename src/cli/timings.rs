@@ -0,0 +1,223 @@
+// src/cli/timings.rs
+//! `--timings`: how long each phase of a run took and what it allocated, for
+//! tracking performance across versions.
+//!
+//! Allocations are counted by [`CountingAllocator`], which the `fmitf` binary
+//! installs as its global allocator; where it is not installed, as in programs
+//! using this crate, they read as 0. The phases are those of the stages run:
+//! `parse` (reading the sources and their imports), `ast` (building the AST
+//! and resolving names), `analyze`, `cfg`, `optimize`, `scgraph`, `cycles`
+//! (searching the mixed cycles on their own) and `verify`. With
+//! `--progress json`, each phase is also told as it starts and finishes.
+//! After the phases come the arenas of the AST (`analyze`) and of the CFG as
+//! built (`cfg`) and optimized (`optimize`), with [`print_memory`].
+//!
+//! ```
+//! use FMitF_rs::cli::{timings_json, PhaseClock};
+//!
+//! let clock = PhaseClock::start("squares");
+//! let squares: Vec<u64> = (0..1000).map(|n| n * n).collect();
+//! let phase = clock.stop();
+//! assert_eq!(squares.len(), 1000);
+//!
+//! let json = timings_json(&[phase]);
+//! assert_eq!(json["phases"][0]["phase"], "squares");
+//! assert!(json["total_ms"].as_f64().unwrap() >= 0.0);
+//! ```
+
+use super::emit_event;
+use crate::memory::MemoryStats;
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// The system allocator, counting the allocations made and the bytes they
+/// asked for. A reallocation counts as an allocation of its new size.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+fn count(size: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed);
+}
+
+/// How `--timings` reports the phases
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TimingsFormat {
+    /// A table for people to read
+    #[default]
+    Text,
+    /// An object with a record per phase
+    Json,
+}
+
+/// One phase of a run, as measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Phase {
+    pub name: &'static str,
+    /// Wall-clock time
+    pub elapsed: Duration,
+    pub allocations: u64,
+    /// Bytes asked for, whether freed since or not
+    pub allocated_bytes: u64,
+}
+
+/// The start of a phase: its name, the time and the allocations so far, and
+/// the `phase` span it runs in, closed when it stops.
+#[derive(Debug)]
+pub struct PhaseClock {
+    name: &'static str,
+    started: Instant,
+    allocations: u64,
+    allocated_bytes: u64,
+    span: tracing::span::EnteredSpan,
+}
+
+impl PhaseClock {
+    pub fn start(name: &'static str) -> Self {
+        emit_event(json!({ "event": "phase_started", "phase": name }));
+        Self {
+            name,
+            started: Instant::now(),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+            span: tracing::trace_span!("phase", phase = name).entered(),
+        }
+    }
+
+    /// The phase, from the start to now.
+    pub fn stop(self) -> Phase {
+        let phase = Phase {
+            name: self.name,
+            elapsed: self.started.elapsed(),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed) - self.allocations,
+            allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed) - self.allocated_bytes,
+        };
+        emit_event(json!({
+            "event": "phase_finished",
+            "phase": phase.name,
+            "elapsed": phase.elapsed.as_secs_f64(),
+        }));
+        self.span.exit();
+        phase
+    }
+}
+
+fn milliseconds(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
+
+/// The phases and their totals as JSON.
+pub fn timings_json(phases: &[Phase]) -> Value {
+    let records: Vec<Value> = phases
+        .iter()
+        .map(|phase| {
+            json!({
+                "phase": phase.name,
+                "elapsed_ms": milliseconds(phase.elapsed),
+                "allocations": phase.allocations,
+                "allocated_bytes": phase.allocated_bytes,
+            })
+        })
+        .collect();
+    json!({
+        "phases": records,
+        "total_ms": milliseconds(phases.iter().map(|phase| phase.elapsed).sum()),
+        "allocations": phases.iter().map(|phase| phase.allocations).sum::<u64>(),
+        "allocated_bytes": phases.iter().map(|phase| phase.allocated_bytes).sum::<u64>(),
+    })
+}
+
+/// Prints the phases as a table, with their totals last.
+pub fn print_timings(phases: &[Phase], writer: &mut dyn Write) -> std::io::Result<()> {
+    let total = Phase {
+        name: "total",
+        elapsed: phases.iter().map(|phase| phase.elapsed).sum(),
+        allocations: phases.iter().map(|phase| phase.allocations).sum(),
+        allocated_bytes: phases.iter().map(|phase| phase.allocated_bytes).sum(),
+    };
+    writeln!(
+        writer,
+        "{:<10} {:>12} {:>12} {:>14}",
+        "Phase", "Time (ms)", "Allocations", "Allocated (B)"
+    )?;
+    for phase in phases.iter().chain([&total]) {
+        writeln!(
+            writer,
+            "{:<10} {:>12.3} {:>12} {:>14}",
+            phase.name,
+            milliseconds(phase.elapsed),
+            phase.allocations,
+            phase.allocated_bytes
+        )?;
+    }
+    Ok(())
+}
+
+/// The arenas of what the phases built, each under the phase that built it,
+/// as a table of their elements and bytes.
+pub fn print_memory(
+    memory: &[(&'static str, MemoryStats)],
+    writer: &mut dyn Write,
+) -> std::io::Result<()> {
+    writeln!(
+        writer,
+        "{:<20} {:>12} {:>14}",
+        "Arena", "Elements", "Size (B)"
+    )?;
+    for (phase, stats) in memory {
+        for arena in &stats.arenas {
+            writeln!(
+                writer,
+                "{:<20} {:>12} {:>14}",
+                format!("{}.{}", phase, arena.name),
+                arena.count,
+                arena.bytes
+            )?;
+        }
+        writeln!(
+            writer,
+            "{:<20} {:>12} {:>14}",
+            format!("{} total", phase),
+            stats.total_count(),
+            stats.total_bytes()
+        )?;
+    }
+    Ok(())
+}
+
+/// The arenas of what the phases built as JSON, keyed by phase.
+pub fn memory_json(memory: &[(&'static str, MemoryStats)]) -> Value {
+    Value::Object(
+        memory
+            .iter()
+            .map(|(phase, stats)| (phase.to_string(), stats.to_json()))
+            .collect(),
+    )
+}
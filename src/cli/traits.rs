@@ -1,6 +1,6 @@
 // src/cli/traits.rs
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::Path;
 
 /// Core trait for all pipeline stages
 pub trait PipelineStage {
@@ -39,7 +39,7 @@ pub trait DirectoryOutput {
     fn write_to_directory(
         &self,
         data: &Self::Data,
-        dir: &PathBuf,
+        dir: &Path,
         cli: &super::Cli,
     ) -> Result<(), String>;
 }
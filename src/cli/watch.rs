@@ -0,0 +1,422 @@
+// src/cli/watch.rs
+//! Watch mode: the pipeline run again on every save of the input or a file it
+//! imports, printing what changed rather than everything found.
+//!
+//! Files are polled for their modification time. A save is applied to the
+//! program in memory as an edit: when it falls within one function, only that
+//! function is analyzed again, and its CFG, the SC-graph vertices of its hops
+//! and the mixed cycles through them are patched. Any other edit rebuilds them
+//! from the program, and one that does not apply, e.g. to the imports, loads
+//! the program again. Cycles are told apart by their ids, so a run names the
+//! cycles fixed and introduced however the hops are numbered.
+
+use super::{plural, print_diagnostic, print_spanned_error, Cli, Mode, Pipeline, PipelineStage};
+use crate::ast::incremental::{reparse, Reparsed, TextEdit};
+use crate::ast::{FunctionId, Severity};
+use crate::cfg::CfgCtx;
+use crate::sc_graph::{MixedCycles, SCGraph};
+use crate::verification::VerificationResult;
+use crate::{AstProgram, CfgBuilder};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the files are checked for saves
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// The CFG and SC-graph of the program, kept to patch after an edit
+struct Analysis {
+    ctx: CfgCtx,
+    /// The SC-graph before verification
+    sc_graph: SCGraph,
+    cycles: MixedCycles,
+}
+
+/// What a run found, for the next to compare against
+struct Outcome {
+    warnings: usize,
+    /// The mixed cycles left, by id, as the hops they pass
+    cycles: BTreeMap<String, String>,
+    truncated: bool,
+}
+
+#[derive(Default)]
+struct WatchState {
+    /// The program as last analyzed without errors
+    program: Option<AstProgram>,
+    /// Its analysis, unless building it failed
+    analysis: Option<Analysis>,
+    outcome: Option<Outcome>,
+}
+
+/// What the saved files changed in the program.
+enum Edit {
+    /// Only these functions, keeping their names
+    Functions(HashSet<FunctionId>),
+    /// The program was rebuilt from its files in memory
+    Program,
+    /// The edit did not apply, so the program must be loaded again
+    Failed,
+}
+
+impl Pipeline {
+    /// Watch mode: run the pipeline of the mode chosen, then again whenever
+    /// a file of the program is saved, until interrupted
+    pub fn watch(&mut self, source_code: String, cli: &Cli) -> Result<(), String> {
+        if cli.mode == Mode::Verify {
+            // A missing prover would fail every run alike
+            self.verification_stage.backend()?;
+            self.verification_stage.progress = None;
+            self.verification_stage.answers = Some(Default::default());
+        }
+
+        let mut state = WatchState::default();
+        self.watch_run(&mut state, Some(source_code), &[], cli);
+        let mut files = watched_files(&state, cli);
+        let mut stamps = modified(&files);
+        self.console.watch_status(&format!(
+            "Watching {} file(s) for changes; press Ctrl+C to stop",
+            files.len()
+        ));
+
+        loop {
+            std::thread::sleep(POLL_INTERVAL);
+            let now = modified(&files);
+            if now == stamps {
+                continue;
+            }
+            let saved: Vec<PathBuf> = files
+                .iter()
+                .zip(now.iter().zip(&stamps))
+                .filter(|(_, (new, old))| new != old)
+                .map(|(file, _)| file.clone())
+                .collect();
+            stamps = now;
+
+            self.watch_run(&mut state, None, &saved, cli);
+            let now_watched = watched_files(&state, cli);
+            if now_watched != files {
+                files = now_watched;
+                stamps = modified(&files);
+            }
+        }
+    }
+
+    /// Runs the pipeline again after the files given were saved, or from
+    /// `source` the first time, and prints how its findings changed
+    fn watch_run(
+        &mut self,
+        state: &mut WatchState,
+        source: Option<String>,
+        saved: &[PathBuf],
+        cli: &Cli,
+    ) {
+        let start = Instant::now();
+        match self.watch_update(state, source, saved, cli) {
+            Ok(Some((outcome, rebuilt))) => {
+                let took = format!("{}, {} ms", rebuilt, start.elapsed().as_millis());
+                self.print_outcome(state.outcome.as_ref(), &outcome, &took, cli);
+                state.outcome = Some(outcome);
+            }
+            Ok(None) => {}
+            Err(e) => self
+                .console
+                .watch_failure(&format!("{}: {}", cli.input.display(), e)),
+        }
+    }
+
+    /// The outcome of a run and what it rebuilt, or `None` when the saves
+    /// left the program as it was
+    fn watch_update(
+        &mut self,
+        state: &mut WatchState,
+        source: Option<String>,
+        saved: &[PathBuf],
+        cli: &Cli,
+    ) -> Result<Option<(Outcome, String)>, String> {
+        let first = source.is_some();
+        let edit = match (&mut state.program, source) {
+            (Some(program), None) => self.apply_saves(program, saved),
+            (_, source) => {
+                let source = match source {
+                    Some(source) => source,
+                    None => std::fs::read_to_string(&cli.input)
+                        .map_err(|e| format!("Failed to read {}: {}", cli.input.display(), e))?,
+                };
+                self.load(state, source)?
+            }
+        };
+        let edit = match edit {
+            Edit::Failed => {
+                let source = std::fs::read_to_string(&cli.input)
+                    .map_err(|e| format!("Failed to read {}: {}", cli.input.display(), e))?;
+                self.load(state, source)?
+            }
+            Edit::Functions(functions) if functions.is_empty() => return Ok(None),
+            edit => edit,
+        };
+        let program = state.program.as_ref().expect("the program was loaded");
+
+        let denied = program
+            .warnings
+            .iter()
+            .filter(|warning| warning.severity == Severity::Error)
+            .count();
+        if cli.mode == Mode::Check || denied > 0 {
+            // The input is loaded after the files it imports
+            let source = program.source_files.last().map_or("", |file| &file.source);
+            for warning in &program.warnings {
+                print_diagnostic(warning, source);
+            }
+        }
+        if denied > 0 {
+            state.analysis = None;
+            return Err(format!("{} of denied lints", plural(denied, "warning")));
+        }
+        let warnings = program.warnings.len();
+        let mut rebuilt = match (&edit, first) {
+            (_, true) => "program analyzed".to_string(),
+            (Edit::Functions(functions), false) => {
+                let mut names: Vec<&str> = functions
+                    .iter()
+                    .map(|&id| program.functions[id].name.as_str())
+                    .collect();
+                names.sort_unstable();
+                format!("{} re-analyzed", names.join(", "))
+            }
+            _ => "program re-analyzed".to_string(),
+        };
+        if cli.mode == Mode::Check {
+            let outcome = Outcome {
+                warnings,
+                cycles: BTreeMap::new(),
+                truncated: false,
+            };
+            return Ok(Some((outcome, rebuilt)));
+        }
+
+        let limits = cli.cycle_limits();
+        let analysis = match (edit, state.analysis.take()) {
+            (Edit::Functions(functions), Some(mut analysis)) => {
+                CfgBuilder::rebuild_functions(program, &mut analysis.ctx, &functions)
+                    .map_err(|e| format!("CFG building failed: {}", e))?;
+                let changed: HashSet<_> = functions
+                    .iter()
+                    .map(|&id| analysis.ctx.function_map[&program.functions[id].name])
+                    .collect();
+                self.optimize_stage
+                    .optimize_functions(&mut analysis.ctx.program, &changed)?;
+                let new_nodes = analysis.sc_graph.update(&analysis.ctx.program, &changed);
+                analysis.sc_graph.isolation = self.scgraph_stage.isolation;
+                analysis
+                    .sc_graph
+                    .eliminate_commuting_edges(&analysis.ctx.program);
+                analysis.cycles =
+                    analysis
+                        .sc_graph
+                        .update_mixed_cycles(&analysis.cycles, &new_nodes, &limits);
+                analysis
+            }
+            _ => {
+                let mut ctx = CfgBuilder::build_from_program(program)
+                    .map_err(|e| format!("CFG building failed: {}", e))?;
+                let functions = ctx.program.functions.iter().map(|(id, _)| id).collect();
+                self.optimize_stage
+                    .optimize_functions(&mut ctx.program, &functions)?;
+                let sc_graph = self.scgraph_stage.build(&ctx.program)?;
+                let cycles = sc_graph.find_mixed_cycles_within(&limits);
+                Analysis {
+                    ctx,
+                    sc_graph,
+                    cycles,
+                }
+            }
+        };
+
+        let cfg = &analysis.ctx.program;
+        let outcome = if cli.mode == Mode::Verify {
+            let mut sc_graph = analysis.sc_graph.clone();
+            let manager = self.verification_stage.verify(cfg, &mut sc_graph)?;
+            let verified = manager
+                .results
+                .values()
+                .filter(|result| matches!(result, VerificationResult::Success))
+                .count();
+            rebuilt = format!(
+                "{}, {}/{} C-edges verified, {} reused",
+                rebuilt,
+                verified,
+                manager.results.len(),
+                manager.reused.len()
+            );
+            let cycles = sc_graph.find_mixed_cycles_within(&limits);
+            Outcome::new(&sc_graph, cfg, &cycles, warnings)
+        } else {
+            Outcome::new(&analysis.sc_graph, cfg, &analysis.cycles, warnings)
+        };
+        state.analysis = Some(analysis);
+        Ok(Some((outcome, rebuilt)))
+    }
+
+    /// Loads the program from the source of the input, printing its errors
+    fn load(&mut self, state: &mut WatchState, source: String) -> Result<Edit, String> {
+        let program = self.ast_stage.execute(source.clone()).map_err(|errors| {
+            for error in &errors {
+                print_spanned_error(error, &source);
+            }
+            plural(errors.len(), "error")
+        })?;
+        state.program = Some(program);
+        state.analysis = None;
+        Ok(Edit::Program)
+    }
+
+    /// Applies the saved files to the program as edits of their text
+    fn apply_saves(&self, program: &mut AstProgram, saved: &[PathBuf]) -> Edit {
+        let mut functions = HashSet::new();
+        let mut whole = false;
+        for index in 0..program.source_files.len() {
+            let Some(path) = program.source_files[index].path.clone() else {
+                continue;
+            };
+            if !saved.iter().any(|file| file.as_path() == &*path) {
+                continue;
+            }
+            let Ok(source) = std::fs::read_to_string(&path) else {
+                return Edit::Failed;
+            };
+            if source == program.source_files[index].source {
+                continue;
+            }
+            let edit = TextEdit::between(index, &program.source_files[index].source, &source);
+            match reparse(
+                program,
+                &edit,
+                &self.ast_stage.defines,
+                &self.ast_stage.config,
+            ) {
+                // A renamed function is a new one to the CFG
+                Ok(Reparsed::Function { old, new })
+                    if program.functions[old].name == program.functions[new].name =>
+                {
+                    functions.insert(new);
+                }
+                Ok(_) => whole = true,
+                Err(_) => return Edit::Failed,
+            }
+        }
+        if whole {
+            Edit::Program
+        } else {
+            Edit::Functions(functions)
+        }
+    }
+
+    /// Prints a run's outcome as what changed since the last
+    fn print_outcome(&self, last: Option<&Outcome>, outcome: &Outcome, took: &str, cli: &Cli) {
+        let warnings = match last {
+            Some(last) if last.warnings != outcome.warnings => format!(
+                "{} (was {})",
+                plural(outcome.warnings, "warning"),
+                last.warnings
+            ),
+            _ => plural(outcome.warnings, "warning"),
+        };
+        if cli.mode == Mode::Check {
+            let status = match outcome.warnings {
+                0 => "no problems found".to_string(),
+                _ => warnings,
+            };
+            self.console
+                .watch_status(&format!("{}: {} ({})", cli.input.display(), status, took));
+            return;
+        }
+
+        let fixed: Vec<&String> = last.map_or(Vec::new(), |last| {
+            last.cycles
+                .iter()
+                .filter(|(id, _)| !outcome.cycles.contains_key(*id))
+                .map(|(_, cycle)| cycle)
+                .collect()
+        });
+        let introduced: Vec<&String> = outcome
+            .cycles
+            .iter()
+            .filter(|(id, _)| last.is_none_or(|last| !last.cycles.contains_key(*id)))
+            .map(|(_, cycle)| cycle)
+            .collect();
+        let mut status = match last {
+            Some(_) => format!(
+                "{} fixed, {} introduced, {} left",
+                plural(fixed.len(), "cycle"),
+                introduced.len(),
+                outcome.cycles.len()
+            ),
+            None => plural(outcome.cycles.len(), "mixed cycle"),
+        };
+        if outcome.truncated {
+            status.push_str(" (--max-cycles reached)");
+        }
+        if outcome.warnings > 0 {
+            status = format!("{}; {}", status, warnings);
+        }
+        self.console
+            .watch_status(&format!("{}: {} ({})", cli.input.display(), status, took));
+        for cycle in fixed {
+            self.console.watch_cycle(true, cycle);
+        }
+        for cycle in introduced {
+            self.console.watch_cycle(false, cycle);
+        }
+    }
+}
+
+impl Outcome {
+    fn new(
+        sc_graph: &SCGraph,
+        cfg: &crate::CfgProgram,
+        cycles: &MixedCycles,
+        warnings: usize,
+    ) -> Self {
+        Self {
+            warnings,
+            cycles: cycles
+                .cycles
+                .iter()
+                .map(|cycle| {
+                    (
+                        sc_graph.cycle_id(cycle, cfg),
+                        sc_graph.describe_cycle(cycle, cfg),
+                    )
+                })
+                .collect(),
+            truncated: cycles.truncated,
+        }
+    }
+}
+
+/// The files of the program, or the input and its links while it has none
+fn watched_files(state: &WatchState, cli: &Cli) -> Vec<PathBuf> {
+    match &state.program {
+        Some(program) => program
+            .source_files
+            .iter()
+            .filter_map(|file| file.path.as_deref().map(PathBuf::from))
+            .collect(),
+        None => std::iter::once(cli.input.clone())
+            .chain(cli.links.iter().cloned())
+            .collect(),
+    }
+}
+
+/// When each file was last modified, if it can be read
+fn modified(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| {
+            std::fs::metadata(file)
+                .and_then(|meta| meta.modified())
+                .ok()
+        })
+        .collect()
+}
@@ -0,0 +1,183 @@
+//! Per-hop read/write effect summaries.
+//!
+//! For each hop, the table fields it reads and writes, with the primary key operands
+//! of each access. Unlike `table_mod_ref`, which accumulates table accesses along
+//! paths through the whole function, these summaries only cover the statements of
+//! the hop itself, at field granularity.
+//!
+//! `HopSubgraph` bundles a hop's summary with its part of the function's CFG,
+//! which is what later stages need to reason about a hop on its own.
+//!
+//! ```no_run
+//! use FMitF_rs::dataflow::{analyze_hop_effects, AccessType};
+//! # let cfg: FMitF_rs::CfgProgram = unimplemented!();
+//!
+//! for (hop_id, accesses) in analyze_hop_effects(&cfg) {
+//!     let writes = accesses.iter().filter(|a| a.access_type == AccessType::Write);
+//!     println!("{:?} writes {} fields", hop_id, writes.count());
+//! }
+//! ```
+
+use crate::cfg::{
+    BasicBlockId, CfgProgram, FieldId, FunctionCfg, FunctionId, HopId, NodeId, Operand, Rvalue,
+    Statement, TableId, Terminator,
+};
+use crate::dataflow::AccessType;
+use std::collections::{HashMap, HashSet};
+
+/// One read or write of a table field, with the primary key it addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FieldAccess {
+    pub table: TableId,
+    pub field: FieldId,
+    pub access_type: AccessType,
+    /// Primary key fields, in the order of `pk_values`.
+    pub pk_fields: Vec<FieldId>,
+    /// Primary key operands: variables of the hop's function or constants.
+    pub pk_values: Vec<Operand>,
+}
+
+impl FieldAccess {
+    fn of(statement: &Statement) -> Option<Self> {
+        match statement {
+            Statement::Assign {
+                rvalue:
+                    Rvalue::TableAccess {
+                        table,
+                        pk_fields,
+                        pk_values,
+                        field,
+                    },
+                ..
+            } => Some(Self {
+                table: *table,
+                field: *field,
+                access_type: AccessType::Read,
+                pk_fields: pk_fields.clone(),
+                pk_values: pk_values.clone(),
+            }),
+            Statement::TableAssign {
+                table,
+                pk_fields,
+                pk_values,
+                field,
+                ..
+            } => Some(Self {
+                table: *table,
+                field: *field,
+                access_type: AccessType::Write,
+                pk_fields: pk_fields.clone(),
+                pk_values: pk_values.clone(),
+            }),
+            Statement::Assign { .. } => None,
+        }
+    }
+}
+
+/// The distinct field accesses of a hop, in the order they first appear.
+pub fn hop_effects(func: &FunctionCfg, hop_id: HopId) -> Vec<FieldAccess> {
+    let mut seen = HashSet::new();
+    func.hops[hop_id]
+        .blocks
+        .iter()
+        .flat_map(|&block_id| &func.blocks[block_id].statements)
+        .filter_map(FieldAccess::of)
+        .filter(|access| seen.insert(access.clone()))
+        .collect()
+}
+
+/// The field accesses of every hop in the program.
+pub fn analyze_hop_effects(cfg: &CfgProgram) -> HashMap<HopId, Vec<FieldAccess>> {
+    cfg.functions
+        .iter()
+        .flat_map(|(_, func)| {
+            func.hops
+                .iter()
+                .map(move |(hop_id, _)| (hop_id, hop_effects(func, hop_id)))
+        })
+        .collect()
+}
+
+/// A hop's part of its function's CFG, with the table fields it reads and writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopSubgraph {
+    pub function: FunctionId,
+    pub hop: HopId,
+    pub node: NodeId,
+    pub entry_block: Option<BasicBlockId>,
+    /// The blocks of the hop, in arena order
+    pub blocks: Vec<BasicBlockId>,
+    /// Control-flow edges between blocks of the hop
+    pub edges: Vec<(BasicBlockId, BasicBlockId)>,
+    /// The blocks that leave the hop, by `HopExit`, `Return` or `Abort`
+    pub exits: Vec<BasicBlockId>,
+    /// Distinct reads, in the order they first appear
+    pub reads: Vec<FieldAccess>,
+    /// Distinct writes, in the order they first appear
+    pub writes: Vec<FieldAccess>,
+}
+
+impl HopSubgraph {
+    /// Extracts a hop of a function.
+    pub fn new(cfg: &CfgProgram, function: FunctionId, hop: HopId) -> Self {
+        let func = &cfg.functions[function];
+        let hop_cfg = &func.hops[hop];
+        let mut blocks = hop_cfg.blocks.clone();
+        blocks.sort_by_key(|block_id| block_id.index());
+
+        let mut edges = Vec::new();
+        let mut exits = Vec::new();
+        for &block_id in &blocks {
+            match func.blocks[block_id].terminator {
+                Terminator::Return(_)
+                | Terminator::Abort
+                | Terminator::BoundExceeded(_)
+                | Terminator::HopExit { .. } => exits.push(block_id),
+                Terminator::Goto(_) | Terminator::Branch { .. } => edges.extend(
+                    func.successors(block_id)
+                        .into_iter()
+                        .map(|succ| (block_id, succ)),
+                ),
+            }
+        }
+
+        let (reads, writes) = hop_effects(func, hop)
+            .into_iter()
+            .partition(|access| access.access_type == AccessType::Read);
+
+        Self {
+            function,
+            hop,
+            node: hop_cfg.node_id,
+            entry_block: hop_cfg.entry_block,
+            blocks,
+            edges,
+            exits,
+            reads,
+            writes,
+        }
+    }
+
+    /// The tables the hop writes, in the order they are first written.
+    pub fn written_tables(&self) -> Vec<TableId> {
+        let mut tables = Vec::new();
+        for access in &self.writes {
+            if !tables.contains(&access.table) {
+                tables.push(access.table);
+            }
+        }
+        tables
+    }
+}
+
+/// Extracts every hop of the program.
+pub fn hop_subgraphs(cfg: &CfgProgram) -> HashMap<HopId, HopSubgraph> {
+    cfg.functions
+        .iter()
+        .flat_map(|(function, func)| {
+            func.hops
+                .iter()
+                .map(move |(hop, _)| (hop, HopSubgraph::new(cfg, function, hop)))
+        })
+        .collect()
+}
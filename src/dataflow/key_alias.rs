@@ -0,0 +1,345 @@
+//! Must-alias analysis for primary keys.
+//!
+//! Decides whether two table accesses address the same record. Each primary
+//! key operand is resolved through reaching definitions and copies to what it
+//! is known to hold: a constant, a parameter of the function as passed in, or
+//! nothing known. Two keys of the same table then provably address the same
+//! record when every field resolves to the same value, and provably different
+//! records when some field resolves to different constants.
+//!
+//! A parameter only equals itself within one run of its function. Different
+//! transactions take independent arguments, so accesses of different functions
+//! can only be told apart or matched by their constants.
+//!
+//! ```
+//! use FMitF_rs::dataflow::{analyze_keyed_accesses, KeyAlias};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void f(int id) {
+//!         hop on A {
+//!             int key = id;
+//!             T[id: id].n = T[id: key].n + 1;
+//!             T[id: 1].n = 0;
+//!         }
+//!     }
+//!     void g() {
+//!         hop on A {
+//!             T[id: 2].n = 0;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let accesses = analyze_keyed_accesses(&cfg);
+//! let f = &accesses[&cfg.functions[cfg.root_functions[0]].hop_order[0]];
+//! let g = &accesses[&cfg.functions[cfg.root_functions[1]].hop_order[0]];
+//!
+//! // The read through the copy and the write both use the parameter
+//! assert_eq!(f[0].alias(&f[1]), KeyAlias::Must);
+//! // Nothing is known about the parameter's value
+//! assert_eq!(f[0].alias(&f[2]), KeyAlias::May);
+//! // Literals 1 and 2
+//! assert_eq!(f[2].alias(&g[0]), KeyAlias::MustNot);
+//!
+//! // T[id: id].n
+//! println!("{}", FMitF_rs::pretty::format_keyed_access(&f[0], &cfg));
+//! ```
+
+use crate::cfg::{
+    BasicBlockId, CfgProgram, Constant, FieldId, FunctionCfg, FunctionId, HopCfg, HopId, Operand,
+    Rvalue, Statement, TableId, Terminator, VarId,
+};
+use crate::dataflow::{AccessType, DefinitionSite, ReachingDefinitions};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// How many copies a key operand is followed through.
+const MAX_COPIES: usize = 8;
+
+/// What a primary key operand is known to hold at an access.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum KeyValue {
+    Const(Constant),
+    /// A parameter of the function, as passed in
+    Param {
+        function: FunctionId,
+        var: VarId,
+    },
+    Unknown,
+}
+
+/// Whether two accesses address the same record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KeyAlias {
+    /// Provably the same record
+    Must,
+    /// Possibly the same record
+    May,
+    /// Provably different records
+    MustNot,
+}
+
+/// A read or write of a table field, with its primary key resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyedAccess {
+    pub function: FunctionId,
+    pub hop: HopId,
+    pub block: BasicBlockId,
+    /// Statement index within the block
+    pub index: usize,
+    pub table: TableId,
+    pub field: FieldId,
+    pub access_type: AccessType,
+    pub key: Vec<(FieldId, KeyValue)>,
+}
+
+impl KeyedAccess {
+    /// Whether this access and another address the same record. Accesses of
+    /// different tables never do.
+    pub fn alias(&self, other: &Self) -> KeyAlias {
+        if self.table != other.table {
+            return KeyAlias::MustNot;
+        }
+        let mut must = true;
+        for (field, value) in &self.key {
+            let other_value = other
+                .key
+                .iter()
+                .find(|(other_field, _)| other_field == field)
+                .map(|(_, value)| value);
+            match (value, other_value) {
+                (KeyValue::Const(a), Some(KeyValue::Const(b))) if a != b => {
+                    return KeyAlias::MustNot
+                }
+                (KeyValue::Unknown, _) | (_, Some(KeyValue::Unknown) | None) => must = false,
+                (a, Some(b)) => must &= a == b,
+            }
+        }
+        if must {
+            KeyAlias::Must
+        } else {
+            KeyAlias::May
+        }
+    }
+}
+
+/// The table accesses of every hop in the program, in block and statement
+/// order. Accesses in blocks the hop can never run, behind a branch on a
+/// constant condition or unreachable from its entry, are left out, so they
+/// create no conflicts even in an unoptimized CFG. So are accesses in blocks
+/// every path from which aborts, as the abort rolls their writes back and the
+/// transaction commits nothing that depends on their reads:
+///
+/// ```
+/// use FMitF_rs::dataflow::analyze_keyed_accesses;
+/// use FMitF_rs::{parse_and_analyze, CfgBuilder};
+///
+/// let program = parse_and_analyze(
+///     r#"
+///     nodes { A }
+///     table T on A { primary int id; int n; }
+///     void f(int id) {
+///         hop on A {
+///             if (false) { T[id: id].n = 1; }
+///             if (id < 0) { T[id: 0].n = 3; abort; }
+///             T[id: id].n = 2;
+///         }
+///     }
+///     "#,
+/// )
+/// .unwrap();
+/// let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+/// let accesses = analyze_keyed_accesses(&cfg);
+/// assert_eq!(accesses[&cfg.functions[cfg.root_functions[0]].hop_order[0]].len(), 1);
+/// ```
+pub fn analyze_keyed_accesses(cfg: &CfgProgram) -> HashMap<HopId, Vec<KeyedAccess>> {
+    cfg.functions
+        .iter()
+        .flat_map(|(function, _)| function_keyed_accesses(cfg, function))
+        .collect()
+}
+
+/// The table accesses of every hop of one function, as
+/// `analyze_keyed_accesses` finds them.
+pub fn function_keyed_accesses(
+    cfg: &CfgProgram,
+    function: FunctionId,
+) -> HashMap<HopId, Vec<KeyedAccess>> {
+    let func = &cfg.functions[function];
+    let mut accesses = HashMap::new();
+    let reaching = ReachingDefinitions::new(func);
+    for (hop, hop_cfg) in func.hops.iter() {
+        let live = live_blocks(func, hop_cfg);
+        let aborting = aborting_blocks(func, &live);
+        let mut blocks: Vec<BasicBlockId> = live.difference(&aborting).copied().collect();
+        blocks.sort();
+        let mut hop_accesses = Vec::new();
+        for block in blocks {
+            for (index, stmt) in func.blocks[block].statements.iter().enumerate() {
+                let (table, pk_fields, pk_values, field, access_type) = match stmt {
+                    Statement::Assign {
+                        rvalue:
+                            Rvalue::TableAccess {
+                                table,
+                                pk_fields,
+                                pk_values,
+                                field,
+                            },
+                        ..
+                    } => (table, pk_fields, pk_values, field, AccessType::Read),
+                    Statement::TableAssign {
+                        table,
+                        pk_fields,
+                        pk_values,
+                        field,
+                        ..
+                    } => (table, pk_fields, pk_values, field, AccessType::Write),
+                    Statement::Assign { .. } => continue,
+                };
+                let resolver = KeyResolver {
+                    function,
+                    func,
+                    reaching: &reaching,
+                };
+                let key = pk_fields
+                    .iter()
+                    .zip(pk_values)
+                    .map(|(&pk_field, value)| {
+                        (pk_field, resolver.resolve(value, block, index, MAX_COPIES))
+                    })
+                    .collect();
+                hop_accesses.push(KeyedAccess {
+                    function,
+                    hop,
+                    block,
+                    index,
+                    table: *table,
+                    field: *field,
+                    access_type,
+                    key,
+                });
+            }
+        }
+        accesses.insert(hop, hop_accesses);
+    }
+    accesses
+}
+
+/// The blocks of a hop reachable from its entry, following only the side of a
+/// branch its condition selects when that condition is a constant.
+fn live_blocks(func: &FunctionCfg, hop: &HopCfg) -> HashSet<BasicBlockId> {
+    let mut live = HashSet::new();
+    let mut stack: Vec<BasicBlockId> = hop.entry_block.into_iter().collect();
+    while let Some(block) = stack.pop() {
+        if !hop.blocks.contains(&block) || !live.insert(block) {
+            continue;
+        }
+        match &func.blocks[block].terminator {
+            Terminator::Goto(target) => stack.push(*target),
+            Terminator::Branch {
+                condition: Operand::Const(Constant::Bool(condition)),
+                then_block,
+                else_block,
+            } => stack.push(if *condition { *then_block } else { *else_block }),
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => stack.extend([*then_block, *else_block]),
+            Terminator::Return(_)
+            | Terminator::Abort
+            | Terminator::HopExit { .. }
+            | Terminator::BoundExceeded(_) => {}
+        }
+    }
+    live
+}
+
+/// The live blocks of a hop every path from which aborts within the hop.
+fn aborting_blocks(func: &FunctionCfg, live: &HashSet<BasicBlockId>) -> HashSet<BasicBlockId> {
+    let mut aborting = HashSet::new();
+    loop {
+        let before = aborting.len();
+        for &block in live {
+            let aborts = match &func.blocks[block].terminator {
+                Terminator::Abort => true,
+                Terminator::Goto(target) => aborting.contains(target),
+                Terminator::Branch {
+                    condition: Operand::Const(Constant::Bool(condition)),
+                    then_block,
+                    else_block,
+                } => aborting.contains(if *condition { then_block } else { else_block }),
+                Terminator::Branch {
+                    then_block,
+                    else_block,
+                    ..
+                } => aborting.contains(then_block) && aborting.contains(else_block),
+                Terminator::Return(_)
+                | Terminator::HopExit { .. }
+                | Terminator::BoundExceeded(_) => false,
+            };
+            if aborts {
+                aborting.insert(block);
+            }
+        }
+        if aborting.len() == before {
+            break;
+        }
+    }
+    aborting
+}
+
+struct KeyResolver<'a> {
+    function: FunctionId,
+    func: &'a FunctionCfg,
+    reaching: &'a ReachingDefinitions,
+}
+
+impl KeyResolver<'_> {
+    /// What an operand used at statement `index` of a block holds, following at
+    /// most `copies` copies.
+    fn resolve(
+        &self,
+        operand: &Operand,
+        block: BasicBlockId,
+        index: usize,
+        copies: usize,
+    ) -> KeyValue {
+        let var = match operand {
+            Operand::Const(constant) => return KeyValue::Const(constant.clone()),
+            Operand::Var(var) => *var,
+        };
+        let mut values = self
+            .reaching
+            .reaching(self.func, block, index, var)
+            .into_iter()
+            .map(|site| match site {
+                DefinitionSite::Entry if self.func.variables[var].is_parameter => KeyValue::Param {
+                    function: self.function,
+                    var,
+                },
+                DefinitionSite::Statement { block, index } if copies > 0 => {
+                    match &self.func.blocks[block].statements[index] {
+                        Statement::Assign {
+                            rvalue: Rvalue::Use(source),
+                            ..
+                        } => self.resolve(source, block, index, copies - 1),
+                        _ => KeyValue::Unknown,
+                    }
+                }
+                _ => KeyValue::Unknown,
+            });
+        // Every definition that may reach the use must agree
+        let first = values.next().unwrap_or(KeyValue::Unknown);
+        if values.all(|value| value == first) {
+            first
+        } else {
+            KeyValue::Unknown
+        }
+    }
+}
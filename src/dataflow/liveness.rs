@@ -1,7 +1,42 @@
-use crate::cfg::{FunctionCfg, Operand, Rvalue, Statement, Terminator, VarId};
+//! Live variables: the variables whose current value may still be read.
+//!
+//! A backward analysis over the whole function. Locals outlive the hop that
+//! assigns them, so liveness flows from each hop into the one before it.
+//!
+//! ```
+//! use FMitF_rs::dataflow::LiveVariables;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table T on A { primary int id; int n; }
+//!     table U on B { primary int id; int n; }
+//!     void copy(int id) {
+//!         hop on A {
+//!             int x = T[id: id].n;
+//!         }
+//!         hop on B {
+//!             U[id: id].n = x;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let func = &cfg.functions[cfg.root_functions[0]];
+//! let first_block = func.hops[func.hop_order[0]].entry_block.unwrap();
+//! let x = func.variables.iter().find(|(_, var)| var.name == "x").unwrap().0;
+//!
+//! // `x` is read by the second hop
+//! assert!(LiveVariables::new(func).live_out(first_block).contains(&x));
+//! ```
+
+use crate::cfg::{BasicBlockId, FunctionCfg, Operand, Rvalue, Statement, Terminator, VarId};
 use crate::dataflow::{
     DataflowAnalysis, DataflowResults, Direction, Lattice, SetLattice, TransferFunction,
 };
+use std::collections::HashSet;
 
 pub struct LiveVariablesTransfer;
 
@@ -69,15 +104,14 @@ impl TransferFunction<SetLattice<VarId>> for LiveVariablesTransfer {
         let mut result = state.clone();
 
         match term {
-            Terminator::Branch { condition, .. } => {
-                if let Operand::Var(v) = condition {
-                    result.set.insert(*v);
-                }
+            Terminator::Branch {
+                condition: Operand::Var(v),
+                ..
+            } => {
+                result.set.insert(*v);
             }
-            Terminator::Return(Some(operand)) => {
-                if let Operand::Var(v) = operand {
-                    result.set.insert(*v);
-                }
+            Terminator::Return(Some(Operand::Var(v))) => {
+                result.set.insert(*v);
             }
             _ => {}
         }
@@ -96,6 +130,49 @@ impl TransferFunction<SetLattice<VarId>> for LiveVariablesTransfer {
 
 /// Helper function to run live variables analysis
 pub fn analyze_live_variables(func: &FunctionCfg) -> DataflowResults<SetLattice<VarId>> {
-    let analysis = DataflowAnalysis::new(Direction::Backward, LiveVariablesTransfer);
+    let analysis = DataflowAnalysis::new(Direction::Backward, LiveVariablesTransfer).across_hops();
     analysis.analyze(func)
 }
+
+/// The live variables of a function, at block boundaries and between statements.
+pub struct LiveVariables {
+    results: DataflowResults<SetLattice<VarId>>,
+}
+
+impl LiveVariables {
+    /// Runs the analysis on a function.
+    pub fn new(func: &FunctionCfg) -> Self {
+        Self {
+            results: analyze_live_variables(func),
+        }
+    }
+
+    /// The variables live on entry to a block.
+    pub fn live_in(&self, block: BasicBlockId) -> &HashSet<VarId> {
+        &self.results.entry[&block].set
+    }
+
+    /// The variables live on exit from a block, including those read by later
+    /// hops.
+    pub fn live_out(&self, block: BasicBlockId) -> &HashSet<VarId> {
+        &self.results.exit[&block].set
+    }
+
+    /// The variables live right after statement `index` of a block. An
+    /// assignment whose variable is not among them is dead.
+    pub fn live_after(
+        &self,
+        func: &FunctionCfg,
+        block: BasicBlockId,
+        index: usize,
+    ) -> HashSet<VarId> {
+        let transfer = LiveVariablesTransfer;
+        let block_data = &func.blocks[block];
+        let mut live =
+            transfer.transfer_terminator(&block_data.terminator, &self.results.exit[&block]);
+        for stmt in block_data.statements[index + 1..].iter().rev() {
+            live = transfer.transfer_statement(stmt, &live);
+        }
+        live.set
+    }
+}
@@ -5,10 +5,13 @@ use std::fmt::Debug;
 use std::hash::Hash;
 
 mod liveness;
-pub use liveness::analyze_live_variables;
+pub use liveness::{analyze_live_variables, LiveVariables};
 
 mod reaching_definitions;
-pub use reaching_definitions::{analyze_reaching_definitions, Definition as ReachingDefinition};
+pub use reaching_definitions::{
+    analyze_reaching_definitions, Definition as ReachingDefinition, DefinitionSite,
+    ReachingDefinitions,
+};
 
 mod available_expressions;
 pub use available_expressions::analyze_available_expressions;
@@ -16,6 +19,17 @@ pub use available_expressions::analyze_available_expressions;
 mod table_mod_ref;
 pub use table_mod_ref::{analyze_table_mod_ref, AccessType, TableAccess};
 
+mod effects;
+pub use effects::{analyze_hop_effects, hop_effects, hop_subgraphs, FieldAccess, HopSubgraph};
+
+mod key_alias;
+pub use key_alias::{
+    analyze_keyed_accesses, function_keyed_accesses, KeyAlias, KeyValue, KeyedAccess,
+};
+
+mod slicing;
+pub use slicing::{slice_field, FieldSlice, SliceRole, SliceSite};
+
 /// Direction of dataflow analysis
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
@@ -60,6 +74,9 @@ pub trait TransferFunction<L: Lattice> {
 pub struct DataflowAnalysis<L: Lattice, T: TransferFunction<L>> {
     pub direction: Direction,
     pub transfer: T,
+    /// Whether values flow along `HopExit` edges into the next hop; otherwise
+    /// each hop is analyzed on its own
+    pub across_hops: bool,
     _phantom: std::marker::PhantomData<L>,
 }
 
@@ -76,10 +93,18 @@ impl<L: Lattice, T: TransferFunction<L>> DataflowAnalysis<L, T> {
         Self {
             direction,
             transfer,
+            across_hops: false,
             _phantom: std::marker::PhantomData,
         }
     }
 
+    /// Lets values flow from each hop into the next, for analyses of values
+    /// that outlive a hop, like local variables.
+    pub fn across_hops(mut self) -> Self {
+        self.across_hops = true;
+        self
+    }
+
     /// Run dataflow analysis on a function
     pub fn analyze(&self, func: &FunctionCfg) -> DataflowResults<L> {
         let mut entry: HashMap<BasicBlockId, L> = HashMap::new();
@@ -143,7 +168,7 @@ impl<L: Lattice, T: TransferFunction<L>> DataflowAnalysis<L, T> {
                 }
                 Direction::Backward => {
                     // Compute exit as meet of successors' entries
-                    let succs = self.get_successors(block);
+                    let succs = self.get_successors(func, block_id);
                     let exit_val = if succs.is_empty() {
                         exit[&block_id].clone()
                     } else {
@@ -183,7 +208,7 @@ impl<L: Lattice, T: TransferFunction<L>> DataflowAnalysis<L, T> {
                 // Add affected blocks to worklist
                 match self.direction {
                     Direction::Forward => {
-                        worklist.extend(self.get_successors(block));
+                        worklist.extend(self.get_successors(func, block_id));
                     }
                     Direction::Backward => {
                         worklist.extend(self.get_predecessors(func, block_id));
@@ -220,24 +245,21 @@ impl<L: Lattice, T: TransferFunction<L>> DataflowAnalysis<L, T> {
         current
     }
 
-    fn get_successors(&self, block: &BasicBlock) -> Vec<BasicBlockId> {
-        match &block.terminator {
-            Terminator::Goto(target) => vec![*target],
-            Terminator::Branch {
-                then_block,
-                else_block,
-                ..
-            } => {
-                vec![*then_block, *else_block]
-            }
-            Terminator::Return(_) | Terminator::Abort | Terminator::HopExit { .. } => vec![],
+    fn get_successors(&self, func: &FunctionCfg, block_id: BasicBlockId) -> Vec<BasicBlockId> {
+        let hop_exit = matches!(func.blocks[block_id].terminator, Terminator::HopExit { .. });
+        if hop_exit && !self.across_hops {
+            return Vec::new();
         }
+        func.successors(block_id)
     }
 
     fn get_predecessors(&self, func: &FunctionCfg, block_id: BasicBlockId) -> Vec<BasicBlockId> {
         let mut preds = Vec::new();
 
         for (pred_id, pred_block) in func.blocks.iter() {
+            if !func.contains_block(pred_id) {
+                continue;
+            }
             match &pred_block.terminator {
                 Terminator::Goto(target) if *target == block_id => {
                     preds.push(pred_id);
@@ -246,10 +268,14 @@ impl<L: Lattice, T: TransferFunction<L>> DataflowAnalysis<L, T> {
                     then_block,
                     else_block,
                     ..
-                } => {
-                    if *then_block == block_id || *else_block == block_id {
+                }
+                    if (*then_block == block_id || *else_block == block_id) => {
                         preds.push(pred_id);
                     }
+                Terminator::HopExit {
+                    next_hop: Some(next_hop),
+                } if self.across_hops && func.hops[*next_hop].entry_block == Some(block_id) => {
+                    preds.push(pred_id);
                 }
                 _ => {}
             }
@@ -1,19 +1,75 @@
-use crate::cfg::{FunctionCfg, Statement, Terminator, VarId};
+//! Reaching definitions: for each use of a variable, the assignments whose value
+//! it may read.
+//!
+//! A forward analysis over the whole function. Locals outlive the hop that
+//! assigns them, so definitions flow from each hop into the next. Besides
+//! assignments, every variable has an entry definition: its parameter value, or
+//! for a local the absence of a value until it is first assigned.
+//!
+//! ```
+//! use FMitF_rs::dataflow::{DefinitionSite, ReachingDefinitions};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table T on A { primary int id; int n; }
+//!     table U on B { primary int id; int n; }
+//!     void copy(int id, int n) {
+//!         hop on A {
+//!             if (T[id: id].n > 0) {
+//!                 n = T[id: id].n;
+//!             }
+//!         }
+//!         hop on B {
+//!             U[id: id].n = n;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let func = &cfg.functions[cfg.root_functions[0]];
+//! let last_block = func.hops[func.hop_order[1]].entry_block.unwrap();
+//! let n = func.variables.iter().find(|(_, var)| var.name == "n").unwrap().0;
+//!
+//! // The write in the second hop reads either the parameter or the assignment
+//! let sites = ReachingDefinitions::new(func).reaching(func, last_block, 0, n);
+//! assert_eq!(sites.len(), 2);
+//! assert_eq!(sites[0], DefinitionSite::Entry);
+//! ```
+
+use crate::cfg::{BasicBlockId, FunctionCfg, Statement, Terminator, VarId};
 use crate::dataflow::{
     DataflowAnalysis, DataflowResults, Direction, Lattice, SetLattice, TransferFunction,
 };
+use std::collections::HashMap;
 use std::ptr::NonNull;
 
 /// Represents a definition of a variable at a specific program site.
-/// The `site` points to the `Statement` that performs the assignment.
+/// The `site` points to the `Statement` that performs the assignment, or is
+/// `None` for the value the variable has when the function starts.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Definition {
     pub var_id: VarId,
-    pub site: NonNull<Statement>,
+    pub site: Option<NonNull<Statement>>,
 }
 
 /// Transfer function for Reaching Definitions analysis.
-pub struct ReachingDefinitionsTransfer;
+pub struct ReachingDefinitionsTransfer {
+    /// The entry definition of every variable of the function
+    entry: SetLattice<Definition>,
+}
+
+impl ReachingDefinitionsTransfer {
+    pub fn new(func: &FunctionCfg) -> Self {
+        let mut entry = SetLattice::bottom();
+        for (var_id, _) in func.variables.iter() {
+            entry.set.insert(Definition { var_id, site: None });
+        }
+        Self { entry }
+    }
+}
 
 impl TransferFunction<SetLattice<Definition>> for ReachingDefinitionsTransfer {
     fn transfer_statement(
@@ -33,7 +89,7 @@ impl TransferFunction<SetLattice<Definition>> for ReachingDefinitionsTransfer {
                 // The site of the definition is the current statement itself.
                 new_state.set.insert(Definition {
                     var_id: *var,
-                    site: NonNull::from(stmt), // `stmt` is `&Statement`
+                    site: Some(NonNull::from(stmt)), // `stmt` is `&Statement`
                 });
             }
             // Other statement types (e.g., TableAssign, function calls if they were statements)
@@ -59,23 +115,94 @@ impl TransferFunction<SetLattice<Definition>> for ReachingDefinitionsTransfer {
     }
 
     fn initial_value(&self) -> SetLattice<Definition> {
-        // For a forward analysis like Reaching Definitions, the initial value
-        // applies to the entry point of the function.
-        // At the very beginning, no definitions (from within the function) have occurred yet.
-        SetLattice::bottom() // Represents an empty set of definitions
+        // At the entry of the function, only the entry definitions reach
+        self.entry.clone()
     }
 
     fn boundary_value(&self) -> SetLattice<Definition> {
-        // This value is not strictly used by the current worklist algorithm's
-        // initialization for the main analysis pass but is part of the trait.
-        // It could represent, e.g., definitions from parameters if they were modeled.
-        SetLattice::bottom()
+        self.entry.clone()
     }
 }
 
 /// Helper function to run Reaching Definitions analysis on a given function CFG.
 pub fn analyze_reaching_definitions(func: &FunctionCfg) -> DataflowResults<SetLattice<Definition>> {
-    let transfer_function = ReachingDefinitionsTransfer;
-    let analysis = DataflowAnalysis::new(Direction::Forward, transfer_function);
+    let transfer_function = ReachingDefinitionsTransfer::new(func);
+    let analysis = DataflowAnalysis::new(Direction::Forward, transfer_function).across_hops();
     analysis.analyze(func)
 }
+
+/// Where a variable is defined.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DefinitionSite {
+    /// The value on entry to the function: a parameter's argument, or no value
+    /// yet for a local
+    Entry,
+    /// Statement `index` of `block`
+    Statement { block: BasicBlockId, index: usize },
+}
+
+/// The reaching definitions of a function, queried by use site.
+pub struct ReachingDefinitions {
+    results: DataflowResults<SetLattice<Definition>>,
+    sites: HashMap<NonNull<Statement>, (BasicBlockId, usize)>,
+}
+
+impl ReachingDefinitions {
+    /// Runs the analysis on a function. Queries must pass the same, unchanged
+    /// function.
+    pub fn new(func: &FunctionCfg) -> Self {
+        let sites = func
+            .blocks
+            .iter()
+            .flat_map(|(block_id, block)| {
+                block
+                    .statements
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, stmt)| (NonNull::from(stmt), (block_id, index)))
+            })
+            .collect();
+        Self {
+            results: analyze_reaching_definitions(func),
+            sites,
+        }
+    }
+
+    /// The definitions of `var` that reach statement `index` of `block`, before
+    /// it runs; an index past the last statement asks about the terminator.
+    /// Sorted with the entry definition first, then by block and statement.
+    pub fn reaching(
+        &self,
+        func: &FunctionCfg,
+        block: BasicBlockId,
+        index: usize,
+        var: VarId,
+    ) -> Vec<DefinitionSite> {
+        let transfer = ReachingDefinitionsTransfer {
+            entry: SetLattice::bottom(),
+        };
+        let statements = &func.blocks[block].statements;
+        let mut state = self.results.entry[&block].clone();
+        for stmt in &statements[..index.min(statements.len())] {
+            state = transfer.transfer_statement(stmt, &state);
+        }
+
+        let mut sites: Vec<DefinitionSite> = state
+            .set
+            .iter()
+            .filter(|def| def.var_id == var)
+            .map(|def| match def.site {
+                Some(site) => {
+                    let (block, index) = self.sites[&site];
+                    DefinitionSite::Statement { block, index }
+                }
+                None => DefinitionSite::Entry,
+            })
+            .collect();
+        sites.sort_by_key(|site| match site {
+            DefinitionSite::Entry => (0, 0, 0),
+            DefinitionSite::Statement { block, index } => (1, block.index(), *index),
+        });
+        sites
+    }
+}
@@ -0,0 +1,320 @@
+//! Program slicing with respect to a table field.
+//!
+//! The slice of a field holds the statements of every function that can affect
+//! the field or observe its value:
+//!
+//! - the reads and writes of the field itself,
+//! - what affects them: the statements computing the primary keys they address
+//!   and the values written, and the branches deciding whether they run, found
+//!   backward through reaching definitions and control dependence,
+//! - what observes a read: the statements and branches using the value read,
+//!   directly or through other variables, found forward through the same
+//!   definitions.
+//!
+//! Branches and returns are part of a slice as the terminators of their blocks.
+//! Sites follow the convention of `ReachingDefinitions::reaching`: statement
+//! `index` of a block, with an index past the last statement meaning the
+//! terminator.
+//!
+//! ```
+//! use FMitF_rs::dataflow::{slice_field, SliceRole};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; int m; }
+//!     void f(int id, int k) {
+//!         hop on A {
+//!             int key = id + 1;
+//!             if (k > 0) {
+//!                 T[id: key].n = k;
+//!             }
+//!             T[id: id].m = 0;
+//!         }
+//!     }
+//!     int g(int id) {
+//!         hop on A {
+//!             int n = T[id: id].n;
+//!             return n * 2;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let table = cfg.root_tables[0];
+//! let n = cfg.tables[table].fields[1];
+//! let slice = slice_field(&cfg, table, n);
+//!
+//! let roles = |function| -> Vec<SliceRole> {
+//!     let sites = slice.sites.iter().filter(|site| site.function == function);
+//!     sites.map(|site| site.role).collect()
+//! };
+//! let (f, g) = (cfg.root_functions[0], cfg.root_functions[1]);
+//! // The key, the condition, the branch it decides and the write, but not the
+//! // write of `m`
+//! use SliceRole::*;
+//! assert_eq!(roles(f), [Affects, Affects, Affects, Affects, Writes]);
+//! // The read, what it is copied into, the product and the return
+//! assert_eq!(roles(g), [Reads, Observes, Observes, Observes]);
+//!
+//! // Slice of T.n:
+//! //   f:
+//! //     bb0[0] affects: %_temp_3 = %id Add 1
+//! //     ...
+//! println!("{}", FMitF_rs::pretty::format_field_slice(&slice, &cfg));
+//! ```
+
+use crate::cfg::{
+    BasicBlockId, CfgProgram, DominatorTree, FieldId, FunctionCfg, FunctionId, Instruction,
+    Operand, Rvalue, Statement, TableId, Terminator, VarId,
+};
+use crate::dataflow::{DefinitionSite, ReachingDefinitions};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+
+/// Why a site is part of a slice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SliceRole {
+    /// Reads the field
+    Reads,
+    /// Writes the field
+    Writes,
+    /// Decides which record a read or write addresses, the value written, or
+    /// whether the access runs
+    Affects,
+    /// Uses a value read from the field
+    Observes,
+}
+
+/// A statement or terminator in a slice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SliceSite {
+    pub function: FunctionId,
+    pub block: BasicBlockId,
+    /// Statement index within the block; the statement count for the terminator
+    pub index: usize,
+    pub role: SliceRole,
+}
+
+impl SliceSite {
+    /// Whether the site is the terminator of its block.
+    pub fn is_terminator(&self, cfg: &CfgProgram) -> bool {
+        self.index
+            >= cfg.functions[self.function].blocks[self.block]
+                .statements
+                .len()
+    }
+}
+
+/// The sites that can affect or observe a table field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSlice {
+    pub table: TableId,
+    pub field: FieldId,
+    /// Sites by function, block and index
+    pub sites: Vec<SliceSite>,
+}
+
+impl FieldSlice {
+    /// The role of a statement or terminator in the slice, if it is part of it.
+    pub fn role(
+        &self,
+        function: FunctionId,
+        block: BasicBlockId,
+        index: usize,
+    ) -> Option<SliceRole> {
+        self.sites
+            .iter()
+            .find(|site| site.function == function && site.block == block && site.index == index)
+            .map(|site| site.role)
+    }
+
+    /// The functions with at least one site in the slice.
+    pub fn functions(&self) -> Vec<FunctionId> {
+        let mut functions: Vec<FunctionId> = self.sites.iter().map(|site| site.function).collect();
+        functions.dedup();
+        functions
+    }
+}
+
+/// Slices every function of the program with respect to a table field.
+pub fn slice_field(cfg: &CfgProgram, table: TableId, field: FieldId) -> FieldSlice {
+    let mut sites = Vec::new();
+    for (function, func) in cfg.functions.iter() {
+        let roles = slice_function(func, table, field);
+        sites.extend(roles.into_iter().map(|((block, index), role)| SliceSite {
+            function,
+            block,
+            index,
+            role,
+        }));
+    }
+    FieldSlice {
+        table,
+        field,
+        sites,
+    }
+}
+
+/// A statement, or with the statement count as index, a terminator.
+type Site = (BasicBlockId, usize);
+
+/// The slice of one function, by site in block and index order.
+fn slice_function(func: &FunctionCfg, table: TableId, field: FieldId) -> BTreeMap<Site, SliceRole> {
+    let mut roles = BTreeMap::new();
+    let mut backward = Vec::new();
+    let mut forward = Vec::new();
+    for (block_id, block) in func.blocks.iter() {
+        if !func.contains_block(block_id) {
+            continue;
+        }
+        for (index, stmt) in block.statements.iter().enumerate() {
+            match stmt {
+                Statement::Assign {
+                    rvalue:
+                        Rvalue::TableAccess {
+                            table: t, field: f, ..
+                        },
+                    ..
+                } if (*t, *f) == (table, field) => {
+                    roles.insert((block_id, index), SliceRole::Reads);
+                    backward.push((block_id, index));
+                    forward.push((block_id, index));
+                }
+                Statement::TableAssign {
+                    table: t, field: f, ..
+                } if (*t, *f) == (table, field) => {
+                    roles.insert((block_id, index), SliceRole::Writes);
+                    backward.push((block_id, index));
+                }
+                _ => {}
+            }
+        }
+    }
+    if roles.is_empty() {
+        return roles;
+    }
+
+    let chains = DefUseChains::new(func);
+    let control = control_dependences(func);
+
+    // Backward from the accesses, through the definitions they use and the
+    // branches they depend on
+    while let Some(site) = backward.pop() {
+        let (block, _) = site;
+        let definitions = chains.definitions.get(&site).into_iter().flatten();
+        let branches = control
+            .get(&block)
+            .into_iter()
+            .flatten()
+            .map(|&branch| (branch, func.blocks[branch].statements.len()));
+        for dependency in definitions.copied().chain(branches) {
+            if let Entry::Vacant(entry) = roles.entry(dependency) {
+                entry.insert(SliceRole::Affects);
+                backward.push(dependency);
+            }
+        }
+    }
+
+    // Forward from the reads, through the uses of what they define
+    while let Some(site) = forward.pop() {
+        for &user in chains.uses.get(&site).into_iter().flatten() {
+            if let Entry::Vacant(entry) = roles.entry(user) {
+                entry.insert(SliceRole::Observes);
+                forward.push(user);
+            }
+        }
+    }
+    roles
+}
+
+/// The def-use chains of a function, over statements and terminators.
+struct DefUseChains {
+    /// The assignments each site may read a variable from
+    definitions: HashMap<Site, Vec<Site>>,
+    /// The sites that may read a variable an assignment defines
+    uses: HashMap<Site, Vec<Site>>,
+}
+
+impl DefUseChains {
+    fn new(func: &FunctionCfg) -> Self {
+        let reaching = ReachingDefinitions::new(func);
+        let mut chains = Self {
+            definitions: HashMap::new(),
+            uses: HashMap::new(),
+        };
+        for (block_id, block) in func.blocks.iter() {
+            if !func.contains_block(block_id) {
+                continue;
+            }
+            let statement_uses = block.statements.iter().map(|stmt| {
+                Instruction::of_statement(stmt)
+                    .used_operands()
+                    .into_iter()
+                    .filter_map(operand_var)
+                    .collect::<Vec<_>>()
+            });
+            let terminator_uses = match &block.terminator {
+                Terminator::Branch { condition, .. } => {
+                    operand_var(condition).into_iter().collect()
+                }
+                Terminator::Return(Some(value)) => operand_var(value).into_iter().collect(),
+                _ => Vec::new(),
+            };
+            for (index, vars) in statement_uses.chain([terminator_uses]).enumerate() {
+                let site = (block_id, index);
+                for var in vars {
+                    for definition in reaching.reaching(func, block_id, index, var) {
+                        if let DefinitionSite::Statement { block, index } = definition {
+                            chains
+                                .definitions
+                                .entry(site)
+                                .or_default()
+                                .push((block, index));
+                            chains.uses.entry((block, index)).or_default().push(site);
+                        }
+                    }
+                }
+            }
+        }
+        chains
+    }
+}
+
+fn operand_var(operand: &Operand) -> Option<VarId> {
+    match operand {
+        Operand::Var(var) => Some(*var),
+        Operand::Const(_) => None,
+    }
+}
+
+/// The branch blocks each block is control dependent on: those with one
+/// successor from which every path reaches the block, and one from which some
+/// path avoids it.
+fn control_dependences(func: &FunctionCfg) -> HashMap<BasicBlockId, Vec<BasicBlockId>> {
+    let post_dominators = DominatorTree::post_dominators(func);
+    let mut dependences: HashMap<BasicBlockId, Vec<BasicBlockId>> = HashMap::new();
+    for (block_id, block) in func.blocks.iter() {
+        if !func.contains_block(block_id) || !matches!(block.terminator, Terminator::Branch { .. })
+        {
+            continue;
+        }
+        let stop = post_dominators.immediate_dominator(block_id);
+        for succ in func.successors(block_id) {
+            // The successor and the blocks post-dominating it, up to the branch's
+            // own immediate post-dominator, which runs either way
+            for dependent in post_dominators.dominators_of(succ) {
+                if Some(dependent) == stop {
+                    break;
+                }
+                let branches = dependences.entry(dependent).or_default();
+                if !branches.contains(&block_id) {
+                    branches.push(block_id);
+                }
+            }
+        }
+    }
+    dependences
+}
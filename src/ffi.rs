@@ -0,0 +1,62 @@
+//! A C interface to the analyzer, for tools not written in Rust: build the
+//! library as a `cdylib` with the `ffi` feature, as
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`, include
+//! `include/fmitf.h` and link it with `-Ltarget/release -lFMitF_rs`.
+//!
+//! `fmitf_analyze` takes source code as a pointer and length of UTF-8 bytes
+//! and returns the JSON of `playground::analyze` as a NUL-terminated string,
+//! owned by the caller until passed to `fmitf_free`. It returns null only when
+//! `source` is null or the analyzer panicked.
+//!
+//! ```
+//! use FMitF_rs::ffi::{fmitf_analyze, fmitf_free};
+//! use std::ffi::CStr;
+//!
+//! let source = "nodes { A } table T on A { primary int id; int n; }";
+//! let json = unsafe { fmitf_analyze(source.as_ptr(), source.len()) };
+//! let text = unsafe { CStr::from_ptr(json) }.to_str().unwrap().to_string();
+//! unsafe { fmitf_free(json) };
+//!
+//! let result: serde_json::Value = serde_json::from_str(&text).unwrap();
+//! assert_eq!(result["diagnostics"], serde_json::json!([]));
+//! assert!(unsafe { fmitf_analyze(std::ptr::null(), 0) }.is_null());
+//! ```
+
+use crate::playground::analyze;
+use std::ffi::{c_char, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Analyzes the `len` bytes of source code at `source`, returning its
+/// diagnostics and SC-graph as JSON; invalid UTF-8 is replaced.
+///
+/// # Safety
+///
+/// `source` must be null or point to `len` initialized bytes.
+#[no_mangle]
+pub unsafe extern "C" fn fmitf_analyze(source: *const u8, len: usize) -> *mut c_char {
+    if source.is_null() {
+        return ptr::null_mut();
+    }
+    let bytes = std::slice::from_raw_parts(source, len);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        analyze(&String::from_utf8_lossy(bytes)).to_string()
+    }));
+    // serde_json escapes NUL, so the JSON has none inside it
+    match result.map(CString::new) {
+        Ok(Ok(json)) => json.into_raw(),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Releases a string returned by `fmitf_analyze`; null is ignored.
+///
+/// # Safety
+///
+/// `json` must be null or a string from `fmitf_analyze` not yet released.
+#[no_mangle]
+pub unsafe extern "C" fn fmitf_free(json: *mut c_char) {
+    if !json.is_null() {
+        drop(CString::from_raw(json));
+    }
+}
@@ -0,0 +1,217 @@
+//! Random TransAct workloads that pass analysis, for fuzzing the pipeline and
+//! measuring how the SC-graph and verification scale.
+//!
+//! A workload has nodes `N0, N1, ...`, tables `T0, T1, ...` keyed by `int id`
+//! with `int` fields `f0, f1, ...`, and transactions `t0, t1, ...` taking
+//! `int` parameters. Each hop runs on a node with tables and reads fields into
+//! locals or writes them, keyed by a parameter or a small constant, sometimes
+//! under an `if`. The same configuration always gives the same workload.
+//! Dense workloads have very many mixed cycles, so bound their search with
+//! `CycleLimits`.
+//!
+//! ```
+//! use FMitF_rs::generator::{generate, WorkloadConfig};
+//! use FMitF_rs::{CfgBuilder, SCGraph};
+//!
+//! let config = WorkloadConfig {
+//!     transactions: 6,
+//!     ..WorkloadConfig::default()
+//! };
+//! let program = generate(&config).build().unwrap();
+//! assert_eq!(program.root_functions.len(), 6);
+//!
+//! // As source, e.g. to benchmark the command line tool
+//! let source = FMitF_rs::format_program(&program);
+//! assert_eq!(source, FMitF_rs::format_program(&generate(&config).build().unwrap()));
+//! assert!(FMitF_rs::parse_and_analyze(&source).is_ok());
+//!
+//! // Without writes nothing conflicts
+//! let read_only = WorkloadConfig {
+//!     conflict_density: 0.0,
+//!     ..config
+//! };
+//! let cfg = CfgBuilder::build_from_program(&generate(&read_only).build().unwrap())
+//!     .unwrap()
+//!     .program;
+//! assert!(SCGraph::new(&cfg).find_mixed_cycles().is_empty());
+//! ```
+
+use crate::ast::program_builder::{Expr, Field, FunctionBuilder, ProgramBuilder, Stmt};
+use crate::ast::{BinaryOp, TypeName};
+
+/// The shape of a generated workload; counts below one are taken as one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorkloadConfig {
+    pub seed: u64,
+    pub nodes: usize,
+    pub tables: usize,
+    /// Fields besides the primary key
+    pub fields_per_table: usize,
+    pub transactions: usize,
+    /// Hops per transaction, at most
+    pub max_hops: usize,
+    /// Statements per hop, at most
+    pub max_statements: usize,
+    /// The share of table accesses that are writes, from 0 for a read-only
+    /// workload to 1 for one that only writes
+    pub conflict_density: f64,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            nodes: 2,
+            tables: 4,
+            fields_per_table: 2,
+            transactions: 4,
+            max_hops: 3,
+            max_statements: 3,
+            conflict_density: 0.5,
+        }
+    }
+}
+
+/// A random workload shaped by `config`, to build or extend.
+pub fn generate(config: &WorkloadConfig) -> ProgramBuilder {
+    let mut rng = XorShift(config.seed.max(1));
+    let nodes = config.nodes.max(1);
+    let fields = config.fields_per_table.max(1);
+
+    let mut builder = ProgramBuilder::new();
+    for node in 0..nodes {
+        builder = builder.node(format!("N{}", node));
+    }
+    // Spread the tables over the nodes before placing the rest at random
+    let mut tables_on: Vec<Vec<String>> = vec![Vec::new(); nodes];
+    for table in 0..config.tables.max(1) {
+        let node = if table < nodes {
+            table
+        } else {
+            rng.below(nodes)
+        };
+        let name = format!("T{}", table);
+        let columns = std::iter::once(Field::primary(TypeName::Int, "id"))
+            .chain((0..fields).map(|field| Field::new(TypeName::Int, format!("f{}", field))));
+        builder = builder.table(&name, format!("N{}", node), columns);
+        tables_on[node].push(name);
+    }
+    let placed: Vec<usize> = (0..nodes)
+        .filter(|&node| !tables_on[node].is_empty())
+        .collect();
+
+    for transaction in 0..config.transactions.max(1) {
+        let params: Vec<String> = (0..1 + rng.below(2)).map(|i| format!("p{}", i)).collect();
+        let mut function = FunctionBuilder::new(format!("t{}", transaction));
+        for param in &params {
+            function = function.param(TypeName::Int, param);
+        }
+        let mut hop = HopGenerator {
+            rng: &mut rng,
+            config,
+            fields,
+            params: &params,
+            locals: Vec::new(),
+        };
+        for _ in 0..1 + hop.rng.below(config.max_hops.max(1)) {
+            let node = *hop.rng.choose(&placed);
+            let statements = (0..1 + hop.rng.below(config.max_statements.max(1)))
+                .map(|_| hop.statement(&tables_on[node]))
+                .collect::<Vec<_>>();
+            function = function.hop(format!("N{}", node), statements);
+        }
+        builder = builder.function(function);
+    }
+    builder
+}
+
+/// Draws the statements of the hops of one transaction.
+struct HopGenerator<'a> {
+    rng: &'a mut XorShift,
+    config: &'a WorkloadConfig,
+    fields: usize,
+    params: &'a [String],
+    /// Declared by earlier statements, in scope for the rest of the function
+    locals: Vec<String>,
+}
+
+impl HopGenerator<'_> {
+    fn statement(&mut self, tables: &[String]) -> Stmt {
+        if !self.rng.chance(self.config.conflict_density) {
+            let local = format!("v{}", self.locals.len());
+            let read = self.access(tables);
+            self.locals.push(local.clone());
+            return Stmt::var(TypeName::Int, local, Some(read));
+        }
+        let (table, key, field) = self.place(tables);
+        let amount = Expr::int(1 + self.rng.below(4) as i64);
+        let value = Expr::binary(self.operand(tables), BinaryOp::Add, amount);
+        let write = Stmt::assign(table, [("id", key)], field, value);
+        if self.rng.below(4) == 0 {
+            let condition = Expr::binary(self.operand(tables), BinaryOp::Gt, self.constant());
+            Stmt::if_then(condition, [write])
+        } else {
+            write
+        }
+    }
+
+    /// A local, a parameter or a field of one of `tables`.
+    fn operand(&mut self, tables: &[String]) -> Expr {
+        match self.rng.below(3) {
+            0 if !self.locals.is_empty() => Expr::var(self.rng.choose(&self.locals).clone()),
+            1 => Expr::var(self.rng.choose(self.params).clone()),
+            _ => self.access(tables),
+        }
+    }
+
+    fn access(&mut self, tables: &[String]) -> Expr {
+        let (table, key, field) = self.place(tables);
+        Expr::field(table, [("id", key)], field)
+    }
+
+    /// A table of `tables`, a key into it and one of its fields.
+    fn place(&mut self, tables: &[String]) -> (String, Expr, String) {
+        let table = self.rng.choose(tables).clone();
+        let key = if self.rng.coin() {
+            Expr::var(self.rng.choose(self.params).clone())
+        } else {
+            self.constant()
+        };
+        (table, key, format!("f{}", self.rng.below(self.fields)))
+    }
+
+    fn constant(&mut self) -> Expr {
+        Expr::int(self.rng.below(4) as i64)
+    }
+}
+
+/// A small deterministic generator, so a seed gives the same choices each run
+pub(crate) struct XorShift(pub(crate) u64);
+
+impl XorShift {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub(crate) fn coin(&mut self) -> bool {
+        self.next() & 1 == 0
+    }
+
+    pub(crate) fn choose<'a, T>(&mut self, values: &'a [T]) -> &'a T {
+        &values[self.below(values.len())]
+    }
+
+    /// A number below `bound`, which must not be zero.
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    /// Whether an event of probability `p` happens.
+    pub(crate) fn chance(&mut self, p: f64) -> bool {
+        let unit = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        unit < p
+    }
+}
@@ -4,18 +4,33 @@ pub mod ast;
 pub mod cfg;
 pub mod cli;
 pub mod dataflow;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod generator;
+pub mod lsp;
+pub mod memory;
 pub mod optimization;
+pub mod passes;
+pub mod pipeline;
+pub mod playground;
 pub mod pretty;
 pub mod runtime;
 pub mod sc_graph;
+pub mod sql;
 pub mod verification;
+// Re-export the staged pipeline
+pub use pipeline::{Pipeline, PipelineError};
+
 // Re-export AST functionality
-pub use ast::{parse_and_analyze, Program as AstProgram, Span as AstSpan};
+pub use ast::{
+    parse_and_analyze, parse_and_analyze_file, Program as AstProgram, Span as AstSpan,
+};
 pub use ast::{AstError, Results as AstResults, SpannedError as AstSpannedError};
 
 // Re-export pretty printing
 pub use pretty::ast_printer::{print_program, PrintMode, PrintOptions};
 pub use pretty::cfg_printer::{print_cfg, CfgFormat, CfgPrintOptions};
+pub use pretty::formatter::format_program;
 pub use pretty::sc_graph_printer::{print_sc_graph, SCGraphFormat, SCGraphPrintOptions};
 
 // Re-export CFG functionality
@@ -29,6 +44,23 @@ pub use cfg::{
 // Re-export SC-Graph functionality
 pub use sc_graph::{EdgeType as SCGraphEdgeType, SCGraph};
 
-// Re-export verification (currently commented out)
-// pub use verification::{AutoVerifier, VerificationError, VerificationOptions};
+// Re-export verification
+pub use verification::{VerificationManager, VerificationResult};
 pub use optimization::CfgOptimizer;
+
+// Programs and what is built from them hold no `Rc` or `Cell`, so they can be
+// shared by threads analyzing in parallel; a verification manager can be moved
+// to another thread.
+const _: fn() = || {
+    fn shared<T: Send + Sync>() {}
+    fn sent<T: Send>() {}
+    shared::<ast::Program>();
+    shared::<ast::AnalysisReport>();
+    shared::<cfg::CfgProgram>();
+    shared::<cfg::CfgCtx>();
+    shared::<SCGraph>();
+    shared::<pipeline::Chopped>();
+    shared::<verification::report::VerificationReport>();
+    sent::<VerificationManager>();
+    sent::<pipeline::Verified>();
+};
@@ -0,0 +1,424 @@
+//! A language server for TransAct, speaking the Language Server Protocol on
+//! stdin and stdout (`fmitf lsp`).
+//!
+//! Each open document is analyzed as the root of a program, with its imports
+//! read from disk. Edits go through `ast::incremental::reparse`, so typing in a
+//! function re-analyzes only that function. The functions it replaces are
+//! dropped by an occasional full rebuild, and names are freed with the last
+//! program that uses them, so memory and the time an edit takes stay steady
+//! over a long session. The server publishes the errors and warnings of each
+//! document, and answers hover, go-to-definition and document symbol requests
+//! from the last program that analyzed cleanly.
+//!
+//! ```
+//! use FMitF_rs::lsp::protocol::{read_message, write_message};
+//! use FMitF_rs::lsp::Server;
+//! use serde_json::json;
+//!
+//! let text = "nodes { A }\ntable T on A { primary int id; int v; }\nvoid f(int x) { hop on A { T[id: x].v = x; } }\n";
+//! let uri = "file:///tmp/bank.transact";
+//! let position = json!({ "textDocument": { "uri": uri }, "position": { "line": 2, "character": 36 } });
+//! // A body that is not JSON is answered with a parse error, and the rest served
+//! let mut input = b"Content-Length: 6\r\n\r\n{oops}".to_vec();
+//! for (id, method, params) in [
+//!     (Some(1), "initialize", json!({})),
+//!     (None, "textDocument/didOpen", json!({ "textDocument": { "uri": uri, "text": text } })),
+//!     (Some(2), "textDocument/hover", position.clone()),
+//!     (Some(3), "textDocument/definition", position),
+//!     (Some(4), "shutdown", json!(null)),
+//!     (None, "exit", json!(null)),
+//! ] {
+//!     let mut message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+//!     if let Some(id) = id {
+//!         message["id"] = json!(id);
+//!     }
+//!     write_message(&mut input, &message).unwrap();
+//! }
+//!
+//! let mut output = Vec::new();
+//! Server::default().run(&input[..], &mut output).unwrap();
+//! let mut output = &output[..];
+//! let mut replies = Vec::new();
+//! while let Some(message) = read_message(&mut output).unwrap() {
+//!     replies.push(message);
+//! }
+//!
+//! assert_eq!(replies[0]["error"]["code"], -32700);
+//! assert_eq!(replies[0]["id"], json!(null));
+//! assert_eq!(replies[1]["result"]["capabilities"]["hoverProvider"], true);
+//! assert_eq!(replies[2]["method"], "textDocument/publishDiagnostics");
+//! assert_eq!(replies[2]["params"]["diagnostics"], json!([]));
+//! // The `v` of `T[id: x].v` is an int field, declared on line 1
+//! assert!(replies[3]["result"]["contents"]["value"].as_str().unwrap().contains("int v"));
+//! assert_eq!(replies[4]["result"]["range"]["start"], json!({ "line": 1, "character": 35 }));
+//! ```
+
+pub mod navigation;
+pub mod protocol;
+
+use crate::ast::incremental::{reparse, TextEdit};
+use crate::ast::{
+    parse_and_analyze_linked, AnalysisConfig, AstError, Diagnostic, Program, Severity, Span,
+};
+use navigation::{document_symbols, hover, name_range, occurrence_at};
+use protocol::*;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// The state of a language server: its analysis settings and open documents.
+#[derive(Default)]
+pub struct Server {
+    defines: HashSet<String>,
+    links: Vec<PathBuf>,
+    config: AnalysisConfig,
+    /// Open documents by URI
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+/// An open document and the last program it analyzed to without errors.
+struct Document {
+    /// `None` for documents not saved to a file
+    path: Option<PathBuf>,
+    text: String,
+    program: Option<Program>,
+}
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+impl Server {
+    /// A server analyzing documents with the `#if` features in `defines`,
+    /// the files in `links` and `config`, like the other modes.
+    pub fn new(defines: HashSet<String>, links: Vec<PathBuf>, config: AnalysisConfig) -> Self {
+        Self {
+            defines,
+            links,
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Serves the messages of `input` until the client exits. A message that
+    /// is not JSON is answered with a parse error and the next one read; only
+    /// the end of the input or a failure to read it ends the server.
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> Result<(), String> {
+        while let Some(body) = read_body(&mut input)? {
+            let message = match parse_body(&body) {
+                Ok(message) => message,
+                Err(e) => {
+                    write_message(&mut output, &error_response(&Value::Null, PARSE_ERROR, &e))?;
+                    continue;
+                }
+            };
+            let params = &message["params"];
+            match (message["method"].as_str(), message.get("id")) {
+                (Some("exit"), _) => return self.exit(),
+                (Some(method), Some(id)) => {
+                    let reply = match self.request(method, params) {
+                        Ok(result) => response(id, result),
+                        Err((code, message)) => error_response(id, code, &message),
+                    };
+                    write_message(&mut output, &reply)?;
+                }
+                (Some(method), None) => {
+                    for reply in self.notify(method, params) {
+                        write_message(&mut output, &reply)?;
+                    }
+                }
+                // Responses; the server sends no requests
+                (None, _) => {}
+            }
+        }
+        self.exit()
+    }
+
+    fn exit(&self) -> Result<(), String> {
+        match self.shutdown {
+            true => Ok(()),
+            false => Err("The client exited without shutting the server down".to_string()),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "The server is shut down".to_string()));
+        }
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    // Incremental changes
+                    "textDocumentSync": { "openClose": true, "change": 2, "save": true },
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => Ok(self.hover(params).unwrap_or(Value::Null)),
+            "textDocument/definition" => Ok(self.definition(params).unwrap_or(Value::Null)),
+            "textDocument/documentSymbol" => Ok(self
+                .document(params)
+                .map(|(document, program)| document_symbols(program, document.path.as_deref()))
+                .unwrap_or(json!([]))),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    /// Handles a notification, returning the notifications to send back.
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let document = Document {
+                    path: uri_to_path(uri),
+                    text: params["textDocument"]["text"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    program: None,
+                };
+                self.documents.insert(uri.to_string(), document);
+                self.analyze(uri, false).into_iter().collect()
+            }
+            "textDocument/didChange" => {
+                let Some(document) = self.documents.get_mut(uri) else {
+                    return Vec::new();
+                };
+                for change in params["contentChanges"].as_array().into_iter().flatten() {
+                    let text = change["text"].as_str().unwrap_or_default();
+                    match change.get("range") {
+                        Some(range) => {
+                            let index = LineIndex::new(&document.text);
+                            let start = index.offset(&range["start"]);
+                            let end = index.offset(&range["end"]).max(start);
+                            document.text.replace_range(start..end, text);
+                        }
+                        None => document.text = text.to_string(),
+                    }
+                }
+                self.analyze(uri, false).into_iter().collect()
+            }
+            // The imports of other documents are read from disk, so a saved
+            // import changes them
+            "textDocument/didSave" => {
+                let Some(saved) = uri_to_path(uri) else {
+                    return Vec::new();
+                };
+                let importers: Vec<String> = self
+                    .documents
+                    .iter()
+                    .filter(|(other, document)| {
+                        *other != uri
+                            && document.program.as_ref().is_some_and(|program| {
+                                program
+                                    .source_files
+                                    .iter()
+                                    .any(|file| file.path.as_deref() == Some(saved.as_path()))
+                            })
+                    })
+                    .map(|(other, _)| other.clone())
+                    .collect();
+                importers
+                    .iter()
+                    .filter_map(|importer| self.analyze(importer, true))
+                    .collect()
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Analyzes a document again after its text or imports changed,
+    /// returning its diagnostics to publish. With `reload`, or when the imports
+    /// changed, the program is loaded again rather than patched.
+    fn analyze(&mut self, uri: &str, reload: bool) -> Option<Value> {
+        let document = self.documents.get_mut(uri)?;
+        let mut patched = None;
+        if let (Some(program), false) = (&mut document.program, reload) {
+            let root = program.source_files.len() - 1;
+            let edit = TextEdit::between(root, &program.source_files[root].source, &document.text);
+            match reparse(program, &edit, &self.defines, &self.config) {
+                Ok(_) => patched = Some(program.warnings.clone()),
+                Err(errors)
+                    if !errors
+                        .iter()
+                        .any(|error| matches!(error.error, AstError::InvalidEdit(_))) =>
+                {
+                    patched = Some(errors.into_iter().map(Diagnostic::from).collect());
+                }
+                Err(_) => {}
+            }
+        }
+        let diagnostics = match patched {
+            Some(diagnostics) => diagnostics,
+            None => match parse_and_analyze_linked(
+                &document.text,
+                document.path.as_deref(),
+                &self.links,
+                &self.defines,
+                &self.config,
+            ) {
+                Ok(program) => {
+                    let warnings = program.warnings.clone();
+                    document.program = Some(program);
+                    warnings
+                }
+                Err(errors) => errors.into_iter().map(Diagnostic::from).collect(),
+            },
+        };
+        let diagnostics: Vec<Value> = diagnostics
+            .iter()
+            .map(|diagnostic| lsp_diagnostic(document, uri, diagnostic))
+            .collect();
+        Some(notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        ))
+    }
+
+    /// The document a request is about, with its program.
+    fn document(&self, params: &Value) -> Option<(&Document, &Program)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let document = self.documents.get(uri)?;
+        Some((document, document.program.as_ref()?))
+    }
+
+    /// The byte offset a request points at, in the source of the program
+    /// rather than the text, which may have errors the program does not.
+    fn offset(program: &Program, params: &Value) -> usize {
+        let root = &program.source_files[program.source_files.len() - 1];
+        LineIndex::new(&root.source).offset(&params["position"])
+    }
+
+    fn hover(&self, params: &Value) -> Option<Value> {
+        let (document, program) = self.document(params)?;
+        let path = document.path.as_deref();
+        let occurrence = occurrence_at(program, path, Self::offset(program, params))?;
+        let root = &program.source_files[program.source_files.len() - 1];
+        let range =
+            LineIndex::new(&root.source).range(occurrence.range.start, occurrence.range.end);
+        Some(json!({
+            "contents": { "kind": "markdown", "value": hover(program, occurrence.target) },
+            "range": range,
+        }))
+    }
+
+    fn definition(&self, params: &Value) -> Option<Value> {
+        let (document, program) = self.document(params)?;
+        let path = document.path.as_deref();
+        let occurrence = occurrence_at(program, path, Self::offset(program, params))?;
+        let (span, name) = name_range(program, occurrence.target);
+        let source = program
+            .source_files
+            .iter()
+            .find(|file| file.path == span.file)?;
+        let uri = match &span.file {
+            Some(file) => path_to_uri(file),
+            None => params["textDocument"]["uri"].as_str()?.to_string(),
+        };
+        Some(json!({
+            "uri": uri,
+            "range": LineIndex::new(&source.source).range(name.start, name.end),
+        }))
+    }
+}
+
+/// A diagnostic of the document at `uri` in LSP form. Diagnostics in the
+/// files it imports are shown at its start, naming where they are.
+fn lsp_diagnostic(document: &Document, uri: &str, diagnostic: &Diagnostic) -> Value {
+    let in_document = |span: &Span| span.file.as_deref() == document.path.as_deref();
+    let index = LineIndex::new(&document.text);
+    let mut message = diagnostic.error.message();
+    let range = match &diagnostic.span {
+        Some(span) if in_document(span) => index.range(span.start, span.end),
+        Some(span) => {
+            message = format!("{}: {}", span, message);
+            index.range(0, 0)
+        }
+        None => match &diagnostic.error {
+            AstError::ParseError(text) => match syntax_error(text) {
+                Some((file, line, column, expected))
+                    if file.is_none() || file == document.path.as_deref() =>
+                {
+                    message = expected.to_string();
+                    let start = index.offset(&json!({ "line": line - 1, "character": column - 1 }));
+                    index.range(start, start)
+                }
+                _ => index.range(0, 0),
+            },
+            _ => index.range(0, 0),
+        },
+    };
+    let related: Vec<Value> = diagnostic
+        .notes
+        .iter()
+        .filter_map(|note| {
+            let span = note.span.as_ref()?;
+            let location = match &span.file {
+                _ if in_document(span) => {
+                    json!({ "uri": uri, "range": index.range(span.start, span.end) })
+                }
+                Some(file) => json!({ "uri": path_to_uri(file), "range": line_range(span) }),
+                None => return None,
+            };
+            Some(json!({ "location": location, "message": note.error.message() }))
+        })
+        .collect();
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+    json!({
+        "range": range,
+        "severity": severity,
+        "code": diagnostic.error.error_type(),
+        "source": env!("CARGO_PKG_NAME"),
+        "message": message,
+        "relatedInformation": related,
+    })
+}
+
+/// The file, line, column and expectation of a syntax error, from the
+/// ` --> file:line:column` and `= expected ...` lines of its message.
+pub(crate) fn syntax_error(text: &str) -> Option<(Option<&Path>, usize, usize, &str)> {
+    let location = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("-->"))?;
+    let mut parts = location.trim().rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line = parts.next()?.parse().ok()?;
+    let file = parts.next().map(Path::new);
+    let expected = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("= "))
+        .unwrap_or(text);
+    Some((file, line, column, expected))
+}
+
+/// The range of a span in a file whose text is not at hand, from its lines
+/// and columns.
+fn line_range(span: &Span) -> Value {
+    json!({
+        "start": { "line": span.line - 1, "character": span.column - 1 },
+        "end": { "line": span.end_line - 1, "character": span.end_column - 1 },
+    })
+}
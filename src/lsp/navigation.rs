@@ -0,0 +1,620 @@
+//! What the identifiers of a program name, for hover, go-to-definition and
+//! the outline of a file.
+//!
+//! Spans cover whole declarations, statements and expressions, so the
+//! identifiers in them are found again in the source text, in the order the
+//! grammar writes them.
+
+use crate::ast::*;
+use crate::lsp::protocol::LineIndex;
+use crate::pretty::formatter::type_name;
+use serde_json::{json, Value};
+use std::ops::Range;
+use std::path::Path;
+
+/// A declaration an identifier can name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Node(NodeId),
+    Table(TableId),
+    Field(FieldId),
+    Function(FunctionId),
+    Variable(VarId),
+    /// An index into `Program::templates`.
+    Template(usize),
+}
+
+/// An identifier in a source file and what it names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    pub range: Range<usize>,
+    pub target: Target,
+}
+
+/// The identifiers of the source file at `file` (`None` for an in-memory
+/// source) that name a declaration, in source order.
+///
+/// ```
+/// use FMitF_rs::lsp::navigation::{occurrences, Target};
+///
+/// let source = "nodes { A } table T on A { primary int id; int v; }
+/// void f(int x) { hop on A { T[id: x].v = x; } }";
+/// let program = FMitF_rs::parse_and_analyze(source).unwrap();
+/// let found = occurrences(&program, None);
+///
+/// // The `v` of `T[id: x].v` names the field declared on the first line
+/// let v = source.rfind(".v").unwrap() + 1;
+/// let access = found.iter().find(|o| o.range.start == v).unwrap();
+/// assert!(matches!(access.target, Target::Field(_)));
+/// assert_eq!(found.iter().filter(|o| matches!(o.target, Target::Variable(_))).count(), 3);
+/// ```
+pub fn occurrences(program: &Program, file: Option<&Path>) -> Vec<Occurrence> {
+    let Some(source) = program
+        .source_files
+        .iter()
+        .find(|source| source.path.as_deref() == file)
+    else {
+        return Vec::new();
+    };
+    let mut collector = Collector {
+        program,
+        source: &source.source,
+        file,
+        found: Vec::new(),
+    };
+    collector.program_items();
+    collector
+        .found
+        .sort_by_key(|occurrence| occurrence.range.start);
+    collector.found
+}
+
+/// The identifier at a byte offset of a file, touching it on either side.
+pub fn occurrence_at(program: &Program, file: Option<&Path>, offset: usize) -> Option<Occurrence> {
+    occurrences(program, file)
+        .into_iter()
+        .find(|occurrence| occurrence.range.start <= offset && offset <= occurrence.range.end)
+}
+
+/// The name a target is declared with.
+pub fn target_name(program: &Program, target: Target) -> Symbol {
+    match target {
+        Target::Node(id) => program.nodes[id].name.clone(),
+        Target::Table(id) => program.tables[id].name.clone(),
+        Target::Field(id) => program.fields[id].field_name.clone(),
+        Target::Function(id) => program.functions[id].name.clone(),
+        Target::Variable(id) => program.variables[id].name.clone(),
+        Target::Template(index) => program.templates[index].name.clone(),
+    }
+}
+
+/// The span of a target's declaration.
+pub fn declaration(program: &Program, target: Target) -> &Span {
+    match target {
+        Target::Node(id) => &program.nodes[id].span,
+        Target::Table(id) => &program.tables[id].span,
+        Target::Field(id) => &program.fields[id].span,
+        Target::Function(id) => &program.functions[id].span,
+        Target::Variable(id) => &program.variables[id].defined_at,
+        Target::Template(index) => &program.templates[index].span,
+    }
+}
+
+/// Where the name of a target is declared, within the span of its
+/// declaration, or the whole span if the name is not found in it.
+pub fn name_range(program: &Program, target: Target) -> (&Span, Range<usize>) {
+    let span = declaration(program, target);
+    let range = program
+        .source_files
+        .iter()
+        .find(|source| source.path == span.file)
+        .and_then(|source| {
+            let name = target_name(program, target);
+            // Past the keyword or type in front of the name
+            let keyword = match target {
+                Target::Table(_) => find_word(&source.source, span.start..span.end, "table"),
+                Target::Template(_) => find_word(&source.source, span.start..span.end, "template"),
+                _ => None,
+            };
+            let from = keyword.map_or(span.start, |keyword| keyword.end);
+            find_word(&source.source, from..span.end, &name)
+        });
+    (span, range.unwrap_or(span.start..span.end))
+}
+
+/// Markdown describing a target, with its declaration as code.
+pub fn hover(program: &Program, target: Target) -> String {
+    let (code, text) = match target {
+        Target::Node(id) => {
+            let tables: Vec<String> = program
+                .root_tables
+                .iter()
+                .filter(|&&table| program.tables[table].node == id)
+                .map(|&table| format!("`{}`", program.tables[table].name))
+                .collect();
+            let text = match tables.is_empty() {
+                true => "No tables".to_string(),
+                false => format!("Tables {}", tables.join(", ")),
+            };
+            (format!("node {}", program.nodes[id].name), text)
+        }
+        Target::Table(id) => {
+            let table = &program.tables[id];
+            let mut code = format!(
+                "table {} on {} {{",
+                table.name, program.nodes[table.node].name
+            );
+            for &field in &table.fields {
+                code.push_str(&format!(
+                    "\n    {};",
+                    field_declaration(&program.fields[field])
+                ));
+            }
+            code.push_str("\n}");
+            (
+                code,
+                format!("Table on node `{}`", program.nodes[table.node].name),
+            )
+        }
+        Target::Field(id) => {
+            let table = program
+                .tables
+                .iter()
+                .find(|(_, table)| table.fields.contains(&id))
+                .map_or("?".to_string(), |(_, table)| table.name.to_string());
+            let text = match program.fields[id].is_primary {
+                true => format!("Primary key of `{}`", table),
+                false => format!("Field of `{}`", table),
+            };
+            (field_declaration(&program.fields[id]), text)
+        }
+        Target::Function(id) => {
+            let function = &program.functions[id];
+            let nodes: Vec<String> = function
+                .hops
+                .iter()
+                .map(|&hop| format!("`{}`", program.hops[hop].node_name))
+                .collect();
+            let text = match nodes.len() {
+                0 => "No hops".to_string(),
+                1 => format!("1 hop, on {}", nodes[0]),
+                n => format!("{} hops, on {}", n, nodes.join(", ")),
+            };
+            (signature(program, id), text)
+        }
+        Target::Variable(id) => {
+            let variable = &program.variables[id];
+            let text = match variable.kind {
+                VarKind::Parameter => "Parameter",
+                VarKind::Local => "Local variable",
+            };
+            (
+                format!("{} {}", type_name(&variable.ty), variable.name),
+                text.to_string(),
+            )
+        }
+        Target::Template(index) => {
+            let template = &program.templates[index];
+            let params: Vec<&str> = template.params.iter().map(|param| param.as_str()).collect();
+            (
+                format!("template {}<{}>", template.name, params.join(", ")),
+                "Template".to_string(),
+            )
+        }
+    };
+    format!("```transact\n{}\n```\n{}", code, text)
+}
+
+/// The declarations of the source file at `file` as LSP document symbols,
+/// with the fields of tables and the hops of functions as children.
+pub fn document_symbols(program: &Program, file: Option<&Path>) -> Value {
+    let Some(source) = program
+        .source_files
+        .iter()
+        .find(|source| source.path.as_deref() == file)
+    else {
+        return json!([]);
+    };
+    let index = LineIndex::new(&source.source);
+    let in_file = |span: &Span| span.file.as_deref() == file;
+    let symbol = |target: Target, kind: u32, detail: String, children: Vec<Value>| {
+        let (span, name) = name_range(program, target);
+        json!({
+            "name": target_name(program, target).as_str(),
+            "detail": detail,
+            "kind": kind,
+            "range": index.range(span.start, span.end),
+            "selectionRange": index.range(name.start, name.end),
+            "children": children,
+        })
+    };
+
+    let mut symbols = Vec::new();
+    for &id in &program.root_nodes {
+        if in_file(&program.nodes[id].span) {
+            symbols.push(symbol(
+                Target::Node(id),
+                NAMESPACE,
+                "node".to_string(),
+                vec![],
+            ));
+        }
+    }
+    for &id in &program.root_tables {
+        let table = &program.tables[id];
+        if !in_file(&table.span) {
+            continue;
+        }
+        let fields = table
+            .fields
+            .iter()
+            .map(|&field| {
+                let detail = field_declaration(&program.fields[field]);
+                symbol(Target::Field(field), FIELD, detail, vec![])
+            })
+            .collect();
+        let detail = format!("on {}", program.nodes[table.node].name);
+        symbols.push(symbol(Target::Table(id), STRUCT, detail, fields));
+    }
+    for (index, template) in program.templates.iter().enumerate() {
+        if in_file(&template.span) {
+            let target = Target::Template(index);
+            symbols.push(symbol(target, FUNCTION, "template".to_string(), vec![]));
+        }
+    }
+    for &id in &program.root_functions {
+        let function = &program.functions[id];
+        // Functions expanded from templates have the template's span
+        if !in_file(&function.span) || program.template_instances.iter().any(|i| i.function == id) {
+            continue;
+        }
+        let hops = function
+            .hops
+            .iter()
+            .enumerate()
+            .map(|(position, &hop)| {
+                let hop = &program.hops[hop];
+                let range = index.range(hop.span.start, hop.span.end);
+                json!({
+                    "name": format!("hop {} on {}", position + 1, hop.node_name),
+                    "kind": NAMESPACE,
+                    "range": range,
+                    "selectionRange": range,
+                })
+            })
+            .collect();
+        symbols.push(symbol(
+            Target::Function(id),
+            FUNCTION,
+            signature(program, id),
+            hops,
+        ));
+    }
+    symbols.sort_by_key(|symbol| {
+        let start = &symbol["range"]["start"];
+        (start["line"].as_u64(), start["character"].as_u64())
+    });
+    Value::Array(symbols)
+}
+
+// LSP symbol kinds
+const NAMESPACE: u32 = 3;
+const FIELD: u32 = 8;
+const FUNCTION: u32 = 12;
+const STRUCT: u32 = 23;
+
+fn field_declaration(field: &FieldDeclaration) -> String {
+    let primary = if field.is_primary { "primary " } else { "" };
+    let collation = match field.collation {
+        Some(collation) => format!(" collate {}", collation.name()),
+        None => String::new(),
+    };
+    format!(
+        "{}{} {}{}",
+        primary,
+        type_name(&field.field_type),
+        field.field_name,
+        collation
+    )
+}
+
+fn signature(program: &Program, id: FunctionId) -> String {
+    let function = &program.functions[id];
+    let return_type = match &function.return_type {
+        ReturnType::Void => "void",
+        ReturnType::Type(ty) => type_name(ty),
+    };
+    let params: Vec<String> = function
+        .parameters
+        .iter()
+        .map(|&param| {
+            let param = &program.parameters[param];
+            format!("{} {}", type_name(&param.param_type), param.param_name)
+        })
+        .collect();
+    format!("{} {}({})", return_type, function.name, params.join(", "))
+}
+
+/// The first occurrence of `word` in `range` of the source that is a whole
+/// identifier.
+fn find_word(source: &str, range: Range<usize>, word: &str) -> Option<Range<usize>> {
+    let text = source.get(range.clone())?;
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    text.match_indices(word)
+        .map(|(i, _)| range.start + i)
+        .find(|&start| {
+            let end = start + word.len();
+            !source[..start].chars().next_back().is_some_and(is_ident)
+                && !source[end..].chars().next().is_some_and(is_ident)
+        })
+        .map(|start| start..start + word.len())
+}
+
+struct Collector<'p> {
+    program: &'p Program,
+    source: &'p str,
+    file: Option<&'p Path>,
+    found: Vec<Occurrence>,
+}
+
+impl Collector<'_> {
+    /// Records the first `name` in `from..to` as naming `target`, returning
+    /// where it ends.
+    fn name(
+        &mut self,
+        name: &str,
+        from: usize,
+        to: usize,
+        target: Option<Target>,
+    ) -> Option<usize> {
+        let range = find_word(self.source, from..to, name)?;
+        let end = range.end;
+        if let Some(target) = target {
+            self.found.push(Occurrence { range, target });
+        }
+        Some(end)
+    }
+
+    fn in_file(&self, span: &Span) -> bool {
+        span.file.as_deref() == self.file
+    }
+
+    fn program_items(&mut self) {
+        let program = self.program;
+        for &id in &program.root_nodes {
+            let node = &program.nodes[id];
+            if self.in_file(&node.span) {
+                self.name(
+                    &node.name,
+                    node.span.start,
+                    node.span.end,
+                    Some(Target::Node(id)),
+                );
+            }
+        }
+        for &id in &program.root_tables {
+            let table = &program.tables[id];
+            let span = &table.span;
+            if !self.in_file(span) {
+                continue;
+            }
+            let keyword = find_word(self.source, span.start..span.end, "table");
+            let from = keyword.map_or(span.start, |keyword| keyword.end);
+            if let Some(end) = self.name(&table.name, from, span.end, Some(Target::Table(id))) {
+                let node = Some(Target::Node(table.node));
+                self.name(&program.nodes[table.node].name, end, span.end, node);
+            }
+            for &field in &table.fields {
+                let span = &program.fields[field].span;
+                let name = program.fields[field].field_name.clone();
+                self.name(&name, span.start, span.end, Some(Target::Field(field)));
+            }
+        }
+        for instance in &program.template_instances {
+            let span = &instance.span;
+            if !self.in_file(span) {
+                continue;
+            }
+            let template = program
+                .templates
+                .iter()
+                .position(|template| template.name == instance.template)
+                .map(Target::Template);
+            let keyword = find_word(self.source, span.start..span.end, "instantiate");
+            let from = keyword.map_or(span.start, |keyword| keyword.end);
+            self.name(&instance.template, from, span.end, template);
+            // The function's name follows the arguments
+            let args_end = self.source[span.start..span.end]
+                .rfind('>')
+                .map_or(span.start, |i| span.start + i);
+            let name = program.functions[instance.function].name.clone();
+            let function = Some(Target::Function(instance.function));
+            self.name(&name, args_end, span.end, function);
+        }
+        for &id in &program.root_functions {
+            self.function(id);
+        }
+    }
+
+    fn function(&mut self, id: FunctionId) {
+        let program = self.program;
+        let function = &program.functions[id];
+        let span = &function.span;
+        if !self.in_file(span) {
+            return;
+        }
+        self.name(
+            &function.name,
+            span.start,
+            span.end,
+            Some(Target::Function(id)),
+        );
+        for &param in &function.parameters {
+            let param = &program.parameters[param];
+            let variable = param.resolved_var.map(Target::Variable);
+            self.name(
+                &param.param_name,
+                param.span.start,
+                param.span.end,
+                variable,
+            );
+        }
+        for &hop in &function.hops {
+            let hop = &program.hops[hop];
+            let span = &hop.span;
+            let on = find_word(self.source, span.start..span.end, "on");
+            let from = on.map_or(span.start, |on| on.end);
+            let node = hop.resolved_node.map(Target::Node);
+            self.name(&hop.node_name, from, span.end, node);
+            self.statements(&hop.statements);
+        }
+    }
+
+    fn statements(&mut self, statements: &[StatementId]) {
+        for &statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, id: StatementId) {
+        let program = self.program;
+        let span = &program.statements[id].span;
+        match &program.statements[id].node {
+            StatementKind::Assignment(assignment) => {
+                let table = assignment.resolved_table.map(Target::Table);
+                let rhs = program.expressions[assignment.rhs].span.start;
+                let after_key = self.keys(
+                    span,
+                    assignment.table_name.clone(),
+                    table,
+                    &assignment.pk_fields,
+                    &assignment.pk_exprs,
+                    &assignment.resolved_pk_fields,
+                );
+                let field = assignment.resolved_field.map(Target::Field);
+                self.name(&assignment.field_name, after_key, rhs, field);
+                self.expression(assignment.rhs);
+            }
+            StatementKind::MultiAssignment(assignment) => {
+                let table = assignment.resolved_table.map(Target::Table);
+                let mut from = self.keys(
+                    span,
+                    assignment.table_name.clone(),
+                    table,
+                    &assignment.pk_fields,
+                    &assignment.pk_exprs,
+                    &assignment.resolved_pk_fields,
+                );
+                for pair in &assignment.assignments {
+                    let rhs = &program.expressions[pair.rhs].span;
+                    let field = pair.resolved_field.map(Target::Field);
+                    self.name(&pair.field_name, from, rhs.start, field);
+                    self.expression(pair.rhs);
+                    from = rhs.end;
+                }
+            }
+            StatementKind::VarAssignment(assignment) => {
+                let rhs = program.expressions[assignment.rhs].span.start;
+                let variable = assignment.resolved_var.map(Target::Variable);
+                self.name(&assignment.var_name, span.start, rhs, variable);
+                self.expression(assignment.rhs);
+            }
+            StatementKind::IfStmt(statement) => {
+                self.expression(statement.condition);
+                self.statements(&statement.then_branch);
+                if let Some(else_branch) = &statement.else_branch {
+                    self.statements(else_branch);
+                }
+            }
+            StatementKind::WhileStmt(statement) => {
+                self.expression(statement.condition);
+                self.statements(&statement.body);
+            }
+            StatementKind::VarDecl(declaration) => {
+                let end = declaration
+                    .init_value
+                    .map_or(span.end, |init| program.expressions[init].span.start);
+                let variable = declaration.resolved_var.map(Target::Variable);
+                self.name(&declaration.var_name, span.start, end, variable);
+                if let Some(init) = declaration.init_value {
+                    self.expression(init);
+                }
+            }
+            StatementKind::Return(statement) => {
+                if let Some(value) = statement.value {
+                    self.expression(value);
+                }
+            }
+            StatementKind::Abort(_)
+            | StatementKind::Break(_)
+            | StatementKind::Continue(_)
+            | StatementKind::Empty => {}
+        }
+    }
+
+    /// Records the table and key fields of a `T[k: e, ...]` at the start of
+    /// `span`, returning where the keys end.
+    fn keys(
+        &mut self,
+        span: &Span,
+        table_name: Symbol,
+        table: Option<Target>,
+        pk_fields: &[Symbol],
+        pk_exprs: &[ExpressionId],
+        resolved: &[Option<FieldId>],
+    ) -> usize {
+        let mut from = self
+            .name(&table_name, span.start, span.end, table)
+            .unwrap_or(span.start);
+        for (i, (name, &expr)) in pk_fields.iter().zip(pk_exprs).enumerate() {
+            let expr_span = &self.program.expressions[expr].span;
+            let field = resolved.get(i).copied().flatten().map(Target::Field);
+            self.name(name, from, expr_span.start, field);
+            self.expression(expr);
+            from = expr_span.end;
+        }
+        from
+    }
+
+    fn expression(&mut self, id: ExpressionId) {
+        let program = self.program;
+        let span = &program.expressions[id].span;
+        match &program.expressions[id].node {
+            ExpressionKind::Ident(name) => {
+                let variable = program.resolutions.get(&id).copied().map(Target::Variable);
+                self.name(name, span.start, span.end, variable);
+            }
+            ExpressionKind::TableFieldAccess {
+                table_name,
+                pk_fields,
+                pk_exprs,
+                field_name,
+                resolved_table,
+                resolved_pk_fields,
+                resolved_field,
+                ..
+            } => {
+                let table = resolved_table.map(Target::Table);
+                let after_key = self.keys(
+                    span,
+                    table_name.clone(),
+                    table,
+                    pk_fields,
+                    pk_exprs,
+                    resolved_pk_fields,
+                );
+                let field = resolved_field.map(Target::Field);
+                self.name(field_name, after_key, span.end, field);
+            }
+            ExpressionKind::UnaryOp { expr, .. } => self.expression(*expr),
+            ExpressionKind::BinaryOp { left, right, .. } => {
+                self.expression(*left);
+                self.expression(*right);
+            }
+            ExpressionKind::IntLit(_)
+            | ExpressionKind::FloatLit(_)
+            | ExpressionKind::StringLit(_)
+            | ExpressionKind::BoolLit(_) => {}
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Language Server Protocol plumbing: the JSON-RPC framing, positions and
+//! file URIs.
+
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Reads one message, `Ok(None)` at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> Result<Option<Value>, String> {
+    match read_body(input)? {
+        Some(body) => parse_body(&body).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Reads the body of one message as it was sent, `Ok(None)` at the end of
+/// the input. Errors are of the input or its headers, after which no more
+/// messages can be told apart.
+pub fn read_body(input: &mut impl BufRead) -> Result<Option<Vec<u8>>, String> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        let read = input
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read a message header: {}", e))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|_| format!("Invalid Content-Length '{}'", value.trim()))?,
+                );
+            }
+        }
+    }
+    let length = length.ok_or("Message without a Content-Length header")?;
+    let mut body = vec![0; length];
+    input
+        .read_exact(&mut body)
+        .map_err(|e| format!("Failed to read a message: {}", e))?;
+    Ok(Some(body))
+}
+
+/// The message of a body, or why it is not JSON.
+pub fn parse_body(body: &[u8]) -> Result<Value, String> {
+    serde_json::from_slice(body).map_err(|e| format!("Invalid message: {}", e))
+}
+
+/// Writes one message with its header.
+pub fn write_message(output: &mut impl Write, message: &Value) -> Result<(), String> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush())
+        .map_err(|e| format!("Failed to write a message: {}", e))
+}
+
+/// The response to the request `id`.
+pub fn response(id: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+/// The error response to the request `id`.
+pub fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// A notification from the server.
+pub fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// Converts between byte offsets and the positions of the protocol, whose
+/// characters are UTF-16 code units.
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        Self { text, line_starts }
+    }
+
+    /// The position of a byte offset.
+    pub fn position(&self, offset: usize) -> Value {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let character: usize = self.text[start..offset].chars().map(char::len_utf16).sum();
+        json!({ "line": line, "character": character })
+    }
+
+    /// The range of a byte range.
+    pub fn range(&self, start: usize, end: usize) -> Value {
+        json!({ "start": self.position(start), "end": self.position(end) })
+    }
+
+    /// The byte offset of a position, clamped to its line.
+    pub fn offset(&self, position: &Value) -> usize {
+        let line = position["line"].as_u64().unwrap_or(0) as usize;
+        let Some(&start) = self.line_starts.get(line) else {
+            return self.text.len();
+        };
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.text.len(), |&next| next - 1);
+        let mut units = position["character"].as_u64().unwrap_or(0) as usize;
+        for (i, c) in self.text[start..end].char_indices() {
+            if units < c.len_utf16() {
+                return start + i;
+            }
+            units -= c.len_utf16();
+        }
+        end
+    }
+}
+
+/// The path of a `file:` URI.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8(decoded).ok()?;
+    // Windows drive letters come as /C:/...
+    match path.as_bytes() {
+        [b'/', _, b':', ..] if cfg!(windows) => Some(PathBuf::from(&path[1..])),
+        _ => Some(PathBuf::from(path)),
+    }
+}
+
+/// The `file:` URI of a path.
+pub fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
@@ -1,45 +1,130 @@
-use clap::Parser;
-use colored::*;
-use std::fs;
+use FMitF_rs::cli::{
+    init_logging, set_json_events, Cli, ColorChoice, Command, CountingAllocator, Mode, Pipeline,
+    ProgressStyle,
+};
 
-use FMitF_rs::cli::{Cli, Pipeline};
+/// Exit code of a run with findings, e.g. warnings in check mode or mixed
+/// cycles with --fail-on-cycles; a clean run exits 0
+const FINDINGS: i32 = 1;
+/// Exit code of a run that failed, in any mode
+const FAILURE: i32 = 2;
+
+/// Exit with the code for how many findings a run had
+fn finish(findings: usize) {
+    if findings > 0 {
+        std::process::exit(FINDINGS);
+    }
+}
+
+/// Log the error of a failed run and exit
+fn fail(message: &str) -> ! {
+    tracing::error!("{}", message);
+    std::process::exit(FAILURE);
+}
+
+/// Report the phases of a run with --timings
+fn report_timings(cli: &Cli, pipeline: &Pipeline) {
+    if let Err(e) = pipeline.report_timings(cli) {
+        fail(&e);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
 fn main() {
-    let cli = Cli::parse();
+    let cli = Cli::parse_args();
 
-    // Handle no-color option using colored::control
-    if cli.no_color {
-        colored::control::set_override(false);
+    // Auto leaves colors to colored, which checks the terminal and NO_COLOR
+    match cli.color_choice() {
+        ColorChoice::Auto => {}
+        ColorChoice::Always => colored::control::set_override(true),
+        ColorChoice::Never => colored::control::set_override(false),
     }
+    FMitF_rs::pretty::set_plain(cli.plain);
+    init_logging(&cli);
+    set_json_events(cli.progress == Some(ProgressStyle::Json));
 
     // Validate CLI arguments
     if let Err(e) = cli.validate() {
-        eprintln!("{} {}", "ERROR:".red().bold(), e.bright_red());
-        std::process::exit(1);
+        fail(&e);
+    }
+
+    // The language server reads its documents from the editor
+    if let Some(Command::Lsp { .. }) = cli.command {
+        if let Err(e) = Pipeline::new(&cli).and_then(|pipeline| pipeline.lsp()) {
+            fail(&e);
+        }
+        return;
     }
 
-    // Read source file
-    let source_code = match fs::read_to_string(&cli.input) {
+    // A new project is written rather than read
+    if let Some(Command::Init { dir }) = &cli.command {
+        if let Err(e) = Pipeline::new(&cli).and_then(|mut pipeline| pipeline.init(dir)) {
+            fail(&e);
+        }
+        return;
+    }
+
+    // A replay reads proof artifacts rather than a source file
+    if cli.replay {
+        match Pipeline::new(&cli).and_then(|pipeline| pipeline.replay(&cli)) {
+            Ok(failed) => return finish(failed),
+            Err(e) => {
+                fail(&e);
+            }
+        }
+    }
+
+    // Several inputs are read as each is processed
+    if cli.is_batch() {
+        match Pipeline::new(&cli).and_then(|pipeline| pipeline.batch(&cli)) {
+            Ok(found) => return finish(found),
+            Err(e) => {
+                fail(&e);
+            }
+        }
+    }
+
+    // Read source file, or stdin for -
+    let source_code = match cli.read_input() {
         Ok(content) => content,
         Err(e) => {
-            eprintln!(
-                "{} Failed to read file {:?}: {}",
-                "ERROR:".red().bold(),
-                cli.input,
-                e.to_string().bright_red()
-            );
-            std::process::exit(1);
+            fail(&e);
         }
     };
 
     // Create and execute pipeline
-    let mut pipeline = Pipeline::new(&cli);
-    if let Err(e) = pipeline.execute(source_code, cli.mode.clone(), &cli) {
-        eprintln!(
-            "{} Pipeline execution failed: {}",
-            "ERROR:".red().bold(),
-            e.bright_red()
-        );
-        std::process::exit(1);
+    let mut pipeline = match Pipeline::new(&cli) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            fail(&e);
+        }
+    };
+    if cli.watch {
+        if let Err(e) = pipeline.watch(source_code, &cli) {
+            fail(&e);
+        }
+        return;
+    }
+    if cli.mode == Mode::Check {
+        match pipeline.check(source_code, &cli) {
+            Ok(warned) => {
+                report_timings(&cli, &pipeline);
+                return finish(warned);
+            }
+            Err(e) => {
+                fail(&e);
+            }
+        }
+    }
+    match pipeline.execute(source_code, cli.mode.clone(), &cli) {
+        Ok(findings) => {
+            report_timings(&cli, &pipeline);
+            finish(findings)
+        }
+        Err(e) => {
+            fail(&format!("Pipeline execution failed: {}", e));
+        }
     }
 }
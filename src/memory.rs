@@ -0,0 +1,90 @@
+//! How many elements the arenas of a program hold and roughly how many bytes
+//! they take, for measuring the memory of large workloads across versions.
+//!
+//! The bytes of an arena are the inline size of its elements times their
+//! number: what the elements own on the heap, such as names and lists, is not
+//! counted, nor is the spare capacity of the arena. `--timings` reports the
+//! arenas of the AST and of the CFG after the phases that build them.
+//!
+//! ```
+//! use FMitF_rs::parse_and_analyze;
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void f(int id) { hop on A { T[id: id].n = 1; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let stats = program.memory_stats();
+//! assert_eq!(stats.count("tables"), 1);
+//! assert_eq!(stats.count("fields"), 2);
+//! assert!(stats.total_bytes() > 0);
+//! ```
+
+use id_arena::Arena;
+use serde_json::{json, Value};
+
+/// The elements of one arena and the bytes they take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArenaStats {
+    pub name: &'static str,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+/// The arenas of a program, in declaration order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub arenas: Vec<ArenaStats>,
+}
+
+impl MemoryStats {
+    /// Counts the elements of `arena` under `name`, adding to those counted
+    /// under it before, as the arenas of each CFG function are.
+    pub fn add<T>(&mut self, name: &'static str, arena: &Arena<T>) {
+        let bytes = arena.len() * std::mem::size_of::<T>();
+        match self.arenas.iter_mut().find(|stats| stats.name == name) {
+            Some(stats) => {
+                stats.count += arena.len();
+                stats.bytes += bytes;
+            }
+            None => self.arenas.push(ArenaStats {
+                name,
+                count: arena.len(),
+                bytes,
+            }),
+        }
+    }
+
+    /// The elements counted under `name`, 0 if none.
+    pub fn count(&self, name: &str) -> usize {
+        self.arenas
+            .iter()
+            .find(|stats| stats.name == name)
+            .map_or(0, |stats| stats.count)
+    }
+
+    pub fn total_count(&self) -> usize {
+        self.arenas.iter().map(|stats| stats.count).sum()
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.arenas.iter().map(|stats| stats.bytes).sum()
+    }
+
+    /// A record per arena (`arena`, `count`, `bytes`) and their totals.
+    pub fn to_json(&self) -> Value {
+        let arenas: Vec<Value> = self
+            .arenas
+            .iter()
+            .map(|stats| json!({ "arena": stats.name, "count": stats.count, "bytes": stats.bytes }))
+            .collect();
+        json!({
+            "arenas": arenas,
+            "count": self.total_count(),
+            "bytes": self.total_bytes(),
+        })
+    }
+}
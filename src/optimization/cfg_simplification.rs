@@ -0,0 +1,270 @@
+use crate::cfg::{BasicBlockId, FunctionCfg, Terminator};
+use crate::optimization::{detach_unreachable_blocks, OptimizationPass};
+use std::collections::{HashMap, HashSet};
+
+/// CFG Simplification optimization pass
+///
+/// Jumps over empty blocks, merges straight-line blocks into their only
+/// predecessor, and drops the blocks this leaves unreachable. The remaining
+/// blocks are renumbered hop by hop in depth-first order from each hop's entry,
+/// taking `then` before `else`, so the numbering only depends on the shape of
+/// the CFG.
+pub struct CfgSimplificationPass;
+
+impl OptimizationPass for CfgSimplificationPass {
+    fn name(&self) -> &'static str {
+        "CFG Simplification"
+    }
+
+    fn optimize_function(&self, func: &mut FunctionCfg) -> bool {
+        let mut changed = self.skip_empty_blocks(func);
+        changed |= self.merge_blocks(func);
+        changed |= detach_unreachable_blocks(func);
+        changed |= self.renumber_blocks(func);
+        changed
+    }
+}
+
+impl Default for CfgSimplificationPass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CfgSimplificationPass {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// The block an empty block jumps to, if it has no statements and only jumps on.
+    fn is_empty_jump(func: &FunctionCfg, block: BasicBlockId) -> Option<BasicBlockId> {
+        let block = &func.blocks[block];
+        match block.terminator {
+            Terminator::Goto(target) if block.statements.is_empty() => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Follows a chain of empty jumps to the first block that does something.
+    /// A chain that loops back on itself is left alone.
+    fn resolve_target(func: &FunctionCfg, block: BasicBlockId) -> BasicBlockId {
+        let mut visited = HashSet::from([block]);
+        let mut current = block;
+        while let Some(target) = Self::is_empty_jump(func, current) {
+            if !visited.insert(target) {
+                return block;
+            }
+            current = target;
+        }
+        current
+    }
+
+    /// Redirects jumps and hop entries past empty blocks. A jump to an empty
+    /// block that returns, aborts or exits the hop takes over its terminator.
+    fn skip_empty_blocks(&self, func: &mut FunctionCfg) -> bool {
+        let mut changed = false;
+
+        let block_ids: Vec<BasicBlockId> = func.blocks.iter().map(|(id, _)| id).collect();
+        for block_id in block_ids {
+            if !func.contains_block(block_id) {
+                continue;
+            }
+            let new_terminator = match &func.blocks[block_id].terminator {
+                Terminator::Goto(target) => {
+                    let target = Self::resolve_target(func, *target);
+                    let target_block = &func.blocks[target];
+                    match &target_block.terminator {
+                        Terminator::Return(_)
+                        | Terminator::Abort
+                        | Terminator::BoundExceeded(_)
+                        | Terminator::HopExit { .. }
+                            if target_block.statements.is_empty() =>
+                        {
+                            let origin = target_block.terminator_origin;
+                            let span = target_block.terminator_span.clone();
+                            let terminator = target_block.terminator.clone();
+                            let block = &mut func.blocks[block_id];
+                            block.terminator_origin = origin;
+                            block.terminator_span = span;
+                            terminator
+                        }
+                        _ => Terminator::Goto(target),
+                    }
+                }
+                Terminator::Branch {
+                    condition,
+                    then_block,
+                    else_block,
+                } => Terminator::Branch {
+                    condition: condition.clone(),
+                    then_block: Self::resolve_target(func, *then_block),
+                    else_block: Self::resolve_target(func, *else_block),
+                },
+                terminator => terminator.clone(),
+            };
+            if new_terminator != func.blocks[block_id].terminator {
+                func.blocks[block_id].terminator = new_terminator;
+                changed = true;
+            }
+        }
+
+        let hop_ids: Vec<_> = func.hops.iter().map(|(id, _)| id).collect();
+        for hop_id in hop_ids {
+            let Some(entry_block) = func.hops[hop_id].entry_block else {
+                continue;
+            };
+            let target = Self::resolve_target(func, entry_block);
+            if target != entry_block {
+                func.hops[hop_id].entry_block = Some(target);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Appends each block whose only predecessor jumps straight to it to that
+    /// predecessor.
+    fn merge_blocks(&self, func: &mut FunctionCfg) -> bool {
+        let mut changed = false;
+
+        // Predecessors within the hop; hop entries are also entered from the
+        // previous hop, so they are never merged
+        let mut predecessor_count: HashMap<BasicBlockId, usize> = HashMap::new();
+        for (block_id, block) in func.blocks.iter() {
+            if !func.contains_block(block_id) {
+                continue;
+            }
+            match &block.terminator {
+                Terminator::Goto(target) => *predecessor_count.entry(*target).or_default() += 1,
+                Terminator::Branch {
+                    then_block,
+                    else_block,
+                    ..
+                } => {
+                    *predecessor_count.entry(*then_block).or_default() += 1;
+                    *predecessor_count.entry(*else_block).or_default() += 1;
+                }
+                Terminator::Return(_)
+                | Terminator::Abort
+                | Terminator::BoundExceeded(_)
+                | Terminator::HopExit { .. } => {}
+            }
+        }
+        let hop_entries: HashSet<BasicBlockId> = func
+            .hops
+            .iter()
+            .filter_map(|(_, hop)| hop.entry_block)
+            .collect();
+
+        // Merged blocks stay attached until unreachable blocks are detached
+        let mut merged = HashSet::new();
+        let block_ids: Vec<BasicBlockId> = func.blocks.iter().map(|(id, _)| id).collect();
+        for block_id in block_ids {
+            if !func.contains_block(block_id) || merged.contains(&block_id) {
+                continue;
+            }
+            // The merged block's terminator may jump to another candidate
+            while let Terminator::Goto(target) = func.blocks[block_id].terminator {
+                if target == block_id
+                    || hop_entries.contains(&target)
+                    || predecessor_count.get(&target) != Some(&1)
+                {
+                    break;
+                }
+                let statements = std::mem::take(&mut func.blocks[target].statements);
+                let terminator = func.blocks[target].terminator.clone();
+                let terminator_origin = func.blocks[target].terminator_origin;
+                let terminator_span = func.blocks[target].terminator_span.clone();
+                let block = &mut func.blocks[block_id];
+                block.statements.extend(statements);
+                block.terminator = terminator;
+                block.terminator_origin = terminator_origin;
+                block.terminator_span = terminator_span;
+                predecessor_count.remove(&target);
+                merged.insert(target);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Rebuilds the block arena in canonical order, dropping detached blocks.
+    fn renumber_blocks(&self, func: &mut FunctionCfg) -> bool {
+        let mut order = Vec::new();
+        let mut seen = HashSet::new();
+        for &hop_id in &func.hop_order {
+            let hop = &func.hops[hop_id];
+            let mut stack: Vec<BasicBlockId> = hop.entry_block.into_iter().collect();
+            while let Some(block_id) = stack.pop() {
+                if !seen.insert(block_id) {
+                    continue;
+                }
+                order.push(block_id);
+                match &func.blocks[block_id].terminator {
+                    Terminator::Goto(target) => stack.push(*target),
+                    Terminator::Branch {
+                        then_block,
+                        else_block,
+                        ..
+                    } => stack.extend([*else_block, *then_block]),
+                    Terminator::Return(_)
+                    | Terminator::Abort
+                    | Terminator::BoundExceeded(_)
+                    | Terminator::HopExit { .. } => {}
+                }
+            }
+            // Blocks of the hop its entry does not reach keep their relative order
+            order.extend(hop.blocks.iter().filter(|&&block_id| seen.insert(block_id)));
+        }
+
+        let unchanged = order.len() == func.blocks.len()
+            && order
+                .iter()
+                .zip(func.blocks.iter())
+                .all(|(&block_id, (old_id, _))| block_id == old_id);
+        if unchanged {
+            return false;
+        }
+
+        let mut old_blocks: Vec<_> = std::mem::take(&mut func.blocks)
+            .into_iter()
+            .map(|(_, block)| Some(block))
+            .collect();
+        let mut new_ids = HashMap::new();
+        for block_id in order {
+            let block = old_blocks[block_id.index()].take().unwrap();
+            new_ids.insert(block_id, func.blocks.alloc(block));
+        }
+
+        for (_, block) in func.blocks.iter_mut() {
+            match &mut block.terminator {
+                Terminator::Goto(target) => *target = new_ids[target],
+                Terminator::Branch {
+                    then_block,
+                    else_block,
+                    ..
+                } => {
+                    *then_block = new_ids[then_block];
+                    *else_block = new_ids[else_block];
+                }
+                Terminator::Return(_)
+                | Terminator::Abort
+                | Terminator::BoundExceeded(_)
+                | Terminator::HopExit { .. } => {}
+            }
+        }
+        for (_, hop) in func.hops.iter_mut() {
+            hop.entry_block = hop.entry_block.map(|block_id| new_ids[&block_id]);
+            hop.blocks = hop
+                .blocks
+                .iter()
+                .map(|block_id| new_ids[block_id])
+                .collect();
+            hop.blocks.sort_by_key(|block_id| block_id.index());
+        }
+
+        true
+    }
+}
@@ -37,7 +37,12 @@ impl OptimizationPass for CommonSubexpressionEliminationPass {
 
             for stmt in &block.statements {
                 match stmt {
-                    Statement::Assign { var, rvalue, span } => {
+                    Statement::Assign {
+                        var,
+                        rvalue,
+                        span,
+                        origin,
+                    } => {
                         // Check if this rvalue is already available
                         if let Some(&existing_var) = expr_to_var.get(rvalue) {
                             // If the expression is available and we have a variable for it,
@@ -47,6 +52,7 @@ impl OptimizationPass for CommonSubexpressionEliminationPass {
                                     var: *var,
                                     rvalue: Rvalue::Use(Operand::Var(existing_var)),
                                     span: span.clone(),
+                                    origin: *origin,
                                 });
                                 changed = true;
                             } else {
@@ -82,6 +88,7 @@ impl OptimizationPass for CommonSubexpressionEliminationPass {
                         field,
                         value: _,
                         span: _,
+                        origin: _,
                     } => {
                         // Table assignments can invalidate expressions that read from the same table/field
                         self.kill_table_expressions(&mut current_available, *table, *field);
@@ -98,6 +105,12 @@ impl OptimizationPass for CommonSubexpressionEliminationPass {
     }
 }
 
+impl Default for CommonSubexpressionEliminationPass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CommonSubexpressionEliminationPass {
     pub fn new() -> Self {
         Self
@@ -1,10 +1,16 @@
-use crate::cfg::{Constant, FunctionCfg, Operand, Rvalue, Statement};
+use crate::cfg::{Constant, FunctionCfg, Operand, Rvalue, Statement, Terminator};
 use crate::dataflow::{analyze_reaching_definitions, ReachingDefinition};
-use crate::optimization::OptimizationPass;
-use std::collections::HashMap;
+use crate::optimization::{detach_unreachable_blocks, OptimizationPass};
+use std::collections::{HashMap, HashSet};
 use std::ptr::NonNull;
 
 /// Constant Propagation optimization pass
+///
+/// Replaces a variable by a constant where every definition reaching the use
+/// assigns that constant, folds the operations this makes constant, and turns
+/// branches on a constant condition into jumps. Blocks no longer reachable from
+/// their hop's entry are detached from the hop, so later stages never see the
+/// table accesses on statically dead paths.
 pub struct ConstantPropagationPass;
 
 // Implement the OptimizationPass trait
@@ -34,23 +40,25 @@ impl OptimizationPass for ConstantPropagationPass {
 
         // Second pass: propagate constants
         for (block_id, block) in func.blocks.iter_mut() {
-            let empty_set = std::collections::HashSet::new();
-            let reaching_defs_at_entry = reaching_defs_results
+            // Definitions reaching the current statement, starting from the block entry
+            let mut reaching_defs = reaching_defs_results
                 .entry
                 .get(&block_id)
-                .map(|set| &set.set)
-                .unwrap_or(&empty_set);
+                .map(|set| set.set.clone())
+                .unwrap_or_default();
 
             let mut new_statements = Vec::new();
 
             for stmt in &block.statements {
                 match stmt {
-                    Statement::Assign { var, rvalue, span } => {
-                        let new_rvalue = self.propagate_in_rvalue(
-                            rvalue,
-                            reaching_defs_at_entry,
-                            &stmt_to_constant,
-                        );
+                    Statement::Assign {
+                        var,
+                        rvalue,
+                        span,
+                        origin,
+                    } => {
+                        let new_rvalue =
+                            self.propagate_in_rvalue(rvalue, &reaching_defs, &stmt_to_constant);
 
                         if new_rvalue != *rvalue {
                             changed = true;
@@ -60,6 +68,14 @@ impl OptimizationPass for ConstantPropagationPass {
                             var: *var,
                             rvalue: new_rvalue,
                             span: span.clone(),
+                            origin: *origin,
+                        });
+
+                        // The assignment kills the other definitions of its variable
+                        reaching_defs.retain(|def| def.var_id != *var);
+                        reaching_defs.insert(ReachingDefinition {
+                            var_id: *var,
+                            site: Some(NonNull::from(stmt)),
                         });
                     }
                     Statement::TableAssign {
@@ -69,22 +85,20 @@ impl OptimizationPass for ConstantPropagationPass {
                         field,
                         value,
                         span,
+                        origin,
                     } => {
                         let new_pk_values: Vec<Operand> = pk_values
                             .iter()
                             .map(|pk_value| {
                                 self.propagate_in_operand(
                                     pk_value,
-                                    reaching_defs_at_entry,
+                                    &reaching_defs,
                                     &stmt_to_constant,
                                 )
                             })
                             .collect();
-                        let new_value = self.propagate_in_operand(
-                            value,
-                            reaching_defs_at_entry,
-                            &stmt_to_constant,
-                        );
+                        let new_value =
+                            self.propagate_in_operand(value, &reaching_defs, &stmt_to_constant);
 
                         // Check if any primary key values changed
                         let pk_values_changed = new_pk_values
@@ -103,18 +117,36 @@ impl OptimizationPass for ConstantPropagationPass {
                             field: *field,
                             value: new_value,
                             span: span.clone(),
+                            origin: *origin,
                         });
                     }
                 }
             }
 
             block.statements = new_statements;
+
+            let new_terminator =
+                self.propagate_in_terminator(&block.terminator, &reaching_defs, &stmt_to_constant);
+            if new_terminator != block.terminator {
+                changed = true;
+                block.terminator = new_terminator;
+            }
+        }
+
+        if detach_unreachable_blocks(func) {
+            changed = true;
         }
 
         changed
     }
 }
 
+impl Default for ConstantPropagationPass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ConstantPropagationPass {
     pub fn new() -> Self {
         Self
@@ -128,11 +160,39 @@ impl ConstantPropagationPass {
         }
     }
 
+    /// Propagate constants in a terminator, folding branches on a constant condition
+    fn propagate_in_terminator(
+        &self,
+        terminator: &Terminator,
+        reaching_defs: &HashSet<ReachingDefinition>,
+        stmt_to_constant: &HashMap<NonNull<Statement>, Constant>,
+    ) -> Terminator {
+        match terminator {
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => match self.propagate_in_operand(condition, reaching_defs, stmt_to_constant) {
+                Operand::Const(Constant::Bool(true)) => Terminator::Goto(*then_block),
+                Operand::Const(Constant::Bool(false)) => Terminator::Goto(*else_block),
+                condition => Terminator::Branch {
+                    condition,
+                    then_block: *then_block,
+                    else_block: *else_block,
+                },
+            },
+            Terminator::Return(Some(value)) => Terminator::Return(Some(
+                self.propagate_in_operand(value, reaching_defs, stmt_to_constant),
+            )),
+            _ => terminator.clone(),
+        }
+    }
+
     /// Propagate constants in an rvalue
     fn propagate_in_rvalue(
         &self,
         rvalue: &Rvalue,
-        reaching_defs: &std::collections::HashSet<ReachingDefinition>,
+        reaching_defs: &HashSet<ReachingDefinition>,
         stmt_to_constant: &HashMap<NonNull<Statement>, Constant>,
     ) -> Rvalue {
         match rvalue {
@@ -195,23 +255,24 @@ impl ConstantPropagationPass {
     fn propagate_in_operand(
         &self,
         operand: &Operand,
-        reaching_defs: &std::collections::HashSet<ReachingDefinition>,
+        reaching_defs: &HashSet<ReachingDefinition>,
         stmt_to_constant: &HashMap<NonNull<Statement>, Constant>,
     ) -> Operand {
         match operand {
             Operand::Var(var_id) => {
-                // Look for constant definitions of this variable
-                let constant_defs: Vec<&Constant> = reaching_defs
+                // The value of every definition reaching the use; the entry value of a
+                // parameter or local is never constant
+                let mut values = reaching_defs
                     .iter()
                     .filter(|def| def.var_id == *var_id)
-                    .filter_map(|def| stmt_to_constant.get(&def.site))
-                    .collect();
-
-                // Only propagate if there's exactly one constant definition
-                if constant_defs.len() == 1 {
-                    Operand::Const(constant_defs[0].clone())
-                } else {
-                    operand.clone()
+                    .map(|def| def.site.and_then(|site| stmt_to_constant.get(&site)));
+
+                // Only propagate if all of them assign the same constant
+                match values.next() {
+                    Some(Some(constant)) if values.all(|value| value == Some(constant)) => {
+                        Operand::Const(constant.clone())
+                    }
+                    _ => operand.clone(),
                 }
             }
             Operand::Const(_) => operand.clone(),
@@ -219,35 +280,42 @@ impl ConstantPropagationPass {
     }
 
     /// Evaluate a unary operation on a constant
-    fn evaluate_unary_op(&self, op: &crate::ast::UnaryOp, operand: &Constant) -> Option<Constant> {
+    pub(crate) fn evaluate_unary_op(
+        &self,
+        op: &crate::ast::UnaryOp,
+        operand: &Constant,
+    ) -> Option<Constant> {
         match (op, operand) {
             (crate::ast::UnaryOp::Not, Constant::Bool(b)) => Some(Constant::Bool(!b)),
-            (crate::ast::UnaryOp::Neg, Constant::Int(i)) => Some(Constant::Int(-i)),
+            (crate::ast::UnaryOp::Neg, Constant::Int(i)) => i.checked_neg().map(Constant::Int),
             (crate::ast::UnaryOp::Neg, Constant::Float(f)) => Some(Constant::Float(-f)),
+            (crate::ast::UnaryOp::FoldCase, Constant::String(s)) => {
+                Some(Constant::String(s.to_lowercase()))
+            }
             _ => None,
         }
     }
 
     /// Evaluate a binary operation on constants
-    fn evaluate_binary_op(
+    pub(crate) fn evaluate_binary_op(
         &self,
         op: &crate::ast::BinaryOp,
         left: &Constant,
         right: &Constant,
     ) -> Option<Constant> {
         match (op, left, right) {
-            // Integer arithmetic
+            // Integer arithmetic, left alone where it would overflow
             (crate::ast::BinaryOp::Add, Constant::Int(a), Constant::Int(b)) => {
-                Some(Constant::Int(a + b))
+                a.checked_add(*b).map(Constant::Int)
             }
             (crate::ast::BinaryOp::Sub, Constant::Int(a), Constant::Int(b)) => {
-                Some(Constant::Int(a - b))
+                a.checked_sub(*b).map(Constant::Int)
             }
             (crate::ast::BinaryOp::Mul, Constant::Int(a), Constant::Int(b)) => {
-                Some(Constant::Int(a * b))
+                a.checked_mul(*b).map(Constant::Int)
             }
-            (crate::ast::BinaryOp::Div, Constant::Int(a), Constant::Int(b)) if *b != 0 => {
-                Some(Constant::Int(a / b))
+            (crate::ast::BinaryOp::Div, Constant::Int(a), Constant::Int(b)) => {
+                a.checked_div(*b).map(Constant::Int)
             }
 
             // Float arithmetic
@@ -278,6 +346,20 @@ impl ConstantPropagationPass {
                 Some(Constant::Bool(a >= b))
             }
 
+            // Comparisons for strings
+            (crate::ast::BinaryOp::Lt, Constant::String(a), Constant::String(b)) => {
+                Some(Constant::Bool(a < b))
+            }
+            (crate::ast::BinaryOp::Lte, Constant::String(a), Constant::String(b)) => {
+                Some(Constant::Bool(a <= b))
+            }
+            (crate::ast::BinaryOp::Gt, Constant::String(a), Constant::String(b)) => {
+                Some(Constant::Bool(a > b))
+            }
+            (crate::ast::BinaryOp::Gte, Constant::String(a), Constant::String(b)) => {
+                Some(Constant::Bool(a >= b))
+            }
+
             // Equality comparisons (works for all types)
             (crate::ast::BinaryOp::Eq, a, b) => Some(Constant::Bool(a == b)),
             (crate::ast::BinaryOp::Neq, a, b) => Some(Constant::Bool(a != b)),
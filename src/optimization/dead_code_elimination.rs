@@ -7,6 +7,12 @@ pub struct DeadCodeEliminationPass {
     // Configuration options could go here
 }
 
+impl Default for DeadCodeEliminationPass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DeadCodeEliminationPass {
     pub fn new() -> Self {
         Self {}
@@ -0,0 +1,221 @@
+//! Bounded loop unrolling.
+//!
+//! The prover needs an invariant for every loop. For bounded checking, this
+//! pass instead copies each loop body `bound` times, so the back edges of one
+//! copy lead into the next. The back edges of the last copy end the path in a
+//! `BoundExceeded` block, which the prover either assumes unreachable or has
+//! to prove unreachable. Inner loops are unrolled before the loops around them.
+//!
+//! ```
+//! use FMitF_rs::cfg::{BoundCheck, Terminator};
+//! use FMitF_rs::optimization::{LoopUnrollingPass, OptimizationPass};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void f(int id) {
+//!         hop on A {
+//!             int i = 0;
+//!             while (i < T[id: id].n) {
+//!                 i = i + 1;
+//!             }
+//!             T[id: id].n = i;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let mut cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let func_id = cfg.root_functions[0];
+//!
+//! let pass = LoopUnrollingPass::new(2, BoundCheck::Assert);
+//! assert!(pass.optimize_function(&mut cfg.functions[func_id]));
+//! // Loop-free now, so a second run has nothing to do
+//! assert!(!pass.optimize_function(&mut cfg.functions[func_id]));
+//!
+//! let func = &cfg.functions[func_id];
+//! let hop = &func.hops[func.hop_order[0]];
+//! let residuals = hop
+//!     .blocks
+//!     .iter()
+//!     .filter(|&&block| {
+//!         func.blocks[block].terminator == Terminator::BoundExceeded(BoundCheck::Assert)
+//!     })
+//!     .count();
+//! assert_eq!(residuals, 1);
+//! ```
+
+use crate::cfg::{BasicBlock, BasicBlockId, BoundCheck, DominatorTree, FunctionCfg, Terminator};
+use crate::optimization::OptimizationPass;
+use std::collections::{HashMap, HashSet};
+
+/// Loop unrolling pass
+///
+/// Unrolls every loop so that its back edge is taken at most `bound` times. A
+/// `while` loop can then run its body up to `bound` times and still leave
+/// through its condition. Unrolling removes all loops, so the pass changes a
+/// function at most once.
+pub struct LoopUnrollingPass {
+    bound: usize,
+    check: BoundCheck,
+}
+
+/// A natural loop: the header and every block that reaches one of its back
+/// edges without passing the header, in block order.
+struct Loop {
+    header: BasicBlockId,
+    body: Vec<BasicBlockId>,
+}
+
+impl OptimizationPass for LoopUnrollingPass {
+    fn name(&self) -> &'static str {
+        "Loop Unrolling"
+    }
+
+    fn optimize_function(&self, func: &mut FunctionCfg) -> bool {
+        let mut changed = false;
+        while let Some(innermost) = Self::innermost_loop(func) {
+            self.unroll(func, &innermost);
+            changed = true;
+        }
+        changed
+    }
+}
+
+impl LoopUnrollingPass {
+    pub fn new(bound: usize, check: BoundCheck) -> Self {
+        Self { bound, check }
+    }
+
+    /// A loop with no other loop inside it, if the function has loops.
+    fn innermost_loop(func: &FunctionCfg) -> Option<Loop> {
+        let dominators = DominatorTree::dominators(func);
+        let mut latches: HashMap<BasicBlockId, Vec<BasicBlockId>> = HashMap::new();
+        let mut predecessors: HashMap<BasicBlockId, Vec<BasicBlockId>> = HashMap::new();
+        for (block_id, _) in func.blocks.iter() {
+            if !func.contains_block(block_id) {
+                continue;
+            }
+            for succ in func.successors(block_id) {
+                predecessors.entry(succ).or_default().push(block_id);
+                if dominators.dominates(succ, block_id) {
+                    latches.entry(succ).or_default().push(block_id);
+                }
+            }
+        }
+
+        let mut headers: Vec<BasicBlockId> = latches.keys().copied().collect();
+        headers.sort();
+        let loops: Vec<Loop> = headers
+            .iter()
+            .map(|&header| {
+                let mut body = HashSet::from([header]);
+                let mut stack = latches[&header].clone();
+                while let Some(block_id) = stack.pop() {
+                    if body.insert(block_id) {
+                        stack.extend(predecessors.get(&block_id).into_iter().flatten());
+                    }
+                }
+                let mut body: Vec<BasicBlockId> = body.into_iter().collect();
+                body.sort();
+                Loop { header, body }
+            })
+            .collect();
+
+        loops.into_iter().find(|candidate| {
+            headers
+                .iter()
+                .all(|&header| header == candidate.header || !candidate.body.contains(&header))
+        })
+    }
+
+    /// Replaces a loop by `bound` copies of its body followed by the residual
+    /// block. Copies are made from the last one back, so each knows the header
+    /// its back edges lead to.
+    fn unroll(&self, func: &mut FunctionCfg, target: &Loop) {
+        let header = &func.blocks[target.header];
+        let hop_id = header.hop_id;
+        let residual = func.blocks.alloc(BasicBlock {
+            hop_id,
+            statements: Vec::new(),
+            terminator: Terminator::BoundExceeded(self.check),
+            span: header.span.clone(),
+            origin: header.origin,
+            terminator_origin: header.terminator_origin,
+            terminator_span: header.terminator_span.clone(),
+        });
+
+        let mut next_header = residual;
+        let mut copies = Vec::new();
+        for _ in 0..self.bound {
+            let mut copy = HashMap::new();
+            for &block_id in &target.body {
+                let block = &func.blocks[block_id];
+                let new_block = func.blocks.alloc(BasicBlock {
+                    hop_id,
+                    statements: block.statements.clone(),
+                    terminator: block.terminator.clone(),
+                    span: block.span.clone(),
+                    origin: block.origin,
+                    terminator_origin: block.terminator_origin,
+                    terminator_span: block.terminator_span.clone(),
+                });
+                copy.insert(block_id, new_block);
+            }
+            for &block_id in &target.body {
+                let terminator = &mut func.blocks[copy[&block_id]].terminator;
+                Self::retarget(terminator, |succ| {
+                    if succ == target.header {
+                        next_header
+                    } else {
+                        copy.get(&succ).copied().unwrap_or(succ)
+                    }
+                });
+            }
+            next_header = copy[&target.header];
+            copies.push(
+                target
+                    .body
+                    .iter()
+                    .map(|block_id| copy[block_id])
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        // The original blocks run the first iteration
+        for &block_id in &target.body {
+            Self::retarget(&mut func.blocks[block_id].terminator, |succ| {
+                if succ == target.header {
+                    next_header
+                } else {
+                    succ
+                }
+            });
+        }
+
+        let hop = &mut func.hops[hop_id];
+        hop.blocks.extend(copies.into_iter().rev().flatten());
+        hop.blocks.push(residual);
+    }
+
+    /// Redirects the jump targets of a terminator.
+    fn retarget(terminator: &mut Terminator, redirect: impl Fn(BasicBlockId) -> BasicBlockId) {
+        match terminator {
+            Terminator::Goto(target) => *target = redirect(*target),
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => {
+                *then_block = redirect(*then_block);
+                *else_block = redirect(*else_block);
+            }
+            Terminator::Return(_)
+            | Terminator::Abort
+            | Terminator::BoundExceeded(_)
+            | Terminator::HopExit { .. } => {}
+        }
+    }
+}
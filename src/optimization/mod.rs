@@ -4,17 +4,27 @@
 //! - Constant propagation and folding
 //! - Dead code elimination  
 //! - Common subexpression elimination
+//! - CFG simplification
+//! - Bounded loop unrolling, for bounded verification
+//!
+//! A `PassManager` orders passes by their dependencies and times each run.
 
-use crate::cfg::{CfgProgram, FunctionCfg, FunctionId};
-use std::collections::HashMap;
+use crate::cfg::{BasicBlockId, CfgProgram, FunctionCfg, FunctionId, Terminator};
+use std::collections::{HashMap, HashSet};
 
+mod cfg_simplification;
 mod common_subexpression_elimination;
 mod constant_propagation;
 mod dead_code_elimination;
+mod loop_unrolling;
+mod pass_manager;
 
+pub use cfg_simplification::CfgSimplificationPass;
 pub use common_subexpression_elimination::CommonSubexpressionEliminationPass;
 pub use constant_propagation::ConstantPropagationPass;
 pub use dead_code_elimination::DeadCodeEliminationPass;
+pub use loop_unrolling::LoopUnrollingPass;
+pub use pass_manager::{PassManager, PassReport, PassRun};
 
 /// Trait for optimization passes
 pub trait OptimizationPass {
@@ -25,12 +35,53 @@ pub trait OptimizationPass {
     fn name(&self) -> &'static str;
 }
 
+/// Detaches the blocks no longer reachable from the entry of their hop, which is
+/// how passes remove blocks. Returns whether any block was detached.
+pub(crate) fn detach_unreachable_blocks(func: &mut FunctionCfg) -> bool {
+    let mut changed = false;
+    for (_, hop) in func.hops.iter_mut() {
+        let Some(entry_block) = hop.entry_block else {
+            continue;
+        };
+        let mut reachable: HashSet<BasicBlockId> = HashSet::new();
+        let mut stack = vec![entry_block];
+        while let Some(block_id) = stack.pop() {
+            if !reachable.insert(block_id) {
+                continue;
+            }
+            match &func.blocks[block_id].terminator {
+                Terminator::Goto(target) => stack.push(*target),
+                Terminator::Branch {
+                    then_block,
+                    else_block,
+                    ..
+                } => stack.extend([*then_block, *else_block]),
+                Terminator::Return(_)
+                | Terminator::Abort
+                | Terminator::BoundExceeded(_)
+                | Terminator::HopExit { .. } => {}
+            }
+        }
+
+        let block_count = hop.blocks.len();
+        hop.blocks.retain(|block_id| reachable.contains(block_id));
+        changed |= hop.blocks.len() != block_count;
+    }
+    changed
+}
+
 /// Main optimizer that orchestrates multiple passes
 pub struct CfgOptimizer {
     passes: Vec<Box<dyn OptimizationPass>>,
     max_iterations: usize,
 }
 
+impl Default for CfgOptimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CfgOptimizer {
     pub fn new() -> Self {
         Self {
@@ -48,7 +99,8 @@ impl CfgOptimizer {
     /// Create a default optimizer with standard passes
     pub fn default_passes() -> Self {
         Self::new()
-        // .add_pass(Box::new(ConstantPropagationPass::new()))
+            .add_pass(Box::new(ConstantPropagationPass::new()))
+            .add_pass(Box::new(CfgSimplificationPass::new()))
         // .add_pass(Box::new(CommonSubexpressionEliminationPass::new()))
         // .add_pass(Box::new(DeadCodeEliminationPass::new()))
     }
@@ -97,6 +149,20 @@ impl CfgOptimizer {
     }
 }
 
+/// The whole optimizer as one pass, so a `PassManager` can run its passes to a
+/// fixed point between other passes.
+impl OptimizationPass for CfgOptimizer {
+    fn optimize_function(&self, func: &mut FunctionCfg) -> bool {
+        !CfgOptimizer::optimize_function(self, func)
+            .pass_applications
+            .is_empty()
+    }
+
+    fn name(&self) -> &'static str {
+        "Fixpoint Optimization"
+    }
+}
+
 /// Results of optimization
 #[derive(Debug)]
 pub struct OptimizationResults {
@@ -109,6 +175,12 @@ pub struct FunctionOptimizationResults {
     pub iterations: usize,
 }
 
+impl Default for OptimizationResults {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OptimizationResults {
     pub fn new() -> Self {
         Self {
@@ -125,6 +197,12 @@ impl OptimizationResults {
     }
 }
 
+impl Default for FunctionOptimizationResults {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FunctionOptimizationResults {
     pub fn new() -> Self {
         Self {
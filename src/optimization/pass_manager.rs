@@ -0,0 +1,218 @@
+//! Pass manager for CFG transforms.
+//!
+//! Passes are registered with the names of the passes they must run after, and
+//! run once each over the whole program in an order that respects those
+//! dependencies, otherwise in registration order. Each run is timed. An
+//! observer sees the program after every pass, which is how `--dump-passes`
+//! prints the CFG between passes.
+//!
+//! Passes that should run to a fixed point together go into a `CfgOptimizer`,
+//! which the manager runs as one pass.
+//!
+//! ```
+//! use FMitF_rs::cfg::BoundCheck;
+//! use FMitF_rs::optimization::{CfgOptimizer, LoopUnrollingPass, PassManager};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void f(int id) {
+//!         hop on A {
+//!             int i = 0;
+//!             while (i < 2) {
+//!                 i = i + 1;
+//!             }
+//!             T[id: id].n = i;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let mut cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//!
+//! // Registered first, but depends on the unrolling
+//! let manager = PassManager::new()
+//!     .register(Box::new(CfgOptimizer::default_passes()), &["Loop Unrolling"])
+//!     .register(Box::new(LoopUnrollingPass::new(3, BoundCheck::Assume)), &[]);
+//!
+//! let mut seen = Vec::new();
+//! let report = manager
+//!     .run_observed(&mut cfg, |run, _cfg| seen.push(run.name))
+//!     .unwrap();
+//! assert_eq!(seen, ["Loop Unrolling", "Fixpoint Optimization"]);
+//! assert!(report.runs.iter().all(|run| run.changed_functions == 1));
+//!
+//! // Only the functions given are run over
+//! let report = manager.run_on(&mut cfg, &Default::default()).unwrap();
+//! assert!(report.runs.iter().all(|run| run.changed_functions == 0));
+//!
+//! // Dependencies must be registered, and must not form a cycle
+//! let missing = PassManager::new().register(Box::new(CfgOptimizer::new()), &["SSA"]);
+//! assert!(missing.run(&mut cfg).is_err());
+//! ```
+
+use crate::cfg::{CfgProgram, FunctionId};
+use crate::optimization::OptimizationPass;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// A pass and the passes it must run after.
+struct RegisteredPass {
+    pass: Box<dyn OptimizationPass>,
+    after: Vec<&'static str>,
+}
+
+/// Runs registered passes in dependency order.
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<RegisteredPass>,
+}
+
+/// One pass run over the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassRun {
+    pub name: &'static str,
+    pub duration: Duration,
+    /// Functions the pass changed
+    pub changed_functions: usize,
+}
+
+/// The pass runs, in the order they ran.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PassReport {
+    pub runs: Vec<PassRun>,
+}
+
+impl PassReport {
+    pub fn total_duration(&self) -> Duration {
+        self.runs.iter().map(|run| run.duration).sum()
+    }
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a pass that must run after the passes named in `after`.
+    pub fn register(mut self, pass: Box<dyn OptimizationPass>, after: &[&'static str]) -> Self {
+        self.passes.push(RegisteredPass {
+            pass,
+            after: after.to_vec(),
+        });
+        self
+    }
+
+    /// Whether no pass is registered.
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// The registered passes in the order they run: each after its
+    /// dependencies, and otherwise in registration order.
+    pub fn schedule(&self) -> Result<Vec<&'static str>, String> {
+        Ok(self
+            .order()?
+            .into_iter()
+            .map(|index| self.passes[index].pass.name())
+            .collect())
+    }
+
+    fn order(&self) -> Result<Vec<usize>, String> {
+        for registered in &self.passes {
+            for dependency in &registered.after {
+                if !self
+                    .passes
+                    .iter()
+                    .any(|other| other.pass.name() == *dependency)
+                {
+                    return Err(format!(
+                        "Pass '{}' depends on '{}', which is not registered",
+                        registered.pass.name(),
+                        dependency
+                    ));
+                }
+            }
+        }
+
+        let mut order: Vec<usize> = Vec::new();
+        while order.len() < self.passes.len() {
+            let ready = (0..self.passes.len()).find(|&index| {
+                !order.contains(&index)
+                    && self.passes[index].after.iter().all(|dependency| {
+                        // Every pass of that name must have run
+                        (0..self.passes.len())
+                            .filter(|&other| self.passes[other].pass.name() == *dependency)
+                            .all(|other| order.contains(&other))
+                    })
+            });
+            match ready {
+                Some(index) => order.push(index),
+                None => {
+                    let stuck: Vec<&str> = (0..self.passes.len())
+                        .filter(|index| !order.contains(index))
+                        .map(|index| self.passes[index].pass.name())
+                        .collect();
+                    return Err(format!(
+                        "Passes depend on each other in a cycle: {}",
+                        stuck.join(", ")
+                    ));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Runs every pass over every function of the program.
+    pub fn run(&self, program: &mut CfgProgram) -> Result<PassReport, String> {
+        self.run_observed(program, |_, _| {})
+    }
+
+    /// Runs every pass, showing the observer the program after each.
+    pub fn run_observed(
+        &self,
+        program: &mut CfgProgram,
+        observer: impl FnMut(&PassRun, &CfgProgram),
+    ) -> Result<PassReport, String> {
+        self.run_filtered(program, |_| true, observer)
+    }
+
+    /// Runs every pass over the functions given only, e.g. the ones rebuilt
+    /// after an edit, leaving the others as they are.
+    pub fn run_on(
+        &self,
+        program: &mut CfgProgram,
+        functions: &HashSet<FunctionId>,
+    ) -> Result<PassReport, String> {
+        self.run_filtered(program, |id| functions.contains(&id), |_, _| {})
+    }
+
+    fn run_filtered(
+        &self,
+        program: &mut CfgProgram,
+        selected: impl Fn(FunctionId) -> bool,
+        mut observer: impl FnMut(&PassRun, &CfgProgram),
+    ) -> Result<PassReport, String> {
+        let mut report = PassReport::default();
+        for index in self.order()? {
+            let pass = &self.passes[index].pass;
+            let start = Instant::now();
+            let mut changed_functions = 0;
+            for (id, function) in program.functions.iter_mut() {
+                if selected(id) && pass.optimize_function(function) {
+                    changed_functions += 1;
+                }
+            }
+            let run = PassRun {
+                name: pass.name(),
+                duration: start.elapsed(),
+                changed_functions,
+            };
+            observer(&run, program);
+            report.runs.push(run);
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,206 @@
+//! Custom analyses, for crates that use this one to add their own lints and
+//! rules pruning C-edges without forking it.
+//!
+//! An `AnalysisPass` sees the program, its CFG and its SC-graph. `prune` may
+//! drop a C-edge the pass knows to be harmless, before its hops are checked
+//! symbolically, and `check` reports warnings once the graph is built. Passes
+//! are given to a `Pipeline` with `pass`, or registered for the whole process
+//! with `register_pass`, which a binary wrapping the command line tool does
+//! in `main` before running it; the SC-graph of every mode then applies them.
+//! Passes are compiled in: nothing is loaded at runtime.
+//!
+//! ```
+//! use FMitF_rs::ast::{AstError, Diagnostic};
+//! use FMitF_rs::passes::{AnalysisPass, PassContext};
+//! use FMitF_rs::sc_graph::Edge;
+//! use FMitF_rs::Pipeline;
+//!
+//! /// Reports are run at night, alone
+//! struct NightlyReports;
+//!
+//! impl AnalysisPass for NightlyReports {
+//!     fn name(&self) -> &'static str {
+//!         "nightly-reports"
+//!     }
+//!
+//!     fn prune(&self, context: &PassContext, edge: &Edge) -> Option<String> {
+//!         let names = context.function_names(edge);
+//!         (names.0 == "report" || names.1 == "report").then(|| "report runs alone".to_string())
+//!     }
+//!
+//!     fn check(&self, context: &PassContext) -> Vec<Diagnostic> {
+//!         context
+//!             .program
+//!             .root_functions
+//!             .iter()
+//!             .map(|&function| &context.program.functions[function])
+//!             .filter(|function| function.hops.len() > 2)
+//!             .map(|function| {
+//!                 let message = format!("'{}' has more than two hops", function.name);
+//!                 Diagnostic::warning(
+//!                     AstError::PassWarning { pass: self.name().to_string(), message },
+//!                     Some(function.span.clone()),
+//!                 )
+//!             })
+//!             .collect()
+//!     }
+//! }
+//!
+//! let chopped = Pipeline::new()
+//!     .pass(NightlyReports)
+//!     .parse(
+//!         r#"
+//!         nodes { A, B }
+//!         table T on A { primary int id; int n; }
+//!         table U on B { primary int id; int n; }
+//!         void f(int id) { hop on A { T[id: id].n = 1; } hop on B { U[id: id].n = 1; } hop on A { T[id: id].n = 2; } }
+//!         int report(int id) { hop on A { return T[id: id].n; } }
+//!         "#,
+//!     )
+//!     .unwrap()
+//!     .analyze()
+//!     .unwrap()
+//!     .scgraph()
+//!     .unwrap();
+//! assert_eq!(chopped.sc_graph.stats().2, 0);
+//! assert!(chopped
+//!     .sc_graph
+//!     .justifications
+//!     .values()
+//!     .all(|justification| justification.reason == "pruned by nightly-reports: report runs alone"));
+//! assert_eq!(chopped.findings.len(), 1);
+//! assert_eq!(
+//!     chopped.findings[0].error.to_string(),
+//!     "PassWarning[W0013]: 'f' has more than two hops (nightly-reports)"
+//! );
+//! ```
+
+use crate::ast::{Diagnostic, Program};
+use crate::cfg::CfgProgram;
+use crate::sc_graph::{Edge, EdgeType, SCGraph};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// What a pass analyzes.
+pub struct PassContext<'a> {
+    pub program: &'a Program,
+    pub cfg: &'a CfgProgram,
+    pub sc_graph: &'a SCGraph,
+}
+
+impl PassContext<'_> {
+    /// The functions of the hops an edge joins, source first.
+    pub fn function_names(&self, edge: &Edge) -> (&str, &str) {
+        let name = |node| {
+            self.cfg.functions[self.sc_graph.nodes[node].cfg_function_id]
+                .name
+                .as_str()
+        };
+        (name(edge.source), name(edge.target))
+    }
+}
+
+/// A custom analysis of a program and its SC-graph.
+pub trait AnalysisPass: Send + Sync {
+    /// The name of the pass, given in the reasons of the edges it prunes
+    fn name(&self) -> &'static str;
+
+    /// Why a C-edge cannot take part in an anomaly, if the pass knows; the
+    /// edge is then dropped
+    fn prune(&self, _context: &PassContext, _edge: &Edge) -> Option<String> {
+        None
+    }
+
+    /// Warnings about the program, seen with its final SC-graph
+    fn check(&self, _context: &PassContext) -> Vec<Diagnostic> {
+        Vec::new()
+    }
+}
+
+impl fmt::Debug for dyn AnalysisPass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AnalysisPass({})", self.name())
+    }
+}
+
+/// The passes every SC-graph of the process applies.
+static REGISTRY: RwLock<Vec<Arc<dyn AnalysisPass>>> = RwLock::new(Vec::new());
+
+/// Registers a pass for every SC-graph built from now on.
+pub fn register_pass(pass: impl AnalysisPass + 'static) {
+    REGISTRY
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(Arc::new(pass));
+}
+
+/// The passes registered, in registration order.
+pub fn registered_passes() -> Vec<Arc<dyn AnalysisPass>> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+impl SCGraph {
+    /// Drops the C-edges a pass prunes, recording the pass and its reason in
+    /// `justifications`, and returns how many were dropped. The first pass to
+    /// prune an edge gives the reason.
+    pub fn prune_with(
+        &mut self,
+        passes: &[Arc<dyn AnalysisPass>],
+        program: &Program,
+        cfg: &CfgProgram,
+    ) -> usize {
+        if passes.is_empty() {
+            return 0;
+        }
+        let context = PassContext {
+            program,
+            cfg,
+            sc_graph: self,
+        };
+        let pruned: Vec<(Edge, String)> = self
+            .edges
+            .iter()
+            .filter(|edge| edge.edge_type == EdgeType::C)
+            .filter_map(|edge| {
+                passes.iter().find_map(|pass| {
+                    let reason = pass.prune(&context, edge)?;
+                    Some((
+                        edge.clone(),
+                        format!("pruned by {}: {}", pass.name(), reason),
+                    ))
+                })
+            })
+            .collect();
+
+        for (edge, reason) in &pruned {
+            self.edges.retain(|e| e != edge);
+            self.pair_checks.remove(edge);
+            if let Some(justification) = self.justifications.get_mut(edge) {
+                justification.conflicting = false;
+                justification.reason = reason.clone();
+            }
+        }
+        pruned.len()
+    }
+
+    /// The warnings of the passes, in pass order.
+    pub fn check_with(
+        &self,
+        passes: &[Arc<dyn AnalysisPass>],
+        program: &Program,
+        cfg: &CfgProgram,
+    ) -> Vec<Diagnostic> {
+        let context = PassContext {
+            program,
+            cfg,
+            sc_graph: self,
+        };
+        passes
+            .iter()
+            .flat_map(|pass| pass.check(&context))
+            .collect()
+    }
+}
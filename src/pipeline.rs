@@ -0,0 +1,336 @@
+//! The stages of the command line tool as a library: parsing, analysis, the
+//! CFG and SC-graph, and verification, each step returning what it built.
+//!
+//! ```
+//! use FMitF_rs::Pipeline;
+//!
+//! let chopped = Pipeline::from_source(
+//!     r#"
+//!     nodes { A, B }
+//!     table T on A { primary int id; int n; }
+//!     table U on B { primary int id; int n; }
+//!     void f(int id) { hop on A { T[id: id].n = T[id: id].n + 1; } hop on B { U[id: id].n = 1; } }
+//!     void g(int id) { hop on A { T[id: id].n = 0; } hop on B { U[id: id].n = 2; } }
+//!     "#,
+//! )
+//! .unwrap()
+//! .analyze()
+//! .unwrap()
+//! .scgraph()
+//! .unwrap();
+//! assert_eq!(chopped.cfg.functions.len(), 2);
+//! assert!(!chopped.mixed_cycles().is_empty());
+//!
+//! // Errors of the source keep their spans
+//! let errors = Pipeline::from_source("nodes { A } void f() { hop on B { } }")
+//!     .and_then(|parsed| parsed.analyze())
+//!     .unwrap_err();
+//! assert!(matches!(errors, FMitF_rs::PipelineError::Frontend(_)));
+//! ```
+//!
+//! Verifying needs a prover, found as `verify` mode finds it:
+//!
+//! ```no_run
+//! use FMitF_rs::pipeline::{Pipeline, VerifyOptions};
+//! use FMitF_rs::verification::solver::SolverKind;
+//!
+//! let options = VerifyOptions {
+//!     solver: SolverKind::Z3,
+//!     ..VerifyOptions::default()
+//! };
+//! let verified = Pipeline::from_file("examples/practical/bank.transact")?
+//!     .analyze()?
+//!     .scgraph()?
+//!     .verify(&options)?;
+//! println!("{} mixed cycles left", verified.report.cycles.len());
+//! # Ok::<(), FMitF_rs::PipelineError>(())
+//! ```
+
+use crate::ast::errors::format_errors;
+use crate::ast::{self, AnalysisConfig, Diagnostic, Program, SpannedError};
+use crate::cfg::{CfgBuilder, CfgProgram};
+use crate::optimization::CfgOptimizer;
+use crate::passes::{registered_passes, AnalysisPass};
+use crate::sc_graph::{CycleLimits, Isolation, SCGraph};
+use crate::verification::report::VerificationReport;
+use crate::verification::solver::{
+    Encoding, SolverKind, SolverLimits, VcGranularity, DEFAULT_TIMEOUT,
+};
+use crate::verification::VerificationManager;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// What stopped a pipeline.
+#[derive(Debug, Clone)]
+pub enum PipelineError {
+    /// The source did not load, parse or pass analysis
+    Frontend(Vec<SpannedError>),
+    /// A later stage failed
+    Stage(String),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Frontend(errors) => write!(f, "{}", format_errors(errors)),
+            PipelineError::Stage(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+impl From<Vec<SpannedError>> for PipelineError {
+    fn from(errors: Vec<SpannedError>) -> Self {
+        PipelineError::Frontend(errors)
+    }
+}
+
+impl From<String> for PipelineError {
+    fn from(message: String) -> Self {
+        PipelineError::Stage(message)
+    }
+}
+
+/// How a pipeline reads and analyzes its source, set before parsing.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    defines: HashSet<String>,
+    links: Vec<PathBuf>,
+    config: AnalysisConfig,
+    skip_optimization: bool,
+    isolation: Isolation,
+    passes: Vec<Arc<dyn AnalysisPass>>,
+}
+
+impl Pipeline {
+    /// A pipeline with the defaults of the command line tool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses source code with the default options, resolving its imports
+    /// from the working directory.
+    pub fn from_source(source: &str) -> Result<Parsed, PipelineError> {
+        Self::new().parse(source)
+    }
+
+    /// Reads and parses the file at `path` with the default options.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Parsed, PipelineError> {
+        Self::new().parse_file(path)
+    }
+
+    /// Keeps the `#if` sections of `feature`, as `--define` does.
+    pub fn define(mut self, feature: impl Into<String>) -> Self {
+        self.defines.insert(feature.into());
+        self
+    }
+
+    /// Links the file at `path` into the program, as `--link` does.
+    pub fn link(mut self, path: impl Into<PathBuf>) -> Self {
+        self.links.push(path.into());
+        self
+    }
+
+    /// Analyzes with `config`, as the `[analysis]` section of a config file.
+    pub fn config(mut self, config: AnalysisConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Builds the SC-graph from the CFG as built, as `--no-optimize` does.
+    pub fn skip_optimization(mut self, skip: bool) -> Self {
+        self.skip_optimization = skip;
+        self
+    }
+
+    /// Judges mixed cycles against `isolation`, as `--isolation` does.
+    pub fn isolation(mut self, isolation: Isolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Applies `pass` to the SC-graph, after the passes registered with
+    /// `register_pass`.
+    pub fn pass(mut self, pass: impl AnalysisPass + 'static) -> Self {
+        self.passes.push(Arc::new(pass));
+        self
+    }
+
+    /// Parses source code, resolving its imports from the working directory.
+    pub fn parse(self, source: &str) -> Result<Parsed, PipelineError> {
+        self.load(source, None)
+    }
+
+    /// Reads and parses the file at `path`, resolving its imports beside it.
+    pub fn parse_file(self, path: impl AsRef<Path>) -> Result<Parsed, PipelineError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+        self.load(&source, Some(path))
+    }
+
+    fn load(self, source: &str, path: Option<&Path>) -> Result<Parsed, PipelineError> {
+        let files = ast::load_sources(source, path, &self.links, &self.defines)?;
+        let program = ast::build_and_resolve(files)?;
+        Ok(Parsed {
+            options: self,
+            program,
+        })
+    }
+}
+
+/// A program parsed, with its names resolved.
+#[derive(Debug)]
+pub struct Parsed {
+    options: Pipeline,
+    /// With the files read in `program.source_files`, the root first
+    pub program: Program,
+}
+
+impl Parsed {
+    /// Checks the program's types and semantics.
+    pub fn analyze(mut self) -> Result<Analyzed, PipelineError> {
+        ast::analyze_program_with_types(&mut self.program, &self.options.config)?;
+        Ok(Analyzed {
+            options: self.options,
+            program: self.program,
+        })
+    }
+}
+
+/// A program that passed analysis; its warnings are in `program.warnings`.
+#[derive(Debug)]
+pub struct Analyzed {
+    options: Pipeline,
+    pub program: Program,
+}
+
+impl Analyzed {
+    /// The program's CFG, optimized unless the pipeline skips optimization.
+    pub fn cfg(&self) -> Result<CfgProgram, PipelineError> {
+        let mut cfg = CfgBuilder::build_from_program(&self.program)?.program;
+        if !self.options.skip_optimization {
+            CfgOptimizer::default_passes().optimize_program(&mut cfg);
+        }
+        Ok(cfg)
+    }
+
+    /// Builds the CFG and its SC-graph, without the C-edges the passes prune
+    /// or a symbolic check shows to commute.
+    pub fn scgraph(self) -> Result<Chopped, PipelineError> {
+        let cfg = self.cfg()?;
+        let mut passes = registered_passes();
+        passes.extend(self.options.passes.iter().cloned());
+        let mut sc_graph = SCGraph::new(&cfg);
+        sc_graph.isolation = self.options.isolation;
+        // Pruned edges need no symbolic check
+        sc_graph.prune_with(&passes, &self.program, &cfg);
+        sc_graph.eliminate_commuting_edges(&cfg);
+        let findings = sc_graph.check_with(&passes, &self.program, &cfg);
+        Ok(Chopped {
+            program: self.program,
+            cfg,
+            sc_graph,
+            findings,
+        })
+    }
+}
+
+/// A program with its CFG and SC-graph.
+#[derive(Debug)]
+pub struct Chopped {
+    pub program: Program,
+    pub cfg: CfgProgram,
+    pub sc_graph: SCGraph,
+    /// The warnings of the analysis passes
+    pub findings: Vec<Diagnostic>,
+}
+
+impl Chopped {
+    /// The mixed cycles of the SC-graph, as the hops on each.
+    pub fn mixed_cycles(&self) -> Vec<Vec<crate::cfg::HopId>> {
+        self.sc_graph.find_mixed_cycles()
+    }
+
+    /// Verifies the C-edges left, removing those proven to commute.
+    pub fn verify(mut self, options: &VerifyOptions) -> Result<Verified, PipelineError> {
+        let backend = match &options.smt_solver {
+            Some(command) => Box::new(crate::verification::solver::SmtBackend::custom(
+                command,
+                options.limits,
+            )?),
+            None => options
+                .solver
+                .discover(options.limits, options.boogie_path.as_deref())?,
+        };
+        let mut manager = VerificationManager::with_solver(backend);
+        manager.jobs = options.jobs;
+        manager.limits = options.limits;
+        manager.granularity = options.granularity;
+        manager.encoding = options.encoding;
+        manager.infer_invariants = options.infer_invariants;
+        manager.run_commutativity_pipeline(&self.cfg, &mut self.sc_graph);
+        manager.check_postconditions(&self.cfg, &self.sc_graph)?;
+        let report =
+            VerificationReport::new(&manager, &self.cfg, &self.sc_graph, &CycleLimits::default());
+        Ok(Verified {
+            program: self.program,
+            cfg: self.cfg,
+            sc_graph: self.sc_graph,
+            findings: self.findings,
+            manager,
+            report,
+        })
+    }
+}
+
+/// How `verify` checks C-edges, with the defaults of `verify` mode.
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    pub solver: SolverKind,
+    /// An SMT solver command to use instead, as `--smt-solver`
+    pub smt_solver: Option<String>,
+    /// The Boogie executable, unless found on `PATH`
+    pub boogie_path: Option<PathBuf>,
+    pub limits: SolverLimits,
+    /// Conditions checked at once
+    pub jobs: usize,
+    pub granularity: VcGranularity,
+    pub encoding: Encoding,
+    /// Whether to infer invariants of the tables for the conditions to assume
+    pub infer_invariants: bool,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self {
+            solver: SolverKind::default(),
+            smt_solver: None,
+            boogie_path: None,
+            limits: SolverLimits {
+                timeout: Some(DEFAULT_TIMEOUT),
+                ..SolverLimits::default()
+            },
+            jobs: 1,
+            granularity: VcGranularity::default(),
+            encoding: Encoding::default(),
+            infer_invariants: false,
+        }
+    }
+}
+
+/// A program whose SC-graph has been verified.
+pub struct Verified {
+    pub program: Program,
+    pub cfg: CfgProgram,
+    /// The SC-graph without the C-edges proven to commute
+    pub sc_graph: SCGraph,
+    /// The warnings of the analysis passes
+    pub findings: Vec<Diagnostic>,
+    pub manager: VerificationManager,
+    /// The outcome of each C-edge and the mixed cycles left
+    pub report: VerificationReport,
+}
@@ -0,0 +1,191 @@
+//! The frontend and SC-graph analysis behind one call, for an in-browser
+//! playground: `analyze` takes source code and returns its diagnostics and
+//! SC-graph as JSON.
+//!
+//! Diagnostics carry their `severity`, stable `code`, `kind`, `message`,
+//! `span` (as in the SC-graph JSON, or `null`) and `notes`; a syntax error
+//! gets the span of the position the parser stopped at. `graph` is the
+//! SC-graph in the form of `--format json`, or `null` when the source does not
+//! pass analysis.
+//!
+//! ```
+//! use FMitF_rs::playground::analyze;
+//!
+//! let result = analyze(include_str!("../examples/sc_graph_demos/crossed_writers.transact"));
+//! assert_eq!(result["diagnostics"], serde_json::json!([]));
+//! // f:1 -- f:2 -- g:2 -- g:1, by the graph's vertices
+//! assert_eq!(result["graph"]["cycles"], serde_json::json!([[0, 1, 3, 2]]));
+//!
+//! let result = analyze("nodes { A }\nvoid f() { hop on B { } }");
+//! let diagnostic = &result["diagnostics"][0];
+//! assert_eq!(diagnostic["code"], "E0104");
+//! assert_eq!(diagnostic["span"]["line"], 2);
+//! assert!(result["graph"].is_null());
+//!
+//! let result = analyze("nodes { A }\nvoid f( { }");
+//! assert_eq!(result["diagnostics"][0]["code"], "E0001");
+//! assert_eq!(result["diagnostics"][0]["span"]["column"], 9);
+//! ```
+//!
+//! Built for `wasm32-unknown-unknown` without the `repl` feature, the library
+//! also exports `analyze` over raw memory for JavaScript: copy the UTF-8
+//! source into a buffer from `playground_alloc(len)`, call
+//! `playground_analyze(ptr, len)` for the length of the JSON, read it from
+//! `playground_result()` and release the buffer with `playground_free(ptr,
+//! len)`. Imports and verification need files and processes, which the
+//! browser does not have; they are reported as errors.
+
+use crate::ast::{AstError, Diagnostic, Severity, Span};
+use crate::lsp::syntax_error;
+use crate::pipeline::{Pipeline, PipelineError};
+use crate::sc_graph::CycleLimits;
+use serde_json::{json, Value};
+
+/// Parses and analyzes `source`, building its SC-graph if it passes.
+pub fn analyze(source: &str) -> Value {
+    let mut diagnostics = Vec::new();
+    let graph = match Pipeline::from_source(source).and_then(|parsed| parsed.analyze()) {
+        Ok(analyzed) => {
+            let warnings = analyzed.program.warnings.iter();
+            diagnostics.extend(warnings.map(|warning| diagnostic_json(warning, source)));
+            match analyzed.scgraph() {
+                Ok(chopped) => Some(
+                    chopped
+                        .sc_graph
+                        .to_json(&chopped.cfg, &CycleLimits::default()),
+                ),
+                Err(error) => {
+                    diagnostics.extend(errors_json(error, source));
+                    None
+                }
+            }
+        }
+        Err(error) => {
+            diagnostics.extend(errors_json(error, source));
+            None
+        }
+    };
+    json!({ "diagnostics": diagnostics, "graph": graph })
+}
+
+fn errors_json(error: PipelineError, source: &str) -> Vec<Value> {
+    match error {
+        PipelineError::Frontend(errors) => errors
+            .into_iter()
+            .map(|error| {
+                let diagnostic = Diagnostic {
+                    severity: Severity::Error,
+                    error: error.error,
+                    span: error.span,
+                    notes: Vec::new(),
+                };
+                diagnostic_json(&diagnostic, source)
+            })
+            .collect(),
+        PipelineError::Stage(message) => vec![json!({
+            "severity": Severity::Error.label(),
+            "code": null,
+            "kind": null,
+            "message": message,
+            "span": null,
+            "notes": [],
+        })],
+    }
+}
+
+fn diagnostic_json(diagnostic: &Diagnostic, source: &str) -> Value {
+    let mut message = diagnostic.error.message();
+    let mut span = diagnostic.span.clone();
+    if let (None, AstError::ParseError(text)) = (&span, &diagnostic.error) {
+        if let Some((None, line, column, expected)) = syntax_error(text) {
+            message = expected.to_string();
+            span = Some(position(source, line, column));
+        }
+    }
+    let notes: Vec<Value> = diagnostic
+        .notes
+        .iter()
+        .map(|note| json!({ "message": note.error.message(), "span": note.span }))
+        .collect();
+    json!({
+        "severity": diagnostic.severity.label(),
+        "code": diagnostic.error.code(),
+        "kind": diagnostic.error.error_type(),
+        "message": message,
+        "span": span,
+        "notes": notes,
+    })
+}
+
+/// The empty span at a 1-based line and column of `source`.
+fn position(source: &str, line: usize, column: usize) -> Span {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let start = source[line_start..]
+        .char_indices()
+        .nth(column.saturating_sub(1))
+        .map_or(source.len(), |(offset, _)| line_start + offset);
+    Span {
+        start,
+        end: start,
+        line,
+        column,
+        end_line: line,
+        end_column: column,
+        file: None,
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod exports {
+    use std::cell::RefCell;
+
+    thread_local! {
+        static RESULT: RefCell<String> = const { RefCell::new(String::new()) };
+    }
+
+    /// A buffer of `len` bytes for the host to write source code into.
+    #[no_mangle]
+    pub extern "C" fn playground_alloc(len: usize) -> *mut u8 {
+        let mut buffer = Vec::<u8>::with_capacity(len);
+        let ptr = buffer.as_mut_ptr();
+        std::mem::forget(buffer);
+        ptr
+    }
+
+    /// Releases a buffer from `playground_alloc`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` and `len` must be those of a buffer from `playground_alloc` not
+    /// yet released.
+    #[no_mangle]
+    pub unsafe extern "C" fn playground_free(ptr: *mut u8, len: usize) {
+        drop(Vec::from_raw_parts(ptr, 0, len));
+    }
+
+    /// Analyzes the `len` bytes of source code at `ptr`, returning the length
+    /// of the JSON result kept for `playground_result`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to `len` initialized bytes.
+    #[no_mangle]
+    pub unsafe extern "C" fn playground_analyze(ptr: *const u8, len: usize) -> usize {
+        let source = String::from_utf8_lossy(std::slice::from_raw_parts(ptr, len));
+        let json = super::analyze(&source).to_string();
+        RESULT.with(|result| {
+            *result.borrow_mut() = json;
+            result.borrow().len()
+        })
+    }
+
+    /// The JSON result of the last `playground_analyze`, valid until the next.
+    #[no_mangle]
+    pub extern "C" fn playground_result() -> *const u8 {
+        RESULT.with(|result| result.borrow().as_ptr())
+    }
+}
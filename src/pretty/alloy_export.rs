@@ -0,0 +1,505 @@
+//! The transactions and their chopping as an Alloy model, to search bounded
+//! schedules for anomalies in the Alloy Analyzer, independently of how the
+//! SC-graph prunes conflicts.
+//!
+//! Each function runs once as a transaction, its hops in order within one
+//! total order of all hops. The accesses of each hop are those the SC-graph
+//! starts from, each on a record keyed by constants, the transaction's
+//! parameters or keys left free. Two accesses conflict when they come from
+//! different transactions, address the same field of the same record and
+//! one of them writes; `Anomaly` is a schedule whose conflicts order the
+//! transactions in a cycle. `ConflictsAreCEdges` checks that every conflict
+//! joins two hops the SC-graph has a C-edge between, and `Serializable` that
+//! no anomaly exists, as the SC-graph claims when it has no mixed cycles.
+//!
+//! Like the SC-graph, the model takes every access of a hop to happen and
+//! does not run a function twice at once.
+//!
+//! ```
+//! use FMitF_rs::pretty::print_alloy;
+//! use FMitF_rs::sc_graph::{CycleLimits, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table Account on A { primary int id; int balance; }
+//!     table Audit on B { primary int id; int total; }
+//!     void deposit(int id, int amount) {
+//!         hop on A { Account[id: id].balance = Account[id: id].balance + amount; }
+//!         hop on B { Audit[id: 0].total = Audit[id: 0].total + amount; }
+//!     }
+//!     int audit() {
+//!         hop on A { int balance = Account[id: 1].balance; }
+//!         hop on B { return Audit[id: 0].total + balance; }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let mut model = Vec::new();
+//! print_alloy(&sc_graph, &cfg, "bank", &CycleLimits::default(), &mut model).unwrap();
+//! let model = String::from_utf8(model).unwrap();
+//!
+//! assert!(model.starts_with("module bank"));
+//! assert!(model.contains("one sig deposit extends Txn {\n    id: one Key\n}"));
+//! assert!(model.contains("key[Account_id] = deposit.id"));
+//! assert!(model.contains("key[Audit_id] = K_0"));
+//! assert!(model.contains("fun CEdges: Hop -> Hop {\n    deposit_1 -> audit_1 + deposit_2 -> audit_2\n}"));
+//! assert!(model.contains("run Anomaly for 3 Key"));
+//! ```
+
+use super::format_keyed_access;
+use super::tla_export::Names;
+use crate::cfg::{CfgProgram, Constant, FieldId, FunctionId, HopId, VarId};
+use crate::dataflow::{analyze_keyed_accesses, AccessType, KeyValue, KeyedAccess};
+use crate::sc_graph::{CycleLimits, EdgeType, SCGraph};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// Words Alloy reserves, including those of Alloy 6, and the names the model
+/// defines itself.
+const TAKEN: &[&str] = &[
+    "abstract",
+    "after",
+    "all",
+    "always",
+    "and",
+    "as",
+    "assert",
+    "before",
+    "but",
+    "check",
+    "disj",
+    "else",
+    "enum",
+    "eventually",
+    "exactly",
+    "expect",
+    "extends",
+    "fact",
+    "for",
+    "fun",
+    "historically",
+    "iden",
+    "iff",
+    "implies",
+    "in",
+    "Int",
+    "int",
+    "let",
+    "lone",
+    "module",
+    "no",
+    "none",
+    "not",
+    "once",
+    "one",
+    "open",
+    "or",
+    "pred",
+    "private",
+    "releases",
+    "run",
+    "seq",
+    "set",
+    "sig",
+    "since",
+    "some",
+    "steps",
+    "String",
+    "sum",
+    "this",
+    "triggered",
+    "univ",
+    "until",
+    "var",
+    "Txn",
+    "Hop",
+    "Field",
+    "Key",
+    "Access",
+    "Mode",
+    "Read",
+    "Write",
+    "Schedule",
+    "precedes",
+    "txn",
+    "hop",
+    "field",
+    "mode",
+    "key",
+    "SEdges",
+    "CEdges",
+    "conflict",
+    "depends",
+    "Anomaly",
+    "Serializable",
+    "ConflictsAreCEdges",
+];
+
+/// Writes the transactions of `cfg_program` and the C-edges of `sc_graph` as
+/// the Alloy module `module`, noting the mixed cycles within `limits`.
+pub fn print_alloy(
+    sc_graph: &SCGraph,
+    cfg_program: &CfgProgram,
+    module: &str,
+    limits: &CycleLimits,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let model = AlloyWriter::new(cfg_program).model(sc_graph, module, limits);
+    writer.write_all(model.as_bytes())
+}
+
+struct AlloyWriter<'a> {
+    cfg: &'a CfgProgram,
+    /// The atom of each transaction
+    txns: Vec<(FunctionId, String)>,
+    /// The atom of each hop, by function, in order
+    hops: HashMap<HopId, String>,
+    /// The atom of each field, primary key fields included
+    fields: HashMap<FieldId, String>,
+    /// The accesses of each hop, in the order of their statements
+    accesses: HashMap<HopId, Vec<KeyedAccess>>,
+    /// The atom of each constant a key takes
+    constants: BTreeMap<Constant, String>,
+    /// The field of its transaction that each parameter used as a key is
+    params: BTreeMap<(FunctionId, VarId), String>,
+}
+
+impl<'a> AlloyWriter<'a> {
+    fn new(cfg: &'a CfgProgram) -> Self {
+        let mut names = Names::new(TAKEN);
+        let txns: Vec<(FunctionId, String)> = cfg
+            .root_functions
+            .iter()
+            .map(|&function| (function, names.claim(&cfg.functions[function].name)))
+            .collect();
+        let mut hops = HashMap::new();
+        for (function, name) in &txns {
+            for (index, &hop) in cfg.functions[*function].hop_order.iter().enumerate() {
+                hops.insert(hop, names.claim(&format!("{}_{}", name, index + 1)));
+            }
+        }
+        let mut fields = HashMap::new();
+        for &table in &cfg.root_tables {
+            let table = &cfg.tables[table];
+            for &field in &table.fields {
+                let name = format!("{}_{}", table.name, cfg.fields[field].name);
+                fields.insert(field, names.claim(&name));
+            }
+        }
+
+        let mut accesses = analyze_keyed_accesses(cfg);
+        accesses.retain(|hop, _| hops.contains_key(hop));
+        let constants: BTreeSet<&Constant> = accesses
+            .values()
+            .flatten()
+            .flat_map(|access| &access.key)
+            .filter_map(|(_, value)| match value {
+                KeyValue::Const(constant) => Some(constant),
+                _ => None,
+            })
+            .collect();
+        let constants: BTreeMap<Constant, String> = constants
+            .into_iter()
+            .map(|constant| {
+                let name = names.claim(&format!("K_{}", constant_name(constant)));
+                (constant.clone(), name)
+            })
+            .collect();
+        // Parameters are fields of their transaction, named apart from the atoms
+        let mut params = BTreeMap::new();
+        for (function, _) in &txns {
+            let mut fields = names.clone();
+            for hop in &cfg.functions[*function].hop_order {
+                let Some(hop_accesses) = accesses.get_mut(hop) else {
+                    continue;
+                };
+                hop_accesses.sort_by_key(|access| (access.block.index(), access.index));
+                for (_, value) in hop_accesses.iter().flat_map(|access| &access.key) {
+                    if let KeyValue::Param { function, var } = value {
+                        params.entry((*function, *var)).or_insert_with(|| {
+                            fields.claim(&cfg.functions[*function].variables[*var].name)
+                        });
+                    }
+                }
+            }
+        }
+        Self {
+            cfg,
+            txns,
+            hops,
+            fields,
+            accesses,
+            constants,
+            params,
+        }
+    }
+
+    fn model(self, sc_graph: &SCGraph, module: &str, limits: &CycleLimits) -> String {
+        let mut out = format!("module {}\n\n", module);
+        out.push_str("-- The transactions and their chopping, as FMitF_rs exported them\n");
+        self.graph_notes(&mut out, sc_graph, limits);
+
+        out.push_str("-- A transaction, running each function once\n");
+        out.push_str("abstract sig Txn {}\n");
+        for (function, name) in &self.txns {
+            let fields: Vec<&str> = self
+                .params
+                .iter()
+                .filter(|((owner, _), _)| owner == function)
+                .map(|(_, field)| field.as_str())
+                .collect();
+            if fields.is_empty() {
+                let _ = writeln!(out, "one sig {} extends Txn {{}}", name);
+            } else {
+                let _ = writeln!(out, "one sig {} extends Txn {{", name);
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|field| format!("    {}: one Key", field))
+                    .collect();
+                let _ = writeln!(out, "{}\n}}", fields.join(",\n"));
+            }
+        }
+
+        out.push_str("\n-- A hop of a transaction, run as one step\n");
+        out.push_str("abstract sig Hop {\n    txn: one Txn\n}\n");
+        for (function, txn) in &self.txns {
+            let function = &self.cfg.functions[*function];
+            for &hop in &function.hop_order {
+                let node = &self.cfg.nodes[function.hops[hop].node_id].name;
+                let _ = writeln!(out, "-- on {}", node);
+                let _ = writeln!(
+                    out,
+                    "one sig {} extends Hop {{}} {{ txn = {} }}",
+                    self.hops[&hop], txn
+                );
+            }
+        }
+
+        out.push_str("\n-- A field of a table\nabstract sig Field {}\n");
+        let mut fields: Vec<&FieldId> = self.fields.keys().collect();
+        fields.sort();
+        if !fields.is_empty() {
+            let names: Vec<&str> = fields
+                .iter()
+                .map(|field| self.fields[field].as_str())
+                .collect();
+            let _ = writeln!(out, "one sig {} extends Field {{}}", names.join(", "));
+        }
+
+        out.push_str("\n-- A value of a primary key field\nsig Key {}\n");
+        if !self.constants.is_empty() {
+            let names: Vec<&str> = self.constants.values().map(String::as_str).collect();
+            let _ = writeln!(out, "one sig {} extends Key {{}}", names.join(", "));
+        }
+
+        out.push_str(
+            "
+abstract sig Mode {}
+one sig Read, Write extends Mode {}
+
+-- An access of a hop to a field of the record its key addresses
+abstract sig Access {
+    hop: one Hop,
+    field: one Field,
+    mode: one Mode,
+    key: Field -> lone Key
+}
+",
+        );
+        let unknown = self.accesses_out(&mut out);
+
+        let s_edges = self.edges(sc_graph, EdgeType::S);
+        let c_edges = self.edges(sc_graph, EdgeType::C);
+        let _ = writeln!(
+            out,
+            "
+-- The hops of each transaction, one after another
+fun SEdges: Hop -> Hop {{
+    {}
+}}
+
+-- The pairs of hops the SC-graph finds may conflict
+fun CEdges: Hop -> Hop {{
+    {}
+}}
+",
+            s_edges, c_edges
+        );
+        out.push_str(
+            "-- The order all hops run in, each transaction's in turn
+one sig Schedule {
+    precedes: Hop -> Hop
+} {
+    no precedes & iden
+    precedes.precedes in precedes
+    all disj h, g: Hop | h -> g in precedes or g -> h in precedes
+    ^SEdges in precedes
+}
+
+pred conflict[a, b: Access] {
+    a.hop.txn != b.hop.txn
+    a.field = b.field
+    a.key = b.key
+    Write in a.mode + b.mode
+}
+
+-- Transaction t runs a conflicting access before transaction u
+fun depends: Txn -> Txn {
+    { t, u: Txn | some a, b: Access {
+        a.hop.txn = t
+        b.hop.txn = u
+        conflict[a, b]
+        a.hop -> b.hop in Schedule.precedes
+    } }
+}
+
+-- A schedule that no serial order of the transactions is equivalent to
+pred Anomaly {
+    some t: Txn | t in t.^depends
+}
+
+-- The SC-graph pruned no conflict
+assert ConflictsAreCEdges {
+    all a, b: Access | conflict[a, b] implies a.hop -> b.hop in CEdges + ~CEdges
+}
+
+-- Holds when the SC-graph has no mixed cycles
+assert Serializable {
+    not Anomaly
+}
+
+",
+        );
+        // Enough keys for every parameter and free key to take a value of its own
+        let keys = (self.constants.len() + self.params.len() + unknown).max(1);
+        let _ = writeln!(out, "run Anomaly for {} Key", keys);
+        let _ = writeln!(out, "check ConflictsAreCEdges for {} Key", keys);
+        let _ = writeln!(out, "check Serializable for {} Key", keys);
+        out
+    }
+
+    /// The C-edges and mixed cycles, as comments.
+    fn graph_notes(&self, out: &mut String, sc_graph: &SCGraph, limits: &CycleLimits) {
+        let cycles = sc_graph.find_mixed_cycles_within(limits);
+        for cycle in &cycles.cycles {
+            let _ = writeln!(
+                out,
+                "-- Mixed cycle [{}] {}",
+                sc_graph.cycle_id(cycle, self.cfg),
+                sc_graph.describe_cycle(cycle, self.cfg)
+            );
+        }
+        if cycles.truncated {
+            out.push_str("-- ... and more mixed cycles, past the limit\n");
+        } else if cycles.cycles.is_empty() {
+            out.push_str("-- No mixed cycles, so Serializable should hold\n");
+        }
+        out.push('\n');
+    }
+
+    /// Declares each access with its hop, field, mode and key, returning how
+    /// many key fields are left free.
+    fn accesses_out(&self, out: &mut String) -> usize {
+        let mut unknown = 0;
+        for (function, _) in &self.txns {
+            for &hop in &self.cfg.functions[*function].hop_order {
+                let Some(accesses) = self.accesses.get(&hop) else {
+                    continue;
+                };
+                for (index, access) in accesses.iter().enumerate() {
+                    let verb = match access.access_type {
+                        AccessType::Read => "reads",
+                        AccessType::Write => "writes",
+                    };
+                    let _ = writeln!(
+                        out,
+                        "-- {} {} {}",
+                        self.cfg.functions[access.function].name,
+                        verb,
+                        format_keyed_access(access, self.cfg)
+                    );
+                    let mode = match access.access_type {
+                        AccessType::Read => "Read",
+                        AccessType::Write => "Write",
+                    };
+                    let columns: Vec<&str> = access
+                        .key
+                        .iter()
+                        .map(|(field, _)| self.fields[field].as_str())
+                        .collect();
+                    let mut facts = vec![
+                        format!("hop = {}", self.hops[&hop]),
+                        format!("field = {}", self.fields[&access.field]),
+                        format!("mode = {}", mode),
+                        if columns.is_empty() {
+                            "no key".to_string()
+                        } else {
+                            format!("key.Key = {}", columns.join(" + "))
+                        },
+                    ];
+                    for (field, value) in &access.key {
+                        let value = match value {
+                            KeyValue::Const(constant) => self.constants[constant].clone(),
+                            KeyValue::Param { function, var } => {
+                                let txn = self
+                                    .txns
+                                    .iter()
+                                    .find(|(owner, _)| owner == function)
+                                    .map_or("", |(_, txn)| txn.as_str());
+                                format!("{}.{}", txn, self.params[&(*function, *var)])
+                            }
+                            KeyValue::Unknown => {
+                                unknown += 1;
+                                continue;
+                            }
+                        };
+                        facts.push(format!("key[{}] = {}", self.fields[field], value));
+                    }
+                    let _ = writeln!(
+                        out,
+                        "one sig {}_a{} extends Access {{}} {{\n    {}\n}}",
+                        self.hops[&hop],
+                        index + 1,
+                        facts.join("\n    ")
+                    );
+                }
+            }
+        }
+        unknown
+    }
+
+    /// The edges of a type between hops of the model, as a relation.
+    fn edges(&self, sc_graph: &SCGraph, edge_type: EdgeType) -> String {
+        let edges: Vec<String> = sc_graph
+            .edges
+            .iter()
+            .filter(|edge| edge.edge_type == edge_type)
+            .filter_map(|edge| {
+                let source = self.hops.get(&sc_graph.nodes[edge.source].cfg_hop_id)?;
+                let target = self.hops.get(&sc_graph.nodes[edge.target].cfg_hop_id)?;
+                Some(format!("{} -> {}", source, target))
+            })
+            .collect();
+        if edges.is_empty() {
+            "none -> none".to_string()
+        } else {
+            edges.join(" + ")
+        }
+    }
+}
+
+/// A constant as part of an identifier, e.g. `m1` for -1.
+fn constant_name(constant: &Constant) -> String {
+    match constant {
+        Constant::Int(value) if *value < 0 => format!("m{}", -(*value as i128)),
+        Constant::Int(value) => value.to_string(),
+        Constant::Float(value) => value.to_string().replace('-', "m").replace('.', "_"),
+        Constant::Bool(value) => value.to_string(),
+        Constant::String(value) => format!("s_{}", value),
+    }
+}
@@ -1,3 +1,4 @@
+use crate::ast::stable_id::{ElementId, StableIds};
 use crate::ast::*;
 use std::io::{Result, Write};
 
@@ -5,6 +6,8 @@ use std::io::{Result, Write};
 pub struct PrintOptions {
     pub mode: PrintMode,
     pub show_spans: bool,
+    /// Print the stable ID (e.g. `fn:transfer/hop:1`) of each element
+    pub show_ids: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -18,776 +21,31 @@ impl Default for PrintOptions {
         Self {
             mode: PrintMode::Verbose,
             show_spans: false,
+            show_ids: false,
         }
     }
 }
 
-pub fn print_program(program: &Program, opts: &PrintOptions) {
-    let mut printer = Printer::new(opts);
-    printer.print_program(program);
-}
-
-pub fn print_program_to_writer(
-    program: &Program,
-    opts: &PrintOptions,
-    writer: &mut impl Write,
-) -> Result<()> {
-    let mut printer = WriterPrinter::new(opts, writer);
+/// Prints the AST to `writer` according to `opts`.
+pub fn print_program(program: &Program, opts: &PrintOptions, writer: &mut dyn Write) -> Result<()> {
+    let mut printer = Printer::new(opts, writer);
     printer.print_program(program)
 }
 
 struct Printer<'a> {
     opts: &'a PrintOptions,
     depth: usize,
+    writer: &'a mut dyn Write,
+    ids: Option<StableIds>,
 }
 
 impl<'a> Printer<'a> {
-    fn new(opts: &'a PrintOptions) -> Self {
-        Self { opts, depth: 0 }
-    }
-
-    fn indent(&self) -> String {
-        "  ".repeat(self.depth)
-    }
-
-    fn with_depth(&mut self, depth: usize) -> &mut Self {
-        self.depth = depth;
-        self
-    }
-
-    fn span(&self, span: &Span) -> String {
-        if self.opts.show_spans {
-            format!(" @{}:{}", span.line, span.column)
-        } else {
-            String::new()
-        }
-    }
-
-    fn print_program(&mut self, program: &Program) {
-        match self.opts.mode {
-            PrintMode::Summary => self.print_summary(program),
-            PrintMode::Verbose => self.print_verbose(program),
-        }
-    }
-
-    fn print_summary(&mut self, program: &Program) {
-        println!("AST Summary:");
-
-        println!(" - Total Nodes: {}", program.root_nodes.len());
-        println!(" - Total Tables: {}", program.root_tables.len());
-        println!(" - Total Functions: {}", program.root_functions.len());
-
-        println!("Tables:");
-        for &table_id in &program.root_tables {
-            let table = &program.tables[table_id];
-            let node_name = &program.nodes[table.node].name;
-
-            let fields: Vec<String> = table
-                .fields
-                .iter()
-                .map(|&field_id| {
-                    let field = &program.fields[field_id];
-                    let prefix = if field.is_primary { "primary " } else { "" };
-                    format!(
-                        "{}{}:{}",
-                        prefix,
-                        field.field_name,
-                        type_name(&field.field_type)
-                    )
-                })
-                .collect();
-            println!(" - {} on {}: {}", table.name, node_name, fields.join(", "));
-        }
-
-        println!("Functions:");
-        for &func_id in &program.root_functions {
-            let func = &program.functions[func_id];
-            let params: Vec<String> = func
-                .parameters
-                .iter()
-                .map(|&param_id| {
-                    let param = &program.parameters[param_id];
-                    format!("{}:{}", param.param_name, type_name(&param.param_type))
-                })
-                .collect();
-
-            println!(
-                " - {}({}) -> {}",
-                func.name,
-                params.join(", "),
-                return_type(&func.return_type)
-            );
-        }
-    }
-
-    fn print_verbose(&mut self, program: &Program) {
-        println!("Program");
-        self.with_depth(1).print_nodes(program);
-        self.with_depth(1).print_tables(program);
-        self.with_depth(1).print_functions(program);
-    }
-
-    fn print_nodes(&mut self, program: &Program) {
-        println!("{}nodes[{}]", self.indent(), program.root_nodes.len());
-        for (i, &node_id) in program.root_nodes.iter().enumerate() {
-            let node = &program.nodes[node_id];
-            println!(
-                "{}[{}] NodeDef{}",
-                self.with_depth(self.depth + 1).indent(),
-                i,
-                self.span(&node.span)
-            );
-            println!(
-                "{}name: {}",
-                self.with_depth(self.depth + 2).indent(),
-                node.name
-            );
-        }
-    }
-
-    fn print_tables(&mut self, program: &Program) {
-        println!("{}tables[{}]", self.indent(), program.root_tables.len());
-        for (i, &table_id) in program.root_tables.iter().enumerate() {
-            let table = &program.tables[table_id];
-            let node = &program.nodes[table.node];
-
-            println!(
-                "{}[{}] TableDeclaration{}",
-                self.with_depth(self.depth + 1).indent(),
-                i,
-                self.span(&table.span)
-            );
-            println!(
-                "{}name: {}",
-                self.with_depth(self.depth + 2).indent(),
-                table.name
-            );
-            println!(
-                "{}node: {}",
-                self.with_depth(self.depth + 2).indent(),
-                node.name
-            );
-
-            // Print all primary key fields
-            print!(
-                "{}primary_keys: [",
-                self.with_depth(self.depth + 2).indent()
-            );
-            for (j, &pk_field_id) in table.primary_keys.iter().enumerate() {
-                let pk_field = &program.fields[pk_field_id];
-                if j > 0 {
-                    print!(", ");
-                }
-                print!("{}", pk_field.field_name);
-            }
-            println!("]");
-
-            self.with_depth(self.depth + 2)
-                .print_fields(program, &table.fields);
-        }
-    }
-
-    fn print_fields(&mut self, program: &Program, field_ids: &[FieldId]) {
-        println!("{}fields[{}]", self.indent(), field_ids.len());
-        for (i, &field_id) in field_ids.iter().enumerate() {
-            let field = &program.fields[field_id];
-            println!(
-                "{}[{}] FieldDeclaration{}",
-                self.with_depth(self.depth + 1).indent(),
-                i,
-                self.span(&field.span)
-            );
-            println!(
-                "{}field_type: {}",
-                self.with_depth(self.depth + 2).indent(),
-                type_name(&field.field_type)
-            );
-            println!(
-                "{}field_name: {}",
-                self.with_depth(self.depth + 2).indent(),
-                field.field_name
-            );
-            println!(
-                "{}is_primary: {}",
-                self.with_depth(self.depth + 2).indent(),
-                field.is_primary
-            );
-        }
-    }
-
-    fn print_functions(&mut self, program: &Program) {
-        println!(
-            "{}functions[{}]",
-            self.indent(),
-            program.root_functions.len()
-        );
-        for (i, &func_id) in program.root_functions.iter().enumerate() {
-            let func = &program.functions[func_id];
-            println!(
-                "{}[{}] FunctionDeclaration{}",
-                self.with_depth(self.depth + 1).indent(),
-                i,
-                self.span(&func.span)
-            );
-            println!(
-                "{}return_type: {}",
-                self.with_depth(self.depth + 2).indent(),
-                return_type(&func.return_type)
-            );
-            println!(
-                "{}name: {}",
-                self.with_depth(self.depth + 2).indent(),
-                func.name
-            );
-            self.with_depth(self.depth + 2)
-                .print_parameters(program, &func.parameters);
-            self.with_depth(self.depth + 2)
-                .print_hops(program, &func.hops);
-        }
-    }
-
-    fn print_parameters(&mut self, program: &Program, param_ids: &[ParameterId]) {
-        println!("{}parameters[{}]", self.indent(), param_ids.len());
-        for (i, &param_id) in param_ids.iter().enumerate() {
-            let param = &program.parameters[param_id];
-            println!(
-                "{}[{}] ParameterDecl{}",
-                self.with_depth(self.depth + 1).indent(),
-                i,
-                self.span(&param.span)
-            );
-            println!(
-                "{}param_type: {}",
-                self.with_depth(self.depth + 2).indent(),
-                type_name(&param.param_type)
-            );
-            println!(
-                "{}param_name: {}",
-                self.with_depth(self.depth + 2).indent(),
-                param.param_name
-            );
-        }
-    }
-
-    fn print_hops(&mut self, program: &Program, hop_ids: &[HopId]) {
-        println!("{}hops[{}]", self.indent(), hop_ids.len());
-        for (i, &hop_id) in hop_ids.iter().enumerate() {
-            let hop = &program.hops[hop_id];
-
-            println!(
-                "{}[{}] HopBlock{}",
-                self.with_depth(self.depth + 1).indent(),
-                i,
-                self.span(&hop.span)
-            );
-            println!(
-                "{}node_name: {}",
-                self.with_depth(self.depth + 2).indent(),
-                hop.node_name
-            );
-
-            if let Some(resolved_node) = hop.resolved_node {
-                let node = &program.nodes[resolved_node];
-                println!(
-                    "{}resolved_node: {} ({})",
-                    self.with_depth(self.depth + 2).indent(),
-                    node.name,
-                    resolved_node.index()
-                );
-            } else {
-                println!(
-                    "{}resolved_node: None",
-                    self.with_depth(self.depth + 2).indent()
-                );
-            }
-
-            self.with_depth(self.depth + 2)
-                .print_statements(program, &hop.statements);
-        }
-    }
-
-    fn print_statements(&mut self, program: &Program, stmt_ids: &[StatementId]) {
-        println!("{}statements[{}]", self.indent(), stmt_ids.len());
-        for (i, &stmt_id) in stmt_ids.iter().enumerate() {
-            let stmt = &program.statements[stmt_id];
-            self.with_depth(self.depth + 1)
-                .print_statement_with_index(program, i, stmt);
-        }
-    }
-
-    fn print_statement_with_index(&mut self, program: &Program, index: usize, stmt: &Statement) {
-        match &stmt.node {
-            StatementKind::VarDecl(v) => {
-                println!(
-                    "{}[{}] VarDeclStatement{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-                println!(
-                    "{}var_type: {}",
-                    self.with_depth(self.depth + 1).indent(),
-                    type_name(&v.var_type)
-                );
-                println!(
-                    "{}var_name: {}",
-                    self.with_depth(self.depth + 1).indent(),
-                    v.var_name
-                );
-                println!("{}init_value:", self.with_depth(self.depth + 1).indent());
-                self.with_depth(self.depth + 2)
-                    .print_expression(program, v.init_value);
-            }
-            StatementKind::VarAssignment(v) => {
-                println!(
-                    "{}[{}] VarAssignmentStatement{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-                println!(
-                    "{}var_name: {}",
-                    self.with_depth(self.depth + 1).indent(),
-                    v.var_name
-                );
-
-                if let Some(resolved_var) = v.resolved_var {
-                    let var = &program.variables[resolved_var];
-                    println!(
-                        "{}resolved_var: {} ({}) - {:?}",
-                        self.with_depth(self.depth + 1).indent(),
-                        var.name,
-                        resolved_var.index(),
-                        var.kind
-                    );
-                } else {
-                    println!(
-                        "{}resolved_var: None",
-                        self.with_depth(self.depth + 1).indent()
-                    );
-                }
-
-                println!("{}rhs:", self.with_depth(self.depth + 1).indent());
-                self.with_depth(self.depth + 2)
-                    .print_expression(program, v.rhs);
-            }
-            StatementKind::Assignment(a) => {
-                println!(
-                    "{}[{}] AssignmentStatement{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-                println!(
-                    "{}table_name: {}",
-                    self.with_depth(self.depth + 1).indent(),
-                    a.table_name
-                );
-
-                // Print primary key fields
-                print!("{}pk_fields: [", self.with_depth(self.depth + 1).indent());
-                for (i, pk_field) in a.pk_fields.iter().enumerate() {
-                    if i > 0 {
-                        print!(", ");
-                    }
-                    print!("{}", pk_field);
-                }
-                println!("]");
-
-                println!(
-                    "{}field_name: {}",
-                    self.with_depth(self.depth + 1).indent(),
-                    a.field_name
-                );
-
-                if let Some(resolved_table) = a.resolved_table {
-                    let table = &program.tables[resolved_table];
-                    println!(
-                        "{}resolved_table: {} ({})",
-                        self.with_depth(self.depth + 1).indent(),
-                        table.name,
-                        resolved_table.index()
-                    );
-                } else {
-                    println!(
-                        "{}resolved_table: None",
-                        self.with_depth(self.depth + 1).indent()
-                    );
-                }
-
-                // Print resolved primary key fields
-                print!(
-                    "{}resolved_pk_fields: [",
-                    self.with_depth(self.depth + 1).indent()
-                );
-                for (i, resolved_pk_field) in a.resolved_pk_fields.iter().enumerate() {
-                    if i > 0 {
-                        print!(", ");
-                    }
-                    if let Some(pk_field_id) = resolved_pk_field {
-                        let pk_field = &program.fields[*pk_field_id];
-                        print!("{} ({})", pk_field.field_name, pk_field_id.index());
-                    } else {
-                        print!("None");
-                    }
-                }
-                println!("]");
-
-                if let Some(resolved_field) = a.resolved_field {
-                    let field = &program.fields[resolved_field];
-                    println!(
-                        "{}resolved_field: {} ({})",
-                        self.with_depth(self.depth + 1).indent(),
-                        field.field_name,
-                        resolved_field.index()
-                    );
-                } else {
-                    println!(
-                        "{}resolved_field: None",
-                        self.with_depth(self.depth + 1).indent()
-                    );
-                }
-
-                // Print primary key expressions
-                println!("{}pk_exprs:", self.with_depth(self.depth + 1).indent());
-                for (i, &pk_expr) in a.pk_exprs.iter().enumerate() {
-                    println!("{}[{}]:", self.with_depth(self.depth + 2).indent(), i);
-                    self.with_depth(self.depth + 3)
-                        .print_expression(program, pk_expr);
-                }
-
-                println!("{}rhs:", self.with_depth(self.depth + 1).indent());
-                self.with_depth(self.depth + 2)
-                    .print_expression(program, a.rhs);
-            }
-            StatementKind::MultiAssignment(ma) => {
-                println!(
-                    "{}[{}] MultiAssignmentStatement{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-                println!(
-                    "{}table_name: {}",
-                    self.with_depth(self.depth + 1).indent(),
-                    ma.table_name
-                );
-
-                // Print primary key fields
-                print!("{}pk_fields: [", self.with_depth(self.depth + 1).indent());
-                for (i, pk_field) in ma.pk_fields.iter().enumerate() {
-                    if i > 0 {
-                        print!(", ");
-                    }
-                    print!("{}", pk_field);
-                }
-                println!("]");
-
-                if let Some(resolved_table) = ma.resolved_table {
-                    let table = &program.tables[resolved_table];
-                    println!(
-                        "{}resolved_table: {} ({})",
-                        self.with_depth(self.depth + 1).indent(),
-                        table.name,
-                        resolved_table.index()
-                    );
-                } else {
-                    println!(
-                        "{}resolved_table: None",
-                        self.with_depth(self.depth + 1).indent()
-                    );
-                }
-
-                // Print resolved primary key fields
-                print!(
-                    "{}resolved_pk_fields: [",
-                    self.with_depth(self.depth + 1).indent()
-                );
-                for (i, resolved_pk_field) in ma.resolved_pk_fields.iter().enumerate() {
-                    if i > 0 {
-                        print!(", ");
-                    }
-                    if let Some(pk_field_id) = resolved_pk_field {
-                        let pk_field = &program.fields[*pk_field_id];
-                        print!("{} ({})", pk_field.field_name, pk_field_id.index());
-                    } else {
-                        print!("None");
-                    }
-                }
-                println!("]");
-
-                // Print assignments
-                println!(
-                    "{}assignments: [",
-                    self.with_depth(self.depth + 1).indent()
-                );
-                for (i, assignment) in ma.assignments.iter().enumerate() {
-                    println!(
-                        "{}[{}] field: {} ->",
-                        self.with_depth(self.depth + 2).indent(),
-                        i,
-                        assignment.field_name
-                    );
-                    if let Some(resolved_field) = assignment.resolved_field {
-                        let field = &program.fields[resolved_field];
-                        println!(
-                            "{}resolved_field: {} ({})",
-                            self.with_depth(self.depth + 3).indent(),
-                            field.field_name,
-                            resolved_field.index()
-                        );
-                    } else {
-                        println!(
-                            "{}resolved_field: None",
-                            self.with_depth(self.depth + 3).indent()
-                        );
-                    }
-                    println!("{}rhs:", self.with_depth(self.depth + 3).indent());
-                    self.with_depth(self.depth + 4)
-                        .print_expression(program, assignment.rhs);
-                }
-                println!("{}]", self.with_depth(self.depth + 1).indent());
-
-                // Print primary key expressions
-                println!("{}pk_exprs:", self.with_depth(self.depth + 1).indent());
-                for (i, &pk_expr) in ma.pk_exprs.iter().enumerate() {
-                    println!("{}[{}]:", self.with_depth(self.depth + 2).indent(), i);
-                    self.with_depth(self.depth + 3)
-                        .print_expression(program, pk_expr);
-                }
-            }
-            StatementKind::Return(r) => {
-                println!(
-                    "{}[{}] ReturnStatement{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-                println!("{}value:", self.with_depth(self.depth + 1).indent());
-                match &r.value {
-                    Some(expr_id) => self
-                        .with_depth(self.depth + 2)
-                        .print_expression(program, *expr_id),
-                    None => println!("{}None", self.with_depth(self.depth + 2).indent()),
-                }
-            }
-            StatementKind::Abort(_) => {
-                println!(
-                    "{}[{}] AbortStatement{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-            }
-            StatementKind::IfStmt(i) => {
-                println!(
-                    "{}[{}] IfStatement{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-                println!("{}condition:", self.with_depth(self.depth + 1).indent());
-                self.with_depth(self.depth + 2)
-                    .print_expression(program, i.condition);
-                println!("{}then_branch:", self.with_depth(self.depth + 1).indent());
-                self.with_depth(self.depth + 2)
-                    .print_statements(program, &i.then_branch);
-                println!("{}else_branch:", self.with_depth(self.depth + 1).indent());
-                match &i.else_branch {
-                    Some(else_stmts) => self
-                        .with_depth(self.depth + 2)
-                        .print_statements(program, else_stmts),
-                    None => println!("{}None", self.with_depth(self.depth + 2).indent()),
-                }
-            }
-            StatementKind::WhileStmt(w) => {
-                println!(
-                    "{}[{}] WhileStatement{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-                println!("{}condition:", self.with_depth(self.depth + 1).indent());
-                self.with_depth(self.depth + 2)
-                    .print_expression(program, w.condition);
-                println!("{}body:", self.with_depth(self.depth + 1).indent());
-                self.with_depth(self.depth + 2)
-                    .print_statements(program, &w.body);
-            }
-            StatementKind::Break(_) => {
-                println!(
-                    "{}[{}] BreakStatement{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-            }
-            StatementKind::Continue(_) => {
-                println!(
-                    "{}[{}] ContinueStatement{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-            }
-            StatementKind::Empty => {
-                println!(
-                    "{}[{}] Empty{}",
-                    self.indent(),
-                    index,
-                    self.span(&stmt.span)
-                );
-            }
-        }
-    }
-
-    fn print_expression(&mut self, program: &Program, expr_id: ExpressionId) {
-        let expr = &program.expressions[expr_id];
-        println!("{}Expression{}", self.indent(), self.span(&expr.span));
-        self.with_depth(self.depth + 1)
-            .print_expression_kind(program, &expr.node);
-    }
-
-    fn print_expression_kind(&mut self, program: &Program, expr: &ExpressionKind) {
-        match expr {
-            ExpressionKind::Ident(name) => {
-                println!("{}Ident {}", self.indent(), name);
-            }
-            ExpressionKind::IntLit(value) => {
-                println!("{}IntLit {}", self.indent(), value);
-            }
-            ExpressionKind::FloatLit(value) => {
-                println!("{}FloatLit {}", self.indent(), value);
-            }
-            ExpressionKind::StringLit(value) => {
-                println!("{}StringLit \"{}\"", self.indent(), value);
-            }
-            ExpressionKind::BoolLit(value) => {
-                println!("{}BoolLit {}", self.indent(), value);
-            }
-            ExpressionKind::TableFieldAccess {
-                table_name,
-                pk_fields,
-                pk_exprs,
-                field_name,
-                resolved_table,
-                resolved_pk_fields,
-                resolved_field,
-                ..
-            } => {
-                println!("{}TableFieldAccess", self.indent());
-                println!(
-                    "{}table_name: {}",
-                    self.with_depth(self.depth + 1).indent(),
-                    table_name
-                );
-
-                // Print primary key fields
-                print!("{}pk_fields: [", self.with_depth(self.depth + 1).indent());
-                for (i, pk_field) in pk_fields.iter().enumerate() {
-                    if i > 0 {
-                        print!(", ");
-                    }
-                    print!("{}", pk_field);
-                }
-                println!("]");
-
-                println!(
-                    "{}field_name: {}",
-                    self.with_depth(self.depth + 1).indent(),
-                    field_name
-                );
-
-                if let Some(resolved_table_id) = resolved_table {
-                    let table = &program.tables[*resolved_table_id];
-                    println!(
-                        "{}resolved_table: {} ({})",
-                        self.with_depth(self.depth + 1).indent(),
-                        table.name,
-                        resolved_table_id.index()
-                    );
-                } else {
-                    println!(
-                        "{}resolved_table: None",
-                        self.with_depth(self.depth + 1).indent()
-                    );
-                }
-
-                // Print resolved primary key fields
-                print!(
-                    "{}resolved_pk_fields: [",
-                    self.with_depth(self.depth + 1).indent()
-                );
-                for (i, resolved_pk_field) in resolved_pk_fields.iter().enumerate() {
-                    if i > 0 {
-                        print!(", ");
-                    }
-                    if let Some(pk_field_id) = resolved_pk_field {
-                        let pk_field = &program.fields[*pk_field_id];
-                        print!("{} ({})", pk_field.field_name, pk_field_id.index());
-                    } else {
-                        print!("None");
-                    }
-                }
-                println!("]");
-
-                if let Some(resolved_field_id) = resolved_field {
-                    let field = &program.fields[*resolved_field_id];
-                    println!(
-                        "{}resolved_field: {} ({})",
-                        self.with_depth(self.depth + 1).indent(),
-                        field.field_name,
-                        resolved_field_id.index()
-                    );
-                } else {
-                    println!(
-                        "{}resolved_field: None",
-                        self.with_depth(self.depth + 1).indent()
-                    );
-                }
-
-                // Print primary key expressions
-                println!("{}pk_exprs:", self.with_depth(self.depth + 1).indent());
-                for (i, &pk_expr) in pk_exprs.iter().enumerate() {
-                    println!("{}[{}]:", self.with_depth(self.depth + 2).indent(), i);
-                    self.with_depth(self.depth + 3)
-                        .print_expression(program, pk_expr);
-                }
-            }
-            ExpressionKind::UnaryOp { op, expr, .. } => {
-                println!("{}UnaryOp", self.indent());
-                println!("{}op: {:?}", self.with_depth(self.depth + 1).indent(), op);
-                println!("{}expr:", self.with_depth(self.depth + 1).indent());
-                self.with_depth(self.depth + 2)
-                    .print_expression(program, *expr);
-            }
-            ExpressionKind::BinaryOp { left, op, right, .. } => {
-                println!("{}BinaryOp", self.indent());
-                println!("{}left:", self.with_depth(self.depth + 1).indent());
-                self.with_depth(self.depth + 2)
-                    .print_expression(program, *left);
-                println!("{}op: {:?}", self.with_depth(self.depth + 1).indent(), op);
-                println!("{}right:", self.with_depth(self.depth + 1).indent());
-                self.with_depth(self.depth + 2)
-                    .print_expression(program, *right);
-            }
-        }
-    }
-}
-
-// WriterPrinter for writing to any writer
-struct WriterPrinter<'a, W: Write> {
-    opts: &'a PrintOptions,
-    depth: usize,
-    writer: &'a mut W,
-}
-
-impl<'a, W: Write> WriterPrinter<'a, W> {
-    fn new(opts: &'a PrintOptions, writer: &'a mut W) -> Self {
+    fn new(opts: &'a PrintOptions, writer: &'a mut dyn Write) -> Self {
         Self {
             opts,
             depth: 0,
             writer,
+            ids: None,
         }
     }
 
@@ -797,13 +55,27 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
 
     fn span(&self, span: &Span) -> String {
         if self.opts.show_spans {
-            format!(" @{}:{}", span.line, span.column)
+            format!(" @{}", span)
         } else {
             String::new()
         }
     }
 
+    /// Span and stable ID suffix for the header line of an element.
+    fn label(&self, span: &Span, element: impl Into<ElementId>) -> String {
+        let id = self
+            .ids
+            .as_ref()
+            .and_then(|ids| ids.get(element))
+            .map(|id| format!(" #{}", id))
+            .unwrap_or_default();
+        format!("{}{}", self.span(span), id)
+    }
+
     fn print_program(&mut self, program: &Program) -> Result<()> {
+        if self.opts.show_ids {
+            self.ids = Some(StableIds::new(program));
+        }
         match self.opts.mode {
             PrintMode::Summary => self.print_summary(program),
             PrintMode::Verbose => self.print_verbose(program),
@@ -836,11 +108,16 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                 .map(|&field_id| {
                     let field = &program.fields[field_id];
                     let prefix = if field.is_primary { "primary " } else { "" };
+                    let suffix = match field.collation {
+                        Some(collation) => format!(" ({})", collation.name()),
+                        None => String::new(),
+                    };
                     format!(
-                        "{}{}:{}",
+                        "{}{}:{}{}",
                         prefix,
                         field.field_name,
-                        type_name(&field.field_type)
+                        type_name(&field.field_type),
+                        suffix
                     )
                 })
                 .collect();
@@ -901,7 +178,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                 "{}[{}] NodeDef{}",
                 indent1,
                 i,
-                self.span(&node.span)
+                self.label(&node.span, node_id)
             )?;
             writeln!(self.writer, "{}name: {}", indent2, node.name)?;
         }
@@ -927,7 +204,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                 "{}[{}] TableDeclaration{}",
                 indent1,
                 i,
-                self.span(&table.span)
+                self.label(&table.span, table_id)
             )?;
             writeln!(self.writer, "{}name: {}", indent2, table.name)?;
             writeln!(self.writer, "{}node: {}", indent2, node.name)?;
@@ -961,7 +238,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                 "{}[{}] FieldDeclaration{}",
                 indent1,
                 i,
-                self.span(&field.span)
+                self.label(&field.span, field_id)
             )?;
             writeln!(
                 self.writer,
@@ -971,6 +248,12 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
             )?;
             writeln!(self.writer, "{}field_name: {}", indent2, field.field_name)?;
             writeln!(self.writer, "{}is_primary: {}", indent2, field.is_primary)?;
+            writeln!(
+                self.writer,
+                "{}collation: {}",
+                indent2,
+                field.collation().name()
+            )?;
         }
         Ok(())
     }
@@ -991,7 +274,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                 "{}[{}] FunctionDeclaration{}",
                 indent1,
                 i,
-                self.span(&func.span)
+                self.label(&func.span, func_id)
             )?;
             writeln!(
                 self.writer,
@@ -1024,7 +307,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                 "{}[{}] ParameterDecl{}",
                 indent1,
                 i,
-                self.span(&param.span)
+                self.label(&param.span, param_id)
             )?;
             writeln!(
                 self.writer,
@@ -1049,7 +332,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                 "{}[{}] HopBlock{}",
                 indent1,
                 i,
-                self.span(&hop.span)
+                self.label(&hop.span, hop_id)
             )?;
             writeln!(self.writer, "{}node_name: {}", indent2, hop.node_name)?;
 
@@ -1081,9 +364,8 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
             stmt_ids.len()
         )?;
         for (i, &stmt_id) in stmt_ids.iter().enumerate() {
-            let stmt = &program.statements[stmt_id];
             self.depth += 1;
-            self.print_statement_with_index(program, i, stmt)?;
+            self.print_statement_with_index(program, i, stmt_id)?;
             self.depth -= 1;
         }
         Ok(())
@@ -1093,8 +375,9 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
         &mut self,
         program: &Program,
         index: usize,
-        stmt: &Statement,
+        stmt_id: StatementId,
     ) -> Result<()> {
+        let stmt = &program.statements[stmt_id];
         let indent = self.indent();
         let indent1 = "  ".repeat(self.depth + 1);
         let indent2 = "  ".repeat(self.depth + 2);
@@ -1107,7 +390,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] VarDeclStatement{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
                 writeln!(
                     self.writer,
@@ -1116,10 +399,12 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     type_name(&v.var_type)
                 )?;
                 writeln!(self.writer, "{}var_name: {}", indent1, v.var_name)?;
-                writeln!(self.writer, "{}init_value:", indent1)?;
-                self.depth += 2;
-                self.print_expression(program, v.init_value)?;
-                self.depth -= 2;
+                if let Some(init_value) = v.init_value {
+                    writeln!(self.writer, "{}init_value:", indent1)?;
+                    self.depth += 2;
+                    self.print_expression(program, init_value)?;
+                    self.depth -= 2;
+                }
             }
             StatementKind::VarAssignment(v) => {
                 writeln!(
@@ -1127,7 +412,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] VarAssignmentStatement{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
                 writeln!(self.writer, "{}var_name: {}", indent1, v.var_name)?;
 
@@ -1156,7 +441,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] AssignmentStatement{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
                 writeln!(self.writer, "{}table_name: {}", indent1, a.table_name)?;
 
@@ -1239,7 +524,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] MultiAssignmentStatement{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
                 writeln!(
                     self.writer,
@@ -1339,7 +624,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] ReturnStatement{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
                 writeln!(self.writer, "{}value:", indent1)?;
                 match &r.value {
@@ -1360,7 +645,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] AbortStatement{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
             }
             StatementKind::IfStmt(i) => {
@@ -1369,7 +654,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] IfStatement{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
                 writeln!(self.writer, "{}condition:", indent1)?;
                 self.depth += 2;
@@ -1398,7 +683,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] WhileStatement{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
                 writeln!(self.writer, "{}condition:", indent1)?;
                 self.depth += 2;
@@ -1415,7 +700,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] BreakStatement{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
             }
             StatementKind::Continue(_) => {
@@ -1424,7 +709,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] ContinueStatement{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
             }
             StatementKind::Empty => {
@@ -1433,7 +718,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
                     "{}[{}] Empty{}",
                     indent,
                     index,
-                    self.span(&stmt.span)
+                    self.label(&stmt.span, stmt_id)
                 )?;
             }
         }
@@ -1446,7 +731,7 @@ impl<'a, W: Write> WriterPrinter<'a, W> {
             self.writer,
             "{}Expression{}",
             self.indent(),
-            self.span(&expr.span)
+            self.label(&expr.span, expr_id)
         )?;
         self.depth += 1;
         self.print_expression_kind(program, &expr.node)?;
@@ -0,0 +1,450 @@
+//! Structured exports of a CFG program for external tools.
+//!
+//! # Overview
+//!
+//! - **print_cfg_json**: The whole program as JSON: nodes, tables and fields,
+//!   then for each function its variables, hops, blocks with their
+//!   instructions, and control-flow edges.
+//! - **print_cfg_graphml**: The control-flow graph of every function as one
+//!   GraphML graph, for graph tools such as yEd, Gephi or NetworkX.
+//!
+//! Ids are written as arena indices. Block and hop ids are local to their
+//! function, so GraphML node ids combine both, as in `f0_bb3`. Edges follow
+//! `HopExit` into the entry block of the next hop, with kind `hop_exit`.
+//!
+//! ```
+//! use FMitF_rs::pretty::print_cfg_json;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void reset(int id) {
+//!         hop on A {
+//!             T[id: id].n = 0;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let mut json = Vec::new();
+//! print_cfg_json(&cfg, &mut json).unwrap();
+//!
+//! let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+//! let instruction = &json["functions"][0]["blocks"][0]["statements"][0];
+//! assert_eq!(instruction["access"]["type"], "write");
+//! ```
+
+use super::cfg_printer::{
+    format_operand, format_statement, format_terminator, return_type_name, type_name,
+};
+use crate::ast::Span;
+use crate::cfg::*;
+use serde::Serialize;
+use std::io::{Result, Write};
+
+#[derive(Serialize)]
+struct ProgramJson<'a> {
+    nodes: Vec<NodeJson<'a>>,
+    tables: Vec<TableJson<'a>>,
+    fields: Vec<FieldJson<'a>>,
+    functions: Vec<FunctionJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct NodeJson<'a> {
+    id: usize,
+    name: &'a str,
+    tables: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct TableJson<'a> {
+    id: usize,
+    name: &'a str,
+    node: usize,
+    fields: Vec<usize>,
+    primary_keys: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct FieldJson<'a> {
+    id: usize,
+    name: &'a str,
+    #[serde(rename = "type")]
+    ty: &'static str,
+    table: Option<usize>,
+    is_primary: bool,
+}
+
+#[derive(Serialize)]
+struct FunctionJson<'a> {
+    id: usize,
+    name: &'a str,
+    return_type: String,
+    span: &'a Span,
+    parameters: Vec<usize>,
+    variables: Vec<VariableJson<'a>>,
+    entry_hop: Option<usize>,
+    hop_order: Vec<usize>,
+    hops: Vec<HopJson<'a>>,
+    blocks: Vec<BlockJson<'a>>,
+    edges: Vec<EdgeJson>,
+}
+
+#[derive(Serialize)]
+struct VariableJson<'a> {
+    id: usize,
+    name: &'a str,
+    #[serde(rename = "type")]
+    ty: &'static str,
+    is_parameter: bool,
+}
+
+#[derive(Serialize)]
+struct HopJson<'a> {
+    id: usize,
+    node: usize,
+    entry_block: Option<usize>,
+    blocks: Vec<usize>,
+    span: &'a Span,
+}
+
+#[derive(Serialize)]
+struct BlockJson<'a> {
+    id: usize,
+    hop: usize,
+    statements: Vec<InstructionJson<'a>>,
+    terminator: TerminatorJson,
+    span: &'a Span,
+}
+
+#[derive(Serialize)]
+struct InstructionJson<'a> {
+    kind: &'static str,
+    text: String,
+    /// The table field the instruction reads or writes, if any
+    access: Option<AccessJson>,
+    span: &'a Span,
+}
+
+#[derive(Serialize)]
+struct AccessJson {
+    #[serde(rename = "type")]
+    access_type: &'static str,
+    table: usize,
+    field: usize,
+    /// Primary key fields and the operands they are matched against
+    pk_fields: Vec<usize>,
+    pk_values: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct TerminatorJson {
+    kind: &'static str,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct EdgeJson {
+    source: usize,
+    target: usize,
+    kind: &'static str,
+}
+
+/// The control-flow edges leaving a block, with their kind.
+fn block_edges(
+    function: &FunctionCfg,
+    block_id: BasicBlockId,
+) -> Vec<(BasicBlockId, &'static str)> {
+    match &function.blocks[block_id].terminator {
+        Terminator::Goto(target) => vec![(*target, "goto")],
+        Terminator::Branch {
+            then_block,
+            else_block,
+            ..
+        } => vec![(*then_block, "then"), (*else_block, "else")],
+        Terminator::HopExit { .. } => function
+            .successors(block_id)
+            .into_iter()
+            .map(|target| (target, "hop_exit"))
+            .collect(),
+        Terminator::Return(_) | Terminator::Abort | Terminator::BoundExceeded(_) => Vec::new(),
+    }
+}
+
+/// The blocks of a function that are still part of their hop.
+fn function_blocks(function: &FunctionCfg) -> impl Iterator<Item = (BasicBlockId, &BasicBlock)> {
+    function
+        .blocks
+        .iter()
+        .filter(|(block_id, _)| function.contains_block(*block_id))
+}
+
+fn access_json(
+    access_type: &'static str,
+    table: TableId,
+    field: FieldId,
+    pk_fields: &[FieldId],
+    pk_values: &[Operand],
+    function: &FunctionCfg,
+) -> Option<AccessJson> {
+    Some(AccessJson {
+        access_type,
+        table: table.index(),
+        field: field.index(),
+        pk_fields: pk_fields.iter().map(|field| field.index()).collect(),
+        pk_values: pk_values
+            .iter()
+            .map(|value| format_operand(value, function))
+            .collect(),
+    })
+}
+
+fn instruction_json<'a>(
+    stmt: &'a Statement,
+    function: &FunctionCfg,
+    program: &CfgProgram,
+) -> InstructionJson<'a> {
+    let (kind, access, span) = match stmt {
+        Statement::Assign {
+            rvalue:
+                Rvalue::TableAccess {
+                    table,
+                    pk_fields,
+                    pk_values,
+                    field,
+                },
+            span,
+            ..
+        } => (
+            "assign",
+            access_json("read", *table, *field, pk_fields, pk_values, function),
+            span,
+        ),
+        Statement::Assign { span, .. } => ("assign", None, span),
+        Statement::TableAssign {
+            table,
+            pk_fields,
+            pk_values,
+            field,
+            span,
+            ..
+        } => (
+            "table_assign",
+            access_json("write", *table, *field, pk_fields, pk_values, function),
+            span,
+        ),
+    };
+    InstructionJson {
+        kind,
+        text: format_statement(stmt, function, program, false),
+        access,
+        span,
+    }
+}
+
+fn function_json<'a>(
+    func_id: FunctionId,
+    function: &'a FunctionCfg,
+    program: &CfgProgram,
+) -> FunctionJson<'a> {
+    let blocks = function_blocks(function)
+        .map(|(block_id, block)| BlockJson {
+            id: block_id.index(),
+            hop: block.hop_id.index(),
+            statements: block
+                .statements
+                .iter()
+                .map(|stmt| instruction_json(stmt, function, program))
+                .collect(),
+            terminator: TerminatorJson {
+                kind: match block.terminator {
+                    Terminator::Goto(_) => "goto",
+                    Terminator::Branch { .. } => "branch",
+                    Terminator::Return(_) => "return",
+                    Terminator::Abort => "abort",
+                    Terminator::BoundExceeded(_) => "bound_exceeded",
+                    Terminator::HopExit { .. } => "hop_exit",
+                },
+                text: format_terminator(&block.terminator, function),
+            },
+            span: &block.span,
+        })
+        .collect();
+    let edges = function_blocks(function)
+        .flat_map(|(block_id, _)| {
+            block_edges(function, block_id)
+                .into_iter()
+                .map(move |(target, kind)| EdgeJson {
+                    source: block_id.index(),
+                    target: target.index(),
+                    kind,
+                })
+        })
+        .collect();
+
+    FunctionJson {
+        id: func_id.index(),
+        name: &function.name,
+        return_type: return_type_name(&function.return_type),
+        span: &function.span,
+        parameters: function.parameters.iter().map(|var| var.index()).collect(),
+        variables: function
+            .variables
+            .iter()
+            .map(|(var_id, var)| VariableJson {
+                id: var_id.index(),
+                name: &var.name,
+                ty: type_name(&var.ty),
+                is_parameter: var.is_parameter,
+            })
+            .collect(),
+        entry_hop: function.entry_hop.map(|hop| hop.index()),
+        hop_order: function.hop_order.iter().map(|hop| hop.index()).collect(),
+        hops: function
+            .hops
+            .iter()
+            .map(|(hop_id, hop)| HopJson {
+                id: hop_id.index(),
+                node: hop.node_id.index(),
+                entry_block: hop.entry_block.map(|block| block.index()),
+                blocks: hop.blocks.iter().map(|block| block.index()).collect(),
+                span: &hop.span,
+            })
+            .collect(),
+        blocks,
+        edges,
+    }
+}
+
+/// Writes the program as pretty-printed JSON.
+pub fn print_cfg_json(program: &CfgProgram, writer: &mut impl Write) -> Result<()> {
+    let json = ProgramJson {
+        nodes: program
+            .nodes
+            .iter()
+            .map(|(node_id, node)| NodeJson {
+                id: node_id.index(),
+                name: &node.name,
+                tables: node.tables.iter().map(|table| table.index()).collect(),
+            })
+            .collect(),
+        tables: program
+            .tables
+            .iter()
+            .map(|(table_id, table)| TableJson {
+                id: table_id.index(),
+                name: &table.name,
+                node: table.node_id.index(),
+                fields: table.fields.iter().map(|field| field.index()).collect(),
+                primary_keys: table
+                    .primary_keys
+                    .iter()
+                    .map(|field| field.index())
+                    .collect(),
+            })
+            .collect(),
+        fields: program
+            .fields
+            .iter()
+            .map(|(field_id, field)| FieldJson {
+                id: field_id.index(),
+                name: &field.name,
+                ty: type_name(&field.ty),
+                table: field.table_id.map(|table| table.index()),
+                is_primary: field.is_primary,
+            })
+            .collect(),
+        functions: program
+            .functions
+            .iter()
+            .map(|(func_id, function)| function_json(func_id, function, program))
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *writer, &json)?;
+    writeln!(writer)
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes the control-flow graphs of all functions as one GraphML graph. Each
+/// block is a node carrying its function, hop, database node, statements and
+/// terminator; each edge carries its kind.
+pub fn print_cfg_graphml(program: &CfgProgram, writer: &mut impl Write) -> Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    let keys = [
+        ("function", "node", "string"),
+        ("hop", "node", "int"),
+        ("node", "node", "string"),
+        ("statements", "node", "string"),
+        ("terminator", "node", "string"),
+        ("kind", "edge", "string"),
+    ];
+    for (name, domain, ty) in keys {
+        writeln!(
+            writer,
+            r#"  <key id="{name}" for="{domain}" attr.name="{name}" attr.type="{ty}"/>"#
+        )?;
+    }
+    writeln!(writer, r#"  <graph id="cfg" edgedefault="directed">"#)?;
+
+    for (func_id, function) in program.functions.iter() {
+        for (block_id, block) in function_blocks(function) {
+            let hop = &function.hops[block.hop_id];
+            let statements: Vec<String> = block
+                .statements
+                .iter()
+                .map(|stmt| format_statement(stmt, function, program, false))
+                .collect();
+            writeln!(
+                writer,
+                r#"    <node id="f{}_bb{}">"#,
+                func_id.index(),
+                block_id.index()
+            )?;
+            let data = [
+                ("function", escape_xml(&function.name)),
+                ("hop", block.hop_id.index().to_string()),
+                ("node", escape_xml(&program.nodes[hop.node_id].name)),
+                ("statements", escape_xml(&statements.join("\n"))),
+                (
+                    "terminator",
+                    escape_xml(&format_terminator(&block.terminator, function)),
+                ),
+            ];
+            for (key, value) in data {
+                writeln!(writer, r#"      <data key="{key}">{value}</data>"#)?;
+            }
+            writeln!(writer, "    </node>")?;
+        }
+    }
+
+    for (func_id, function) in program.functions.iter() {
+        for (block_id, _) in function_blocks(function) {
+            for (target, kind) in block_edges(function, block_id) {
+                writeln!(
+                    writer,
+                    r#"    <edge source="f{f}_bb{}" target="f{f}_bb{}"><data key="kind">{kind}</data></edge>"#,
+                    block_id.index(),
+                    target.index(),
+                    f = func_id.index(),
+                )?;
+            }
+        }
+    }
+
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</graphml>")
+}
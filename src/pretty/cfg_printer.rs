@@ -1,5 +1,7 @@
 use crate::ast::{ReturnType, Span, TypeName};
 use crate::cfg::*;
+use crate::dataflow::{FieldSlice, KeyValue, KeyedAccess, SliceRole};
+use crate::pretty::{print_cfg_graphml, print_cfg_json};
 use std::io::{Result, Write};
 
 fn escape_dot_label(s: &str) -> String {
@@ -11,24 +13,29 @@ fn escape_dot_label(s: &str) -> String {
 
 fn format_span_str(span: &Span, show_spans: bool) -> String {
     if show_spans {
-        format!(" @{}:{}", span.line, span.column)
+        format!(" @{}", span)
     } else {
         String::new()
     }
 }
 
-fn format_operand(operand: &Operand, function: &FunctionCfg) -> String {
+pub(super) fn format_operand(operand: &Operand, function: &FunctionCfg) -> String {
     match operand {
         Operand::Var(var_id) => {
             let var = &function.variables[*var_id];
             format!("%{}", var.name)
         }
-        Operand::Const(c) => match c {
-            Constant::Int(i) => i.to_string(),
-            Constant::Float(f) => f.to_string(),
-            Constant::Bool(b) => b.to_string(),
-            Constant::String(s) => format!("\"{}\"", escape_dot_label(s)),
-        },
+        Operand::Const(c) => format_constant(c),
+    }
+}
+
+/// Formats a constant as it is written in source.
+pub fn format_constant(constant: &Constant) -> String {
+    match constant {
+        Constant::Int(i) => i.to_string(),
+        Constant::Float(f) => f.to_string(),
+        Constant::Bool(b) => b.to_string(),
+        Constant::String(s) => format!("{:?}", s),
     }
 }
 
@@ -79,7 +86,7 @@ fn format_rvalue(rvalue: &Rvalue, function: &FunctionCfg, program: &CfgProgram)
     }
 }
 
-fn format_statement(
+pub(super) fn format_statement(
     stmt: &Statement,
     function: &FunctionCfg,
     program: &CfgProgram,
@@ -135,7 +142,7 @@ fn format_statement(
     format!("{}{}", stmt_str, format_span_str(span_val, show_spans))
 }
 
-fn format_terminator(terminator: &Terminator, function: &FunctionCfg) -> String {
+pub(super) fn format_terminator(terminator: &Terminator, function: &FunctionCfg) -> String {
     match terminator {
         Terminator::Goto(block_id) => format!("goto bb{}", block_id.index()),
         Terminator::Branch {
@@ -153,6 +160,8 @@ fn format_terminator(terminator: &Terminator, function: &FunctionCfg) -> String
         Terminator::Return(Some(op)) => format!("return {}", format_operand(op, function)),
         Terminator::Return(None) => "return".to_string(),
         Terminator::Abort => "abort".to_string(),
+        Terminator::BoundExceeded(BoundCheck::Assume) => "assume false (loop bound)".to_string(),
+        Terminator::BoundExceeded(BoundCheck::Assert) => "assert false (loop bound)".to_string(),
         Terminator::HopExit { next_hop } => match next_hop {
             Some(hop_id) => format!("hop_exit -> hop{}", hop_id.index()),
             None => "hop_exit -> function_end".to_string(),
@@ -160,6 +169,218 @@ fn format_terminator(terminator: &Terminator, function: &FunctionCfg) -> String
     }
 }
 
+fn format_key(key: &[(FieldId, Operand)], function: &FunctionCfg, program: &CfgProgram) -> String {
+    key.iter()
+        .map(|(field, value)| {
+            format!(
+                "{}:{}",
+                program.fields[*field].name,
+                format_operand(value, function)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Formats an access with its resolved primary key, as in `T[id: 1].n`.
+/// Parameters show by name, and values nothing is known about as `?`.
+pub fn format_keyed_access(access: &KeyedAccess, program: &CfgProgram) -> String {
+    let function = &program.functions[access.function];
+    let key: Vec<String> = access
+        .key
+        .iter()
+        .map(|(field, value)| {
+            let value = match value {
+                KeyValue::Const(constant) => format_constant(constant),
+                KeyValue::Param { var, .. } => function.variables[*var].name.clone(),
+                KeyValue::Unknown => "?".to_string(),
+            };
+            format!("{}: {}", program.fields[*field].name, value)
+        })
+        .collect();
+    format!(
+        "{}[{}].{}",
+        program.tables[access.table].name,
+        key.join(", "),
+        program.fields[access.field].name
+    )
+}
+
+/// Formats a lowered instruction, with labels flush left and everything else
+/// indented, as in a listing.
+pub fn format_instruction(
+    instruction: &Instruction,
+    function: &FunctionCfg,
+    program: &CfgProgram,
+) -> String {
+    let var = |var: &VarId| format!("%{}", function.variables[*var].name);
+    let text = match instruction {
+        Instruction::Label(block_id) => return format!("bb{}:", block_id.index()),
+        Instruction::Copy { dst, src } => {
+            format!("{} = {}", var(dst), format_operand(src, function))
+        }
+        Instruction::Unary { dst, op, operand } => format!(
+            "{} = {:?} {}",
+            var(dst),
+            op,
+            format_operand(operand, function)
+        ),
+        Instruction::Binary {
+            dst,
+            op,
+            left,
+            right,
+        } => format!(
+            "{} = {} {:?} {}",
+            var(dst),
+            format_operand(left, function),
+            op,
+            format_operand(right, function)
+        ),
+        Instruction::Load {
+            dst,
+            table,
+            key,
+            field,
+        } => format!(
+            "{} = load {}[{}].{}",
+            var(dst),
+            program.tables[*table].name,
+            format_key(key, function, program),
+            program.fields[*field].name
+        ),
+        Instruction::Store {
+            table,
+            key,
+            field,
+            value,
+        } => format!(
+            "store {}[{}].{} = {}",
+            program.tables[*table].name,
+            format_key(key, function, program),
+            program.fields[*field].name,
+            format_operand(value, function)
+        ),
+        Instruction::Jump(target) => format!("jump bb{}", target.index()),
+        Instruction::Branch {
+            condition,
+            then_label,
+            else_label,
+        } => format!(
+            "branch {} bb{} bb{}",
+            format_operand(condition, function),
+            then_label.index(),
+            else_label.index()
+        ),
+        Instruction::Return(Some(value)) => format!("return {}", format_operand(value, function)),
+        Instruction::Return(None) => "return".to_string(),
+        Instruction::Abort => "abort".to_string(),
+        Instruction::BoundExceeded(BoundCheck::Assume) => "assume false".to_string(),
+        Instruction::BoundExceeded(BoundCheck::Assert) => "assert false".to_string(),
+        Instruction::HopExit { next_hop } => match next_hop {
+            Some(hop_id) => format!("hop_exit hop{}", hop_id.index()),
+            None => "hop_exit".to_string(),
+        },
+    };
+    format!("    {}", text)
+}
+
+/// Formats a field slice as its sites grouped by function, each with its role
+/// and the statement or terminator at the site.
+pub fn format_field_slice(slice: &FieldSlice, program: &CfgProgram) -> String {
+    let mut text = format!(
+        "Slice of {}.{}:\n",
+        program.tables[slice.table].name, program.fields[slice.field].name
+    );
+    for function_id in slice.functions() {
+        let function = &program.functions[function_id];
+        text.push_str(&format!("  {}:\n", function.name));
+        for site in slice
+            .sites
+            .iter()
+            .filter(|site| site.function == function_id)
+        {
+            let block = &function.blocks[site.block];
+            let code = match block.statements.get(site.index) {
+                Some(stmt) => format_statement(stmt, function, program, false),
+                None => format_terminator(&block.terminator, function),
+            };
+            let role = match site.role {
+                SliceRole::Reads => "reads",
+                SliceRole::Writes => "writes",
+                SliceRole::Affects => "affects",
+                SliceRole::Observes => "observes",
+            };
+            text.push_str(&format!(
+                "    bb{}[{}] {}: {}\n",
+                site.block.index(),
+                site.index,
+                role,
+                code
+            ));
+        }
+    }
+    text
+}
+
+/// Formats a path through a hop as its blocks, with the branch condition on each
+/// edge that takes a branch and the terminator the path ends in, as in
+/// `bb0 -[%c]-> bb1 -> bb2 -[!%d]-> bb4 (return)`.
+pub fn format_hop_path(path: &HopPath, function: &FunctionCfg) -> String {
+    let mut text = format!("bb{}", path.blocks[0].index());
+    let mut conditions = path.conditions.iter().peekable();
+    for window in path.blocks.windows(2) {
+        match conditions.next_if(|cond| cond.block == window[0]) {
+            Some(cond) => text.push_str(&format!(
+                " -[{}{}]-> ",
+                if cond.taken { "" } else { "!" },
+                format_operand(&cond.condition, function)
+            )),
+            None => text.push_str(" -> "),
+        }
+        text.push_str(&format!("bb{}", window[1].index()));
+    }
+    let exit = &function.blocks[path.exit()].terminator;
+    format!("{} ({})", text, format_terminator(exit, function))
+}
+
+/// Border colors for the blocks of each node, cycled when there are more nodes
+const NODE_COLORS: [&str; 8] = [
+    "steelblue",
+    "darkorange",
+    "forestgreen",
+    "crimson",
+    "purple",
+    "goldenrod",
+    "teal",
+    "saddlebrown",
+];
+
+fn node_color(node_id: NodeId) -> &'static str {
+    NODE_COLORS[node_id.index() % NODE_COLORS.len()]
+}
+
+/// Formats a branch condition as the expression its block assigns it, which is
+/// more telling than the temporary holding it.
+fn format_branch_condition(
+    block: &BasicBlock,
+    condition: &Operand,
+    function: &FunctionCfg,
+    program: &CfgProgram,
+) -> String {
+    let definition = block.statements.iter().rev().find_map(|stmt| match stmt {
+        Statement::Assign { var, rvalue, .. } if *condition == Operand::Var(*var) => Some(rvalue),
+        _ => None,
+    });
+    match definition {
+        Some(rvalue) => format_rvalue(rvalue, function, program),
+        None => format_operand(condition, function),
+    }
+}
+
+/// Prints the CFG in DOT format: a cluster per function holding a cluster per
+/// hop, with block borders colored by the node the hop runs on and branch edges
+/// labeled with their conditions.
 pub fn print_cfg_dot_format(
     program: &CfgProgram,
     writer: &mut impl Write,
@@ -167,8 +388,12 @@ pub fn print_cfg_dot_format(
 ) -> Result<()> {
     writeln!(writer, "digraph CFG {{")?;
     writeln!(writer, "  compound=true;")?;
-    writeln!(writer, "  node [shape=box, style=rounded];")?;
-    writeln!(writer, "")?;
+    writeln!(
+        writer,
+        "  node [shape=box, style=\"rounded,filled\", fillcolor=white, fontname=monospace];"
+    )?;
+    writeln!(writer, "  edge [fontname=monospace, fontsize=10];")?;
+    writeln!(writer)?;
 
     for (func_id, function) in program.functions.iter() {
         writeln!(writer, "  subgraph cluster_func_{} {{", func_id.index())?;
@@ -181,27 +406,22 @@ pub fn print_cfg_dot_format(
         )?;
         writeln!(writer, "    style=filled;")?;
         writeln!(writer, "    color=lightgrey;")?;
-        writeln!(writer, "")?;
-
-        let mut hop_to_blocks: std::collections::HashMap<HopId, Vec<BasicBlockId>> =
-            std::collections::HashMap::new();
+        writeln!(writer)?;
 
-        for (block_id, block) in function.blocks.iter() {
-            hop_to_blocks
-                .entry(block.hop_id)
-                .or_default()
-                .push(block_id);
-        }
+        let entry_block = function
+            .entry_hop
+            .and_then(|hop_id| function.hops[hop_id].entry_block);
 
-        for (hop_id, blocks_in_hop) in &hop_to_blocks {
+        for &hop_id in &function.hop_order {
             writeln!(
                 writer,
                 "    subgraph cluster_func_{}_hop_{} {{",
                 func_id.index(),
                 hop_id.index()
             )?;
-            let hop = &function.hops[*hop_id];
+            let hop = &function.hops[hop_id];
             let node_name = &program.nodes[hop.node_id].name;
+            let color = node_color(hop.node_id);
             let hop_span_str = format_span_str(&hop.span, show_spans);
             writeln!(
                 writer,
@@ -210,43 +430,65 @@ pub fn print_cfg_dot_format(
                 escape_dot_label(node_name),
                 hop_span_str
             )?;
-            writeln!(writer, "      style=dotted;")?;
+            writeln!(writer, "      style=dashed;")?;
+            writeln!(writer, "      color={};", color)?;
+            writeln!(writer, "      fontcolor={};", color)?;
 
-            for &block_id in blocks_in_hop {
-                let block = &function.blocks[block_id];
-                let block_span_str = format_span_str(&block.span, show_spans);
-                let mut label = format!(
-                    "BB{} (Hop {}){}\n",
+            // Blocks in arena order, so the output is stable
+            for (block_id, block) in function.blocks.iter() {
+                if block.hop_id != hop_id || !function.contains_block(block_id) {
+                    continue;
+                }
+                let mut lines = vec![format!(
+                    "BB{} (Hop {}){}",
                     block_id.index(),
                     hop_id.index(),
-                    block_span_str
+                    format_span_str(&block.span, show_spans)
+                )];
+                lines.extend(
+                    block
+                        .statements
+                        .iter()
+                        .map(|stmt| format_statement(stmt, function, program, show_spans)),
                 );
-
-                for stmt in &block.statements {
-                    label.push_str(&format!(
-                        "{}\n",
-                        format_statement(stmt, function, program, show_spans)
-                    ));
-                }
-
-                label.push_str(&format!(
+                lines.push(format!(
                     "Term: {}",
                     format_terminator(&block.terminator, function)
                 ));
-
+                // Left-justified lines read better than centered ones
+                let label: String = lines
+                    .iter()
+                    .map(|line| format!("{}\\l", escape_dot_label(line)))
+                    .collect();
+
+                // The function's entry stands out, and so do the blocks that abort
+                let penwidth = if Some(block_id) == entry_block { 4 } else { 2 };
+                let mut attributes = format!(
+                    "label=\"{}\", color={}, penwidth={}",
+                    label, color, penwidth
+                );
+                if matches!(
+                    block.terminator,
+                    Terminator::Abort | Terminator::BoundExceeded(_)
+                ) {
+                    attributes.push_str(", fillcolor=mistyrose");
+                }
                 writeln!(
                     writer,
-                    "      f{}_bb{} [label=\"{}\"];",
+                    "      f{}_bb{} [{}];",
                     func_id.index(),
                     block_id.index(),
-                    escape_dot_label(&label)
+                    attributes
                 )?;
             }
             writeln!(writer, "    }}")?;
         }
-        writeln!(writer, "")?;
+        writeln!(writer)?;
 
         for (block_id, block) in function.blocks.iter() {
+            if !function.contains_block(block_id) {
+                continue;
+            }
             let source_node = format!("f{}_bb{}", func_id.index(), block_id.index());
 
             match &block.terminator {
@@ -264,46 +506,95 @@ pub fn print_cfg_dot_format(
                     then_block,
                     else_block,
                 } => {
-                    let cond_str = format_operand(condition, function);
+                    let cond_str = format_branch_condition(block, condition, function, program);
                     writeln!(
                         writer,
-                        "    {} -> f{}_bb{} [label=\"{}\"];",
+                        "    {} -> f{}_bb{} [label=\"{}\", color=darkgreen, fontcolor=darkgreen];",
                         source_node,
                         func_id.index(),
                         then_block.index(),
-                        escape_dot_label(&format!("{} is true", cond_str))
+                        escape_dot_label(&cond_str)
                     )?;
                     writeln!(
                         writer,
-                        "    {} -> f{}_bb{} [label=\"{}\"];",
+                        "    {} -> f{}_bb{} [label=\"{}\", color=firebrick, fontcolor=firebrick];",
                         source_node,
                         func_id.index(),
                         else_block.index(),
-                        escape_dot_label(&format!("{} is false", cond_str))
+                        escape_dot_label(&format!("!({})", cond_str))
                     )?;
                 }
                 Terminator::HopExit {
                     next_hop: Some(next_hop_id),
                 } => {
+                    // Drawn between the hop clusters rather than the blocks
                     let next_hop_entry = function.hops[*next_hop_id].entry_block.unwrap(); // Use unwrap()
                     writeln!(
                         writer,
-                        "    {} -> f{}_bb{} [label=\"To Hop {}\", style=dashed];",
+                        "    {} -> f{}_bb{} [label=\"To Hop {}\", style=dashed, ltail=cluster_func_{}_hop_{}, lhead=cluster_func_{}_hop_{}];",
                         source_node,
                         func_id.index(),
                         next_hop_entry.index(),
+                        next_hop_id.index(),
+                        func_id.index(),
+                        block.hop_id.index(),
+                        func_id.index(),
                         next_hop_id.index()
                     )?;
                 }
                 Terminator::Return(_)
                 | Terminator::Abort
+                | Terminator::BoundExceeded(_)
                 | Terminator::HopExit { next_hop: None } => {
                     // No outgoing edges for these terminators in the dot graph
                 }
             }
         }
         writeln!(writer, "  }}")?;
-        writeln!(writer, "")?;
+        writeln!(writer)?;
+    }
+
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+/// Prints a dominator or post-dominator tree of a function in DOT format, with
+/// an edge from each block to the blocks it immediately dominates.
+pub fn print_dominator_tree_dot(
+    function: &FunctionCfg,
+    tree: &DominatorTree,
+    title: &str,
+    writer: &mut impl Write,
+) -> Result<()> {
+    writeln!(writer, "digraph DominatorTree {{")?;
+    writeln!(
+        writer,
+        "  label=\"{}: {}\";",
+        escape_dot_label(title),
+        escape_dot_label(&function.name)
+    )?;
+    writeln!(writer, "  node [shape=box, style=rounded];")?;
+    writeln!(writer)?;
+
+    for (block_id, block) in function.blocks.iter() {
+        if !tree.contains(block_id) {
+            continue;
+        }
+        writeln!(
+            writer,
+            "  bb{} [label=\"BB{} (Hop {})\\n{}\"];",
+            block_id.index(),
+            block_id.index(),
+            block.hop_id.index(),
+            escape_dot_label(&format_terminator(&block.terminator, function))
+        )?;
+    }
+    writeln!(writer)?;
+
+    for (block_id, _) in function.blocks.iter() {
+        for child in tree.children(block_id) {
+            writeln!(writer, "  bb{} -> bb{};", block_id.index(), child.index())?;
+        }
     }
 
     writeln!(writer, "}}")?;
@@ -360,7 +651,7 @@ pub fn format_cfg_text(program: &CfgProgram, options: &CfgPrintOptions) -> Strin
                     param_str
                 ));
             }
-            s.push_str("\n");
+            s.push('\n');
 
             s.push_str("  Hops:\n");
             for (hop_id, hop) in function.hops.iter() {
@@ -381,10 +672,13 @@ pub fn format_cfg_text(program: &CfgProgram, options: &CfgPrintOptions) -> Strin
                     hop.blocks.iter().map(|b| b.index()).collect::<Vec<_>>()
                 ));
             }
-            s.push_str("\n");
+            s.push('\n');
 
             s.push_str("  Basic Blocks:\n");
             for (block_id, block) in function.blocks.iter() {
+                if !function.contains_block(block_id) {
+                    continue;
+                }
                 let block_span_str = format_span_str(&block.span, options.show_spans);
                 s.push_str(&format!(
                     "    BB{} (Hop {}):{}\n",
@@ -422,6 +716,52 @@ pub fn format_cfg_text(program: &CfgProgram, options: &CfgPrintOptions) -> Strin
     s
 }
 
+/// The basic blocks of one hop as text, for inspecting a hop on its own.
+pub fn format_hop_text(
+    program: &CfgProgram,
+    function_id: FunctionId,
+    hop_id: HopId,
+    show_spans: bool,
+) -> String {
+    let function = &program.functions[function_id];
+    let hop = &function.hops[hop_id];
+    let mut s = format!(
+        "Hop {} of {} on Node {}{}\n",
+        hop_id.index(),
+        function.name,
+        program.nodes[hop.node_id].name,
+        format_span_str(&hop.span, show_spans)
+    );
+    for &block_id in &hop.blocks {
+        if !function.contains_block(block_id) {
+            continue;
+        }
+        let block = &function.blocks[block_id];
+        let entry = if hop.entry_block == Some(block_id) {
+            " (entry)"
+        } else {
+            ""
+        };
+        s.push_str(&format!(
+            "  BB{}{}:{}\n",
+            block_id.index(),
+            entry,
+            format_span_str(&block.span, show_spans)
+        ));
+        for stmt in &block.statements {
+            s.push_str(&format!(
+                "    {}\n",
+                format_statement(stmt, function, program, show_spans)
+            ));
+        }
+        s.push_str(&format!(
+            "    Terminator: {}\n",
+            format_terminator(&block.terminator, function)
+        ));
+    }
+    s
+}
+
 pub fn print_cfg_summary(program: &CfgProgram) -> String {
     let mut s = String::new();
     s.push_str("CFG Summary:\n");
@@ -449,7 +789,7 @@ pub fn print_cfg_summary(program: &CfgProgram) -> String {
 }
 
 // Helper functions for type names
-fn type_name(t: &TypeName) -> &'static str {
+pub(super) fn type_name(t: &TypeName) -> &'static str {
     match t {
         TypeName::Int => "int",
         TypeName::Float => "float",
@@ -458,7 +798,7 @@ fn type_name(t: &TypeName) -> &'static str {
     }
 }
 
-fn return_type_name(ret: &ReturnType) -> String {
+pub(super) fn return_type_name(ret: &ReturnType) -> String {
     match ret {
         ReturnType::Void => "void".to_string(),
         ReturnType::Type(t) => type_name(t).to_string(),
@@ -479,6 +819,8 @@ pub enum CfgFormat {
     Text,
     Dot,
     Summary,
+    Json,
+    GraphMl,
 }
 
 impl Default for CfgPrintOptions {
@@ -518,6 +860,8 @@ pub fn print_cfg(
             let output = print_cfg_summary(program);
             write!(writer, "{}", output)?;
         }
+        CfgFormat::Json => print_cfg_json(program, writer)?,
+        CfgFormat::GraphMl => print_cfg_graphml(program, writer)?,
     }
     Ok(())
 }
@@ -0,0 +1,99 @@
+use crate::ast::diff::{ChangeKind, ProgramDiff};
+use crate::sc_graph::GraphDelta;
+use std::io::{Result, Write};
+
+/// Prints a structural program diff to `writer`.
+///
+/// Each change starts with `+` (added), `-` (removed) or `~` (changed) and its
+/// path, followed by the affected source lines.
+pub fn print_program_diff(diff: &ProgramDiff, writer: &mut dyn Write) -> Result<()> {
+    if diff.is_empty() {
+        return writeln!(writer, "No differences");
+    }
+
+    for change in &diff.changes {
+        let marker = match change.kind {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Changed => '~',
+        };
+        let location = change
+            .new_span
+            .as_ref()
+            .or(change.old_span.as_ref())
+            .map(|span| format!(" @{}", span))
+            .unwrap_or_default();
+        writeln!(writer, "{} {}{}", marker, change.path, location)?;
+
+        if let Some(old) = &change.old {
+            for line in old.lines() {
+                writeln!(writer, "    - {}", line)?;
+            }
+        }
+        if let Some(new) = &change.new {
+            for line in new.lines() {
+                writeln!(writer, "    + {}", line)?;
+            }
+        }
+    }
+
+    let count = |kind| diff.changes.iter().filter(|c| c.kind == kind).count();
+    writeln!(
+        writer,
+        "{} added, {} removed, {} changed",
+        count(ChangeKind::Added),
+        count(ChangeKind::Removed),
+        count(ChangeKind::Changed)
+    )
+}
+
+/// Prints what changed in the SC-graph to `writer`: the C-edges and mixed
+/// cycles added (`+`) and removed (`-`), and the C-edges whose verification
+/// answers no longer apply if they were compared.
+pub fn print_graph_delta(delta: &GraphDelta, writer: &mut dyn Write) -> Result<()> {
+    writeln!(
+        writer,
+        "C-edges: {} added, {} removed",
+        delta.added_edges.len(),
+        delta.removed_edges.len()
+    )?;
+    for edge in &delta.added_edges {
+        writeln!(writer, "+ {}", edge)?;
+    }
+    for edge in &delta.removed_edges {
+        writeln!(writer, "- {}", edge)?;
+    }
+
+    let truncated = if delta.truncated {
+        " (--max-cycles reached)"
+    } else {
+        ""
+    };
+    writeln!(
+        writer,
+        "Mixed cycles: {} fixed, {} introduced, {} kept{}",
+        delta.fixed_cycles.len(),
+        delta.introduced_cycles.len(),
+        delta.kept_cycles,
+        truncated
+    )?;
+    for (id, hops) in &delta.fixed_cycles {
+        writeln!(writer, "- [{}] {}", id, hops)?;
+    }
+    for (id, hops) in &delta.introduced_cycles {
+        writeln!(writer, "+ [{}] {}", id, hops)?;
+    }
+
+    if let Some(answers) = &delta.answers {
+        writeln!(
+            writer,
+            "Verification answers: {} reused, {} invalidated",
+            answers.reused,
+            answers.invalidated.len()
+        )?;
+        for edge in &answers.invalidated {
+            writeln!(writer, "~ {}", edge)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,508 @@
+//! Documentation of a workload, written from its source so the two cannot
+//! drift apart: the nodes, the tables with their columns and primary keys,
+//! and the transactions with their signatures and the tables each hop reads
+//! and writes.
+//!
+//! The comments just before a declaration, or after a column on its line,
+//! describe it; `assume(...)` and `ensures(...)` comments are listed as the
+//! properties of tables and transactions instead.
+//!
+//! ```
+//! use FMitF_rs::pretty::doc_printer::{markdown_docs, WorkloadDocs};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     // Balances, in cents
+//!     table Account on A { primary int id; int balance; }
+//!     table Log on B { primary int id; int amount; }
+//!     // Moves money out of an account
+//!     void withdraw(int id, int amount) {
+//!         hop on A { Account[id: id].balance = Account[id: id].balance - amount; }
+//!         hop on B { Log[id: id].amount = amount; }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let docs = WorkloadDocs::new(&program, &cfg, "bank");
+//! assert_eq!(docs.tables[0].description, ["Balances, in cents"]);
+//! let hop = &docs.transactions[0].hops[0];
+//! assert_eq!(hop.reads, ["Account.balance"]);
+//! assert_eq!(hop.writes, ["Account.balance"]);
+//!
+//! let markdown = markdown_docs(&docs);
+//! assert!(markdown.contains("`void withdraw(int id, int amount)`"));
+//! assert!(markdown.contains("| H1 | B |  | `Log.amount` |"));
+//! ```
+
+use super::formatter::type_name;
+use super::html_report::escape;
+use crate::ast::{Program, ReturnType, Symbol, SyntaxAnchor, Trivia};
+use crate::cfg::{CfgProgram, FunctionCfg, HopCfg, Rvalue, Statement};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// A workload, documented.
+#[derive(Serialize, Debug, Clone)]
+pub struct WorkloadDocs {
+    pub title: String,
+    pub nodes: Vec<NodeDocs>,
+    pub tables: Vec<TableDocs>,
+    pub transactions: Vec<TransactionDocs>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct NodeDocs {
+    pub name: String,
+    pub description: Vec<String>,
+    /// The tables placed on it
+    pub tables: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TableDocs {
+    pub name: String,
+    pub node: String,
+    pub description: Vec<String>,
+    pub columns: Vec<ColumnDocs>,
+    /// Its `assume(...)` properties, as written
+    pub assumptions: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ColumnDocs {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    pub primary: bool,
+    /// The collation of a string column declared with `collate`
+    pub collation: Option<&'static str>,
+    pub description: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TransactionDocs {
+    pub name: String,
+    /// As declared, e.g. `void transfer(int from, int to)`
+    pub signature: String,
+    pub description: Vec<String>,
+    /// The hops, in the order they run
+    pub hops: Vec<HopDocs>,
+    /// Its `ensures(...)` properties, as written
+    pub ensures: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HopDocs {
+    pub node: String,
+    pub description: Vec<String>,
+    /// The table fields it may read, as `Table.field`, in the order of the source
+    pub reads: Vec<String>,
+    /// The table fields it may write
+    pub writes: Vec<String>,
+}
+
+impl WorkloadDocs {
+    /// Documents the workload of `program`, whose CFG is `cfg`.
+    pub fn new(program: &Program, cfg: &CfgProgram, title: &str) -> Self {
+        let nodes = cfg
+            .root_nodes
+            .iter()
+            .map(|&node_id| {
+                let node = &cfg.nodes[node_id];
+                let anchor = program
+                    .node_map
+                    .get(&Symbol::intern(&node.name))
+                    .map(|&id| SyntaxAnchor::Node(id));
+                NodeDocs {
+                    name: node.name.clone(),
+                    description: description(program, anchor),
+                    tables: node
+                        .tables
+                        .iter()
+                        .map(|&table| cfg.tables[table].name.clone())
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let tables = cfg
+            .root_tables
+            .iter()
+            .map(|&table_id| {
+                let table = &cfg.tables[table_id];
+                let declaration = program.table_map.get(&Symbol::intern(&table.name));
+                let columns = table
+                    .fields
+                    .iter()
+                    .map(|&field_id| {
+                        let field = &cfg.fields[field_id];
+                        let declared =
+                            declaration.and_then(|&table| {
+                                program.tables[table].fields.iter().copied().find(|&id| {
+                                    program.fields[id].field_name == field.name.as_str()
+                                })
+                            });
+                        ColumnDocs {
+                            name: field.name.clone(),
+                            ty: type_name(&field.ty),
+                            primary: field.is_primary,
+                            collation: declared.and_then(|id| {
+                                program.fields[id]
+                                    .collation
+                                    .map(|collation| collation.name())
+                            }),
+                            description: description(program, declared.map(SyntaxAnchor::Field)),
+                        }
+                    })
+                    .collect();
+                TableDocs {
+                    name: table.name.clone(),
+                    node: cfg.nodes[table.node_id].name.clone(),
+                    description: description(
+                        program,
+                        declaration.map(|&id| SyntaxAnchor::Table(id)),
+                    ),
+                    columns,
+                    assumptions: table.assumptions.clone(),
+                }
+            })
+            .collect();
+
+        let transactions = cfg
+            .root_functions
+            .iter()
+            .map(|&func_id| transaction(program, cfg, &cfg.functions[func_id]))
+            .collect();
+
+        Self {
+            title: title.to_string(),
+            nodes,
+            tables,
+            transactions,
+        }
+    }
+}
+
+fn transaction(program: &Program, cfg: &CfgProgram, function: &FunctionCfg) -> TransactionDocs {
+    let declaration = program
+        .function_map
+        .get(&Symbol::intern(&function.name))
+        .copied();
+    // An instantiated template is described by its `instantiate` statement
+    let anchor = declaration.map(|id| {
+        match program
+            .template_instances
+            .iter()
+            .position(|instance| instance.function == id)
+        {
+            Some(index) => SyntaxAnchor::Instance(index),
+            None => SyntaxAnchor::Function(id),
+        }
+    });
+    let params: Vec<String> = function
+        .parameters
+        .iter()
+        .map(|&var| {
+            let variable = &function.variables[var];
+            format!("{} {}", type_name(&variable.ty), variable.name)
+        })
+        .collect();
+    let ret = match &function.return_type {
+        ReturnType::Void => "void",
+        ReturnType::Type(ty) => type_name(ty),
+    };
+    let hop_blocks = declaration.map(|id| &program.functions[id].hops);
+    let hops = function
+        .hop_order
+        .iter()
+        .enumerate()
+        .map(|(index, &hop_id)| {
+            let hop = &function.hops[hop_id];
+            let (reads, writes) = hop_accesses(cfg, function, hop);
+            let declared = hop_blocks.and_then(|hops| hops.get(index)).copied();
+            HopDocs {
+                node: cfg.nodes[hop.node_id].name.clone(),
+                description: description(program, declared.map(SyntaxAnchor::Hop)),
+                reads,
+                writes,
+            }
+        })
+        .collect();
+    TransactionDocs {
+        name: function.name.clone(),
+        signature: format!("{} {}({})", ret, function.name, params.join(", ")),
+        description: description(program, anchor),
+        hops,
+        ensures: function.ensures.clone(),
+    }
+}
+
+/// The table fields a hop may read and write, each once, in the order of the
+/// source.
+fn hop_accesses(
+    cfg: &CfgProgram,
+    function: &FunctionCfg,
+    hop: &HopCfg,
+) -> (Vec<String>, Vec<String>) {
+    let mut statements: Vec<&Statement> = hop
+        .blocks
+        .iter()
+        .flat_map(|&block| &function.blocks[block].statements)
+        .collect();
+    statements.sort_by_key(|statement| statement.span().start);
+
+    let name = |table, field| format!("{}.{}", cfg.tables[table].name, cfg.fields[field].name);
+    let (mut reads, mut writes) = (Vec::new(), Vec::new());
+    for statement in statements {
+        let (list, access) = match statement {
+            Statement::Assign {
+                rvalue: Rvalue::TableAccess { table, field, .. },
+                ..
+            } => (&mut reads, name(*table, *field)),
+            Statement::TableAssign { table, field, .. } => (&mut writes, name(*table, *field)),
+            Statement::Assign { .. } => continue,
+        };
+        if !list.contains(&access) {
+            list.push(access);
+        }
+    }
+    (reads, writes)
+}
+
+/// The lines of the comments just before `anchor`, and of the one after it on
+/// its line, without their slashes or the annotations listed on their own.
+fn description(program: &Program, anchor: Option<SyntaxAnchor>) -> Vec<String> {
+    let Some(anchor) = anchor else {
+        return Vec::new();
+    };
+    let leading = program.trivia.leading(anchor);
+    // Only the comments not separated from the declaration by a blank line
+    let start = leading
+        .iter()
+        .rposition(|item| !matches!(item, Trivia::Comment { .. }))
+        .map_or(0, |index| index + 1);
+    leading[start..]
+        .iter()
+        .chain(program.trivia.trailing(anchor))
+        .filter_map(|item| match item {
+            Trivia::Comment { text, .. } => Some(text.trim_start_matches('/').trim()),
+            _ => None,
+        })
+        .filter(|text| !text.starts_with("assume(") && !text.starts_with("ensures("))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The documentation as Markdown.
+pub fn markdown_docs(docs: &WorkloadDocs) -> String {
+    let cell = |text: &str| text.replace('|', "\\|");
+    let code = |items: &[String]| {
+        items
+            .iter()
+            .map(|item| format!("`{}`", item))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}\n", docs.title);
+    let _ = writeln!(
+        out,
+        "Generated by {} {} from the workload's source.\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    out.push_str("## Nodes\n\n| Node | Tables | Description |\n|---|---|---|\n");
+    for node in &docs.nodes {
+        let tables: Vec<String> = node
+            .tables
+            .iter()
+            .map(|table| format!("[{0}](#{1})", table, anchor_name(table)))
+            .collect();
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} |",
+            node.name,
+            tables.join(", "),
+            cell(&node.description.join(" "))
+        );
+    }
+
+    out.push_str("\n## Tables\n");
+    for table in &docs.tables {
+        let _ = writeln!(out, "\n### {}\n\nOn node {}.\n", table.name, table.node);
+        for line in &table.description {
+            let _ = writeln!(out, "{}", line);
+        }
+        if !table.description.is_empty() {
+            out.push('\n');
+        }
+        out.push_str("| Column | Type | Key | Description |\n|---|---|---|---|\n");
+        for column in &table.columns {
+            let ty = match column.collation {
+                Some(collation) => format!("{} collate {}", column.ty, collation),
+                None => column.ty.to_string(),
+            };
+            let _ = writeln!(
+                out,
+                "| `{}` | {} | {} | {} |",
+                column.name,
+                ty,
+                if column.primary { "primary" } else { "" },
+                cell(&column.description.join(" "))
+            );
+        }
+        if !table.assumptions.is_empty() {
+            let _ = writeln!(out, "\nAssumes {}.", code(&table.assumptions));
+        }
+    }
+
+    out.push_str("\n## Transactions\n");
+    for transaction in &docs.transactions {
+        let _ = writeln!(
+            out,
+            "\n### {}\n\n`{}`\n",
+            transaction.name, transaction.signature
+        );
+        for line in &transaction.description {
+            let _ = writeln!(out, "{}", line);
+        }
+        if !transaction.description.is_empty() {
+            out.push('\n');
+        }
+        out.push_str("| Hop | Node | Reads | Writes | Description |\n|---|---|---|---|---|\n");
+        for (index, hop) in transaction.hops.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "| H{} | {} | {} | {} | {} |",
+                index,
+                hop.node,
+                code(&hop.reads),
+                code(&hop.writes),
+                cell(&hop.description.join(" "))
+            );
+        }
+        if !transaction.ensures.is_empty() {
+            let _ = writeln!(out, "\nEnsures {}.", code(&transaction.ensures));
+        }
+    }
+    out
+}
+
+/// The id GitHub gives the heading of a name in Markdown.
+fn anchor_name(name: &str) -> String {
+    name.to_lowercase()
+}
+
+/// The documentation as a self-contained HTML page.
+pub fn html_docs(docs: &WorkloadDocs) -> String {
+    let code = |items: &[String]| {
+        items
+            .iter()
+            .map(|item| format!("<code>{}</code>", escape(item)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let lines = |lines: &[String]| escape(&lines.join(" "));
+    let mut html = String::new();
+    let _ = writeln!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{0}</title>\n<style>\n{1}\n</style>\n</head>\n<body>\n<h1>{0}</h1>",
+        escape(&docs.title),
+        STYLE
+    );
+    let _ = writeln!(
+        html,
+        "<p class=\"note\">Generated by {} {} from the workload's source.</p>",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    html.push_str(
+        "<h2>Nodes</h2>\n<table>\n<tr><th>Node</th><th>Tables</th><th>Description</th></tr>\n",
+    );
+    for node in &docs.nodes {
+        let tables: Vec<String> = node
+            .tables
+            .iter()
+            .map(|table| format!("<a href=\"#table-{0}\">{0}</a>", escape(table)))
+            .collect();
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&node.name),
+            tables.join(", "),
+            lines(&node.description)
+        );
+    }
+    html.push_str("</table>\n<h2>Tables</h2>\n");
+    for table in &docs.tables {
+        let _ = writeln!(
+            html,
+            "<h3 id=\"table-{0}\">{0}</h3>\n<p>On node {1}. {2}</p>",
+            escape(&table.name),
+            escape(&table.node),
+            lines(&table.description)
+        );
+        html.push_str(
+            "<table>\n<tr><th>Column</th><th>Type</th><th>Key</th><th>Description</th></tr>\n",
+        );
+        for column in &table.columns {
+            let ty = match column.collation {
+                Some(collation) => format!("{} collate {}", column.ty, collation),
+                None => column.ty.to_string(),
+            };
+            let _ = writeln!(
+                html,
+                "<tr><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&column.name),
+                ty,
+                if column.primary { "primary" } else { "" },
+                lines(&column.description)
+            );
+        }
+        html.push_str("</table>\n");
+        if !table.assumptions.is_empty() {
+            let _ = writeln!(html, "<p>Assumes {}.</p>", code(&table.assumptions));
+        }
+    }
+    html.push_str("<h2>Transactions</h2>\n");
+    for transaction in &docs.transactions {
+        let _ = writeln!(
+            html,
+            "<h3 id=\"transaction-{}\">{}</h3>\n<p><code>{}</code></p>\n<p>{}</p>",
+            escape(&transaction.name),
+            escape(&transaction.name),
+            escape(&transaction.signature),
+            lines(&transaction.description)
+        );
+        html.push_str("<table>\n<tr><th>Hop</th><th>Node</th><th>Reads</th><th>Writes</th><th>Description</th></tr>\n");
+        for (index, hop) in transaction.hops.iter().enumerate() {
+            let _ = writeln!(
+                html,
+                "<tr><td>H{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                index,
+                escape(&hop.node),
+                code(&hop.reads),
+                code(&hop.writes),
+                lines(&hop.description)
+            );
+        }
+        html.push_str("</table>\n");
+        if !transaction.ensures.is_empty() {
+            let _ = writeln!(html, "<p>Ensures {}.</p>", code(&transaction.ensures));
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+const STYLE: &str =
+    "body { font-family: sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+.note { color: #666; }";
@@ -0,0 +1,89 @@
+use crate::ast::SourceFile;
+use crate::cfg::{CfgProgram, CfgSite};
+use crate::sc_graph::{EdgeExplanation, EdgeType, Explanation};
+use std::io::{Result, Write};
+
+/// Prints an explained finding to `writer`, quoting the source line of each
+/// conflicting access from `sources`.
+pub fn print_explanation(
+    explanation: &Explanation,
+    cfg: &CfgProgram,
+    sources: &[SourceFile],
+    writer: &mut dyn Write,
+) -> Result<()> {
+    match explanation {
+        Explanation::Edge(edge) => print_edge(edge, cfg, sources, writer),
+        Explanation::Cycle {
+            id,
+            hops,
+            explanation,
+            c_edges,
+        } => {
+            writeln!(writer, "Mixed cycle [{}]: {}", id, hops)?;
+            for (number, step) in explanation.steps.iter().enumerate() {
+                let edge_type = match step.edge_type {
+                    EdgeType::S => "S",
+                    EdgeType::C => "C",
+                };
+                writeln!(
+                    writer,
+                    "  {}. {}-edge: {}",
+                    number + 1,
+                    edge_type,
+                    step.text
+                )?;
+            }
+            writeln!(writer, "{}.", explanation.conclusion)?;
+            writeln!(writer, "\nDropping any of its C-edges breaks it:")?;
+            for edge in c_edges {
+                writeln!(writer)?;
+                print_edge(edge, cfg, sources, writer)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn print_edge(
+    edge: &EdgeExplanation,
+    cfg: &CfgProgram,
+    sources: &[SourceFile],
+    writer: &mut dyn Write,
+) -> Result<()> {
+    let status = if edge.kept { "kept" } else { "dropped" };
+    writeln!(writer, "C-edge {} ({})", edge.hops, status)?;
+    if !edge.conflicts.is_empty() {
+        writeln!(writer, "  Conflicts:")?;
+    }
+    for (conflict, text) in &edge.conflicts {
+        writeln!(writer, "  - {}: {}", conflict.kind, text)?;
+        for site in [conflict.source, conflict.target] {
+            writeln!(writer, "      | {}", source_line(site, cfg, sources))?;
+        }
+    }
+    if edge.kept {
+        writeln!(writer, "  Not pruned:")?;
+        for step in &edge.pruning {
+            writeln!(writer, "  - {}", step)?;
+        }
+    } else {
+        for reason in &edge.pruning {
+            writeln!(writer, "  Pruned: {}", reason)?;
+        }
+    }
+    if let Some(obligation) = &edge.obligation {
+        writeln!(writer, "  Verification must prove {}", obligation)?;
+    }
+    Ok(())
+}
+
+/// The line of source an access is on, trimmed.
+fn source_line<'a>(site: CfgSite, cfg: &CfgProgram, sources: &'a [SourceFile]) -> &'a str {
+    let span = cfg.site_span(site);
+    sources
+        .iter()
+        .find(|file| file.path == span.file)
+        .and_then(|file| file.source.lines().nth(span.line.saturating_sub(1)))
+        .unwrap_or_default()
+        .trim()
+}
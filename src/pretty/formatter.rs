@@ -0,0 +1,713 @@
+//! Canonical source formatter for TransAct programs.
+//!
+//! `format_program` prints a `Program` back as TransAct source with four-space
+//! indentation, one statement per line and a blank line between top-level
+//! declarations. Parentheses are emitted only where operator precedence needs
+//! them, so formatting is idempotent: formatting the output again yields the
+//! same text. Comments recorded in the program's trivia are kept next to the
+//! declaration or statement they belong to, and runs of blank lines between
+//! statements are collapsed to one. A statement with a comment inside it, as in
+//! the middle of an expression, is printed as written.
+
+use crate::ast::*;
+use std::sync::Arc;
+
+const INDENT: &str = "    ";
+
+/// Formats the root file of `program` as canonical TransAct source.
+///
+/// For programs assembled from several files only the declarations of the root
+/// file are printed; its `import` statements are kept as written. Template
+/// declarations are printed as written, since their bodies only exist in the
+/// AST once expanded.
+///
+/// ```rust
+/// let source = "nodes {\n    A\n}\n\nvoid f(bool a, bool b, bool c) {\n    hop on A {\n        bool x = a && (b || c);\n        bool y = a && b || c;\n    }\n}\n";
+/// let program = FMitF_rs::parse_and_analyze(source).unwrap();
+/// assert_eq!(FMitF_rs::format_program(&program), source);
+/// ```
+///
+/// Comments closing a then-branch stay in it, even before an empty else block:
+///
+/// ```rust
+/// let source = "nodes {\n    A\n}\n\nvoid f(bool a) {\n    hop on A {\n        if (a) {\n            abort;\n            // then\n        } else {}\n    }\n}\n";
+/// let program = FMitF_rs::parse_and_analyze(source).unwrap();
+/// assert_eq!(FMitF_rs::format_program(&program), source);
+/// ```
+///
+/// Comments inside an expression are kept with the statement around them:
+///
+/// ```rust
+/// let source = "nodes {\n    A\n}\n\nvoid f(int x) {\n    hop on A {\n        int y = x + // mid-expr\n            1;\n    }\n}\n";
+/// let program = FMitF_rs::parse_and_analyze(source).unwrap();
+/// assert_eq!(FMitF_rs::format_program(&program), source);
+/// ```
+///
+/// `&&` binds tighter than `||`, so a chain mixing them prints as the same
+/// chain with its redundant parentheses dropped:
+///
+/// ```rust
+/// let format = |expr: &str| {
+///     let source = format!("nodes {{ A }} void f(bool a, bool b, bool c) {{ hop on A {{ bool x = {}; }} }}", expr);
+///     let program = FMitF_rs::parse_and_analyze(&source).unwrap();
+///     let formatted = FMitF_rs::format_program(&program);
+///     formatted.lines().find_map(|line| line.trim().strip_prefix("bool x = ")).unwrap().to_string()
+/// };
+/// assert_eq!(format("a && b || c"), "a && b || c;");
+/// assert_eq!(format("(a && b) || c"), "a && b || c;");
+/// assert_eq!(format("a && (b || c)"), "a && (b || c);");
+/// assert_eq!(format("a || b && c"), "a || b && c;");
+/// assert_eq!(format("a || (b && c)"), "a || b && c;");
+/// assert_eq!(format("(a || b) && c"), "(a || b) && c;");
+/// ```
+pub fn format_program(program: &Program) -> String {
+    let mut formatter = SourceFormatter::new(program, &program.trivia);
+    formatter.format();
+    formatter.out
+}
+
+/// Formats a single statement, including nested blocks, without comments.
+pub fn format_statement(program: &Program, stmt_id: StatementId) -> String {
+    let trivia = TriviaMap::default();
+    let mut formatter = SourceFormatter::new(program, &trivia);
+    formatter.format_statement(stmt_id);
+    formatter.out.trim_end().to_string()
+}
+
+enum TopLevelItem {
+    Function(FunctionId),
+    Template(usize),
+    Instance(usize),
+}
+
+struct SourceFormatter<'a> {
+    program: &'a Program,
+    trivia: &'a TriviaMap,
+    root_file: Option<Arc<std::path::Path>>,
+    root_index: usize,
+    out: String,
+    depth: usize,
+}
+
+impl<'a> SourceFormatter<'a> {
+    fn new(program: &'a Program, trivia: &'a TriviaMap) -> Self {
+        Self {
+            program,
+            trivia,
+            root_file: program.source_files.last().and_then(|f| f.path.clone()),
+            root_index: program.source_files.len().saturating_sub(1),
+            out: String::new(),
+            depth: 0,
+        }
+    }
+
+    fn in_root_file(&self, span: &Span) -> bool {
+        self.program.source_files.len() <= 1 || span.file == self.root_file
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn separate(&mut self) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+    }
+
+    /// Prints the comments (and, where allowed, one blank line) before `anchor`.
+    fn leading(&mut self, anchor: SyntaxAnchor, keep_blank_lines: bool) {
+        self.leading_items(self.trivia.leading(anchor), keep_blank_lines);
+    }
+
+    /// Prints a blank line and the leading trivia of a declaration or hop that is
+    /// always separated from the previous one.
+    ///
+    /// `#else` and `#endif` lines directly after the previous item stay attached to
+    /// it, before the separating blank line.
+    fn separated_leading(&mut self, anchor: SyntaxAnchor) {
+        let items = self.trivia.leading(anchor);
+        let closing = items
+            .iter()
+            .take_while(|item| match item {
+                Trivia::Disabled { text, .. } => {
+                    text.trim_start().starts_with("#else")
+                        || text.trim_start().starts_with("#endif")
+                }
+                _ => false,
+            })
+            .count();
+        self.leading_items(&items[..closing], false);
+        self.separate();
+        self.leading_items(&items[closing..], false);
+    }
+
+    /// Prints trivia items. Blank lines before the first printed item are only kept
+    /// when `keep_blank_lines` is set; runs of blank lines collapse to one.
+    fn leading_items(&mut self, items: &[Trivia], keep_blank_lines: bool) {
+        let mut printed = false;
+        for item in items {
+            match item {
+                Trivia::BlankLines(_) => {
+                    let at_block_start = self.out.is_empty() || self.out.ends_with("{\n");
+                    if (keep_blank_lines || printed)
+                        && !at_block_start
+                        && !self.out.ends_with("\n\n")
+                    {
+                        self.out.push('\n');
+                    }
+                }
+                Trivia::Comment { text, .. } => {
+                    self.line(text);
+                    printed = true;
+                }
+                Trivia::Disabled { text, .. } => {
+                    self.verbatim(text);
+                    printed = true;
+                }
+            }
+        }
+    }
+
+    /// Prints lines as written, without re-indenting them.
+    fn verbatim(&mut self, text: &str) {
+        for line in text.lines() {
+            self.out.push_str(line.trim_end());
+            self.out.push('\n');
+        }
+    }
+
+    /// Prints the source text of `span` at the current indentation. Later lines
+    /// keep their indentation relative to the first.
+    fn as_written(&mut self, span: &Span) {
+        let Some(file) = self
+            .program
+            .source_files
+            .iter()
+            .find(|f| f.path == span.file)
+        else {
+            return;
+        };
+        let base = span.column.saturating_sub(1);
+        for (index, line) in file.source[span.start..span.end].lines().enumerate() {
+            let line = if index == 0 {
+                line
+            } else {
+                let indent = line.len() - line.trim_start().len();
+                &line[indent.min(base)..]
+            };
+            self.line(line.trim_end());
+        }
+    }
+
+    /// Appends the same-line comment of `anchor` to the last printed line.
+    fn trailing(&mut self, anchor: SyntaxAnchor) {
+        if let Some(Trivia::Comment { text, .. }) = self.trivia.trailing(anchor) {
+            self.out.pop();
+            self.out.push(' ');
+            self.out.push_str(text);
+            self.out.push('\n');
+        }
+    }
+
+    /// Prints the comments inside `anchor` that precede its closing brace.
+    ///
+    /// Blank lines are kept between items but dropped at the start of the block.
+    fn dangling(&mut self, anchor: SyntaxAnchor, filter: impl Fn(&Span) -> bool) {
+        let items = self.trivia.dangling(anchor);
+        for (index, item) in items.iter().enumerate() {
+            match item {
+                Trivia::Comment { text, span } if filter(span) => self.line(text),
+                Trivia::Disabled { text, span } if filter(span) => self.verbatim(text),
+                Trivia::BlankLines(_) => {
+                    let next_printed = match items.get(index + 1) {
+                        Some(Trivia::Comment { span, .. } | Trivia::Disabled { span, .. }) => {
+                            filter(span)
+                        }
+                        _ => false,
+                    };
+                    if next_printed && !self.out.ends_with("{\n") && !self.out.ends_with("\n\n") {
+                        self.out.push('\n');
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn has_dangling(&self, anchor: SyntaxAnchor) -> bool {
+        !self.trivia.dangling(anchor).is_empty()
+    }
+
+    /// Whether `anchor` has dangling comments or disabled sections that pass `filter`.
+    fn has_dangling_comments(&self, anchor: SyntaxAnchor, filter: impl Fn(&Span) -> bool) -> bool {
+        self.program
+            .trivia
+            .dangling(anchor)
+            .iter()
+            .any(|item| match item {
+                Trivia::Comment { span, .. } | Trivia::Disabled { span, .. } => filter(span),
+                Trivia::BlankLines(_) => false,
+            })
+    }
+
+    fn format(&mut self) {
+        let program = self.program;
+        let file = SyntaxAnchor::File(self.root_index);
+
+        self.leading(file, false);
+        if let Some(root) = program.source_files.last() {
+            if let Some(version) = root.version {
+                self.line(&format!("version {};", version));
+            }
+            for import in &root.imports {
+                self.line(&format!("import \"{}\";", import));
+            }
+        }
+
+        let nodes: Vec<(NodeId, &NodeDef)> = program
+            .root_nodes
+            .iter()
+            .map(|id| (*id, &program.nodes[*id]))
+            .filter(|(_, node)| self.in_root_file(&node.span))
+            .collect();
+        if !nodes.is_empty() {
+            let block = SyntaxAnchor::NodesBlock(self.root_index);
+            self.separated_leading(block);
+            self.line("nodes {");
+            self.depth += 1;
+            for (i, (id, node)) in nodes.iter().enumerate() {
+                let sep = if i + 1 < nodes.len() { "," } else { "" };
+                self.leading(SyntaxAnchor::Node(*id), true);
+                self.line(&format!("{}{}", node.name, sep));
+                self.trailing(SyntaxAnchor::Node(*id));
+            }
+            self.dangling(block, |_| true);
+            self.depth -= 1;
+            self.line("}");
+            self.trailing(block);
+        }
+
+        for table_id in &program.root_tables {
+            let table = &program.tables[*table_id];
+            if self.in_root_file(&table.span) {
+                self.format_table(*table_id, table);
+            }
+        }
+
+        // Functions, templates and instantiations, in source order
+        let instances: Vec<FunctionId> = program
+            .template_instances
+            .iter()
+            .map(|inst| inst.function)
+            .collect();
+        let mut items: Vec<(usize, TopLevelItem)> = Vec::new();
+        for function_id in &program.root_functions {
+            let function = &program.functions[*function_id];
+            if self.in_root_file(&function.span) && !instances.contains(function_id) {
+                items.push((function.span.start, TopLevelItem::Function(*function_id)));
+            }
+        }
+        for (index, template) in program.templates.iter().enumerate() {
+            if self.in_root_file(&template.span) {
+                items.push((template.span.start, TopLevelItem::Template(index)));
+            }
+        }
+        for (index, instance) in program.template_instances.iter().enumerate() {
+            if self.in_root_file(&instance.span) {
+                items.push((instance.span.start, TopLevelItem::Instance(index)));
+            }
+        }
+        items.sort_by_key(|(start, _)| *start);
+
+        for (_, item) in items {
+            match item {
+                TopLevelItem::Function(id) => self.format_function(id, &program.functions[id]),
+                TopLevelItem::Template(index) => self.format_template(index),
+                TopLevelItem::Instance(index) => self.format_instance(index),
+            }
+        }
+
+        if self.has_dangling(file) {
+            self.separate();
+            self.dangling(file, |_| true);
+        }
+    }
+
+    /// Prints a template declaration as written; its body is only built per instance.
+    fn format_template(&mut self, index: usize) {
+        let anchor = SyntaxAnchor::Template(index);
+        let span = &self.program.templates[index].span;
+        let source = match self.program.source_files.last() {
+            Some(file) => &file.source[span.start..span.end],
+            None => "",
+        };
+        self.separated_leading(anchor);
+        self.verbatim(source);
+        self.trailing(anchor);
+    }
+
+    fn format_instance(&mut self, index: usize) {
+        let anchor = SyntaxAnchor::Instance(index);
+        let instance = &self.program.template_instances[index];
+        let name = &self.program.functions[instance.function].name;
+        self.separated_leading(anchor);
+        self.line(&format!(
+            "instantiate {}<{}> as {};",
+            instance.template,
+            instance.args.join(", "),
+            name
+        ));
+        self.trailing(anchor);
+    }
+
+    fn format_table(&mut self, table_id: TableId, table: &TableDeclaration) {
+        let anchor = SyntaxAnchor::Table(table_id);
+        let node_name = &self.program.nodes[table.node].name;
+        self.separated_leading(anchor);
+        self.line(&format!("table {} on {} {{", table.name, node_name));
+        self.depth += 1;
+        for field_id in &table.fields {
+            let field = &self.program.fields[*field_id];
+            let primary = if field.is_primary { "primary " } else { "" };
+            self.leading(SyntaxAnchor::Field(*field_id), true);
+            let collation = match field.collation {
+                Some(collation) => format!(" collate {}", collation.name()),
+                None => String::new(),
+            };
+            self.line(&format!(
+                "{}{} {}{};",
+                primary,
+                type_name(&field.field_type),
+                field.field_name,
+                collation
+            ));
+            self.trailing(SyntaxAnchor::Field(*field_id));
+        }
+        self.dangling(anchor, |_| true);
+        self.depth -= 1;
+        self.line("}");
+        self.trailing(anchor);
+    }
+
+    fn format_function(&mut self, function_id: FunctionId, function: &FunctionDeclaration) {
+        let anchor = SyntaxAnchor::Function(function_id);
+        let params: Vec<String> = function
+            .parameters
+            .iter()
+            .map(|id| {
+                let param = &self.program.parameters[*id];
+                format!("{} {}", type_name(&param.param_type), param.param_name)
+            })
+            .collect();
+        let ret = match &function.return_type {
+            ReturnType::Void => "void",
+            ReturnType::Type(ty) => type_name(ty),
+        };
+        self.separated_leading(anchor);
+        self.line(&format!(
+            "{} {}({}) {{",
+            ret,
+            function.name,
+            params.join(", ")
+        ));
+
+        self.depth += 1;
+        for (i, hop_id) in function.hops.iter().enumerate() {
+            let hop = &self.program.hops[*hop_id];
+            let hop_anchor = SyntaxAnchor::Hop(*hop_id);
+            if i > 0 {
+                self.separated_leading(hop_anchor);
+            } else {
+                self.leading(hop_anchor, false);
+            }
+            self.format_block(
+                &format!("hop on {} ", hop.node_name),
+                &hop.statements,
+                "",
+                Some(hop_anchor),
+            );
+            self.trailing(hop_anchor);
+        }
+        self.dangling(anchor, |_| true);
+        self.depth -= 1;
+        self.line("}");
+        self.trailing(anchor);
+    }
+
+    /// Prints `{header}{ ... }{trailer}`, collapsing empty blocks to `{}`.
+    ///
+    /// Dangling comments of `owner` are printed before the closing brace.
+    fn format_block(
+        &mut self,
+        header: &str,
+        statements: &[StatementId],
+        trailer: &str,
+        owner: Option<SyntaxAnchor>,
+    ) {
+        self.format_block_with(header, statements, trailer, owner, |_| true)
+    }
+
+    fn format_block_with(
+        &mut self,
+        header: &str,
+        statements: &[StatementId],
+        trailer: &str,
+        owner: Option<SyntaxAnchor>,
+        dangling_filter: impl Fn(&Span) -> bool,
+    ) {
+        let has_dangling =
+            owner.is_some_and(|owner| self.has_dangling_comments(owner, &dangling_filter));
+        if statements.is_empty() && !has_dangling {
+            self.line(&format!("{}{{}}{}", header, trailer));
+            return;
+        }
+        self.line(&format!("{}{{", header));
+        self.depth += 1;
+        for stmt_id in statements {
+            self.format_statement(*stmt_id);
+        }
+        if let Some(owner) = owner {
+            self.dangling(owner, &dangling_filter);
+        }
+        self.depth -= 1;
+        self.line(&format!("}}{}", trailer));
+    }
+
+    fn format_statement(&mut self, stmt_id: StatementId) {
+        let program = self.program;
+        let anchor = SyntaxAnchor::Statement(stmt_id);
+        self.leading(anchor, true);
+        let stmt = &program.statements[stmt_id];
+        // Blocks print their dangling comments before the closing brace; other
+        // statements have nowhere to put a comment written inside them
+        let is_block = matches!(
+            stmt.node,
+            StatementKind::IfStmt(_)
+                | StatementKind::WhileStmt(_)
+                | StatementKind::MultiAssignment(_)
+        );
+        if !is_block && self.has_dangling_comments(anchor, |_| true) {
+            self.as_written(&stmt.span);
+            self.trailing(anchor);
+            return;
+        }
+        match &stmt.node {
+            StatementKind::VarDecl(decl) => {
+                let init = match decl.init_value {
+                    Some(init_value) => format!(" = {}", self.expr(init_value)),
+                    None => String::new(),
+                };
+                self.line(&format!(
+                    "{} {}{};",
+                    type_name(&decl.var_type),
+                    decl.var_name,
+                    init
+                ));
+            }
+            StatementKind::VarAssignment(assign) => {
+                let rhs = self.expr(assign.rhs);
+                self.line(&format!("{} = {};", assign.var_name, rhs));
+            }
+            StatementKind::Assignment(assign) => {
+                let target =
+                    self.table_ref(&assign.table_name, &assign.pk_fields, &assign.pk_exprs);
+                let rhs = self.expr(assign.rhs);
+                self.line(&format!("{}.{} = {};", target, assign.field_name, rhs));
+            }
+            StatementKind::MultiAssignment(multi) => {
+                let target = self.table_ref(&multi.table_name, &multi.pk_fields, &multi.pk_exprs);
+                self.line(&format!("{} = {{", target));
+                self.depth += 1;
+                for (i, pair) in multi.assignments.iter().enumerate() {
+                    let sep = if i + 1 < multi.assignments.len() {
+                        ","
+                    } else {
+                        ""
+                    };
+                    let rhs = self.expr(pair.rhs);
+                    self.line(&format!("{}: {}{}", pair.field_name, rhs, sep));
+                }
+                self.dangling(anchor, |_| true);
+                self.depth -= 1;
+                self.line("};");
+            }
+            StatementKind::IfStmt(if_stmt) => {
+                let cond = self.expr(if_stmt.condition);
+                let header = format!("if ({}) ", cond);
+                // Dangling comments before the `else` keyword close the then-branch
+                let else_start = if_stmt.else_span.as_ref().map(|span| span.start);
+                let in_then = |span: &Span| else_start.is_some_and(|start| span.start < start);
+                match &if_stmt.else_branch {
+                    Some(else_branch)
+                        if else_branch.is_empty()
+                            && !self.has_dangling_comments(anchor, |span| !in_then(span)) =>
+                    {
+                        self.format_block_with(
+                            &header,
+                            &if_stmt.then_branch,
+                            " else {}",
+                            Some(anchor),
+                            in_then,
+                        );
+                    }
+                    Some(else_branch) => {
+                        // The opening brace of the else block is printed as the trailer
+                        self.format_block_with(
+                            &header,
+                            &if_stmt.then_branch,
+                            " else {",
+                            Some(anchor),
+                            in_then,
+                        );
+                        self.depth += 1;
+                        for stmt in else_branch {
+                            self.format_statement(*stmt);
+                        }
+                        self.dangling(anchor, |span| !in_then(span));
+                        self.depth -= 1;
+                        self.line("}");
+                    }
+                    None => self.format_block(&header, &if_stmt.then_branch, "", Some(anchor)),
+                }
+            }
+            StatementKind::WhileStmt(while_stmt) => {
+                let cond = self.expr(while_stmt.condition);
+                self.format_block(
+                    &format!("while ({}) ", cond),
+                    &while_stmt.body,
+                    "",
+                    Some(anchor),
+                );
+            }
+            StatementKind::Return(ret) => match ret.value {
+                Some(value) => {
+                    let value = self.expr(value);
+                    self.line(&format!("return {};", value));
+                }
+                None => self.line("return;"),
+            },
+            StatementKind::Abort(_) => self.line("abort;"),
+            StatementKind::Break(_) => self.line("break;"),
+            StatementKind::Continue(_) => self.line("continue;"),
+            StatementKind::Empty => self.line(";"),
+        }
+        self.trailing(anchor);
+    }
+
+    fn table_ref(&self, table: &str, pk_fields: &[Symbol], pk_exprs: &[ExpressionId]) -> String {
+        let keys: Vec<String> = pk_fields
+            .iter()
+            .zip(pk_exprs)
+            .map(|(field, expr)| format!("{}: {}", field, self.expr(*expr)))
+            .collect();
+        format!("{}[{}]", table, keys.join(", "))
+    }
+
+    fn expr(&self, expr_id: ExpressionId) -> String {
+        self.expr_with_precedence(expr_id, 0)
+    }
+
+    /// Formats an expression, parenthesizing it if it binds looser than `min_prec`.
+    fn expr_with_precedence(&self, expr_id: ExpressionId, min_prec: u8) -> String {
+        let (text, prec) = match &self.program.expressions[expr_id].node {
+            ExpressionKind::Ident(name) => (name.to_string(), PRIMARY_PREC),
+            ExpressionKind::IntLit(value) => (value.to_string(), PRIMARY_PREC),
+            ExpressionKind::FloatLit(value) => (float_literal(*value), PRIMARY_PREC),
+            ExpressionKind::StringLit(value) => (format!("\"{}\"", value), PRIMARY_PREC),
+            ExpressionKind::BoolLit(value) => (value.to_string(), PRIMARY_PREC),
+            ExpressionKind::TableFieldAccess {
+                table_name,
+                pk_fields,
+                pk_exprs,
+                field_name,
+                ..
+            } => (
+                format!(
+                    "{}.{}",
+                    self.table_ref(table_name, pk_fields, pk_exprs),
+                    field_name
+                ),
+                PRIMARY_PREC,
+            ),
+            ExpressionKind::UnaryOp { op, expr, .. } => {
+                let op = match op {
+                    UnaryOp::Not => "!",
+                    UnaryOp::Neg => "-",
+                    UnaryOp::FoldCase => unreachable!("FoldCase is only inserted into the CFG"),
+                };
+                let operand = self.expr_with_precedence(*expr, UNARY_PREC);
+                (format!("{}{}", op, operand), UNARY_PREC)
+            }
+            ExpressionKind::BinaryOp {
+                left, op, right, ..
+            } => {
+                let prec = binary_precedence(op);
+                // All binary operators are left-associative
+                let left = self.expr_with_precedence(*left, prec);
+                let right = self.expr_with_precedence(*right, prec + 1);
+                (format!("{} {} {}", left, binary_op_str(op), right), prec)
+            }
+        };
+
+        if prec < min_prec {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+}
+
+const UNARY_PREC: u8 = 7;
+const PRIMARY_PREC: u8 = 8;
+
+fn binary_precedence(op: &BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Or => 1,
+        BinaryOp::And => 2,
+        BinaryOp::Eq | BinaryOp::Neq => 3,
+        BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte => 4,
+        BinaryOp::Add | BinaryOp::Sub => 5,
+        BinaryOp::Mul | BinaryOp::Div => 6,
+    }
+}
+
+pub(crate) fn binary_op_str(op: &BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Lt => "<",
+        BinaryOp::Lte => "<=",
+        BinaryOp::Gt => ">",
+        BinaryOp::Gte => ">=",
+        BinaryOp::Eq => "==",
+        BinaryOp::Neq => "!=",
+        BinaryOp::And => "&&",
+        BinaryOp::Or => "||",
+    }
+}
+
+/// Float literals always need a fractional part to re-parse as floats.
+pub(crate) fn float_literal(value: f64) -> String {
+    let text = value.to_string();
+    if text.contains('.') {
+        text
+    } else {
+        format!("{}.0", text)
+    }
+}
+
+pub(crate) fn type_name(t: &TypeName) -> &'static str {
+    match t {
+        TypeName::Int => "int",
+        TypeName::Float => "float",
+        TypeName::String => "string",
+        TypeName::Bool => "bool",
+    }
+}
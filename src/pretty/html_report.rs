@@ -0,0 +1,409 @@
+//! A self-contained HTML report of a chopping, for design reviews.
+//!
+//! One file, with no scripts and nothing to fetch, holds the program's
+//! statistics, the SC-graph drawn as SVG, the mixed cycles explained step by
+//! step and, when verification ran, what it found of each C-edge, the verdict
+//! and the assumptions it rests on.
+//!
+//! The SC-graph is laid out with a column per transaction and its hops from
+//! top to bottom in the order they run, so S-edges are the vertical lines and
+//! C-edges the curves between columns. C-edges on a cycle are drawn thicker;
+//! those verification removed are dashed, and those it failed or could not
+//! decide are red or orange.
+//!
+//! ```
+//! use FMitF_rs::pretty::html_report::html_report;
+//! use FMitF_rs::sc_graph::{CycleLimits, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table T on A { primary int id; int n; }
+//!     table U on B { primary int id; int n; }
+//!     void f(int id) { hop on A { T[id: id].n = 1; } hop on B { int n = U[id: id].n; } }
+//!     void g(int id) { hop on A { int n = T[id: id].n; } hop on B { U[id: id].n = 2; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//!
+//! let html = html_report(&cfg, &sc_graph, Err("no prover"), &CycleLimits::default(), "bank");
+//! assert!(html.starts_with("<!DOCTYPE html>"));
+//! assert!(html.contains("<svg"));
+//! assert!(html.contains("1 cycle remains"));
+//! assert!(html.contains("f reads U.n at"));
+//! assert!(html.contains("Verification was not run: no prover"));
+//! ```
+
+use crate::cfg::CfgProgram;
+use crate::sc_graph::{CycleLimits, EdgeType, SCGraph, SCGraphNodeId};
+use crate::verification::report::{hop_label, verdict, VerificationReport};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+const COLUMN_WIDTH: usize = 170;
+const ROW_HEIGHT: usize = 80;
+const MARGIN: usize = 40;
+const TOP: usize = 70;
+
+const STYLE: &str =
+    "body { font-family: sans-serif; margin: 2em auto; max-width: 70em; color: #222; }
+h1 { margin-bottom: 0.2em; }
+.verdict { font-size: 1.3em; font-weight: bold; }
+.good { color: #2a7a2a; }
+.bad { color: #b22; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.7em; text-align: left; vertical-align: top; }
+th { background: #f3f3f3; }
+code { font-size: 0.95em; }
+.graph { overflow-x: auto; border: 1px solid #ddd; }
+.note { color: #666; }
+.details { white-space: pre-wrap; }";
+
+/// The report of a chopping as an HTML page titled `title`. `verification`
+/// is the report of a verify run on `sc_graph`, or why none ran; mixed cycles
+/// are searched within `limits`.
+pub fn html_report(
+    cfg: &CfgProgram,
+    sc_graph: &SCGraph,
+    verification: Result<&VerificationReport, &str>,
+    limits: &CycleLimits,
+    title: &str,
+) -> String {
+    let mixed_cycles = sc_graph.find_mixed_cycles_within(limits);
+    let cycles = &mixed_cycles.cycles;
+    let mut html = String::new();
+    let _ = writeln!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{} - chopping report</title>\n<style>\n{}\n</style>\n</head>\n<body>",
+        escape(title),
+        STYLE
+    );
+    let _ = writeln!(html, "<h1>{}</h1>", escape(title));
+    let _ = writeln!(
+        html,
+        "<p class=\"note\">Generated by {} {}</p>",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let class = if cycles.is_empty() { "good" } else { "bad" };
+    let qualifier = match verification {
+        Ok(_) => "",
+        Err(_) => " (before verification)",
+    };
+    let _ = writeln!(
+        html,
+        "<p class=\"verdict {}\">{}{}</p>",
+        class,
+        escape(&verdict(cycles.len(), sc_graph.isolation)),
+        qualifier
+    );
+
+    // Statistics
+    let (hops, s_edges, c_edges) = sc_graph.stats();
+    let dropped = sc_graph
+        .justifications
+        .iter()
+        .filter(|(_, justification)| !justification.conflicting)
+        .count();
+    let mut stats = vec![
+        ("Database nodes", cfg.nodes.len().to_string()),
+        ("Tables", cfg.tables.len().to_string()),
+        ("Transactions", cfg.functions.len().to_string()),
+        ("Hops", hops.to_string()),
+        ("S-edges", s_edges.to_string()),
+        ("C-edges", c_edges.to_string()),
+        ("C-edges dropped before verification", dropped.to_string()),
+    ];
+    if let Ok(report) = verification {
+        let removed = report.edges.iter().filter(|edge| edge.removed).count();
+        stats.push(("C-edges removed by verification", removed.to_string()));
+    }
+    let truncated = if mixed_cycles.truncated { "+" } else { "" };
+    stats.push(("Mixed cycles", format!("{}{}", cycles.len(), truncated)));
+    stats.push(("Isolation", sc_graph.isolation.name().to_string()));
+    html.push_str("<h2>Program</h2>\n<table>\n");
+    for (label, value) in stats {
+        let _ = writeln!(
+            html,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            label,
+            escape(&value)
+        );
+    }
+    html.push_str("</table>\n");
+
+    // The SC-graph
+    let on_cycle: HashSet<(SCGraphNodeId, SCGraphNodeId)> = cycles
+        .iter()
+        .flat_map(|cycle| {
+            let nodes: Vec<_> = cycle
+                .iter()
+                .filter_map(|&hop| sc_graph.get_sc_node_id(hop))
+                .collect();
+            (0..nodes.len())
+                .map(|i| (nodes[i], nodes[(i + 1) % nodes.len()]))
+                .collect::<Vec<_>>()
+        })
+        .flat_map(|(a, b)| [(a, b), (b, a)])
+        .collect();
+    html.push_str("<h2>SC-graph</h2>\n<div class=\"graph\">\n");
+    html.push_str(&sc_graph_svg(cfg, sc_graph, verification.ok(), &on_cycle));
+    html.push_str("</div>\n");
+
+    // Cycles
+    html.push_str("<h2>Mixed cycles</h2>\n");
+    if cycles.is_empty() {
+        html.push_str("<p>None: every run of the pieces is equivalent to a serial one.</p>\n");
+    }
+    for (index, cycle) in cycles.iter().enumerate() {
+        let explanation = sc_graph.explain_cycle(cycle, cfg);
+        let _ = writeln!(
+            html,
+            "<h3>Cycle {}: <code>{}</code></h3>\n<p class=\"note\">id {}</p>\n<ol>",
+            index + 1,
+            escape(&sc_graph.describe_cycle(cycle, cfg)),
+            sc_graph.cycle_id(cycle, cfg)
+        );
+        for step in &explanation.steps {
+            let _ = writeln!(html, "<li>{}</li>", escape(&step.text));
+        }
+        let _ = writeln!(html, "</ol>\n<p>{}</p>", escape(&explanation.conclusion));
+    }
+    if mixed_cycles.truncated {
+        html.push_str(
+            "<p class=\"note\">The search stopped at its limit; more cycles may exist.</p>\n",
+        );
+    }
+
+    // Verification
+    html.push_str("<h2>Verification</h2>\n");
+    match verification {
+        Ok(report) => {
+            html.push_str(
+                "<table>\n<tr><th>C-edge</th><th>Result</th><th>Removed</th><th>Details</th></tr>\n",
+            );
+            for edge in &report.edges {
+                let class = match edge.result {
+                    "verified" => "good",
+                    _ => "bad",
+                };
+                let cached = if edge.reused { " (cached)" } else { "" };
+                let _ = writeln!(
+                    html,
+                    "<tr><td><code>{} -- {}</code></td><td class=\"{}\">{}{}</td><td>{}</td><td class=\"details\">{}</td></tr>",
+                    escape(&edge.hops[0]),
+                    escape(&edge.hops[1]),
+                    class,
+                    edge.result,
+                    cached,
+                    if edge.removed { "yes" } else { "no" },
+                    escape(edge.message.as_deref().unwrap_or(""))
+                );
+            }
+            html.push_str("</table>\n<h3>Assumptions</h3>\n<table>\n");
+            html.push_str("<tr><th>Assumption</th><th>What it leaves out</th></tr>\n");
+            for assumption in &report.ledger {
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td></tr>",
+                    escape(&assumption.assumption),
+                    escape(&assumption.consequence)
+                );
+            }
+            html.push_str("</table>\n");
+        }
+        Err(reason) => {
+            let _ = writeln!(
+                html,
+                "<p class=\"note\">Verification was not run: {}</p>",
+                escape(reason)
+            );
+        }
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// The SC-graph as SVG, with a column per transaction; edges in `on_cycle`,
+/// both ways round, are drawn thicker.
+fn sc_graph_svg(
+    cfg: &CfgProgram,
+    sc_graph: &SCGraph,
+    verification: Option<&VerificationReport>,
+    on_cycle: &HashSet<(SCGraphNodeId, SCGraphNodeId)>,
+) -> String {
+    // Columns in the order the program declares the transactions
+    let columns: Vec<_> = cfg
+        .root_functions
+        .iter()
+        .filter(|&&function| {
+            sc_graph
+                .nodes
+                .iter()
+                .any(|(_, node)| node.cfg_function_id == function)
+        })
+        .collect();
+    let mut positions = HashMap::new();
+    for (id, node) in sc_graph.nodes.iter() {
+        let column = columns
+            .iter()
+            .position(|&&function| function == node.cfg_function_id)
+            .unwrap_or_default();
+        let row = cfg.functions[node.cfg_function_id]
+            .hop_order
+            .iter()
+            .position(|&hop| hop == node.cfg_hop_id)
+            .unwrap_or_default();
+        let x = MARGIN + column * COLUMN_WIDTH + COLUMN_WIDTH / 2;
+        let y = TOP + row * ROW_HEIGHT;
+        positions.insert(id, (x, y));
+    }
+    let rows = positions.values().map(|&(_, y)| y).max().unwrap_or(TOP);
+    let legend = [
+        ("#555", "", "S-edge"),
+        ("#1f5fbf", "", "C-edge"),
+        (
+            "#2a7a2a",
+            " stroke-dasharray=\"6 4\"",
+            "removed by verification",
+        ),
+        ("#c22", "", "failed"),
+        ("#e08a00", "", "undecided"),
+    ];
+    let width = 2 * MARGIN + (columns.len() * COLUMN_WIDTH).max(legend.len() * 150);
+    let height = rows + ROW_HEIGHT + 40;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         viewBox=\"0 0 {} {}\" font-family=\"sans-serif\" font-size=\"13\">",
+        width, height, width, height
+    );
+    for (column, &&function) in columns.iter().enumerate() {
+        let _ = writeln!(
+            svg,
+            "<text x=\"{}\" y=\"30\" text-anchor=\"middle\" font-weight=\"bold\">{}</text>",
+            MARGIN + column * COLUMN_WIDTH + COLUMN_WIDTH / 2,
+            escape(&cfg.functions[function].name)
+        );
+    }
+
+    // C-edges verification removed are no longer in the graph
+    let mut edges: Vec<_> = sc_graph
+        .edges
+        .iter()
+        .map(|edge| (edge.clone(), false))
+        .collect();
+    let mut results = HashMap::new();
+    for outcome in verification.iter().flat_map(|report| &report.edges) {
+        results.insert(&outcome.edge, outcome.result);
+        if outcome.removed {
+            edges.push((outcome.edge.clone(), true));
+        }
+    }
+    for (edge, removed) in &edges {
+        let (Some(&(x1, y1)), Some(&(x2, y2))) =
+            (positions.get(&edge.source), positions.get(&edge.target))
+        else {
+            continue;
+        };
+        let width = if on_cycle.contains(&(edge.source, edge.target)) {
+            3
+        } else {
+            1
+        };
+        match edge.edge_type {
+            EdgeType::S => {
+                let _ = writeln!(
+                    svg,
+                    "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#555\" stroke-width=\"{}\"/>",
+                    x1, y1, x2, y2, width
+                );
+            }
+            EdgeType::C => {
+                let color = match results.get(&edge).copied() {
+                    Some("verified") => "#2a7a2a",
+                    Some("failed") => "#c22",
+                    Some("unknown") => "#e08a00",
+                    _ => "#1f5fbf",
+                };
+                let dash = if *removed {
+                    " stroke-dasharray=\"6 4\""
+                } else {
+                    ""
+                };
+                // Bend the curve so edges between the same columns stay apart
+                let bend = 25 + (x1.abs_diff(x2) / COLUMN_WIDTH) * 15;
+                let _ = writeln!(
+                    svg,
+                    "<path d=\"M {} {} Q {} {} {} {}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{}\"{}>\
+                     <title>{} -- {}</title></path>",
+                    x1,
+                    y1,
+                    (x1 + x2) / 2,
+                    (y1 + y2) / 2 - bend.min((y1 + y2) / 2),
+                    x2,
+                    y2,
+                    color,
+                    width,
+                    dash,
+                    escape(&hop_label(cfg, &sc_graph.nodes[edge.source])),
+                    escape(&hop_label(cfg, &sc_graph.nodes[edge.target]))
+                );
+            }
+        }
+    }
+
+    for (id, node) in sc_graph.nodes.iter() {
+        let (x, y) = positions[&id];
+        let label = hop_label(cfg, node);
+        let hop = label.rsplit(':').next().unwrap_or_default();
+        let _ = writeln!(
+            svg,
+            "<g><title>{}</title><rect x=\"{}\" y=\"{}\" width=\"90\" height=\"30\" rx=\"8\" \
+             fill=\"#fff\" stroke=\"#333\"/><text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{} on {}</text></g>",
+            escape(&label),
+            x - 45,
+            y - 15,
+            x,
+            y + 5,
+            escape(hop),
+            escape(&cfg.nodes[node.cfg_node_id].name)
+        );
+    }
+
+    for (index, (color, dash, text)) in legend.iter().enumerate() {
+        let x = MARGIN + index * 150;
+        let y = height - 15;
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"2\"{}/>\
+             <text x=\"{}\" y=\"{}\">{}</text>",
+            x,
+            y - 4,
+            x + 25,
+            y - 4,
+            color,
+            dash,
+            x + 30,
+            y,
+            text
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,7 +1,62 @@
+pub mod alloy_export;
 pub mod ast_printer;
+pub mod cfg_export;
 pub mod cfg_printer;
+pub mod diff_printer;
+pub mod doc_printer;
+pub mod explain_printer;
+pub mod formatter;
+pub mod html_report;
+pub mod proto_export;
+pub mod rust_skeleton;
+pub mod sarif;
 pub mod sc_graph_printer;
+pub mod sql_ddl;
+pub mod stats_printer;
+pub mod tla_export;
 
+pub use alloy_export::*;
 pub use ast_printer::*;
+pub use cfg_export::*;
 pub use cfg_printer::*;
+pub use diff_printer::*;
+pub use explain_printer::*;
+pub use formatter::*;
+pub use proto_export::*;
+pub use rust_skeleton::*;
 pub use sc_graph_printer::*;
+pub use sql_ddl::*;
+pub use stats_printer::*;
+pub use tla_export::*;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether output keeps to ASCII (--plain); process-wide, like the color
+/// override of `colored`
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Keep output to ASCII, e.g. `->` for arrows
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+}
+
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// The arrow between the ends of an edge
+///
+/// ```
+/// use FMitF_rs::pretty::{arrow, set_plain};
+///
+/// assert_eq!(arrow(), "→");
+/// set_plain(true);
+/// assert_eq!(arrow(), "->");
+/// ```
+pub fn arrow() -> &'static str {
+    if is_plain() {
+        "->"
+    } else {
+        "→"
+    }
+}
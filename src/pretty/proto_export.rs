@@ -0,0 +1,272 @@
+//! The node topology of a program as a Protocol Buffers (proto3) service
+//! definition, so a deployment runs exactly the hops that were verified on the
+//! nodes they were verified on.
+//!
+//! Each node gets a service with an rpc per hop that runs on it, which takes
+//! the state of its transaction (its parameters and the variables its hops
+//! declare) and replies with the state after the hop and whether it returned.
+//! A `Coordinator` service has an rpc per transaction, documented with the
+//! hops it calls in order; a hop that runs `abort` fails with status `ABORTED`.
+//!
+//! ```
+//! use FMitF_rs::parse_and_analyze;
+//! use FMitF_rs::pretty::format_proto;
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { Bank, Audit }
+//!     table Account on Bank { primary int id; int balance; }
+//!     table Log on Audit { primary int id; float total; }
+//!     float deposit(int accountId, int amount) {
+//!         hop on Bank {
+//!             int balance = Account[id: accountId].balance + amount;
+//!             Account[id: accountId].balance = balance;
+//!         }
+//!         hop on Audit {
+//!             Log[id: 0].total = Log[id: 0].total + amount;
+//!             return Log[id: 0].total;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let proto = format_proto(&program, "bank");
+//! assert!(proto.starts_with("syntax = \"proto3\";\n\npackage bank;\n"));
+//! assert!(proto.contains(
+//!     "service Bank {
+//!   // Hop 1 of deposit
+//!   rpc DepositHop1(DepositState) returns (DepositHopReply);
+//! }"
+//! ));
+//! assert!(proto.contains(
+//!     "message DepositState {
+//!   int64 account_id = 1;
+//!   int64 amount = 2;
+//!   int64 balance = 3;
+//! }"
+//! ));
+//! assert!(proto.contains("  // Calls Bank.DepositHop1, then Audit.DepositHop2, until one returns\n"));
+//! assert!(proto.contains("message DepositReply {\n  double value = 1;\n}"));
+//! ```
+
+use super::rust_skeleton::{camel_case, snake_case};
+use crate::ast::{FunctionDeclaration, NodeId, Program, ReturnType, StatementKind, TypeName};
+use std::collections::HashSet;
+use std::fmt::Write as _;
+
+/// The message and service names of one transaction
+struct Transaction<'a> {
+    function: &'a FunctionDeclaration,
+    rpc: String,
+    state: String,
+    hop_reply: String,
+    request: String,
+    reply: String,
+}
+
+/// The proto3 definition of the services of the nodes of `program` and of
+/// the coordinator running its transactions, in package `package`.
+pub fn format_proto(program: &Program, package: &str) -> String {
+    let mut taken = HashSet::new();
+    let mut claim = |wanted: String| {
+        let mut name = identifier(&wanted);
+        while !taken.insert(name.clone()) {
+            name.push('_');
+        }
+        name
+    };
+    let services: Vec<(NodeId, String)> = program
+        .root_nodes
+        .iter()
+        .map(|&node| (node, claim(camel_case(program.nodes[node].name.as_str()))))
+        .collect();
+    let coordinator = claim("Coordinator".to_string());
+    let transactions: Vec<Transaction> = program
+        .root_functions
+        .iter()
+        .map(|&function| {
+            let function = &program.functions[function];
+            let rpc = camel_case(function.name.as_str());
+            Transaction {
+                function,
+                state: claim(format!("{}State", rpc)),
+                hop_reply: claim(format!("{}HopReply", rpc)),
+                request: claim(format!("{}Request", rpc)),
+                reply: claim(format!("{}Reply", rpc)),
+                rpc: identifier(&rpc),
+            }
+        })
+        .collect();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "syntax = \"proto3\";\n");
+    let _ = writeln!(out, "package {};", identifier(&snake_case(package)));
+
+    for (node, service) in &services {
+        let _ = writeln!(
+            out,
+            "\n// The hops on node {}, each to run as one local transaction",
+            program.nodes[*node].name
+        );
+        let _ = writeln!(out, "service {} {{", service);
+        for transaction in &transactions {
+            for (index, &hop) in transaction.function.hops.iter().enumerate() {
+                if program.hops[hop].resolved_node != Some(*node) {
+                    continue;
+                }
+                let _ = writeln!(
+                    out,
+                    "  // Hop {} of {}",
+                    index + 1,
+                    transaction.function.name
+                );
+                let _ = writeln!(
+                    out,
+                    "  rpc {}Hop{}({}) returns ({});",
+                    transaction.rpc,
+                    index + 1,
+                    transaction.state,
+                    transaction.hop_reply
+                );
+            }
+        }
+        out.push_str("}\n");
+    }
+
+    let _ = writeln!(
+        out,
+        "\n// Runs each transaction by calling its hops in order, each on its node"
+    );
+    let _ = writeln!(out, "service {} {{", coordinator);
+    for transaction in &transactions {
+        let calls: Vec<String> = transaction
+            .function
+            .hops
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &hop)| {
+                let node = program.hops[hop].resolved_node?;
+                let service = services.iter().find(|(other, _)| *other == node)?;
+                Some(format!("{}.{}Hop{}", service.1, transaction.rpc, index + 1))
+            })
+            .collect();
+        if calls.is_empty() {
+            let _ = writeln!(out, "  // Calls no hops");
+        } else {
+            let _ = writeln!(
+                out,
+                "  // Calls {}, until one returns",
+                calls.join(", then ")
+            );
+        }
+        let _ = writeln!(
+            out,
+            "  rpc {}({}) returns ({});",
+            transaction.rpc, transaction.request, transaction.reply
+        );
+    }
+    out.push_str("}\n");
+
+    for transaction in &transactions {
+        transaction_messages(&mut out, program, transaction);
+    }
+    out
+}
+
+/// The messages of one transaction: its state, the reply of its hops, and
+/// its request and reply to the coordinator.
+fn transaction_messages(out: &mut String, program: &Program, transaction: &Transaction) {
+    let function = transaction.function;
+    let mut taken = HashSet::new();
+    let mut claim = |wanted: &str| {
+        let mut name = identifier(&snake_case(wanted));
+        while !taken.insert(name.clone()) {
+            name.push('_');
+        }
+        name
+    };
+    let mut params = Vec::new();
+    for &param in &function.parameters {
+        let param = &program.parameters[param];
+        params.push((claim(param.param_name.as_str()), &param.param_type));
+    }
+    let mut locals = Vec::new();
+    for &hop in &function.hops {
+        for &statement in &program.hops[hop].statements {
+            if let StatementKind::VarDecl(decl) = &program.statements[statement].node {
+                locals.push((claim(decl.var_name.as_str()), &decl.var_type));
+            }
+        }
+    }
+    let value = match &function.return_type {
+        ReturnType::Void => None,
+        ReturnType::Type(ty) => Some(proto_type(ty)),
+    };
+
+    let _ = writeln!(
+        out,
+        "\n// The parameters of {} and the variables its hops declare",
+        function.name
+    );
+    message(out, &transaction.state, params.iter().chain(&locals));
+
+    let _ = writeln!(out, "\nmessage {} {{", transaction.hop_reply);
+    let _ = writeln!(out, "  {} state = 1;", transaction.state);
+    out.push_str("  // The hop returned, and no further hop runs\n");
+    out.push_str("  bool returned = 2;\n");
+    if let Some(value) = value {
+        let _ = writeln!(out, "  {} value = 3;", value);
+    }
+    out.push_str("}\n");
+
+    out.push('\n');
+    message(out, &transaction.request, params.iter());
+    out.push('\n');
+    match value {
+        Some(value) => {
+            let _ = writeln!(
+                out,
+                "message {} {{\n  {} value = 1;\n}}",
+                transaction.reply, value
+            );
+        }
+        None => {
+            let _ = writeln!(out, "message {} {{}}", transaction.reply);
+        }
+    }
+}
+
+fn message<'a>(
+    out: &mut String,
+    name: &str,
+    fields: impl Iterator<Item = &'a (String, &'a TypeName)>,
+) {
+    let fields: Vec<String> = fields
+        .enumerate()
+        .map(|(index, (field, ty))| format!("  {} {} = {};\n", proto_type(ty), field, index + 1))
+        .collect();
+    if fields.is_empty() {
+        let _ = writeln!(out, "message {} {{}}", name);
+    } else {
+        let _ = writeln!(out, "message {} {{\n{}}}", name, fields.concat());
+    }
+}
+
+fn proto_type(ty: &TypeName) -> &'static str {
+    match ty {
+        TypeName::Int => "int64",
+        TypeName::Float => "double",
+        TypeName::String => "string",
+        TypeName::Bool => "bool",
+    }
+}
+
+/// A name as protobuf takes it, starting with a letter.
+fn identifier(name: &str) -> String {
+    let name = name.trim_start_matches('_');
+    if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.to_string()
+    } else {
+        format!("x{}", name)
+    }
+}
@@ -0,0 +1,838 @@
+//! Rust skeletons of the transactions of a program, so an implementation keeps
+//! the chopping that was verified: a storage trait per node with a getter and
+//! setter per field of its tables, an async function per hop that runs the
+//! hop's statements against the storage of its node, and an async function per
+//! transaction that runs its hops in order.
+//!
+//! Each hop must run as one atomic step on its node, e.g. as a local
+//! transaction; the function of the transaction carries its parameters and the
+//! variables hops declare from one hop to the next in a state struct. `abort`
+//! becomes `TxnError::Aborted`, and reading a missing record should give the
+//! defaults TransAct reads: `0`, `0.0`, `false` or `""`. The output has no
+//! dependencies and builds as a module of a Rust 2021 crate.
+//!
+//! ```
+//! use FMitF_rs::parse_and_analyze;
+//! use FMitF_rs::pretty::format_rust_skeleton;
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { Bank, Audit }
+//!     table Account on Bank { primary int id; int balance; }
+//!     table Log on Audit { primary int id; int total; }
+//!     int deposit(int accountId, int amount) {
+//!         hop on Bank {
+//!             if (amount <= 0) { abort; }
+//!             Account[id: accountId].balance = Account[id: accountId].balance + amount;
+//!         }
+//!         hop on Audit {
+//!             Log[id: 0].total = Log[id: 0].total + amount;
+//!             return Log[id: 0].total;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let code = format_rust_skeleton(&program);
+//! assert!(code.contains("pub trait BankStorage {"));
+//! assert!(code.contains(
+//!     "    async fn set_account_balance(&mut self, id: i64, value: i64) -> Result<(), StorageError>;"
+//! ));
+//! assert!(code.contains("pub struct DepositState {\n    pub account_id: i64,\n    pub amount: i64,\n}"));
+//! assert!(code.contains(
+//!     "    storage.set_account_balance(state.account_id, storage.account_balance(state.account_id).await? + state.amount).await?;"
+//! ));
+//! assert!(code.contains(
+//!     "    if let Flow::Return(value) = deposit_hop_2(&mut *audit_storage, &mut state).await? {"
+//! ));
+//! ```
+
+use super::formatter::{binary_op_str, float_literal};
+use crate::ast::{
+    BinaryOp, Collation, ExpressionId, ExpressionKind, FieldId, FunctionDeclaration, NodeId,
+    Program, ReturnType, StatementId, StatementKind, TableId, TypeName, UnaryOp, VarId,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+/// Words that cannot name a variable, even as raw identifiers
+const UNRAW: &[&str] = &["self", "Self", "super", "crate", "_"];
+
+/// Rust's keywords, strict and reserved, in the 2021 edition
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "do", "final", "macro", "override", "priv", "try", "typeof",
+    "unsized", "virtual", "yield",
+];
+
+/// The names hop functions give their own parameters
+const HOP_PARAMETERS: &[&str] = &["storage", "state", "value"];
+
+const PRELUDE: &str = "\
+//! Transaction skeletons generated by FMitF_rs. Each hop must run as one
+//! atomic step on the storage of its node; keep the hops, their order and the
+//! records each accesses, and the verified chopping stays serializable.
+#![allow(unused_mut, unused_variables, unreachable_code, clippy::all)]
+
+/// An error of a storage backend
+pub type StorageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Why a transaction did not commit
+#[derive(Debug)]
+pub enum TxnError {
+    /// It ran `abort`, in its first hop
+    Aborted,
+    Storage(StorageError),
+}
+
+impl From<StorageError> for TxnError {
+    fn from(error: StorageError) -> Self {
+        TxnError::Storage(error)
+    }
+}
+
+/// What a transaction does after a hop
+pub enum Flow<R> {
+    /// Runs its next hop, if any
+    Next,
+    /// Returns
+    Return(R),
+}
+";
+
+/// The Rust skeleton of the transactions of `program`, as one module.
+///
+/// Table reads become getter calls wherever an expression holds them, and
+/// reading the key field of a record gives back the key it was read by.
+///
+/// ```
+/// use FMitF_rs::parse_and_analyze;
+/// use FMitF_rs::pretty::format_rust_skeleton;
+///
+/// let program = parse_and_analyze(
+///     r#"
+///     nodes { A }
+///     table Acc on A { primary int id; int bal; string owner; }
+///     void f(int id) {
+///         hop on A {
+///             if (Acc[id: id].bal < 10 && Acc[id: id].id == id) { abort; }
+///             int left = -Acc[id: id].bal * 2;
+///             Acc[id: id + 1].owner = Acc[id: id].owner;
+///         }
+///     }
+///     "#,
+/// )
+/// .unwrap();
+/// let code = format_rust_skeleton(&program);
+/// assert!(code.contains("    if storage.acc_bal(state.id).await? < 10 && state.id == state.id {"));
+/// assert!(code.contains("    state.left = -storage.acc_bal(state.id).await? * 2;"));
+/// assert!(code.contains(
+///     "    storage.set_acc_owner(state.id + 1, storage.acc_owner(state.id).await?).await?;"
+/// ));
+/// ```
+pub fn format_rust_skeleton(program: &Program) -> String {
+    let mut out = String::from(PRELUDE);
+    for &node in &program.root_nodes {
+        storage_trait(&mut out, program, node);
+    }
+    for &function in &program.root_functions {
+        FunctionWriter::new(program, &program.functions[function]).write(&mut out);
+    }
+    out
+}
+
+/// The trait of the storage of a node: a getter and a setter for each field
+/// outside the primary key of each of its tables.
+fn storage_trait(out: &mut String, program: &Program, node: NodeId) {
+    let name = program.nodes[node].name.as_str();
+    let _ = writeln!(out, "\n/// The tables on node {}", name);
+    let _ = writeln!(out, "#[allow(async_fn_in_trait)]");
+    let _ = writeln!(out, "pub trait {}Storage {{", camel_case(name));
+    let tables = program
+        .root_tables
+        .iter()
+        .filter(|&&table| program.tables[table].node == node);
+    for &table in tables {
+        let declaration = &program.tables[table];
+        let keys: Vec<String> = declaration
+            .primary_keys
+            .iter()
+            .map(|&key| {
+                let field = &program.fields[key];
+                format!(
+                    "{}: {}",
+                    variable(field.field_name.as_str()),
+                    rust_type(&field.field_type)
+                )
+            })
+            .collect();
+        for &field in &declaration.fields {
+            let declaration_field = &program.fields[field];
+            if declaration_field.is_primary {
+                continue;
+            }
+            let ty = rust_type(&declaration_field.field_type);
+            let method = accessor(program, table, field);
+            let _ = writeln!(
+                out,
+                "    /// Reads {}.{}",
+                declaration.name, declaration_field.field_name
+            );
+            let _ = writeln!(
+                out,
+                "    async fn {}(&self, {}) -> Result<{}, StorageError>;",
+                method,
+                keys.join(", "),
+                ty
+            );
+            let _ = writeln!(
+                out,
+                "    async fn set_{}(&mut self, {}, value: {}) -> Result<(), StorageError>;",
+                method,
+                keys.join(", "),
+                ty
+            );
+        }
+    }
+    out.push_str("}\n");
+}
+
+/// Writes the state, hops and sequencing of one transaction.
+struct FunctionWriter<'a> {
+    program: &'a Program,
+    function: &'a FunctionDeclaration,
+    /// Snake case name of the function
+    name: String,
+    /// The fields of the state, for parameters and variables hops declare
+    state: Vec<(VarId, String)>,
+    /// The names of the variables declared inside blocks
+    locals: HashMap<VarId, String>,
+}
+
+impl<'a> FunctionWriter<'a> {
+    fn new(program: &'a Program, function: &'a FunctionDeclaration) -> Self {
+        let mut taken = HashSet::new();
+        let mut state = Vec::new();
+        let mut claim = |var: VarId, name: &str| {
+            let mut field = variable(name);
+            while !taken.insert(field.clone()) {
+                field.push('_');
+            }
+            state.push((var, field));
+        };
+        for &param in &function.parameters {
+            let param = &program.parameters[param];
+            if let Some(var) = param.resolved_var {
+                claim(var, param.param_name.as_str());
+            }
+        }
+        for &hop in &function.hops {
+            for &statement in &program.hops[hop].statements {
+                if let StatementKind::VarDecl(decl) = &program.statements[statement].node {
+                    if let Some(var) = decl.resolved_var {
+                        claim(var, decl.var_name.as_str());
+                    }
+                }
+            }
+        }
+        Self {
+            program,
+            function,
+            name: variable(function.name.as_str()),
+            state,
+            locals: HashMap::new(),
+        }
+    }
+
+    fn returns(&self) -> &'static str {
+        match &self.function.return_type {
+            ReturnType::Void => "()",
+            ReturnType::Type(ty) => rust_type(ty),
+        }
+    }
+
+    /// The node a hop runs on, as the name of the storage for it.
+    fn storage(&self, node: NodeId) -> String {
+        format!(
+            "{}_storage",
+            variable(self.program.nodes[node].name.as_str())
+        )
+    }
+
+    fn write(mut self, out: &mut String) {
+        let program = self.program;
+        let function = self.function;
+        let state_type = format!("{}State", camel_case(function.name.as_str()));
+
+        let _ = writeln!(
+            out,
+            "\n/// The parameters of {} and the variables its hops declare",
+            function.name
+        );
+        out.push_str("#[derive(Debug, Clone, Default)]\n");
+        if self.state.is_empty() {
+            let _ = writeln!(out, "pub struct {} {{}}", state_type);
+        } else {
+            let _ = writeln!(out, "pub struct {} {{", state_type);
+            for (var, field) in &self.state {
+                let ty = rust_type(&program.variables[*var].ty);
+                let _ = writeln!(out, "    pub {}: {},", field, ty);
+            }
+            out.push_str("}\n");
+        }
+
+        let mut nodes: Vec<NodeId> = Vec::new();
+        for (index, &hop) in function.hops.iter().enumerate() {
+            let hop = &program.hops[hop];
+            let Some(node) = hop.resolved_node else {
+                continue;
+            };
+            if !nodes.contains(&node) {
+                nodes.push(node);
+            }
+            let node_name = program.nodes[node].name.as_str();
+            let _ = writeln!(
+                out,
+                "\n/// Hop {} of {}, on {}",
+                index + 1,
+                function.name,
+                node_name
+            );
+            let _ = writeln!(
+                out,
+                "pub async fn {}_hop_{}(\n    storage: &mut impl {}Storage,\n    state: &mut {},\n) -> Result<Flow<{}>, TxnError> {{",
+                self.name,
+                index + 1,
+                camel_case(node_name),
+                state_type,
+                self.returns()
+            );
+            self.block(out, &hop.statements, 1, true);
+            out.push_str("    Ok(Flow::Next)\n}\n");
+        }
+
+        let mut params: Vec<String> = nodes
+            .iter()
+            .map(|&node| {
+                format!(
+                    "{}: &mut impl {}Storage",
+                    self.storage(node),
+                    camel_case(program.nodes[node].name.as_str())
+                )
+            })
+            .collect();
+        let mut fields = Vec::new();
+        for &param in &function.parameters {
+            let param = &program.parameters[param];
+            let field = param
+                .resolved_var
+                .and_then(|var| self.state_field(var))
+                .unwrap_or_else(|| variable(param.param_name.as_str()));
+            params.push(format!("{}: {}", field, rust_type(&param.param_type)));
+            fields.push(field);
+        }
+        let _ = writeln!(
+            out,
+            "\n/// Runs {}, its hops one after another",
+            function.name
+        );
+        if params.is_empty() {
+            let _ = writeln!(
+                out,
+                "pub async fn {}() -> Result<{}, TxnError> {{",
+                self.name,
+                self.returns()
+            );
+        } else {
+            let _ = writeln!(out, "pub async fn {}(", self.name);
+            for param in &params {
+                let _ = writeln!(out, "    {},", param);
+            }
+            let _ = writeln!(out, ") -> Result<{}, TxnError> {{", self.returns());
+        }
+        if fields.is_empty() {
+            let _ = writeln!(out, "    let mut state = {}::default();", state_type);
+        } else if fields.len() == self.state.len() {
+            let _ = writeln!(
+                out,
+                "    let mut state = {} {{ {} }};",
+                state_type,
+                fields.join(", ")
+            );
+        } else {
+            let _ = writeln!(
+                out,
+                "    let mut state = {} {{\n        {},\n        ..Default::default()\n    }};",
+                state_type,
+                fields.join(",\n        ")
+            );
+        }
+        for (index, &hop) in function.hops.iter().enumerate() {
+            let Some(node) = program.hops[hop].resolved_node else {
+                continue;
+            };
+            let _ = writeln!(
+                out,
+                "    if let Flow::Return(value) = {}_hop_{}(&mut *{}, &mut state).await? {{\n        return Ok(value);\n    }}",
+                self.name,
+                index + 1,
+                self.storage(node)
+            );
+        }
+        match function.return_type {
+            ReturnType::Void => out.push_str("    Ok(())\n}\n"),
+            ReturnType::Type(_) => {
+                let _ = writeln!(
+                    out,
+                    "    unreachable!(\"{} returns on every path\")\n}}",
+                    function.name
+                );
+            }
+        }
+    }
+
+    fn state_field(&self, var: VarId) -> Option<String> {
+        self.state
+            .iter()
+            .find(|(other, _)| *other == var)
+            .map(|(_, field)| field.clone())
+    }
+
+    /// Writes statements at `depth`; those of a hop itself declare variables
+    /// of the state.
+    fn block(&mut self, out: &mut String, statements: &[StatementId], depth: usize, hop: bool) {
+        let indent = "    ".repeat(depth);
+        for &statement in statements {
+            match &self.program.statements[statement].node {
+                StatementKind::VarDecl(decl) => {
+                    let value = match decl.init_value {
+                        Some(value) => self.coerced(value, Some(decl.var_type.clone()), 0),
+                        None => default_value(&decl.var_type).to_string(),
+                    };
+                    let Some(var) = decl.resolved_var else {
+                        continue;
+                    };
+                    match self.state_field(var).filter(|_| hop) {
+                        Some(field) => {
+                            let _ = writeln!(out, "{}state.{} = {};", indent, field, value);
+                        }
+                        None => {
+                            let mut name = variable(decl.var_name.as_str());
+                            if HOP_PARAMETERS.contains(&name.as_str()) {
+                                name.push('_');
+                            }
+                            let _ = writeln!(
+                                out,
+                                "{}let mut {}: {} = {};",
+                                indent,
+                                name,
+                                rust_type(&decl.var_type),
+                                value
+                            );
+                            self.locals.insert(var, name);
+                        }
+                    }
+                }
+                StatementKind::VarAssignment(assign) => {
+                    let target = assign
+                        .resolved_var
+                        .map(|var| self.place(var))
+                        .unwrap_or_else(|| variable(assign.var_name.as_str()));
+                    let ty = assign
+                        .resolved_var
+                        .map(|var| self.program.variables[var].ty.clone());
+                    let value = self.coerced(assign.rhs, ty, 0);
+                    let _ = writeln!(out, "{}{} = {};", indent, target, value);
+                }
+                StatementKind::Assignment(assign) => {
+                    let (Some(table), Some(field)) = (assign.resolved_table, assign.resolved_field)
+                    else {
+                        continue;
+                    };
+                    let keys = self.keys(table, &assign.resolved_pk_fields, &assign.pk_exprs);
+                    let _ = writeln!(
+                        out,
+                        "{}storage.set_{}({}, {}).await?;",
+                        indent,
+                        accessor(self.program, table, field),
+                        keys.join(", "),
+                        self.coerced(assign.rhs, self.field_type(field), 0)
+                    );
+                }
+                StatementKind::MultiAssignment(multi) => {
+                    let Some(table) = multi.resolved_table else {
+                        continue;
+                    };
+                    // The key is evaluated once, before the fields are written in turn
+                    let keys = self.keys(table, &multi.resolved_pk_fields, &multi.pk_exprs);
+                    let _ = writeln!(out, "{}{{", indent);
+                    let mut names = Vec::new();
+                    for (index, key) in keys.iter().enumerate() {
+                        let _ = writeln!(out, "{}    let key_{} = {};", indent, index, key);
+                        names.push(format!("key_{}", index));
+                    }
+                    for pair in &multi.assignments {
+                        let Some(field) = pair.resolved_field else {
+                            continue;
+                        };
+                        let key_args: Vec<String> = names
+                            .iter()
+                            .zip(&self.program.tables[table].primary_keys)
+                            .map(|(name, &key)| {
+                                if self.program.fields[key].field_type == TypeName::String {
+                                    format!("{}.clone()", name)
+                                } else {
+                                    name.clone()
+                                }
+                            })
+                            .collect();
+                        let _ = writeln!(
+                            out,
+                            "{}    storage.set_{}({}, {}).await?;",
+                            indent,
+                            accessor(self.program, table, field),
+                            key_args.join(", "),
+                            self.coerced(pair.rhs, self.field_type(field), 0)
+                        );
+                    }
+                    let _ = writeln!(out, "{}}}", indent);
+                }
+                StatementKind::IfStmt(if_stmt) => {
+                    let _ = writeln!(out, "{}if {} {{", indent, self.expr(if_stmt.condition));
+                    self.block(out, &if_stmt.then_branch, depth + 1, false);
+                    match &if_stmt.else_branch {
+                        Some(branch) if !branch.is_empty() => {
+                            let _ = writeln!(out, "{}}} else {{", indent);
+                            self.block(out, branch, depth + 1, false);
+                            let _ = writeln!(out, "{}}}", indent);
+                        }
+                        _ => {
+                            let _ = writeln!(out, "{}}}", indent);
+                        }
+                    }
+                }
+                StatementKind::WhileStmt(while_stmt) => {
+                    let _ = writeln!(
+                        out,
+                        "{}while {} {{",
+                        indent,
+                        self.expr(while_stmt.condition)
+                    );
+                    self.block(out, &while_stmt.body, depth + 1, false);
+                    let _ = writeln!(out, "{}}}", indent);
+                }
+                StatementKind::Return(ret) => {
+                    let returns = match &self.function.return_type {
+                        ReturnType::Void => None,
+                        ReturnType::Type(ty) => Some(ty.clone()),
+                    };
+                    let value = ret
+                        .value
+                        .map_or("()".to_string(), |value| self.coerced(value, returns, 0));
+                    let _ = writeln!(out, "{}return Ok(Flow::Return({}));", indent, value);
+                }
+                StatementKind::Abort(_) => {
+                    let _ = writeln!(out, "{}return Err(TxnError::Aborted);", indent);
+                }
+                StatementKind::Break(_) => {
+                    let _ = writeln!(out, "{}break;", indent);
+                }
+                StatementKind::Continue(_) => {
+                    let _ = writeln!(out, "{}continue;", indent);
+                }
+                StatementKind::Empty => {}
+            }
+        }
+    }
+
+    /// Where a variable lives: a field of the state or a local.
+    fn place(&self, var: VarId) -> String {
+        if let Some(name) = self.locals.get(&var) {
+            return name.clone();
+        }
+        match self.state_field(var) {
+            Some(field) => format!("state.{}", field),
+            None => variable(self.program.variables[var].name.as_str()),
+        }
+    }
+
+    /// The values of the primary key of an access, in the order the table
+    /// declares its key.
+    fn keys(
+        &self,
+        table: TableId,
+        fields: &[Option<FieldId>],
+        exprs: &[ExpressionId],
+    ) -> Vec<String> {
+        self.program.tables[table]
+            .primary_keys
+            .iter()
+            .filter_map(|key| {
+                let index = fields.iter().position(|field| *field == Some(*key))?;
+                Some(self.coerced(exprs[index], self.field_type(*key), 0))
+            })
+            .collect()
+    }
+
+    fn expr(&self, expr: ExpressionId) -> String {
+        self.expr_with_precedence(expr, 0)
+    }
+
+    /// An expression stored as or combined with a value of type `to`, cast
+    /// from an int where `to` is a float.
+    fn coerced(&self, expr: ExpressionId, to: Option<TypeName>, min_prec: u8) -> String {
+        if to == Some(TypeName::Float) && self.expr_type(expr) == Some(TypeName::Int) {
+            if let ExpressionKind::IntLit(value) = self.program.expressions[expr].node {
+                return float_literal(value as f64);
+            }
+            let text = format!("{} as f64", self.expr_with_precedence(expr, UNARY_PREC));
+            if CAST_PREC < min_prec {
+                return format!("({})", text);
+            }
+            return text;
+        }
+        self.expr_with_precedence(expr, min_prec)
+    }
+
+    fn field_type(&self, field: FieldId) -> Option<TypeName> {
+        Some(self.program.fields[field].field_type.clone())
+    }
+
+    /// The type of an expression, as semantic analysis types it.
+    fn expr_type(&self, expr: ExpressionId) -> Option<TypeName> {
+        match &self.program.expressions[expr].node {
+            ExpressionKind::Ident(_) => self
+                .program
+                .resolutions
+                .get(&expr)
+                .map(|&var| self.program.variables[var].ty.clone()),
+            ExpressionKind::IntLit(_) => Some(TypeName::Int),
+            ExpressionKind::FloatLit(_) => Some(TypeName::Float),
+            ExpressionKind::StringLit(_) => Some(TypeName::String),
+            ExpressionKind::BoolLit(_) => Some(TypeName::Bool),
+            ExpressionKind::TableFieldAccess { resolved_field, .. } => {
+                resolved_field.and_then(|field| self.field_type(field))
+            }
+            ExpressionKind::UnaryOp {
+                op: UnaryOp::Not, ..
+            } => Some(TypeName::Bool),
+            ExpressionKind::UnaryOp { expr, .. } => self.expr_type(*expr),
+            ExpressionKind::BinaryOp {
+                left, op, right, ..
+            } => match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+                    match (self.expr_type(*left)?, self.expr_type(*right)?) {
+                        (TypeName::Int, TypeName::Int) => Some(TypeName::Int),
+                        _ => Some(TypeName::Float),
+                    }
+                }
+                _ => Some(TypeName::Bool),
+            },
+        }
+    }
+
+    /// An expression, parenthesized if it binds looser than `min_prec`.
+    fn expr_with_precedence(&self, expr: ExpressionId, min_prec: u8) -> String {
+        let program = self.program;
+        let (text, prec) = match &program.expressions[expr].node {
+            ExpressionKind::Ident(name) => {
+                let var = program.resolutions.get(&expr).copied();
+                let text = var
+                    .map(|var| self.place(var))
+                    .unwrap_or_else(|| variable(name.as_str()));
+                let string = var.is_some_and(|var| program.variables[var].ty == TypeName::String);
+                if string {
+                    (format!("{}.clone()", text), PRIMARY_PREC)
+                } else {
+                    (text, PRIMARY_PREC)
+                }
+            }
+            ExpressionKind::IntLit(value) => (value.to_string(), PRIMARY_PREC),
+            ExpressionKind::FloatLit(value) => (float_literal(*value), PRIMARY_PREC),
+            ExpressionKind::StringLit(value) => (format!("{:?}.to_string()", value), PRIMARY_PREC),
+            ExpressionKind::BoolLit(value) => (value.to_string(), PRIMARY_PREC),
+            ExpressionKind::TableFieldAccess {
+                pk_exprs,
+                resolved_table: Some(table),
+                resolved_pk_fields,
+                resolved_field: Some(field),
+                ..
+            } => {
+                // The key field of the record read is the key it was read by
+                if let Some(index) = resolved_pk_fields
+                    .iter()
+                    .position(|key| *key == Some(*field))
+                {
+                    return self.expr_with_precedence(pk_exprs[index], min_prec);
+                }
+                let keys = self.keys(*table, resolved_pk_fields, pk_exprs);
+                (
+                    format!(
+                        "storage.{}({}).await?",
+                        accessor(program, *table, *field),
+                        keys.join(", ")
+                    ),
+                    PRIMARY_PREC,
+                )
+            }
+            ExpressionKind::TableFieldAccess { .. } => {
+                unreachable!("name resolution rejects accesses to unknown tables and fields")
+            }
+            ExpressionKind::UnaryOp { op, expr, .. } => {
+                let op = match op {
+                    UnaryOp::Not => "!",
+                    UnaryOp::Neg => "-",
+                    UnaryOp::FoldCase => unreachable!("FoldCase is only inserted into the CFG"),
+                };
+                let operand = self.expr_with_precedence(*expr, UNARY_PREC);
+                (format!("{}{}", op, operand), UNARY_PREC)
+            }
+            ExpressionKind::BinaryOp {
+                left, op, right, ..
+            } => {
+                let prec = binary_precedence(op);
+                let comparison = prec == COMPARISON_PREC;
+                let nocase =
+                    comparison && program.comparison_collation(*left, *right) == Collation::NoCase;
+                // Ints meet floats as floats, comparisons do not chain, and the
+                // other operators associate left
+                let numeric = |expr, other| match (self.expr_type(expr), self.expr_type(other)) {
+                    (Some(TypeName::Int), Some(TypeName::Float)) => Some(TypeName::Float),
+                    _ => None,
+                };
+                let (left, right) = if nocase {
+                    (
+                        format!(
+                            "{}.to_lowercase()",
+                            self.expr_with_precedence(*left, PRIMARY_PREC)
+                        ),
+                        format!(
+                            "{}.to_lowercase()",
+                            self.expr_with_precedence(*right, PRIMARY_PREC)
+                        ),
+                    )
+                } else if comparison {
+                    (
+                        self.coerced(*left, numeric(*left, *right), prec + 1),
+                        self.coerced(*right, numeric(*right, *left), prec + 1),
+                    )
+                } else {
+                    (
+                        self.coerced(*left, numeric(*left, *right), prec),
+                        self.coerced(*right, numeric(*right, *left), prec + 1),
+                    )
+                };
+                (format!("{} {} {}", left, binary_op_str(op), right), prec)
+            }
+        };
+        if prec < min_prec {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+}
+
+const COMPARISON_PREC: u8 = 3;
+const CAST_PREC: u8 = 6;
+const UNARY_PREC: u8 = 7;
+const PRIMARY_PREC: u8 = 8;
+
+/// How tightly a binary operator binds in Rust.
+fn binary_precedence(op: &BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Or => 1,
+        BinaryOp::And => 2,
+        BinaryOp::Eq
+        | BinaryOp::Neq
+        | BinaryOp::Lt
+        | BinaryOp::Lte
+        | BinaryOp::Gt
+        | BinaryOp::Gte => COMPARISON_PREC,
+        BinaryOp::Add | BinaryOp::Sub => 4,
+        BinaryOp::Mul | BinaryOp::Div => 5,
+    }
+}
+
+/// The getter of a field, `table_field`; its setter adds `set_`.
+fn accessor(program: &Program, table: TableId, field: FieldId) -> String {
+    format!(
+        "{}_{}",
+        snake_case(program.tables[table].name.as_str()),
+        snake_case(program.fields[field].field_name.as_str())
+    )
+}
+
+fn rust_type(ty: &TypeName) -> &'static str {
+    match ty {
+        TypeName::Int => "i64",
+        TypeName::Float => "f64",
+        TypeName::String => "String",
+        TypeName::Bool => "bool",
+    }
+}
+
+fn default_value(ty: &TypeName) -> &'static str {
+    match ty {
+        TypeName::Int => "0",
+        TypeName::Float => "0.0",
+        TypeName::String => "String::new()",
+        TypeName::Bool => "false",
+    }
+}
+
+/// A TransAct name as a Rust variable: snake case, raw if a keyword.
+fn variable(name: &str) -> String {
+    let name = snake_case(name);
+    if UNRAW.contains(&name.as_str()) {
+        format!("{}_", name)
+    } else if KEYWORDS.contains(&name.as_str()) {
+        format!("r#{}", name)
+    } else {
+        name
+    }
+}
+
+/// `accountId` and `AccountID` as `account_id`.
+pub(crate) fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            out.push('_');
+            continue;
+        }
+        if c.is_ascii_uppercase() && index > 0 {
+            let previous = chars[index - 1];
+            let next_lower = chars.get(index + 1).is_some_and(|c| c.is_ascii_lowercase());
+            if previous.is_ascii_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_ascii_uppercase() && next_lower)
+            {
+                out.push('_');
+            }
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    if out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+/// `bank_node1` and `BankNode1` as `BankNode1`.
+pub(crate) fn camel_case(name: &str) -> String {
+    snake_case(name)
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
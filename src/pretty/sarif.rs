@@ -0,0 +1,130 @@
+//! SARIF 2.1.0 logs, for code scanning in CI.
+//!
+//! Check mode reports the frontend's errors and warnings here, and verify mode
+//! the C-edges that could not be proven to commute, so code-review tools
+//! annotate the TransAct source alike for both. A result has the region of its
+//! span, with paths relative to the working directory; the notes of a
+//! diagnostic become its related locations.
+//!
+//! ```
+//! use FMitF_rs::ast::Diagnostic;
+//! use FMitF_rs::parse_and_analyze;
+//! use FMitF_rs::pretty::sarif::diagnostics_log;
+//!
+//! let source = r#"
+//! nodes { A }
+//! table T on A { primary int id; int n; }
+//! void f(int id) { hop on A { int unused = 1; T[id: id].n = 0; } }
+//! "#;
+//! let program = parse_and_analyze(source).unwrap();
+//! let log = diagnostics_log(&program.warnings);
+//! let result = &log["runs"][0]["results"][0];
+//! assert_eq!(result["ruleId"], "UnusedVariable");
+//! assert_eq!(result["level"], "warning");
+//! assert_eq!(result["locations"][0]["physicalLocation"]["region"]["startLine"], 4);
+//! assert_eq!(result["properties"]["lint"], "unused_variables");
+//!
+//! // Errors come from failed analyses
+//! let errors = parse_and_analyze("nodes { A, A }").unwrap_err();
+//! let diagnostics: Vec<Diagnostic> = errors.into_iter().map(Diagnostic::from).collect();
+//! let result = &diagnostics_log(&diagnostics)["runs"][0]["results"][0];
+//! assert_eq!(result["ruleId"], "DuplicateNode");
+//! assert_eq!(result["level"], "error");
+//! assert_eq!(result["relatedLocations"][0]["message"]["text"], "First declared here");
+//! ```
+
+use crate::ast::{Diagnostic, Span};
+use serde_json::{json, Value};
+
+/// A rule results refer to by id.
+pub fn rule(id: &str, text: &str) -> Value {
+    json!({ "id": id, "shortDescription": { "text": text } })
+}
+
+/// A log of one run of this tool, with the rules its results follow and
+/// properties of the run as a whole.
+pub fn log(rules: Vec<Value>, results: Vec<Value>, properties: Value) -> Value {
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+            "properties": properties,
+        }],
+    })
+}
+
+/// A span as a SARIF location; spans of source parsed from memory have a
+/// region but no file.
+pub fn location(span: &Span) -> Value {
+    let mut location = json!({
+        "physicalLocation": {
+            "region": {
+                "startLine": span.line,
+                "startColumn": span.column,
+                "endLine": span.end_line,
+                "endColumn": span.end_column,
+            }
+        }
+    });
+    if let Some(file) = &span.file {
+        // Relative to the working directory, as code scanning resolves them
+        let file = std::env::current_dir()
+            .ok()
+            .and_then(|dir| file.strip_prefix(dir).ok())
+            .unwrap_or(file);
+        location["physicalLocation"]["artifactLocation"] =
+            json!({ "uri": file.to_string_lossy().replace('\\', "/") });
+    }
+    location
+}
+
+/// Frontend diagnostics as a SARIF log, with a rule per kind of error or
+/// warning; warnings name the lint that `// allow(...)` silences.
+pub fn diagnostics_log(diagnostics: &[Diagnostic]) -> Value {
+    let mut rules: Vec<Value> = Vec::new();
+    let mut results = Vec::new();
+    for diagnostic in diagnostics {
+        let kind = diagnostic.error.error_type();
+        if !rules.iter().any(|rule| rule["id"] == kind) {
+            let text = match diagnostic.error.lint_name() {
+                Some(lint) => format!("Warning of the {} lint", lint),
+                None => "Error in the program".to_string(),
+            };
+            rules.push(rule(kind, &text));
+        }
+
+        let mut text = diagnostic.error.message();
+        let mut related = Vec::new();
+        for note in &diagnostic.notes {
+            match &note.span {
+                Some(span) => {
+                    let mut location = location(span);
+                    location["id"] = related.len().into();
+                    location["message"] = json!({ "text": note.error.message() });
+                    related.push(location);
+                }
+                None => text = format!("{}\n{}", text, note.error.message()),
+            }
+        }
+        let mut result = json!({
+            "ruleId": kind,
+            "level": diagnostic.severity.label(),
+            "message": { "text": text },
+            "locations": diagnostic.span.iter().map(location).collect::<Vec<_>>(),
+            "relatedLocations": related,
+        });
+        if let Some(lint) = diagnostic.error.lint_name() {
+            result["properties"] = json!({ "lint": lint });
+        }
+        results.push(result);
+    }
+    log(rules, results, json!({}))
+}
@@ -1,6 +1,10 @@
-use crate::cfg::{CfgProgram, FunctionId as CfgFunctionId};
-use crate::sc_graph::{EdgeType as SCGraphEdgeType, SCGraph, SCGraphNodeId};
-use std::collections::HashMap;
+use crate::cfg::{CfgProgram, FunctionId as CfgFunctionId, HopId as CfgHopId};
+use crate::pretty::{format_keyed_access, print_alloy, print_tla};
+use crate::sc_graph::{
+    CycleLimits, EdgeType as SCGraphEdgeType, Isolation, Lock, SCGraph, SCGraphNodeId,
+    TransactionWeights,
+};
+use std::collections::{BTreeMap, HashMap};
 use std::io::{Result, Write};
 
 fn escape_dot_label(s: &str) -> String {
@@ -10,12 +14,34 @@ fn escape_dot_label(s: &str) -> String {
         .replace("}", "\\}")
 }
 
+/// The file each function was declared in, when the functions come from more
+/// than one file, as with linked workloads; empty otherwise.
+fn function_files(cfg_program: &CfgProgram) -> HashMap<CfgFunctionId, String> {
+    let files: HashMap<CfgFunctionId, String> = cfg_program
+        .functions
+        .iter()
+        .filter_map(|(id, function)| Some((id, function.span.file.as_ref()?.display().to_string())))
+        .collect();
+    let mut distinct: Vec<&String> = files.values().collect();
+    distinct.sort();
+    distinct.dedup();
+    if distinct.len() > 1 {
+        files
+    } else {
+        HashMap::new()
+    }
+}
+
 /// Print options for SC-Graph output
 #[derive(Debug, Clone)]
 pub struct SCGraphPrintOptions {
     pub format: SCGraphFormat,
     pub verbose: bool,
     pub show_spans: bool,
+    /// Bounds on the mixed cycles listed
+    pub cycle_limits: CycleLimits,
+    /// Transaction weights to rank the mixed cycles by
+    pub weights: Option<TransactionWeights>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -23,6 +49,17 @@ pub enum SCGraphFormat {
     Text,
     Dot,
     Summary,
+    Json,
+    /// C-edges per pair of functions
+    PairReport,
+    /// C-edges per table and field
+    HotspotReport,
+    /// Pairs of hops that may deadlock under record locks
+    DeadlockReport,
+    /// A TLA+ specification, as the module named
+    Tla(String),
+    /// An Alloy model, as the module named
+    Alloy(String),
 }
 
 impl Default for SCGraphPrintOptions {
@@ -31,18 +68,70 @@ impl Default for SCGraphPrintOptions {
             format: SCGraphFormat::Summary,
             verbose: false,
             show_spans: false,
+            cycle_limits: CycleLimits::default(),
+            weights: None,
         }
     }
 }
 
 /// Main entry point for printing SC-Graph
+///
+/// Every format lists nodes, edges and cycles in the order of their ids, so
+/// the same program always prints the same bytes, as golden files and
+/// baselines need:
+///
+/// ```
+/// use FMitF_rs::pretty::{print_sc_graph, SCGraphFormat, SCGraphPrintOptions};
+/// use FMitF_rs::sc_graph::SCGraph;
+/// use FMitF_rs::{parse_and_analyze, CfgBuilder};
+///
+/// let source = r#"
+///     nodes { A, B }
+///     table T on A { primary int id; int n; }
+///     table U on B { primary int id; int n; }
+///     void f() { hop on A { T[id: 1].n = 0; } hop on B { U[id: 1].n = 0; } }
+///     void g() { hop on B { U[id: 1].n = 1; } hop on A { T[id: 1].n = 1; } }
+///     void h() { hop on A { T[id: 1].n = 2; } hop on B { U[id: 1].n = 2; } }
+///     void k() { hop on B { U[id: 1].n = 3; } hop on A { T[id: 1].n = 3; } }
+/// "#;
+/// // Each run builds its own maps, each hashing in an order of its own
+/// let render = |format: SCGraphFormat| {
+///     let program = parse_and_analyze(source).unwrap();
+///     let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+///     let sc_graph = SCGraph::new(&cfg);
+///     let options = SCGraphPrintOptions {
+///         format,
+///         verbose: true,
+///         ..Default::default()
+///     };
+///     let mut output = Vec::new();
+///     print_sc_graph(&sc_graph, &cfg, &options, &mut output).unwrap();
+///     output
+/// };
+/// for format in [
+///     SCGraphFormat::Text,
+///     SCGraphFormat::Dot,
+///     SCGraphFormat::Summary,
+///     SCGraphFormat::PairReport,
+///     SCGraphFormat::HotspotReport,
+///     SCGraphFormat::DeadlockReport,
+///     SCGraphFormat::Json,
+///     SCGraphFormat::Tla("Workload".to_string()),
+///     SCGraphFormat::Alloy("workload".to_string()),
+/// ] {
+///     let first = render(format.clone());
+///     for _ in 0..4 {
+///         assert_eq!(render(format.clone()), first, "{:?}", format);
+///     }
+/// }
+/// ```
 pub fn print_sc_graph(
     sc_graph: &SCGraph,
     cfg_program: &CfgProgram,
     options: &SCGraphPrintOptions,
     writer: &mut impl Write,
 ) -> Result<()> {
-    match options.format {
+    match &options.format {
         SCGraphFormat::Text => {
             let output = format_sc_graph_text(sc_graph, cfg_program, options);
             write!(writer, "{}", output)?;
@@ -51,28 +140,208 @@ pub fn print_sc_graph(
             format_sc_graph_dot(sc_graph, cfg_program, writer)?;
         }
         SCGraphFormat::Summary => {
-            let output = format_sc_graph_summary(sc_graph, cfg_program);
+            let output = format_sc_graph_summary(sc_graph, cfg_program, options);
+            write!(writer, "{}", output)?;
+        }
+        SCGraphFormat::PairReport => {
+            let output = format_pair_report(sc_graph, cfg_program);
+            write!(writer, "{}", output)?;
+        }
+        SCGraphFormat::HotspotReport => {
+            let output = format_hotspot_report(sc_graph, cfg_program);
+            write!(writer, "{}", output)?;
+        }
+        SCGraphFormat::DeadlockReport => {
+            let output = format_deadlock_report(sc_graph, cfg_program);
             write!(writer, "{}", output)?;
         }
+        SCGraphFormat::Json => {
+            let json = sc_graph.to_json(cfg_program, &options.cycle_limits);
+            serde_json::to_writer_pretty(&mut *writer, &json)?;
+            writeln!(writer)?;
+        }
+        SCGraphFormat::Tla(module) => {
+            print_tla(sc_graph, cfg_program, module, &options.cycle_limits, writer)?;
+        }
+        SCGraphFormat::Alloy(module) => {
+            print_alloy(sc_graph, cfg_program, module, &options.cycle_limits, writer)?;
+        }
     }
     Ok(())
 }
 
-fn format_sc_graph_summary(sc_graph: &SCGraph, _cfg_program: &CfgProgram) -> String {
+/// Marks a cycle count as a lower bound when the search stopped at its limit.
+fn truncation_note(truncated: bool) -> &'static str {
+    if truncated {
+        " (limit reached)"
+    } else {
+        ""
+    }
+}
+
+fn format_sc_graph_summary(
+    sc_graph: &SCGraph,
+    cfg_program: &CfgProgram,
+    options: &SCGraphPrintOptions,
+) -> String {
     let (nodes_count, s_edges_count, c_edges_count) = sc_graph.stats();
-    let mixed_cycles = sc_graph.find_mixed_cycles();
+    let mixed_cycles = sc_graph.find_mixed_cycles_within(&options.cycle_limits);
+    let plan = sc_graph.c_edge_hitting_set(&mixed_cycles.cycles);
 
-    format!(
+    let mut s = format!(
         "SC-Graph Summary:\n\
          - Total Nodes (Hops): {}\n\
          - Total S-Edges: {}\n\
          - Total C-Edges: {}\n\
-         - Mixed S/C Cycles Found: {}\n",
+         - Mixed S/C Cycles Found: {}{}\n\
+         - C-Edges to Verify First: {}\n",
         nodes_count,
         s_edges_count,
         c_edges_count,
-        mixed_cycles.len()
-    )
+        mixed_cycles.cycles.len(),
+        truncation_note(mixed_cycles.truncated),
+        plan.len()
+    );
+    if sc_graph.isolation == Isolation::Snapshot {
+        s.push_str(&format!(
+            "- Isolation: {} (cycles that are write skew left out)\n",
+            sc_graph.isolation.name()
+        ));
+    }
+    s.push_str(&format_exclusions(sc_graph));
+    if let Some(weights) = &options.weights {
+        s.push_str(&format_ranked_cycles(
+            sc_graph,
+            cfg_program,
+            &mixed_cycles.cycles,
+            weights,
+        ));
+    }
+    s
+}
+
+/// What conflict analysis left out, and how many C-edges that dropped.
+fn format_exclusions(sc_graph: &SCGraph) -> String {
+    let exclusions = &sc_graph.exclusions;
+    if exclusions.is_empty() {
+        return String::new();
+    }
+    let mut s = format!(
+        "Excluded from Conflict Analysis: {} C-Edges\n",
+        sc_graph.excluded_edges().len()
+    );
+    for (a, b) in &exclusions.ignore_pairs {
+        s.push_str(&format!("  Pair: {} -- {}\n", a, b));
+    }
+    for table in &exclusions.ignore_tables {
+        s.push_str(&format!("  Table: {}\n", table));
+    }
+    s
+}
+
+/// The cycles heaviest first, each with its weight and id.
+fn format_ranked_cycles(
+    sc_graph: &SCGraph,
+    cfg_program: &CfgProgram,
+    cycles: &[Vec<CfgHopId>],
+    weights: &TransactionWeights,
+) -> String {
+    if cycles.is_empty() {
+        return String::new();
+    }
+    let mut s = String::from("Mixed Cycles by Weight:\n");
+    for (index, weight) in sc_graph.rank_cycles(cycles, cfg_program, weights) {
+        let cycle = &cycles[index];
+        s.push_str(&format!(
+            "  {} [{}]: {}\n",
+            weight,
+            sc_graph.cycle_id(cycle, cfg_program),
+            sc_graph.describe_cycle(cycle, cfg_program)
+        ));
+    }
+    s
+}
+
+fn format_pair_report(sc_graph: &SCGraph, cfg_program: &CfgProgram) -> String {
+    let pairs = sc_graph.pair_conflicts();
+    let mut s = format!("C-Edges per Transaction Pair: {}\n", pairs.len());
+    for pair in pairs {
+        let tables: Vec<&str> = pair
+            .tables
+            .iter()
+            .map(|&table| cfg_program.tables[table].name.as_str())
+            .collect();
+        s.push_str(&format!(
+            "  {} -- {}: {} C-Edges over {}\n",
+            cfg_program.functions[pair.functions.0].name,
+            cfg_program.functions[pair.functions.1].name,
+            pair.c_edges,
+            tables.join(", ")
+        ));
+    }
+    s
+}
+
+fn format_hotspot_report(sc_graph: &SCGraph, cfg_program: &CfgProgram) -> String {
+    let total = sc_graph
+        .edges
+        .iter()
+        .filter(|edge| edge.edge_type == SCGraphEdgeType::C)
+        .count();
+    let mut s = format!("C-Edge Hotspots: {} C-Edges\n", total);
+    for hotspot in sc_graph.hotspots() {
+        let table = &cfg_program.tables[hotspot.table];
+        s.push_str(&format!(
+            "  {}: {} of {} C-Edges\n",
+            table.name, hotspot.c_edges, total
+        ));
+        for (field, c_edges) in hotspot.fields {
+            s.push_str(&format!(
+                "    {}.{}: {} of {} C-Edges\n",
+                table.name, cfg_program.fields[field].name, c_edges, total
+            ));
+        }
+    }
+    s
+}
+
+fn format_deadlock_report(sc_graph: &SCGraph, cfg_program: &CfgProgram) -> String {
+    let deadlocks = sc_graph.potential_deadlocks(cfg_program);
+    let mut s = format!("Potential Deadlocks: {}\n", deadlocks.len());
+    let hop = |hop: CfgHopId| {
+        let node = &sc_graph.nodes[sc_graph.get_sc_node_id(hop).unwrap()];
+        format!(
+            "{}:H{}",
+            cfg_program.functions[node.cfg_function_id].name,
+            hop.index()
+        )
+    };
+    let lock = |lock: &Lock| {
+        let access = format_keyed_access(&lock.access, cfg_program);
+        match lock.exclusive {
+            true => format!("{} (exclusive)", access),
+            false => format!("{} (shared)", access),
+        }
+    };
+    for deadlock in deadlocks {
+        let [first, second] = &deadlock.orders;
+        s.push_str(&format!(
+            "  {} -- {} on {}\n",
+            hop(first.hop),
+            hop(second.hop),
+            cfg_program.nodes[deadlock.node].name
+        ));
+        for order in &deadlock.orders {
+            let held: Vec<String> = order.locks[..order.waits].iter().map(lock).collect();
+            s.push_str(&format!(
+                "    {} locks {}, then waits for {}\n",
+                hop(order.hop),
+                held.join(", "),
+                lock(&order.locks[order.waits])
+            ));
+        }
+    }
+    s
 }
 
 fn format_sc_graph_text(
@@ -88,18 +357,29 @@ fn format_sc_graph_text(
         "Stats: {} Nodes (Hops), {} S-Edges, {} C-Edges\n\n",
         nodes_count, s_edges_count, c_edges_count
     ));
+    let exclusions = format_exclusions(sc_graph);
+    if !exclusions.is_empty() {
+        s.push_str(&exclusions);
+        s.push('\n');
+    }
 
     if options.verbose {
         s.push_str("Nodes (Hops):\n");
+        let files = function_files(cfg_program);
         for (sc_node_id, sc_node) in sc_graph.nodes.iter() {
             let func_name = &cfg_program.functions[sc_node.cfg_function_id].name;
             let cfg_node_name = &cfg_program.nodes[sc_node.cfg_node_id].name;
+            let file = match files.get(&sc_node.cfg_function_id) {
+                Some(file) => format!(", File='{}'", file),
+                None => String::new(),
+            };
             s.push_str(&format!(
-                "  SCNode {} (CFG Hop {}): Func='{}', CFGNode='{}'\n",
+                "  SCNode {} (CFG Hop {}): Func='{}', CFGNode='{}'{}\n",
                 sc_node_id.index(),
                 sc_node.cfg_hop_id.index(),
                 func_name,
-                cfg_node_name
+                cfg_node_name,
+                file
             ));
         }
         s.push_str("\nEdges:\n");
@@ -122,12 +402,137 @@ fn format_sc_graph_text(
                 target_sc_node.cfg_hop_id.index(),
                 edge.edge_type
             ));
+            if let Some(justification) = sc_graph.justifications.get(edge) {
+                s.push_str(&format!("    {}\n", justification.reason));
+                for conflict in &justification.conflicts {
+                    s.push_str(&format!("      {}\n", conflict.describe(cfg_program)));
+                }
+            }
+        }
+
+        // Candidate C-edges whose hops provably do not conflict
+        let mut dropped: Vec<_> = sc_graph
+            .justifications
+            .iter()
+            .filter(|(_, justification)| !justification.conflicting)
+            .collect();
+        dropped.sort_by_key(|(edge, _)| (edge.source.index(), edge.target.index()));
+        if !dropped.is_empty() {
+            s.push_str("\nDropped C-Edges:\n");
+            for (edge, justification) in dropped {
+                let source_sc_node = &sc_graph.nodes[edge.source];
+                let target_sc_node = &sc_graph.nodes[edge.target];
+                s.push_str(&format!(
+                    "  SC{} ({}:H{}) -- SC{} ({}:H{})\n    {}\n",
+                    edge.source.index(),
+                    cfg_program.functions[source_sc_node.cfg_function_id].name,
+                    source_sc_node.cfg_hop_id.index(),
+                    edge.target.index(),
+                    cfg_program.functions[target_sc_node.cfg_function_id].name,
+                    target_sc_node.cfg_hop_id.index(),
+                    justification.reason
+                ));
+                for conflict in &justification.conflicts {
+                    s.push_str(&format!("      {}\n", conflict.describe(cfg_program)));
+                }
+            }
+        }
+        s.push('\n');
+    }
+
+    let mixed_cycles = sc_graph.find_mixed_cycles_within(&options.cycle_limits);
+    s.push_str(&format!(
+        "Mixed S/C Cycles Found: {}{}\n",
+        mixed_cycles.cycles.len(),
+        truncation_note(mixed_cycles.truncated)
+    ));
+    let mixed_cycles = mixed_cycles.cycles;
+    if let Some(weights) = &options.weights {
+        s.push_str(&format_ranked_cycles(
+            sc_graph,
+            cfg_program,
+            &mixed_cycles,
+            weights,
+        ));
+    }
+
+    // C-edges that, once verified, leave none of the cycles found
+    let plan = sc_graph.c_edge_hitting_set(&mixed_cycles);
+    if !plan.is_empty() {
+        s.push_str(&format!("Verify These {} C-Edges First:\n", plan.len()));
+        for edge in &plan {
+            let source_sc_node = &sc_graph.nodes[edge.source];
+            let target_sc_node = &sc_graph.nodes[edge.target];
+            s.push_str(&format!(
+                "  SC{} ({}:H{}) -- SC{} ({}:H{})\n",
+                edge.source.index(),
+                cfg_program.functions[source_sc_node.cfg_function_id].name,
+                source_sc_node.cfg_hop_id.index(),
+                edge.target.index(),
+                cfg_program.functions[target_sc_node.cfg_function_id].name,
+                target_sc_node.cfg_hop_id.index()
+            ));
+        }
+    }
+
+    // Hop merges that would break some of the cycles
+    if !mixed_cycles.is_empty() {
+        let advice = sc_graph.advise_merges(&options.cycle_limits);
+        if !advice.is_empty() {
+            s.push_str("Suggested Hop Merges:\n");
+        }
+        for preview in advice {
+            let merges: Vec<String> = preview
+                .merges
+                .iter()
+                .map(|merge| {
+                    format!(
+                        "merge hops H{} and H{} of `{}`",
+                        merge.first.index(),
+                        merge.second.index(),
+                        cfg_program.functions[merge.function].name
+                    )
+                })
+                .collect();
+            let (nodes_count, s_edges_count, c_edges_count) = preview.stats;
+            s.push_str(&format!(
+                "  {}: {} mixed cycles left{} ({} Nodes, {} S-Edges, {} C-Edges)\n",
+                merges.join(", "),
+                preview.cycles,
+                truncation_note(preview.truncated),
+                nodes_count,
+                s_edges_count,
+                c_edges_count
+            ));
+        }
+    }
+
+    // Hop orders, as dependencies allow, that would break some of the cycles
+    if !mixed_cycles.is_empty() {
+        let advice = sc_graph.advise_reorderings(cfg_program, &options.cycle_limits);
+        if !advice.is_empty() {
+            s.push_str("Suggested Hop Reorderings:\n");
+        }
+        for preview in advice {
+            let order: Vec<String> = preview
+                .order
+                .iter()
+                .map(|hop| format!("H{}", hop.index()))
+                .collect();
+            let (nodes_count, s_edges_count, c_edges_count) = preview.stats;
+            s.push_str(&format!(
+                "  run the hops of `{}` as {}: {} mixed cycles left{} ({} Nodes, {} S-Edges, {} C-Edges)\n",
+                cfg_program.functions[preview.function].name,
+                order.join(", "),
+                preview.cycles,
+                truncation_note(preview.truncated),
+                nodes_count,
+                s_edges_count,
+                c_edges_count
+            ));
         }
-        s.push_str("\n");
     }
 
-    let mixed_cycles = sc_graph.find_mixed_cycles();
-    s.push_str(&format!("Mixed S/C Cycles Found: {}\n", mixed_cycles.len()));
     if options.verbose && !mixed_cycles.is_empty() {
         s.push_str("Cycles:\n");
         for (i, cycle) in mixed_cycles.iter().enumerate() {
@@ -137,14 +542,40 @@ fn format_sc_graph_text(
                     // Find the SC node that corresponds to this CFG hop
                     if let Some(sc_node_id) = sc_graph.get_sc_node_id(h_id) {
                         let sc_node = &sc_graph.nodes[sc_node_id];
-                        let func_name = &cfg_program.functions[sc_node.cfg_function_id].name;
-                        format!("SC{}({}:H{})", sc_node_id.index(), func_name, h_id.index())
+                        let function = &cfg_program.functions[sc_node.cfg_function_id];
+                        format!(
+                            "SC{}({}:H{} @{})",
+                            sc_node_id.index(),
+                            function.name,
+                            h_id.index(),
+                            function.hops[h_id].span
+                        )
                     } else {
                         format!("H{}", h_id.index())
                     }
                 })
                 .collect();
-            s.push_str(&format!("  Cycle {}: {}\n", i + 1, cycle_str.join(" -- ")));
+            s.push_str(&format!(
+                "  Cycle {} [{}]: {}\n",
+                i + 1,
+                sc_graph.cycle_id(cycle, cfg_program),
+                cycle_str.join(" -- ")
+            ));
+            let explanation = sc_graph.explain_cycle(cycle, cfg_program);
+            for (step_number, step) in explanation.steps.iter().enumerate() {
+                s.push_str(&format!(
+                    "    {}. SC{} -> SC{} ({:?}): {}\n",
+                    step_number + 1,
+                    step.from.index(),
+                    step.to.index(),
+                    step.edge_type,
+                    step.text
+                ));
+            }
+            s.push_str(&format!("    => {}\n", explanation.conclusion));
+            if let Some(schedule) = sc_graph.cycle_schedule(cycle, cfg_program) {
+                s.push_str(&format!("    Schedule: {}\n", schedule));
+            }
         }
     }
 
@@ -159,10 +590,10 @@ fn format_sc_graph_dot(
     writeln!(writer, "graph SCGraph {{")?; // Changed from "digraph" to "graph" for undirected
     writeln!(writer, "  compound=true;")?;
     writeln!(writer, "  node [shape=box, style=rounded];")?;
-    writeln!(writer, "")?;
+    writeln!(writer)?;
 
     // Group nodes by CFG Function
-    let mut func_to_sc_nodes: HashMap<CfgFunctionId, Vec<SCGraphNodeId>> = HashMap::new();
+    let mut func_to_sc_nodes: BTreeMap<CfgFunctionId, Vec<SCGraphNodeId>> = BTreeMap::new();
     for (sc_node_id, sc_node_data) in sc_graph.nodes.iter() {
         func_to_sc_nodes
             .entry(sc_node_data.cfg_function_id)
@@ -170,14 +601,15 @@ fn format_sc_graph_dot(
             .push(sc_node_id);
     }
 
+    let files = function_files(cfg_program);
     for (cfg_func_id, sc_node_ids) in &func_to_sc_nodes {
         let func_name = &cfg_program.functions[*cfg_func_id].name;
+        let label = match files.get(cfg_func_id) {
+            Some(file) => format!("Function: {} ({})", func_name, file),
+            None => format!("Function: {}", func_name),
+        };
         writeln!(writer, "  subgraph cluster_func_{} {{", cfg_func_id.index())?;
-        writeln!(
-            writer,
-            "    label=\"Function: {}\";",
-            escape_dot_label(func_name)
-        )?;
+        writeln!(writer, "    label=\"{}\";", escape_dot_label(&label))?;
         writeln!(writer, "    style=filled;")?;
         writeln!(writer, "    color=lightgrey;")?;
 
@@ -199,7 +631,7 @@ fn format_sc_graph_dot(
         }
         writeln!(writer, "  }}")?;
     }
-    writeln!(writer, "")?;
+    writeln!(writer)?;
 
     // Output edges
     for edge in &sc_graph.edges {
@@ -208,14 +640,27 @@ fn format_sc_graph_dot(
             SCGraphEdgeType::S => ("blue", "solid"),
             SCGraphEdgeType::C => ("red", "dashed"),
         };
+        // C-edges list the accesses they conflict through on hover
+        let tooltip = match sc_graph.justifications.get(edge) {
+            Some(justification) if !justification.conflicts.is_empty() => {
+                let conflicts: Vec<String> = justification
+                    .conflicts
+                    .iter()
+                    .map(|conflict| conflict.describe(cfg_program))
+                    .collect();
+                format!(", tooltip=\"{}\"", escape_dot_label(&conflicts.join("\n")))
+            }
+            _ => String::new(),
+        };
         writeln!(
             writer,
-            "  sc_node_{} -- sc_node_{} [color={}, style={}, label=\"{:?}\"];",
+            "  sc_node_{} -- sc_node_{} [color={}, style={}, label=\"{:?}\"{}];",
             edge.source.index(),
             edge.target.index(),
             color,
             style,
-            edge.edge_type
+            edge.edge_type,
+            tooltip
         )?;
     }
 
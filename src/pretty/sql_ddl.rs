@@ -0,0 +1,199 @@
+//! The table declarations of a program as SQL `CREATE TABLE` statements, for
+//! PostgreSQL, so the verified schema can be deployed as it is.
+//!
+//! Each table gets its columns in declaration order and its primary key, with
+//! a comment naming the node it is on. Columns are `NOT NULL`, and those
+//! outside the key default to the value a TransAct read of a missing record
+//! sees: `0`, `0.0`, `FALSE` or `''`. Strings `collate nocase` become
+//! `CITEXT`, from the `citext` extension. Names keep their case, quoted where
+//! PostgreSQL would fold or reject them.
+//!
+//! ```
+//! use FMitF_rs::parse_and_analyze;
+//! use FMitF_rs::pretty::format_sql_ddl;
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table orders on A { primary int id; primary string region; float total; bool paid; }
+//!     table User on A { primary int id; string email collate nocase; }
+//!     "#,
+//! )
+//! .unwrap();
+//! let ddl = format_sql_ddl(&program);
+//! assert!(ddl.starts_with("CREATE EXTENSION IF NOT EXISTS citext;"));
+//! assert!(ddl.contains(
+//!     "-- On node A
+//! CREATE TABLE orders (
+//!     id BIGINT NOT NULL,
+//!     region TEXT NOT NULL,
+//!     total DOUBLE PRECISION NOT NULL DEFAULT 0.0,
+//!     paid BOOLEAN NOT NULL DEFAULT FALSE,
+//!     PRIMARY KEY (id, region)
+//! );"
+//! ));
+//! assert!(ddl.contains("CREATE TABLE \"User\" (\n    id BIGINT NOT NULL,\n    email CITEXT NOT NULL DEFAULT '',"));
+//! ```
+
+use crate::ast::{Collation, FieldDeclaration, Program, TypeName};
+
+/// PostgreSQL's reserved key words, which cannot name a table or column
+/// unquoted.
+const RESERVED: &[&str] = &[
+    "all",
+    "analyse",
+    "analyze",
+    "and",
+    "any",
+    "array",
+    "as",
+    "asc",
+    "asymmetric",
+    "both",
+    "case",
+    "cast",
+    "check",
+    "collate",
+    "column",
+    "constraint",
+    "create",
+    "current_catalog",
+    "current_date",
+    "current_role",
+    "current_time",
+    "current_timestamp",
+    "current_user",
+    "default",
+    "deferrable",
+    "desc",
+    "distinct",
+    "do",
+    "else",
+    "end",
+    "except",
+    "false",
+    "fetch",
+    "for",
+    "foreign",
+    "from",
+    "grant",
+    "group",
+    "having",
+    "in",
+    "initially",
+    "intersect",
+    "into",
+    "lateral",
+    "leading",
+    "limit",
+    "localtime",
+    "localtimestamp",
+    "not",
+    "null",
+    "offset",
+    "on",
+    "only",
+    "or",
+    "order",
+    "placing",
+    "primary",
+    "references",
+    "returning",
+    "select",
+    "session_user",
+    "some",
+    "symmetric",
+    "table",
+    "then",
+    "to",
+    "trailing",
+    "true",
+    "union",
+    "unique",
+    "user",
+    "using",
+    "variadic",
+    "when",
+    "where",
+    "window",
+    "with",
+];
+
+/// The `CREATE TABLE` statements of the tables of `program`, in declaration
+/// order.
+pub fn format_sql_ddl(program: &Program) -> String {
+    let mut out = String::new();
+    let nocase = program.root_tables.iter().any(|&table| {
+        program.tables[table].fields.iter().any(|&field| {
+            program.fields[field].field_type == TypeName::String
+                && program.fields[field].collation() == Collation::NoCase
+        })
+    });
+    if nocase {
+        out.push_str("CREATE EXTENSION IF NOT EXISTS citext;\n\n");
+    }
+
+    let tables: Vec<String> = program
+        .root_tables
+        .iter()
+        .map(|&table| {
+            let table = &program.tables[table];
+            let mut lines: Vec<String> = table
+                .fields
+                .iter()
+                .map(|&field| column(&program.fields[field]))
+                .collect();
+            let keys: Vec<String> = table
+                .primary_keys
+                .iter()
+                .map(|&key| identifier(program.fields[key].field_name.as_str()))
+                .collect();
+            lines.push(format!("PRIMARY KEY ({})", keys.join(", ")));
+            format!(
+                "-- On node {}\nCREATE TABLE {} (\n    {}\n);\n",
+                program.nodes[table.node].name,
+                identifier(table.name.as_str()),
+                lines.join(",\n    ")
+            )
+        })
+        .collect();
+    out.push_str(&tables.join("\n"));
+    out
+}
+
+/// A column definition.
+fn column(field: &FieldDeclaration) -> String {
+    let sql_type = match (&field.field_type, field.collation()) {
+        (TypeName::Int, _) => "BIGINT",
+        (TypeName::Float, _) => "DOUBLE PRECISION",
+        (TypeName::String, Collation::Binary) => "TEXT",
+        (TypeName::String, Collation::NoCase) => "CITEXT",
+        (TypeName::Bool, _) => "BOOLEAN",
+    };
+    let name = identifier(field.field_name.as_str());
+    if field.is_primary {
+        return format!("{} {} NOT NULL", name, sql_type);
+    }
+    let default = match field.field_type {
+        TypeName::Int => "0",
+        TypeName::Float => "0.0",
+        TypeName::String => "''",
+        TypeName::Bool => "FALSE",
+    };
+    format!("{} {} NOT NULL DEFAULT {}", name, sql_type, default)
+}
+
+/// A name as PostgreSQL takes it: bare when it is lowercase and not reserved,
+/// quoted otherwise.
+fn identifier(name: &str) -> String {
+    let bare = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !RESERVED.contains(&name);
+    if bare {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
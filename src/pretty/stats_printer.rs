@@ -0,0 +1,42 @@
+use crate::sc_graph::{Spread, WorkloadStats};
+use std::io::{Result, Write};
+
+/// Prints workload metrics to `writer`, one line per metric.
+pub fn print_workload_stats(stats: &WorkloadStats, writer: &mut dyn Write) -> Result<()> {
+    let spread = |spread: &Spread| {
+        format!(
+            "min {}, avg {:.2}, max {}",
+            spread.min, spread.mean, spread.max
+        )
+    };
+    let nodes: Vec<String> = stats
+        .nodes
+        .iter()
+        .map(|node| format!("{} {}", node.name, node.tables))
+        .collect();
+
+    writeln!(writer, "Transactions: {}", stats.transactions)?;
+    writeln!(writer, "Hops per transaction: {}", spread(&stats.hops))?;
+    writeln!(writer, "Tables per node: {}", nodes.join(", "))?;
+    writeln!(
+        writer,
+        "Cross-node data flow: {} hop changes, {} values carried",
+        stats.cross_node.hop_changes, stats.cross_node.values_carried
+    )?;
+    writeln!(
+        writer,
+        "Statements: {} ({} table reads, {} table writes)",
+        stats.statements.total, stats.statements.table_reads, stats.statements.table_writes
+    )?;
+    writeln!(
+        writer,
+        "Statements per transaction: {}",
+        spread(&stats.statements.per_transaction)
+    )?;
+    let graph = &stats.sc_graph;
+    writeln!(
+        writer,
+        "SC-graph: {} vertices, {} S-edges, {} of {} possible C-edges (density {:.2})",
+        graph.vertices, graph.s_edges, graph.c_edges, graph.possible_c_edges, graph.density
+    )
+}
@@ -0,0 +1,865 @@
+//! The chopped workload as a TLA+ specification, to check with TLC that the
+//! chopping is serializable, or to read as the formal model behind it.
+//!
+//! Each table is a variable mapping primary key tuples to records of its other
+//! fields, starting from any contents over the sets of values the model gives
+//! as constants (`IntValues`, `FloatValues`, `StringValues`). Each transaction
+//! runs once, with any parameters from those sets, as the hops of its function
+//! in order. A hop is one atomic step: its blocks are operators from a state of
+//! the tables and locals to the next, and an abort rolls back the hop and ends
+//! the transaction. `Serializability` holds when every transaction has ended
+//! with the tables and outcomes of some serial order of the transactions. The
+//! C-edges and mixed cycles of the SC-graph are listed in comments; TLC can
+//! only find violations through the mixed cycles.
+//!
+//! TLC has no real numbers and no order on strings: floats take integer values
+//! and divide rounding down, and ordering strings, or comparing them without
+//! case, is not exported. Reading a key outside the sets of values is an error
+//! in TLC, so the sets should hold the keys the transactions compute.
+//!
+//! ```
+//! use FMitF_rs::pretty::print_tla;
+//! use FMitF_rs::sc_graph::{CycleLimits, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table Account on A { primary int id; int balance; }
+//!     table Audit on B { primary int id; int total; }
+//!     void deposit(int id, int amount) {
+//!         hop on A { Account[id: id].balance = Account[id: id].balance + amount; }
+//!         hop on B { Audit[id: 0].total = Audit[id: 0].total + amount; }
+//!     }
+//!     int audit() {
+//!         hop on A { if (Account[id: 0].balance < 0) { abort; } }
+//!         hop on B { return Audit[id: 0].total; }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let mut spec = Vec::new();
+//! print_tla(&sc_graph, &cfg, "Bank", &CycleLimits::default(), &mut spec).unwrap();
+//! let spec = String::from_utf8(spec).unwrap();
+//!
+//! assert!(spec.starts_with("---- MODULE Bank ----"));
+//! assert!(spec.contains("VARIABLES Account, Audit"));
+//! assert!(spec.contains("Keys_Account == {<<k1>> : k1 \\in IntValues}"));
+//! assert!(spec.contains("\\* Mixed cycle ["));
+//! assert!(spec.contains("Serializability =="));
+//! assert!(spec.ends_with("====\n"));
+//! ```
+
+use crate::cfg::{
+    BasicBlockId, BinaryOp, CfgProgram, Constant, FieldId, FunctionCfg, Operand, Rvalue, Statement,
+    TableId, Terminator, TypeName, UnaryOp,
+};
+use crate::sc_graph::{CycleLimits, EdgeType, SCGraph};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// Words TLA+ reserves, the operators of the modules the specification
+/// extends, and the names it defines itself.
+const TAKEN: &[&str] = &[
+    "ASSUME",
+    "ASSUMPTION",
+    "AXIOM",
+    "BOOLEAN",
+    "CASE",
+    "CHOOSE",
+    "CONSTANT",
+    "CONSTANTS",
+    "DOMAIN",
+    "ELSE",
+    "ENABLED",
+    "EXCEPT",
+    "EXTENDS",
+    "FALSE",
+    "IF",
+    "IN",
+    "INSTANCE",
+    "LET",
+    "LOCAL",
+    "MODULE",
+    "OTHER",
+    "STRING",
+    "SUBSET",
+    "THEN",
+    "THEOREM",
+    "TRUE",
+    "UNCHANGED",
+    "UNION",
+    "VARIABLE",
+    "VARIABLES",
+    "WITH",
+    "Nat",
+    "Int",
+    "Seq",
+    "Len",
+    "Append",
+    "Head",
+    "Tail",
+    "SubSeq",
+    "SelectSeq",
+    "Cardinality",
+    "IsFiniteSet",
+    "Print",
+    "PrintT",
+    "Assert",
+    "JavaTime",
+    "Permutations",
+    "SortSeq",
+    "RandomElement",
+    "Any",
+    "ToString",
+    "TLCGet",
+    "TLCSet",
+    "TLCEval",
+    "IntValues",
+    "FloatValues",
+    "StringValues",
+    "Committed",
+    "Aborted",
+    "Tables",
+    "Store",
+    "Txns",
+    "StepOf",
+    "Step",
+    "Running",
+    "Hop",
+    "Done",
+    "Next",
+    "Init",
+    "Spec",
+    "vars",
+    "Run",
+    "Serial",
+    "Outcomes",
+    "Orders",
+    "Serializability",
+    "frames",
+    "initial",
+];
+
+/// Hands out identifiers of letters, digits and `_`, starting with a letter,
+/// renaming those taken.
+#[derive(Clone)]
+pub(crate) struct Names(HashSet<String>);
+
+impl Names {
+    pub(crate) fn new(taken: &[&str]) -> Self {
+        Names(taken.iter().map(|name| name.to_string()).collect())
+    }
+
+    pub(crate) fn claim(&mut self, wanted: &str) -> String {
+        let mut name: String = wanted
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic())
+            || name.starts_with("WF_")
+            || name.starts_with("SF_")
+        {
+            name.insert(0, 'x');
+        }
+        while !self.0.insert(name.clone()) {
+            name.push('_');
+        }
+        name
+    }
+}
+
+/// Writes the workload of `cfg_program` as the TLA+ module `module`, noting
+/// the mixed cycles of `sc_graph` within `limits`.
+pub fn print_tla(
+    sc_graph: &SCGraph,
+    cfg_program: &CfgProgram,
+    module: &str,
+    limits: &CycleLimits,
+    writer: &mut impl Write,
+) -> io::Result<()> {
+    let spec = TlaWriter::new(cfg_program)
+        .spec(sc_graph, module, limits)
+        .map_err(io::Error::other)?;
+    writer.write_all(spec.as_bytes())
+}
+
+struct TlaWriter<'a> {
+    cfg: &'a CfgProgram,
+    names: Names,
+    /// The variable of each table
+    tables: Vec<(TableId, String)>,
+    /// The label of each field in the records of its table
+    fields: HashMap<FieldId, String>,
+    /// The sets of values the model needs
+    sets: BTreeSet<&'static str>,
+}
+
+impl<'a> TlaWriter<'a> {
+    fn new(cfg: &'a CfgProgram) -> Self {
+        let mut names = Names::new(TAKEN);
+        let tables = cfg
+            .root_tables
+            .iter()
+            .map(|&table| (table, names.claim(&cfg.tables[table].name)))
+            .collect();
+        let fields = cfg
+            .fields
+            .iter()
+            .map(|(id, field)| (id, Names::new(TAKEN).claim(&field.name)))
+            .collect();
+        Self {
+            cfg,
+            names,
+            tables,
+            fields,
+            sets: BTreeSet::new(),
+        }
+    }
+
+    fn spec(
+        mut self,
+        sc_graph: &SCGraph,
+        module: &str,
+        limits: &CycleLimits,
+    ) -> Result<String, String> {
+        // The definitions first, to learn which sets of values they need
+        let mut body = String::new();
+        for (table, name) in self.tables.clone() {
+            self.table(&mut body, table, &name);
+        }
+        let mut transactions = Vec::new();
+        for &function in &self.cfg.root_functions {
+            let function = &self.cfg.functions[function];
+            let name = self.names.claim(&function.name);
+            let operators = self.function(&mut body, function, &name)?;
+            transactions.push((name, operators));
+        }
+        self.transitions(&mut body, &transactions);
+
+        let mut out = format!("---- MODULE {} ----\n", module);
+        out.push_str("\\* The chopped workload, as FMitF_rs exported it\n");
+        out.push_str("EXTENDS Integers, Sequences, FiniteSets, TLC\n\n");
+        self.graph_notes(&mut out, sc_graph, limits);
+        let sets: Vec<&str> = self.sets.iter().copied().collect();
+        if !sets.is_empty() {
+            let _ = writeln!(out, "\\* The values of each type");
+            let _ = writeln!(out, "CONSTANTS {}\n", sets.join(", "));
+        }
+        if !self.tables.is_empty() {
+            let tables: Vec<&str> = self.tables.iter().map(|(_, name)| name.as_str()).collect();
+            let _ = writeln!(out, "\\* The records of each table, by primary key");
+            let _ = writeln!(out, "VARIABLES {}", tables.join(", "));
+        }
+        out.push_str("\\* The locals, next hop and result of each transaction, and where they\n");
+        out.push_str("\\* all started\n");
+        out.push_str("VARIABLES frames, initial\n\n");
+        out.push_str("\\* The next hop of a transaction that has ended\n");
+        out.push_str("Committed == 0\nAborted == -1\n\n");
+        out.push_str(&body);
+        out.push_str("\\* A model for TLC, in a .cfg file next to this one:\n");
+        if !sets.is_empty() {
+            let values: Vec<String> = sets
+                .iter()
+                .map(|&set| match set {
+                    "StringValues" => format!("{} = {{\"a\", \"b\"}}", set),
+                    _ => format!("{} = {{0, 1}}", set),
+                })
+                .collect();
+            let _ = writeln!(out, "\\*   CONSTANTS {}", values.join(" "));
+        }
+        out.push_str("\\*   SPECIFICATION Spec\n\\*   INVARIANT Serializability\n====\n");
+        Ok(out)
+    }
+
+    /// The C-edges and mixed cycles, as comments.
+    fn graph_notes(&self, out: &mut String, sc_graph: &SCGraph, limits: &CycleLimits) {
+        for edge in &sc_graph.edges {
+            if edge.edge_type == EdgeType::C {
+                let _ = writeln!(
+                    out,
+                    "\\* C-edge {} -- {}",
+                    sc_graph.hop_name(edge.source, self.cfg),
+                    sc_graph.hop_name(edge.target, self.cfg)
+                );
+            }
+        }
+        let cycles = sc_graph.find_mixed_cycles_within(limits);
+        for cycle in &cycles.cycles {
+            let _ = writeln!(
+                out,
+                "\\* Mixed cycle [{}] {}",
+                sc_graph.cycle_id(cycle, self.cfg),
+                sc_graph.describe_cycle(cycle, self.cfg)
+            );
+        }
+        if cycles.truncated {
+            out.push_str("\\* ... and more mixed cycles, past the limit\n");
+        } else if cycles.cycles.is_empty() {
+            out.push_str("\\* No mixed cycles, so every run should be serializable\n");
+        }
+        out.push('\n');
+    }
+
+    /// The keys and records a table may hold.
+    fn table(&mut self, out: &mut String, table: TableId, name: &str) {
+        let cfg = self.cfg;
+        let info = &cfg.tables[table];
+        let keys: Vec<&str> = info
+            .primary_keys
+            .iter()
+            .map(|&key| self.set(&cfg.fields[key].ty))
+            .collect();
+        let keys = match keys.as_slice() {
+            [key] => format!("{{<<k1>> : k1 \\in {}}}", key),
+            _ => keys.join(" \\X "),
+        };
+        let mut rows = Vec::new();
+        for &field in &info.fields {
+            if !cfg.fields[field].is_primary {
+                let set = self.set(&cfg.fields[field].ty);
+                rows.push(format!("{} : {}", self.fields[&field], set));
+            }
+        }
+        let rows = if rows.is_empty() {
+            "{[f \\in {} |-> 0]}".to_string()
+        } else {
+            format!("[{}]", rows.join(", "))
+        };
+        let key_names: Vec<&str> = info
+            .primary_keys
+            .iter()
+            .map(|&key| cfg.fields[key].name.as_str())
+            .collect();
+        let _ = writeln!(
+            out,
+            "\\* Table {} on node {}, keyed by ({})",
+            info.name,
+            cfg.nodes[info.node_id].name,
+            key_names.join(", ")
+        );
+        let _ = writeln!(out, "Keys_{} == {}", name, keys);
+        let _ = writeln!(out, "Rows_{} == {}\n", name, rows);
+    }
+
+    /// The set of values a type takes in the model.
+    fn set(&mut self, ty: &TypeName) -> &'static str {
+        let set = match ty {
+            TypeName::Int => "IntValues",
+            TypeName::Float => "FloatValues",
+            TypeName::String => "StringValues",
+            TypeName::Bool => return "BOOLEAN",
+        };
+        self.sets.insert(set);
+        set
+    }
+
+    /// Defines the blocks of a function, its hops and the frames it starts
+    /// from, returning the names of the last two.
+    fn function(
+        &mut self,
+        out: &mut String,
+        function: &FunctionCfg,
+        name: &str,
+    ) -> Result<(String, String), String> {
+        let mut local_names = Names::new(TAKEN);
+        let locals: Vec<String> = function
+            .variables
+            .iter()
+            .map(|(_, var)| local_names.claim(&var.name))
+            .collect();
+        let mut blocks = HashMap::new();
+        for &hop in &function.hop_order {
+            for &block in &function.hops[hop].blocks {
+                let operator = self.names.claim(&format!("{}_bb{}", name, block.index()));
+                blocks.insert(block, operator);
+            }
+        }
+        let block = BlockWriter {
+            cfg: self.cfg,
+            function,
+            locals: &locals,
+            blocks: &blocks,
+            tables: &self.tables,
+            fields: &self.fields,
+        };
+
+        let params: Vec<&str> = function
+            .parameters
+            .iter()
+            .map(|&param| function.variables[param].name.as_str())
+            .collect();
+        let hops: Vec<String> = function
+            .hop_order
+            .iter()
+            .enumerate()
+            .map(|(index, &hop)| {
+                let node = &self.cfg.nodes[function.hops[hop].node_id].name;
+                format!("hop {} on {}", index + 1, node)
+            })
+            .collect();
+        let _ = writeln!(
+            out,
+            "\\* Transaction {}({}): {}",
+            function.name,
+            params.join(", "),
+            hops.join(", ")
+        );
+
+        let mut ordered: Vec<&BasicBlockId> = blocks.keys().collect();
+        ordered.sort();
+        if !ordered.is_empty() {
+            let declared: Vec<String> = ordered
+                .iter()
+                .map(|block| format!("{}(_)", blocks[*block]))
+                .collect();
+            let _ = writeln!(out, "RECURSIVE {}", declared.join(", "));
+        }
+        for &&id in &ordered {
+            block.define(out, id)?;
+        }
+
+        let hop = self.names.claim(&format!("{}_hop", name));
+        let arms = function
+            .hop_order
+            .iter()
+            .enumerate()
+            .map(|(index, &hop)| {
+                let entry = function.hops[hop]
+                    .entry_block
+                    .ok_or_else(|| format!("Hop {} of {} has no entry block", index + 1, name))?;
+                Ok(format!("s.next = {} -> {}(s)", index + 1, blocks[&entry]))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if arms.is_empty() {
+            let _ = writeln!(out, "{}(s) == s\n", hop);
+        } else {
+            let _ = writeln!(
+                out,
+                "{}(s) ==\n    CASE {}\n",
+                hop,
+                arms.join("\n      [] ")
+            );
+        }
+
+        let start = self.names.claim(&format!("{}_start", name));
+        let mut initial = Vec::new();
+        for (id, var) in function.variables.iter() {
+            let values = if var.is_parameter {
+                self.set(&var.ty).to_string()
+            } else {
+                format!("{{{}}}", default_value(&var.ty))
+            };
+            initial.push(format!("{} : {}", locals[id.index()], values));
+        }
+        let initial = if initial.is_empty() {
+            "{[f \\in {} |-> 0]}".to_string()
+        } else {
+            format!("[{}]", initial.join(", "))
+        };
+        let next = if function.hop_order.is_empty() {
+            "Committed"
+        } else {
+            "1"
+        };
+        let _ = writeln!(
+            out,
+            "{} == [l : {}, next : {{{}}}, result : {{{{}}}}]\n",
+            start, initial, next
+        );
+        Ok((hop, start))
+    }
+
+    /// The steps of the transactions, their serial runs and `Serializability`.
+    fn transitions(&self, out: &mut String, transactions: &[(String, (String, String))]) {
+        let names: Vec<String> = transactions
+            .iter()
+            .map(|(name, _)| format!("\"{}\"", name))
+            .collect();
+        let _ = writeln!(out, "Txns == {{{}}}\n", names.join(", "));
+
+        let tables: Vec<String> = self
+            .tables
+            .iter()
+            .map(|(_, name)| format!("{} |-> {}", name, name))
+            .collect();
+        if tables.is_empty() {
+            out.push_str("Tables == [f \\in {} |-> 0]\n");
+            out.push_str("Store(db) == TRUE\n\n");
+        } else {
+            let _ = writeln!(out, "Tables == [{}]", tables.join(", "));
+            let stores: Vec<String> = self
+                .tables
+                .iter()
+                .map(|(_, name)| format!("/\\ {}' = db.{}", name, name))
+                .collect();
+            let _ = writeln!(out, "Store(db) ==\n    {}\n", stores.join("\n    "));
+        }
+
+        let arms: Vec<String> = transactions
+            .iter()
+            .map(|(name, (hop, _))| format!("t = \"{}\" -> {}(s)", name, hop))
+            .collect();
+        if arms.is_empty() {
+            out.push_str("StepOf(t, s) == s\n\n");
+        } else {
+            let _ = writeln!(
+                out,
+                "StepOf(t, s) ==\n    CASE {}\n",
+                arms.join("\n      [] ")
+            );
+        }
+        out.push_str(
+            "\\* The next hop of transaction t from state s, rolled back if it aborts
+Step(t, s) ==
+    LET r == StepOf(t, s)
+    IN IF r.next = Aborted THEN [r EXCEPT !.db = s.db] ELSE r
+
+Running(t) == frames[t].next > 0
+
+\\* The next hop of transaction t, as one atomic step
+Hop(t) ==
+    /\\ Running(t)
+    /\\ LET f == frames[t]
+           s == Step(t, [db |-> Tables, l |-> f.l, next |-> f.next, result |-> f.result])
+       IN /\\ Store(s.db)
+          /\\ frames' = [frames EXCEPT ![t] = [l |-> s.l, next |-> s.next, result |-> s.result]]
+    /\\ UNCHANGED initial
+
+",
+        );
+
+        out.push_str("Init ==\n");
+        for (_, name) in &self.tables {
+            let _ = writeln!(
+                out,
+                "    /\\ {} \\in [Keys_{} -> Rows_{}]",
+                name, name, name
+            );
+        }
+        let frames: Vec<String> = transactions
+            .iter()
+            .map(|(name, (_, start))| format!("{} : {}", name, start))
+            .collect();
+        if frames.is_empty() {
+            out.push_str("    /\\ frames = [t \\in Txns |-> 0]\n");
+        } else {
+            let _ = writeln!(out, "    /\\ frames \\in [{}]", frames.join(", "));
+        }
+        out.push_str("    /\\ initial = [db |-> Tables, frames |-> frames]\n\n");
+
+        let mut vars: Vec<&str> = self.tables.iter().map(|(_, name)| name.as_str()).collect();
+        vars.extend(["frames", "initial"]);
+        let _ = writeln!(out, "vars == <<{}>>\n", vars.join(", "));
+        out.push_str(
+            "Done == \\A t \\in Txns : ~Running(t)
+
+Next ==
+    \\/ \\E t \\in Txns : Hop(t)
+    \\/ Done /\\ UNCHANGED vars
+
+Spec == Init /\\ [][Next]_vars
+
+\\* Runs transaction t from state s to its end
+RECURSIVE Run(_, _)
+Run(t, s) == IF s.next > 0 THEN Run(t, Step(t, s)) ELSE s
+
+\\* Runs the transactions of order one after another, from position i
+RECURSIVE Serial(_, _, _)
+Serial(order, i, st) ==
+    IF i > Len(order) THEN st
+    ELSE LET t == order[i]
+             f == initial.frames[t]
+             s == Run(t, [db |-> st.db, l |-> f.l, next |-> f.next, result |-> f.result])
+         IN Serial(order, i + 1,
+                   [db |-> s.db,
+                    outcomes |-> [st.outcomes EXCEPT ![t] = [next |-> s.next, result |-> s.result]]])
+
+Outcomes == [t \\in Txns |-> [next |-> frames[t].next, result |-> frames[t].result]]
+
+Orders == {o \\in [1..Cardinality(Txns) -> Txns] : \\A i, j \\in DOMAIN o : i # j => o[i] # o[j]}
+
+\\* Once every transaction has ended, the tables and the outcomes are those of
+\\* running the transactions in some order, one at a time
+Serializability ==
+    Done => \\E o \\in Orders :
+                Serial(o, 1, [db |-> initial.db, outcomes |-> Outcomes])
+                    = [db |-> Tables, outcomes |-> Outcomes]
+
+",
+        );
+    }
+}
+
+/// Writes the operators of the blocks of one function.
+struct BlockWriter<'a> {
+    cfg: &'a CfgProgram,
+    function: &'a FunctionCfg,
+    /// The label of each local, by its index in the function's arena
+    locals: &'a [String],
+    blocks: &'a HashMap<BasicBlockId, String>,
+    tables: &'a [(TableId, String)],
+    fields: &'a HashMap<FieldId, String>,
+}
+
+impl BlockWriter<'_> {
+    /// A block as an operator from the state it starts in to the state its hop
+    /// ends in: its statements in a `LET`, then its terminator.
+    fn define(&self, out: &mut String, id: BasicBlockId) -> Result<(), String> {
+        let block = &self.function.blocks[id];
+        let mut states = Vec::new();
+        let mut state = "s".to_string();
+        for (index, statement) in block.statements.iter().enumerate() {
+            let next = format!("s{}", index + 1);
+            states.push(format!(
+                "{} == {}",
+                next,
+                self.statement(statement, &state)?
+            ));
+            state = next;
+        }
+        let end = self.terminator(&block.terminator, &state)?;
+        let _ = write!(out, "{}(s) ==\n    ", self.blocks[&id]);
+        if states.is_empty() {
+            let _ = writeln!(out, "{}\n", end);
+        } else {
+            let _ = writeln!(out, "LET {}\n    IN {}\n", states.join("\n        "), end);
+        }
+        Ok(())
+    }
+
+    fn statement(&self, statement: &Statement, state: &str) -> Result<String, String> {
+        match statement {
+            Statement::Assign { var, rvalue, .. } => Ok(format!(
+                "[{} EXCEPT !.l.{} = {}]",
+                state,
+                self.locals[var.index()],
+                self.rvalue(rvalue, state)?
+            )),
+            Statement::TableAssign {
+                table,
+                pk_fields,
+                pk_values,
+                field,
+                value,
+                ..
+            } => Ok(format!(
+                "[{} EXCEPT !.db.{}[{}].{} = {}]",
+                state,
+                self.table(*table),
+                self.key(*table, pk_fields, pk_values, state)?,
+                self.fields[field],
+                self.operand(value, state)?
+            )),
+        }
+    }
+
+    fn terminator(&self, terminator: &Terminator, state: &str) -> Result<String, String> {
+        let block = |id: &BasicBlockId| {
+            self.blocks
+                .get(id)
+                .map(|operator| format!("{}({})", operator, state))
+                .ok_or_else(|| {
+                    format!(
+                        "Block bb{} of {} is in no hop",
+                        id.index(),
+                        self.function.name
+                    )
+                })
+        };
+        Ok(match terminator {
+            Terminator::Goto(target) => block(target)?,
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => format!(
+                "IF {} THEN {} ELSE {}",
+                self.operand(condition, state)?,
+                block(then_block)?,
+                block(else_block)?
+            ),
+            Terminator::Return(None) | Terminator::HopExit { next_hop: None } => {
+                format!("[{} EXCEPT !.next = Committed]", state)
+            }
+            Terminator::Return(Some(value)) => format!(
+                "[{} EXCEPT !.next = Committed, !.result = {{{}}}]",
+                state,
+                self.operand(value, state)?
+            ),
+            Terminator::Abort => format!("[{} EXCEPT !.next = Aborted]", state),
+            Terminator::HopExit {
+                next_hop: Some(hop),
+            } => {
+                let position = self
+                    .function
+                    .hop_order
+                    .iter()
+                    .position(|other| other == hop)
+                    .ok_or_else(|| {
+                        format!("A hop of {} is not in its order", self.function.name)
+                    })?;
+                format!("[{} EXCEPT !.next = {}]", state, position + 1)
+            }
+            Terminator::BoundExceeded(_) => {
+                "Assert(FALSE, \"a loop ran past its unroll bound\")".to_string()
+            }
+        })
+    }
+
+    fn rvalue(&self, rvalue: &Rvalue, state: &str) -> Result<String, String> {
+        match rvalue {
+            Rvalue::Use(operand) => self.operand(operand, state),
+            Rvalue::TableAccess {
+                table,
+                pk_fields,
+                pk_values,
+                field,
+            } => {
+                if let Some(index) = pk_fields.iter().position(|key| key == field) {
+                    return self.operand(&pk_values[index], state);
+                }
+                Ok(format!(
+                    "{}.db.{}[{}].{}",
+                    state,
+                    self.table(*table),
+                    self.key(*table, pk_fields, pk_values, state)?,
+                    self.fields[field]
+                ))
+            }
+            Rvalue::UnaryOp { op, operand } => {
+                let operand = self.operand(operand, state)?;
+                match op {
+                    UnaryOp::Neg => Ok(format!("(-{})", operand)),
+                    UnaryOp::Not => Ok(format!("(~{})", operand)),
+                    UnaryOp::FoldCase => {
+                        Err("TLA+ cannot compare strings without case".to_string())
+                    }
+                }
+            }
+            Rvalue::BinaryOp { op, left, right } => {
+                let ordering = matches!(
+                    op,
+                    BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte
+                );
+                if ordering && self.operand_type(left) == TypeName::String {
+                    return Err(format!(
+                        "{} orders strings, which TLA+ cannot",
+                        self.function.name
+                    ));
+                }
+                let op = match op {
+                    BinaryOp::Add => "+",
+                    BinaryOp::Sub => "-",
+                    BinaryOp::Mul => "*",
+                    BinaryOp::Div => "\\div",
+                    BinaryOp::Lt => "<",
+                    BinaryOp::Lte => "<=",
+                    BinaryOp::Gt => ">",
+                    BinaryOp::Gte => ">=",
+                    BinaryOp::Eq => "=",
+                    BinaryOp::Neq => "#",
+                    BinaryOp::And => "/\\",
+                    BinaryOp::Or => "\\/",
+                };
+                Ok(format!(
+                    "({} {} {})",
+                    self.operand(left, state)?,
+                    op,
+                    self.operand(right, state)?
+                ))
+            }
+        }
+    }
+
+    fn operand(&self, operand: &Operand, state: &str) -> Result<String, String> {
+        match operand {
+            Operand::Var(var) => Ok(format!("{}.l.{}", state, self.locals[var.index()])),
+            Operand::Const(constant) => constant_value(constant),
+        }
+    }
+
+    fn operand_type(&self, operand: &Operand) -> TypeName {
+        match operand {
+            Operand::Var(var) => self.function.variables[*var].ty.clone(),
+            Operand::Const(Constant::Int(_)) => TypeName::Int,
+            Operand::Const(Constant::Float(_)) => TypeName::Float,
+            Operand::Const(Constant::Bool(_)) => TypeName::Bool,
+            Operand::Const(Constant::String(_)) => TypeName::String,
+        }
+    }
+
+    fn table(&self, table: TableId) -> &str {
+        self.tables
+            .iter()
+            .find(|(other, _)| *other == table)
+            .map_or("", |(_, name)| name.as_str())
+    }
+
+    /// The primary key of an access, as a tuple in the order of the table's
+    /// primary key fields.
+    fn key(
+        &self,
+        table: TableId,
+        pk_fields: &[FieldId],
+        pk_values: &[Operand],
+        state: &str,
+    ) -> Result<String, String> {
+        let values = self.cfg.tables[table]
+            .primary_keys
+            .iter()
+            .map(|key| {
+                let index = pk_fields
+                    .iter()
+                    .position(|field| field == key)
+                    .ok_or_else(|| {
+                        format!(
+                            "An access to {} in {} misses a primary key field",
+                            self.cfg.tables[table].name, self.function.name
+                        )
+                    })?;
+                self.operand(&pk_values[index], state)
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(format!("<<{}>>", values.join(", ")))
+    }
+}
+
+/// The value a local of a type starts with.
+fn default_value(ty: &TypeName) -> &'static str {
+    match ty {
+        TypeName::Int | TypeName::Float => "0",
+        TypeName::String => "\"\"",
+        TypeName::Bool => "FALSE",
+    }
+}
+
+fn constant_value(constant: &Constant) -> Result<String, String> {
+    match constant {
+        Constant::Int(value) if *value < 0 => Ok(format!("({})", value)),
+        Constant::Int(value) => Ok(value.to_string()),
+        Constant::Float(value) if value.fract() == 0.0 && value.abs() < 1e15 => {
+            constant_value(&Constant::Int(value.into_inner() as i64))
+        }
+        Constant::Float(value) => Err(format!("TLA+ has no real numbers, such as {}", value)),
+        Constant::Bool(value) => Ok(if *value { "TRUE" } else { "FALSE" }.to_string()),
+        Constant::String(value) => {
+            let mut quoted = String::from("\"");
+            for c in value.chars() {
+                match c {
+                    '"' => quoted.push_str("\\\""),
+                    '\\' => quoted.push_str("\\\\"),
+                    '\n' => quoted.push_str("\\n"),
+                    '\t' => quoted.push_str("\\t"),
+                    '\r' => quoted.push_str("\\r"),
+                    _ => quoted.push(c),
+                }
+            }
+            quoted.push('"');
+            Ok(quoted)
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! Differential testing - run chopped transactions against serial runs
+//!
+//! An executable check of a chopping, beside the SC-graph and the verifier
+//! that judge it statically. The schedule generator of the SC-graph gives an
+//! interleaving of the hops of each mixed cycle that realizes the cycle, and
+//! the serial schedules of the cycle's functions. Each trial takes one such
+//! schedule, in turn, and calls each function on it once, with arguments and
+//! records drawn as equivalence checking draws them; the arguments of a call
+//! often repeat those of earlier calls, so the calls touch the same records.
+//! It runs the calls as the schedule interleaves them, each hop committing
+//! when it exits as the runtime runs it, then runs them in every serial order
+//! from the same records. A chopped run that ends as no serial order does, in
+//! how a call ends or in what the tables hold, is a divergence: a schedule the
+//! chopping lets through that no serial run reproduces.
+//!
+//! Each call may run `max_steps` blocks, so a loop that never ends on the
+//! drawn arguments stops the run instead of the check. A trial whose chopped
+//! run, or a serial run it could have matched, runs out of steps proves
+//! nothing either way and is counted as inconclusive.
+//!
+//! The seed is fixed, so results are reproducible. Only the schedules of mixed
+//! cycles are run, so a program without them has no trials; a cycle that shows
+//! no divergence may still be harmful, as finding none is evidence, not a
+//! proof. The serial orders tried grow as the factorial of the functions on a
+//! cycle.
+//!
+//! ```
+//! use FMitF_rs::runtime::{check_chopping, DifferentialCheck, DifferentialOptions};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let build = |source: &str| {
+//!     let program = parse_and_analyze(source).unwrap();
+//!     CfgBuilder::build_from_program(&program).unwrap().program
+//! };
+//! let schema = "nodes { A, B } table T on A { primary int id; int n; } \
+//!     table U on B { primary int id; int n; }";
+//! let options = DifferentialOptions::default();
+//!
+//! // g may read T.n before f writes it, yet write U.n after f
+//! let chopped = build(&format!(
+//!     "{schema}
+//!     void f() {{ hop on A {{ T[id: 1].n = 1; }} hop on B {{ U[id: 1].n = 1; }} }}
+//!     int g() {{ hop on A {{ int t = T[id: 1].n; }} hop on B {{ U[id: 1].n = 2; return t; }} }}"
+//! ));
+//! let DifferentialCheck::Diverged(divergence) = check_chopping(chopped, &options).unwrap() else {
+//!     panic!("expected a divergence");
+//! };
+//! println!("{}", divergence);
+//!
+//! assert_eq!(divergence.schedule.to_string(), "g:1 -> f:1 -> f:2 -> g:2");
+//!
+//! // With a hop each there is no mixed cycle to run
+//! let whole = build(&format!(
+//!     "{schema}
+//!     void f() {{ hop on A {{ T[id: 1].n = 1; }} }}
+//!     int g() {{ hop on A {{ int t = T[id: 1].n; T[id: 1].n = 2; return t; }} }}"
+//! ));
+//! assert_eq!(
+//!     check_chopping(whole, &options).unwrap(),
+//!     DifferentialCheck::NoDivergence { trials: 0, inconclusive: 0 }
+//! );
+//!
+//! // A loop that never ends runs out of steps, in every trial
+//! let endless = build(&format!(
+//!     "{schema}
+//!     void f(int x) {{
+//!         hop on A {{ T[id: 1].n = 1; }}
+//!         hop on B {{ while (x == x) {{ x = x + 1; }} U[id: 1].n = 1; }}
+//!     }}
+//!     int g() {{ hop on A {{ int t = T[id: 1].n; }} hop on B {{ U[id: 1].n = 2; return t; }} }}"
+//! ));
+//! let options = DifferentialOptions { trials: 4, max_steps: 100, ..options };
+//! assert_eq!(
+//!     check_chopping(endless, &options).unwrap(),
+//!     DifferentialCheck::NoDivergence { trials: 4, inconclusive: 4 }
+//! );
+//! ```
+
+use super::equivalence::{
+    format_snapshot, load_snapshot, seed_tables, table_schema, take_snapshot, trial_outcome,
+    value_type, Candidates,
+};
+use super::executor::Transaction;
+use super::{RuntimeError, RuntimeState, RuntimeValue, TableSnapshot, TrialOutcome};
+use crate::cfg::{CfgProgram, FunctionId};
+use crate::generator::XorShift;
+use crate::sc_graph::{CycleSchedule, SCGraph};
+use std::fmt;
+
+/// How many trials to run and where the choices start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DifferentialOptions {
+    pub trials: usize,
+    pub seed: u64,
+    /// Records seeded per table before each trial
+    pub records_per_table: usize,
+    /// Blocks each call may run before its trial is inconclusive
+    pub max_steps: usize,
+}
+
+impl Default for DifferentialOptions {
+    fn default() -> Self {
+        Self {
+            trials: 256,
+            seed: 0x5eed,
+            records_per_table: 4,
+            max_steps: 10_000,
+        }
+    }
+}
+
+/// What running the trials found
+#[derive(Debug, Clone, PartialEq)]
+pub enum DifferentialCheck {
+    /// Every chopped run ended as some serial order does, except in the
+    /// `inconclusive` trials, where a call ran out of steps
+    NoDivergence { trials: usize, inconclusive: usize },
+    /// A chopped run that no serial order reproduces
+    Diverged(Box<Divergence>),
+}
+
+/// How the calls of a run ended, in the order the schedule first runs them,
+/// and what the tables held after them
+pub type RunEnd = (Vec<TrialOutcome>, TableSnapshot);
+
+/// A chopped run that ends as no serial run of its calls does
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// The functions called, each once, with their arguments
+    pub calls: Vec<(String, Vec<RuntimeValue>)>,
+    /// The schedule of the mixed cycle the calls ran in
+    pub schedule: CycleSchedule,
+    /// The records the calls started from
+    pub initial: TableSnapshot,
+    pub chopped: RunEnd,
+    /// Each serial schedule of the calls and how it ended
+    pub serial: Vec<(CycleSchedule, RunEnd)>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let calls: Vec<String> = self
+            .calls
+            .iter()
+            .map(|(function, args)| {
+                let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+                format!("{}({})", function, args.join(", "))
+            })
+            .collect();
+        writeln!(f, "{} as {}", calls.join(", "), self.schedule)?;
+        writeln!(f, "  starting from: {}", format_snapshot(&self.initial))?;
+        let end = |(outcomes, tables): &RunEnd| {
+            let outcomes: Vec<String> = outcomes
+                .iter()
+                .zip(&self.calls)
+                .map(|(outcome, (function, _))| format!("{} {}", function, outcome))
+                .collect();
+            format!(
+                "{}; leaving {}",
+                outcomes.join(", "),
+                format_snapshot(tables)
+            )
+        };
+        writeln!(f, "  chopped: {}", end(&self.chopped))?;
+        for (schedule, run) in &self.serial {
+            writeln!(
+                f,
+                "  serially as {}: {}",
+                schedule.functions().join(", "),
+                end(run)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the trials on `cfg`, stopping at the first divergence.
+pub fn check_chopping(
+    cfg: CfgProgram,
+    options: &DifferentialOptions,
+) -> Result<DifferentialCheck, String> {
+    let schema = table_schema(&cfg);
+    let candidates = Candidates::new(&[&cfg]);
+    let sc_graph = SCGraph::new(&cfg);
+    // The schedule of each mixed cycle that has one, with its serial schedules
+    // and the function of each call, in the order the schedule first runs them
+    let plans: Vec<(CycleSchedule, Vec<CycleSchedule>, Vec<FunctionId>)> = sc_graph
+        .find_mixed_cycles()
+        .iter()
+        .filter_map(|cycle| sc_graph.cycle_schedule(cycle, &cfg))
+        .map(|schedule| {
+            let serial = sc_graph.serial_schedules(&schedule, &cfg);
+            let mut functions = Vec::new();
+            for step in &schedule.steps {
+                let function = sc_graph.nodes[step.node].cfg_function_id;
+                if !functions.contains(&function) {
+                    functions.push(function);
+                }
+            }
+            (schedule, serial, functions)
+        })
+        .collect();
+    if plans.is_empty() {
+        return Ok(DifferentialCheck::NoDivergence {
+            trials: 0,
+            inconclusive: 0,
+        });
+    }
+
+    let mut state = RuntimeState::new();
+    state.load_cfg(cfg).map_err(|e| e.to_string())?;
+    let mut rng = XorShift(options.seed.max(1));
+    let mut inconclusive = 0;
+    for trial in 0..options.trials {
+        let (schedule, serial_schedules, functions) = &plans[trial % plans.len()];
+        // The arguments of a call often repeat those of earlier calls, so
+        // the calls touch the same records
+        let mut args: Vec<RuntimeValue> = Vec::new();
+        let mut calls: Vec<(FunctionId, Vec<RuntimeValue>)> = Vec::new();
+        for &func_id in functions {
+            let func = &state.cfg_program.as_ref().unwrap().functions[func_id];
+            let mut call_args = Vec::new();
+            for &param in &func.parameters {
+                let ty = &func.variables[param].ty;
+                let earlier: Vec<&RuntimeValue> =
+                    args.iter().filter(|arg| value_type(arg) == *ty).collect();
+                let arg = if !earlier.is_empty() && rng.coin() {
+                    (*rng.choose(&earlier)).clone()
+                } else {
+                    rng.choose(candidates.of(ty)).clone()
+                };
+                call_args.push(arg);
+            }
+            args.extend(call_args.iter().cloned());
+            calls.push((func_id, call_args));
+        }
+        let initial = seed_tables(
+            &schema,
+            &args,
+            &candidates,
+            options.records_per_table,
+            &mut rng,
+        );
+
+        let names: Vec<String> = {
+            let cfg = state.cfg_program.as_ref().unwrap();
+            functions
+                .iter()
+                .map(|&func_id| cfg.functions[func_id].name.clone())
+                .collect()
+        };
+        let run = |state: &mut RuntimeState, schedule: &CycleSchedule| {
+            run_schedule(state, &calls, &names, schedule, &initial, options.max_steps)
+        };
+        let Some(chopped) = run(&mut state, schedule)? else {
+            inconclusive += 1;
+            continue;
+        };
+        let mut serial = Vec::new();
+        let mut out_of_steps = false;
+        for serial_schedule in serial_schedules {
+            match run(&mut state, serial_schedule)? {
+                Some(run) if run == chopped => {
+                    serial.clear();
+                    out_of_steps = false;
+                    break;
+                }
+                Some(run) => serial.push((serial_schedule.clone(), run)),
+                None => out_of_steps = true,
+            }
+        }
+        if out_of_steps {
+            inconclusive += 1;
+        } else if !serial.is_empty() {
+            return Ok(DifferentialCheck::Diverged(Box::new(Divergence {
+                calls: names
+                    .into_iter()
+                    .zip(calls)
+                    .map(|(name, (_, args))| (name, args))
+                    .collect(),
+                schedule: schedule.clone(),
+                initial,
+                chopped,
+                serial,
+            })));
+        }
+    }
+    Ok(DifferentialCheck::NoDivergence {
+        trials: options.trials,
+        inconclusive,
+    })
+}
+
+/// Runs the calls, named by their functions, with their hops in the order of
+/// `schedule`, then each to its end in order; a call that fails to run stops
+/// there, its hop rolled back. `None` if a call ran more than `max_steps`
+/// blocks.
+fn run_schedule(
+    state: &mut RuntimeState,
+    calls: &[(FunctionId, Vec<RuntimeValue>)],
+    names: &[String],
+    schedule: &CycleSchedule,
+    initial: &TableSnapshot,
+    max_steps: usize,
+) -> Result<Option<RunEnd>, String> {
+    load_snapshot(state, initial);
+    let mut transactions = calls
+        .iter()
+        .map(|(func_id, args)| {
+            Transaction::start(state, *func_id, args.clone())
+                .map(|transaction| transaction.with_step_limit(max_steps))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let mut failures: Vec<Option<RuntimeError>> = (0..calls.len()).map(|_| None).collect();
+    let mut advance = |call: usize, hop: Option<usize>, state: &mut RuntimeState| {
+        let transaction = &mut transactions[call];
+        while failures[call].is_none()
+            && transaction.outcome().is_none()
+            && hop.is_none_or(|hop| transaction.hops_run() < hop)
+        {
+            if let Err(error) = transaction.run_hop(state) {
+                failures[call] = Some(error);
+            }
+        }
+    };
+    for step in &schedule.steps {
+        let call = names
+            .iter()
+            .position(|name| *name == step.function)
+            .ok_or_else(|| format!("No call of '{}' in the schedule", step.function))?;
+        advance(call, Some(step.hop), state);
+    }
+    for call in 0..calls.len() {
+        advance(call, None, state);
+    }
+
+    if failures
+        .iter()
+        .any(|failure| matches!(failure, Some(RuntimeError::StepLimit(_))))
+    {
+        return Ok(None);
+    }
+    let outcomes = transactions
+        .iter()
+        .zip(failures)
+        .map(|(transaction, failure)| match failure {
+            Some(error) => TrialOutcome::Failed(error.to_string()),
+            None => trial_outcome(Ok(transaction.outcome().cloned().expect("the call ended"))),
+        })
+        .collect();
+    Ok(Some((outcomes, take_snapshot(state))))
+}
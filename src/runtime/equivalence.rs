@@ -0,0 +1,537 @@
+//! Equivalence checking - run two versions of a program side by side
+//!
+//! Validates a refactoring of a program, such as merging two hops or reordering
+//! statements, by calling each function of both versions with the same arguments
+//! on the same tables and comparing how the calls end: whether they commit, what
+//! they return and what the tables hold afterwards. Tables are compared as a
+//! later read would see them, so a record only holding default values is the
+//! same as no record.
+//!
+//! Arguments and records are drawn from small values and the constants of the
+//! programs, with a fixed seed so results are reproducible. Finding no
+//! difference is evidence, not a proof.
+//!
+//! ```
+//! use FMitF_rs::runtime::{check_equivalence, EquivalenceCheck, EquivalenceOptions};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let build = |source: &str| {
+//!     let program = parse_and_analyze(source).unwrap();
+//!     CfgBuilder::build_from_program(&program).unwrap().program
+//! };
+//! let schema = "nodes { A } table T on A { primary int id; int n; int m; }";
+//! let split = || {
+//!     build(&format!(
+//!         "{schema} void f(int id) {{
+//!             hop on A {{ T[id: id].n = T[id: id].n + 1; }}
+//!             hop on A {{ T[id: id].m = T[id: id].n; }}
+//!         }}"
+//!     ))
+//! };
+//! let merged = build(&format!(
+//!     "{schema} void f(int id) {{
+//!         hop on A {{ T[id: id].n = T[id: id].n + 1; T[id: id].m = T[id: id].n; }}
+//!     }}"
+//! ));
+//! let swapped = build(&format!(
+//!     "{schema} void f(int id) {{
+//!         hop on A {{ T[id: id].m = T[id: id].n; T[id: id].n = T[id: id].n + 1; }}
+//!     }}"
+//! ));
+//!
+//! // Merging the hops keeps the effects
+//! let options = EquivalenceOptions::default();
+//! let results = check_equivalence(split(), merged, &options).unwrap();
+//! assert!(matches!(results[0].1, EquivalenceCheck::NoDifference { .. }));
+//!
+//! // Swapping the statements does not
+//! let results = check_equivalence(split(), swapped, &options).unwrap();
+//! let EquivalenceCheck::Different(counterexample) = &results[0].1 else {
+//!     panic!("expected a difference");
+//! };
+//! println!("{}", counterexample);
+//! ```
+
+use super::{run_function, ExecutionOutcome, RuntimeError, RuntimeState, RuntimeValue};
+use crate::ast::TypeName;
+use crate::cfg::{CfgProgram, Constant, FunctionCfg, Operand, Rvalue, Statement, Terminator};
+use crate::generator::XorShift;
+use ordered_float::OrderedFloat;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Records before or after a call: table name → primary key → field name →
+/// value, leaving out primary key fields
+pub type TableSnapshot =
+    BTreeMap<String, BTreeMap<Vec<RuntimeValue>, BTreeMap<String, RuntimeValue>>>;
+
+/// How many calls to try per function, and where the value choices start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EquivalenceOptions {
+    pub trials: usize,
+    pub seed: u64,
+    /// Records seeded per table before each call
+    pub records_per_table: usize,
+}
+
+impl Default for EquivalenceOptions {
+    fn default() -> Self {
+        Self {
+            trials: 256,
+            seed: 0x5eed,
+            records_per_table: 4,
+        }
+    }
+}
+
+/// What comparing the two versions of a function found
+#[derive(Debug, Clone, PartialEq)]
+pub enum EquivalenceCheck {
+    /// Every call tried ended the same way in both versions
+    NoDifference { trials: usize },
+    /// A call on which the versions end differently
+    Different(Box<Counterexample>),
+    /// The function is missing from one version, or takes other parameters
+    Incomparable(String),
+}
+
+/// How one call ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrialOutcome {
+    Committed(Option<RuntimeValue>),
+    /// Aborted in some hop; earlier hops stay committed, as the tables show
+    Aborted,
+    /// Failed with a runtime error, such as a division by zero
+    Failed(String),
+}
+
+impl fmt::Display for TrialOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrialOutcome::Committed(Some(value)) => write!(f, "committed, returned {}", value),
+            TrialOutcome::Committed(None) => write!(f, "committed"),
+            TrialOutcome::Aborted => write!(f, "aborted"),
+            TrialOutcome::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// A call that tells the two versions of a function apart
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    pub function: String,
+    pub args: Vec<RuntimeValue>,
+    /// The records the call started from
+    pub initial: TableSnapshot,
+    pub before: (TrialOutcome, TableSnapshot),
+    pub after: (TrialOutcome, TableSnapshot),
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let args: Vec<String> = self.args.iter().map(ToString::to_string).collect();
+        writeln!(f, "{}({})", self.function, args.join(", "))?;
+        writeln!(f, "  starting from: {}", format_snapshot(&self.initial))?;
+        for (version, (outcome, tables)) in [("before", &self.before), ("after", &self.after)] {
+            writeln!(
+                f,
+                "  {}: {}, leaving {}",
+                version,
+                outcome,
+                format_snapshot(tables)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn format_snapshot(snapshot: &TableSnapshot) -> String {
+    let records: Vec<String> = snapshot
+        .iter()
+        .flat_map(|(table, records)| {
+            records.iter().map(move |(key, fields)| {
+                let key: Vec<String> = key.iter().map(ToString::to_string).collect();
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| format!("{}: {}", field, value))
+                    .collect();
+                format!("{}[{}] {{ {} }}", table, key.join(", "), fields.join(", "))
+            })
+        })
+        .collect();
+    if records.is_empty() {
+        "empty tables".to_string()
+    } else {
+        records.join(", ")
+    }
+}
+
+/// Compares every function of two versions of a program, in the order the
+/// first version declares them, followed by functions only the second has.
+/// Fails if the versions declare different tables or fields.
+pub fn check_equivalence(
+    before: CfgProgram,
+    after: CfgProgram,
+    options: &EquivalenceOptions,
+) -> Result<Vec<(String, EquivalenceCheck)>, String> {
+    let schema = table_schema(&before);
+    if schema != table_schema(&after) {
+        return Err("The programs declare different tables or fields".to_string());
+    }
+    let candidates = Candidates::new(&[&before, &after]);
+
+    // What to compare, decided before the programs move into the runtime: the
+    // parameter types of each function both have, or why it cannot be compared
+    let mut plan: Vec<(String, Result<Vec<TypeName>, String>)> = Vec::new();
+    for &func_id in &before.root_functions {
+        let func = &before.functions[func_id];
+        let types = match find_function(&after, &func.name) {
+            None => Err(format!(
+                "'{}' is missing from the second program",
+                func.name
+            )),
+            Some(other) if parameter_types(func) != parameter_types(other) => {
+                Err(format!("'{}' takes different parameters", func.name))
+            }
+            Some(_) => Ok(parameter_types(func)),
+        };
+        plan.push((func.name.clone(), types));
+    }
+    for &func_id in &after.root_functions {
+        let name = &after.functions[func_id].name;
+        if find_function(&before, name).is_none() {
+            plan.push((
+                name.clone(),
+                Err(format!("'{}' is missing from the first program", name)),
+            ));
+        }
+    }
+
+    let mut states = [RuntimeState::new(), RuntimeState::new()];
+    for (state, cfg) in states.iter_mut().zip([before, after]) {
+        state.load_cfg(cfg).map_err(|e| e.to_string())?;
+    }
+    Ok(plan
+        .into_iter()
+        .map(|(name, types)| {
+            let check = match types {
+                Ok(types) => {
+                    compare_function(&mut states, &name, &types, &schema, &candidates, options)
+                }
+                Err(reason) => EquivalenceCheck::Incomparable(reason),
+            };
+            (name, check)
+        })
+        .collect())
+}
+
+/// Tables by name, with their primary key fields and other fields by name
+pub(super) type Schema = BTreeMap<String, (Vec<(String, TypeName)>, Vec<(String, TypeName)>)>;
+
+pub(super) fn table_schema(cfg: &CfgProgram) -> Schema {
+    cfg.tables
+        .iter()
+        .map(|(_, table)| {
+            let (primary, fields): (Vec<_>, Vec<_>) = table
+                .fields
+                .iter()
+                .map(|&field| &cfg.fields[field])
+                .partition(|field| field.is_primary);
+            let named = |fields: Vec<&crate::cfg::FieldInfo>| {
+                fields
+                    .into_iter()
+                    .map(|field| (field.name.clone(), field.ty.clone()))
+                    .collect()
+            };
+            (table.name.clone(), (named(primary), named(fields)))
+        })
+        .collect()
+}
+
+fn find_function<'a>(cfg: &'a CfgProgram, name: &str) -> Option<&'a FunctionCfg> {
+    cfg.functions
+        .iter()
+        .map(|(_, func)| func)
+        .find(|func| func.name == name)
+}
+
+fn parameter_types(func: &FunctionCfg) -> Vec<TypeName> {
+    func.parameters
+        .iter()
+        .map(|&param| func.variables[param].ty.clone())
+        .collect()
+}
+
+fn compare_function(
+    states: &mut [RuntimeState; 2],
+    name: &str,
+    param_types: &[TypeName],
+    schema: &Schema,
+    candidates: &Candidates,
+    options: &EquivalenceOptions,
+) -> EquivalenceCheck {
+    let mut rng = XorShift(options.seed.max(1));
+    for _ in 0..options.trials {
+        let args: Vec<RuntimeValue> = param_types
+            .iter()
+            .map(|ty| rng.choose(candidates.of(ty)).clone())
+            .collect();
+        let initial = seed_tables(
+            schema,
+            &args,
+            candidates,
+            options.records_per_table,
+            &mut rng,
+        );
+        let [before, after] = states;
+        let end_before = run_trial(before, name, &args, &initial);
+        let end_after = run_trial(after, name, &args, &initial);
+        if end_before != end_after {
+            return EquivalenceCheck::Different(Box::new(Counterexample {
+                function: name.to_string(),
+                args,
+                initial,
+                before: end_before,
+                after: end_after,
+            }));
+        }
+    }
+    EquivalenceCheck::NoDifference {
+        trials: options.trials,
+    }
+}
+
+/// Random records, keyed mostly by the arguments so the call is likely to
+/// touch them
+pub(super) fn seed_tables(
+    schema: &Schema,
+    args: &[RuntimeValue],
+    candidates: &Candidates,
+    records_per_table: usize,
+    rng: &mut XorShift,
+) -> TableSnapshot {
+    let mut snapshot = TableSnapshot::new();
+    for (table, (primary, fields)) in schema {
+        let mut records = BTreeMap::new();
+        for _ in 0..records_per_table {
+            let key = primary
+                .iter()
+                .map(|(_, ty)| {
+                    let matching: Vec<&RuntimeValue> =
+                        args.iter().filter(|arg| value_type(arg) == *ty).collect();
+                    if !matching.is_empty() && rng.coin() {
+                        (*rng.choose(&matching)).clone()
+                    } else {
+                        rng.choose(candidates.of(ty)).clone()
+                    }
+                })
+                .collect();
+            let record = fields
+                .iter()
+                .map(|(field, ty)| (field.clone(), rng.choose(candidates.of(ty)).clone()))
+                .collect();
+            records.insert(key, record);
+        }
+        snapshot.insert(table.clone(), records);
+    }
+    normalize(schema, snapshot)
+}
+
+/// Runs a call on the given records, replacing whatever the tables held
+fn run_trial(
+    state: &mut RuntimeState,
+    name: &str,
+    args: &[RuntimeValue],
+    initial: &TableSnapshot,
+) -> (TrialOutcome, TableSnapshot) {
+    load_snapshot(state, initial);
+    let func_id = state.functions[name];
+    let outcome = trial_outcome(run_function(state, func_id, args.to_vec()));
+    (outcome, take_snapshot(state))
+}
+
+pub(super) fn trial_outcome(result: Result<ExecutionOutcome, RuntimeError>) -> TrialOutcome {
+    match result {
+        Ok(ExecutionOutcome::Committed(value)) => TrialOutcome::Committed(value),
+        Ok(ExecutionOutcome::Aborted { .. }) => TrialOutcome::Aborted,
+        Err(error) => TrialOutcome::Failed(error.to_string()),
+    }
+}
+
+/// Replaces whatever the tables hold with the records of a snapshot
+pub(super) fn load_snapshot(state: &mut RuntimeState, initial: &TableSnapshot) {
+    let cfg = state.cfg_program.as_ref().expect("the program is loaded");
+    let mut table_data: HashMap<_, HashMap<_, HashMap<_, _>>> = HashMap::new();
+    for (table_id, table) in cfg.tables.iter() {
+        let fields: HashMap<&str, _> = table
+            .fields
+            .iter()
+            .map(|&field| (cfg.fields[field].name.as_str(), field))
+            .collect();
+        let records = initial.get(&table.name).into_iter().flatten();
+        let records = records
+            .map(|(key, record)| {
+                let record = record
+                    .iter()
+                    .map(|(field, value)| (fields[field.as_str()], value.clone()))
+                    .collect();
+                (key.clone(), record)
+            })
+            .collect();
+        table_data.insert(table_id, records);
+    }
+    state.table_data = table_data;
+}
+
+/// The records the tables hold, as a later read would see them
+pub(super) fn take_snapshot(state: &RuntimeState) -> TableSnapshot {
+    let cfg = state.cfg_program.as_ref().expect("the program is loaded");
+    let mut snapshot = TableSnapshot::new();
+    for (table_id, records) in &state.table_data {
+        let records = records
+            .iter()
+            .map(|(key, record)| {
+                let record = record
+                    .iter()
+                    .map(|(field, value)| (cfg.fields[*field].name.clone(), value.clone()))
+                    .collect();
+                (key.clone(), record)
+            })
+            .collect();
+        snapshot.insert(cfg.tables[*table_id].name.clone(), records);
+    }
+    normalize(&table_schema(cfg), snapshot)
+}
+
+/// Fills in the defaults a read of a missing field sees, then drops the
+/// records holding nothing but defaults, since reads cannot tell them from
+/// missing records
+fn normalize(schema: &Schema, snapshot: TableSnapshot) -> TableSnapshot {
+    let mut normalized = TableSnapshot::new();
+    for (table, (_, fields)) in schema {
+        let mut records = snapshot.get(table).cloned().unwrap_or_default();
+        for record in records.values_mut() {
+            for (field, ty) in fields {
+                record
+                    .entry(field.clone())
+                    .or_insert_with(|| default_value(ty));
+            }
+        }
+        records.retain(|_, record| {
+            fields
+                .iter()
+                .any(|(field, ty)| record[field] != default_value(ty))
+        });
+        if !records.is_empty() {
+            normalized.insert(table.clone(), records);
+        }
+    }
+    normalized
+}
+
+fn default_value(ty: &TypeName) -> RuntimeValue {
+    match ty {
+        TypeName::Int => RuntimeValue::Int(0),
+        TypeName::Float => RuntimeValue::Float(OrderedFloat(0.0)),
+        TypeName::Bool => RuntimeValue::Bool(false),
+        TypeName::String => RuntimeValue::String(String::new()),
+    }
+}
+
+pub(super) fn value_type(value: &RuntimeValue) -> TypeName {
+    match value {
+        RuntimeValue::Int(_) => TypeName::Int,
+        RuntimeValue::Float(_) => TypeName::Float,
+        RuntimeValue::Bool(_) => TypeName::Bool,
+        RuntimeValue::String(_) => TypeName::String,
+    }
+}
+
+/// The values tried for each type: a few small ones, the constants of the
+/// programs and, for integers, their neighbours
+pub(super) struct Candidates {
+    by_type: Vec<(TypeName, Vec<RuntimeValue>)>,
+}
+
+impl Candidates {
+    pub(super) fn new(programs: &[&CfgProgram]) -> Self {
+        let mut values = vec![
+            RuntimeValue::Int(0),
+            RuntimeValue::Int(1),
+            RuntimeValue::Int(2),
+            RuntimeValue::Int(-1),
+            RuntimeValue::Float(OrderedFloat(0.0)),
+            RuntimeValue::Float(OrderedFloat(1.5)),
+            RuntimeValue::Bool(false),
+            RuntimeValue::Bool(true),
+            RuntimeValue::String(String::new()),
+            RuntimeValue::String("a".to_string()),
+        ];
+        for cfg in programs.iter() {
+            for (_, func) in cfg.functions.iter() {
+                for (_, block) in func.blocks.iter() {
+                    for operand in block_operands(&block.statements, &block.terminator) {
+                        let Operand::Const(constant) = operand else {
+                            continue;
+                        };
+                        match constant {
+                            Constant::Int(i) => values.extend(
+                                [i.checked_sub(1), Some(*i), i.checked_add(1)]
+                                    .into_iter()
+                                    .flatten()
+                                    .map(RuntimeValue::Int),
+                            ),
+                            Constant::Float(f) => values.push(RuntimeValue::Float(*f)),
+                            Constant::Bool(b) => values.push(RuntimeValue::Bool(*b)),
+                            Constant::String(s) => values.push(RuntimeValue::String(s.clone())),
+                        }
+                    }
+                }
+            }
+        }
+        let mut by_type: Vec<(TypeName, Vec<RuntimeValue>)> = Vec::new();
+        for value in values {
+            let ty = value_type(&value);
+            match by_type.iter_mut().find(|(other, _)| *other == ty) {
+                Some((_, values)) if !values.contains(&value) => values.push(value),
+                Some(_) => {}
+                None => by_type.push((ty, vec![value])),
+            }
+        }
+        Self { by_type }
+    }
+
+    pub(super) fn of(&self, ty: &TypeName) -> &[RuntimeValue] {
+        let (_, values) = self
+            .by_type
+            .iter()
+            .find(|(other, _)| other == ty)
+            .expect("every type has small candidates");
+        values
+    }
+}
+
+fn block_operands<'a>(statements: &'a [Statement], terminator: &'a Terminator) -> Vec<&'a Operand> {
+    let mut operands = Vec::new();
+    for stmt in statements {
+        match stmt {
+            Statement::Assign { rvalue, .. } => match rvalue {
+                Rvalue::Use(operand) | Rvalue::UnaryOp { operand, .. } => operands.push(operand),
+                Rvalue::BinaryOp { left, right, .. } => operands.extend([left, right]),
+                Rvalue::TableAccess { pk_values, .. } => operands.extend(pk_values),
+            },
+            Statement::TableAssign {
+                pk_values, value, ..
+            } => {
+                operands.extend(pk_values);
+                operands.push(value);
+            }
+        }
+    }
+    match terminator {
+        Terminator::Branch { condition, .. } => operands.push(condition),
+        Terminator::Return(Some(value)) => operands.push(value),
+        _ => {}
+    }
+    operands
+}
@@ -1,142 +1,284 @@
 //! Function Executor - CFG interpreter with proper control flow
 
-use super::{RuntimeError, RuntimeState, RuntimeValue};
-use crate::cfg::{BasicBlockId, BinaryOp, Constant, FunctionId, HopId, Operand, Rvalue, Statement, Terminator, UnaryOp};
+use super::{ExecutionOutcome, RuntimeError, RuntimeState, RuntimeValue};
+use crate::cfg::{
+    BasicBlockId, BinaryOp, Constant, FieldId, FunctionId, HopId, Operand, Rvalue, Statement,
+    TableId, Terminator, UnaryOp,
+};
 use std::collections::HashMap;
 
-/// Execute a function by properly following CFG control flow
-/// 
-/// This executor respects the CFG structure:
-/// 1. Starts from the entry hop
-/// 2. Follows terminators (branches, gotos, returns) properly
-/// 3. Evaluates conditions for branch decisions
-/// 4. Handles hop transitions correctly
-/// 5. Maintains proper execution state between blocks
+/// A record as it was before the current hop first wrote it, `None` if it did
+/// not exist yet
+type UndoEntry = (TableId, Vec<RuntimeValue>, Option<HashMap<FieldId, RuntimeValue>>);
+
+/// Execute a function, treating an abort as an error
 pub fn execute_function(
     state: &mut RuntimeState,
     func_id: FunctionId,
     args: Vec<RuntimeValue>,
 ) -> Result<Option<RuntimeValue>, RuntimeError> {
-    let cfg = state
-        .cfg_program
-        .as_ref()
-        .ok_or_else(|| RuntimeError::ExecutionError("No program loaded".to_string()))?;
-
-    let func = &cfg.functions[func_id];
-    
-    // Set up parameter bindings
-    let mut local_vars: HashMap<String, RuntimeValue> = HashMap::new();
-    for (i, &param_var_id) in func.parameters.iter().enumerate() {
-        if i < args.len() {
-            let param_name = &func.variables[param_var_id].name;
-            local_vars.insert(param_name.clone(), args[i].clone());
+    match run_function(state, func_id, args)? {
+        ExecutionOutcome::Committed(value) => Ok(value),
+        ExecutionOutcome::Aborted { .. } => {
+            Err(RuntimeError::ExecutionError("Function aborted".to_string()))
         }
     }
-
-    // Start execution from entry hop
-    let entry_hop = func.entry_hop.ok_or_else(|| {
-        RuntimeError::ExecutionError("Function has no entry hop".to_string())
-    })?;
-
-    execute_hop(state, func_id, entry_hop, &mut local_vars)
 }
 
-/// Execute a hop starting from its entry block
-fn execute_hop(
+/// Run a function as one transaction by following its CFG
+///
+/// The executor respects the CFG structure:
+/// 1. Starts from the entry hop
+/// 2. Follows terminators (branches, gotos, returns) properly
+/// 3. Evaluates conditions for branch decisions
+/// 4. Commits each hop when it exits to the next one
+/// 5. Rolls back the current hop on an abort or a runtime error
+///
+/// Hops that already committed stay committed, as they would on the real nodes.
+pub fn run_function(
     state: &mut RuntimeState,
     func_id: FunctionId,
-    hop_id: HopId,
-    local_vars: &mut HashMap<String, RuntimeValue>,
-) -> Result<Option<RuntimeValue>, RuntimeError> {
-    let cfg = state.cfg_program.as_ref().unwrap();
-    let func = &cfg.functions[func_id];
-    let hop = &func.hops[hop_id];
-
-    let entry_block = hop.entry_block.ok_or_else(|| {
-        RuntimeError::ExecutionError("Hop has no entry block".to_string())
-    })?;
-
-    execute_block(state, func_id, entry_block, local_vars)
+    args: Vec<RuntimeValue>,
+) -> Result<ExecutionOutcome, RuntimeError> {
+    let mut transaction = Transaction::start(state, func_id, args)?;
+    loop {
+        if let Some(outcome) = transaction.run_hop(state)? {
+            return Ok(outcome);
+        }
+    }
 }
 
-/// Execute a basic block and handle its terminator
-fn execute_block(
-    state: &mut RuntimeState,
+/// A transaction that runs one hop at a time, so that the hops of several
+/// transactions can interleave
+pub(super) struct Transaction {
     func_id: FunctionId,
+    local_vars: HashMap<String, RuntimeValue>,
+    /// The block to run next
     block_id: BasicBlockId,
-    local_vars: &mut HashMap<String, RuntimeValue>,
-) -> Result<Option<RuntimeValue>, RuntimeError> {
-    // Get the statements and terminator first to avoid borrowing issues
-    let (statements, terminator) = {
-        let cfg = state.cfg_program.as_ref().unwrap();
+    /// The hop `block_id` belongs to, counting from 0
+    hop_index: usize,
+    /// How the transaction ended, once it has
+    outcome: Option<ExecutionOutcome>,
+    /// The most blocks it may run and how many it has run, if it is limited
+    steps: Option<(usize, usize)>,
+}
+
+impl Transaction {
+    /// Bind the arguments and stand at the entry hop
+    pub(super) fn start(
+        state: &RuntimeState,
+        func_id: FunctionId,
+        args: Vec<RuntimeValue>,
+    ) -> Result<Self, RuntimeError> {
+        let cfg = state
+            .cfg_program
+            .as_ref()
+            .ok_or_else(|| RuntimeError::ExecutionError("No program loaded".to_string()))?;
+
         let func = &cfg.functions[func_id];
-        let block = &func.blocks[block_id];
-        (block.statements.clone(), block.terminator.clone())
-    };
+        if args.len() != func.parameters.len() {
+            return Err(RuntimeError::ExecutionError(format!(
+                "Function '{}' expects {} arguments, got {}",
+                func.name,
+                func.parameters.len(),
+                args.len()
+            )));
+        }
+
+        // Set up parameter bindings
+        let mut local_vars: HashMap<String, RuntimeValue> = HashMap::new();
+        for (&param_var_id, arg) in func.parameters.iter().zip(args) {
+            let param_name = &func.variables[param_var_id].name;
+            local_vars.insert(param_name.clone(), arg);
+        }
 
-    // Execute all statements in the block
-    for stmt in &statements {
-        execute_statement_isolated(state, stmt, local_vars, func_id)?;
+        // Start execution from entry hop
+        let entry_hop = func.entry_hop.ok_or_else(|| {
+            RuntimeError::ExecutionError("Function has no entry hop".to_string())
+        })?;
+        Ok(Self {
+            func_id,
+            local_vars,
+            block_id: hop_entry_block(state, func_id, entry_hop)?,
+            hop_index: 0,
+            outcome: None,
+            steps: None,
+        })
     }
 
-    // Handle the terminator
-    match terminator {
-        Terminator::Goto(next_block) => {
-            execute_block(state, func_id, next_block, local_vars)
-        }
-        
-        Terminator::Branch {
-            condition,
-            then_block,
-            else_block,
-        } => {
-            let condition_value = evaluate_operand_isolated(&condition, local_vars, state, func_id)?;
-            let next_block = match condition_value {
-                RuntimeValue::Bool(true) => then_block,
-                RuntimeValue::Bool(false) => else_block,
-                _ => return Err(RuntimeError::ExecutionError(
-                    "Branch condition must be boolean".to_string()
-                )),
-            };
-            execute_block(state, func_id, next_block, local_vars)
+    /// Fail with `RuntimeError::StepLimit` once the transaction has run
+    /// `limit` blocks
+    pub(super) fn with_step_limit(mut self, limit: usize) -> Self {
+        self.steps = Some((limit, 0));
+        self
+    }
+
+    /// The hops that have run so far, including one the transaction ended in
+    pub(super) fn hops_run(&self) -> usize {
+        self.hop_index + usize::from(self.outcome.is_some())
+    }
+
+    /// How the transaction ended, if it has
+    pub(super) fn outcome(&self) -> Option<&ExecutionOutcome> {
+        self.outcome.as_ref()
+    }
+
+    /// Run the current hop until it exits to the next one, committing it, or
+    /// the transaction ends. An abort or a runtime error rolls back the writes
+    /// of the hop.
+    pub(super) fn run_hop(
+        &mut self,
+        state: &mut RuntimeState,
+    ) -> Result<Option<ExecutionOutcome>, RuntimeError> {
+        if let Some(outcome) = &self.outcome {
+            return Ok(Some(outcome.clone()));
         }
-        
-        Terminator::Return(operand) => {
-            if let Some(operand) = operand {
-                let return_value = evaluate_operand_isolated(&operand, local_vars, state, func_id)?;
-                Ok(Some(return_value))
-            } else {
-                Ok(None)
+        let mut undo_log = Vec::new();
+        let result = self.run_blocks(state, &mut undo_log);
+        if !matches!(result, Ok(None) | Ok(Some(ExecutionOutcome::Committed(_)))) {
+            // Restore the records in reverse, so each ends up as it was first seen
+            for (table, key, record) in undo_log.into_iter().rev() {
+                let table_data = state.table_data.entry(table).or_default();
+                match record {
+                    Some(record) => table_data.insert(key, record),
+                    None => table_data.remove(&key),
+                };
             }
         }
-        
-        Terminator::Abort => {
-            Err(RuntimeError::ExecutionError("Function aborted".to_string()))
+        if let Ok(Some(outcome)) = &result {
+            self.outcome = Some(outcome.clone());
         }
-        
-        Terminator::HopExit { next_hop } => {
-            if let Some(next_hop_id) = next_hop {
-                execute_hop(state, func_id, next_hop_id, local_vars)
-            } else {
-                // End of function
-                Ok(None)
+        result
+    }
+
+    /// Execute blocks from `block_id` until the hop exits, returning how the
+    /// transaction ended if it did
+    fn run_blocks(
+        &mut self,
+        state: &mut RuntimeState,
+        undo_log: &mut Vec<UndoEntry>,
+    ) -> Result<Option<ExecutionOutcome>, RuntimeError> {
+        let func_id = self.func_id;
+        loop {
+            if let Some((limit, run)) = &mut self.steps {
+                if *run == *limit {
+                    return Err(RuntimeError::StepLimit(*limit));
+                }
+                *run += 1;
+            }
+
+            // Get the statements and terminator first to avoid borrowing issues
+            let (statements, terminator) = {
+                let cfg = state.cfg_program.as_ref().unwrap();
+                let block = &cfg.functions[func_id].blocks[self.block_id];
+                (block.statements.clone(), block.terminator.clone())
+            };
+
+            // Execute all statements in the block
+            for stmt in &statements {
+                execute_statement_isolated(state, stmt, &mut self.local_vars, func_id, undo_log)?;
+            }
+
+            // Handle the terminator
+            match terminator {
+                Terminator::Goto(next_block) => self.block_id = next_block,
+
+                Terminator::Branch {
+                    condition,
+                    then_block,
+                    else_block,
+                } => {
+                    let condition_value =
+                        evaluate_operand_isolated(&condition, &self.local_vars, state, func_id)?;
+                    self.block_id = match condition_value {
+                        RuntimeValue::Bool(true) => then_block,
+                        RuntimeValue::Bool(false) => else_block,
+                        _ => return Err(RuntimeError::ExecutionError(
+                            "Branch condition must be boolean".to_string()
+                        )),
+                    };
+                }
+
+                Terminator::Return(operand) => {
+                    let return_value = match operand {
+                        Some(operand) => Some(evaluate_operand_isolated(&operand, &self.local_vars, state, func_id)?),
+                        None => None,
+                    };
+                    return Ok(Some(ExecutionOutcome::Committed(return_value)));
+                }
+
+                Terminator::Abort => {
+                    return Ok(Some(ExecutionOutcome::Aborted {
+                        hop: self.hop_index,
+                    }))
+                }
+
+                Terminator::BoundExceeded(_) => {
+                    return Err(RuntimeError::ExecutionError(
+                        "Loop ran longer than its unroll bound".to_string(),
+                    ));
+                }
+
+                // The hop commits, so its writes can no longer be rolled back
+                Terminator::HopExit { next_hop } => match next_hop {
+                    Some(next_hop_id) => {
+                        self.hop_index += 1;
+                        self.block_id = hop_entry_block(state, func_id, next_hop_id)?;
+                        return Ok(None);
+                    }
+                    // End of function
+                    None => return Ok(Some(ExecutionOutcome::Committed(None))),
+                },
             }
         }
     }
 }
 
+/// The block a hop starts at
+fn hop_entry_block(
+    state: &RuntimeState,
+    func_id: FunctionId,
+    hop_id: HopId,
+) -> Result<BasicBlockId, RuntimeError> {
+    let cfg = state.cfg_program.as_ref().unwrap();
+    cfg.functions[func_id].hops[hop_id].entry_block.ok_or_else(|| {
+        RuntimeError::ExecutionError("Hop has no entry block".to_string())
+    })
+}
+
+/// The primary key of a record, in the order the table declares its primary
+/// key fields, whatever order the access lists them in
+fn record_key(
+    state: &RuntimeState,
+    table: TableId,
+    pk_fields: &[FieldId],
+    pk_vals: Vec<RuntimeValue>,
+) -> Vec<RuntimeValue> {
+    let cfg = state.cfg_program.as_ref().unwrap();
+    let mut keyed: Vec<_> = pk_fields.iter().zip(pk_vals).collect();
+    keyed.sort_by_key(|(field, _)| {
+        cfg.tables[table]
+            .primary_keys
+            .iter()
+            .position(|pk| pk == *field)
+    });
+    keyed.into_iter().map(|(_, value)| value).collect()
+}
+
 /// Execute a single statement
 fn execute_statement_isolated(
     state: &mut RuntimeState,
     stmt: &Statement,
     local_vars: &mut HashMap<String, RuntimeValue>,
     func_id: FunctionId,
+    undo_log: &mut Vec<UndoEntry>,
 ) -> Result<(), RuntimeError> {
     match stmt {
         Statement::Assign {
             var,
             rvalue,
             span: _,
+            origin: _,
         } => {
             // Get variable name
             let var_name = if let Some(cfg) = &state.cfg_program {
@@ -154,11 +296,12 @@ fn execute_statement_isolated(
 
         Statement::TableAssign {
             table,
-            pk_fields: _,
+            pk_fields,
             pk_values,
             field,
             value,
             span: _,
+            origin: _,
         } => {
             // Get primary key values
             let mut pk_vals = Vec::new();
@@ -167,6 +310,8 @@ fn execute_statement_isolated(
                 pk_vals.push(val);
             }
 
+            let key = record_key(state, *table, pk_fields, pk_vals);
+
             // Get field value
             let field_value = evaluate_operand_isolated(value, local_vars, state, func_id)?;
 
@@ -175,8 +320,11 @@ fn execute_statement_isolated(
                 RuntimeError::ExecutionError(format!("Table {:?} not found", table))
             })?;
 
+            // Remember the record before the write, for rolling back the hop
+            undo_log.push((*table, key.clone(), table_data.get(&key).cloned()));
+
             let record = table_data
-                .entry(pk_vals.clone())
+                .entry(key)
                 .or_insert_with(HashMap::new);
             record.insert(*field, field_value.clone());
 
@@ -199,9 +347,9 @@ fn evaluate_rvalue_isolated(
 
         Rvalue::TableAccess {
             table,
-            pk_values, 
-            field, 
-            ..
+            pk_fields,
+            pk_values,
+            field,
         } => {
             // Get primary key values
             let mut pk_vals = Vec::new();
@@ -210,13 +358,15 @@ fn evaluate_rvalue_isolated(
                 pk_vals.push(val);
             }
 
+            let key = record_key(state, *table, pk_fields, pk_vals);
+
             // Read from the specific table
             let table_data = state.table_data.get(table).ok_or_else(|| {
                 RuntimeError::ExecutionError(format!("Table {:?} not found", table))
             })?;
             
             let value = table_data
-                .get(&pk_vals)
+                .get(&key)
                 .and_then(|record| record.get(field))
                 .cloned()
                 .or_else(|| {
@@ -244,19 +394,19 @@ fn evaluate_rvalue_isolated(
             match (op, &left_val, &right_val) {
                 // Integer operations
                 (BinaryOp::Add, RuntimeValue::Int(a), RuntimeValue::Int(b)) => {
-                    Ok(RuntimeValue::Int(a + b))
+                    a.checked_add(*b).map(RuntimeValue::Int).ok_or_else(integer_overflow)
                 }
                 (BinaryOp::Sub, RuntimeValue::Int(a), RuntimeValue::Int(b)) => {
-                    Ok(RuntimeValue::Int(a - b))
+                    a.checked_sub(*b).map(RuntimeValue::Int).ok_or_else(integer_overflow)
                 }
                 (BinaryOp::Mul, RuntimeValue::Int(a), RuntimeValue::Int(b)) => {
-                    Ok(RuntimeValue::Int(a * b))
+                    a.checked_mul(*b).map(RuntimeValue::Int).ok_or_else(integer_overflow)
                 }
                 (BinaryOp::Div, RuntimeValue::Int(a), RuntimeValue::Int(b)) => {
                     if *b == 0 {
                         Err(RuntimeError::ExecutionError("Division by zero".to_string()))
                     } else {
-                        Ok(RuntimeValue::Int(a / b))
+                        a.checked_div(*b).map(RuntimeValue::Int).ok_or_else(integer_overflow)
                     }
                 }
                 
@@ -312,6 +462,14 @@ fn evaluate_rvalue_isolated(
                     }
                 }
                 
+                // Logical operations; both operands are already evaluated
+                (BinaryOp::And, RuntimeValue::Bool(a), RuntimeValue::Bool(b)) => {
+                    Ok(RuntimeValue::Bool(*a && *b))
+                }
+                (BinaryOp::Or, RuntimeValue::Bool(a), RuntimeValue::Bool(b)) => {
+                    Ok(RuntimeValue::Bool(*a || *b))
+                }
+
                 // Equality operations (work with any type)
                 (BinaryOp::Eq, a, b) => Ok(RuntimeValue::Bool(a == b)),
                 (BinaryOp::Neq, a, b) => Ok(RuntimeValue::Bool(a != b)),
@@ -343,6 +501,21 @@ fn evaluate_rvalue_isolated(
                 (BinaryOp::Gte, RuntimeValue::Float(a), RuntimeValue::Float(b)) => {
                     Ok(RuntimeValue::Bool(a >= b))
                 }
+
+                // Comparison operations for strings, byte by byte; `nocase` comparisons
+                // fold their operands first
+                (BinaryOp::Lt, RuntimeValue::String(a), RuntimeValue::String(b)) => {
+                    Ok(RuntimeValue::Bool(a < b))
+                }
+                (BinaryOp::Lte, RuntimeValue::String(a), RuntimeValue::String(b)) => {
+                    Ok(RuntimeValue::Bool(a <= b))
+                }
+                (BinaryOp::Gt, RuntimeValue::String(a), RuntimeValue::String(b)) => {
+                    Ok(RuntimeValue::Bool(a > b))
+                }
+                (BinaryOp::Gte, RuntimeValue::String(a), RuntimeValue::String(b)) => {
+                    Ok(RuntimeValue::Bool(a >= b))
+                }
                 
                 // Mixed comparison operations
                 (BinaryOp::Lt, RuntimeValue::Int(a), RuntimeValue::Float(b)) => {
@@ -380,9 +553,10 @@ fn evaluate_rvalue_isolated(
             let val = evaluate_operand_isolated(operand, local_vars, state, func_id)?;
 
             match (op, &val) {
-                (UnaryOp::Neg, RuntimeValue::Int(a)) => Ok(RuntimeValue::Int(-a)),
+                (UnaryOp::Neg, RuntimeValue::Int(a)) => a.checked_neg().map(RuntimeValue::Int).ok_or_else(integer_overflow),
                 (UnaryOp::Neg, RuntimeValue::Float(a)) => Ok(RuntimeValue::Float(ordered_float::OrderedFloat(-a.into_inner()))),
                 (UnaryOp::Not, RuntimeValue::Bool(a)) => Ok(RuntimeValue::Bool(!a)),
+                (UnaryOp::FoldCase, RuntimeValue::String(a)) => Ok(RuntimeValue::String(a.to_lowercase())),
                 _ => Err(RuntimeError::ExecutionError(format!(
                     "Unsupported unary operation: {:?} {:?}",
                     op, val
@@ -392,6 +566,11 @@ fn evaluate_rvalue_isolated(
     }
 }
 
+/// The error for int arithmetic whose result does not fit in 64 bits
+fn integer_overflow() -> RuntimeError {
+    RuntimeError::ExecutionError("Integer overflow".to_string())
+}
+
 /// Evaluate an operand
 fn evaluate_operand_isolated(
     operand: &Operand,
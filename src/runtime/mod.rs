@@ -1,13 +1,15 @@
 //! TransAct Interactive Runtime
 //!
-//! This module provides a simple REPL environment for testing TransAct programs.
+//! This module provides a simple REPL environment for testing TransAct programs,
+//! a batch simulation that runs transactions one after another, and checks
+//! that run programs against each other or chopped runs against serial ones.
 //! It's designed for quick testing and experimentation, not production use.
 
 use std::fmt;
 use ordered_float::OrderedFloat;
 
 /// Runtime values - keep it simple
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum RuntimeValue {
     Int(i64),
     Float(OrderedFloat<f64>),
@@ -21,6 +23,9 @@ pub enum RuntimeError {
     ParseError(String),
     NotFound(String),
     ExecutionError(String),
+    /// A call ran more blocks than its step budget allows, as a loop that
+    /// never ends would
+    StepLimit(usize),
 }
 
 impl fmt::Display for RuntimeError {
@@ -29,6 +34,27 @@ impl fmt::Display for RuntimeError {
             RuntimeError::ParseError(msg) => write!(f, "Parse error: {}", msg),
             RuntimeError::NotFound(msg) => write!(f, "Not found: {}", msg),
             RuntimeError::ExecutionError(msg) => write!(f, "Execution error: {}", msg),
+            RuntimeError::StepLimit(steps) => write!(f, "Ran out of steps after {} blocks", steps),
+        }
+    }
+}
+
+/// How a transaction run ended. Each hop commits when it exits, so an abort
+/// only rolls back the writes of the hop it runs in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    /// The transaction ran to the end, returning a value if it has one
+    Committed(Option<RuntimeValue>),
+    /// The transaction aborted in the hop with this index, counting from 0
+    Aborted { hop: usize },
+}
+
+impl fmt::Display for ExecutionOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionOutcome::Committed(Some(value)) => write!(f, "committed, returned {}", value),
+            ExecutionOutcome::Committed(None) => write!(f, "committed"),
+            ExecutionOutcome::Aborted { hop } => write!(f, "aborted in hop {}", hop + 1),
         }
     }
 }
@@ -71,11 +97,43 @@ pub enum ReplCommand {
 }
 
 // Module declarations
+mod differential;
+mod equivalence;
 mod executor;
+#[cfg(feature = "repl")]
 mod repl;
+mod simulate;
 mod state;
 
 // Re-exports
-pub use executor::execute_function;
+pub use differential::{
+    check_chopping, DifferentialCheck, DifferentialOptions, Divergence, RunEnd,
+};
+pub use equivalence::{
+    check_equivalence, Counterexample, EquivalenceCheck, EquivalenceOptions, TableSnapshot,
+    TrialOutcome,
+};
+pub use executor::{execute_function, run_function};
+#[cfg(feature = "repl")]
 pub use repl::{start_runtime_repl, start_runtime_repl_with_cfg};
+pub use simulate::{run_simulation, run_test};
 pub use state::RuntimeState;
+#[cfg(not(feature = "repl"))]
+pub use without_repl::{start_runtime_repl, start_runtime_repl_with_cfg};
+
+/// Stand-ins for the REPL in builds without the `repl` feature, e.g. for
+/// WebAssembly, where there is no terminal.
+#[cfg(not(feature = "repl"))]
+mod without_repl {
+    use crate::cfg::CfgProgram;
+
+    const UNAVAILABLE: &str = "The runtime REPL needs the `repl` feature";
+
+    pub fn start_runtime_repl() -> Result<(), String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub fn start_runtime_repl_with_cfg(_cfg_program: CfgProgram) -> Result<(), String> {
+        Err(UNAVAILABLE.to_string())
+    }
+}
@@ -49,7 +49,7 @@ impl Completer for TransActCompleter {
                     });
                 }
             }
-        } else if words.len() >= 1 {
+        } else if !words.is_empty() {
             // Complete command arguments
             match words[0] {
                 "call" => {
@@ -113,26 +113,26 @@ impl Completer for TransActCompleter {
 struct FMitFPrompt;
 
 impl Prompt for FMitFPrompt {
-    fn render_prompt_left(&self) -> std::borrow::Cow<str> {
+    fn render_prompt_left(&self) -> std::borrow::Cow<'_, str> {
         "FMitF> ".into()
     }
 
-    fn render_prompt_right(&self) -> std::borrow::Cow<str> {
+    fn render_prompt_right(&self) -> std::borrow::Cow<'_, str> {
         "".into()
     }
 
-    fn render_prompt_indicator(&self, _edit_mode: PromptEditMode) -> std::borrow::Cow<str> {
+    fn render_prompt_indicator(&self, _edit_mode: PromptEditMode) -> std::borrow::Cow<'_, str> {
         "".into()
     }
 
-    fn render_prompt_multiline_indicator(&self) -> std::borrow::Cow<str> {
+    fn render_prompt_multiline_indicator(&self) -> std::borrow::Cow<'_, str> {
         "> ".into()
     }
 
     fn render_prompt_history_search_indicator(
         &self,
         _history_search: PromptHistorySearch,
-    ) -> std::borrow::Cow<str> {
+    ) -> std::borrow::Cow<'_, str> {
         "(search) ".into()
     }
 }
@@ -186,7 +186,7 @@ pub fn start_runtime_repl() -> Result<(), String> {
                 // Just continue on empty lines
             }
             ReadCommandOutput::ClapError(err) => {
-                println!("{}", err);
+                let _ = err.print();
             }
             ReadCommandOutput::ShlexError => {
                 eprintln!("ERROR: Invalid input: unmatched quotes");
@@ -216,14 +216,13 @@ pub fn start_runtime_repl_with_cfg(cfg_program: CfgProgram) -> Result<(), String
     print!("{}", "SUCCESS:".green().bold());
     println!(
         "Loaded optimized CFG with {} functions and {} tables",
-        cfg_program.functions.len().to_string(),
-        cfg_program.tables.len().to_string()
+        cfg_program.functions.len(),
+        cfg_program.tables.len()
     );
     println!();
     println!("{}", "TransAct Interactive Runtime".bold());
     println!(
-        "Type {} for commands, {} for completion, {} or {} to quit",
-        "'help'", "TAB", "Ctrl+C", "'exit'",
+        "Type 'help' for commands, TAB for completion, Ctrl+C or 'exit' to quit",
     );
     println!(
         "Available commands: {}",
@@ -269,7 +268,7 @@ pub fn start_runtime_repl_with_cfg(cfg_program: CfgProgram) -> Result<(), String
                 // Just continue on empty lines
             }
             ReadCommandOutput::ClapError(err) => {
-                println!("{}", err);
+                let _ = err.print();
             }
             ReadCommandOutput::ShlexError => {
                 eprintln!("ERROR: Invalid input: unmatched quotes");
@@ -297,7 +296,8 @@ fn handle_command(command: ReplCommand, state: &mut RuntimeState) -> Result<(),
     match command {
         ReplCommand::Call { function, args } => {
             println!("Executing: {} {:?}", function, args);
-            state.call_function(&function, args)?;
+            let outcome = state.call_function(&function, args)?;
+            println!("Result: {}", outcome);
         }
 
         ReplCommand::Table { name } => {
@@ -333,10 +333,9 @@ fn handle_command(command: ReplCommand, state: &mut RuntimeState) -> Result<(),
             state.clear_data()?;
         }
 
-        ReplCommand::Exit => {
-            // This should be handled in the main loop, but just in case
-            std::process::exit(0);
-        }
+        // The loops stop on `exit` before handling it; the host decides how
+        // the process ends
+        ReplCommand::Exit => {}
     }
 
     Ok(())
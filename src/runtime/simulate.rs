@@ -0,0 +1,116 @@
+//! Simulation - run a batch of transactions one after another, or with their
+//! hops interleaved by a schedule, as in the test schedules verify mode writes
+
+use super::RuntimeState;
+use crate::cfg::CfgProgram;
+use crate::verification::schedules::{TestRecord, TestSchedule};
+
+/// Run calls like `transfer 1 2 100` in order against empty tables, each as its
+/// own transaction, then print how each ended and the tables that hold data.
+/// With a schedule like `g:1 -> f:1 -> f:2 -> g:2`, the hops of the calls
+/// interleave as it gives instead.
+pub fn run_simulation(
+    cfg_program: CfgProgram,
+    calls: &[String],
+    schedule: Option<&str>,
+) -> Result<(), String> {
+    let parsed_calls = calls
+        .iter()
+        .map(|call| {
+            let mut words = call.split_whitespace().map(str::to_string);
+            let function = words
+                .next()
+                .ok_or_else(|| "--call needs a function name".to_string())?;
+            Ok((function, words.collect()))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let schedule = schedule.map(parse_schedule).transpose()?;
+    run(cfg_program, &[], parsed_calls, schedule.as_deref())
+}
+
+/// Run a test schedule that verify mode wrote: set the records it starts
+/// from, then run its calls with their hops interleaved as it gives.
+pub fn run_test(cfg_program: CfgProgram, test: &TestSchedule) -> Result<(), String> {
+    let calls = test
+        .calls
+        .iter()
+        .map(|call| (call.function.clone(), call.args.clone()))
+        .collect();
+    let schedule = parse_schedule(&test.schedule)?;
+    run(cfg_program, &test.records, calls, Some(&schedule))
+}
+
+fn run(
+    cfg_program: CfgProgram,
+    records: &[TestRecord],
+    calls: Vec<(String, Vec<String>)>,
+    schedule: Option<&[(String, usize)]>,
+) -> Result<(), String> {
+    let mut state = RuntimeState::new();
+    state.load_cfg(cfg_program).map_err(|e| e.to_string())?;
+    for record in records {
+        state
+            .set_field(
+                &record.table,
+                record.key.clone(),
+                &record.field,
+                record.value.clone(),
+            )
+            .map_err(|e| e.to_string())?;
+    }
+
+    let labels: Vec<String> = calls
+        .iter()
+        .map(|(function, args)| {
+            std::iter::once(function)
+                .chain(args)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    let outcomes = match schedule {
+        Some(schedule) => state.replay(calls, schedule),
+        None => state.simulate(calls),
+    }
+    .map_err(|e| e.to_string())?;
+    for (call, outcome) in labels.iter().zip(&outcomes) {
+        println!("{}: {}", call, outcome);
+    }
+
+    // Tables in declaration order
+    let cfg = state.cfg_program.as_ref().unwrap();
+    let tables: Vec<String> = cfg
+        .root_tables
+        .iter()
+        .filter(|table| !state.table_data[table].is_empty())
+        .map(|&table| cfg.tables[table].name.clone())
+        .collect();
+    for table in tables {
+        println!();
+        println!("Table: {}", table);
+        state.print_table(&table).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Steps like `f:2`, separated by spaces or arrows
+fn parse_schedule(schedule: &str) -> Result<Vec<(String, usize)>, String> {
+    schedule
+        .split_whitespace()
+        .filter(|word| *word != "->")
+        .map(|step| {
+            let (function, hop) = step
+                .split_once(':')
+                .ok_or_else(|| format!("Schedule step '{}' is not FUNCTION:HOP", step))?;
+            match hop.parse::<usize>() {
+                Ok(hop) if hop > 0 => Ok((function.to_string(), hop)),
+                _ => Err(format!(
+                    "Schedule step '{}' needs a hop number from 1",
+                    step
+                )),
+            }
+        })
+        .collect()
+}
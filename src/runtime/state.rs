@@ -1,12 +1,15 @@
 //! Runtime State - One struct with CFG + simple maps for everything
 
-use super::{RuntimeError, RuntimeValue};
+use super::executor::Transaction;
+use super::{run_function, ExecutionOutcome, RuntimeError, RuntimeValue};
 use crate::{
-    ast::parse_and_analyze,
+    ast::{parse_and_analyze_with_path, TypeName},
     cfg::{CfgBuilder, CfgProgram, FieldId, FunctionId, TableId},
+    pretty::is_plain,
 };
 use colored::*;
 use std::collections::HashMap;
+use std::path::Path;
 
 /// Main runtime state - keeps everything simple for testing
 pub struct RuntimeState {
@@ -23,6 +26,12 @@ pub struct RuntimeState {
     pub table_data: HashMap<TableId, HashMap<Vec<RuntimeValue>, HashMap<FieldId, RuntimeValue>>>,
 }
 
+impl Default for RuntimeState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RuntimeState {
     /// Create new empty runtime state
     pub fn new() -> Self {
@@ -42,7 +51,7 @@ impl RuntimeState {
             .map_err(|e| RuntimeError::ParseError(format!("Failed to read file: {}", e)))?;
 
         // Parse and analyze
-        let ast_program = parse_and_analyze(&source)
+        let ast_program = parse_and_analyze_with_path(&source, Some(Path::new(file_path)))
             .map_err(|errors| RuntimeError::ParseError(format!("Parse errors: {:?}", errors)))?;
 
         // Build CFG
@@ -84,38 +93,196 @@ impl RuntimeState {
         Ok(())
     }
 
-    /// Call a function by name with string arguments
+    /// Call a function by name with string arguments, parsed by parameter type
     pub fn call_function(
         &mut self,
         function_name: &str,
         args: Vec<String>,
-    ) -> Result<(), RuntimeError> {
+    ) -> Result<ExecutionOutcome, RuntimeError> {
+        let (func_id, parsed_args) = self.parse_call(function_name, args)?;
+
+        // Execute function (call executor)
+        run_function(self, func_id, parsed_args)
+    }
+
+    /// The function a call names, and its arguments parsed by parameter type
+    fn parse_call(
+        &self,
+        function_name: &str,
+        args: Vec<String>,
+    ) -> Result<(FunctionId, Vec<RuntimeValue>), RuntimeError> {
         let func_id = self
             .functions
             .get(function_name)
             .copied()
             .ok_or_else(|| RuntimeError::NotFound(format!("Function '{}'", function_name)))?;
 
-        // Parse string args to RuntimeValue (try int, float, bool, then string)
-        let parsed_args: Vec<RuntimeValue> = args
-            .into_iter()
-            .map(|arg| {
-                if let Ok(int_val) = arg.parse::<i64>() {
-                    RuntimeValue::Int(int_val)
-                } else if let Ok(float_val) = arg.parse::<f64>() {
-                    RuntimeValue::Float(ordered_float::OrderedFloat(float_val))
-                } else if arg == "true" {
-                    RuntimeValue::Bool(true)
-                } else if arg == "false" {
-                    RuntimeValue::Bool(false)
-                } else {
-                    RuntimeValue::String(arg)
-                }
+        let cfg = self
+            .cfg_program
+            .as_ref()
+            .ok_or_else(|| RuntimeError::ExecutionError("No program loaded".to_string()))?;
+        let func = &cfg.functions[func_id];
+        if args.len() != func.parameters.len() {
+            return Err(RuntimeError::ParseError(format!(
+                "Function '{}' expects {} arguments, got {}",
+                function_name,
+                func.parameters.len(),
+                args.len()
+            )));
+        }
+        let parsed_args = func
+            .parameters
+            .iter()
+            .zip(args)
+            .map(|(&param, arg)| {
+                let param = &func.variables[param];
+                parse_argument(&param.ty, arg).map_err(|e| {
+                    RuntimeError::ParseError(format!("Parameter '{}': {}", param.name, e))
+                })
             })
-            .collect();
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((func_id, parsed_args))
+    }
 
-        // Execute function (call executor)
-        let _return_value = super::executor::execute_function(self, func_id, parsed_args)?;
+    /// Run calls one after another, each as its own transaction, and return how
+    /// each ended. Stops at the first call that fails to run; aborted calls do not
+    /// stop the run.
+    ///
+    /// ```
+    /// use FMitF_rs::runtime::{ExecutionOutcome, RuntimeState, RuntimeValue};
+    /// use FMitF_rs::{parse_and_analyze, CfgBuilder};
+    ///
+    /// let program = parse_and_analyze(
+    ///     r#"
+    ///     nodes { A }
+    ///     table T on A { primary int id; int n; }
+    ///     int add(int id, int n) {
+    ///         hop on A {
+    ///             T[id: id].n = T[id: id].n + n;
+    ///             if (T[id: id].n > 10) {
+    ///                 abort;
+    ///             }
+    ///             return T[id: id].n;
+    ///         }
+    ///     }
+    ///     "#,
+    /// )
+    /// .unwrap();
+    /// let mut state = RuntimeState::new();
+    /// state.load_cfg(CfgBuilder::build_from_program(&program).unwrap().program).unwrap();
+    ///
+    /// let call = |n: &str| ("add".to_string(), vec!["1".to_string(), n.to_string()]);
+    /// let outcomes = state.simulate([call("4"), call("20"), call("3")]).unwrap();
+    /// assert_eq!(outcomes[0], ExecutionOutcome::Committed(Some(RuntimeValue::Int(4))));
+    /// // The abort rolls back the write of its hop
+    /// assert_eq!(outcomes[1], ExecutionOutcome::Aborted { hop: 0 });
+    /// assert_eq!(outcomes[2], ExecutionOutcome::Committed(Some(RuntimeValue::Int(7))));
+    /// ```
+    pub fn simulate<I>(&mut self, calls: I) -> Result<Vec<ExecutionOutcome>, RuntimeError>
+    where
+        I: IntoIterator<Item = (String, Vec<String>)>,
+    {
+        calls
+            .into_iter()
+            .map(|(function, args)| self.call_function(&function, args))
+            .collect()
+    }
+
+    /// Run calls with their hops interleaved: each `(FUNCTION, HOP)` step of
+    /// the schedule runs the call of that function until it has run its hop
+    /// HOP, counting from 1, and the calls then run to the end in order.
+    /// Each function may be called once. Returns how each call ended; a step
+    /// of a call that already ended does nothing.
+    pub fn replay<I>(
+        &mut self,
+        calls: I,
+        schedule: &[(String, usize)],
+    ) -> Result<Vec<ExecutionOutcome>, RuntimeError>
+    where
+        I: IntoIterator<Item = (String, Vec<String>)>,
+    {
+        let mut names = Vec::new();
+        let mut transactions = Vec::new();
+        for (function, args) in calls {
+            if names.contains(&function) {
+                return Err(RuntimeError::ExecutionError(format!(
+                    "Function '{}' is called more than once in a replay",
+                    function
+                )));
+            }
+            let (func_id, parsed_args) = self.parse_call(&function, args)?;
+            transactions.push(Transaction::start(self, func_id, parsed_args)?);
+            names.push(function);
+        }
+
+        for (function, hop) in schedule {
+            let index = names.iter().position(|name| name == function).ok_or_else(|| {
+                RuntimeError::NotFound(format!("Call of '{}' in the schedule", function))
+            })?;
+            let transaction = &mut transactions[index];
+            while transaction.outcome().is_none() && transaction.hops_run() < *hop {
+                transaction.run_hop(self)?;
+            }
+        }
+
+        let mut outcomes = Vec::new();
+        for transaction in &mut transactions {
+            while transaction.run_hop(self)?.is_none() {}
+            outcomes.extend(transaction.outcome().cloned());
+        }
+        Ok(outcomes)
+    }
+
+    /// Set a field of a record before calls run, with its key in the order the
+    /// table declares its primary key fields and values written as arguments
+    pub fn set_field(
+        &mut self,
+        table_name: &str,
+        key: Vec<String>,
+        field_name: &str,
+        value: String,
+    ) -> Result<(), RuntimeError> {
+        let cfg = self
+            .cfg_program
+            .as_ref()
+            .ok_or_else(|| RuntimeError::ExecutionError("No program loaded".to_string()))?;
+        let table_id = self
+            .tables
+            .get(table_name)
+            .copied()
+            .ok_or_else(|| RuntimeError::NotFound(format!("Table '{}'", table_name)))?;
+        let table = &cfg.tables[table_id];
+        let field_id = table
+            .fields
+            .iter()
+            .copied()
+            .find(|&field| cfg.fields[field].name == field_name)
+            .ok_or_else(|| {
+                RuntimeError::NotFound(format!("Field '{}' of '{}'", field_name, table_name))
+            })?;
+        if key.len() != table.primary_keys.len() {
+            return Err(RuntimeError::ParseError(format!(
+                "Table '{}' has {} key fields, got {}",
+                table_name,
+                table.primary_keys.len(),
+                key.len()
+            )));
+        }
+        let key = table
+            .primary_keys
+            .iter()
+            .zip(key)
+            .map(|(&pk, part)| parse_argument(&cfg.fields[pk].ty, part))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(RuntimeError::ParseError)?;
+        let value = parse_argument(&cfg.fields[field_id].ty, value)
+            .map_err(|e| RuntimeError::ParseError(format!("Field '{}': {}", field_name, e)))?;
+        self.table_data
+            .entry(table_id)
+            .or_default()
+            .entry(key)
+            .or_default()
+            .insert(field_id, value);
         Ok(())
     }
 
@@ -152,6 +319,10 @@ impl RuntimeState {
             return Ok(());
         }
 
+        // Rows in primary key order, so the same data always prints the same way
+        let mut data: Vec<_> = data.iter().collect();
+        data.sort_by_key(|(pk_values, _)| *pk_values);
+
         // Calculate column widths
         let mut column_widths = Vec::new();
         let mut column_headers = Vec::new();
@@ -168,7 +339,7 @@ impl RuntimeState {
             let mut max_width = header.len().max(6);
 
             // Check data widths
-            for (pk_values, field_data) in data {
+            for (pk_values, field_data) in &data {
                 let mut field_idx = 0;
                 for &check_field_id in &table_info.fields {
                     if check_field_id == field_id {
@@ -199,37 +370,24 @@ impl RuntimeState {
         }
 
         // Print header row
-        print!("┌");
-        for (i, width) in column_widths.iter().enumerate() {
-            print!("{}", "─".repeat(width + 2));
-            if i < column_widths.len() - 1 {
-                print!("┬");
-            }
-        }
-        println!("┐");
+        let bar = if is_plain() { "|" } else { "│" };
+        print_border(&column_widths, ["┌", "┬", "┐"]);
 
-        print!("│");
+        print!("{}", bar);
         for (i, (header, width)) in column_headers.iter().zip(&column_widths).enumerate() {
             print!(" {:<width$} ", header, width = width);
             if i < column_widths.len() - 1 {
-                print!("│");
+                print!("{}", bar);
             }
         }
-        println!("│");
+        println!("{}", bar);
 
         // Print separator
-        print!("├");
-        for (i, width) in column_widths.iter().enumerate() {
-            print!("{}", "─".repeat(width + 2));
-            if i < column_widths.len() - 1 {
-                print!("┼");
-            }
-        }
-        println!("┤");
+        print_border(&column_widths, ["├", "┼", "┤"]);
 
         // Print data rows
-        for (pk_values, field_data) in data {
-            print!("│");
+        for (pk_values, field_data) in &data {
+            print!("{}", bar);
             let mut field_idx = 0;
 
             for (col_idx, &field_id) in table_info.fields.iter().enumerate() {
@@ -252,21 +410,14 @@ impl RuntimeState {
                 let width = column_widths[col_idx];
                 print!(" {:<width$} ", value_str, width = width);
                 if col_idx < table_info.fields.len() - 1 {
-                    print!("│");
+                    print!("{}", bar);
                 }
             }
-            println!("│");
+            println!("{}", bar);
         }
 
         // Print bottom border
-        print!("└");
-        for (i, width) in column_widths.iter().enumerate() {
-            print!("{}", "─".repeat(width + 2));
-            if i < column_widths.len() - 1 {
-                print!("┴");
-            }
-        }
-        println!("┘");
+        print_border(&column_widths, ["└", "┴", "┘"]);
 
         Ok(())
     }
@@ -335,3 +486,35 @@ impl RuntimeState {
         Ok(())
     }
 }
+
+/// A border line of a table over columns of these widths, with its left,
+/// inner and right joints; all of them `+` with --plain
+fn print_border(widths: &[usize], joints: [&str; 3]) {
+    let (line, joints) = if is_plain() {
+        ("-", ["+"; 3])
+    } else {
+        ("─", joints)
+    };
+    let segments: Vec<String> = widths.iter().map(|width| line.repeat(width + 2)).collect();
+    println!("{}{}{}", joints[0], segments.join(joints[1]), joints[2]);
+}
+
+/// Parse a command-line argument as a value of the given type
+fn parse_argument(ty: &TypeName, arg: String) -> Result<RuntimeValue, String> {
+    let value = match ty {
+        TypeName::Int => arg.parse().ok().map(RuntimeValue::Int),
+        TypeName::Float => arg
+            .parse()
+            .ok()
+            .map(|value| RuntimeValue::Float(ordered_float::OrderedFloat(value))),
+        TypeName::Bool => arg.parse().ok().map(RuntimeValue::Bool),
+        TypeName::String => Some(RuntimeValue::String(arg.clone())),
+    };
+    let type_name = match ty {
+        TypeName::Int => "int",
+        TypeName::Float => "float",
+        TypeName::Bool => "bool",
+        TypeName::String => "string",
+    };
+    value.ok_or_else(|| format!("'{}' is not a valid {}", arg, type_name))
+}
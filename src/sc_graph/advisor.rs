@@ -0,0 +1,176 @@
+//! Suggesting hop merges that break mixed cycles.
+//!
+//! Merging two consecutive hops of a function that run on the same node
+//! contracts their S-edge: the merged hop conflicts with whatever either hop
+//! conflicted with, and cycles that needed both hops apart are gone. Each such
+//! merge is previewed on a copy of the graph with the two vertices contracted,
+//! which is exact for the edges justified from accesses but keeps C-edges that
+//! were dropped as commuting dropped. Splitting a hop only adds an S-edge
+//! between its pieces, and every cycle through the hop still passes one of
+//! them, so splits never break a cycle and are not suggested.
+//!
+//! Merges that leave fewer cycles on their own are suggested one by one; when
+//! all merges together leave fewer than the best of them, they are suggested
+//! together as well, since cycles often need a merge on each side.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{CycleLimits, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void f() { hop on A { T[id: 1].n = 1; } hop on A { T[id: 2].n = 1; } }
+//!     void g() { hop on A { T[id: 1].n = 2; } hop on A { T[id: 2].n = 2; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! assert_eq!(sc_graph.find_mixed_cycles().len(), 1);
+//!
+//! // Merging the hops of either function leaves the cycle through the other
+//! let advice = sc_graph.advise_merges(&CycleLimits::default());
+//! assert_eq!(advice.len(), 1);
+//! assert_eq!(advice[0].merges.len(), 2);
+//! assert_eq!(advice[0].cycles, 0);
+//! assert_eq!(advice[0].stats, (2, 0, 1));
+//! ```
+
+use crate::cfg::{FunctionId as CfgFunctionId, HopId as CfgHopId};
+use crate::sc_graph::{CycleLimits, Edge, EdgeType, SCGraph, SCGraphNodeId};
+use id_arena::Arena;
+use std::collections::{HashMap, HashSet};
+
+/// Merging a hop of a function into the hop before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopMerge {
+    pub function: CfgFunctionId,
+    pub first: CfgHopId,
+    pub second: CfgHopId,
+}
+
+/// A set of merges and the graph they would leave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeAdvice {
+    pub merges: Vec<HopMerge>,
+    /// Nodes, S-edges and C-edges after the merges, as `SCGraph::stats`
+    pub stats: (usize, usize, usize),
+    /// Mixed cycles left, within the limits the advice was given for
+    pub cycles: usize,
+    /// Whether the cycle search stopped at its limit, so more may be left
+    pub truncated: bool,
+}
+
+impl SCGraph {
+    /// Merges of consecutive hops on the same node that leave fewer mixed
+    /// cycles, fewest cycles left first.
+    pub fn advise_merges(&self, limits: &CycleLimits) -> Vec<MergeAdvice> {
+        let before = self.find_mixed_cycles_within(limits).cycles.len();
+        let candidates = self.merge_candidates();
+
+        let mut advice: Vec<MergeAdvice> = candidates
+            .iter()
+            .map(|&merge| self.preview_merges(vec![merge], limits))
+            .filter(|preview| preview.cycles < before)
+            .collect();
+        if candidates.len() > 1 {
+            let best = advice.iter().map(|preview| preview.cycles).min();
+            let all = self.preview_merges(candidates, limits);
+            if all.cycles < best.unwrap_or(before) {
+                advice.push(all);
+            }
+        }
+        advice.sort_by_key(|preview| (preview.cycles, preview.merges.len()));
+        advice
+    }
+
+    /// Consecutive hops of each function that run on the same node, in the
+    /// order of their S-edges.
+    fn merge_candidates(&self) -> Vec<HopMerge> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.edge_type == EdgeType::S)
+            .map(|edge| (&self.nodes[edge.source], &self.nodes[edge.target]))
+            .filter(|(first, second)| first.cfg_node_id == second.cfg_node_id)
+            .map(|(first, second)| HopMerge {
+                function: first.cfg_function_id,
+                first: first.cfg_hop_id,
+                second: second.cfg_hop_id,
+            })
+            .collect()
+    }
+
+    /// The graph left by the merges, as advice.
+    fn preview_merges(&self, merges: Vec<HopMerge>, limits: &CycleLimits) -> MergeAdvice {
+        let merged = self.contract(&merges);
+        let mixed_cycles = merged.find_mixed_cycles_within(limits);
+        MergeAdvice {
+            merges,
+            stats: merged.stats(),
+            cycles: mixed_cycles.cycles.len(),
+            truncated: mixed_cycles.truncated,
+        }
+    }
+
+    /// A copy of the vertices and edges with the hops of each merge contracted
+    /// into the first of them.
+    fn contract(&self, merges: &[HopMerge]) -> SCGraph {
+        let mut representative: HashMap<SCGraphNodeId, SCGraphNodeId> = HashMap::new();
+        let find = |representative: &HashMap<_, _>, mut node: SCGraphNodeId| {
+            while let Some(&next) = representative.get(&node) {
+                node = next;
+            }
+            node
+        };
+        for merge in merges {
+            let first = find(&representative, self.cfg_hop_to_sc_node[&merge.first]);
+            let second = find(&representative, self.cfg_hop_to_sc_node[&merge.second]);
+            if first != second {
+                representative.insert(second, first);
+            }
+        }
+
+        let mut nodes = Arena::new();
+        let mut new_ids: HashMap<SCGraphNodeId, SCGraphNodeId> = HashMap::new();
+        for (id, node) in self.nodes.iter() {
+            if find(&representative, id) == id {
+                new_ids.insert(id, nodes.alloc(node.clone()));
+            }
+        }
+        let new_id = |id: SCGraphNodeId| new_ids[&find(&representative, id)];
+
+        let mut seen = HashSet::new();
+        let mut edges = Vec::new();
+        for edge in &self.edges {
+            let (source, target) = (new_id(edge.source), new_id(edge.target));
+            if source == target {
+                continue;
+            }
+            let (source, target) = if source.index() < target.index() {
+                (source, target)
+            } else {
+                (target, source)
+            };
+            let edge = Edge::new(source, target, edge.edge_type.clone());
+            if seen.insert(edge.clone()) {
+                edges.push(edge);
+            }
+        }
+
+        SCGraph {
+            nodes,
+            edges,
+            justifications: HashMap::new(),
+            pair_checks: HashMap::new(),
+            exclusions: self.exclusions.clone(),
+            isolation: self.isolation,
+            cfg_hop_to_sc_node: self
+                .cfg_hop_to_sc_node
+                .iter()
+                .map(|(&hop, &id)| (hop, new_id(id)))
+                .collect(),
+        }
+    }
+}
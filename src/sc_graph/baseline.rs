@@ -0,0 +1,171 @@
+//! Stable cycle ids and baselines of accepted cycles.
+//!
+//! A cycle's id is a hash of the hops it passes, each named by its function
+//! and its position among the hops of the function, taken from whichever hop
+//! and in whichever direction gives the smallest sequence of names. It does
+//! not depend on the order of declarations, or on where the cycle search
+//! happens to start, so it survives unrelated edits to the program.
+//!
+//! A baseline lists the ids of cycles accepted as known, so that CI only
+//! fails on cycles a change introduces:
+//!
+//! ```toml
+//! accepted = [
+//!     "ca71351db6b03d9f", # f:1 -- f:2 -- g:2 -- g:1
+//! ]
+//! ```
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{Baseline, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let source = include_str!("../../examples/sc_graph_demos/crossed_writers.transact");
+//! let program = parse_and_analyze(source).unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let cycles = sc_graph.find_mixed_cycles();
+//! assert_eq!(sc_graph.cycle_id(&cycles[0], &cfg), "ca71351db6b03d9f");
+//! let baseline = Baseline::from_toml(&Baseline::write(&sc_graph, &cycles, &cfg)).unwrap();
+//! assert!(sc_graph.new_cycles(&baseline, &cycles, &cfg).is_empty());
+//!
+//! // Declaring g first keeps its id, while a new transaction brings new cycles
+//! let reordered = source.replace("void f", "void z").replace("void g", "void f").replace("void z", "void g");
+//! let program = parse_and_analyze(&(reordered + "void h(int id) { hop on A { T[id: id].n = 3; } hop on B { U[id: id].n = 3; } }")).unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let cycles = sc_graph.find_mixed_cycles();
+//! assert_eq!(cycles.len(), 12);
+//! assert_eq!(sc_graph.new_cycles(&baseline, &cycles, &cfg).len(), 11);
+//! ```
+
+use crate::cfg::{CfgProgram, HopId as CfgHopId};
+use crate::sc_graph::{SCGraph, SCGraphNodeId};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// The file a baseline is read from and written to when none is named.
+pub const DEFAULT_BASELINE_FILE: &str = "fmitf-baseline.toml";
+
+/// The ids of cycles accepted as known.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Baseline {
+    pub accepted: BTreeSet<String>,
+}
+
+impl Baseline {
+    /// Parses a baseline from TOML, rejecting unknown keys.
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.message().to_string())
+    }
+
+    /// Reads and parses the TOML baseline file at `path`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+        Self::from_toml(&text).map_err(|e| format!("Invalid baseline '{}': {}", path.display(), e))
+    }
+
+    /// A baseline accepting the cycles, as TOML with the hops of each cycle in
+    /// a comment after its id.
+    pub fn write(sc_graph: &SCGraph, cycles: &[Vec<CfgHopId>], cfg: &CfgProgram) -> String {
+        let mut entries: Vec<(String, String)> = cycles
+            .iter()
+            .map(|cycle| {
+                (
+                    sc_graph.cycle_id(cycle, cfg),
+                    sc_graph.describe_cycle(cycle, cfg),
+                )
+            })
+            .collect();
+        entries.sort();
+        entries.dedup();
+        let mut s = String::from("# Mixed cycles accepted as known, by id\naccepted = [\n");
+        for (id, names) in entries {
+            s.push_str(&format!("    \"{}\", # {}\n", id, names));
+        }
+        s.push_str("]\n");
+        s
+    }
+
+    /// Whether the cycle with this id is accepted.
+    pub fn accepts(&self, id: &str) -> bool {
+        self.accepted.contains(id)
+    }
+}
+
+impl SCGraph {
+    /// A hop as `f:2`, its function and 1-based position there.
+    pub fn hop_name(&self, node: SCGraphNodeId, cfg: &CfgProgram) -> String {
+        let node = &self.nodes[node];
+        let function = &cfg.functions[node.cfg_function_id];
+        let position = function
+            .hop_order
+            .iter()
+            .position(|&hop| hop == node.cfg_hop_id)
+            .unwrap_or_default();
+        format!("{}:{}", function.name, position + 1)
+    }
+
+    /// The id of a cycle, as `find_mixed_cycles` returns it: 16 hex digits.
+    pub fn cycle_id(&self, cycle: &[CfgHopId], cfg: &CfgProgram) -> String {
+        cycle_hash(&canonical_names(self, cycle, cfg))
+    }
+
+    /// The hops of a cycle as `f:1 -- f:2 -- g:2 -- g:1`, in the order its id
+    /// is computed from.
+    pub fn describe_cycle(&self, cycle: &[CfgHopId], cfg: &CfgProgram) -> String {
+        self.cycle_hops(cycle, cfg).join(" -- ")
+    }
+
+    /// The hops of a cycle as `f:1`, in the order its id is computed from.
+    pub fn cycle_hops(&self, cycle: &[CfgHopId], cfg: &CfgProgram) -> Vec<String> {
+        canonical_names(self, cycle, cfg)
+    }
+
+    /// The cycles the baseline does not accept, with their ids.
+    pub fn new_cycles<'a>(
+        &self,
+        baseline: &Baseline,
+        cycles: &'a [Vec<CfgHopId>],
+        cfg: &CfgProgram,
+    ) -> Vec<(String, &'a [CfgHopId])> {
+        cycles
+            .iter()
+            .map(|cycle| (self.cycle_id(cycle, cfg), cycle.as_slice()))
+            .filter(|(id, _)| !baseline.accepts(id))
+            .collect()
+    }
+}
+
+/// The hops of a cycle as `f:2`, rotated and turned to the smallest sequence.
+fn canonical_names(sc_graph: &SCGraph, cycle: &[CfgHopId], cfg: &CfgProgram) -> Vec<String> {
+    let names: Vec<String> = cycle
+        .iter()
+        .filter_map(|&hop| sc_graph.get_sc_node_id(hop))
+        .map(|node| sc_graph.hop_name(node, cfg))
+        .collect();
+    let reversed: Vec<String> = names.iter().rev().cloned().collect();
+    (0..names.len())
+        .flat_map(|start| {
+            [&names, &reversed].map(|names| {
+                let mut rotated = names.clone();
+                rotated.rotate_left(start);
+                rotated
+            })
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+/// FNV-1a over the names, which unlike `DefaultHasher` stays the same across
+/// Rust releases.
+fn cycle_hash(names: &[String]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in names.join(" -- ").bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
@@ -0,0 +1,567 @@
+//! Dropping C-edges whose hops commute.
+//!
+//! A C-edge is kept when its hops may access the same field of a record, but
+//! many such hops still commute: two blind increments of a balance end the same
+//! in either order. Before any verification run, each C-edge is first matched
+//! against syntactic patterns, and failing those its hops are executed
+//! symbolically in both orders, as `verification::symbolic` does for the final
+//! hops of a C-edge. Edges shown to commute are dropped from the graph, and
+//! their justification records why.
+//!
+//! The patterns are checked for every pair of accesses of the two hops that may
+//! address the same field of a record, one of them writing:
+//!
+//! - **Increments**: both accesses belong to an update `T[k].f = T[k].f + x`
+//!   (or `- x`) of an integer field, whose read value flows through copies into
+//!   the sum and nowhere else, and whose `x` does not use it.
+//! - **Equal writes**: both accesses write the same constant.
+//! - **Maxima** and **minima**: both accesses belong to an update
+//!   `if (x > T[k].f) { T[k].f = x; }` of an integer field (or `<` for a
+//!   minimum, either way round, strict or not), whose branch does nothing else
+//!   and whose read value flows into the comparison alone.
+//!
+//! Any other access of the field, like a read whose value is kept, defeats the
+//! patterns. Each pattern rests on a lemma of [`LEMMAS`], whose name the
+//! justification cites, as does that of accesses to different records.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void add(int id) { hop on A { int n = T[id: id].n; T[id: id].n = n + 1; } }
+//!     void sub(int id) { hop on A { T[id: id].n = T[id: id].n - 2; } }
+//!     void clear() { hop on A { int zero = 0; T[id: 1].n = zero; } }
+//!     void reset(int id) { hop on A { T[id: id].n = 0; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let mut sc_graph = SCGraph::new(&cfg);
+//! assert_eq!(sc_graph.stats().2, 6);
+//!
+//! // The increments match a pattern, the two resets only commute symbolically
+//! assert_eq!(sc_graph.eliminate_commuting_edges(&cfg), 2);
+//! assert_eq!(sc_graph.stats().2, 4);
+//! let mut reasons: Vec<_> = sc_graph
+//!     .justifications
+//!     .values()
+//!     .filter(|j| !j.conflicting)
+//!     .map(|j| j.reason.as_str())
+//!     .collect();
+//! reasons.sort();
+//! assert!(reasons[0].starts_with("add increments T[id: id].n"), "{}", reasons[0]);
+//! assert!(reasons[0].ends_with("which commute (lemma `increments`)"), "{}", reasons[0]);
+//! assert_eq!(reasons[1], "clear and reset end the same in either order, from any state");
+//! ```
+
+use crate::ast::TypeName;
+use crate::cfg::{
+    BasicBlockId, BinaryOp, CfgProgram, Constant, FunctionCfg, Instruction, Operand, Rvalue,
+    Statement, Terminator, VarId,
+};
+use crate::dataflow::{
+    analyze_keyed_accesses, AccessType, DefinitionSite, KeyAlias, KeyedAccess, ReachingDefinitions,
+};
+use crate::pretty::format_constant;
+use crate::sc_graph::{describe_access, Edge, EdgeType, SCGraph, SCGraphNodeId};
+use crate::verification::postconditions::ensured;
+use crate::verification::symbolic::{HopPair, PairCheck};
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+/// How many copies a value is followed through between a read and its sum.
+const MAX_COPIES: usize = 8;
+
+/// A law that makes two updates of a field commute, with its proof: an
+/// SMT-LIB2 script denying the law, which a prover finds unsatisfiable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lemma {
+    /// The name justifications cite
+    pub name: &'static str,
+    /// The law, in words
+    pub law: &'static str,
+    /// The script denying the law
+    pub proof: &'static str,
+}
+
+impl Lemma {
+    /// The citation closing a justification, as `(lemma `increments`)`.
+    pub fn cite(&self) -> String {
+        format!("(lemma `{}`)", self.name)
+    }
+}
+
+const INCREMENTS: Lemma = Lemma {
+    name: "increments",
+    law: "adding x then y to a field ends as adding y then x",
+    proof: "(declare-const n Int) (declare-const x Int) (declare-const y Int)
+(assert (not (= (+ (+ n x) y) (+ (+ n y) x))))",
+};
+
+const EQUAL_WRITES: Lemma = Lemma {
+    name: "equal-writes",
+    law: "writing c to the field of two records ends the same in either order",
+    proof: "(declare-const t (Array Int Int)) (declare-const j Int) (declare-const k Int)
+(declare-const c Int)
+(assert (not (= (store (store t j c) k c) (store (store t k c) j c))))",
+};
+
+const MAXIMA: Lemma = Lemma {
+    name: "maxima",
+    law: "raising a field to x then y ends as raising it to y then x",
+    proof: "(declare-const n Int) (declare-const x Int) (declare-const y Int)
+(define-fun raise ((n Int) (x Int)) Int (ite (> x n) x n))
+(assert (not (= (raise (raise n x) y) (raise (raise n y) x))))",
+};
+
+const MINIMA: Lemma = Lemma {
+    name: "minima",
+    law: "lowering a field to x then y ends as lowering it to y then x",
+    proof: "(declare-const n Int) (declare-const x Int) (declare-const y Int)
+(define-fun lower ((n Int) (x Int)) Int (ite (< x n) x n))
+(assert (not (= (lower (lower n x) y) (lower (lower n y) x))))",
+};
+
+pub(super) const DISJOINT_KEYS: Lemma = Lemma {
+    name: "disjoint-keys",
+    law: "accesses to different records neither see nor undo each other",
+    proof: "(declare-const t (Array Int Int)) (declare-const j Int) (declare-const k Int)
+(declare-const x Int) (declare-const y Int)
+(assert (distinct j k))
+(assert (or (not (= (store (store t j x) k y) (store (store t k y) j x)))
+    (not (= (select (store t j x) k) (select t k)))))",
+};
+
+/// The lemmas commuting C-edges are dropped by, before any verification
+/// condition is written.
+///
+/// ```
+/// use FMitF_rs::sc_graph::LEMMAS;
+///
+/// assert!(LEMMAS.iter().any(|lemma| lemma.name == "increments"));
+/// #[cfg(feature = "z3")]
+/// {
+///     use FMitF_rs::verification::solver::{SolverAnswer, SolverLimits};
+///     use FMitF_rs::verification::z3::InProcessZ3;
+///
+///     let mut z3 = InProcessZ3::new(SolverLimits::default());
+///     for lemma in LEMMAS {
+///         assert_eq!(z3.solve(lemma.proof), SolverAnswer::Unsat, "{}", lemma.name);
+///     }
+/// }
+/// ```
+pub const LEMMAS: &[Lemma] = &[INCREMENTS, EQUAL_WRITES, MAXIMA, MINIMA, DISJOINT_KEYS];
+
+/// What an access does to its field, as far as the patterns go.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Update {
+    /// The read or the write of an increment
+    Increment,
+    /// A write of a constant
+    Write(Constant),
+    /// The read or the write of an update keeping the greater value
+    Maximum,
+    /// The read or the write of an update keeping the smaller value
+    Minimum,
+}
+
+impl Update {
+    fn lemma(&self) -> &'static Lemma {
+        match self {
+            Update::Increment => &INCREMENTS,
+            Update::Write(_) => &EQUAL_WRITES,
+            Update::Maximum => &MAXIMA,
+            Update::Minimum => &MINIMA,
+        }
+    }
+}
+
+impl SCGraph {
+    /// Drops the C-edges whose hops commute, recording why in
+    /// `justifications`, and returns how many were dropped. Edges with a
+    /// `pair_checks` entry are not checked again, so after `update` only the
+    /// new edges are.
+    pub fn eliminate_commuting_edges(&mut self, cfg: &CfgProgram) -> usize {
+        let accesses = analyze_keyed_accesses(cfg);
+        let mut updates = HashMap::new();
+        for (function, func) in cfg.functions.iter() {
+            let finder = UpdateFinder::new(cfg, func);
+            for &hop in &func.hop_order {
+                for access in &accesses[&hop] {
+                    if let Some(update) = finder.update(access, &accesses[&hop]) {
+                        updates.insert((function, access.block, access.index), update);
+                    }
+                }
+            }
+        }
+        let update_of =
+            |access: &KeyedAccess| updates.get(&(access.function, access.block, access.index));
+
+        // Edges checked symbolically before were kept then, and still are.
+        // Hops committing a transaction that ensures properties are left to
+        // the prover, which checks those too. Edges are decided
+        // independently, so in parallel
+        let ensures = |node: SCGraphNodeId| {
+            let node = &self.nodes[node];
+            !ensured(cfg, node.cfg_function_id, node.cfg_hop_id).is_empty()
+        };
+        let pending: Vec<&Edge> = self
+            .edges
+            .iter()
+            .filter(|e| e.edge_type == EdgeType::C && !self.pair_checks.contains_key(e))
+            .filter(|e| !ensures(e.source) && !ensures(e.target))
+            .collect();
+        let decisions: Vec<(Edge, Option<String>, Option<PairCheck>)> = pending
+            .into_par_iter()
+            .map(|edge| {
+                let (node_a, node_b) = (&self.nodes[edge.source], &self.nodes[edge.target]);
+                let mut first_pair = None;
+                let covered = accesses[&node_a.cfg_hop_id].iter().all(|a| {
+                    accesses[&node_b.cfg_hop_id].iter().all(|b| {
+                        if a.table != b.table
+                            || a.field != b.field
+                            || (a.access_type == AccessType::Read
+                                && b.access_type == AccessType::Read)
+                            || a.alias(b) == KeyAlias::MustNot
+                        {
+                            return true;
+                        }
+                        match (update_of(a), update_of(b)) {
+                            (Some(update_a), Some(update_b)) if update_a == update_b => {
+                                // Describe writes rather than the reads of increments
+                                if a.access_type == AccessType::Write
+                                    && b.access_type == AccessType::Write
+                                {
+                                    first_pair.get_or_insert((a, update_a, b, update_b));
+                                }
+                                true
+                            }
+                            _ => false,
+                        }
+                    })
+                });
+
+                if let Some((a, update_a, b, update_b)) = first_pair.filter(|_| covered) {
+                    let reason = format!(
+                        "{} and {}, which commute {}",
+                        describe_update(a, update_a, cfg),
+                        describe_update(b, update_b, cfg),
+                        update_a.lemma().cite()
+                    );
+                    return (edge.clone(), Some(reason), None);
+                }
+                let pair = HopPair {
+                    function_a: node_a.cfg_function_id,
+                    hop_a: node_a.cfg_hop_id,
+                    function_b: node_b.cfg_function_id,
+                    hop_b: node_b.cfg_hop_id,
+                };
+                let check = pair.check(cfg);
+                let reason = matches!(check, PairCheck::Commutative).then(|| {
+                    format!(
+                        "{} and {} end the same in either order, from any state",
+                        cfg.functions[node_a.cfg_function_id].name,
+                        cfg.functions[node_b.cfg_function_id].name
+                    )
+                });
+                (edge.clone(), reason, Some(check))
+            })
+            .collect();
+
+        let mut count = 0;
+        for (edge, reason, check) in decisions {
+            if let Some(check) = check {
+                self.pair_checks.insert(edge.clone(), check);
+            }
+            let Some(reason) = reason else {
+                continue;
+            };
+            self.edges.retain(|e| *e != edge);
+            // The accesses still meet; they just commute
+            if let Some(justification) = self.justifications.get_mut(&edge) {
+                justification.conflicting = false;
+                justification.reason = reason;
+            }
+            count += 1;
+        }
+        count
+    }
+}
+
+/// An update as `f increments T[id: 1].n at file:3:5`.
+fn describe_update(access: &KeyedAccess, update: &Update, cfg: &CfgProgram) -> String {
+    match update {
+        Update::Increment => describe_access(access, "increments", cfg),
+        Update::Write(constant) => describe_access(
+            access,
+            &format!("writes {} to", format_constant(constant)),
+            cfg,
+        ),
+        Update::Maximum => describe_access(access, "raises", cfg),
+        Update::Minimum => describe_access(access, "lowers", cfg),
+    }
+}
+
+/// Finds the increments, constant writes, maxima and minima of a function.
+struct UpdateFinder<'a> {
+    cfg: &'a CfgProgram,
+    func: &'a FunctionCfg,
+    reaching: ReachingDefinitions,
+    /// How often each variable is used in the function
+    uses: HashMap<VarId, usize>,
+}
+
+impl<'a> UpdateFinder<'a> {
+    fn new(cfg: &'a CfgProgram, func: &'a FunctionCfg) -> Self {
+        let mut uses = HashMap::new();
+        for (block_id, block) in func.blocks.iter() {
+            if !func.contains_block(block_id) {
+                continue;
+            }
+            let terminator_operand = match &block.terminator {
+                Terminator::Branch { condition, .. } => Some(condition),
+                Terminator::Return(value) => value.as_ref(),
+                _ => None,
+            };
+            let instructions: Vec<_> = block
+                .statements
+                .iter()
+                .map(Instruction::of_statement)
+                .collect();
+            for operand in instructions
+                .iter()
+                .flat_map(Instruction::used_operands)
+                .chain(terminator_operand)
+            {
+                if let Operand::Var(var) = operand {
+                    *uses.entry(*var).or_insert(0) += 1;
+                }
+            }
+        }
+        Self {
+            cfg,
+            func,
+            reaching: ReachingDefinitions::new(func),
+            uses,
+        }
+    }
+
+    /// What an access of a hop does to its field, if it fits a pattern.
+    fn update(&self, access: &KeyedAccess, hop_accesses: &[KeyedAccess]) -> Option<Update> {
+        match access.access_type {
+            AccessType::Write => self.write_update(access, hop_accesses),
+            // A read belongs to an update when a write of the hop sums or
+            // compares it
+            AccessType::Read => hop_accesses
+                .iter()
+                .filter(|write| write.access_type == AccessType::Write)
+                .find_map(|write| {
+                    if self.increment_read(write, hop_accesses) == Some(access) {
+                        return Some(Update::Increment);
+                    }
+                    self.extremum_read(write, hop_accesses)
+                        .filter(|(read, _)| *read == access)
+                        .map(|(_, update)| update)
+                }),
+        }
+    }
+
+    fn write_update(&self, write: &KeyedAccess, hop_accesses: &[KeyedAccess]) -> Option<Update> {
+        let Statement::TableAssign { value, .. } =
+            &self.func.blocks[write.block].statements[write.index]
+        else {
+            return None;
+        };
+        if let Some((_, update)) = self.extremum_read(write, hop_accesses) {
+            return Some(update);
+        }
+        match value {
+            Operand::Const(constant) => Some(Update::Write(constant.clone())),
+            Operand::Var(_) => self
+                .increment_read(write, hop_accesses)
+                .map(|_| Update::Increment),
+        }
+    }
+
+    /// The read of the same record a write adds to, when the write is an
+    /// increment of an integer field.
+    fn increment_read<'b>(
+        &self,
+        write: &KeyedAccess,
+        hop_accesses: &'b [KeyedAccess],
+    ) -> Option<&'b KeyedAccess> {
+        if self.cfg.fields[write.field].ty != TypeName::Int {
+            return None;
+        }
+        let Statement::TableAssign {
+            value: Operand::Var(sum),
+            ..
+        } = &self.func.blocks[write.block].statements[write.index]
+        else {
+            return None;
+        };
+        let (block, index) = self.source(*sum, write.block, write.index)?;
+        let Statement::Assign {
+            rvalue: Rvalue::BinaryOp { op, left, right },
+            ..
+        } = &self.func.blocks[block].statements[index]
+        else {
+            return None;
+        };
+        let summands = match op {
+            BinaryOp::Add => vec![left, right],
+            BinaryOp::Sub => vec![left],
+            _ => return None,
+        };
+        summands.into_iter().find_map(|summand| {
+            let Operand::Var(var) = summand else {
+                return None;
+            };
+            let (block, index) = self.source(*var, block, index)?;
+            hop_accesses.iter().find(|read| {
+                read.access_type == AccessType::Read
+                    && (read.block, read.index) == (block, index)
+                    && read.field == write.field
+                    && read.alias(write) == KeyAlias::Must
+            })
+        })
+    }
+
+    /// The read of the same record a write is guarded by, when the write is
+    /// the whole branch of `if (x > T[k].f) { T[k].f = x; }` on an integer
+    /// field, and whether it keeps the greater or the smaller value.
+    fn extremum_read<'b>(
+        &self,
+        write: &KeyedAccess,
+        hop_accesses: &'b [KeyedAccess],
+    ) -> Option<(&'b KeyedAccess, Update)> {
+        if self.cfg.fields[write.field].ty != TypeName::Int {
+            return None;
+        }
+        let block = &self.func.blocks[write.block];
+        let Statement::TableAssign { value, .. } = &block.statements[write.index] else {
+            return None;
+        };
+        if block.statements.len() != 1 {
+            return None;
+        }
+        let mut guards = self.func.blocks.iter().filter(|(id, _)| {
+            self.func.contains_block(*id) && self.func.successors(*id).contains(&write.block)
+        });
+        let (guard_id, guard) = guards.next()?;
+        if guards.next().is_some() {
+            return None;
+        }
+        let Terminator::Branch {
+            condition: Operand::Var(condition),
+            then_block,
+            else_block,
+        } = &guard.terminator
+        else {
+            return None;
+        };
+        // Past the write, the branch goes where the empty else does
+        let otherwise = &self.func.blocks[*else_block];
+        let rejoins = match &block.terminator {
+            Terminator::Goto(join) => join == else_block,
+            terminator => otherwise.statements.is_empty() && otherwise.terminator == *terminator,
+        };
+        if *then_block != write.block || !rejoins {
+            return None;
+        }
+
+        let (block, index) = self.source(*condition, guard_id, guard.statements.len())?;
+        let Statement::Assign {
+            rvalue: Rvalue::BinaryOp { op, left, right },
+            ..
+        } = &self.func.blocks[block].statements[index]
+        else {
+            return None;
+        };
+        let greater = match op {
+            BinaryOp::Gt | BinaryOp::Gte => true,
+            BinaryOp::Lt | BinaryOp::Lte => false,
+            _ => return None,
+        };
+        // `x > n` keeps the greater value, `n > x` the smaller
+        [(left, right, greater), (right, left, !greater)]
+            .into_iter()
+            .find_map(|(new, old, greater)| {
+                if !self.same_value(new, (block, index), value, (write.block, write.index)) {
+                    return None;
+                }
+                let Operand::Var(old) = old else {
+                    return None;
+                };
+                let (block, index) = self.source(*old, block, index)?;
+                let read = hop_accesses.iter().find(|read| {
+                    read.access_type == AccessType::Read
+                        && (read.block, read.index) == (block, index)
+                        && read.field == write.field
+                        && read.alias(write) == KeyAlias::Must
+                })?;
+                let update = if greater {
+                    Update::Maximum
+                } else {
+                    Update::Minimum
+                };
+                Some((read, update))
+            })
+    }
+
+    /// Whether two operands hold the same value, at their statements: equal
+    /// constants, or a variable with the same definitions reaching both.
+    fn same_value(
+        &self,
+        a: &Operand,
+        (block_a, index_a): (BasicBlockId, usize),
+        b: &Operand,
+        (block_b, index_b): (BasicBlockId, usize),
+    ) -> bool {
+        match (a, b) {
+            (Operand::Const(a), Operand::Const(b)) => a == b,
+            (Operand::Var(a), Operand::Var(b)) => {
+                a == b
+                    && self.reaching.reaching(self.func, block_a, index_a, *a)
+                        == self.reaching.reaching(self.func, block_b, index_b, *b)
+            }
+            _ => false,
+        }
+    }
+
+    /// The statement computing the value a variable has at statement `index`
+    /// of a block, following copies. Every variable on the way must be
+    /// defined there alone and used nowhere else.
+    fn source(
+        &self,
+        mut var: VarId,
+        mut block: BasicBlockId,
+        mut index: usize,
+    ) -> Option<(BasicBlockId, usize)> {
+        for _ in 0..=MAX_COPIES {
+            if self.uses.get(&var) != Some(&1) {
+                return None;
+            }
+            let [DefinitionSite::Statement {
+                block: def_block,
+                index: def_index,
+            }] = self.reaching.reaching(self.func, block, index, var)[..]
+            else {
+                return None;
+            };
+            (block, index) = (def_block, def_index);
+            match &self.func.blocks[block].statements[index] {
+                Statement::Assign {
+                    rvalue: Rvalue::Use(Operand::Var(source)),
+                    ..
+                } => var = *source,
+                _ => return Some((block, index)),
+            }
+        }
+        None
+    }
+}
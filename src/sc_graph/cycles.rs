@@ -0,0 +1,267 @@
+//! Enumeration of the simple mixed cycles of an SC-graph.
+//!
+//! A mixed cycle runs through at least one S-edge and one C-edge, and passes
+//! each hop at most once. The graph is undirected, so cycles are enumerated
+//! with Johnson's algorithm on the graph with each edge in both directions:
+//! every cycle is searched for from its smallest node, through larger nodes
+//! only, and a node is blocked while no path from it leads back to the start
+//! outside the current path. Each cycle is then found in both directions, and
+//! kept in the one whose second node is smaller than its last.
+//!
+//! The number of cycles can grow exponentially with the graph, so the search
+//! can stop after a number of cycles and skip cycles through too many hops.
+//! Cycles are returned shortest first, then by the SC-graph nodes they pass,
+//! each starting from its smallest node. Against snapshot isolation, cycles
+//! that are write skew are left out, as `isolation` tells.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{CycleLimits, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let source = include_str!("../../examples/sc_graph_demos/crossed_writers.transact");
+//! let h = "void h(int id) { hop on A { T[id: id].n = 3; } hop on B { U[id: id].n = 3; } }";
+//! let program = parse_and_analyze(&format!("{}{}", source, h)).unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//!
+//! // Three cycles through two functions, and nine through all three, which
+//! // pass five or six hops
+//! let all = sc_graph.find_mixed_cycles();
+//! assert_eq!(all.len(), 3 + 6 + 3);
+//! assert_eq!(sc_graph.describe_cycle(&all[0], &cfg), "f:1 -- f:2 -- g:2 -- g:1");
+//! assert!(all.windows(2).all(|pair| pair[0].len() <= pair[1].len()));
+//!
+//! let limits = CycleLimits { max_cycles: Some(5), max_length: Some(5) };
+//! let limited = sc_graph.find_mixed_cycles_within(&limits);
+//! assert_eq!(limited.cycles.len(), 5);
+//! assert!(limited.truncated);
+//! assert!(limited.cycles.iter().all(|cycle| cycle.len() <= 5));
+//! ```
+
+use crate::cfg::HopId as CfgHopId;
+use crate::sc_graph::isolation::SnapshotEdges;
+use crate::sc_graph::{EdgeType, Isolation, SCGraph};
+use rayon::prelude::*;
+
+/// Bounds on the mixed cycles enumerated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CycleLimits {
+    /// Stop once this many cycles are found
+    pub max_cycles: Option<usize>,
+    /// Skip cycles through more hops than this
+    pub max_length: Option<usize>,
+}
+
+/// The mixed cycles of an SC-graph, within some limits.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MixedCycles {
+    /// Each cycle as the hops it passes, starting from its smallest SC-graph node
+    pub cycles: Vec<Vec<CfgHopId>>,
+    /// Whether the search stopped at `max_cycles`, so more cycles may exist
+    pub truncated: bool,
+}
+
+impl SCGraph {
+    /// Finds every simple cycle in the SC-Graph that contains at least one S-edge
+    /// and one C-edge, and that the isolation level of the graph does not
+    /// admit, as the CFG HopIds it passes.
+    pub fn find_mixed_cycles(&self) -> Vec<Vec<CfgHopId>> {
+        self.find_mixed_cycles_within(&CycleLimits::default())
+            .cycles
+    }
+
+    /// Finds the simple mixed cycles within the limits.
+    pub fn find_mixed_cycles_within(&self, limits: &CycleLimits) -> MixedCycles {
+        let (cycles, truncated) = self.search_cycles(&vec![true; self.nodes.len()], limits);
+        self.sorted_cycles(cycles, truncated)
+    }
+
+    /// The mixed cycles whose smallest node is one of the `starts`, as node
+    /// indices, and whether the search stopped at `max_cycles`.
+    pub(super) fn search_cycles(
+        &self,
+        starts: &[bool],
+        limits: &CycleLimits,
+    ) -> (Vec<Vec<usize>>, bool) {
+        let mut adjacency = vec![Vec::new(); self.nodes.len()];
+        for edge in &self.edges {
+            let (source, target) = (edge.source.index(), edge.target.index());
+            adjacency[source].push((target, edge.edge_type.clone()));
+            adjacency[target].push((source, edge.edge_type.clone()));
+        }
+        for neighbors in &mut adjacency {
+            neighbors.sort_by_key(|(node, edge_type)| (*node, *edge_type == EdgeType::C));
+        }
+        let snapshot = match self.isolation {
+            Isolation::Serializable => None,
+            Isolation::Snapshot => Some(self.snapshot_edges()),
+        };
+
+        // The searches from each start are independent, so they run in
+        // parallel, each stopping at `max_cycles` on its own; taking their
+        // cycles in start order gives what one search in that order finds
+        let found: Vec<(Vec<Vec<usize>>, bool)> = (0..self.nodes.len())
+            .into_par_iter()
+            .filter(|&start| starts[start])
+            .map(|start| {
+                let mut search = CycleSearch {
+                    adjacency: &adjacency,
+                    snapshot: snapshot.as_ref(),
+                    limits: *limits,
+                    start,
+                    blocked: vec![false; self.nodes.len()],
+                    blocked_by: vec![Vec::new(); self.nodes.len()],
+                    path: Vec::new(),
+                    c_steps: Vec::new(),
+                    s_edges: 0,
+                    c_edges: 0,
+                    cycles: Vec::new(),
+                    truncated: false,
+                };
+                search.circuit(start);
+                (search.cycles, search.truncated)
+            })
+            .collect();
+
+        let mut cycles = Vec::new();
+        for (start_cycles, truncated) in found {
+            cycles.extend(start_cycles);
+            if let Some(max_cycles) = limits.max_cycles {
+                if truncated || cycles.len() >= max_cycles {
+                    cycles.truncate(max_cycles);
+                    return (cycles, true);
+                }
+            }
+        }
+        (cycles, false)
+    }
+
+    /// Cycles of node indices as hops, shortest first.
+    pub(super) fn sorted_cycles(
+        &self,
+        mut cycles: Vec<Vec<usize>>,
+        truncated: bool,
+    ) -> MixedCycles {
+        cycles.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+        let hops: Vec<CfgHopId> = self.nodes.iter().map(|(_, node)| node.cfg_hop_id).collect();
+        MixedCycles {
+            cycles: cycles
+                .into_iter()
+                .map(|cycle| cycle.into_iter().map(|node| hops[node]).collect())
+                .collect(),
+            truncated,
+        }
+    }
+}
+
+/// The state of Johnson's algorithm from one start, over SC-graph node indices.
+struct CycleSearch<'a> {
+    adjacency: &'a [Vec<(usize, EdgeType)>],
+    /// The edges that tell write skew, against snapshot isolation
+    snapshot: Option<&'a SnapshotEdges>,
+    limits: CycleLimits,
+    /// The node cycles are searched from; smaller nodes are left out
+    start: usize,
+    blocked: Vec<bool>,
+    /// The nodes to unblock along with each node
+    blocked_by: Vec<Vec<usize>>,
+    path: Vec<usize>,
+    /// Whether each step from a node of `path` takes a C-edge
+    c_steps: Vec<bool>,
+    /// Edges of each type along `path`
+    s_edges: usize,
+    c_edges: usize,
+    cycles: Vec<Vec<usize>>,
+    truncated: bool,
+}
+
+impl CycleSearch<'_> {
+    /// Extends the path through `node`, returning whether some path from it
+    /// leads back to the start. A path cut off by the length limit counts as
+    /// one, so that nodes are never left blocked for want of a longer path.
+    fn circuit(&mut self, node: usize) -> bool {
+        let mut closes = false;
+        self.path.push(node);
+        self.blocked[node] = true;
+
+        for index in 0..self.adjacency[node].len() {
+            if self.truncated {
+                break;
+            }
+            let (next, edge_type) = self.adjacency[node][index].clone();
+            if next < self.start {
+                continue;
+            }
+            self.count_edge(&edge_type, true);
+            if next == self.start {
+                closes = true;
+                self.record_cycle();
+            } else if !self.blocked[next] {
+                closes |= match self.limits.max_length {
+                    Some(max_length) if self.path.len() >= max_length => true,
+                    _ => self.circuit(next),
+                };
+            }
+            self.count_edge(&edge_type, false);
+        }
+
+        if closes {
+            self.unblock(node);
+        } else {
+            for index in 0..self.adjacency[node].len() {
+                let next = self.adjacency[node][index].0;
+                if next >= self.start && !self.blocked_by[next].contains(&node) {
+                    self.blocked_by[next].push(node);
+                }
+            }
+        }
+        self.path.pop();
+        closes
+    }
+
+    fn unblock(&mut self, node: usize) {
+        self.blocked[node] = false;
+        for other in std::mem::take(&mut self.blocked_by[node]) {
+            if self.blocked[other] {
+                self.unblock(other);
+            }
+        }
+    }
+
+    fn count_edge(&mut self, edge_type: &EdgeType, add: bool) {
+        let count = match edge_type {
+            EdgeType::S => &mut self.s_edges,
+            EdgeType::C => &mut self.c_edges,
+        };
+        if add {
+            *count += 1;
+            self.c_steps.push(*edge_type == EdgeType::C);
+        } else {
+            *count -= 1;
+            self.c_steps.pop();
+        }
+    }
+
+    /// Keeps the path closed by its last edge as a cycle, when it is a mixed
+    /// cycle found in its canonical direction.
+    fn record_cycle(&mut self) {
+        let path = &self.path;
+        // Going back along the edge just taken is no cycle
+        if path.len() < 3 || path[1] > path[path.len() - 1] {
+            return;
+        }
+        if self.s_edges == 0 || self.c_edges == 0 {
+            return;
+        }
+        if self
+            .snapshot
+            .is_some_and(|snapshot| snapshot.write_skew(path, &self.c_steps))
+        {
+            return;
+        }
+        self.cycles.push(path.clone());
+        if Some(self.cycles.len()) == self.limits.max_cycles {
+            self.truncated = true;
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Deadlocks between hops that lock records as they go.
+//!
+//! A node that runs each hop under two-phase locking of its records, rather
+//! than one lock for the whole node, can deadlock even when the chopping is
+//! serializable: two hops on the node each hold a record the other waits for.
+//! A hop locks a record when it first accesses it, exclusively if it writes
+//! the record anywhere, as the accesses of the hop have it, so reads that are
+//! later written never wait for an upgrade, and releases its locks when it
+//! ends. Hops on different nodes never wait for each other, and neither do the
+//! hops of one transaction, which run one at a time.
+//!
+//! Two hops on a node, or two runs of the same hop, may deadlock when each can
+//! hold a lock while waiting for one that conflicts with a lock the other
+//! holds. Locks conflict when their records may be the same and one of them is
+//! exclusive; the keys of two runs are only known to differ when they are
+//! different constants. Cycles through more than two hops are not looked for.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void transfer(int from, int to) {
+//!         hop on A {
+//!             T[id: from].n = T[id: from].n - 1;
+//!             T[id: to].n = T[id: to].n + 1;
+//!         }
+//!     }
+//!     void audit(int id) { hop on A { int n = T[id: id].n; } }
+//!     void bump() { hop on A { T[id: 1].n = T[id: 1].n + 1; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//!
+//! // Two transfers in opposite directions each hold the record the other
+//! // waits for; audit and bump lock one record each, so never wait holding one
+//! let deadlocks = sc_graph.potential_deadlocks(&cfg);
+//! assert_eq!(deadlocks.len(), 1);
+//! let [first, second] = &deadlocks[0].orders;
+//! assert_eq!(first.hop, second.hop);
+//! assert_eq!((first.waits, first.locks.len()), (1, 2));
+//! assert!(first.locks.iter().all(|lock| lock.exclusive));
+//! ```
+
+use crate::cfg::{CfgProgram, HopId as CfgHopId, NodeId as CfgNodeId};
+use crate::dataflow::{analyze_keyed_accesses, AccessType, KeyAlias, KeyedAccess};
+use crate::sc_graph::SCGraph;
+use std::collections::BTreeMap;
+
+/// A record a hop locks, at the access that first locks it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lock {
+    pub access: KeyedAccess,
+    /// Whether the hop writes the record, so locks it exclusively
+    pub exclusive: bool,
+}
+
+/// The locks of a hop in the order it takes them, up to the one it waits for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockOrder {
+    pub hop: CfgHopId,
+    pub locks: Vec<Lock>,
+    /// The lock waited for, with the ones before it held
+    pub waits: usize,
+}
+
+/// Two hops, or two runs of one hop, that may each wait for the other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PotentialDeadlock {
+    pub node: CfgNodeId,
+    pub orders: [LockOrder; 2],
+}
+
+impl SCGraph {
+    /// The pairs of hops on a node that may deadlock, in node order, each with
+    /// the locks it takes before waiting; pairs of functions the exclusions
+    /// ignore never run together, so are left out.
+    pub fn potential_deadlocks(&self, cfg: &CfgProgram) -> Vec<PotentialDeadlock> {
+        let accesses = analyze_keyed_accesses(cfg);
+        let mut hops_on_node: BTreeMap<usize, Vec<(CfgHopId, Vec<Lock>)>> = BTreeMap::new();
+        for (_, node) in self.nodes.iter() {
+            let locks = locks(accesses.get(&node.cfg_hop_id).map_or(&[], Vec::as_slice));
+            // A hop that takes one lock never waits holding another
+            if locks.len() > 1 {
+                hops_on_node
+                    .entry(node.cfg_node_id.index())
+                    .or_default()
+                    .push((node.cfg_hop_id, locks));
+            }
+        }
+
+        let mut deadlocks = Vec::new();
+        for hops in hops_on_node.values() {
+            for (i, (hop_a, locks_a)) in hops.iter().enumerate() {
+                for (hop_b, locks_b) in &hops[i..] {
+                    let functions = (
+                        &cfg.functions[self.nodes[self.cfg_hop_to_sc_node[hop_a]].cfg_function_id],
+                        &cfg.functions[self.nodes[self.cfg_hop_to_sc_node[hop_b]].cfg_function_id],
+                    );
+                    if self
+                        .exclusions
+                        .ignores_pair(&functions.0.name, &functions.1.name)
+                    {
+                        continue;
+                    }
+                    if let Some((waits_a, waits_b)) = waiting(locks_a, locks_b) {
+                        let order = |hop: &CfgHopId, locks: &Vec<Lock>, waits| LockOrder {
+                            hop: *hop,
+                            locks: locks[..=waits].to_vec(),
+                            waits,
+                        };
+                        deadlocks.push(PotentialDeadlock {
+                            node: functions.0.hops[*hop_a].node_id,
+                            orders: [
+                                order(hop_a, locks_a, waits_a),
+                                order(hop_b, locks_b, waits_b),
+                            ],
+                        });
+                    }
+                }
+            }
+        }
+        deadlocks
+    }
+}
+
+/// The locks a hop takes, in the order of its accesses.
+fn locks(accesses: &[KeyedAccess]) -> Vec<Lock> {
+    let mut locks: Vec<Lock> = Vec::new();
+    for access in accesses {
+        if locks
+            .iter()
+            .any(|lock| lock.access.alias(access) == KeyAlias::Must)
+        {
+            continue;
+        }
+        locks.push(Lock {
+            access: access.clone(),
+            exclusive: accesses.iter().any(|other| {
+                other.access_type == AccessType::Write && other.alias(access) == KeyAlias::Must
+            }),
+        });
+    }
+    locks
+}
+
+/// Whether locks of two runs may be on the same record in modes that conflict.
+fn conflict(a: &Lock, b: &Lock) -> bool {
+    (a.exclusive || b.exclusive) && a.access.alias(&b.access) != KeyAlias::MustNot
+}
+
+/// The locks two runs may wait for, each while holding one the other waits
+/// for, the earliest such pair first.
+fn waiting(locks_a: &[Lock], locks_b: &[Lock]) -> Option<(usize, usize)> {
+    let mut pairs: Vec<(usize, usize)> = (1..locks_a.len())
+        .flat_map(|a| (1..locks_b.len()).map(move |b| (a, b)))
+        .collect();
+    pairs.sort_by_key(|&(a, b)| (a + b, a));
+    pairs.into_iter().find(|&(a, b)| {
+        locks_b[..b].iter().any(|held| conflict(&locks_a[a], held))
+            && locks_a[..a].iter().any(|held| conflict(&locks_b[b], held))
+    })
+}
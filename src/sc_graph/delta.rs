@@ -0,0 +1,168 @@
+//! What an edit changes in the SC-graph.
+//!
+//! The C-edges of two versions of a program are matched by the hops they
+//! join, each named by its function and position as in cycle ids, and their
+//! mixed cycles by id, so that reordering declarations changes nothing. A
+//! C-edge's verification answer carries over when the edge's cache key, which
+//! hashes the code of its functions, is the key of an edge of the old version.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{CycleLimits, GraphDelta, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let source = include_str!("../../examples/sc_graph_demos/crossed_writers.transact");
+//! let build = |source: &str| {
+//!     let program = parse_and_analyze(source).unwrap();
+//!     let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//!     let sc_graph = SCGraph::new(&cfg);
+//!     (cfg, sc_graph)
+//! };
+//! let old = build(source);
+//! let new = build(&source.replace("U[id: id].n = 2", "U[id: id].m = 2"));
+//!
+//! let mut delta = GraphDelta::new((&old.0, &old.1), (&new.0, &new.1), &CycleLimits::default());
+//! assert_eq!(delta.removed_edges, ["f:2 -- g:2"]);
+//! assert_eq!(delta.fixed_cycles.len(), 1);
+//! assert!(delta.introduced_cycles.is_empty());
+//!
+//! // g changed, so the answers about its C-edges do not carry over
+//! delta.compare_answers((&old.0, &old.1), (&new.0, &new.1));
+//! let answers = delta.answers.unwrap();
+//! assert!(answers.invalidated.iter().all(|edge| edge.contains("g:")));
+//! ```
+
+use crate::cfg::CfgProgram;
+use crate::sc_graph::{CycleLimits, Edge, EdgeType, SCGraph};
+use crate::verification::cache::cache_key;
+use crate::verification::commutativity_check::create_verification_unit;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// The C-edges and mixed cycles one version of a program has and the other
+/// has not.
+#[derive(Debug, Clone, Default)]
+pub struct GraphDelta {
+    /// C-edges of the new version only, as `f:1 -- g:1`
+    pub added_edges: Vec<String>,
+    /// C-edges of the old version only
+    pub removed_edges: Vec<String>,
+    /// Mixed cycles of the old version only, as their ids and hops
+    pub fixed_cycles: Vec<(String, String)>,
+    /// Mixed cycles of the new version only
+    pub introduced_cycles: Vec<(String, String)>,
+    /// How many mixed cycles both versions have
+    pub kept_cycles: usize,
+    /// Whether either cycle search stopped at `max_cycles`
+    pub truncated: bool,
+    /// Which verification answers carry over, once compared
+    pub answers: Option<AnswerDelta>,
+}
+
+/// The C-edges of the new version by whether their answers carry over.
+#[derive(Debug, Clone, Default)]
+pub struct AnswerDelta {
+    /// How many C-edges keep the answers of the old version
+    pub reused: usize,
+    /// C-edges to verify again, as `f:1 -- g:1`
+    pub invalidated: Vec<String>,
+}
+
+impl GraphDelta {
+    /// Compares the C-edges and the mixed cycles within `limits` of the old
+    /// and the new version.
+    pub fn new(
+        old: (&CfgProgram, &SCGraph),
+        new: (&CfgProgram, &SCGraph),
+        limits: &CycleLimits,
+    ) -> Self {
+        let old_edges = edge_names(old);
+        let new_edges = edge_names(new);
+        let old_cycles = cycle_ids(old, limits);
+        let new_cycles = cycle_ids(new, limits);
+
+        let only = |ids: &BTreeMap<String, String>, other: &BTreeMap<String, String>| {
+            ids.iter()
+                .filter(|(id, _)| !other.contains_key(*id))
+                .map(|(id, hops)| (id.clone(), hops.clone()))
+                .collect::<Vec<_>>()
+        };
+        Self {
+            added_edges: new_edges.difference(&old_edges).cloned().collect(),
+            removed_edges: old_edges.difference(&new_edges).cloned().collect(),
+            fixed_cycles: only(&old_cycles.0, &new_cycles.0),
+            introduced_cycles: only(&new_cycles.0, &old_cycles.0),
+            kept_cycles: new_cycles
+                .0
+                .keys()
+                .filter(|id| old_cycles.0.contains_key(*id))
+                .count(),
+            truncated: old_cycles.1 || new_cycles.1,
+            answers: None,
+        }
+    }
+
+    /// Finds the C-edges of the new version whose verification answers carry
+    /// over from the old version.
+    pub fn compare_answers(&mut self, old: (&CfgProgram, &SCGraph), new: (&CfgProgram, &SCGraph)) {
+        // The prover is the same for both versions, so it is left out of the keys
+        let key = |(cfg, sc_graph): (&CfgProgram, &SCGraph), edge: &Edge| {
+            cache_key(
+                &create_verification_unit(edge.clone(), cfg, sc_graph),
+                cfg,
+                "",
+            )
+        };
+        let old_keys: HashSet<String> = c_edges(old.1).map(|edge| key(old, edge)).collect();
+
+        let mut answers = AnswerDelta::default();
+        let mut invalidated = BTreeSet::new();
+        for edge in c_edges(new.1) {
+            if old_keys.contains(&key(new, edge)) {
+                answers.reused += 1;
+            } else {
+                invalidated.insert(edge_name(new, edge));
+            }
+        }
+        answers.invalidated = invalidated.into_iter().collect();
+        self.answers = Some(answers);
+    }
+}
+
+fn c_edges(sc_graph: &SCGraph) -> impl Iterator<Item = &Edge> {
+    sc_graph
+        .edges
+        .iter()
+        .filter(|edge| edge.edge_type == EdgeType::C)
+}
+
+/// A C-edge as its hops, in order of their names.
+fn edge_name((cfg, sc_graph): (&CfgProgram, &SCGraph), edge: &Edge) -> String {
+    let mut hops = [edge.source, edge.target].map(|node| sc_graph.hop_name(node, cfg));
+    hops.sort();
+    hops.join(" -- ")
+}
+
+fn edge_names(version: (&CfgProgram, &SCGraph)) -> BTreeSet<String> {
+    c_edges(version.1)
+        .map(|edge| edge_name(version, edge))
+        .collect()
+}
+
+/// The mixed cycles by id, as the hops they pass, and whether the search
+/// stopped at `max_cycles`.
+fn cycle_ids(
+    (cfg, sc_graph): (&CfgProgram, &SCGraph),
+    limits: &CycleLimits,
+) -> (BTreeMap<String, String>, bool) {
+    let cycles = sc_graph.find_mixed_cycles_within(limits);
+    let ids = cycles
+        .cycles
+        .iter()
+        .map(|cycle| {
+            (
+                sc_graph.cycle_id(cycle, cfg),
+                sc_graph.describe_cycle(cycle, cfg),
+            )
+        })
+        .collect();
+    (ids, cycles.truncated)
+}
@@ -0,0 +1,124 @@
+//! Ranking C-edges by how hard they are likely to be to verify.
+//!
+//! The prover's effort grows with the code of the two hops it compares: each
+//! statement adds to the verification condition, each table access adds a map
+//! to reason about, string operations fall outside the arithmetic solvers
+//! handle well, and loops need invariants. A hop scores its statements, plus
+//! `TABLE_ACCESS_WEIGHT` per table access, `STRING_OP_WEIGHT` per string
+//! operation and `LOOP_WEIGHT` per loop, and a C-edge the scores of its two
+//! hops. Verifying the easiest edges first drops the edges that commute
+//! before any hard case can time out.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; string s; }
+//!     void f(int id) { hop on A { T[id: id].n = 1; } }
+//!     void g(int id) { hop on A { T[id: id].n = 2; } }
+//!     void h(int id) {
+//!         hop on A {
+//!             int i = 0;
+//!             while (i < 3) { T[id: id].n = T[id: id].n + 1; i = i + 1; }
+//!             if (T[id: id].s == "done") { T[id: id].n = 0; }
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//!
+//! // f and g write one field each; h loops and compares strings
+//! let order = sc_graph.c_edges_by_difficulty(&cfg);
+//! assert_eq!(order.len(), 3);
+//! let first = &order[0].0;
+//! assert_eq!(cfg.functions[sc_graph.nodes[first.source].cfg_function_id].name, "f");
+//! assert_eq!(cfg.functions[sc_graph.nodes[first.target].cfg_function_id].name, "g");
+//! assert!(order[0].1 < order[1].1);
+//! ```
+
+use crate::cfg::{
+    CfgProgram, Constant, DominatorTree, FunctionCfg, HopId as CfgHopId, Operand, Rvalue,
+    Statement, TypeName,
+};
+use crate::sc_graph::{Edge, EdgeType, SCGraph};
+use std::collections::HashMap;
+
+/// What each table access adds to the score of a hop.
+pub const TABLE_ACCESS_WEIGHT: usize = 4;
+/// What each string operation adds to the score of a hop.
+pub const STRING_OP_WEIGHT: usize = 8;
+/// What each loop adds to the score of a hop.
+pub const LOOP_WEIGHT: usize = 16;
+
+impl SCGraph {
+    /// The C-edges with their difficulty scores, easiest first, and in node
+    /// order among equal scores.
+    pub fn c_edges_by_difficulty(&self, cfg: &CfgProgram) -> Vec<(Edge, usize)> {
+        let scores: HashMap<CfgHopId, usize> = cfg
+            .functions
+            .iter()
+            .flat_map(|(_, func)| hop_scores(func))
+            .collect();
+        let mut edges: Vec<(Edge, usize)> = self
+            .edges
+            .iter()
+            .filter(|edge| edge.edge_type == EdgeType::C)
+            .map(|edge| {
+                let score = scores[&self.nodes[edge.source].cfg_hop_id]
+                    + scores[&self.nodes[edge.target].cfg_hop_id];
+                (edge.clone(), score)
+            })
+            .collect();
+        edges.sort_by_key(|(edge, score)| (*score, edge.source.index(), edge.target.index()));
+        edges
+    }
+}
+
+/// The score of each hop of a function.
+fn hop_scores(func: &FunctionCfg) -> Vec<(CfgHopId, usize)> {
+    let dominators = DominatorTree::dominators(func);
+    let is_string = |operand: &Operand| match operand {
+        Operand::Var(var) => func.variables[*var].ty == TypeName::String,
+        Operand::Const(constant) => matches!(constant, Constant::String(_)),
+    };
+
+    func.hops
+        .iter()
+        .map(|(hop_id, hop)| {
+            let mut score = 0;
+            for &block_id in &hop.blocks {
+                for statement in &func.blocks[block_id].statements {
+                    score += 1;
+                    match statement {
+                        Statement::Assign { rvalue, .. } => match rvalue {
+                            Rvalue::TableAccess { .. } => score += TABLE_ACCESS_WEIGHT,
+                            Rvalue::UnaryOp { operand, .. } if is_string(operand) => {
+                                score += STRING_OP_WEIGHT
+                            }
+                            Rvalue::BinaryOp { left, right, .. }
+                                if is_string(left) || is_string(right) =>
+                            {
+                                score += STRING_OP_WEIGHT
+                            }
+                            _ => {}
+                        },
+                        Statement::TableAssign { .. } => score += TABLE_ACCESS_WEIGHT,
+                    }
+                }
+                // A back edge leads to a block that dominates it, closing a loop
+                let back_edges = func
+                    .successors(block_id)
+                    .into_iter()
+                    .filter(|&succ| dominators.dominates(succ, block_id))
+                    .count();
+                score += LOOP_WEIGHT * back_edges;
+            }
+            (hop_id, score)
+        })
+        .collect()
+}
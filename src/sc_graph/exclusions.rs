@@ -0,0 +1,222 @@
+//! Leaving pairs of functions and tables out of conflict analysis.
+//!
+//! Some conflicts are known to be harmless: two transactions that never run
+//! at the same time, or an audit table only ever appended to and read
+//! offline. Ignoring a pair of functions drops every C-edge between their
+//! hops; ignoring a table drops the conflicts through it, and with them the
+//! C-edges that conflict through nothing else. Dropped edges keep their
+//! justification with the exclusion that dropped them, and the graph keeps
+//! the exclusions, so its output shows what was left out.
+//!
+//! A config file lists them under `[exclusions]`:
+//!
+//! ```toml
+//! [exclusions]
+//! ignore_pairs = [["deposit", "report"]]
+//! ignore_tables = ["Audit"]
+//! ```
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{ConflictExclusions, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     table Audit on A { primary int id; int n; }
+//!     void f(int id) { hop on A { T[id: id].n = 1; Audit[id: 1].n = 1; } }
+//!     void g(int id) { hop on A { Audit[id: 1].n = 2; } }
+//!     void h(int id) { hop on A { T[id: id].n = 3; Audit[id: 1].n = 3; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let mut sc_graph = SCGraph::new(&cfg);
+//! assert_eq!(sc_graph.stats().2, 3);
+//!
+//! let mut exclusions = ConflictExclusions::default();
+//! exclusions.add_pair("h,f").unwrap();
+//! exclusions.ignore_tables.push("Audit".to_string());
+//! exclusions.check_names(&cfg).unwrap();
+//!
+//! // f and h are ignored, and g conflicts with the others through Audit only
+//! assert_eq!(sc_graph.exclude(&exclusions, &cfg), 3);
+//! assert_eq!(sc_graph.stats().2, 0);
+//! assert_eq!(sc_graph.excluded_edges().len(), 3);
+//! ```
+
+use crate::cfg::CfgProgram;
+use crate::sc_graph::{Edge, EdgeType, SCGraph};
+use serde::{Deserialize, Serialize};
+
+/// Starts the reason of every C-edge an exclusion dropped.
+const EXCLUDED: &str = "excluded: ";
+
+/// The pairs of functions and the tables left out of conflict analysis.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConflictExclusions {
+    /// Pairs of functions, by name, whose hops get no C-edges
+    pub ignore_pairs: Vec<(String, String)>,
+    /// Tables, by name, whose conflicts do not count
+    pub ignore_tables: Vec<String>,
+}
+
+impl ConflictExclusions {
+    /// Adds a pair of functions given as `--ignore-pair` takes it, `TxnA,TxnB`.
+    pub fn add_pair(&mut self, pair: &str) -> Result<(), String> {
+        match pair.split(',').map(str::trim).collect::<Vec<_>>()[..] {
+            [a, b] if !a.is_empty() && !b.is_empty() => {
+                self.ignore_pairs.push((a.to_string(), b.to_string()));
+                Ok(())
+            }
+            _ => Err(format!(
+                "Invalid pair '{}', expected FUNCTION,FUNCTION",
+                pair
+            )),
+        }
+    }
+
+    /// Whether nothing is excluded.
+    pub fn is_empty(&self) -> bool {
+        self.ignore_pairs.is_empty() && self.ignore_tables.is_empty()
+    }
+
+    /// Whether the pair of functions is ignored, in either order.
+    pub fn ignores_pair(&self, a: &str, b: &str) -> bool {
+        self.ignore_pairs
+            .iter()
+            .any(|(x, y)| (x == a && y == b) || (x == b && y == a))
+    }
+
+    /// Whether the table is ignored.
+    pub fn ignores_table(&self, table: &str) -> bool {
+        self.ignore_tables.iter().any(|name| name == table)
+    }
+
+    /// Rejects functions and tables the program does not have, which are most
+    /// likely misspelled.
+    pub fn check_names(&self, cfg: &CfgProgram) -> Result<(), String> {
+        let has_function = |name: &String| cfg.functions.iter().any(|(_, f)| &f.name == name);
+        for name in self.ignore_pairs.iter().flat_map(|(a, b)| [a, b]) {
+            if !has_function(name) {
+                return Err(format!("Cannot ignore unknown function '{}'", name));
+            }
+        }
+        for name in &self.ignore_tables {
+            if !cfg.tables.iter().any(|(_, table)| &table.name == name) {
+                return Err(format!("Cannot ignore unknown table '{}'", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SCGraph {
+    /// Keeps the exclusions with the graph and drops the C-edges they exclude,
+    /// recording why in `justifications`, and returns how many were dropped.
+    pub fn exclude(&mut self, exclusions: &ConflictExclusions, cfg: &CfgProgram) -> usize {
+        self.exclusions = exclusions.clone();
+        self.apply_exclusions(cfg)
+    }
+
+    /// Drops the C-edges the graph's exclusions exclude, as `exclude` does.
+    pub(super) fn apply_exclusions(&mut self, cfg: &CfgProgram) -> usize {
+        if self.exclusions.is_empty() {
+            return 0;
+        }
+        let c_edges: Vec<Edge> = self
+            .edges
+            .iter()
+            .filter(|edge| edge.edge_type == EdgeType::C)
+            .cloned()
+            .collect();
+
+        let mut count = 0;
+        for edge in c_edges {
+            let names = (
+                &cfg.functions[self.nodes[edge.source].cfg_function_id].name,
+                &cfg.functions[self.nodes[edge.target].cfg_function_id].name,
+            );
+            let reason = if self.exclusions.ignores_pair(names.0, names.1) {
+                Some(format!(
+                    "{}{} and {} are an ignored pair",
+                    EXCLUDED, names.0, names.1
+                ))
+            } else if let Some(justification) = self.justifications.get_mut(&edge) {
+                let (ignored, kept): (Vec<_>, Vec<_>) = justification
+                    .conflicts
+                    .iter()
+                    .cloned()
+                    .partition(|conflict| {
+                        self.exclusions
+                            .ignores_table(&cfg.tables[conflict.table].name)
+                    });
+                if ignored.is_empty() {
+                    None
+                } else if kept.is_empty() {
+                    let mut tables: Vec<&str> = ignored
+                        .iter()
+                        .map(|conflict| cfg.tables[conflict.table].name.as_str())
+                        .collect();
+                    tables.sort();
+                    tables.dedup();
+                    Some(format!(
+                        "{}conflicts only through ignored {} {}",
+                        EXCLUDED,
+                        if tables.len() == 1 { "table" } else { "tables" },
+                        tables.join(", ")
+                    ))
+                } else {
+                    // Still conflicting, through what is not ignored
+                    let mut fields: Vec<String> = kept
+                        .iter()
+                        .map(|conflict| {
+                            format!(
+                                "{}.{}",
+                                cfg.tables[conflict.table].name, cfg.fields[conflict.field].name
+                            )
+                        })
+                        .collect();
+                    fields.sort();
+                    fields.dedup();
+                    justification.reason = format!(
+                        "conflicts through {} besides the ignored tables",
+                        fields.join(", ")
+                    );
+                    justification.conflicts = kept;
+                    None
+                }
+            } else {
+                None
+            };
+
+            let Some(reason) = reason else {
+                continue;
+            };
+            self.edges.retain(|e| *e != edge);
+            self.pair_checks.remove(&edge);
+            if let Some(justification) = self.justifications.get_mut(&edge) {
+                justification.conflicting = false;
+                justification.reason = reason;
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// The C-edges an exclusion dropped, in node order.
+    pub fn excluded_edges(&self) -> Vec<&Edge> {
+        let mut edges: Vec<&Edge> = self
+            .justifications
+            .iter()
+            .filter(|(_, justification)| {
+                !justification.conflicting && justification.reason.starts_with(EXCLUDED)
+            })
+            .map(|(edge, _)| edge)
+            .collect();
+        edges.sort_by_key(|edge| (edge.source.index(), edge.target.index()));
+        edges
+    }
+}
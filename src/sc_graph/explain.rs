@@ -0,0 +1,489 @@
+//! Explaining mixed cycles step by step, and single C-edges.
+//!
+//! A cycle is walked edge by edge from its first hop. A C-edge step names the
+//! statements of the two hops that conflict, with what each does to which
+//! field, and a step along an S-edge names the order the transaction runs its
+//! two hops in. Together they show why no serial order fits: each transaction
+//! on the cycle would have to run both before and after the next one.
+//!
+//! A C-edge is explained by its conflicts, each pruning step that failed to
+//! drop it, or the one that did, and what verification has to prove for it to
+//! go: that its hops end the same in either order, after any interleaving of
+//! the hops before them.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{EdgeType, Explanation, Finding, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table T on A { primary int id; int n; }
+//!     table U on B { primary int id; int n; }
+//!     void f(int id) { hop on A { T[id: id].n = 1; } hop on B { int n = U[id: id].n; } }
+//!     void g(int id) { hop on A { int n = T[id: id].n; } hop on B { U[id: id].n = 2; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let cycles = sc_graph.find_mixed_cycles();
+//!
+//! let explanation = sc_graph.explain_cycle(&cycles[0], &cfg);
+//! let types: Vec<_> = explanation.steps.iter().map(|step| step.edge_type.clone()).collect();
+//! assert_eq!(types, [EdgeType::S, EdgeType::C, EdgeType::S, EdgeType::C]);
+//! assert!(explanation.steps[1].text.starts_with("f reads U.n at"), "{}", explanation.steps[1].text);
+//! assert!(explanation.steps[1].text.contains(", g writes it at"), "{}", explanation.steps[1].text);
+//! assert!(explanation.conclusion.contains("f and g"), "{}", explanation.conclusion);
+//!
+//! // The same C-edge, found by its hops
+//! let finding = Finding::parse("edge:g.h2-f:2").unwrap();
+//! let Explanation::Edge(edge) = sc_graph.explain(&finding, &cfg, &Default::default()).unwrap()
+//! else {
+//!     unreachable!()
+//! };
+//! assert_eq!(edge.hops, "f:2 -- g:2");
+//! assert!(edge.kept);
+//! assert!(edge.obligation.unwrap().contains("after f:1 and g:1"));
+//! ```
+
+use crate::cfg::{CfgProgram, HopId as CfgHopId};
+use crate::dataflow::KeyAlias;
+use crate::sc_graph::commutativity::DISJOINT_KEYS;
+use crate::sc_graph::{
+    Conflict, ConflictKind, CycleLimits, Edge, EdgeType, SCGraph, SCGraphNodeId, LEMMAS,
+};
+use crate::verification::commutativity_check::create_verification_unit;
+use crate::verification::symbolic::{HopPair, PairCheck};
+
+/// One edge of a cycle, walked from `from` to `to`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleStep {
+    pub from: SCGraphNodeId,
+    pub to: SCGraphNodeId,
+    pub edge_type: EdgeType,
+    /// What the edge stands for, with the source location of each hop or
+    /// statement it names
+    pub text: String,
+}
+
+/// The steps of a cycle and why they close it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleExplanation {
+    pub steps: Vec<CycleStep>,
+    pub conclusion: String,
+}
+
+/// A finding to explain: a mixed cycle by its id, or the start of it, or a
+/// pair of hops that may conflict, each named as `f:1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    Cycle(String),
+    Edge(String, String),
+}
+
+impl Finding {
+    /// Parses `cycle:ab12f3` or `edge:f:1-g:2`; hops may also be written as
+    /// `f.h1`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "Cannot explain '{}'; name a cycle:ID or an edge:f:1-g:2",
+                text
+            )
+        };
+        if let Some(id) = text.strip_prefix("cycle:") {
+            return match id.is_empty() {
+                true => Err(invalid()),
+                false => Ok(Finding::Cycle(id.to_string())),
+            };
+        }
+        let hops = text.strip_prefix("edge:").ok_or_else(invalid)?;
+        let (a, b) = hops
+            .split_once("--")
+            .or_else(|| hops.split_once('-'))
+            .ok_or_else(invalid)?;
+        let hop = |hop: &str| -> Result<String, String> {
+            let (function, position) = hop
+                .trim()
+                .split_once(':')
+                .or_else(|| {
+                    let (function, position) = hop.trim().rsplit_once('.')?;
+                    Some((function, position.strip_prefix('h')?))
+                })
+                .ok_or_else(invalid)?;
+            let position: usize = position.parse().map_err(|_| invalid())?;
+            Ok(format!("{}:{}", function, position))
+        };
+        Ok(Finding::Edge(hop(a)?, hop(b)?))
+    }
+}
+
+/// A finding, explained.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Explanation {
+    Cycle {
+        id: String,
+        /// The hops the cycle passes, as `f:1 -- g:1`
+        hops: String,
+        explanation: CycleExplanation,
+        /// The C-edges of the cycle, any of which breaks it once dropped
+        c_edges: Vec<EdgeExplanation>,
+    },
+    Edge(EdgeExplanation),
+}
+
+/// Why a pair of hops has a C-edge or not, and what dropping it takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdgeExplanation {
+    pub edge: Edge,
+    /// The hops, as `f:1 -- g:2`
+    pub hops: String,
+    /// Whether the C-edge is in the graph
+    pub kept: bool,
+    /// The conflicts of the hops, each worded as in a cycle step
+    pub conflicts: Vec<(Conflict, String)>,
+    /// Each pruning step that failed to drop a kept edge, or the reason an
+    /// edge was dropped
+    pub pruning: Vec<String>,
+    /// What verification has to prove to drop a kept edge
+    pub obligation: Option<String>,
+}
+
+impl SCGraph {
+    /// Explains a finding, with the mixed cycles searched within `limits`.
+    pub fn explain(
+        &self,
+        finding: &Finding,
+        cfg: &CfgProgram,
+        limits: &CycleLimits,
+    ) -> Result<Explanation, String> {
+        match finding {
+            Finding::Cycle(prefix) => {
+                let cycles = self.find_mixed_cycles_within(limits).cycles;
+                let ids: Vec<String> = cycles
+                    .iter()
+                    .map(|cycle| self.cycle_id(cycle, cfg))
+                    .collect();
+                let matching: Vec<usize> = (0..ids.len())
+                    .filter(|&index| ids[index].starts_with(prefix.as_str()))
+                    .collect();
+                let index = match matching[..] {
+                    [index] => index,
+                    [] if ids.is_empty() => {
+                        return Err(format!("No mixed cycle '{}'; there are none", prefix))
+                    }
+                    [] => {
+                        return Err(format!(
+                            "No mixed cycle '{}'; the mixed cycles are {}",
+                            prefix,
+                            ids.join(", ")
+                        ))
+                    }
+                    _ => {
+                        return Err(format!(
+                            "'{}' starts the ids of {} mixed cycles; give more of it",
+                            prefix,
+                            matching.len()
+                        ))
+                    }
+                };
+                let cycle = &cycles[index];
+                let explanation = self.explain_cycle(cycle, cfg);
+                let c_edges = explanation
+                    .steps
+                    .iter()
+                    .filter(|step| step.edge_type == EdgeType::C)
+                    .filter_map(|step| {
+                        self.edges.iter().find(|edge| {
+                            edge.edge_type == EdgeType::C
+                                && ([edge.source, edge.target] == [step.from, step.to]
+                                    || [edge.target, edge.source] == [step.from, step.to])
+                        })
+                    })
+                    .map(|edge| self.explain_edge(edge, cfg))
+                    .collect();
+                Ok(Explanation::Cycle {
+                    id: ids[index].clone(),
+                    hops: self.describe_cycle(cycle, cfg),
+                    explanation,
+                    c_edges,
+                })
+            }
+            Finding::Edge(a, b) => {
+                for hop in [a, b] {
+                    if !self
+                        .nodes
+                        .iter()
+                        .any(|(node, _)| self.hop_name(node, cfg) == *hop)
+                    {
+                        return Err(format!(
+                            "No hop '{}'; name hops as function:position, e.g. f:1",
+                            hop
+                        ));
+                    }
+                }
+                let edge = self
+                    .justifications
+                    .keys()
+                    .find(|edge| {
+                        let hops = [edge.source, edge.target].map(|node| self.hop_name(node, cfg));
+                        hops == [a.clone(), b.clone()] || hops == [b.clone(), a.clone()]
+                    })
+                    .ok_or_else(|| {
+                        format!(
+                            "{} and {} cannot conflict: they run on different nodes, in one \
+                             transaction or in two that only read",
+                            a, b
+                        )
+                    })?;
+                Ok(Explanation::Edge(self.explain_edge(edge, cfg)))
+            }
+        }
+    }
+
+    /// Explains a candidate C-edge, kept or dropped.
+    pub fn explain_edge(&self, edge: &Edge, cfg: &CfgProgram) -> EdgeExplanation {
+        let mut hops = [edge.source, edge.target].map(|node| self.hop_name(node, cfg));
+        hops.sort();
+        let kept = self.edges.contains(edge);
+        let justification = self.justifications.get(edge);
+        let conflicts: Vec<(Conflict, String)> = justification
+            .map(|justification| {
+                justification
+                    .conflicts
+                    .iter()
+                    .map(|conflict| (conflict.clone(), describe_step(conflict, true, cfg)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let reason = justification.map(|justification| justification.reason.clone());
+        if !kept {
+            return EdgeExplanation {
+                edge: edge.clone(),
+                hops: hops.join(" -- "),
+                kept,
+                conflicts,
+                pruning: reason.into_iter().collect(),
+                obligation: None,
+            };
+        }
+
+        let mut pruning = Vec::new();
+        if conflicts
+            .iter()
+            .all(|(conflict, _)| conflict.alias == KeyAlias::Must)
+        {
+            pruning.push("the accesses address the same record".to_string());
+        } else {
+            pruning.push(format!(
+                "the keys may address the same record, so {} does not apply",
+                DISJOINT_KEYS.cite().trim_matches(|c| c == '(' || c == ')')
+            ));
+        }
+        let lemmas: Vec<String> = LEMMAS
+            .iter()
+            .filter(|lemma| lemma.name != DISJOINT_KEYS.name)
+            .map(|lemma| format!("`{}`", lemma.name))
+            .collect();
+        pruning.push(format!(
+            "no lemma of commuting updates ({}) covers every conflict",
+            lemmas.join(", ")
+        ));
+        let hop_pair = HopPair {
+            function_a: self.nodes[edge.source].cfg_function_id,
+            hop_a: self.nodes[edge.source].cfg_hop_id,
+            function_b: self.nodes[edge.target].cfg_function_id,
+            hop_b: self.nodes[edge.target].cfg_hop_id,
+        };
+        pruning.push(match self.pair_checks.get(edge) {
+            Some(PairCheck::Conflict(witness)) => format!(
+                "executing both orders symbolically found a start state on which they end \
+                 differently: {}",
+                hop_pair.format_witness(cfg, witness)
+            ),
+            Some(PairCheck::Unknown) => "executing both orders symbolically showed neither that \
+                                         they end the same nor a start state on which they differ"
+                .to_string(),
+            Some(PairCheck::Commutative) | None => {
+                "the hops were not executed symbolically".to_string()
+            }
+        });
+
+        EdgeExplanation {
+            edge: edge.clone(),
+            hops: hops.join(" -- "),
+            kept,
+            conflicts,
+            pruning,
+            obligation: Some(self.obligation(edge, cfg)),
+        }
+    }
+
+    /// What the prover is asked about a C-edge, in words.
+    fn obligation(&self, edge: &Edge, cfg: &CfgProgram) -> String {
+        let unit = create_verification_unit(edge.clone(), cfg, self);
+        let hop = |function, hop| {
+            let position = cfg.functions[function]
+                .hop_order
+                .iter()
+                .position(|&h| h == hop)
+                .unwrap_or_default();
+            format!("{}:{}", cfg.functions[function].name, position + 1)
+        };
+        let (a, b) = (
+            hop(unit.function_a, unit.final_a),
+            hop(unit.function_b, unit.final_b),
+        );
+        let mut before: Vec<String> = unit
+            .prefix_a
+            .iter()
+            .map(|&h| hop(unit.function_a, h))
+            .chain(unit.prefix_b.iter().map(|&h| hop(unit.function_b, h)))
+            .collect();
+        let after = match before.pop() {
+            None => String::new(),
+            Some(last) if before.is_empty() => format!("after {}, ", last),
+            Some(last) => format!(
+                "after {} and {} in any order ({} to check{}), ",
+                before.join(", "),
+                last,
+                unit.merges.len(),
+                match unit.reduced {
+                    0 => String::new(),
+                    reduced => format!(", {} more left out as equivalent", reduced),
+                }
+            ),
+        };
+        let mut tables: Vec<&str> = unit
+            .relevant_tables
+            .iter()
+            .map(|&table| cfg.tables[table].name.as_str())
+            .collect();
+        tables.sort();
+        let state = match tables.len() {
+            0 => "the same values".to_string(),
+            _ => format!("the same {} and values", tables.join(", ")),
+        };
+        format!(
+            "that {}running {} then {} ends as {} then {} does, with {} for the rest of \
+             each transaction to read",
+            after, a, b, b, a, state
+        )
+    }
+
+    /// Walks a cycle, as `find_mixed_cycles` returns it, from its first hop.
+    pub fn explain_cycle(&self, cycle: &[CfgHopId], cfg: &CfgProgram) -> CycleExplanation {
+        let nodes: Vec<SCGraphNodeId> = cycle
+            .iter()
+            .filter_map(|&hop| self.get_sc_node_id(hop))
+            .collect();
+        let mut steps = Vec::new();
+        for (position, &from) in nodes.iter().enumerate() {
+            let to = nodes[(position + 1) % nodes.len()];
+            let Some(edge) = self.edges.iter().find(|edge| {
+                (edge.source, edge.target) == (from, to) || (edge.source, edge.target) == (to, from)
+            }) else {
+                continue;
+            };
+            let text = match edge.edge_type {
+                EdgeType::S => self.explain_s_edge(from, to, cfg),
+                EdgeType::C => self.explain_c_edge(edge, from, cfg),
+            };
+            steps.push(CycleStep {
+                from,
+                to,
+                edge_type: edge.edge_type.clone(),
+                text,
+            });
+        }
+
+        let mut functions: Vec<&str> = Vec::new();
+        for &node in &nodes {
+            let name = cfg.functions[self.nodes[node].cfg_function_id]
+                .name
+                .as_str();
+            if !functions.contains(&name) {
+                functions.push(name);
+            }
+        }
+        let names = match functions.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+            _ => functions.concat(),
+        };
+        let conclusion = format!(
+            "Each conflict orders one transaction before another, and the S-edges tie the \
+             hops of each transaction into one, so {} would each have to run both before and \
+             after another: no serial order of them allows this interleaving",
+            names
+        );
+        CycleExplanation { steps, conclusion }
+    }
+
+    /// `f runs H0 at file:3:5, then H1 at file:4:5`, whichever way the step goes.
+    fn explain_s_edge(&self, from: SCGraphNodeId, to: SCGraphNodeId, cfg: &CfgProgram) -> String {
+        let function = &cfg.functions[self.nodes[from].cfg_function_id];
+        let (from_hop, to_hop) = (self.nodes[from].cfg_hop_id, self.nodes[to].cfg_hop_id);
+        let position = |hop| function.hop_order.iter().position(|&h| h == hop);
+        let (first, second) = if position(from_hop) <= position(to_hop) {
+            (from_hop, to_hop)
+        } else {
+            (to_hop, from_hop)
+        };
+        format!(
+            "{} runs H{} at {}, then H{} at {}, in one transaction",
+            function.name,
+            first.index(),
+            function.hops[first].span,
+            second.index(),
+            function.hops[second].span
+        )
+    }
+
+    /// `f writes T.n at file:3:5, g reads it at file:9:5 (the same record)`,
+    /// for each conflict of the edge, seen from the hop the step leaves.
+    fn explain_c_edge(&self, edge: &Edge, from: SCGraphNodeId, cfg: &CfgProgram) -> String {
+        let Some(justification) = self.justifications.get(edge) else {
+            return String::new();
+        };
+        if justification.conflicts.is_empty() {
+            return justification.reason.clone();
+        }
+        let forward = edge.source == from;
+        let conflicts: Vec<String> = justification
+            .conflicts
+            .iter()
+            .map(|conflict| describe_step(conflict, forward, cfg))
+            .collect();
+        conflicts.join("; ")
+    }
+}
+
+fn describe_step(conflict: &Conflict, forward: bool, cfg: &CfgProgram) -> String {
+    let (from, to) = if forward {
+        (conflict.source, conflict.target)
+    } else {
+        (conflict.target, conflict.source)
+    };
+    let (from_verb, to_verb) = match (conflict.kind, forward) {
+        (ConflictKind::WriteWrite, _) => ("writes", "writes"),
+        (ConflictKind::ReadWrite, true) | (ConflictKind::WriteRead, false) => ("reads", "writes"),
+        (ConflictKind::WriteRead, true) | (ConflictKind::ReadWrite, false) => ("writes", "reads"),
+    };
+    let record = match conflict.alias {
+        KeyAlias::Must => "the same record",
+        _ => "possibly the same record",
+    };
+    format!(
+        "{} {} {}.{} at {}, {} {} it at {} ({})",
+        cfg.functions[from.function].name,
+        from_verb,
+        cfg.tables[conflict.table].name,
+        cfg.fields[conflict.field].name,
+        cfg.site_span(from),
+        cfg.functions[to.function].name,
+        to_verb,
+        cfg.site_span(to),
+        record
+    )
+}
@@ -0,0 +1,112 @@
+//! Choosing the C-edges to verify first.
+//!
+//! Every mixed cycle has to lose a C-edge for the chopping to be serializable,
+//! so a set of C-edges that hits every cycle is a verification plan: if those
+//! edges verify, no cycle is left, whatever becomes of the others. The smallest
+//! such set is a minimum hitting set, which is NP-hard to find, so the set is
+//! built greedily, each time taking the C-edge on the most cycles not yet hit,
+//! and then edges that only hit cycles other edges of the set also hit are
+//! dropped again. Ties go to the edge listed first in the graph.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let source = include_str!("../../examples/sc_graph_demos/crossed_writers.transact");
+//! let h = "void h(int id) { hop on A { T[id: id].n = 3; } hop on B { U[id: id].n = 3; } }";
+//! let program = parse_and_analyze(&format!("{}{}", source, h)).unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let cycles = sc_graph.find_mixed_cycles();
+//!
+//! // The cycles through two functions share no C-edge, so three edges are
+//! // needed, and every cycle passes a C-edge between hops on A
+//! let plan = sc_graph.c_edge_hitting_set(&cycles);
+//! assert_eq!(plan.len(), 3);
+//! assert_eq!(cycles.len(), 12);
+//! let hop = |node| sc_graph.nodes[node].cfg_hop_id;
+//! assert!(cycles.iter().all(|cycle| plan
+//!     .iter()
+//!     .any(|edge| cycle.contains(&hop(edge.source)) && cycle.contains(&hop(edge.target)))));
+//! ```
+
+use crate::cfg::HopId as CfgHopId;
+use crate::sc_graph::{Edge, EdgeType, SCGraph};
+use std::collections::{HashMap, HashSet};
+
+impl SCGraph {
+    /// A small set of C-edges such that every one of the cycles passes one of
+    /// them, in the order of `edges`. Cycles are given as the hops they pass,
+    /// as `find_mixed_cycles` returns them; cycles without a C-edge of the
+    /// graph cannot be hit and are left out.
+    pub fn c_edge_hitting_set(&self, cycles: &[Vec<CfgHopId>]) -> Vec<Edge> {
+        let c_edges: Vec<&Edge> = self
+            .edges
+            .iter()
+            .filter(|edge| edge.edge_type == EdgeType::C)
+            .collect();
+        // C-edges are stored with the smaller node first
+        let edge_indices: HashMap<(usize, usize), usize> = c_edges
+            .iter()
+            .enumerate()
+            .map(|(edge_index, edge)| ((edge.source.index(), edge.target.index()), edge_index))
+            .collect();
+        // The cycles each C-edge is on
+        let mut hits: Vec<HashSet<usize>> = vec![HashSet::new(); c_edges.len()];
+        let mut hittable = HashSet::new();
+        for (cycle_index, cycle) in cycles.iter().enumerate() {
+            let nodes: Vec<_> = cycle
+                .iter()
+                .filter_map(|&hop| self.get_sc_node_id(hop))
+                .collect();
+            for (position, &node) in nodes.iter().enumerate() {
+                let next = nodes[(position + 1) % nodes.len()];
+                let ends = (
+                    node.index().min(next.index()),
+                    node.index().max(next.index()),
+                );
+                if let Some(&edge_index) = edge_indices.get(&ends) {
+                    hits[edge_index].insert(cycle_index);
+                    hittable.insert(cycle_index);
+                }
+            }
+        }
+
+        // Greedily take the edge on the most cycles not yet hit
+        let mut chosen = Vec::new();
+        let mut unhit = hittable;
+        while !unhit.is_empty() {
+            let (best, _) = hits
+                .iter()
+                .enumerate()
+                .map(|(edge_index, on)| (edge_index, on.intersection(&unhit).count()))
+                .rev()
+                .max_by_key(|&(_, count)| count)
+                .expect("a cycle that can be hit is on some C-edge");
+            unhit.retain(|cycle| !hits[best].contains(cycle));
+            chosen.push(best);
+        }
+
+        // Drop edges whose cycles the rest of the set hits as well, latest
+        // choices first since they tend to hit the fewest
+        let mut index = chosen.len();
+        while index > 0 {
+            index -= 1;
+            let edge_index = chosen[index];
+            let redundant = hits[edge_index].iter().all(|cycle| {
+                chosen
+                    .iter()
+                    .any(|&other| other != edge_index && hits[other].contains(cycle))
+            });
+            if redundant {
+                chosen.remove(index);
+            }
+        }
+
+        chosen.sort_unstable();
+        chosen
+            .into_iter()
+            .map(|edge_index| c_edges[edge_index].clone())
+            .collect()
+    }
+}
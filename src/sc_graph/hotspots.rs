@@ -0,0 +1,96 @@
+//! C-edges summed up per table and field.
+//!
+//! A field that most C-edges conflict over is where a schema change, such as
+//! splitting a hot counter, or a different chopping pays off. Each C-edge is
+//! counted once for every table and field among the accesses it conflicts
+//! through, so the counts of a table's fields may add up to more than the
+//! table's own.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; int m; }
+//!     void f(int id) { hop on A { T[id: id].n = 1; T[id: id].m = 1; } }
+//!     void g(int id) { hop on A { T[id: id].n = 2; } }
+//!     void h(int id) { hop on A { int m = T[id: id].m; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//!
+//! // f -- g over T.n, f -- h over T.m
+//! let hotspots = sc_graph.hotspots();
+//! assert_eq!(hotspots.len(), 1);
+//! assert_eq!(cfg.tables[hotspots[0].table].name, "T");
+//! assert_eq!(hotspots[0].c_edges, 2);
+//! let fields: Vec<_> = hotspots[0]
+//!     .fields
+//!     .iter()
+//!     .map(|&(field, c_edges)| (cfg.fields[field].name.as_str(), c_edges))
+//!     .collect();
+//! assert_eq!(fields, [("n", 1), ("m", 1)]);
+//! ```
+
+use crate::cfg::{FieldId as CfgFieldId, TableId as CfgTableId};
+use crate::sc_graph::{EdgeType, SCGraph};
+use std::collections::{BTreeSet, HashMap};
+
+/// The C-edges conflicting over a table, and over each of its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableHotspot {
+    pub table: CfgTableId,
+    pub c_edges: usize,
+    /// The fields of the table with C-edges, most C-edges first
+    pub fields: Vec<(CfgFieldId, usize)>,
+}
+
+impl SCGraph {
+    /// The tables that C-edges conflict over, most C-edges first.
+    pub fn hotspots(&self) -> Vec<TableHotspot> {
+        let mut tables: HashMap<CfgTableId, usize> = HashMap::new();
+        let mut fields: HashMap<(CfgTableId, CfgFieldId), usize> = HashMap::new();
+        for edge in self.edges.iter().filter(|e| e.edge_type == EdgeType::C) {
+            let Some(justification) = self.justifications.get(edge) else {
+                continue;
+            };
+            let conflicts: BTreeSet<(CfgTableId, CfgFieldId)> = justification
+                .conflicts
+                .iter()
+                .map(|conflict| (conflict.table, conflict.field))
+                .collect();
+            let conflict_tables: BTreeSet<CfgTableId> =
+                conflicts.iter().map(|&(table, _)| table).collect();
+            for table in conflict_tables {
+                *tables.entry(table).or_default() += 1;
+            }
+            for key in conflicts {
+                *fields.entry(key).or_default() += 1;
+            }
+        }
+
+        let mut hotspots: Vec<TableHotspot> = tables
+            .into_iter()
+            .map(|(table, c_edges)| {
+                let mut table_fields: Vec<(CfgFieldId, usize)> = fields
+                    .iter()
+                    .filter(|((field_table, _), _)| *field_table == table)
+                    .map(|(&(_, field), &count)| (field, count))
+                    .collect();
+                table_fields
+                    .sort_by_key(|&(field, count)| (std::cmp::Reverse(count), field.index()));
+                TableHotspot {
+                    table,
+                    c_edges,
+                    fields: table_fields,
+                }
+            })
+            .collect();
+        hotspots.sort_by_key(|hotspot| (std::cmp::Reverse(hotspot.c_edges), hotspot.table.index()));
+        hotspots
+    }
+}
@@ -0,0 +1,226 @@
+//! Patching an SC-graph after some functions changed.
+//!
+//! When a function is edited, only the vertices of its hops and the edges
+//! incident to them can change: edges between two unchanged hops depend on
+//! those hops alone. `update` keeps these edges, with their justifications and
+//! symbolic checks, and recomputes the rest, so that a following
+//! `eliminate_commuting_edges` only checks the new C-edges. Vertices are laid
+//! out as `SCGraph::new` lays them out, so the patched graph numbers its nodes
+//! the same as one built from scratch.
+//!
+//! Mixed cycles are patched the same way. The graph is undirected, so its
+//! strongly connected components are its connected components, and a cycle
+//! stays within one. A cycle in a component without new vertices passes
+//! unchanged edges only and is kept; the components with new vertices are
+//! searched again.
+//!
+//! ```
+//! use FMitF_rs::ast::incremental::{reparse, Reparsed, TextEdit};
+//! use FMitF_rs::sc_graph::{CycleLimits, SCGraph};
+//! use FMitF_rs::CfgBuilder;
+//! use std::collections::HashSet;
+//!
+//! let h = "void h(int id) { hop on A { T[id: id].n = 3; } hop on B { U[id: id].n = 3; } }";
+//! let source = format!(
+//!     "{}{}",
+//!     include_str!("../../examples/sc_graph_demos/crossed_writers.transact"),
+//!     h
+//! );
+//! let mut program = FMitF_rs::parse_and_analyze(&source).unwrap();
+//! let mut ctx = CfgBuilder::build_from_program(&program).unwrap();
+//! let mut sc_graph = SCGraph::new(&ctx.program);
+//! let limits = CycleLimits::default();
+//! let cycles = sc_graph.find_mixed_cycles_within(&limits);
+//! assert_eq!(cycles.cycles.len(), 12);
+//!
+//! // `g` now writes another field on A, so it only conflicts on B
+//! let start = source.find("n = 2").unwrap();
+//! let edit = TextEdit { file: 0, range: start..start + 1, text: "m".to_string() };
+//! let result = reparse(&mut program, &edit, &Default::default(), &Default::default()).unwrap();
+//! let Reparsed::Function { new, .. } = result else { unreachable!() };
+//! CfgBuilder::rebuild_functions(&program, &mut ctx, &HashSet::from([new])).unwrap();
+//!
+//! let g = ctx.function_map[&FMitF_rs::ast::Symbol::intern("g")];
+//! let new_nodes = sc_graph.update(&ctx.program, &HashSet::from([g]));
+//! assert_eq!(new_nodes.len(), 2);
+//! let cycles = sc_graph.update_mixed_cycles(&cycles, &new_nodes, &limits);
+//!
+//! let fresh = SCGraph::new(&ctx.program);
+//! assert_eq!(sc_graph.stats(), fresh.stats());
+//! assert_eq!(cycles, fresh.find_mixed_cycles_within(&limits));
+//! // f and h still conflict on A, and on B either directly or through g
+//! assert_eq!(cycles.cycles.len(), 2);
+//! ```
+
+use crate::cfg::{CfgProgram, FunctionId as CfgFunctionId};
+use crate::sc_graph::{CycleLimits, Edge, MixedCycles, SCGraph, SCGraphNode, SCGraphNodeId};
+use id_arena::Arena;
+use std::collections::{HashMap, HashSet};
+
+impl SCGraph {
+    /// Patches the graph for a CFG program in which the `changed` functions
+    /// were rebuilt, as `CfgBuilder::rebuild_functions` does, and returns the
+    /// vertices of their hops. Functions whose hops do not match the vertices
+    /// of the graph count as changed too, so new functions need not be listed;
+    /// when the functions themselves were rebuilt, so is the whole graph.
+    pub fn update(
+        &mut self,
+        cfg_program: &CfgProgram,
+        changed: &HashSet<CfgFunctionId>,
+    ) -> Vec<SCGraphNodeId> {
+        let stale = self
+            .nodes
+            .iter()
+            .any(|(_, node)| cfg_program.functions.get(node.cfg_function_id).is_none());
+        if stale {
+            let exclusions = std::mem::take(&mut self.exclusions);
+            *self = SCGraph::new(cfg_program);
+            self.exclude(&exclusions, cfg_program);
+            return self.nodes.iter().map(|(id, _)| id).collect();
+        }
+
+        let mut changed = changed.clone();
+        for (_, node) in self.nodes.iter() {
+            if cfg_program.functions[node.cfg_function_id]
+                .hops
+                .get(node.cfg_hop_id)
+                .is_none()
+            {
+                changed.insert(node.cfg_function_id);
+            }
+        }
+        for (cfg_func_id, cfg_function) in cfg_program.functions.iter() {
+            if cfg_function
+                .hops
+                .iter()
+                .any(|(cfg_hop_id, _)| !self.cfg_hop_to_sc_node.contains_key(&cfg_hop_id))
+            {
+                changed.insert(cfg_func_id);
+            }
+        }
+
+        // Lay out the vertices as `new` does, carrying over unchanged ones
+        let mut nodes = Arena::new();
+        let mut cfg_hop_to_sc_node = HashMap::new();
+        let mut kept = HashMap::new();
+        let mut new_nodes = Vec::new();
+        for (cfg_func_id, cfg_function) in cfg_program.functions.iter() {
+            for (cfg_hop_id, cfg_hop) in cfg_function.hops.iter() {
+                let sc_node_id = nodes.alloc(SCGraphNode {
+                    cfg_hop_id,
+                    cfg_function_id: cfg_func_id,
+                    cfg_node_id: cfg_hop.node_id,
+                });
+                cfg_hop_to_sc_node.insert(cfg_hop_id, sc_node_id);
+                if changed.contains(&cfg_func_id) {
+                    new_nodes.push(sc_node_id);
+                } else {
+                    kept.insert(self.cfg_hop_to_sc_node[&cfg_hop_id], sc_node_id);
+                }
+            }
+        }
+
+        let remap = |edge: &Edge| {
+            Some(Edge::new(
+                *kept.get(&edge.source)?,
+                *kept.get(&edge.target)?,
+                edge.edge_type.clone(),
+            ))
+        };
+        self.edges = self.edges.iter().filter_map(remap).collect();
+        self.justifications = std::mem::take(&mut self.justifications)
+            .into_iter()
+            .filter_map(|(edge, justification)| Some((remap(&edge)?, justification)))
+            .collect();
+        self.pair_checks = std::mem::take(&mut self.pair_checks)
+            .into_iter()
+            .filter_map(|(edge, check)| Some((remap(&edge)?, check)))
+            .collect();
+        self.nodes = nodes;
+        self.cfg_hop_to_sc_node = cfg_hop_to_sc_node;
+
+        for &cfg_func_id in &changed {
+            self.add_s_edges(&cfg_program.functions[cfg_func_id]);
+        }
+        self.add_c_edges(cfg_program, |function1, function2| {
+            changed.contains(&function1) || changed.contains(&function2)
+        });
+        self.apply_exclusions(cfg_program);
+        new_nodes
+    }
+
+    /// The mixed cycles after an `update` that added `new_nodes`, from the
+    /// ones found before it within the same limits. Only the components with
+    /// new vertices are searched; when the earlier search was truncated,
+    /// everything is.
+    pub fn update_mixed_cycles(
+        &self,
+        previous: &MixedCycles,
+        new_nodes: &[SCGraphNodeId],
+        limits: &CycleLimits,
+    ) -> MixedCycles {
+        if previous.truncated {
+            return self.find_mixed_cycles_within(limits);
+        }
+
+        let components = self.components();
+        let affected: HashSet<usize> = new_nodes
+            .iter()
+            .map(|node| components[node.index()])
+            .collect();
+
+        // Cycles through removed hops no longer map to vertices
+        let kept: Vec<Vec<usize>> = previous
+            .cycles
+            .iter()
+            .filter_map(|cycle| {
+                cycle
+                    .iter()
+                    .map(|&hop| self.get_sc_node_id(hop).map(|node| node.index()))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .filter(|cycle| !affected.contains(&components[cycle[0]]))
+            .collect();
+        if limits.max_cycles.is_some_and(|max| kept.len() >= max) {
+            return self.sorted_cycles(kept, true);
+        }
+
+        let starts: Vec<bool> = components
+            .iter()
+            .map(|component| affected.contains(component))
+            .collect();
+        let remaining = CycleLimits {
+            max_cycles: limits.max_cycles.map(|max| max - kept.len()),
+            ..*limits
+        };
+        let (mut cycles, truncated) = self.search_cycles(&starts, &remaining);
+        cycles.extend(kept);
+        self.sorted_cycles(cycles, truncated)
+    }
+
+    /// The connected component of each vertex, by node index.
+    fn components(&self) -> Vec<usize> {
+        let mut neighbors = vec![Vec::new(); self.nodes.len()];
+        for edge in &self.edges {
+            neighbors[edge.source.index()].push(edge.target.index());
+            neighbors[edge.target.index()].push(edge.source.index());
+        }
+        let mut components = vec![usize::MAX; self.nodes.len()];
+        for root in 0..self.nodes.len() {
+            if components[root] != usize::MAX {
+                continue;
+            }
+            components[root] = root;
+            let mut stack = vec![root];
+            while let Some(node) = stack.pop() {
+                for &next in &neighbors[node] {
+                    if components[next] == usize::MAX {
+                        components[next] = root;
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        components
+    }
+}
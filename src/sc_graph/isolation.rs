@@ -0,0 +1,139 @@
+//! The isolation level the chopped workload is checked against.
+//!
+//! Under serializability every mixed cycle may let a chopped run do what no
+//! serial run of the whole transactions does. Snapshot isolation admits more
+//! runs of the whole transactions: two of them may each read what the other
+//! overwrites, which is write skew. A run of the pieces that only does that
+//! is one snapshot isolation admits anyway, so against it a mixed cycle
+//! counts only when it can be taken in a direction with no two
+//! anti-dependency C-edges in a row, S-edges between them aside. A C-edge is
+//! an anti-dependency in a direction when each of its conflicts is a read of
+//! the hop it leaves and a write of the hop it enters; C-edges without a
+//! justification, as in graphs with merged hops, count as dependencies. Hops
+//! of a transaction run in order, so a run can only take a cycle in a
+//! direction that goes back along some S-edge.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{Isolation, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table X on A { primary int id; int n; int m; }
+//!     table Y on B { primary int id; int n; int m; int k; }
+//!     void f(int id) { hop on A { X[id: id].m = X[id: id].n; } hop on B { Y[id: id].n = 1; } }
+//!     void g(int id) { hop on B { Y[id: id].m = Y[id: id].n; } hop on A { X[id: id].n = 2; } }
+//!     void h(int id) { hop on A { X[id: id].n = 3; } hop on B { Y[id: id].k = Y[id: id].n; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let mut sc_graph = SCGraph::new(&cfg);
+//! assert_eq!(sc_graph.find_mixed_cycles().len(), 6);
+//!
+//! // f and g each read what the other writes: write skew, which snapshot
+//! // isolation allows. Run between the hops of f, h sees what f wrote and f
+//! // what h wrote, which it does not.
+//! sc_graph.isolation = Isolation::Snapshot;
+//! let cycles = sc_graph.find_mixed_cycles();
+//! assert_eq!(cycles.len(), 3);
+//! let function = |hop| sc_graph.nodes[sc_graph.get_sc_node_id(hop).unwrap()].cfg_function_id;
+//! let through_h = |cycle: &Vec<_>| cycle.iter().any(|&hop| cfg.functions[function(hop)].name == "h");
+//! assert!(cycles.iter().all(through_h));
+//! ```
+
+use crate::sc_graph::{ConflictKind, EdgeType, SCGraph};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What a run of the chopped transactions must be equivalent to.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Isolation {
+    /// A serial run of the whole transactions
+    #[default]
+    Serializable,
+    /// A run of the whole transactions under snapshot isolation, which
+    /// admits write skew
+    Snapshot,
+}
+
+impl Isolation {
+    /// The level as `--isolation` names it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Isolation::Serializable => "serializable",
+            Isolation::Snapshot => "snapshot",
+        }
+    }
+}
+
+/// The edges that decide whether a cycle is write skew, by node index.
+pub(super) struct SnapshotEdges {
+    /// The C-edges that are anti-dependencies, in the direction they are
+    anti: HashSet<(usize, usize)>,
+    /// The S-edges, from the earlier hop
+    successors: HashSet<(usize, usize)>,
+}
+
+impl SCGraph {
+    pub(super) fn snapshot_edges(&self) -> SnapshotEdges {
+        let mut edges = SnapshotEdges {
+            anti: HashSet::new(),
+            successors: HashSet::new(),
+        };
+        for edge in &self.edges {
+            let (source, target) = (edge.source.index(), edge.target.index());
+            if edge.edge_type == EdgeType::S {
+                edges.successors.insert((source, target));
+                continue;
+            }
+            let Some(justification) = self.justifications.get(edge) else {
+                continue;
+            };
+            let only = |kind| {
+                !justification.conflicts.is_empty()
+                    && justification
+                        .conflicts
+                        .iter()
+                        .all(|conflict| conflict.kind == kind)
+            };
+            if only(ConflictKind::ReadWrite) {
+                edges.anti.insert((source, target));
+            }
+            if only(ConflictKind::WriteRead) {
+                edges.anti.insert((target, source));
+            }
+        }
+        edges
+    }
+}
+
+impl SnapshotEdges {
+    /// Whether a cycle of node indices, with whether each step to the next
+    /// node takes a C-edge, has two anti-dependencies in a row in every
+    /// direction a run can take it.
+    pub(super) fn write_skew(&self, path: &[usize], c_steps: &[bool]) -> bool {
+        let forward: Vec<((usize, usize), bool)> = (0..path.len())
+            .map(|index| (path[index], path[(index + 1) % path.len()]))
+            .zip(c_steps.iter().copied())
+            .collect();
+        let backward: Vec<((usize, usize), bool)> = forward
+            .iter()
+            .rev()
+            .map(|&((from, to), c_step)| ((to, from), c_step))
+            .collect();
+        [forward, backward].iter().all(|steps| {
+            let runs = steps
+                .iter()
+                .any(|&((from, to), c_step)| !c_step && self.successors.contains(&(to, from)));
+            let anti: Vec<bool> = steps
+                .iter()
+                .filter(|(_, c_step)| *c_step)
+                .map(|(step, _)| self.anti.contains(step))
+                .collect();
+            !runs || (0..anti.len()).any(|index| anti[index] && anti[(index + 1) % anti.len()])
+        })
+    }
+}
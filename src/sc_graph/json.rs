@@ -0,0 +1,268 @@
+//! The SC-graph as JSON, for CI pipelines and dashboards.
+//!
+//! Vertices are the hops, with their function, CFG hop id, position among the
+//! hops of the function, database node, and the file the function comes from.
+//! Edges refer to vertices by id and carry their type; C-edges carry why they
+//! were kept, the accesses they conflict through and how hard they are likely
+//! to verify, and the C-edges dropped before verification are listed apart
+//! with why they were dropped. Cycles are the mixed cycles within the limits
+//! given, as vertex ids, with their stable ids in `cycle_ids`, each explained
+//! step by step in `explanations` and with a schedule realizing it in
+//! `schedules`, along with the C-edges to verify first, as indices into
+//! `edges`, and the level they were judged against in `isolation`. The pairs
+//! of functions and tables left out of conflict analysis are listed in
+//! `exclusions`.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{CycleLimits, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program =
+//!     parse_and_analyze(include_str!("../../examples/sc_graph_demos/crossed_writers.transact"))
+//!         .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let json = sc_graph.to_json(&cfg, &CycleLimits::default());
+//!
+//! assert_eq!(json["vertices"][1]["function"], "f");
+//! assert_eq!(json["vertices"][1]["position"], 1);
+//! assert_eq!(json["vertices"][1]["node"], "B");
+//! assert_eq!(json["edges"].as_array().unwrap().len(), 4);
+//! assert_eq!(json["cycles"], serde_json::json!([[0, 1, 3, 2]]));
+//! assert_eq!(json["verify_first"].as_array().unwrap().len(), 1);
+//! assert_eq!(json["explanations"][0]["steps"].as_array().unwrap().len(), 4);
+//! assert_eq!(json["schedules"][0].as_array().unwrap().len(), 4);
+//! assert_eq!(json["cycle_ids"][0], sc_graph.cycle_id(&sc_graph.find_mixed_cycles()[0], &cfg));
+//!
+//! let c_edge = &json["edges"][2];
+//! assert_eq!(c_edge["conflicts"][0]["kind"], "WW");
+//! assert_eq!(c_edge["conflicts"][0]["table"], "T");
+//! assert_eq!(c_edge["difficulty"], 10);
+//! ```
+
+use crate::ast::Span;
+use crate::cfg::{CfgProgram, HopId as CfgHopId};
+use crate::dataflow::KeyAlias;
+use crate::sc_graph::{ConflictExclusions, CycleLimits, Edge, EdgeType, Isolation, SCGraph};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Serialize)]
+struct GraphJson<'a> {
+    vertices: Vec<VertexJson<'a>>,
+    edges: Vec<EdgeJson<'a>>,
+    dropped_edges: Vec<EdgeJson<'a>>,
+    cycles: Vec<Vec<usize>>,
+    /// One per cycle, in the same order, as a baseline file lists them
+    cycle_ids: Vec<String>,
+    /// One per cycle, in the same order
+    explanations: Vec<ExplanationJson>,
+    /// One per cycle, in the same order: the vertex ids in an order that
+    /// realizes the cycle, or null if there is none
+    schedules: Vec<Option<Vec<usize>>>,
+    /// Whether the cycle search stopped at its limit, so more cycles may exist
+    cycles_truncated: bool,
+    isolation: Isolation,
+    verify_first: Vec<usize>,
+    exclusions: &'a ConflictExclusions,
+}
+
+#[derive(Serialize)]
+struct ExplanationJson {
+    steps: Vec<StepJson>,
+    conclusion: String,
+}
+
+#[derive(Serialize)]
+struct StepJson {
+    from: usize,
+    to: usize,
+    #[serde(rename = "type")]
+    edge_type: &'static str,
+    text: String,
+}
+
+#[derive(Serialize)]
+struct VertexJson<'a> {
+    id: usize,
+    function: &'a str,
+    hop: usize,
+    /// Index of the hop in the order the function runs its hops
+    position: usize,
+    node: &'a str,
+    /// The file the function was declared in, to tell linked workloads apart
+    file: Option<String>,
+    span: &'a Span,
+}
+
+#[derive(Serialize)]
+struct EdgeJson<'a> {
+    source: usize,
+    target: usize,
+    #[serde(rename = "type")]
+    edge_type: &'static str,
+    /// Why a C-edge was kept or dropped
+    reason: Option<&'a str>,
+    /// How hard a kept C-edge is likely to be to verify; see `c_edges_by_difficulty`
+    difficulty: Option<usize>,
+    conflicts: Vec<ConflictJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct ConflictJson<'a> {
+    /// `RW`, `WR` or `WW`, seen from the source vertex
+    kind: String,
+    table: &'a str,
+    field: &'a str,
+    /// Whether both accesses certainly address the same record
+    same_record: bool,
+    source: &'a Span,
+    target: &'a Span,
+}
+
+impl SCGraph {
+    /// The graph, its mixed cycles within the limits, and the C-edges to verify
+    /// first, as a JSON value.
+    pub fn to_json(&self, cfg: &CfgProgram, limits: &CycleLimits) -> serde_json::Value {
+        let difficulties: HashMap<Edge, usize> =
+            self.c_edges_by_difficulty(cfg).into_iter().collect();
+        let edge_json = |edge: &Edge| {
+            let justification = self.justifications.get(edge);
+            EdgeJson {
+                source: edge.source.index(),
+                target: edge.target.index(),
+                edge_type: edge_type_name(&edge.edge_type),
+                reason: justification.map(|justification| justification.reason.as_str()),
+                difficulty: difficulties.get(edge).copied(),
+                conflicts: justification
+                    .into_iter()
+                    .flat_map(|justification| &justification.conflicts)
+                    .map(|conflict| ConflictJson {
+                        kind: conflict.kind.to_string(),
+                        table: &cfg.tables[conflict.table].name,
+                        field: &cfg.fields[conflict.field].name,
+                        same_record: conflict.alias == KeyAlias::Must,
+                        source: cfg.site_span(conflict.source),
+                        target: cfg.site_span(conflict.target),
+                    })
+                    .collect(),
+            }
+        };
+
+        let mut dropped: Vec<&Edge> = self
+            .justifications
+            .iter()
+            .filter(|(_, justification)| !justification.conflicting)
+            .map(|(edge, _)| edge)
+            .collect();
+        dropped.sort_by_key(|edge| (edge.source.index(), edge.target.index()));
+
+        let edge_indices: HashMap<&Edge, usize> = self
+            .edges
+            .iter()
+            .enumerate()
+            .map(|(index, edge)| (edge, index))
+            .collect();
+        let hop_positions: HashMap<CfgHopId, usize> = cfg
+            .functions
+            .iter()
+            .flat_map(|(_, function)| function.hop_order.iter().copied().zip(0..))
+            .collect();
+
+        let mixed_cycles = self.find_mixed_cycles_within(limits);
+        let verify_first = self
+            .c_edge_hitting_set(&mixed_cycles.cycles)
+            .iter()
+            .filter_map(|edge| edge_indices.get(edge).copied())
+            .collect();
+
+        let json = GraphJson {
+            vertices: self
+                .nodes
+                .iter()
+                .map(|(id, node)| {
+                    let function = &cfg.functions[node.cfg_function_id];
+                    VertexJson {
+                        id: id.index(),
+                        function: &function.name,
+                        hop: node.cfg_hop_id.index(),
+                        position: hop_positions
+                            .get(&node.cfg_hop_id)
+                            .copied()
+                            .unwrap_or_default(),
+                        node: &cfg.nodes[node.cfg_node_id].name,
+                        file: function
+                            .span
+                            .file
+                            .as_ref()
+                            .map(|file| file.display().to_string()),
+                        span: &function.hops[node.cfg_hop_id].span,
+                    }
+                })
+                .collect(),
+            edges: self.edges.iter().map(edge_json).collect(),
+            dropped_edges: dropped.into_iter().map(edge_json).collect(),
+            cycles: mixed_cycles
+                .cycles
+                .iter()
+                .map(|cycle| {
+                    cycle
+                        .iter()
+                        .filter_map(|&hop| self.get_sc_node_id(hop))
+                        .map(|node| node.index())
+                        .collect()
+                })
+                .collect(),
+            cycle_ids: mixed_cycles
+                .cycles
+                .iter()
+                .map(|cycle| self.cycle_id(cycle, cfg))
+                .collect(),
+            explanations: mixed_cycles
+                .cycles
+                .iter()
+                .map(|cycle| {
+                    let explanation = self.explain_cycle(cycle, cfg);
+                    ExplanationJson {
+                        steps: explanation
+                            .steps
+                            .into_iter()
+                            .map(|step| StepJson {
+                                from: step.from.index(),
+                                to: step.to.index(),
+                                edge_type: edge_type_name(&step.edge_type),
+                                text: step.text,
+                            })
+                            .collect(),
+                        conclusion: explanation.conclusion,
+                    }
+                })
+                .collect(),
+            schedules: mixed_cycles
+                .cycles
+                .iter()
+                .map(|cycle| {
+                    self.cycle_schedule(cycle, cfg).map(|schedule| {
+                        schedule
+                            .steps
+                            .iter()
+                            .map(|step| step.node.index())
+                            .collect()
+                    })
+                })
+                .collect(),
+            cycles_truncated: mixed_cycles.truncated,
+            isolation: self.isolation,
+            verify_first,
+            exclusions: &self.exclusions,
+        };
+        serde_json::to_value(json).expect("the SC-graph serializes to JSON")
+    }
+}
+
+fn edge_type_name(edge_type: &EdgeType) -> &'static str {
+    match edge_type {
+        EdgeType::S => "S",
+        EdgeType::C => "C",
+    }
+}
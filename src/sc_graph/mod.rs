@@ -1,9 +1,56 @@
-use crate::cfg::{CfgProgram, FunctionId as CfgFunctionId, HopId as CfgHopId, NodeId as CfgNodeId};
+use crate::cfg::serialize::{self, arena, entries, id, id_map};
+use crate::cfg::{
+    CfgProgram, CfgSite, FieldId as CfgFieldId, FunctionCfg, FunctionId as CfgFunctionId,
+    HopId as CfgHopId, NodeId as CfgNodeId, TableId as CfgTableId,
+};
+use crate::dataflow::{analyze_keyed_accesses, AccessType, KeyAlias, KeyedAccess};
+use crate::pretty::format_keyed_access;
+use crate::verification::symbolic::PairCheck;
 use id_arena::{Arena, Id};
+use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+mod advisor;
+mod baseline;
+mod commutativity;
+mod cycles;
+mod deadlocks;
+mod delta;
+mod difficulty;
+mod exclusions;
+mod explain;
+mod hitting_set;
+mod hotspots;
+mod incremental;
+mod isolation;
+mod json;
+mod pairs;
+mod reorder;
+mod schedule;
+mod scope;
+mod stats;
+mod weights;
+pub use advisor::{HopMerge, MergeAdvice};
+pub use baseline::{Baseline, DEFAULT_BASELINE_FILE};
+pub use commutativity::{Lemma, LEMMAS};
+pub use cycles::{CycleLimits, MixedCycles};
+pub use deadlocks::{Lock, LockOrder, PotentialDeadlock};
+pub use delta::{AnswerDelta, GraphDelta};
+pub use difficulty::{LOOP_WEIGHT, STRING_OP_WEIGHT, TABLE_ACCESS_WEIGHT};
+pub use exclusions::ConflictExclusions;
+pub use explain::{CycleExplanation, CycleStep, EdgeExplanation, Explanation, Finding};
+pub use hotspots::TableHotspot;
+pub use isolation::Isolation;
+pub use pairs::PairConflicts;
+pub use reorder::{HopReordering, MAX_ORDERS};
+pub use schedule::{CycleSchedule, ScheduleStep};
+pub use stats::{CrossNodeFlow, GraphDensity, NodeStats, Spread, StatementCounts, WorkloadStats};
+pub use weights::{TransactionWeights, WeightCombination};
 
 /// Represents an edge type in the SC-Graph.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum EdgeType {
     /// Sequential edge, representing program order within a function.
     S,
@@ -13,13 +60,16 @@ pub enum EdgeType {
 }
 
 /// Represents a node in the SC-Graph, which corresponds to a Hop in the CFG.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SCGraphNode {
     /// The ID of the corresponding Hop in the CFG.
+    #[serde(with = "id")]
     pub cfg_hop_id: CfgHopId,
     /// The ID of the CFG Function this hop belongs to.
+    #[serde(with = "id")]
     pub cfg_function_id: CfgFunctionId,
     /// The ID of the CFG Node (e.g., server, client) this hop executes on.
+    #[serde(with = "id")]
     pub cfg_node_id: CfgNodeId,
 }
 
@@ -27,11 +77,13 @@ pub type SCGraphNodeId = Id<SCGraphNode>;
 
 /// Represents an edge in the SC-Graph.
 /// Edges are between SCGraphNodes.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Edge {
     /// The source SCGraphNodeId.
+    #[serde(with = "id")]
     pub source: SCGraphNodeId,
     /// The target SCGraphNodeId.
+    #[serde(with = "id")]
     pub target: SCGraphNodeId,
     /// The type of the edge (S or C).
     pub edge_type: EdgeType,
@@ -47,22 +99,194 @@ impl Edge {
     }
 }
 
+/// Why a C-edge between two hops was kept or dropped.
+///
+/// ```
+/// use FMitF_rs::sc_graph::{ConflictKind, SCGraph};
+/// use FMitF_rs::{parse_and_analyze, CfgBuilder};
+///
+/// let program = parse_and_analyze(
+///     r#"
+///     nodes { A }
+///     table T on A { primary int id; int n; int m; }
+///     void f() { hop on A { T[id: 1].n = 0; } }
+///     void g() { hop on A { T[id: 2].n = 0; } }
+///     void h(int id) { hop on A { T[id: id].n = 0; } }
+///     void k(int id) { hop on A { T[id: id].m = 0; } }
+///     "#,
+/// )
+/// .unwrap();
+/// let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+/// let sc_graph = SCGraph::new(&cfg);
+///
+/// // f and g write different records; h may write either; k writes another field
+/// assert_eq!(sc_graph.stats().2, 2);
+/// let mut dropped: Vec<_> = sc_graph.justifications.values().filter(|j| !j.conflicting).collect();
+/// dropped.sort_by_key(|j| j.reason.clone());
+/// assert_eq!(dropped.len(), 4);
+/// assert!(
+///     dropped[0].reason.ends_with("different records (lemma `disjoint-keys`)"),
+///     "{}",
+///     dropped[0].reason
+/// );
+/// assert!(dropped[1].reason.starts_with("no field written"), "{}", dropped[1].reason);
+///
+/// // Each kept edge conflicts through the two writes of T.n
+/// let kept: Vec<_> = sc_graph.justifications.values().filter(|j| j.conflicting).collect();
+/// assert!(kept.iter().all(|j| j.conflicts.len() == 1));
+/// assert_eq!(kept[0].conflicts[0].kind, ConflictKind::WriteWrite);
+/// assert_eq!(cfg.fields[kept[0].conflicts[0].field].name, "n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictJustification {
+    /// Whether the edge is kept: the hops may access the same field of the same
+    /// record, one of them writing it, and were not shown to commute
+    pub conflicting: bool,
+    pub reason: String,
+    /// The pairs of accesses that may address the same field of a record, one
+    /// of them writing, in statement order; kept for edges dropped as commuting
+    pub conflicts: Vec<Conflict>,
+}
+
+/// How the accesses of a conflict meet, seen from the source hop of the edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ConflictKind {
+    /// The source hop reads what the target hop writes
+    ReadWrite,
+    /// The source hop writes what the target hop reads
+    WriteRead,
+    /// Both hops write
+    WriteWrite,
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictKind::ReadWrite => write!(f, "RW"),
+            ConflictKind::WriteRead => write!(f, "WR"),
+            ConflictKind::WriteWrite => write!(f, "WW"),
+        }
+    }
+}
+
+/// A pair of accesses through which the hops of a C-edge may conflict.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    #[serde(with = "id")]
+    pub table: CfgTableId,
+    #[serde(with = "id")]
+    pub field: CfgFieldId,
+    /// The access of the source hop of the edge
+    pub source: CfgSite,
+    /// The access of the target hop of the edge
+    pub target: CfgSite,
+    /// `Must` when both address the same record, `May` when they might
+    pub alias: KeyAlias,
+}
+
+impl Conflict {
+    /// The conflict as `WW on T.n: f at file:3:5, g at file:7:5`.
+    pub fn describe(&self, cfg_program: &CfgProgram) -> String {
+        format!(
+            "{} on {}.{}: {} at {}, {} at {}",
+            self.kind,
+            cfg_program.tables[self.table].name,
+            cfg_program.fields[self.field].name,
+            cfg_program.functions[self.source.function].name,
+            cfg_program.site_span(self.source),
+            cfg_program.functions[self.target.function].name,
+            cfg_program.site_span(self.target)
+        )
+    }
+}
+
 /// The Serializability Conflict Graph.
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct SCGraph {
     /// Arena storing all nodes (hops) in the SC-Graph.
+    #[serde(with = "arena")]
     pub nodes: Arena<SCGraphNode>,
     /// List of all edges in the SC-Graph.
     pub edges: Vec<Edge>,
+    /// Why each candidate C-edge was kept in `edges` or dropped, from the field
+    /// accesses of its hops, whether their primary keys alias and, after
+    /// `eliminate_commuting_edges`, whether the hops commute.
+    #[serde(with = "entries")]
+    pub justifications: HashMap<Edge, ConflictJustification>,
+    /// What executing the hops of a C-edge symbolically found, for the edges
+    /// `eliminate_commuting_edges` checked, so verification need not repeat it.
+    #[serde(with = "entries")]
+    pub pair_checks: HashMap<Edge, PairCheck>,
+    /// The pairs of functions and tables left out of conflict analysis, as
+    /// `exclude` was given them.
+    pub exclusions: ConflictExclusions,
+    /// What runs of the chopped transactions must be equivalent to, which
+    /// decides the mixed cycles that count.
+    pub isolation: Isolation,
     /// Mapping from CFG HopId to SCGraphNodeId, used during construction and for lookups.
+    #[serde(with = "id_map")]
     cfg_hop_to_sc_node: HashMap<CfgHopId, SCGraphNodeId>,
 }
 
+impl Serialize for SCGraph {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SCGraph::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SCGraph {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serialize::read_arenas(|| SCGraph::deserialize(deserializer))
+    }
+}
+
 impl SCGraph {
     /// Creates a new SC-Graph from a given CFG program.
+    ///
+    /// C-edges come from the read and write sets of the hops in the CFG, as
+    /// `analyze_keyed_accesses` finds them, so accesses that constant folding
+    /// and dead code elimination removed, or that no run can reach, create no
+    /// edge, and neither do accesses whose keys resolve to different constants.
+    ///
+    /// Transactions that never write can only conflict with transactions that
+    /// do, so the hops of two read-only functions are not even a candidate
+    /// C-edge, and get no justification:
+    ///
+    /// ```
+    /// use FMitF_rs::sc_graph::SCGraph;
+    /// use FMitF_rs::{parse_and_analyze, CfgBuilder};
+    ///
+    /// let program = parse_and_analyze(
+    ///     r#"
+    ///     nodes { A }
+    ///     table T on A { primary int id; int n; }
+    ///     int f(int id) { hop on A { return T[id: id].n; } }
+    ///     int g(int id) { hop on A { return T[id: id].n; } }
+    ///     void h(int id) { hop on A { T[id: id].n = 1; } }
+    ///     "#,
+    /// )
+    /// .unwrap();
+    /// let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+    /// let sc_graph = SCGraph::new(&cfg);
+    ///
+    /// // f and g each conflict with h, but are never compared with each other
+    /// assert_eq!(sc_graph.stats().2, 2);
+    /// assert_eq!(sc_graph.justifications.len(), 2);
+    /// ```
     pub fn new(cfg_program: &CfgProgram) -> Self {
+        let mut sc_graph = Self::without_c_edges(cfg_program);
+
+        // 3. Add C-edges (Conflict edges between hops on the same CFG node but from different functions)
+        //    unless their accesses provably touch different fields or records
+        sc_graph.add_c_edges(cfg_program, |_, _| true);
+        sc_graph
+    }
+
+    /// The vertices and S-edges of the graph, for `add_c_edges` to add to.
+    fn without_c_edges(cfg_program: &CfgProgram) -> Self {
         let mut nodes_arena = Arena::new(); // Renamed to avoid conflict
-        let mut edges = Vec::new();
         let mut cfg_hop_to_sc_node_map = HashMap::new(); // Renamed to avoid conflict
 
         // 1. Create SCGraphNodes from CFG Hops
@@ -78,20 +302,59 @@ impl SCGraph {
             }
         }
 
+        let mut sc_graph = SCGraph {
+            nodes: nodes_arena,
+            edges: Vec::new(),
+            justifications: HashMap::new(),
+            pair_checks: HashMap::new(),
+            exclusions: ConflictExclusions::default(),
+            isolation: Isolation::default(),
+            cfg_hop_to_sc_node: cfg_hop_to_sc_node_map,
+        };
+
         // 2. Add S-edges (Sequential edges within each function's hops)
         for (_cfg_func_id, cfg_function) in cfg_program.functions.iter() {
-            let mut prev_cfg_hop_id: Option<CfgHopId> = None;
-            for &current_cfg_hop_id in &cfg_function.hop_order {
-                if let Some(prev_id) = prev_cfg_hop_id {
-                    let source_sc_node_id = cfg_hop_to_sc_node_map[&prev_id];
-                    let target_sc_node_id = cfg_hop_to_sc_node_map[&current_cfg_hop_id];
-                    edges.push(Edge::new(source_sc_node_id, target_sc_node_id, EdgeType::S));
-                }
-                prev_cfg_hop_id = Some(current_cfg_hop_id);
+            sc_graph.add_s_edges(cfg_function);
+        }
+        sc_graph
+    }
+
+    /// Adds the S-edges between consecutive hops of a function.
+    fn add_s_edges(&mut self, cfg_function: &FunctionCfg) {
+        let mut prev_cfg_hop_id: Option<CfgHopId> = None;
+        for &current_cfg_hop_id in &cfg_function.hop_order {
+            if let Some(prev_id) = prev_cfg_hop_id {
+                let source_sc_node_id = self.cfg_hop_to_sc_node[&prev_id];
+                let target_sc_node_id = self.cfg_hop_to_sc_node[&current_cfg_hop_id];
+                self.edges
+                    .push(Edge::new(source_sc_node_id, target_sc_node_id, EdgeType::S));
             }
+            prev_cfg_hop_id = Some(current_cfg_hop_id);
         }
+    }
 
-        // 3. Add C-edges (Conflict edges between hops on the same CFG node but from different functions)
+    /// Justifies a C-edge for each pair of hops on the same CFG node from
+    /// different functions that `include` accepts, adding the edges kept in
+    /// node order. Pairs of read-only functions never conflict, so are not
+    /// candidates; the others are justified in parallel.
+    fn add_c_edges(
+        &mut self,
+        cfg_program: &CfgProgram,
+        include: impl Fn(CfgFunctionId, CfgFunctionId) -> bool,
+    ) {
+        let accesses = analyze_keyed_accesses(cfg_program);
+        let read_only: HashSet<CfgFunctionId> = cfg_program
+            .functions
+            .iter()
+            .filter(|(_, function)| {
+                function.hops.iter().all(|(hop_id, _)| {
+                    accesses[&hop_id]
+                        .iter()
+                        .all(|access| access.access_type == AccessType::Read)
+                })
+            })
+            .map(|(function_id, _)| function_id)
+            .collect();
         let mut hop_ids_on_cfg_node: HashMap<CfgNodeId, Vec<CfgHopId>> = HashMap::new();
         for (_cfg_func_id, cfg_function) in cfg_program.functions.iter() {
             for (cfg_hop_id, cfg_hop) in cfg_function.hops.iter() {
@@ -102,167 +365,55 @@ impl SCGraph {
             }
         }
 
+        let mut candidates = Vec::new();
         for (_cfg_node_id, hop_ids) in hop_ids_on_cfg_node {
             for i in 0..hop_ids.len() {
                 for j in (i + 1)..hop_ids.len() {
                     let cfg_hop1_id = hop_ids[i];
                     let cfg_hop2_id = hop_ids[j];
 
-                    let sc_node1_id = cfg_hop_to_sc_node_map[&cfg_hop1_id];
-                    let sc_node2_id = cfg_hop_to_sc_node_map[&cfg_hop2_id];
+                    let sc_node1_id = self.cfg_hop_to_sc_node[&cfg_hop1_id];
+                    let sc_node2_id = self.cfg_hop_to_sc_node[&cfg_hop2_id];
+                    let function1 = self.nodes[sc_node1_id].cfg_function_id;
+                    let function2 = self.nodes[sc_node2_id].cfg_function_id;
 
                     // Check if hops are from different functions by looking at SCGraphNode properties
-                    if nodes_arena[sc_node1_id].cfg_function_id
-                        != nodes_arena[sc_node2_id].cfg_function_id
+                    if function1 != function2
+                        && !(read_only.contains(&function1) && read_only.contains(&function2))
+                        && include(function1, function2)
                     {
                         // For undirected graph, store only one edge per pair with consistent ordering
                         // Always store with smaller node ID first to avoid duplicates
                         let (source, target) = if sc_node1_id.index() < sc_node2_id.index() {
-                            (sc_node1_id, sc_node2_id)
+                            ((sc_node1_id, cfg_hop1_id), (sc_node2_id, cfg_hop2_id))
                         } else {
-                            (sc_node2_id, sc_node1_id)
+                            ((sc_node2_id, cfg_hop2_id), (sc_node1_id, cfg_hop1_id))
                         };
-                        edges.push(Edge::new(source, target, EdgeType::C));
+                        let edge = Edge::new(source.0, target.0, EdgeType::C);
+                        candidates.push((edge, source.1, target.1));
                     }
                 }
             }
         }
-
-        SCGraph {
-            nodes: nodes_arena,
-            edges,
-            cfg_hop_to_sc_node: cfg_hop_to_sc_node_map,
-        }
-    }
-
-    /// Finds unique simple cycles in the SC-Graph that contain at least one S-edge and one C-edge.
-    /// A cycle is represented by a list of CFG HopIds.
-    pub fn find_mixed_cycles(&self) -> Vec<Vec<CfgHopId>> {
-        let mut mixed_cycles = Vec::new();
-        let mut visited_paths_for_cycle_detection: HashSet<Vec<CfgHopId>> = HashSet::new();
-
-        for (start_sc_node_id, _start_node_data) in self.nodes.iter() {
-            let mut path: Vec<SCGraphNodeId> = vec![start_sc_node_id];
-            let mut visited_in_dfs: HashSet<SCGraphNodeId> = HashSet::new();
-            visited_in_dfs.insert(start_sc_node_id);
-
-            self.dfs_find_cycles(
-                start_sc_node_id, // current_sc_node_id
-                start_sc_node_id, // start_sc_node_id_for_cycle
-                None, // parent_sc_node_id (to prevent immediate backtrack in undirected graph)
-                &mut path,
-                &mut visited_in_dfs,
-                &mut mixed_cycles,
-                &mut visited_paths_for_cycle_detection,
-                0, // s_edges_count
-                0, // c_edges_count
-            );
-        }
-        mixed_cycles
-    }
-
-    fn dfs_find_cycles(
-        &self,
-        current_sc_node_id: SCGraphNodeId,
-        start_sc_node_id_for_cycle: SCGraphNodeId,
-        parent_sc_node_id: Option<SCGraphNodeId>, // Parent to avoid immediate backtrack
-        path: &mut Vec<SCGraphNodeId>,            // Path of SCGraphNodeIds
-        visited_in_dfs: &mut HashSet<SCGraphNodeId>,
-        mixed_cycles: &mut Vec<Vec<CfgHopId>>,
-        visited_paths_for_cycle_detection: &mut HashSet<Vec<CfgHopId>>,
-        s_edges_count: usize,
-        c_edges_count: usize,
-    ) {
-        for edge in &self.edges {
-            // For undirected graph, check both directions of each edge
-            let neighbor_sc_node_id = if edge.source == current_sc_node_id {
-                Some(edge.target)
-            } else if edge.target == current_sc_node_id {
-                Some(edge.source)
-            } else {
-                None
-            };
-
-            if let Some(neighbor_sc_node_id) = neighbor_sc_node_id {
-                // Skip if this would be immediate backtrack to parent
-                if Some(neighbor_sc_node_id) == parent_sc_node_id {
-                    continue;
-                }
-
-                let mut next_s_edges_count = s_edges_count;
-                let mut next_c_edges_count = c_edges_count;
-
-                match edge.edge_type {
-                    EdgeType::S => next_s_edges_count += 1,
-                    EdgeType::C => next_c_edges_count += 1,
-                }
-
-                if neighbor_sc_node_id == start_sc_node_id_for_cycle {
-                    // Found a cycle
-                    if path.len() >= 2 && next_s_edges_count > 0 && next_c_edges_count > 0 {
-                        // Convert path of SCGraphNodeId to Vec<CfgHopId>
-                        let cycle_cfg_hops: Vec<CfgHopId> = path
-                            .iter()
-                            .map(|&sc_id| self.nodes[sc_id].cfg_hop_id)
-                            .collect();
-
-                        let canonical_cycle = self.canonicalize_cycle(&cycle_cfg_hops);
-                        if visited_paths_for_cycle_detection.insert(canonical_cycle.clone()) {
-                            mixed_cycles.push(canonical_cycle);
-                        }
-                    }
-                } else if !visited_in_dfs.contains(&neighbor_sc_node_id) {
-                    path.push(neighbor_sc_node_id);
-                    visited_in_dfs.insert(neighbor_sc_node_id);
-
-                    self.dfs_find_cycles(
-                        neighbor_sc_node_id,
-                        start_sc_node_id_for_cycle,
-                        Some(current_sc_node_id), // Current node becomes parent
-                        path,
-                        visited_in_dfs,
-                        mixed_cycles,
-                        visited_paths_for_cycle_detection,
-                        next_s_edges_count,
-                        next_c_edges_count,
-                    );
-
-                    visited_in_dfs.remove(&neighbor_sc_node_id);
-                    path.pop();
-                }
+        // Keep the edge order independent of hashing and scheduling
+        candidates.sort_by_key(|(edge, _, _)| (edge.source.index(), edge.target.index()));
+
+        let justified: Vec<_> = candidates
+            .into_par_iter()
+            .map(|(edge, source_hop_id, target_hop_id)| {
+                let justification = justify_conflict(
+                    &accesses[&source_hop_id],
+                    &accesses[&target_hop_id],
+                    cfg_program,
+                );
+                (edge, justification)
+            })
+            .collect();
+        for (edge, justification) in justified {
+            if justification.conflicting {
+                self.edges.push(edge.clone());
             }
-        }
-    }
-
-    /// Helper to normalize a cycle representation (of CfgHopIds) to its canonical form.
-    fn canonicalize_cycle(&self, cycle_cfg_hops: &[CfgHopId]) -> Vec<CfgHopId> {
-        if cycle_cfg_hops.is_empty() {
-            return Vec::new();
-        }
-
-        // Find the CfgHopId with the smallest index value to start the canonical form
-        let min_hop_id_val = cycle_cfg_hops.iter().min_by_key(|h| h.index()).unwrap();
-        let min_pos = cycle_cfg_hops
-            .iter()
-            .position(|&h| h == *min_hop_id_val)
-            .unwrap();
-
-        // Create two possible canonical forms (forward and reverse from min position)
-        let mut forward_canonical = Vec::with_capacity(cycle_cfg_hops.len());
-        let mut reverse_canonical = Vec::with_capacity(cycle_cfg_hops.len());
-
-        for i in 0..cycle_cfg_hops.len() {
-            forward_canonical.push(cycle_cfg_hops[(min_pos + i) % cycle_cfg_hops.len()]);
-            reverse_canonical
-                .push(cycle_cfg_hops[(min_pos + cycle_cfg_hops.len() - i) % cycle_cfg_hops.len()]);
-        }
-
-        // Return the lexicographically smaller one to ensure unique representation
-        // for undirected cycles that can be traversed in both directions
-        if forward_canonical <= reverse_canonical {
-            forward_canonical
-        } else {
-            reverse_canonical
+            self.justifications.insert(edge, justification);
         }
     }
 
@@ -286,3 +437,107 @@ impl SCGraph {
         self.cfg_hop_to_sc_node.get(&cfg_hop_id).cloned()
     }
 }
+
+/// Whether two hops may conflict: some access of one may address a field of a
+/// record the other accesses, with at least one of the two writing. Accesses to
+/// different fields of a record never conflict, since every field is stored and
+/// read on its own. The reason names the pair of accesses that decided,
+/// preferring one that provably addresses the same record.
+fn justify_conflict(
+    accesses_a: &[KeyedAccess],
+    accesses_b: &[KeyedAccess],
+    cfg_program: &CfgProgram,
+) -> ConflictJustification {
+    let describe = |a: &KeyedAccess, b: &KeyedAccess, relation: &str| {
+        let verb = |access: &KeyedAccess| match access.access_type {
+            AccessType::Read => "reads",
+            AccessType::Write => "writes",
+        };
+        format!(
+            "{} and {}, {}",
+            describe_access(a, verb(a), cfg_program),
+            describe_access(b, verb(b), cfg_program),
+            relation
+        )
+    };
+
+    let mut must = None;
+    let mut may = None;
+    let mut disjoint = None;
+    let mut conflicts = Vec::new();
+    for a in accesses_a {
+        for b in accesses_b {
+            if a.table != b.table
+                || a.field != b.field
+                || (a.access_type == AccessType::Read && b.access_type == AccessType::Read)
+            {
+                continue;
+            }
+            let alias = a.alias(b);
+            match alias {
+                KeyAlias::Must => {
+                    must.get_or_insert_with(|| describe(a, b, "the same record"));
+                }
+                KeyAlias::May => {
+                    may.get_or_insert_with(|| describe(a, b, "possibly the same record"));
+                }
+                KeyAlias::MustNot => {
+                    disjoint.get_or_insert_with(|| {
+                        let relation =
+                            format!("different records {}", commutativity::DISJOINT_KEYS.cite());
+                        describe(a, b, &relation)
+                    });
+                    continue;
+                }
+            }
+            conflicts.push(Conflict {
+                kind: match (&a.access_type, &b.access_type) {
+                    (AccessType::Read, _) => ConflictKind::ReadWrite,
+                    (AccessType::Write, AccessType::Read) => ConflictKind::WriteRead,
+                    (AccessType::Write, AccessType::Write) => ConflictKind::WriteWrite,
+                },
+                table: a.table,
+                field: a.field,
+                source: access_site(a),
+                target: access_site(b),
+                alias,
+            });
+        }
+    }
+    match (must.or(may), disjoint) {
+        (Some(reason), _) => ConflictJustification {
+            conflicting: true,
+            reason,
+            conflicts,
+        },
+        (None, Some(reason)) => ConflictJustification {
+            conflicting: false,
+            reason,
+            conflicts,
+        },
+        (None, None) => ConflictJustification {
+            conflicting: false,
+            reason: "no field written by one hop is accessed by the other".to_string(),
+            conflicts,
+        },
+    }
+}
+
+fn access_site(access: &KeyedAccess) -> CfgSite {
+    CfgSite {
+        function: access.function,
+        block: access.block,
+        index: access.index,
+    }
+}
+
+/// An access as `f writes T[id: 1].n at file:3:5`, with the verb given.
+fn describe_access(access: &KeyedAccess, verb: &str, cfg_program: &CfgProgram) -> String {
+    format!(
+        "{} {} {} at {}",
+        cfg_program.functions[access.function].name,
+        verb,
+        format_keyed_access(access, cfg_program),
+        cfg_program.site_span(access_site(access))
+    )
+}
@@ -0,0 +1,79 @@
+//! C-edges summed up per pair of functions.
+//!
+//! In a large workload the C-edges of a few transaction pairs tend to make up
+//! most of the cycles. Counting the C-edges between the hops of each pair of
+//! functions, with the tables they conflict over, shows which pairs those are.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let source = include_str!("../../examples/sc_graph_demos/crossed_writers.transact");
+//! let h = "void h(int id) { hop on B { U[id: id].n = 3; } }";
+//! let program = parse_and_analyze(&format!("{}{}", source, h)).unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//!
+//! let pairs = sc_graph.pair_conflicts();
+//! assert_eq!(pairs.len(), 3);
+//! // f and g conflict on both nodes, the others on B only
+//! assert_eq!(cfg.functions[pairs[0].functions.0].name, "f");
+//! assert_eq!(cfg.functions[pairs[0].functions.1].name, "g");
+//! assert_eq!(pairs[0].c_edges, 2);
+//! assert_eq!(pairs[0].tables.len(), 2);
+//! assert!(pairs[1..].iter().all(|pair| pair.c_edges == 1));
+//! ```
+
+use crate::cfg::{FunctionId as CfgFunctionId, TableId as CfgTableId};
+use crate::sc_graph::{EdgeType, SCGraph};
+use std::collections::{BTreeSet, HashMap};
+
+/// The C-edges between the hops of two functions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairConflicts {
+    /// The two functions, the one allocated first first
+    pub functions: (CfgFunctionId, CfgFunctionId),
+    pub c_edges: usize,
+    /// The tables the edges conflict over, in allocation order
+    pub tables: Vec<CfgTableId>,
+}
+
+impl SCGraph {
+    /// The pairs of functions with C-edges between their hops, most C-edges
+    /// first.
+    pub fn pair_conflicts(&self) -> Vec<PairConflicts> {
+        let mut pairs: HashMap<(CfgFunctionId, CfgFunctionId), (usize, BTreeSet<CfgTableId>)> =
+            HashMap::new();
+        for edge in self.edges.iter().filter(|e| e.edge_type == EdgeType::C) {
+            let function_a = self.nodes[edge.source].cfg_function_id;
+            let function_b = self.nodes[edge.target].cfg_function_id;
+            let key = if function_a.index() <= function_b.index() {
+                (function_a, function_b)
+            } else {
+                (function_b, function_a)
+            };
+            let (count, tables) = pairs.entry(key).or_default();
+            *count += 1;
+            if let Some(justification) = self.justifications.get(edge) {
+                tables.extend(justification.conflicts.iter().map(|c| c.table));
+            }
+        }
+
+        let mut pairs: Vec<PairConflicts> = pairs
+            .into_iter()
+            .map(|(functions, (c_edges, tables))| PairConflicts {
+                functions,
+                c_edges,
+                tables: tables.into_iter().collect(),
+            })
+            .collect();
+        pairs.sort_by_key(|pair| {
+            (
+                std::cmp::Reverse(pair.c_edges),
+                pair.functions.0.index(),
+                pair.functions.1.index(),
+            )
+        });
+        pairs
+    }
+}
@@ -0,0 +1,247 @@
+//! Suggesting hop reorderings that break mixed cycles.
+//!
+//! The S-edges of a function chain its hops in program order, so running the
+//! hops in another order moves its S-edges while its C-edges, which depend on
+//! the accesses of each hop alone, stay. A hop may only move past another when
+//! the two are independent: neither reads or writes a variable the other
+//! writes, they access no field of a possibly shared record with one of them
+//! writing it, and neither can end the transaction by a `return` or an `abort`,
+//! which would decide whether the other runs at all.
+//!
+//! Each order of a function's hops that keeps its dependent hops in program
+//! order is previewed on a copy of the graph with the S-edges of the function
+//! rebuilt, and orders that leave fewer mixed cycles are suggested, fewest
+//! first. A function with many independent hops has too many orders to try
+//! them all, so only the first `MAX_ORDERS` of each function are.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{CycleLimits, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B, C, D }
+//!     table TA on A { primary int id; int n; }
+//!     table TB on B { primary int id; int n; }
+//!     table TC on C { primary int id; int n; }
+//!     table TD on D { primary int id; int n; }
+//!     void f(int id) {
+//!         hop on B { TB[id: id].n = 1; }
+//!         hop on C { TC[id: id].n = 1; }
+//!         hop on D { TD[id: id].n = 1; }
+//!         hop on A { TA[id: id].n = 1; }
+//!     }
+//!     void g(int id) { hop on A { TA[id: id].n = 2; } hop on B { TB[id: id].n = 2; } }
+//!     void h(int id) {
+//!         hop on C { int n = TC[id: id].n; }
+//!         hop on D { TD[id: id].n = n; }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! assert_eq!(sc_graph.find_mixed_cycles().len(), 3);
+//!
+//! // A cycle passes f twice, through g and through h; running the hops of f
+//! // on B and A, or on C and D, one after the other breaks it. The hops of h
+//! // stay, since its second hop needs the n its first hop reads.
+//! let advice = sc_graph.advise_reorderings(&cfg, &CycleLimits::default());
+//! assert_eq!(advice.len(), 2);
+//! assert!(advice.iter().all(|preview| cfg.functions[preview.function].name == "f"));
+//! assert_eq!(advice[0].cycles, 2);
+//! assert_eq!(advice[0].stats, (8, 5, 4));
+//! ```
+
+use crate::cfg::{
+    lower_hop, CfgProgram, FunctionId as CfgFunctionId, HopId as CfgHopId, Instruction, Operand,
+};
+use crate::dataflow::{analyze_keyed_accesses, AccessType, KeyAlias, KeyedAccess};
+use crate::sc_graph::{CycleLimits, Edge, EdgeType, SCGraph};
+use std::collections::{HashMap, HashSet};
+
+/// The most orders of one function's hops that are previewed.
+pub const MAX_ORDERS: usize = 120;
+
+/// An order of a function's hops and the graph it would leave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HopReordering {
+    pub function: CfgFunctionId,
+    /// Every hop of the function, in the order it would run them
+    pub order: Vec<CfgHopId>,
+    /// Nodes, S-edges and C-edges after the reordering, as `SCGraph::stats`
+    pub stats: (usize, usize, usize),
+    /// Mixed cycles left, within the limits the advice was given for
+    pub cycles: usize,
+    /// Whether the cycle search stopped at its limit, so more may be left
+    pub truncated: bool,
+}
+
+impl SCGraph {
+    /// Orders of each function's hops that respect their dependencies and
+    /// leave fewer mixed cycles, fewest cycles left first.
+    pub fn advise_reorderings(&self, cfg: &CfgProgram, limits: &CycleLimits) -> Vec<HopReordering> {
+        let before = self.find_mixed_cycles_within(limits).cycles.len();
+        if before == 0 {
+            return Vec::new();
+        }
+        let accesses = analyze_keyed_accesses(cfg);
+
+        let mut advice = Vec::new();
+        for (function_id, function) in cfg.functions.iter() {
+            if function.hop_order.len() < 2 {
+                continue;
+            }
+            let dependencies = hop_dependencies(cfg, function_id, &accesses);
+            for order in legal_orders(&function.hop_order, &dependencies) {
+                let preview = self.preview_reordering(function_id, order, limits);
+                if preview.cycles < before {
+                    advice.push(preview);
+                }
+            }
+        }
+        advice.sort_by_key(|preview| (preview.cycles, preview.function.index()));
+        advice
+    }
+
+    /// The graph left by running the hops of a function in `order`, as advice.
+    fn preview_reordering(
+        &self,
+        function: CfgFunctionId,
+        order: Vec<CfgHopId>,
+        limits: &CycleLimits,
+    ) -> HopReordering {
+        let mut edges: Vec<Edge> = self
+            .edges
+            .iter()
+            .filter(|edge| {
+                edge.edge_type == EdgeType::C || self.nodes[edge.source].cfg_function_id != function
+            })
+            .cloned()
+            .collect();
+        for pair in order.windows(2) {
+            edges.push(Edge::new(
+                self.cfg_hop_to_sc_node[&pair[0]],
+                self.cfg_hop_to_sc_node[&pair[1]],
+                EdgeType::S,
+            ));
+        }
+        let reordered = SCGraph {
+            nodes: self.nodes.clone(),
+            edges,
+            // Reordering keeps the C-edges, and with them what they are
+            justifications: self.justifications.clone(),
+            pair_checks: HashMap::new(),
+            exclusions: self.exclusions.clone(),
+            isolation: self.isolation,
+            cfg_hop_to_sc_node: self.cfg_hop_to_sc_node.clone(),
+        };
+        let mixed_cycles = reordered.find_mixed_cycles_within(limits);
+        HopReordering {
+            function,
+            order,
+            stats: reordered.stats(),
+            cycles: mixed_cycles.cycles.len(),
+            truncated: mixed_cycles.truncated,
+        }
+    }
+}
+
+/// The pairs of hops of a function, as positions in its hop order, that must
+/// keep their order.
+fn hop_dependencies(
+    cfg: &CfgProgram,
+    function: CfgFunctionId,
+    accesses: &HashMap<CfgHopId, Vec<KeyedAccess>>,
+) -> HashSet<(usize, usize)> {
+    let func = &cfg.functions[function];
+    let summaries: Vec<(HashSet<_>, HashSet<_>, bool)> = func
+        .hop_order
+        .iter()
+        .map(|&hop| {
+            let instructions = lower_hop(func, hop);
+            let defined: HashSet<_> = instructions
+                .iter()
+                .filter_map(Instruction::defined_var)
+                .collect();
+            let used: HashSet<_> = instructions
+                .iter()
+                .flat_map(Instruction::used_operands)
+                .filter_map(|operand| match operand {
+                    Operand::Var(var) => Some(*var),
+                    Operand::Const(_) => None,
+                })
+                .collect();
+            let ends = instructions.iter().any(|instruction| {
+                matches!(
+                    instruction,
+                    Instruction::Return(_) | Instruction::Abort | Instruction::BoundExceeded(_)
+                )
+            });
+            (defined, used, ends)
+        })
+        .collect();
+
+    let mut dependencies = HashSet::new();
+    for i in 0..summaries.len() {
+        for j in (i + 1)..summaries.len() {
+            let (defined_i, used_i, ends_i) = &summaries[i];
+            let (defined_j, used_j, ends_j) = &summaries[j];
+            let variables = !defined_i.is_disjoint(used_j)
+                || !defined_i.is_disjoint(defined_j)
+                || !used_i.is_disjoint(defined_j);
+            let tables = accesses[&func.hop_order[i]].iter().any(|a| {
+                accesses[&func.hop_order[j]].iter().any(|b| {
+                    a.table == b.table
+                        && a.field == b.field
+                        && (a.access_type == AccessType::Write
+                            || b.access_type == AccessType::Write)
+                        && a.alias(b) != KeyAlias::MustNot
+                })
+            });
+            if *ends_i || *ends_j || variables || tables {
+                dependencies.insert((i, j));
+            }
+        }
+    }
+    dependencies
+}
+
+/// The orders of the hops, other than the given one, in which each dependent
+/// pair keeps its order, up to `MAX_ORDERS` of them.
+fn legal_orders(hops: &[CfgHopId], dependencies: &HashSet<(usize, usize)>) -> Vec<Vec<CfgHopId>> {
+    fn extend(
+        placed: &mut Vec<usize>,
+        len: usize,
+        dependencies: &HashSet<(usize, usize)>,
+        orders: &mut Vec<Vec<usize>>,
+    ) {
+        if orders.len() > MAX_ORDERS {
+            return;
+        }
+        if placed.len() == len {
+            orders.push(placed.clone());
+            return;
+        }
+        for next in 0..len {
+            let ready = !placed.contains(&next)
+                && (0..next).all(|before| {
+                    placed.contains(&before) || !dependencies.contains(&(before, next))
+                });
+            if ready {
+                placed.push(next);
+                extend(placed, len, dependencies, orders);
+                placed.pop();
+            }
+        }
+    }
+
+    let mut orders = Vec::new();
+    extend(&mut Vec::new(), hops.len(), dependencies, &mut orders);
+    orders
+        .into_iter()
+        .filter(|order| order.iter().enumerate().any(|(i, &hop)| i != hop))
+        .take(MAX_ORDERS)
+        .map(|order| order.into_iter().map(|i| hops[i]).collect())
+        .collect()
+}
@@ -0,0 +1,229 @@
+//! Interleavings of hops that realize a mixed cycle.
+//!
+//! Walking a cycle, each C-edge step orders the hop it leaves before the hop it
+//! enters, as the conflict would, and each transaction runs its hops in program
+//! order. When those orders agree, any order of the hops that respects them is
+//! a schedule no serial run can reproduce. If they contradict each other, the
+//! cycle is walked the other way round; a cycle that fits neither way has no
+//! schedule.
+//!
+//! A schedule names each step as `FUNCTION:HOP`, counting the hops of a
+//! function from 1, which is the form `--schedule` takes in simulate mode to
+//! replay it. The serial schedules of a cycle run its functions one after
+//! another instead, in every order, each to its last hop; a run of the cycle's
+//! schedule that ends as none of them does is an anomaly.
+//!
+//! ```
+//! use FMitF_rs::runtime::{ExecutionOutcome, RuntimeState, RuntimeValue};
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table T on A { primary int id; int n; }
+//!     table U on B { primary int id; int n; }
+//!     void f() { hop on A { T[id: 1].n = 1; } hop on B { U[id: 1].n = 1; } }
+//!     int g() { hop on A { int t = T[id: 1].n; } hop on B { U[id: 1].n = 2; return t; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let cycles = sc_graph.find_mixed_cycles();
+//!
+//! let schedule = sc_graph.cycle_schedule(&cycles[0], &cfg).unwrap();
+//! assert_eq!(schedule.to_string(), "g:1 -> f:1 -> f:2 -> g:2");
+//!
+//! // Serially, each function runs to its last hop before the other starts
+//! let serial: Vec<String> = sc_graph
+//!     .serial_schedules(&schedule, &cfg)
+//!     .iter()
+//!     .map(ToString::to_string)
+//!     .collect();
+//! assert_eq!(serial, ["g:2 -> f:2", "f:2 -> g:2"]);
+//!
+//! // g reads T.n before f writes it, yet its write of U.n comes last
+//! let mut state = RuntimeState::new();
+//! state.load_cfg(cfg).unwrap();
+//! let calls = [("f".to_string(), vec![]), ("g".to_string(), vec![])];
+//! let steps: Vec<_> = schedule.steps.iter().map(|step| (step.function.clone(), step.hop)).collect();
+//! let outcomes = state.replay(calls, &steps).unwrap();
+//! assert_eq!(outcomes[1], ExecutionOutcome::Committed(Some(RuntimeValue::Int(0))));
+//! assert_eq!(state.get_table_data("U").unwrap()[&vec![RuntimeValue::Int(1)]].values().next(),
+//!     Some(&RuntimeValue::Int(2)));
+//! ```
+
+use crate::cfg::{CfgProgram, HopId as CfgHopId};
+use crate::sc_graph::{SCGraph, SCGraphNodeId};
+use std::fmt;
+
+/// One hop of a schedule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleStep {
+    pub node: SCGraphNodeId,
+    pub function: String,
+    /// The position of the hop among the hops of its function, from 1
+    pub hop: usize,
+}
+
+/// The hops of a cycle in an order that realizes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleSchedule {
+    pub steps: Vec<ScheduleStep>,
+}
+
+impl fmt::Display for CycleSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps: Vec<String> = self
+            .steps
+            .iter()
+            .map(|step| format!("{}:{}", step.function, step.hop))
+            .collect();
+        write!(f, "{}", steps.join(" -> "))
+    }
+}
+
+impl CycleSchedule {
+    /// The functions the schedule runs hops of, in the order they first run.
+    pub fn functions(&self) -> Vec<&str> {
+        let mut functions: Vec<&str> = Vec::new();
+        for step in &self.steps {
+            if !functions.contains(&step.function.as_str()) {
+                functions.push(&step.function);
+            }
+        }
+        functions
+    }
+}
+
+impl SCGraph {
+    /// An interleaving of the hops of a cycle, as `find_mixed_cycles` returns
+    /// it, that realizes the cycle, or `None` if its conflicts and program
+    /// orders contradict each other whichever way it is walked.
+    pub fn cycle_schedule(&self, cycle: &[CfgHopId], cfg: &CfgProgram) -> Option<CycleSchedule> {
+        let nodes: Vec<SCGraphNodeId> = cycle
+            .iter()
+            .filter_map(|&hop| self.get_sc_node_id(hop))
+            .collect();
+        let position = |node: SCGraphNodeId| {
+            let node = &self.nodes[node];
+            cfg.functions[node.cfg_function_id]
+                .hop_order
+                .iter()
+                .position(|&hop| hop == node.cfg_hop_id)
+                .unwrap_or_default()
+        };
+
+        // Program order within each function, between the hops on the cycle
+        let mut program_order = Vec::new();
+        for (i, &a) in nodes.iter().enumerate() {
+            for (j, &b) in nodes.iter().enumerate() {
+                if self.nodes[a].cfg_function_id == self.nodes[b].cfg_function_id
+                    && position(a) < position(b)
+                {
+                    program_order.push((i, j));
+                }
+            }
+        }
+        // Each step between functions crosses a C-edge
+        let conflict_order: Vec<(usize, usize)> = (0..nodes.len())
+            .map(|i| (i, (i + 1) % nodes.len()))
+            .filter(|&(i, j)| {
+                self.nodes[nodes[i]].cfg_function_id != self.nodes[nodes[j]].cfg_function_id
+            })
+            .collect();
+
+        let forward = conflict_order.iter().copied();
+        let backward = conflict_order.iter().map(|&(i, j)| (j, i));
+        let order = topological_order(nodes.len(), program_order.iter().copied().chain(forward))
+            .or_else(|| {
+                topological_order(nodes.len(), program_order.iter().copied().chain(backward))
+            })?;
+
+        let steps = order
+            .into_iter()
+            .map(|i| ScheduleStep {
+                node: nodes[i],
+                function: cfg.functions[self.nodes[nodes[i]].cfg_function_id]
+                    .name
+                    .clone(),
+                hop: position(nodes[i]) + 1,
+            })
+            .collect();
+        Some(CycleSchedule { steps })
+    }
+}
+
+impl SCGraph {
+    /// The serial schedules of the functions of `schedule`: one for each order
+    /// of them, each step running a function to its last hop.
+    pub fn serial_schedules(
+        &self,
+        schedule: &CycleSchedule,
+        cfg: &CfgProgram,
+    ) -> Vec<CycleSchedule> {
+        // The last hop of each function, as a step that runs it to the end
+        let mut ends: Vec<ScheduleStep> = Vec::new();
+        for step in &schedule.steps {
+            if ends.iter().any(|end| end.function == step.function) {
+                continue;
+            }
+            let function = &cfg.functions[self.nodes[step.node].cfg_function_id];
+            let Some(node) = function
+                .hop_order
+                .last()
+                .and_then(|&hop| self.get_sc_node_id(hop))
+            else {
+                continue;
+            };
+            ends.push(ScheduleStep {
+                node,
+                function: step.function.clone(),
+                hop: function.hop_order.len(),
+            });
+        }
+
+        // Each order of the functions, built by placing one more function at
+        // every position of the orders of the ones before it
+        let mut orders: Vec<Vec<ScheduleStep>> = vec![Vec::new()];
+        for end in ends {
+            let mut longer = Vec::new();
+            for order in &orders {
+                for position in (0..=order.len()).rev() {
+                    let mut order = order.clone();
+                    order.insert(position, end.clone());
+                    longer.push(order);
+                }
+            }
+            orders = longer;
+        }
+        orders
+            .into_iter()
+            .map(|steps| CycleSchedule { steps })
+            .collect()
+    }
+}
+
+/// An order of `0..len` in which each `(before, after)` pair holds, taking the
+/// smallest index free to go next, or `None` if the pairs form a cycle.
+fn topological_order(
+    len: usize,
+    pairs: impl Iterator<Item = (usize, usize)>,
+) -> Option<Vec<usize>> {
+    let mut successors = vec![Vec::new(); len];
+    let mut predecessors = vec![0; len];
+    for (before, after) in pairs {
+        successors[before].push(after);
+        predecessors[after] += 1;
+    }
+    let mut order = Vec::with_capacity(len);
+    while order.len() < len {
+        let next = (0..len).find(|&i| predecessors[i] == 0 && !order.contains(&i))?;
+        order.push(next);
+        for &after in &successors[next] {
+            predecessors[after] -= 1;
+        }
+    }
+    Some(order)
+}
@@ -0,0 +1,62 @@
+//! Scoping analysis to some transactions.
+//!
+//! When working on one transaction of a large workload, only it and the
+//! transactions it conflicts with need to be analyzed: the others take part
+//! in no C-edge of its hops. Its partners are found by justifying the pairs
+//! of hops it takes part in alone, as `SCGraph::new` would, with the
+//! exclusions applied and the C-edges whose hops commute dropped. Mixed
+//! cycles that leave these transactions, through transactions that conflict
+//! with none of the selected ones, are not found in the scoped program.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{ConflictExclusions, SCGraph};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//! use std::collections::HashSet;
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     table U on A { primary int id; int n; }
+//!     void f(int id) { hop on A { T[id: id].n = 1; } }
+//!     void g(int id) { hop on A { T[id: id].n = 2; } }
+//!     void h(int id) { hop on A { U[id: id].n = 3; } }
+//!     void k(int id) { hop on A { U[id: id].n = 4; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let f = cfg.root_functions[0];
+//! let scope = SCGraph::conflicting_functions(&cfg, &HashSet::from([f]), &ConflictExclusions::default());
+//! let names: HashSet<_> = scope.iter().map(|&id| cfg.functions[id].name.as_str()).collect();
+//! assert_eq!(names, HashSet::from(["f", "g"]));
+//! ```
+
+use crate::cfg::{CfgProgram, FunctionId as CfgFunctionId};
+use crate::sc_graph::{ConflictExclusions, EdgeType, SCGraph};
+use std::collections::HashSet;
+
+impl SCGraph {
+    /// The `functions` and the functions with a C-edge to one of their hops.
+    pub fn conflicting_functions(
+        cfg_program: &CfgProgram,
+        functions: &HashSet<CfgFunctionId>,
+        exclusions: &ConflictExclusions,
+    ) -> HashSet<CfgFunctionId> {
+        let mut sc_graph = SCGraph::without_c_edges(cfg_program);
+        sc_graph.add_c_edges(cfg_program, |function1, function2| {
+            functions.contains(&function1) || functions.contains(&function2)
+        });
+        sc_graph.exclude(exclusions, cfg_program);
+        sc_graph.eliminate_commuting_edges(cfg_program);
+
+        let mut scope = functions.clone();
+        for edge in &sc_graph.edges {
+            if edge.edge_type == EdgeType::C {
+                scope.insert(sc_graph.nodes[edge.source].cfg_function_id);
+                scope.insert(sc_graph.nodes[edge.target].cfg_function_id);
+            }
+        }
+        scope
+    }
+}
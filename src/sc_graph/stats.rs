@@ -0,0 +1,209 @@
+//! Workload metrics, to track how complex a workload grows over time.
+//!
+//! Data flows across nodes where a transaction moves on to a hop on another
+//! node, carrying the locals the rest of the transaction reads; parameters are
+//! not counted, as every hop has them. The density of the SC-graph is the
+//! share of the pairs of hops that could conflict, those on the same node
+//! from different transactions, that have a C-edge.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table T on A { primary int id; int n; }
+//!     table U on B { primary int id; int n; }
+//!     void copy(int id) {
+//!         hop on A { int x = T[id: id].n; }
+//!         hop on B { U[id: id].n = x; }
+//!     }
+//!     void read(int id) { hop on A { int y = T[id: id].n; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let stats = SCGraph::new(&cfg).workload_stats(&cfg);
+//!
+//! assert_eq!(stats.transactions, 2);
+//! assert_eq!((stats.hops.min, stats.hops.max), (1, 2));
+//! // copy carries x from A to B
+//! assert_eq!(stats.cross_node.hop_changes, 1);
+//! assert_eq!(stats.cross_node.values_carried, 1);
+//! assert_eq!(stats.statements.table_writes, 1);
+//! // Both transactions only read T
+//! assert_eq!(stats.sc_graph.possible_c_edges, 1);
+//! assert_eq!(stats.sc_graph.density, 0.0);
+//! ```
+
+use crate::cfg::{CfgProgram, Rvalue, Statement};
+use crate::dataflow::LiveVariables;
+use crate::sc_graph::SCGraph;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Metrics of a workload and its SC-graph.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WorkloadStats {
+    pub transactions: usize,
+    /// Hops per transaction
+    pub hops: Spread,
+    /// The tables of each node, in order of declaration
+    pub nodes: Vec<NodeStats>,
+    pub cross_node: CrossNodeFlow,
+    pub statements: StatementCounts,
+    pub sc_graph: GraphDensity,
+}
+
+/// The fewest, mean and most of something per transaction.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Spread {
+    pub min: usize,
+    pub mean: f64,
+    pub max: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NodeStats {
+    pub name: String,
+    pub tables: usize,
+}
+
+/// Data flowing from a hop to the next one on another node.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct CrossNodeFlow {
+    /// Consecutive hops of a transaction on different nodes
+    pub hop_changes: usize,
+    /// Locals live on entry to the second hop of such a pair
+    pub values_carried: usize,
+}
+
+/// Statements of the CFG, after lowering and optimization.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct StatementCounts {
+    pub total: usize,
+    /// Per transaction
+    pub per_transaction: Spread,
+    pub table_reads: usize,
+    pub table_writes: usize,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct GraphDensity {
+    pub vertices: usize,
+    pub s_edges: usize,
+    pub c_edges: usize,
+    /// Pairs of hops on the same node from different transactions
+    pub possible_c_edges: usize,
+    /// `c_edges` over `possible_c_edges`, 0 if there are none
+    pub density: f64,
+}
+
+impl Spread {
+    fn of(counts: &[usize]) -> Self {
+        if counts.is_empty() {
+            return Self::default();
+        }
+        Self {
+            min: counts.iter().copied().min().unwrap_or_default(),
+            mean: counts.iter().sum::<usize>() as f64 / counts.len() as f64,
+            max: counts.iter().copied().max().unwrap_or_default(),
+        }
+    }
+}
+
+impl SCGraph {
+    /// The metrics of the transactions of `cfg` and of this graph of them.
+    pub fn workload_stats(&self, cfg: &CfgProgram) -> WorkloadStats {
+        let mut hops = Vec::new();
+        let mut statements_per_transaction = Vec::new();
+        let mut cross_node = CrossNodeFlow::default();
+        let mut statements = StatementCounts::default();
+        for &function_id in &cfg.root_functions {
+            let function = &cfg.functions[function_id];
+            hops.push(function.hop_order.len());
+
+            let live = LiveVariables::new(function);
+            for pair in function.hop_order.windows(2) {
+                let (from, to) = (&function.hops[pair[0]], &function.hops[pair[1]]);
+                if from.node_id == to.node_id {
+                    continue;
+                }
+                cross_node.hop_changes += 1;
+                if let Some(entry) = to.entry_block {
+                    cross_node.values_carried += live
+                        .live_in(entry)
+                        .iter()
+                        .filter(|&&var| !function.variables[var].is_parameter)
+                        .count();
+                }
+            }
+
+            let mut count = 0;
+            for (_, block) in function.blocks.iter() {
+                for statement in &block.statements {
+                    count += 1;
+                    match statement {
+                        Statement::Assign {
+                            rvalue: Rvalue::TableAccess { .. },
+                            ..
+                        } => statements.table_reads += 1,
+                        Statement::TableAssign { .. } => statements.table_writes += 1,
+                        Statement::Assign { .. } => {}
+                    }
+                }
+            }
+            statements.total += count;
+            statements_per_transaction.push(count);
+        }
+        statements.per_transaction = Spread::of(&statements_per_transaction);
+
+        WorkloadStats {
+            transactions: cfg.root_functions.len(),
+            hops: Spread::of(&hops),
+            nodes: cfg
+                .root_nodes
+                .iter()
+                .map(|&node| NodeStats {
+                    name: cfg.nodes[node].name.clone(),
+                    tables: cfg.nodes[node].tables.len(),
+                })
+                .collect(),
+            cross_node,
+            statements,
+            sc_graph: self.density(),
+        }
+    }
+
+    fn density(&self) -> GraphDensity {
+        let (vertices, s_edges, c_edges) = self.stats();
+        // Hops on the same node from different functions, by node and function
+        let mut hops = HashMap::new();
+        for (_, node) in self.nodes.iter() {
+            *hops
+                .entry(node.cfg_node_id)
+                .or_insert_with(HashMap::new)
+                .entry(node.cfg_function_id)
+                .or_insert(0) += 1;
+        }
+        let possible_c_edges = hops
+            .values()
+            .map(|functions| {
+                let total: usize = functions.values().sum();
+                let same_function: usize = functions.values().map(|n| n * n).sum();
+                (total * total - same_function) / 2
+            })
+            .sum();
+        GraphDensity {
+            vertices,
+            s_edges,
+            c_edges,
+            possible_c_edges,
+            density: match possible_c_edges {
+                0 => 0.0,
+                possible => c_edges as f64 / possible as f64,
+            },
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Ranking mixed cycles by how often their transactions run.
+//!
+//! A cycle can only show up in production when all of its transactions run
+//! concurrently, so cycles through rarely run transactions matter less. A
+//! weight file gives each function a weight, such as its share of the
+//! workload, and a cycle weighs the product of the weights of the functions it
+//! passes, or their minimum. Functions the file leaves out weigh 1.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{SCGraph, TransactionWeights};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let source = include_str!("../../examples/sc_graph_demos/crossed_writers.transact");
+//! let h = "void h(int id) { hop on A { T[id: id].n = 3; } hop on B { U[id: id].n = 3; } }";
+//! let program = parse_and_analyze(&format!("{}{}", source, h)).unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let cycles = sc_graph.find_mixed_cycles();
+//!
+//! let weights = TransactionWeights::from_toml(
+//!     r#"
+//!     combine = "min"
+//!
+//!     [weights]
+//!     f = 50
+//!     g = 40
+//!     h = 0.5
+//!     "#,
+//! )
+//! .unwrap();
+//! weights.check_functions(&cfg).unwrap();
+//!
+//! // The cycle through f and g alone comes first, the ones through h last
+//! let ranked = sc_graph.rank_cycles(&cycles, &cfg, &weights);
+//! assert_eq!(ranked[0].1, 40.0);
+//! assert_eq!(sc_graph.describe_cycle(&cycles[ranked[0].0], &cfg), "f:1 -- f:2 -- g:2 -- g:1");
+//! assert!(ranked[1..].iter().all(|&(_, weight)| weight == 0.5));
+//! ```
+
+use crate::cfg::{CfgProgram, HopId as CfgHopId};
+use crate::sc_graph::SCGraph;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// How the weights of the functions on a cycle make up its weight.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WeightCombination {
+    /// The product of the weights, as if the transactions ran independently
+    #[default]
+    Product,
+    /// The smallest weight, the transaction that runs most rarely
+    Min,
+}
+
+/// The weight of each function, read from a TOML weight file.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransactionWeights {
+    pub combine: WeightCombination,
+    /// Weights of functions by name; others weigh 1
+    pub weights: HashMap<String, f64>,
+}
+
+impl TransactionWeights {
+    /// Parses weights from TOML, rejecting unknown keys and negative weights.
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let weights: Self = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        let mut names: Vec<&String> = weights.weights.keys().collect();
+        names.sort();
+        for name in names {
+            let weight = weights.weights[name];
+            if !(weight >= 0.0 && weight.is_finite()) {
+                return Err(format!(
+                    "Weight of '{}' must be a non-negative number",
+                    name
+                ));
+            }
+        }
+        Ok(weights)
+    }
+
+    /// Reads and parses the TOML weight file at `path`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+        Self::from_toml(&text)
+            .map_err(|e| format!("Invalid weight file '{}': {}", path.display(), e))
+    }
+
+    /// Rejects weights of functions the program does not have, which are
+    /// most likely misspelled.
+    pub fn check_functions(&self, cfg: &CfgProgram) -> Result<(), String> {
+        let mut names: Vec<&String> = self.weights.keys().collect();
+        names.sort();
+        match names.into_iter().find(|name| {
+            !cfg.functions
+                .iter()
+                .any(|(_, function)| &function.name == *name)
+        }) {
+            Some(name) => Err(format!("Weight file names unknown function '{}'", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// The weight of a function.
+    pub fn weight(&self, function: &str) -> f64 {
+        self.weights.get(function).copied().unwrap_or(1.0)
+    }
+}
+
+impl SCGraph {
+    /// The weight of a cycle, as `find_mixed_cycles` returns it, counting each
+    /// function it passes once.
+    pub fn cycle_weight(
+        &self,
+        cycle: &[CfgHopId],
+        cfg: &CfgProgram,
+        weights: &TransactionWeights,
+    ) -> f64 {
+        let functions: BTreeSet<&str> = cycle
+            .iter()
+            .filter_map(|&hop| self.get_sc_node_id(hop))
+            .map(|node| {
+                cfg.functions[self.nodes[node].cfg_function_id]
+                    .name
+                    .as_str()
+            })
+            .collect();
+        let function_weights = functions.into_iter().map(|name| weights.weight(name));
+        match weights.combine {
+            WeightCombination::Product => function_weights.product(),
+            WeightCombination::Min => function_weights.fold(f64::INFINITY, f64::min),
+        }
+    }
+
+    /// The cycles as indices into `cycles` with their weights, heaviest first,
+    /// and in their order in `cycles` among equal weights.
+    pub fn rank_cycles(
+        &self,
+        cycles: &[Vec<CfgHopId>],
+        cfg: &CfgProgram,
+        weights: &TransactionWeights,
+    ) -> Vec<(usize, f64)> {
+        let mut ranked: Vec<(usize, f64)> = cycles
+            .iter()
+            .enumerate()
+            .map(|(index, cycle)| (index, self.cycle_weight(cycle, cfg, weights)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked
+    }
+}
@@ -0,0 +1,907 @@
+//! Converts a parsed SQL script into TransAct source text.
+//!
+//! Tables become table declarations on the node given by the table→node mapping.
+//! Each routine becomes a function whose statements are grouped into hops: a
+//! statement that reads or writes a table runs on that table's node, and
+//! statements without table access join the hop that is currently open.
+
+use pest::iterators::Pair;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::Rule;
+use crate::ast::errors::next_pair;
+use crate::ast::{AstError, Results, Span, SpannedError, TypeName};
+
+struct Column {
+    name: String,
+    ty: TypeName,
+    primary: bool,
+}
+
+struct Table {
+    name: String,
+    node: String,
+    columns: Vec<Column>,
+    span: Span,
+}
+
+impl Table {
+    fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+
+    fn primary_keys(&self) -> impl Iterator<Item = &Column> {
+        self.columns.iter().filter(|column| column.primary)
+    }
+}
+
+/// Parameters and locals of the routine being converted, with their declarations.
+#[derive(Default)]
+struct Routine {
+    variables: HashMap<String, Span>,
+}
+
+/// The row an `UPDATE` writes; bare column names in its `SET` list read this row.
+struct Row<'t> {
+    table: &'t Table,
+    key: String,
+}
+
+/// TransAct lines for one SQL statement and the node whose tables it touches.
+struct Lines {
+    lines: Vec<String>,
+    node: Option<String>,
+}
+
+impl Lines {
+    fn local(lines: Vec<String>) -> Self {
+        Self { lines, node: None }
+    }
+
+    fn on(node: &str, lines: Vec<String>) -> Self {
+        Self {
+            lines,
+            node: Some(node.to_string()),
+        }
+    }
+}
+
+pub(super) struct Converter<'a> {
+    file: Option<Arc<Path>>,
+    table_nodes: HashMap<String, &'a str>,
+    tables: Vec<Table>,
+}
+
+impl<'a> Converter<'a> {
+    pub(super) fn new(path: Option<&Path>, table_nodes: &'a HashMap<String, String>) -> Self {
+        Self {
+            file: path.map(Arc::from),
+            table_nodes: table_nodes
+                .iter()
+                .map(|(table, node)| (table.to_lowercase(), node.as_str()))
+                .collect(),
+            tables: Vec::new(),
+        }
+    }
+
+    /// Converts a `script` pair. Tables are collected first, so routines may use
+    /// tables created further down the script.
+    pub(super) fn convert(mut self, script: Pair<Rule>) -> Results<String> {
+        let mut errors = Vec::new();
+        let items: Vec<_> = script.into_inner().collect();
+
+        for item in items
+            .iter()
+            .filter(|item| item.as_rule() == Rule::create_table)
+        {
+            match self.convert_table(item.clone()) {
+                Ok(table) => self.tables.push(table),
+                Err(e) => errors.extend(e),
+            }
+        }
+
+        let mut unknown: Vec<_> = self
+            .table_nodes
+            .keys()
+            .filter(|name| !self.tables.iter().any(|table| table.name == **name))
+            .collect();
+        unknown.sort();
+        for name in unknown {
+            errors.push(SpannedError {
+                error: AstError::SqlError(format!(
+                    "--table-node maps table '{}', which the script does not create",
+                    name
+                )),
+                span: None,
+            });
+        }
+
+        if self.tables.is_empty() && errors.is_empty() {
+            errors.push(SpannedError {
+                error: AstError::SqlError("The script does not create any tables".to_string()),
+                span: None,
+            });
+        }
+
+        let mut functions = Vec::new();
+        for item in items
+            .into_iter()
+            .filter(|item| item.as_rule() == Rule::create_routine)
+        {
+            match self.convert_routine(item) {
+                Ok(function) => functions.push(function),
+                Err(e) => errors.extend(e),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(self.emit(&functions))
+    }
+
+    fn emit(&self, functions: &[String]) -> String {
+        let mut nodes: Vec<&str> = Vec::new();
+        for table in &self.tables {
+            if !nodes.contains(&table.node.as_str()) {
+                nodes.push(&table.node);
+            }
+        }
+
+        let mut out = String::from("nodes {\n");
+        out.push_str(
+            &nodes
+                .iter()
+                .map(|node| format!("    {}", node))
+                .collect::<Vec<_>>()
+                .join(",\n"),
+        );
+        out.push_str("\n}\n");
+
+        for table in &self.tables {
+            out.push_str(&format!("\ntable {} on {} {{\n", table.name, table.node));
+            for column in &table.columns {
+                let primary = if column.primary { "primary " } else { "" };
+                out.push_str(&format!(
+                    "    {}{} {};\n",
+                    primary,
+                    type_text(&column.ty),
+                    column.name
+                ));
+            }
+            out.push_str("}\n");
+        }
+
+        for function in functions {
+            out.push('\n');
+            out.push_str(function);
+        }
+        out
+    }
+
+    // ========================================================================
+    // Tables
+    // ========================================================================
+
+    fn convert_table(&self, pair: Pair<Rule>) -> Results<Table> {
+        let span = self.span(&pair);
+        let mut inner = pair.into_inner();
+        let name_pair = next_pair(&mut inner, &span)?;
+        let name = lower(&name_pair);
+
+        if let Some(previous) = self.tables.iter().find(|table| table.name == name) {
+            let previous = previous.span.clone();
+            return Err(self.error_at(&name_pair, AstError::DuplicateTable { name, previous }));
+        }
+        let node = match self.table_nodes.get(&name) {
+            Some(node) => node.to_string(),
+            None => {
+                return Err(self.sql_error(
+                    &name_pair,
+                    format!(
+                        "Table '{}' has no node; pass --table-node {}=<NODE>",
+                        name, name
+                    ),
+                ))
+            }
+        };
+
+        let mut columns: Vec<Column> = Vec::new();
+        let mut key_clauses = Vec::new();
+        for element in inner {
+            match element.as_rule() {
+                Rule::column_def => {
+                    let mut parts = element.into_inner();
+                    let column_pair = next_pair(&mut parts, &span)?;
+                    let column = lower(&column_pair);
+                    if columns.iter().any(|c| c.name == column) {
+                        return Err(self.sql_error(
+                            &column_pair,
+                            format!("Column '{}' is declared twice", column),
+                        ));
+                    }
+                    columns.push(Column {
+                        name: column,
+                        ty: sql_type(&next_pair(&mut parts, &span)?),
+                        primary: parts.any(|p| p.as_rule() == Rule::primary_key_constraint),
+                    });
+                }
+                _ => key_clauses.push(element),
+            }
+        }
+
+        for clause in key_clauses {
+            for key_pair in identifiers(next_pair(&mut clause.into_inner(), &span)?) {
+                let key = lower(&key_pair);
+                match columns.iter_mut().find(|column| column.name == key) {
+                    Some(column) => column.primary = true,
+                    None => {
+                        return Err(self.error_at(
+                            &key_pair,
+                            AstError::UndeclaredField {
+                                table: name,
+                                field: key,
+                            },
+                        ))
+                    }
+                }
+            }
+        }
+
+        if !columns.iter().any(|column| column.primary) {
+            return Err(vec![SpannedError {
+                error: AstError::SqlError(format!("Table '{}' has no primary key", name)),
+                span: Some(span),
+            }]);
+        }
+
+        Ok(Table {
+            name,
+            node,
+            columns,
+            span,
+        })
+    }
+
+    fn table(&self, pair: &Pair<Rule>) -> Results<&Table> {
+        let name = lower(pair);
+        self.tables
+            .iter()
+            .find(|table| table.name == name)
+            .ok_or_else(|| self.error_at(pair, AstError::UndeclaredTable(name)))
+    }
+
+    fn column<'t>(&self, table: &'t Table, pair: &Pair<Rule>) -> Results<&'t Column> {
+        let name = lower(pair);
+        table.column(&name).ok_or_else(|| {
+            self.error_at(
+                pair,
+                AstError::UndeclaredField {
+                    table: table.name.clone(),
+                    field: name,
+                },
+            )
+        })
+    }
+
+    // ========================================================================
+    // Routines
+    // ========================================================================
+
+    fn convert_routine(&self, pair: Pair<Rule>) -> Results<String> {
+        let span = self.span(&pair);
+        let mut inner = pair.into_inner();
+        let name = lower(&next_pair(&mut inner, &span)?);
+        let mut routine = Routine::default();
+        let mut params = Vec::new();
+        let mut ret_type = "void";
+        let mut locals = Vec::new();
+        let mut statements = Vec::new();
+
+        for part in inner {
+            match part.as_rule() {
+                Rule::routine_params => {
+                    for param in part.into_inner() {
+                        let mut parts = param
+                            .into_inner()
+                            .filter(|p| p.as_rule() != Rule::param_mode);
+                        let param_pair = next_pair(&mut parts, &span)?;
+                        let ty = sql_type(&next_pair(&mut parts, &span)?);
+                        self.declare(&mut routine, &param_pair)?;
+                        params.push(format!("{} {}", type_text(&ty), lower(&param_pair)));
+                    }
+                }
+                Rule::return_type => {
+                    let ty = next_pair(&mut part.into_inner(), &span)?;
+                    if ty.as_rule() == Rule::sql_type {
+                        ret_type = type_text(&sql_type(&ty));
+                    }
+                }
+                Rule::block => {
+                    for item in part.into_inner() {
+                        if item.as_rule() == Rule::declare_section {
+                            for decl in item.into_inner() {
+                                locals.push(self.convert_local(decl, &mut routine)?);
+                            }
+                        } else {
+                            statements.push(self.statement(item, &routine)?);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Locals open the first hop; a hop changes whenever a statement touches
+        // a table on another node
+        let mut hops: Vec<(Option<String>, Vec<String>)> = vec![(None, locals)];
+        for statement in statements {
+            let (node, lines) = hops.last_mut().unwrap();
+            match (statement.node, node.as_ref()) {
+                (Some(next), Some(current)) if next != *current => {
+                    hops.push((Some(next), statement.lines));
+                }
+                (Some(next), None) => {
+                    *node = Some(next);
+                    lines.extend(statement.lines);
+                }
+                _ => lines.extend(statement.lines),
+            }
+        }
+
+        let mut out = format!("{} {}({}) {{\n", ret_type, name, params.join(", "));
+        for (node, lines) in hops {
+            // Hops without table access run on the first table's node
+            let Some(node) = node.or_else(|| self.tables.first().map(|t| t.node.clone())) else {
+                return Err(vec![SpannedError {
+                    error: AstError::SqlError(format!(
+                        "Routine '{}' accesses no table, and the script declares none to run it on",
+                        name
+                    )),
+                    span: Some(span),
+                }]);
+            };
+            out.push_str(&format!("    hop on {} {{\n", node));
+            for line in lines {
+                out.push_str(&format!("        {}\n", line));
+            }
+            out.push_str("    }\n");
+        }
+        out.push_str("}\n");
+        Ok(out)
+    }
+
+    fn declare(&self, routine: &mut Routine, pair: &Pair<Rule>) -> Results<()> {
+        let name = lower(pair);
+        if let Some(previous) = routine.variables.get(&name) {
+            let previous = previous.clone();
+            return Err(self.error_at(pair, AstError::DuplicateVariable { name, previous }));
+        }
+        routine.variables.insert(name, self.span(pair));
+        Ok(())
+    }
+
+    /// A `DECLARE` entry; locals without an initializer start at the type's zero value.
+    fn convert_local(&self, pair: Pair<Rule>, routine: &mut Routine) -> Results<String> {
+        let span = self.span(&pair);
+        let mut parts = pair.into_inner();
+        let name_pair = next_pair(&mut parts, &span)?;
+        let ty = sql_type(&next_pair(&mut parts, &span)?);
+        let init = match parts.next() {
+            Some(init) => self.expression(init, routine, None)?,
+            None => default_value(&ty).to_string(),
+        };
+        self.declare(routine, &name_pair)?;
+        Ok(format!(
+            "{} {} = {};",
+            type_text(&ty),
+            lower(&name_pair),
+            init
+        ))
+    }
+
+    // ========================================================================
+    // Statements
+    // ========================================================================
+
+    fn statement(&self, pair: Pair<Rule>, routine: &Routine) -> Results<Lines> {
+        let span = self.span(&pair);
+        match pair.as_rule() {
+            Rule::assignment => {
+                let mut parts = pair.into_inner();
+                let target = self.variable(&next_pair(&mut parts, &span)?, routine)?;
+                let value = self.expression(next_pair(&mut parts, &span)?, routine, None)?;
+                Ok(Lines::local(vec![format!("{} = {};", target, value)]))
+            }
+            Rule::select_into => self.select_into(pair, routine),
+            Rule::update_stmt => self.update(pair, routine),
+            Rule::insert_stmt => self.insert(pair, routine),
+            Rule::if_stmt => {
+                let mut parts = pair.into_inner();
+                let mut branches = vec![(next_pair(&mut parts, &span)?, Vec::new())];
+                let mut otherwise = None;
+                for part in parts {
+                    match part.as_rule() {
+                        Rule::elsif_branch => {
+                            let mut inner = part.into_inner();
+                            branches.push((next_pair(&mut inner, &span)?, inner.collect()));
+                        }
+                        Rule::else_branch => otherwise = Some(part.into_inner().collect()),
+                        _ => branches.last_mut().unwrap().1.push(part),
+                    }
+                }
+                self.if_chain(branches, otherwise, routine, &span)
+            }
+            Rule::while_stmt => {
+                let mut parts = pair.into_inner();
+                let condition = self.expression(next_pair(&mut parts, &span)?, routine, None)?;
+                let body = self.block(parts.collect(), routine, &span)?;
+                let mut lines = vec![format!("while ({}) {{", condition)];
+                lines.extend(indent(body.lines));
+                lines.push("}".to_string());
+                Ok(Lines {
+                    lines,
+                    node: body.node,
+                })
+            }
+            Rule::exit_stmt => Ok(Lines::local(vec!["break;".to_string()])),
+            Rule::continue_stmt => Ok(Lines::local(vec!["continue;".to_string()])),
+            Rule::return_stmt => match pair.into_inner().next() {
+                Some(value) => {
+                    let value = self.expression(value, routine, None)?;
+                    Ok(Lines::local(vec![format!("return {};", value)]))
+                }
+                None => Ok(Lines::local(vec!["return;".to_string()])),
+            },
+            Rule::raise_stmt => Ok(Lines::local(vec!["abort;".to_string()])),
+            _ => Ok(Lines::local(Vec::new())),
+        }
+    }
+
+    /// Converts nested statements, which must all stay on one node.
+    fn block(&self, pairs: Vec<Pair<Rule>>, routine: &Routine, span: &Span) -> Results<Lines> {
+        let mut block = Lines::local(Vec::new());
+        for pair in pairs {
+            let statement = self.statement(pair, routine)?;
+            block.node = self.same_node(block.node, statement.node, span)?;
+            block.lines.extend(statement.lines);
+        }
+        Ok(block)
+    }
+
+    fn same_node(
+        &self,
+        current: Option<String>,
+        next: Option<String>,
+        span: &Span,
+    ) -> Results<Option<String>> {
+        match (current, next) {
+            (Some(current), Some(next)) if current != next => Err(vec![SpannedError {
+                error: AstError::SqlError(format!(
+                    "Statement touches tables on nodes '{}' and '{}', but a hop runs on one node",
+                    current, next
+                )),
+                span: Some(span.clone()),
+            }]),
+            (current, next) => Ok(current.or(next)),
+        }
+    }
+
+    /// `IF ... ELSIF ... ELSE` becomes nested `if`/`else` blocks.
+    fn if_chain(
+        &self,
+        mut branches: Vec<(Pair<Rule>, Vec<Pair<Rule>>)>,
+        otherwise: Option<Vec<Pair<Rule>>>,
+        routine: &Routine,
+        span: &Span,
+    ) -> Results<Lines> {
+        let (condition, body) = branches.remove(0);
+        let condition = self.expression(condition, routine, None)?;
+        let body = self.block(body, routine, span)?;
+
+        let else_body = if !branches.is_empty() {
+            Some(self.if_chain(branches, otherwise, routine, span)?)
+        } else {
+            match otherwise {
+                Some(pairs) => Some(self.block(pairs, routine, span)?),
+                None => None,
+            }
+        };
+
+        let mut lines = vec![format!("if ({}) {{", condition)];
+        lines.extend(indent(body.lines));
+        let mut node = body.node;
+        match else_body {
+            Some(else_body) => {
+                node = self.same_node(node, else_body.node, span)?;
+                lines.push("} else {".to_string());
+                lines.extend(indent(else_body.lines));
+                lines.push("}".to_string());
+            }
+            None => lines.push("}".to_string()),
+        }
+        Ok(Lines { lines, node })
+    }
+
+    /// `SELECT c1, c2 INTO v1, v2 FROM t WHERE <key>` reads one row into variables.
+    fn select_into(&self, pair: Pair<Rule>, routine: &Routine) -> Results<Lines> {
+        let span = self.span(&pair);
+        let mut parts = pair.into_inner();
+        let columns: Vec<_> = identifiers(next_pair(&mut parts, &span)?).collect();
+        let targets: Vec<_> = identifiers(next_pair(&mut parts, &span)?).collect();
+        let table = self.table(&next_pair(&mut parts, &span)?)?;
+        let key = self.key(table, next_pair(&mut parts, &span)?, routine)?;
+
+        if columns.len() != targets.len() {
+            return Err(vec![SpannedError {
+                error: AstError::SqlError(format!(
+                    "SELECT lists {} column(s) but INTO names {} variable(s)",
+                    columns.len(),
+                    targets.len()
+                )),
+                span: Some(span),
+            }]);
+        }
+
+        let mut lines = Vec::new();
+        for (column, target) in columns.iter().zip(&targets) {
+            let column = self.column(table, column)?;
+            let target = self.variable(target, routine)?;
+            lines.push(format!(
+                "{} = {}[{}].{};",
+                target, table.name, key, column.name
+            ));
+        }
+        Ok(Lines::on(&table.node, lines))
+    }
+
+    /// `UPDATE t SET c = e, ... WHERE <key>` writes one row.
+    fn update(&self, pair: Pair<Rule>, routine: &Routine) -> Results<Lines> {
+        let span = self.span(&pair);
+        let mut parts = pair.into_inner();
+        let table = self.table(&next_pair(&mut parts, &span)?)?;
+        let mut items: Vec<_> = parts.collect();
+        let Some(where_clause) = items.pop() else {
+            return Err(vec![SpannedError {
+                error: AstError::SqlError("UPDATE must have a WHERE clause".to_string()),
+                span: Some(span),
+            }]);
+        };
+        let row = Row {
+            table,
+            key: self.key(table, where_clause, routine)?,
+        };
+
+        let mut writes = Vec::new();
+        for item in items {
+            let mut item = item.into_inner();
+            let column_pair = next_pair(&mut item, &span)?;
+            let column = self.column(table, &column_pair)?;
+            if column.primary {
+                return Err(self.sql_error(
+                    &column_pair,
+                    format!("Cannot update primary key column '{}'", column.name),
+                ));
+            }
+            let value = self.expression(next_pair(&mut item, &span)?, routine, Some(&row))?;
+            writes.push((column.name.clone(), value));
+        }
+        Ok(Lines::on(&table.node, write_row(table, &row.key, writes)))
+    }
+
+    /// `INSERT INTO t (cols) VALUES (...)` writes the non-key columns of one row.
+    fn insert(&self, pair: Pair<Rule>, routine: &Routine) -> Results<Lines> {
+        let span = self.span(&pair);
+        let mut parts = pair.into_inner();
+        let table = self.table(&next_pair(&mut parts, &span)?)?;
+        let columns: Vec<_> = identifiers(next_pair(&mut parts, &span)?).collect();
+        let values: Vec<_> = parts.collect();
+
+        if columns.len() != values.len() {
+            return Err(vec![SpannedError {
+                error: AstError::SqlError(format!(
+                    "INSERT lists {} column(s) but {} value(s)",
+                    columns.len(),
+                    values.len()
+                )),
+                span: Some(span),
+            }]);
+        }
+
+        let mut keys = HashMap::new();
+        let mut writes = Vec::new();
+        for (column_pair, value) in columns.iter().zip(values) {
+            let column = self.column(table, column_pair)?;
+            let value = self.expression(value, routine, None)?;
+            let duplicate = if column.primary {
+                keys.insert(column.name.clone(), value).is_some()
+            } else {
+                let duplicate = writes.iter().any(|(name, _)| *name == column.name);
+                writes.push((column.name.clone(), value));
+                duplicate
+            };
+            if duplicate {
+                return Err(self.sql_error(
+                    column_pair,
+                    format!("Column '{}' is listed twice", column.name),
+                ));
+            }
+        }
+
+        let key = self.key_text(table, keys, &span)?;
+        if writes.is_empty() {
+            return Err(vec![SpannedError {
+                error: AstError::SqlError(format!(
+                    "INSERT into '{}' must set at least one non-key column",
+                    table.name
+                )),
+                span: Some(span),
+            }]);
+        }
+        Ok(Lines::on(&table.node, write_row(table, &key, writes)))
+    }
+
+    /// A `WHERE` clause, which must fix the whole primary key with equalities.
+    fn key(&self, table: &Table, pair: Pair<Rule>, routine: &Routine) -> Results<String> {
+        let span = self.span(&pair);
+        let mut keys = HashMap::new();
+        for condition in pair.into_inner() {
+            let mut parts = condition.into_inner();
+            let column_pair = next_pair(&mut parts, &span)?;
+            let column = self.column(table, &column_pair)?;
+            if !column.primary {
+                return Err(self.error_at(
+                    &column_pair,
+                    AstError::InvalidPrimaryKey {
+                        table: table.name.clone(),
+                        column: column.name.clone(),
+                    },
+                ));
+            }
+            let value = self.expression(next_pair(&mut parts, &span)?, routine, None)?;
+            if keys.insert(column.name.clone(), value).is_some() {
+                return Err(self.sql_error(
+                    &column_pair,
+                    format!("Column '{}' is listed twice", column.name),
+                ));
+            }
+        }
+        self.key_text(table, keys, &span)
+    }
+
+    /// Formats key values in declaration order, reporting missing key columns.
+    fn key_text(
+        &self,
+        table: &Table,
+        mut keys: HashMap<String, String>,
+        span: &Span,
+    ) -> Results<String> {
+        let mut pairs = Vec::new();
+        for column in table.primary_keys() {
+            match keys.remove(&column.name) {
+                Some(value) => pairs.push(format!("{}: {}", column.name, value)),
+                None => {
+                    return Err(vec![SpannedError {
+                        error: AstError::SqlError(format!(
+                            "Statement must fix primary key column '{}' of table '{}'",
+                            column.name, table.name
+                        )),
+                        span: Some(span.clone()),
+                    }])
+                }
+            }
+        }
+        Ok(pairs.join(", "))
+    }
+
+    // ========================================================================
+    // Expressions
+    // ========================================================================
+
+    fn variable(&self, pair: &Pair<Rule>, routine: &Routine) -> Results<String> {
+        let name = lower(pair);
+        if routine.variables.contains_key(&name) {
+            Ok(name)
+        } else {
+            Err(self.error_at(pair, AstError::UndeclaredVariable(name)))
+        }
+    }
+
+    fn expression(
+        &self,
+        pair: Pair<Rule>,
+        routine: &Routine,
+        row: Option<&Row>,
+    ) -> Results<String> {
+        let span = self.span(&pair);
+        match pair.as_rule() {
+            Rule::expression
+            | Rule::or_expr
+            | Rule::and_expr
+            | Rule::comparison
+            | Rule::additive
+            | Rule::multiplicative => {
+                let mut parts = pair.into_inner();
+                let first = next_pair(&mut parts, &span)?;
+                if parts.peek().is_none() {
+                    return self.expression(first, routine, row);
+                }
+                let mut text = self.operand(first, routine, row)?;
+                while let Some(op) = parts.next() {
+                    let rhs = self.operand(next_pair(&mut parts, &span)?, routine, row)?;
+                    text = format!("{} {} {}", text, operator(&op), rhs);
+                }
+                Ok(text)
+            }
+            Rule::not_expr | Rule::unary => {
+                let mut parts = pair.into_inner();
+                let first = next_pair(&mut parts, &span)?;
+                match first.as_rule() {
+                    Rule::not_op | Rule::negate_op => {
+                        let operand = self.operand(next_pair(&mut parts, &span)?, routine, row)?;
+                        Ok(format!("{}{}", operator(&first), operand))
+                    }
+                    _ => self.expression(first, routine, row),
+                }
+            }
+            Rule::bool_literal => Ok(pair.as_str().to_lowercase()),
+            Rule::string_literal => {
+                let text = pair.as_str();
+                let text = text[1..text.len() - 1].replace("''", "'");
+                if text.contains('"') {
+                    return Err(self.sql_error(
+                        &pair,
+                        "String literals cannot contain '\"' in TransAct".to_string(),
+                    ));
+                }
+                Ok(format!("\"{}\"", text))
+            }
+            Rule::identifier => {
+                let name = lower(&pair);
+                let column =
+                    row.and_then(|row| row.table.column(&name).map(|column| (row, column)));
+                match (routine.variables.contains_key(&name), column) {
+                    (true, Some((row, _))) => Err(self.sql_error(
+                        &pair,
+                        format!(
+                            "'{}' is both a variable and a column of '{}'",
+                            name, row.table.name
+                        ),
+                    )),
+                    (true, None) => Ok(name),
+                    (false, Some((row, column))) => {
+                        Ok(format!("{}[{}].{}", row.table.name, row.key, column.name))
+                    }
+                    (false, None) => Err(self.error_at(&pair, AstError::UndeclaredVariable(name))),
+                }
+            }
+            _ => Ok(pair.as_str().to_string()),
+        }
+    }
+
+    /// Converts an operand, parenthesizing it when it is itself a binary expression.
+    fn operand(&self, pair: Pair<Rule>, routine: &Routine, row: Option<&Row>) -> Results<String> {
+        let compound = is_compound(&pair);
+        let text = self.expression(pair, routine, row)?;
+        Ok(if compound {
+            format!("({})", text)
+        } else {
+            text
+        })
+    }
+
+    // ========================================================================
+    // Errors
+    // ========================================================================
+
+    fn span(&self, pair: &Pair<Rule>) -> Span {
+        Span::from_pest_in(pair.as_span(), self.file.clone())
+    }
+
+    fn error_at(&self, pair: &Pair<Rule>, error: AstError) -> Vec<SpannedError> {
+        vec![SpannedError {
+            error,
+            span: Some(self.span(pair)),
+        }]
+    }
+
+    fn sql_error(&self, pair: &Pair<Rule>, message: String) -> Vec<SpannedError> {
+        self.error_at(pair, AstError::SqlError(message))
+    }
+}
+
+/// SQL identifiers are case-insensitive, so names are folded to lower case.
+fn lower(pair: &Pair<Rule>) -> String {
+    pair.as_str().to_lowercase()
+}
+
+fn identifiers(list: Pair<Rule>) -> impl Iterator<Item = Pair<Rule>> {
+    list.into_inner()
+}
+
+fn sql_type(pair: &Pair<Rule>) -> TypeName {
+    let word = pair
+        .clone()
+        .into_inner()
+        .next()
+        .map_or(pair.as_str(), |word| word.as_str())
+        .to_lowercase();
+    match word.split_whitespace().next().unwrap_or_default() {
+        "double" | "real" | "float" | "numeric" | "decimal" => TypeName::Float,
+        "text" | "varchar" | "character" | "char" => TypeName::String,
+        "boolean" | "bool" => TypeName::Bool,
+        _ => TypeName::Int,
+    }
+}
+
+fn type_text(ty: &TypeName) -> &'static str {
+    match ty {
+        TypeName::Int => "int",
+        TypeName::Float => "float",
+        TypeName::String => "string",
+        TypeName::Bool => "bool",
+    }
+}
+
+fn default_value(ty: &TypeName) -> &'static str {
+    match ty {
+        TypeName::Int => "0",
+        TypeName::Float => "0.0",
+        TypeName::String => "\"\"",
+        TypeName::Bool => "false",
+    }
+}
+
+fn operator(pair: &Pair<Rule>) -> &'static str {
+    match pair.as_rule() {
+        Rule::or_op => "||",
+        Rule::and_op => "&&",
+        Rule::not_op => "!",
+        _ => match pair.as_str() {
+            "=" => "==",
+            "<>" | "!=" => "!=",
+            "<" => "<",
+            "<=" => "<=",
+            ">" => ">",
+            ">=" => ">=",
+            "+" => "+",
+            "-" => "-",
+            "*" => "*",
+            _ => "/",
+        },
+    }
+}
+
+/// Whether an expression pair prints as a binary expression.
+fn is_compound(pair: &Pair<Rule>) -> bool {
+    let mut inner = pair.clone().into_inner();
+    match (inner.next(), inner.next()) {
+        (Some(child), None) => is_compound(&child),
+        (Some(_), Some(_)) => !matches!(pair.as_rule(), Rule::not_expr | Rule::unary),
+        _ => false,
+    }
+}
+
+fn indent(lines: Vec<String>) -> impl Iterator<Item = String> {
+    lines.into_iter().map(|line| format!("    {}", line))
+}
+
+/// A single-column write, or a multi-assignment when several columns change.
+fn write_row(table: &Table, key: &str, mut writes: Vec<(String, String)>) -> Vec<String> {
+    if writes.len() == 1 {
+        let (column, value) = writes.remove(0);
+        return vec![format!("{}[{}].{} = {};", table.name, key, column, value)];
+    }
+    let pairs: Vec<_> = writes
+        .iter()
+        .map(|(column, value)| format!("{}: {}", column, value))
+        .collect();
+    vec![format!(
+        "{}[{}] = {{ {} }};",
+        table.name,
+        key,
+        pairs.join(", ")
+    )]
+}
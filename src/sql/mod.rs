@@ -0,0 +1,98 @@
+//! The `sql` module imports SQL stored procedures as TransAct programs.
+//! It accepts a constrained subset of SQL and PL/pgSQL and emits TransAct source,
+//! so existing transactional code can be analyzed without rewriting it by hand.
+//!
+//! # Overview
+//!
+//! - **import_sql**: Converts a SQL script into TransAct source text.
+//!
+//! # Supported subset
+//!
+//! - `CREATE TABLE` with `INT`/`BIGINT`/`SERIAL`, `REAL`/`NUMERIC`/`DOUBLE PRECISION`,
+//!   `TEXT`/`VARCHAR`/`CHAR` and `BOOLEAN` columns and a column or table `PRIMARY KEY`.
+//! - `CREATE [OR REPLACE] FUNCTION|PROCEDURE` with a `DECLARE ... BEGIN ... END` body,
+//!   optionally quoted with `$$`.
+//! - Statements: `:=` assignment, `SELECT ... INTO ... FROM ... WHERE`, `UPDATE ... SET`,
+//!   `INSERT INTO ... VALUES`, `IF`/`ELSIF`/`ELSE`, `WHILE ... LOOP`, `EXIT`, `CONTINUE`,
+//!   `RETURN`, `RAISE EXCEPTION` (becomes `abort`) and `NULL`.
+//! - Every `WHERE` clause must fix the whole primary key with `=`, since TransAct only
+//!   addresses single rows.
+//!
+//! # Hop inference
+//!
+//! Each table is placed on a node by a table→node mapping. Statements are grouped into
+//! hops in order: a new hop starts whenever a statement touches a table on another node.
+//! `IF` and `WHILE` statements must stay on one node. Identifiers are folded to lower
+//! case, as PostgreSQL does for unquoted names.
+//!
+//! # Usage
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//! use FMitF_rs::sql::import_sql;
+//!
+//! let script = "
+//!     CREATE TABLE accounts (id INT PRIMARY KEY, balance INT);
+//!     CREATE FUNCTION deposit(p_id INT, p_amount INT) RETURNS VOID AS $$
+//!     BEGIN
+//!         UPDATE accounts SET balance = balance + p_amount WHERE id = p_id;
+//!     END;
+//!     $$ LANGUAGE plpgsql;
+//! ";
+//! let nodes = HashMap::from([("accounts".to_string(), "Bank".to_string())]);
+//! let source = import_sql(script, None, &nodes).unwrap();
+//! assert!(source.contains("accounts[id: p_id].balance = accounts[id: p_id].balance + p_amount;"));
+//! ```
+
+mod converter;
+
+use pest::Parser;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::ast::{check_nesting, AstError, Lexical, Results, SpannedError};
+use converter::Converter;
+
+#[derive(pest_derive::Parser)]
+#[grammar = "sql/grammar.pest"]
+struct SqlParser;
+
+const SQL: Lexical = Lexical {
+    quote: '\'',
+    line_comment: "--",
+    block_comment: Some(("/*", "*/")),
+    prefix_words: &["not"],
+};
+
+/// Converts a SQL script into TransAct source.
+///
+/// `table_nodes` maps table names (case-insensitively) to the node they live on; every
+/// table the script creates needs an entry. Error spans point into the SQL script.
+pub fn import_sql(
+    source: &str,
+    path: Option<&Path>,
+    table_nodes: &HashMap<String, String>,
+) -> Results<String> {
+    check_nesting(source, &SQL, path.map(Arc::from))?;
+    let script = SqlParser::parse(Rule::script, source)
+        .map_err(|e| {
+            let e = match path {
+                Some(path) => e.with_path(&path.display().to_string()),
+                None => e,
+            };
+            vec![SpannedError {
+                error: AstError::ParseError(e.to_string()),
+                span: None,
+            }]
+        })?
+        .next()
+        .ok_or_else(|| {
+            vec![SpannedError {
+                error: AstError::ParseError("No script found".to_string()),
+                span: None,
+            }]
+        })?;
+
+    Converter::new(path, table_nodes).convert(script)
+}
@@ -0,0 +1,213 @@
+//! Proof artifacts, to audit the claim that a chopping is serializable.
+//!
+//! For every C-edge a verify run removes from the SC-graph, `export` keeps how
+//! it was discharged: the verification condition exactly as the prover was
+//! given it, with the prover's proof for provers that write one (Z3 and CVC5;
+//! Boogie does not), or, for an edge removed without a prover, why. A
+//! `manifest.json` lists the edges with the prover, its version and the
+//! options the conditions were written and checked with. `replay` checks the
+//! conditions of such a directory again, with the prover of the run or any
+//! other reading the same language, without the workload.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::verification::artifacts::{export, replay, Manifest};
+//! use FMitF_rs::verification::solver::SolverLimits;
+//! use FMitF_rs::verification::VerificationManager;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void inc(int id) { hop on A { T[id: id].n = T[id: id].n + 1; } }
+//!     void add(int id) { hop on A { T[id: id].n = T[id: id].n + 2; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let mut sc_graph = SCGraph::new(&cfg);
+//! let mut manager = VerificationManager::new();
+//! manager.discharges = Some(Vec::new());
+//! manager.run_commutativity_pipeline(&cfg, &mut sc_graph);
+//!
+//! let dir = std::env::temp_dir().join(format!("fmitf-artifacts-{}", std::process::id()));
+//! export(&manager, &cfg, SolverLimits::default(), &dir).unwrap();
+//! let manifest = Manifest::load(&dir).unwrap();
+//! assert_eq!(manifest.prover, "Boogie");
+//! // Increments commute from any state, so no prover was needed
+//! assert_eq!(manifest.edges[0].discharged_by, "symbolic execution");
+//! assert_eq!(manifest.edges[0].condition, None);
+//!
+//! // So there is nothing to check again
+//! let (_, answers) = replay(&dir, manager.solver.as_mut()).unwrap();
+//! assert!(answers.is_empty());
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::cfg::CfgProgram;
+use crate::verification::boogie_file_manager::{BoogieFile, BoogieFileManager};
+use crate::verification::invariants::InitialState;
+use crate::verification::solver::{
+    IntEncoding, SolverAnswer, SolverBackend, SolverLimits, StringEncoding,
+};
+use crate::verification::VerificationManager;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The file in an artifact directory that lists its edges.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// What discharged the C-edges of a run, and with what.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// This tool and its version, which wrote the conditions
+    pub tool: String,
+    /// The prover, as messages name it
+    pub prover: String,
+    /// Its version, as it reports it
+    pub version: Option<String>,
+    /// What tells its answers apart, such as the command of an SMT solver
+    pub command: String,
+    pub limits: SolverLimits,
+    pub integers: IntEncoding,
+    pub strings: StringEncoding,
+    /// What records start from, which decides the invariants assumed
+    #[serde(default)]
+    pub initial_state: InitialState,
+    /// The invariants every condition assumes
+    pub invariants: Vec<String>,
+    pub edges: Vec<EdgeArtifact>,
+}
+
+/// How one C-edge was discharged, as the manifest lists it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct EdgeArtifact {
+    /// The hops at either end, as `f:H0`
+    pub hops: [String; 2],
+    /// `prover`, or what removed the edge without one: `disjoint accesses`
+    /// or `symbolic execution`
+    pub discharged_by: String,
+    /// The file of the condition the prover proved
+    pub condition: Option<String>,
+    /// The file of its proof, if the prover wrote one
+    pub proof: Option<String>,
+}
+
+/// How one C-edge was discharged, as the manager keeps it.
+#[derive(Debug, Clone)]
+pub struct Discharge {
+    /// The hops at either end, as `f:H0`
+    pub hops: [String; 2],
+    /// What discharged it, as `EdgeArtifact::discharged_by`
+    pub by: &'static str,
+    /// The condition the prover proved
+    pub condition: Option<BoogieFile>,
+    /// The prover's proof of it
+    pub proof: Option<String>,
+}
+
+impl Manifest {
+    /// Reads the manifest of an artifact directory.
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let path = dir.join(MANIFEST_FILE);
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid manifest '{}': {}", path.display(), e))
+    }
+}
+
+/// Writes the discharges a manager kept to `dir`, each condition and proof in
+/// a file of its own next to the manifest, and returns the manifest.
+pub fn export(
+    manager: &VerificationManager,
+    cfg: &CfgProgram,
+    limits: SolverLimits,
+    dir: &Path,
+) -> Result<Manifest, String> {
+    let mut discharges: Vec<&Discharge> = manager.discharges.iter().flatten().collect();
+    discharges.sort_by(|a, b| a.hops.cmp(&b.hops));
+    let mut files = Vec::new();
+    let mut edges = Vec::new();
+    for discharge in discharges {
+        let proof = match (&discharge.condition, &discharge.proof) {
+            (Some(condition), Some(proof)) => {
+                let stem = condition
+                    .filename
+                    .rsplit_once('.')
+                    .map_or(condition.filename.as_str(), |(stem, _)| stem);
+                Some(BoogieFile {
+                    filename: format!("{}.proof", stem),
+                    code: proof.clone(),
+                })
+            }
+            _ => None,
+        };
+        edges.push(EdgeArtifact {
+            hops: discharge.hops.clone(),
+            discharged_by: discharge.by.to_string(),
+            condition: discharge.condition.as_ref().map(|c| c.filename.clone()),
+            proof: proof.as_ref().map(|p| p.filename.clone()),
+        });
+        files.extend(discharge.condition.clone());
+        files.extend(proof);
+    }
+    let manifest = Manifest {
+        tool: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        prover: manager.solver.name().to_string(),
+        version: manager.solver.version(),
+        command: manager.solver.fingerprint(),
+        limits,
+        integers: manager.encoding.ints,
+        strings: manager.encoding.strings,
+        initial_state: manager.initial_state,
+        invariants: manager
+            .assumed()
+            .iter()
+            .map(|invariant| invariant.format(cfg))
+            .collect(),
+        edges,
+    };
+    files.push(BoogieFile {
+        filename: MANIFEST_FILE.to_string(),
+        code: serde_json::to_string_pretty(&manifest).expect("manifests serialize"),
+    });
+    BoogieFileManager::write_files(&files, dir)?;
+    Ok(manifest)
+}
+
+/// Submits the condition of every edge in the artifact directory `dir` to a
+/// prover again, and returns the manifest with each such edge and the
+/// prover's answer, in order. The prover must read the conditions' language.
+pub fn replay(
+    dir: &Path,
+    solver: &mut dyn SolverBackend,
+) -> Result<(Manifest, Vec<(EdgeArtifact, SolverAnswer)>), String> {
+    let manifest = Manifest::load(dir)?;
+    let mut answers = Vec::new();
+    for edge in &manifest.edges {
+        let Some(filename) = &edge.condition else {
+            continue;
+        };
+        if !filename.ends_with(&format!(".{}", solver.extension())) {
+            return Err(format!(
+                "'{}' was written for {}, which {} cannot read",
+                filename,
+                manifest.prover,
+                solver.name()
+            ));
+        }
+        let path = dir.join(filename);
+        let code = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+        let condition = BoogieFile {
+            filename: filename.clone(),
+            code,
+        };
+        answers.push((edge.clone(), solver.submit(&condition)));
+    }
+    Ok((manifest, answers))
+}
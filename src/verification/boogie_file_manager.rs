@@ -28,6 +28,15 @@ impl BoogieFileManager {
         )
     }
 
+    /// The name, without an extension, of the file holding the conditions of
+    /// every C-edge between the functions of a unit
+    pub fn generate_batch_name(unit: &VerificationUnit, cfg: &CfgProgram) -> String {
+        format!(
+            "{}_{}",
+            cfg.functions[unit.function_a].name, cfg.functions[unit.function_b].name
+        )
+    }
+
     /// Write a Boogie file to a specific directory
     pub fn write_file(file: &BoogieFile, dir: &Path) -> Result<PathBuf, String> {
         let file_path = dir.join(&file.filename);
@@ -0,0 +1,283 @@
+//! Prover answers kept across runs.
+//!
+//! What a prover answers about a C-edge depends on the code of the edge's two
+//! functions, the tables they access and the prover, so answers are keyed by a
+//! hash of these: the functions' optimized CFGs with variables, hops and blocks
+//! numbered within their function, and tables and fields by name. Adding,
+//! removing or moving other declarations leaves the key of an edge as it is,
+//! so verifying an unchanged workload again submits nothing, and after an edit
+//! only the edges of the edited functions are submitted.
+//!
+//! Only definite answers are kept: an edge the prover could not decide, because
+//! it timed out or could not be run, is submitted again on the next run. A
+//! saved cache holds the answers of its last run only.
+//!
+//! ```
+//! use FMitF_rs::verification::cache::{cache_key, VerificationCache};
+//! use FMitF_rs::verification::commutativity_check::create_verification_unit;
+//! use FMitF_rs::verification::solver::SolverAnswer;
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let key = |source: &str| {
+//!     let program = parse_and_analyze(source).unwrap();
+//!     let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//!     let sc_graph = SCGraph::new(&cfg);
+//!     let edge = sc_graph.edges[0].clone();
+//!     cache_key(&create_verification_unit(edge, &cfg, &sc_graph), &cfg, "Z3")
+//! };
+//! let source = "nodes { A }
+//! table T on A { primary int id; int n; }
+//! void inc(int id) { hop on A { T[id: id].n = T[id: id].n + 1; } }
+//! void set(int id) { hop on A { T[id: id].n = 0; } }";
+//!
+//! let mut cache = VerificationCache::default();
+//! cache.insert(key(source), SolverAnswer::Unsat);
+//! let mut cache = VerificationCache::from_json(&cache.to_json()).unwrap();
+//!
+//! // Another table does not change the edge, another constant does
+//! let other = source.replace("nodes { A }", "nodes { A }\ntable U on A { primary int id; }");
+//! assert_eq!(cache.get(&key(&other)), Some(&SolverAnswer::Unsat));
+//! assert_eq!(cache.get(&key(&source.replace("= 0", "= 1"))), None);
+//! ```
+
+use crate::cfg::{CfgProgram, FunctionId, Operand, Rvalue, Statement, TableId, Terminator};
+use crate::pretty::format_constant;
+use crate::verification::commutativity_check::VerificationUnit;
+use crate::verification::solver::SolverAnswer;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::Path;
+
+/// The file answers are kept in when none is named.
+pub const DEFAULT_CACHE_FILE: &str = "fmitf-verify-cache.json";
+
+/// Definite prover answers, by the key `cache_key` gives their C-edge.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct VerificationCache {
+    pub answers: BTreeMap<String, SolverAnswer>,
+    /// The keys looked up or added since loading, which are saved
+    #[serde(skip)]
+    used: BTreeSet<String>,
+    /// How many lookups found an answer
+    #[serde(skip)]
+    pub hits: usize,
+    /// Whether unused answers are saved too
+    #[serde(skip)]
+    keep_unused: bool,
+}
+
+impl VerificationCache {
+    /// Parses a cache from JSON.
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|e| e.to_string())
+    }
+
+    /// The cache as JSON, keeping only the answers used since it was loaded
+    /// unless it keeps all.
+    pub fn to_json(&self) -> String {
+        let answers: BTreeMap<_, _> = self
+            .answers
+            .iter()
+            .filter(|(key, _)| self.keep_unused || self.used.contains(*key))
+            .collect();
+        serde_json::to_string_pretty(&serde_json::json!({ "answers": answers }))
+            .expect("answers serialize")
+    }
+
+    /// Reads the cache file at `path`, or an empty cache if there is none yet.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+        Self::from_json(&text).map_err(|e| format!("Invalid cache '{}': {}", path.display(), e))
+    }
+
+    /// Writes the cache file at `path`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_json())
+            .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))
+    }
+
+    /// Saves the answers not used since loading too, for a cache shared by
+    /// several programs.
+    pub fn keep_unused(&mut self) {
+        self.keep_unused = true;
+    }
+
+    /// The answer kept for a key.
+    pub fn get(&mut self, key: &str) -> Option<&SolverAnswer> {
+        let answer = self.answers.get(key)?;
+        self.used.insert(key.to_string());
+        self.hits += 1;
+        Some(answer)
+    }
+
+    /// Keeps an answer, unless the prover could not decide.
+    pub fn insert(&mut self, key: String, answer: SolverAnswer) {
+        if !matches!(answer, SolverAnswer::Unknown(_)) {
+            self.used.insert(key.clone());
+            self.answers.insert(key, answer);
+        }
+    }
+}
+
+/// The key of a C-edge's answer from a prover: 16 hex digits hashing the
+/// edge's functions, the hops it ends at, the tables the functions access,
+/// the prover and the version of this tool, which writes the conditions.
+pub fn cache_key(unit: &VerificationUnit, cfg: &CfgProgram, prover: &str) -> String {
+    let mut text = format!("{} {}\n", env!("CARGO_PKG_VERSION"), prover);
+    let mut tables = BTreeSet::new();
+    for (function, hop) in [
+        (unit.function_a, unit.final_a),
+        (unit.function_b, unit.final_b),
+    ] {
+        let position = cfg.functions[function]
+            .hop_order
+            .iter()
+            .position(|&h| h == hop);
+        let _ = writeln!(text, "ends at hop {:?}", position);
+        describe_function(&mut text, &mut tables, cfg, function);
+    }
+    // By name, which does not depend on the order of declarations
+    let mut tables: Vec<TableId> = tables.into_iter().collect();
+    tables.sort_by_key(|&table| &cfg.tables[table].name);
+    for table in tables {
+        let info = &cfg.tables[table];
+        let fields: Vec<String> = info
+            .fields
+            .iter()
+            .map(|&field| {
+                let field = &cfg.fields[field];
+                let primary = if field.is_primary { " primary" } else { "" };
+                format!("{:?} {}{}", field.ty, field.name, primary)
+            })
+            .collect();
+        let _ = writeln!(
+            text,
+            "table {} on {} {{ {} }}",
+            info.name,
+            cfg.nodes[info.node_id].name,
+            fields.join(", ")
+        );
+    }
+    hash_text(&text)
+}
+
+/// A text's FNV-1a hash as 16 hex digits, which stay the same across runs
+/// and platforms.
+pub(crate) fn hash_text(text: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Writes a function's CFG with everything it refers to by position within
+/// the function or by name, and collects the tables it accesses.
+fn describe_function(
+    text: &mut String,
+    tables: &mut BTreeSet<TableId>,
+    cfg: &CfgProgram,
+    function: FunctionId,
+) {
+    let func = &cfg.functions[function];
+    let operand = |operand: &Operand| match operand {
+        Operand::Var(var) => format!("v{}", var.index()),
+        Operand::Const(constant) => format_constant(constant),
+    };
+    let access = |tables: &mut BTreeSet<TableId>, table: TableId, key: &[Operand], field| {
+        tables.insert(table);
+        let key: Vec<String> = key.iter().map(operand).collect();
+        format!(
+            "{}[{}].{}",
+            cfg.tables[table].name,
+            key.join(", "),
+            cfg.fields[field].name
+        )
+    };
+    let hop_position = |hop| func.hop_order.iter().position(|&h| h == hop);
+
+    let _ = writeln!(text, "fn {} -> {:?}", func.name, func.return_type);
+    for property in &func.ensures {
+        let _ = writeln!(text, "ensures {}", property);
+    }
+    for (var, info) in func.variables.iter() {
+        let parameter = if info.is_parameter { " parameter" } else { "" };
+        let _ = writeln!(text, "v{} {:?}{}", var.index(), info.ty, parameter);
+    }
+    for &hop in &func.hop_order {
+        let info = &func.hops[hop];
+        let _ = writeln!(
+            text,
+            "hop on {} from b{:?}",
+            cfg.nodes[info.node_id].name,
+            info.entry_block.map(|block| block.index())
+        );
+        for &block in &info.blocks {
+            let _ = write!(text, "b{}:", block.index());
+            for statement in &func.blocks[block].statements {
+                let statement = match statement {
+                    Statement::Assign { var, rvalue, .. } => {
+                        let rvalue = match rvalue {
+                            Rvalue::Use(value) => operand(value),
+                            Rvalue::TableAccess {
+                                table,
+                                pk_values,
+                                field,
+                                ..
+                            } => access(tables, *table, pk_values, *field),
+                            Rvalue::UnaryOp { op, operand: value } => {
+                                format!("{:?} {}", op, operand(value))
+                            }
+                            Rvalue::BinaryOp { op, left, right } => {
+                                format!("{} {:?} {}", operand(left), op, operand(right))
+                            }
+                        };
+                        format!("v{} = {}", var.index(), rvalue)
+                    }
+                    Statement::TableAssign {
+                        table,
+                        pk_values,
+                        field,
+                        value,
+                        ..
+                    } => format!(
+                        "{} = {}",
+                        access(tables, *table, pk_values, *field),
+                        operand(value)
+                    ),
+                };
+                let _ = write!(text, " {};", statement);
+            }
+            let terminator = match &func.blocks[block].terminator {
+                Terminator::Goto(target) => format!("goto b{}", target.index()),
+                Terminator::Branch {
+                    condition,
+                    then_block,
+                    else_block,
+                } => format!(
+                    "if {} b{} else b{}",
+                    operand(condition),
+                    then_block.index(),
+                    else_block.index()
+                ),
+                Terminator::Return(value) => {
+                    format!("return {:?}", value.as_ref().map(operand))
+                }
+                Terminator::Abort => "abort".to_string(),
+                Terminator::HopExit { next_hop } => {
+                    format!("exit to hop {:?}", next_hop.and_then(hop_position))
+                }
+                Terminator::BoundExceeded(check) => format!("bound exceeded {:?}", check),
+            };
+            let _ = writeln!(text, " {}", terminator);
+        }
+    }
+}
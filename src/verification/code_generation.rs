@@ -1,15 +1,108 @@
 use super::commutativity_check::VerificationUnit;
+use super::invariants::Invariant;
+use super::postconditions::ensured;
+use super::solver::Encoding;
 use crate::ast::{BinaryOp, UnaryOp};
 use crate::cfg::{
-    BasicBlockId, CfgProgram, Constant, FunctionId, HopId, Operand, Rvalue, Statement, TableId,
-    TypeName, VarId,
+    lower_function, BasicBlockId, CfgProgram, Constant, FieldId, FunctionId, HopId, Operand,
+    Rvalue, Statement, TableId, Terminator, TypeName, VarId,
 };
-use std::collections::HashSet;
+use crate::dataflow::{hop_effects, AccessType};
+use std::collections::{BTreeSet, HashSet};
+use std::ops::RangeInclusive;
+
+/// Strings as an uninterpreted type with a total order, `str_lt`, and the
+/// lowercase folding of `nocase` comparisons, `str_fold`
+const STRING_PRELUDE: &str = "type string;
+function str_lt(a: string, b: string): bool;
+axiom (forall a: string :: !str_lt(a, a));
+axiom (forall a, b, c: string :: str_lt(a, b) && str_lt(b, c) ==> str_lt(a, c));
+axiom (forall a, b: string :: a == b || str_lt(a, b) || str_lt(b, a));
+function str_fold(s: string): string;
+axiom (forall s: string :: str_fold(str_fold(s)) == str_fold(s));
+
+";
+
+/// How string literals are declared, as constants no two of which are equal
+const STRING_CONSTANT: &str = "const unique ";
+
+/// The name of the constant that stands for a string literal, in Boogie and
+/// in SMT-LIB2 scripts that leave strings uninterpreted. Letters and digits
+/// are kept and any other character is written as its code point in hex
+/// between underscores, so no two literals share a name, and the `$` keeps
+/// the name apart from the program's own.
+pub(crate) fn string_literal_name(s: &str) -> String {
+    let mut name = "str$".to_string();
+    for c in s.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else {
+            name.push_str(&format!("_{:x}_", c as u32));
+        }
+    }
+    name
+}
+
+/// The literal a `string_literal_name` stands for, if `name` is one.
+pub(crate) fn string_literal(name: &str) -> Option<String> {
+    let mut literal = String::new();
+    let mut rest = name.strip_prefix("str$")?;
+    while let Some(c) = rest.chars().next() {
+        if c == '_' {
+            let (code, tail) = rest[1..].split_once('_')?;
+            literal.push(char::from_u32(u32::from_str_radix(code, 16).ok()?)?);
+            rest = tail;
+        } else {
+            literal.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    Some(literal)
+}
+
+/// Boogie's bitvectors have no arithmetic of their own, so integers encoded as
+/// bitvectors `bits` wide are added, compared and so on, signed, by functions
+/// of the prover's bitvector theory
+fn bitvector_prelude(bits: u32) -> String {
+    let mut prelude = String::new();
+    let ops = [
+        ("add", "bvadd", "bv"),
+        ("sub", "bvsub", "bv"),
+        ("mul", "bvmul", "bv"),
+        ("div", "bvsdiv", "bv"),
+        ("lt", "bvslt", "bool"),
+        ("le", "bvsle", "bool"),
+        ("gt", "bvsgt", "bool"),
+        ("ge", "bvsge", "bool"),
+    ];
+    for (name, builtin, result) in ops {
+        let result = if result == "bv" {
+            format!("bv{}", bits)
+        } else {
+            result.to_string()
+        };
+        prelude.push_str(&format!(
+            "function {{:bvbuiltin \"{}\"}} bv{}_{}(a: bv{}, b: bv{}): {};\n",
+            builtin, bits, name, bits, bits, result
+        ));
+    }
+    prelude.push_str(&format!(
+        "function {{:bvbuiltin \"bvneg\"}} bv{}_neg(a: bv{}): bv{};\n\n",
+        bits, bits, bits
+    ));
+    prelude
+}
 
 /// Structure to manage Boogie code generation
 pub struct BoogieCodeGenerator<'a> {
     unit: &'a VerificationUnit,
     cfg: &'a CfgProgram,
+    /// Invariants every record is assumed to start with
+    invariants: &'a [Invariant],
+    /// How integers and strings are encoded
+    encoding: Encoding,
+    /// The table fields the hop being generated rolls back on abort
+    rollback: Vec<String>,
     code: String,
     indent_level: usize,
 }
@@ -19,14 +112,37 @@ impl<'a> BoogieCodeGenerator<'a> {
         Self {
             unit,
             cfg,
+            invariants: &[],
+            encoding: Encoding::default(),
+            rollback: Vec::new(),
             code: String::new(),
             indent_level: 0,
         }
     }
 
+    /// Assume the invariants of every record the tables start with
+    pub fn assuming(mut self, invariants: &'a [Invariant]) -> Self {
+        self.invariants = invariants;
+        self
+    }
+
+    /// Encode integers as `encoding` says; Boogie has no theory of strings,
+    /// so they are always uninterpreted
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
     /// Generate complete Boogie code for the verification unit
     pub fn generate(&mut self) -> String {
         self.generate_header_comment();
+        if self.uses_strings() {
+            self.generate_string_prelude();
+            self.generate_string_literals();
+        }
+        if let Some(bits) = self.encoding.ints.bits() {
+            self.code.push_str(&bitvector_prelude(bits));
+        }
         self.generate_main_procedure();
         self.code.clone()
     }
@@ -50,10 +166,61 @@ impl<'a> BoogieCodeGenerator<'a> {
             "// Number of prefix interleavings: {}",
             self.unit.merges.len()
         ));
+        if self.unit.reduced > 0 {
+            self.writeln(&format!(
+                "// ({} more left out, as moving hops past each other makes them equal)",
+                self.unit.reduced
+            ));
+        }
         self.writeln("// -------------------------------------------------------------------");
         self.writeln("");
     }
 
+    /// Whether the unit involves string values, which need the string prelude
+    fn uses_strings(&self) -> bool {
+        let is_string = |ty: &TypeName| *ty == TypeName::String;
+        [self.unit.function_a, self.unit.function_b]
+            .iter()
+            .any(|&function_id| {
+                self.cfg.functions[function_id]
+                    .variables
+                    .iter()
+                    .any(|(_, var)| is_string(&var.ty))
+            })
+            || self.cfg.fields.iter().any(|(_, field)| is_string(&field.ty))
+    }
+
+    /// Declare strings, as `STRING_PRELUDE` does
+    fn generate_string_prelude(&mut self) {
+        self.code.push_str(STRING_PRELUDE);
+    }
+
+    /// Declare the string literals both functions use as distinct constants
+    fn generate_string_literals(&mut self) {
+        let mut literals = BTreeSet::new();
+        for function_id in [self.unit.function_a, self.unit.function_b] {
+            for (_, instructions) in lower_function(&self.cfg.functions[function_id]) {
+                for operand in instructions.iter().flat_map(|inst| inst.used_operands()) {
+                    if let Operand::Const(Constant::String(s)) = operand {
+                        literals.insert(s.clone());
+                    }
+                }
+            }
+        }
+        if literals.is_empty() {
+            return;
+        }
+        for literal in &literals {
+            self.writeln(&format!(
+                "{}{}: string; // {:?}",
+                STRING_CONSTANT,
+                string_literal_name(literal),
+                literal
+            ));
+        }
+        self.writeln("");
+    }
+
     /// Generate the main verification procedure
     fn generate_main_procedure(&mut self) {
         // Extract function parameters for the procedure signature
@@ -116,7 +283,7 @@ impl<'a> BoogieCodeGenerator<'a> {
         // 2. Declare backup tables for state restoration for ALL tables
         for &table_id in &all_tables {
             let table = &self.cfg.tables[table_id];
-            self.generate_table_backup_declaration(table_id, table);
+            self.generate_table_backup_declaration(table_id, table, "init");
         }
 
         // 3. Declare final state tables for comparison (only for relevant tables)
@@ -125,7 +292,22 @@ impl<'a> BoogieCodeGenerator<'a> {
             self.generate_final_state_declarations(table_id, table);
         }
 
-        // 4. Declare local variables used in hops (ALL variables used in any hop)
+        // 4. Declare which transaction aborted in each execution, and the
+        //    tables a hop rolls back to when it aborts
+        if self.may_abort() {
+            self.writeln("var aborted_A: bool;");
+            self.writeln("var aborted_B: bool;");
+            self.writeln("var aborted_A_AB: bool;");
+            self.writeln("var aborted_B_AB: bool;");
+            self.writeln("var aborted_A_BA: bool;");
+            self.writeln("var aborted_B_BA: bool;");
+            for &table_id in &all_tables {
+                let table = &self.cfg.tables[table_id];
+                self.generate_table_backup_declaration(table_id, table, "rollback");
+            }
+        }
+
+        // 5. Declare local variables used in hops (ALL variables used in any hop)
         self.generate_all_variable_declarations();
 
         self.writeln("");
@@ -159,11 +341,13 @@ impl<'a> BoogieCodeGenerator<'a> {
         }
     }
 
-    /// Generate backup declaration for table state restoration
+    /// Generate backup declaration for table state restoration, named with a
+    /// prefix
     fn generate_table_backup_declaration(
         &mut self,
         _table_id: TableId,
         table: &crate::cfg::TableInfo,
+        prefix: &str,
     ) {
         // Generate nested map type for primary keys
         let primary_key_types: Vec<String> = table
@@ -186,8 +370,8 @@ impl<'a> BoogieCodeGenerator<'a> {
                 map_type.push_str(&self.type_to_boogie(&field.ty));
 
                 self.writeln(&format!(
-                    "var init_{}_{}: {};",
-                    table.name, field.name, map_type
+                    "var {}_{}_{}: {};",
+                    prefix, table.name, field.name, map_type
                 ));
             }
         }
@@ -241,70 +425,17 @@ impl<'a> BoogieCodeGenerator<'a> {
             all_tables.insert(table_id);
         }
 
-        // Scan through all hops in both functions to find table accesses
-        let func_a = &self.cfg.functions[self.unit.function_a];
-        let func_b = &self.cfg.functions[self.unit.function_b];
-
-        // Scan function A hops
-        for (_hop_id, hop) in func_a.hops.iter() {
-            for &block_id in &hop.blocks {
-                let block = &func_a.blocks[block_id];
-                for statement in &block.statements {
-                    self.collect_tables_from_statement(statement, &mut all_tables);
-                }
-            }
-        }
-
-        // Scan function B hops
-        for (_hop_id, hop) in func_b.hops.iter() {
-            for &block_id in &hop.blocks {
-                let block = &func_b.blocks[block_id];
-                for statement in &block.statements {
-                    self.collect_tables_from_statement(statement, &mut all_tables);
-                }
+        // Add tables accessed by any hop of either function
+        for function_id in [self.unit.function_a, self.unit.function_b] {
+            let func = &self.cfg.functions[function_id];
+            for (hop_id, _) in func.hops.iter() {
+                all_tables.extend(hop_effects(func, hop_id).iter().map(|access| access.table));
             }
         }
 
         all_tables.into_iter().collect()
     }
 
-    /// Helper method to collect table IDs from a statement
-    fn collect_tables_from_statement(&self, statement: &Statement, tables: &mut HashSet<TableId>) {
-        match statement {
-            Statement::Assign { rvalue, .. } => {
-                self.collect_tables_from_rvalue(rvalue, tables);
-            }
-            Statement::TableAssign { table, .. } => {
-                tables.insert(*table);
-            }
-        }
-    }
-
-    /// Helper method to collect table IDs from an rvalue
-    fn collect_tables_from_rvalue(&self, rvalue: &Rvalue, tables: &mut HashSet<TableId>) {
-        match rvalue {
-            Rvalue::Use(_) => {}
-            Rvalue::TableAccess { table, .. } => {
-                tables.insert(*table);
-            }
-            Rvalue::UnaryOp { operand, .. } => {
-                if let Operand::Const(_) = operand {
-                    // No tables in constants
-                }
-                // Variables don't contain table references
-            }
-            Rvalue::BinaryOp { left, right, .. } => {
-                // Check operands for table references (though unlikely)
-                if let Operand::Const(_) = left {
-                    // No tables in constants
-                }
-                if let Operand::Const(_) = right {
-                    // No tables in constants
-                }
-            }
-        }
-    }
-
     /// Generate ALL variable declarations (from all hops, not just relevant_vars)
     fn generate_all_variable_declarations(&mut self) {
         let mut declared_vars = HashSet::new();
@@ -361,6 +492,35 @@ impl<'a> BoogieCodeGenerator<'a> {
             }
         }
 
+        // Start from states every execution keeps
+        let invariants: Vec<&Invariant> = self
+            .invariants
+            .iter()
+            .filter(|invariant| all_tables.contains(&invariant.table()))
+            .collect();
+        if !invariants.is_empty() {
+            self.writeln("");
+            self.writeln("// Assume the inferred invariants");
+            for invariant in invariants {
+                let assumption = self.generate_invariant(invariant);
+                self.writeln(&format!("assume {};", assumption));
+            }
+        }
+        let mut ensured = self.ensured(self.unit.function_a, self.unit.final_a);
+        for invariant in self.ensured(self.unit.function_b, self.unit.final_b) {
+            if !ensured.contains(&invariant) {
+                ensured.push(invariant);
+            }
+        }
+        if !ensured.is_empty() {
+            self.writeln("");
+            self.writeln("// Assume every record starts with what the transactions ensure");
+            for invariant in &ensured {
+                let assumption = self.generate_invariant(invariant);
+                self.writeln(&format!("assume {};", assumption));
+            }
+        }
+
         // Save initial state for ALL tables
         self.writeln("");
         self.writeln("// Save initial state");
@@ -385,6 +545,70 @@ impl<'a> BoogieCodeGenerator<'a> {
         }
     }
 
+    /// Generate an invariant of every record of its table as a quantified
+    /// condition
+    fn generate_invariant(&self, invariant: &Invariant) -> String {
+        let table = &self.cfg.tables[invariant.table()];
+        let keys: Vec<String> = table
+            .primary_keys
+            .iter()
+            .enumerate()
+            .map(|(i, &pk_id)| {
+                format!(
+                    "k{}: {}",
+                    i,
+                    self.type_to_boogie(&self.cfg.fields[pk_id].ty)
+                )
+            })
+            .collect();
+        let index: String = (0..keys.len()).map(|i| format!("[k{}]", i)).collect();
+        let field = |field_id: FieldId| {
+            format!("{}_{}{}", table.name, self.cfg.fields[field_id].name, index)
+        };
+        let bound = |constant: &Constant| match constant {
+            // Boogie reads a real literal only with a decimal point
+            Constant::Float(f) => format!("{:?}", f.0),
+            constant => self.generate_constant(constant),
+        };
+        // Integers encoded as bitvectors are compared and added by functions
+        let bits = invariant
+            .fields()
+            .first()
+            .filter(|&&field_id| self.cfg.fields[field_id].ty == TypeName::Int)
+            .and_then(|_| self.encoding.ints.bits());
+        let apply = |op: &BinaryOp, left: String, right: String| match bitvector_op(op).zip(bits) {
+            Some((name, bits)) => format!("bv{}_{}({}, {})", bits, name, left, right),
+            None => format!("{} {} {}", left, self.binary_op_to_boogie(op), right),
+        };
+        let condition = match invariant {
+            Invariant::AtLeast {
+                field: field_id,
+                bound: constant,
+                ..
+            } => apply(&BinaryOp::Gte, field(*field_id), bound(constant)),
+            Invariant::AtMost {
+                field: field_id,
+                bound: constant,
+                ..
+            } => apply(&BinaryOp::Lte, field(*field_id), bound(constant)),
+            Invariant::Equal { left, right, .. } => {
+                format!("{} == {}", field(*left), field(*right))
+            }
+            Invariant::Sum {
+                left, right, total, ..
+            } => format!(
+                "{} == {}",
+                apply(&BinaryOp::Add, field(*left), field(*right)),
+                field(*total)
+            ),
+        };
+        if keys.is_empty() {
+            condition
+        } else {
+            format!("(forall {} :: {})", keys.join(", "), condition)
+        }
+    }
+
     /// Generate verification check for a single prefix interleaving
     fn generate_single_interleaving_check(
         &mut self,
@@ -398,12 +622,16 @@ impl<'a> BoogieCodeGenerator<'a> {
 
         // First execution: prefix + [final_a, final_b]
         self.writeln("// First execution: [final_a, final_b]");
+        self.generate_abort_reset();
         self.generate_hop_sequence(merge);
         self.generate_hop_execution(self.unit.final_a, self.unit.function_a);
+        self.generate_ensures_check(self.unit.function_a, self.unit.final_a);
         self.generate_hop_execution(self.unit.final_b, self.unit.function_b);
+        self.generate_ensures_check(self.unit.function_b, self.unit.final_b);
 
         // Save final state A->B (only for relevant tables)
         self.generate_state_save("AB");
+        self.generate_abort_save("AB");
 
         // Restore initial state for ALL tables
         self.writeln("");
@@ -424,12 +652,16 @@ impl<'a> BoogieCodeGenerator<'a> {
         // Second execution: prefix + [final_b, final_a]
         self.writeln("");
         self.writeln("// Second execution: [final_b, final_a]");
+        self.generate_abort_reset();
         self.generate_hop_sequence(merge);
         self.generate_hop_execution(self.unit.final_b, self.unit.function_b);
+        self.generate_ensures_check(self.unit.function_b, self.unit.final_b);
         self.generate_hop_execution(self.unit.final_a, self.unit.function_a);
+        self.generate_ensures_check(self.unit.function_a, self.unit.final_a);
 
         // Save final state B->A (only for relevant tables)
         self.generate_state_save("BA");
+        self.generate_abort_save("BA");
 
         // Compare states (only for relevant tables)
         self.writeln("");
@@ -439,6 +671,84 @@ impl<'a> BoogieCodeGenerator<'a> {
         self.writeln("");
     }
 
+    /// What a function ensures when `hop_id` commits it, of the tables the
+    /// unit declares; the tables it leaves undeclared are never written.
+    fn ensured(&self, function_id: FunctionId, hop_id: HopId) -> Vec<Invariant> {
+        let tables = self.collect_all_tables_used();
+        ensured(self.cfg, function_id, hop_id)
+            .into_iter()
+            .filter(|invariant| tables.contains(&invariant.table()))
+            .collect()
+    }
+
+    /// Generate assertions that the records keep what a function ensures
+    /// once `hop_id` commits it, unless it aborted
+    fn generate_ensures_check(&mut self, function_id: FunctionId, hop_id: HopId) {
+        let ensured = self.ensured(function_id, hop_id);
+        if ensured.is_empty() {
+            return;
+        }
+        let guard = if self.may_abort() {
+            format!("{} || ", self.aborted_flag(function_id))
+        } else {
+            String::new()
+        };
+        self.writeln(&format!(
+            "// {} commits: check what it ensures",
+            self.cfg.functions[function_id].name
+        ));
+        for invariant in &ensured {
+            let assertion = self.generate_invariant(invariant);
+            self.writeln(&format!("assert {}{};", guard, assertion));
+        }
+    }
+
+    /// Whether a hop of either function can abort, so executions track aborts
+    fn may_abort(&self) -> bool {
+        [self.unit.function_a, self.unit.function_b]
+            .iter()
+            .any(|&function_id| {
+                let func = &self.cfg.functions[function_id];
+                func.hops
+                    .iter()
+                    .any(|(hop_id, _)| self.hop_aborts(function_id, hop_id))
+            })
+    }
+
+    /// Whether a hop contains an abort
+    fn hop_aborts(&self, function_id: FunctionId, hop_id: HopId) -> bool {
+        let func = &self.cfg.functions[function_id];
+        func.hops[hop_id]
+            .blocks
+            .iter()
+            .any(|&block_id| matches!(func.blocks[block_id].terminator, Terminator::Abort))
+    }
+
+    /// The flag set when a function's transaction aborts
+    fn aborted_flag(&self, function_id: FunctionId) -> &'static str {
+        if function_id == self.unit.function_a {
+            "aborted_A"
+        } else {
+            "aborted_B"
+        }
+    }
+
+    /// Generate code starting an execution with neither transaction aborted
+    fn generate_abort_reset(&mut self) {
+        if self.may_abort() {
+            self.writeln("aborted_A := false;");
+            self.writeln("aborted_B := false;");
+        }
+    }
+
+    /// Generate code recording which transactions aborted in an execution
+    fn generate_abort_save(&mut self, suffix: &str) {
+        if self.may_abort() {
+            self.writeln(&format!("aborted_A_{} := aborted_A;", suffix));
+            self.writeln(&format!("aborted_B_{} := aborted_B;", suffix));
+        }
+    }
+
     /// Generate execution sequence for a list of hops
     fn generate_hop_sequence(&mut self, hops: &[HopId]) {
         for &hop_id in hops {
@@ -463,6 +773,32 @@ impl<'a> BoogieCodeGenerator<'a> {
         // Generate unique execution context identifier for this hop execution
         let exec_id = self.get_next_execution_id();
 
+        // An aborted transaction runs no further hops, and an abort rolls
+        // back the writes of its hop
+        if self.may_abort() {
+            let flag = self.aborted_flag(function_id);
+            self.writeln(&format!("if ({}) {{", flag));
+            self.indent();
+            self.writeln(&format!("goto hop_end_{};", exec_id));
+            self.dedent();
+            self.writeln("}");
+        }
+        self.rollback.clear();
+        if self.hop_aborts(function_id, hop_id) {
+            for access in hop_effects(function, hop_id) {
+                let name = format!(
+                    "{}_{}",
+                    self.cfg.tables[access.table].name, self.cfg.fields[access.field].name
+                );
+                if access.access_type == AccessType::Write && !self.rollback.contains(&name) {
+                    self.rollback.push(name);
+                }
+            }
+            for name in self.rollback.clone() {
+                self.writeln(&format!("rollback_{} := {};", name, name));
+            }
+        }
+
         // Start execution at the entry block
         if let Some(entry_block) = hop.entry_block {
             self.writeln(&format!(
@@ -569,8 +905,19 @@ impl<'a> BoogieCodeGenerator<'a> {
                 self.writeln(&format!("goto hop_end_{};", exec_id));
             }
             crate::cfg::Terminator::Abort => {
-                self.writeln("// Abort");
-                // Treat abort as goto to hop end
+                self.writeln("// Abort: roll back the hop and skip the later ones");
+                for name in self.rollback.clone() {
+                    self.writeln(&format!("{} := rollback_{};", name, name));
+                }
+                self.writeln(&format!("{} := true;", self.aborted_flag(function_id)));
+                self.writeln(&format!("goto hop_end_{};", exec_id));
+            }
+            crate::cfg::Terminator::BoundExceeded(check) => {
+                self.writeln("// Loop bound exceeded");
+                match check {
+                    crate::cfg::BoundCheck::Assume => self.writeln("assume false;"),
+                    crate::cfg::BoundCheck::Assert => self.writeln("assert false;"),
+                }
                 self.writeln(&format!("goto hop_end_{};", exec_id));
             }
             crate::cfg::Terminator::HopExit { .. } => {
@@ -630,8 +977,8 @@ impl<'a> BoogieCodeGenerator<'a> {
 
                         // Build the access path up to this level: table[k1][k2]...[ki-1]
                         let mut access_path = table_field_name.clone();
-                        for j in 0..i {
-                            let access_key_code = self.generate_operand(&pk_values[j], function_id);
+                        for pk_value in pk_values.iter().take(i) {
+                            let access_key_code = self.generate_operand(pk_value, function_id);
                             access_path.push_str(&format!("[{}]", access_key_code));
                         }
 
@@ -674,11 +1021,30 @@ impl<'a> BoogieCodeGenerator<'a> {
             Rvalue::UnaryOp { op, operand } => {
                 let operand_code = self.generate_operand(operand, function_id);
                 let op_str = self.unary_op_to_boogie(op);
-                format!("({} {})", op_str, operand_code)
+                match (op, self.bitvector_operand(operand, function_id)) {
+                    (UnaryOp::Neg, Some(bits)) => format!("bv{}_neg({})", bits, operand_code),
+                    (UnaryOp::FoldCase, _) => format!("{}({})", op_str, operand_code),
+                    _ => format!("({} {})", op_str, operand_code),
+                }
             }
             Rvalue::BinaryOp { op, left, right } => {
                 let left_code = self.generate_operand(left, function_id);
                 let right_code = self.generate_operand(right, function_id);
+                if self.is_string_operand(left, function_id) {
+                    // Strings are ordered by `str_lt` from the string prelude
+                    match op {
+                        BinaryOp::Lt => return format!("str_lt({}, {})", left_code, right_code),
+                        BinaryOp::Gt => return format!("str_lt({}, {})", right_code, left_code),
+                        BinaryOp::Lte => return format!("!str_lt({}, {})", right_code, left_code),
+                        BinaryOp::Gte => return format!("!str_lt({}, {})", left_code, right_code),
+                        _ => {}
+                    }
+                }
+                if let Some(bits) = self.bitvector_operand(left, function_id) {
+                    if let Some(name) = bitvector_op(op) {
+                        return format!("bv{}_{}({}, {})", bits, name, left_code, right_code);
+                    }
+                }
                 let op_str = self.binary_op_to_boogie(op);
                 format!("({} {} {})", left_code, op_str, right_code)
             }
@@ -693,13 +1059,38 @@ impl<'a> BoogieCodeGenerator<'a> {
         }
     }
 
-    /// Generate Boogie code for a constant
+    /// Whether an operand holds a string
+    fn is_string_operand(&self, operand: &Operand, function_id: FunctionId) -> bool {
+        match operand {
+            Operand::Var(var_id) => {
+                self.cfg.functions[function_id].variables[*var_id].ty == TypeName::String
+            }
+            Operand::Const(constant) => matches!(constant, Constant::String(_)),
+        }
+    }
+
+    /// The width of an operand that is an integer encoded as a bitvector
+    fn bitvector_operand(&self, operand: &Operand, function_id: FunctionId) -> Option<u32> {
+        let is_int = match operand {
+            Operand::Var(var_id) => {
+                self.cfg.functions[function_id].variables[*var_id].ty == TypeName::Int
+            }
+            Operand::Const(constant) => matches!(constant, Constant::Int(_)),
+        };
+        self.encoding.ints.bits().filter(|_| is_int)
+    }
+
+    /// Generate Boogie code for a constant; integers too wide for the
+    /// bitvectors wrap, as they would in the program
     fn generate_constant(&self, constant: &Constant) -> String {
         match constant {
-            Constant::Int(i) => i.to_string(),
+            Constant::Int(i) => match self.encoding.ints.bits() {
+                Some(bits) => format!("{}bv{}", (*i as u64) & (u64::MAX >> (64 - bits)), bits),
+                None => i.to_string(),
+            },
             Constant::Float(f) => f.to_string(),
             Constant::Bool(b) => b.to_string(),
-            Constant::String(s) => format!("\"{}\"", s),
+            Constant::String(s) => string_literal_name(s),
         }
     }
 
@@ -747,8 +1138,15 @@ impl<'a> BoogieCodeGenerator<'a> {
         }
     }
 
-    /// Generate code to compare final states
+    /// Generate code to compare which transactions aborted in the two
+    /// executions, then their final states unless both abort the same way
     fn generate_state_comparison(&mut self) {
+        let guard = if self.may_abort() {
+            self.writeln("assert aborted_A_AB == aborted_A_BA && aborted_B_AB == aborted_B_BA;");
+            "!aborted_A_AB && !aborted_B_AB ==> "
+        } else {
+            ""
+        };
         for &table_id in &self.unit.relevant_tables {
             let table = &self.cfg.tables[table_id];
             for &field_id in &table.fields {
@@ -758,7 +1156,8 @@ impl<'a> BoogieCodeGenerator<'a> {
                     if table.primary_keys.len() == 1 {
                         // Single key case
                         self.writeln(&format!(
-                            "assert (forall k: {} :: final_AB_{}_{} [k] == final_BA_{}_{} [k]);",
+                            "assert {}(forall k: {} :: final_AB_{}_{} [k] == final_BA_{}_{} [k]);",
+                            guard,
                             self.type_to_boogie(&self.cfg.fields[table.primary_keys[0]].ty),
                             table.name,
                             field.name,
@@ -786,7 +1185,8 @@ impl<'a> BoogieCodeGenerator<'a> {
                         }
 
                         self.writeln(&format!(
-                            "assert (forall {} :: final_AB_{}_{}{}  == final_BA_{}_{}{}  );",
+                            "assert {}(forall {} :: final_AB_{}_{}{}  == final_BA_{}_{}{}  );",
+                            guard,
                             forall_vars.join(", "),
                             table.name,
                             field.name,
@@ -806,6 +1206,7 @@ impl<'a> BoogieCodeGenerator<'a> {
         match op {
             UnaryOp::Not => "!".to_string(),
             UnaryOp::Neg => "-".to_string(),
+            UnaryOp::FoldCase => "str_fold".to_string(),
         }
     }
 
@@ -830,10 +1231,13 @@ impl<'a> BoogieCodeGenerator<'a> {
     /// Convert a TypeName to Boogie type string
     fn type_to_boogie(&self, ty: &TypeName) -> String {
         match ty {
-            TypeName::Int => "int".to_string(),
+            TypeName::Int => match self.encoding.ints.bits() {
+                Some(bits) => format!("bv{}", bits),
+                None => "int".to_string(),
+            },
             TypeName::Float => "real".to_string(),
             TypeName::Bool => "bool".to_string(),
-            TypeName::String => "string".to_string(), // Note: Boogie doesn't have native strings
+            TypeName::String => "string".to_string(), // Declared by the string prelude
         }
     }
 
@@ -869,8 +1273,84 @@ impl<'a> BoogieCodeGenerator<'a> {
     }
 }
 
+/// The name of the prelude function of a binary operator on bitvectors, if
+/// it has one
+fn bitvector_op(op: &BinaryOp) -> Option<&'static str> {
+    match op {
+        BinaryOp::Add => Some("add"),
+        BinaryOp::Sub => Some("sub"),
+        BinaryOp::Mul => Some("mul"),
+        BinaryOp::Div => Some("div"),
+        BinaryOp::Lt => Some("lt"),
+        BinaryOp::Lte => Some("le"),
+        BinaryOp::Gt => Some("gt"),
+        BinaryOp::Gte => Some("ge"),
+        BinaryOp::Eq | BinaryOp::Neq | BinaryOp::And | BinaryOp::Or => None,
+    }
+}
+
 /// Generate Boogie code with CFG access
 pub fn generate_boogie_for_unit_with_cfg(unit: &VerificationUnit, cfg: &CfgProgram) -> String {
-    let mut generator = BoogieCodeGenerator::new(unit, cfg);
+    generate_boogie_assuming(unit, cfg, &[], Encoding::default())
+}
+
+/// Generate Boogie code assuming the invariants of every record, with
+/// values encoded as `encoding` says
+pub fn generate_boogie_assuming(
+    unit: &VerificationUnit,
+    cfg: &CfgProgram,
+    invariants: &[Invariant],
+    encoding: Encoding,
+) -> String {
+    let mut generator = BoogieCodeGenerator::new(unit, cfg)
+        .assuming(invariants)
+        .encoding(encoding);
     generator.generate()
 }
+
+/// The Boogie files of several C-edges as one, with each prelude once and the
+/// procedure of the `i`th file renamed `main_i`, so Boogie checks all in one
+/// run; the string constants they declare are declared once too. Also returns the lines each file's code takes, from 1, to tell which
+/// procedure Boogie's messages are about.
+pub fn batch_boogie(conditions: &[&str]) -> (String, Vec<RangeInclusive<usize>>) {
+    let preludes = [
+        STRING_PRELUDE.to_string(),
+        bitvector_prelude(32),
+        bitvector_prelude(64),
+    ];
+    let mut batch = String::new();
+    for prelude in &preludes {
+        if conditions
+            .iter()
+            .any(|code| code.contains(prelude.as_str()))
+        {
+            batch.push_str(prelude);
+        }
+    }
+    let mut constants: Vec<&str> = Vec::new();
+    for line in conditions.iter().flat_map(|code| code.lines()) {
+        if line.starts_with(STRING_CONSTANT) && !constants.contains(&line) {
+            constants.push(line);
+        }
+    }
+    for constant in &constants {
+        batch.push_str(constant);
+        batch.push('\n');
+    }
+    let mut lines = Vec::new();
+    for (i, code) in conditions.iter().enumerate() {
+        let code: String = code
+            .lines()
+            .filter(|line| !line.starts_with(STRING_CONSTANT))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let code = preludes
+            .iter()
+            .fold(code, |code, prelude| code.replacen(prelude.as_str(), "", 1))
+            .replacen("procedure main(", &format!("procedure main_{}(", i), 1);
+        let first = batch.lines().count() + 1;
+        batch.push_str(&code);
+        lines.push(first..=batch.lines().count());
+    }
+    (batch, lines)
+}
@@ -1,9 +1,9 @@
 // src/verify/commutativity_check.rs
 
 use crate::cfg::{CfgProgram, FunctionId, HopId, TableId, VarId};
-use crate::dataflow::{analyze_live_variables, analyze_table_mod_ref, AccessType};
+use crate::dataflow::{analyze_live_variables, HopSubgraph};
 use crate::sc_graph::{Edge, SCGraph};
-use crate::verification::interleaving::enumerate_interleavings;
+use crate::verification::movers::Movers;
 use std::collections::HashSet;
 
 pub struct VerificationUnit {
@@ -13,7 +13,8 @@ pub struct VerificationUnit {
     pub final_b: HopId,                // Bₖ
     pub function_a: FunctionId,        // Function containing prefix_a and final_a
     pub function_b: FunctionId,        // Function containing prefix_b and final_b
-    pub merges: Vec<Vec<HopId>>,       // All merges of prefix_a and prefix_b
+    pub merges: Vec<Vec<HopId>>,       // Merges of prefix_a and prefix_b, up to movers
+    pub reduced: usize,                // Merges left out as equal to one kept
     pub relevant_tables: Vec<TableId>, // table to be compared (final_a and final_b read/write)
     pub relevant_vars: Vec<VarId>, // variables to be compared (live var at the end of final_a and final_b)
 }
@@ -58,9 +59,6 @@ pub fn create_verification_unit(
     let liveness_results_a = analyze_live_variables(func_cfg_a);
     let liveness_results_b = analyze_live_variables(func_cfg_b);
 
-    let table_mod_ref_a = analyze_table_mod_ref(func_cfg_a);
-    let table_mod_ref_b = analyze_table_mod_ref(func_cfg_b);
-
     // Get live variables at the exit of final hops
     let mut live_vars_a = HashSet::new();
     let mut live_vars_b = HashSet::new();
@@ -86,41 +84,17 @@ pub fn create_verification_unit(
     // Union of live variables from both functions
     let relevant_vars: Vec<VarId> = live_vars_a.union(&live_vars_b).cloned().collect();
 
-    // Extract relevant tables from both functions
+    // Extract relevant tables from both functions: those written by the final hops
     let mut relevant_tables_set = HashSet::new();
-
-    // For function A - get tables accessed in final_a
-    if let Some(hop_cfg_a) = func_cfg_a.hops.get(final_a) {
-        for &block_id in &hop_cfg_a.blocks {
-            if let Some(exit_state) = table_mod_ref_a.exit.get(&block_id) {
-                for table_access in &exit_state.set {
-                    if table_access.access_type == AccessType::Write {
-                        // Only consider tables that are written
-                        relevant_tables_set.insert(table_access.table_id);
-                    }
-                }
-            }
-        }
-    }
-
-    // For function B - get tables accessed in final_b
-    if let Some(hop_cfg_b) = func_cfg_b.hops.get(final_b) {
-        for &block_id in &hop_cfg_b.blocks {
-            if let Some(exit_state) = table_mod_ref_b.exit.get(&block_id) {
-                for table_access in &exit_state.set {
-                    if table_access.access_type == AccessType::Write {
-                        // Only consider tables that are written
-                        relevant_tables_set.insert(table_access.table_id);
-                    }
-                }
-            }
-        }
-    }
+    relevant_tables_set.extend(HopSubgraph::new(cfg, function_a, final_a).written_tables());
+    relevant_tables_set.extend(HopSubgraph::new(cfg, function_b, final_b).written_tables());
 
     let relevant_tables: Vec<TableId> = relevant_tables_set.into_iter().collect();
 
-    // 4) Enumerate merges
-    let merges = enumerate_interleavings(&prefix_a, &prefix_b);
+    // 4) Enumerate merges, up to swapping hops that move past each other
+    let merges = Movers::new(cfg, function_a, function_b).interleavings(&prefix_a, &prefix_b);
+    let reduced =
+        binomial(prefix_a.len() + prefix_b.len(), prefix_a.len()).saturating_sub(merges.len());
 
     // Return the constructed VerificationUnit
     VerificationUnit {
@@ -131,7 +105,19 @@ pub fn create_verification_unit(
         function_a,
         function_b,
         merges,
+        reduced,
         relevant_tables,
         relevant_vars,
     }
 }
+
+/// How many ways `k` of `n` items can be chosen, or `usize::MAX` if that
+/// overflows.
+fn binomial(n: usize, k: usize) -> usize {
+    (0..k.min(n - k) as u128)
+        .try_fold(1u128, |acc, i| {
+            Some(acc.checked_mul(n as u128 - i)? / (i + 1))
+        })
+        .and_then(|count| usize::try_from(count).ok())
+        .unwrap_or(usize::MAX)
+}
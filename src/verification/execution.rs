@@ -1,22 +1,64 @@
-use std::path::Path;
-use std::process::Command;
+use crate::verification::solver::SolverLimits;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone)]
 pub enum VerificationResult {
     Success,
     Failure(String),
+    /// The prover could not decide, e.g. because it ran out of time
+    Unknown(String),
 }
 
 #[derive(Debug)]
-pub struct VerificationExecution;
+pub struct VerificationExecution {
+    /// The Boogie executable
+    pub boogie: PathBuf,
+    /// What Boogie may spend on each file
+    pub limits: SolverLimits,
+}
 
 impl VerificationExecution {
+    /// Runs Boogie on a file. The time limit bounds each procedure, through
+    /// `/timeLimit`, and the whole run, after which Boogie is stopped; the
+    /// memory limit and the seed are passed on to Z3.
     pub fn execute_boogie<P: AsRef<Path>>(&self, file_path: P) -> VerificationResult {
+        self.execute_boogie_procedures(file_path, 1)
+    }
+
+    /// Runs Boogie on a file of several procedures, each held to the time
+    /// limit, so the whole run to that many times it.
+    pub fn execute_boogie_procedures<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+        procedures: u32,
+    ) -> VerificationResult {
+        let wall_clock = self.limits.timeout.map(|timeout| timeout * procedures);
         // Run the boogie verifier with /quiet flag
-        let output = Command::new("boogie")
-            .arg(file_path.as_ref())
-            .arg("/quiet")
-            .output();
+        let mut command = Command::new(&self.boogie);
+        command.arg(file_path.as_ref()).arg("/quiet");
+        if let Some(timeout) = self.limits.timeout {
+            command.arg(format!("/timeLimit:{}", timeout));
+        }
+        if let Some(memory) = self.limits.memory {
+            command.arg(format!("/proverOpt:O:memory_max_size={}", memory));
+        }
+        if let Some(seed) = self.limits.seed {
+            command.arg(format!("/randomSeed:{}", seed));
+        }
+        let output = match run(&mut command, wall_clock) {
+            Ok(Some(output)) => Ok(output),
+            Ok(None) => {
+                return VerificationResult::Unknown(format!(
+                    "Boogie timed out after {} seconds",
+                    wall_clock.unwrap_or_default()
+                ))
+            }
+            Err(e) => Err(e),
+        };
 
         let result = match output {
             Ok(output) => {
@@ -40,9 +82,54 @@ impl VerificationExecution {
                     VerificationResult::Failure(error_msg)
                 }
             }
-            Err(e) => VerificationResult::Failure(format!("Failed to run Boogie: {}", e)),
+            Err(e) => VerificationResult::Failure(format!(
+                "Failed to run Boogie at {}: {}",
+                self.boogie.display(),
+                e
+            )),
         };
 
         result
     }
 }
+
+/// Runs a command to completion, or stops it once `timeout` seconds have
+/// passed and returns `None`.
+pub(crate) fn run(command: &mut Command, timeout: Option<u32>) -> std::io::Result<Option<Output>> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // The pipes are drained on other threads, so a chatty process cannot
+    // block on a full pipe
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut bytes = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut bytes);
+            }
+            bytes
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = drain(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let deadline = timeout.map(|seconds| Instant::now() + Duration::from_secs(seconds.into()));
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    Ok(Some(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    }))
+}
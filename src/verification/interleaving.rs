@@ -3,15 +3,28 @@
 use crate::cfg::HopId;
 
 pub fn enumerate_interleavings(a: &[HopId], b: &[HopId]) -> Vec<Vec<HopId>> {
+    enumerate_reduced_interleavings(a, b, |_, _| false)
+}
+
+/// The interleavings of `a` and `b` in which no hop of `b` is directly
+/// followed by a hop of `a` that `moves` with it: one of each class of
+/// interleavings equal up to swapping such hops.
+pub fn enumerate_reduced_interleavings(
+    a: &[HopId],
+    b: &[HopId],
+    moves: impl Fn(HopId, HopId) -> bool,
+) -> Vec<Vec<HopId>> {
     let mut results = vec![];
     let mut current = vec![];
-    interleave_rec(a, b, &mut current, &mut results);
+    interleave_rec(a, b, None, &moves, &mut current, &mut results);
     results
 }
 
 fn interleave_rec(
     a: &[HopId],
     b: &[HopId],
+    last_b: Option<HopId>,
+    moves: &dyn Fn(HopId, HopId) -> bool,
     current: &mut Vec<HopId>,
     results: &mut Vec<Vec<HopId>>,
 ) {
@@ -19,14 +32,14 @@ fn interleave_rec(
         results.push(current.clone());
         return;
     }
-    if !a.is_empty() {
+    if !a.is_empty() && !last_b.is_some_and(|hop| moves(a[0], hop)) {
         current.push(a[0]);
-        interleave_rec(&a[1..], b, current, results);
+        interleave_rec(&a[1..], b, None, moves, current, results);
         current.pop();
     }
     if !b.is_empty() {
         current.push(b[0]);
-        interleave_rec(a, &b[1..], current, results);
+        interleave_rec(a, &b[1..], Some(b[0]), moves, current, results);
         current.pop();
     }
 }
@@ -0,0 +1,618 @@
+//! Inferring invariants of the tables, Houdini style.
+//!
+//! The final hops of a C-edge are verified from any start state, so an edge
+//! may fail on a state no execution reaches, such as a negative stock that
+//! every function keeps at zero or more. Inference proposes candidate
+//! invariants of every record: bounds of numeric fields by zero and the
+//! constants of the program, equalities between fields of the same type, and
+//! a field holding the sum of two others. Records start with default values,
+//! so candidates false of a default record are dropped. Then each hop of each
+//! function is run from any state whose records satisfy the candidates left,
+//! and a candidate the solver cannot show the hop keeps on every record it
+//! writes is dropped, until a round drops none.
+//!
+//! The candidates left hold before and after every hop, whatever the
+//! interleaving, so verification conditions may assume them of every record
+//! the final hops read. A candidate is dropped when the solver times out too,
+//! so none is kept without a proof; runs past an unroll bound that is assumed
+//! rather than asserted are left out, as everywhere else.
+//!
+//! Properties can also be declared of a table, in `// assume(...)` comments on
+//! it or its fields or in the `[assumptions]` of the config. Final hops start
+//! from any values unless `InitialState::Assumed` is chosen; then conditions
+//! assume the declared properties of every record too, without a check, so a
+//! proof holds only for workloads that keep them.
+//!
+//! ```
+//! use FMitF_rs::verification::invariants::{candidates, Invariant};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table Stock on A { primary int id; int count; int reserved; }
+//!     void restock(int id) {
+//!         hop on A { Stock[id: id].count = Stock[id: id].count + 10; }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let proposed: Vec<String> = candidates(&cfg).iter().map(|i| i.format(&cfg)).collect();
+//! assert!(proposed.contains(&"Stock.count >= 0".to_string()));
+//! assert!(proposed.contains(&"Stock.count <= 10".to_string()));
+//! assert!(proposed.contains(&"Stock.count == Stock.reserved".to_string()));
+//!
+//! let declared = Invariant::parse(&cfg, "count + reserved == 10", Some(cfg.root_tables[0]));
+//! assert!(declared.is_err());
+//! let declared = Invariant::parse(&cfg, "Stock.count == 10", None).unwrap();
+//! assert_eq!(declared[0].format(&cfg), "Stock.count >= 10");
+//! assert_eq!(declared[1].format(&cfg), "Stock.count <= 10");
+//! ```
+
+use crate::ast::TypeName;
+use crate::cfg::{
+    BinaryOp, CfgProgram, Constant, FieldId, FunctionId, HopId, Operand, Rvalue, Statement, TableId,
+};
+use crate::verification::solver::{Encoding, SolverAnswer, SolverBackend};
+use crate::verification::symbolic::{
+    collect_initial, default_value, read, HopEnd, HopPair, Side, SymExpr,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Constants of each numeric type tried as bounds, besides zero
+const MAX_BOUNDS: usize = 8;
+
+/// What conditions assume of the records the final hops start from.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InitialState {
+    /// Any values, or only those of invariants inferred with a proof
+    #[default]
+    Havoc,
+    /// Values with the properties declared of the tables besides, which are
+    /// trusted rather than checked
+    Assumed,
+}
+
+impl InitialState {
+    /// The choice as `--initial-state` names it.
+    pub fn name(self) -> &'static str {
+        match self {
+            InitialState::Havoc => "havoc",
+            InitialState::Assumed => "assumed",
+        }
+    }
+
+    /// What a proof from such states does and does not cover.
+    pub fn soundness(self) -> &'static str {
+        match self {
+            InitialState::Havoc => "records start from any values, so no state is left out",
+            InitialState::Assumed => {
+                "declared properties are assumed, so proofs hold only if the workload keeps them"
+            }
+        }
+    }
+}
+
+/// The `[assumptions]` of a config.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Assumptions {
+    /// What records start from
+    pub initial_state: InitialState,
+    /// Properties declared besides `// assume(...)` comments, as
+    /// `Table.field >= 0`
+    pub invariants: Vec<String>,
+}
+
+/// A property every record of a table is proposed to have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invariant {
+    /// `field >= bound`
+    AtLeast {
+        table: TableId,
+        field: FieldId,
+        bound: Constant,
+    },
+    /// `field <= bound`
+    AtMost {
+        table: TableId,
+        field: FieldId,
+        bound: Constant,
+    },
+    /// `left == right`
+    Equal {
+        table: TableId,
+        left: FieldId,
+        right: FieldId,
+    },
+    /// `left + right == total`
+    Sum {
+        table: TableId,
+        left: FieldId,
+        right: FieldId,
+        total: FieldId,
+    },
+}
+
+impl Invariant {
+    /// The table whose records it is about.
+    pub fn table(&self) -> TableId {
+        match self {
+            Invariant::AtLeast { table, .. }
+            | Invariant::AtMost { table, .. }
+            | Invariant::Equal { table, .. }
+            | Invariant::Sum { table, .. } => *table,
+        }
+    }
+
+    /// The fields it reads.
+    pub fn fields(&self) -> Vec<FieldId> {
+        match self {
+            Invariant::AtLeast { field, .. } | Invariant::AtMost { field, .. } => vec![*field],
+            Invariant::Equal { left, right, .. } => vec![*left, *right],
+            Invariant::Sum {
+                left, right, total, ..
+            } => vec![*left, *right, *total],
+        }
+    }
+
+    /// The invariant of a record whose fields have the values `value` gives,
+    /// as a condition.
+    pub fn on(&self, value: impl Fn(FieldId) -> SymExpr) -> SymExpr {
+        match self {
+            Invariant::AtLeast { field, bound, .. } => {
+                SymExpr::binary(BinaryOp::Gte, value(*field), SymExpr::Const(bound.clone()))
+            }
+            Invariant::AtMost { field, bound, .. } => {
+                SymExpr::binary(BinaryOp::Lte, value(*field), SymExpr::Const(bound.clone()))
+            }
+            Invariant::Equal { left, right, .. } => {
+                SymExpr::binary(BinaryOp::Eq, value(*left), value(*right))
+            }
+            Invariant::Sum {
+                left, right, total, ..
+            } => SymExpr::binary(
+                BinaryOp::Eq,
+                SymExpr::binary(BinaryOp::Add, value(*left), value(*right)),
+                value(*total),
+            ),
+        }
+    }
+
+    /// The invariant of the record at `key` before the hops run.
+    pub fn at(&self, key: &[SymExpr]) -> SymExpr {
+        let table = self.table();
+        self.on(|field| SymExpr::Initial {
+            table,
+            field,
+            key: key.to_vec(),
+        })
+    }
+
+    /// Whether a record with default values has it.
+    pub fn holds_initially(&self, cfg: &CfgProgram) -> bool {
+        let start = self.on(|field| SymExpr::Const(default_value(&cfg.fields[field].ty)));
+        start == SymExpr::Const(Constant::Bool(true))
+    }
+
+    /// The invariant as written in messages, such as `T.n >= 0`.
+    pub fn format(&self, cfg: &CfgProgram) -> String {
+        let table = &cfg.tables[self.table()].name;
+        let field = |field: &FieldId| format!("{}.{}", table, cfg.fields[*field].name);
+        match self {
+            Invariant::AtLeast {
+                field: f, bound, ..
+            } => {
+                format!("{} >= {}", field(f), crate::pretty::format_constant(bound))
+            }
+            Invariant::AtMost {
+                field: f, bound, ..
+            } => {
+                format!("{} <= {}", field(f), crate::pretty::format_constant(bound))
+            }
+            Invariant::Equal { left, right, .. } => format!("{} == {}", field(left), field(right)),
+            Invariant::Sum {
+                left, right, total, ..
+            } => format!("{} + {} == {}", field(left), field(right), field(total)),
+        }
+    }
+}
+
+impl Invariant {
+    /// Parses a property as `format` writes it: a field bounded by a constant
+    /// with `>=`, `<=` or `==`, two fields equal, or a field the sum of two
+    /// others. Fields are named as `Table.field`, or just `field` of `table`.
+    pub fn parse(
+        cfg: &CfgProgram,
+        text: &str,
+        table: Option<TableId>,
+    ) -> Result<Vec<Invariant>, String> {
+        let field = |name: &str| -> Result<(TableId, FieldId), String> {
+            let name = name.trim();
+            let (table, name) = match name.split_once('.') {
+                Some((table, name)) => {
+                    let id = cfg
+                        .root_tables
+                        .iter()
+                        .copied()
+                        .find(|&id| cfg.tables[id].name == table)
+                        .ok_or_else(|| format!("Unknown table '{}'", table))?;
+                    (id, name)
+                }
+                None => (
+                    table.ok_or_else(|| format!("Name the table of '{}', as Table.field", name))?,
+                    name,
+                ),
+            };
+            let field = cfg.tables[table]
+                .fields
+                .iter()
+                .copied()
+                .find(|&field| cfg.fields[field].name == name)
+                .ok_or_else(|| {
+                    format!("Table '{}' has no field '{}'", cfg.tables[table].name, name)
+                })?;
+            if cfg.fields[field].is_primary {
+                return Err(format!("'{}' is a key, which records are found by", name));
+            }
+            Ok((table, field))
+        };
+        let same_table = |fields: &[(TableId, FieldId)]| {
+            if fields.iter().all(|(table, _)| *table == fields[0].0) {
+                Ok(fields[0].0)
+            } else {
+                Err("Fields of different tables".to_string())
+            }
+        };
+        let same_type = |fields: &[(TableId, FieldId)]| {
+            let ty = &cfg.fields[fields[0].1].ty;
+            if fields.iter().all(|(_, field)| cfg.fields[*field].ty == *ty) {
+                Ok(())
+            } else {
+                Err("Fields of different types".to_string())
+            }
+        };
+
+        let (op, left, right) = [">=", "<=", "=="]
+            .into_iter()
+            .find_map(|op| text.split_once(op).map(|(l, r)| (op, l, r.trim())))
+            .ok_or_else(|| "Expected '>=', '<=' or '=='".to_string())?;
+        if let Some((a, b)) = left.split_once('+') {
+            if op != "==" {
+                return Err("A sum can only equal a field".to_string());
+            }
+            let fields = [field(a)?, field(b)?, field(right)?];
+            let table = same_table(&fields)?;
+            same_type(&fields)?;
+            if !matches!(cfg.fields[fields[0].1].ty, TypeName::Int | TypeName::Float) {
+                return Err("Only int and float fields have sums".to_string());
+            }
+            return Ok(vec![Invariant::Sum {
+                table,
+                left: fields[0].1,
+                right: fields[1].1,
+                total: fields[2].1,
+            }]);
+        }
+        let (table, field_id) = field(left)?;
+        let bound = match &cfg.fields[field_id].ty {
+            TypeName::Int => right.parse().ok().map(Constant::Int),
+            TypeName::Float => right
+                .parse()
+                .ok()
+                .map(|f| Constant::Float(ordered_float::OrderedFloat(f))),
+            TypeName::Bool | TypeName::String => None,
+        };
+        match (op, bound) {
+            (">=", Some(bound)) => Ok(vec![Invariant::AtLeast {
+                table,
+                field: field_id,
+                bound,
+            }]),
+            ("<=", Some(bound)) => Ok(vec![Invariant::AtMost {
+                table,
+                field: field_id,
+                bound,
+            }]),
+            ("==", Some(bound)) => Ok(vec![
+                Invariant::AtLeast {
+                    table,
+                    field: field_id,
+                    bound: bound.clone(),
+                },
+                Invariant::AtMost {
+                    table,
+                    field: field_id,
+                    bound,
+                },
+            ]),
+            ("==", None) => {
+                let fields = [(table, field_id), field(right)?];
+                same_table(&fields)?;
+                same_type(&fields)?;
+                Ok(vec![Invariant::Equal {
+                    table,
+                    left: field_id,
+                    right: fields[1].1,
+                }])
+            }
+            _ => Err(format!(
+                "'{}' is no bound of the field '{}'",
+                right, cfg.fields[field_id].name
+            )),
+        }
+    }
+}
+
+/// The properties declared of the tables: those of their `// assume(...)`
+/// comments, then `extra`, written as `Table.field >= 0`.
+pub fn declared(cfg: &CfgProgram, extra: &[String]) -> Result<Vec<Invariant>, String> {
+    let comments = cfg.root_tables.iter().flat_map(|&table| {
+        cfg.tables[table]
+            .assumptions
+            .iter()
+            .map(move |text| (text, Some(table)))
+    });
+    let mut declared = Vec::new();
+    for (text, table) in comments.chain(extra.iter().map(|text| (text, None))) {
+        let invariants = Invariant::parse(cfg, text, table)
+            .map_err(|e| format!("Invalid assumption '{}': {}", text, e))?;
+        for invariant in invariants {
+            if !declared.contains(&invariant) {
+                declared.push(invariant);
+            }
+        }
+    }
+    Ok(declared)
+}
+
+/// The candidates a default record has: bounds of the numeric fields by zero
+/// and the program's constants, equalities of fields of the same type, and
+/// sums of numeric fields. Keys are left out, as Boogie keeps no map of them.
+pub fn candidates(cfg: &CfgProgram) -> Vec<Invariant> {
+    let mut ints = BTreeSet::from([0]);
+    let mut floats = BTreeSet::from([ordered_float::OrderedFloat(0.0)]);
+    for (_, func) in cfg.functions.iter() {
+        for (_, block) in func.blocks.iter() {
+            for statement in &block.statements {
+                let operands = match statement {
+                    Statement::Assign { rvalue, .. } => match rvalue {
+                        Rvalue::Use(value) | Rvalue::UnaryOp { operand: value, .. } => {
+                            vec![value]
+                        }
+                        Rvalue::BinaryOp { left, right, .. } => vec![left, right],
+                        Rvalue::TableAccess { .. } => Vec::new(),
+                    },
+                    Statement::TableAssign { value, .. } => vec![value],
+                };
+                for operand in operands {
+                    match operand {
+                        Operand::Const(Constant::Int(i)) if ints.len() <= MAX_BOUNDS => {
+                            ints.insert(*i);
+                        }
+                        Operand::Const(Constant::Float(f)) if floats.len() <= MAX_BOUNDS => {
+                            floats.insert(*f);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    let bounds = |ty: &TypeName| -> Vec<Constant> {
+        match ty {
+            TypeName::Int => ints.iter().map(|&i| Constant::Int(i)).collect(),
+            TypeName::Float => floats.iter().map(|&f| Constant::Float(f)).collect(),
+            TypeName::Bool | TypeName::String => Vec::new(),
+        }
+    };
+
+    let mut candidates = Vec::new();
+    for (table, info) in cfg.tables.iter() {
+        let fields: Vec<FieldId> = info
+            .fields
+            .iter()
+            .copied()
+            .filter(|&field| !cfg.fields[field].is_primary)
+            .collect();
+        let ty = |field: FieldId| &cfg.fields[field].ty;
+        let numeric = |field: FieldId| matches!(ty(field), TypeName::Int | TypeName::Float);
+        for &field in &fields {
+            for bound in bounds(ty(field)) {
+                candidates.push(Invariant::AtLeast {
+                    table,
+                    field,
+                    bound: bound.clone(),
+                });
+                candidates.push(Invariant::AtMost {
+                    table,
+                    field,
+                    bound,
+                });
+            }
+        }
+        for (i, &left) in fields.iter().enumerate() {
+            for &right in &fields[i + 1..] {
+                if ty(left) == ty(right) {
+                    candidates.push(Invariant::Equal { table, left, right });
+                }
+            }
+        }
+        for (i, &left) in fields.iter().enumerate() {
+            for &right in &fields[i + 1..] {
+                for &total in &fields {
+                    let same = ty(left) == ty(right) && ty(right) == ty(total);
+                    if same && numeric(total) && total != left && total != right {
+                        candidates.push(Invariant::Sum {
+                            table,
+                            left,
+                            right,
+                            total,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    candidates.retain(|candidate| candidate.holds_initially(cfg));
+    candidates
+}
+
+/// The candidates every hop of every function keeps, checked by `solver`
+/// with values encoded as `encoding` says, without bounds implied by tighter
+/// ones kept.
+pub fn infer(
+    cfg: &CfgProgram,
+    solver: &mut dyn SolverBackend,
+    encoding: Encoding,
+) -> Vec<Invariant> {
+    let hops: Vec<(FunctionId, HopId)> = cfg
+        .functions
+        .iter()
+        .flat_map(|(function, func)| func.hop_order.iter().map(move |&hop| (function, hop)))
+        .collect();
+    let mut kept = candidates(cfg);
+    loop {
+        let before = kept.len();
+        for &(function, hop) in &hops {
+            let written = written_fields(cfg, function, hop);
+            let mut index = 0;
+            while index < kept.len() {
+                let touched = kept[index]
+                    .fields()
+                    .iter()
+                    .any(|&field| written.contains(&(kept[index].table(), field)));
+                if !touched || preserves(cfg, solver, encoding, function, hop, &kept, index) {
+                    index += 1;
+                } else {
+                    kept.remove(index);
+                }
+            }
+        }
+        if kept.len() == before {
+            break;
+        }
+    }
+
+    let implied = |invariant: &Invariant| {
+        kept.iter().any(|other| match (invariant, other) {
+            (
+                Invariant::AtLeast { field, bound, .. },
+                Invariant::AtLeast {
+                    field: f, bound: b, ..
+                },
+            ) => field == f && b > bound,
+            (
+                Invariant::AtMost { field, bound, .. },
+                Invariant::AtMost {
+                    field: f, bound: b, ..
+                },
+            ) => field == f && b < bound,
+            _ => false,
+        })
+    };
+    kept.iter()
+        .filter(|invariant| !implied(invariant))
+        .cloned()
+        .collect()
+}
+
+/// Conjoins to `formula` the invariants of every record it reads.
+pub(super) fn assume(invariants: &[Invariant], formula: SymExpr) -> SymExpr {
+    let mut reads = Vec::new();
+    collect_initial(&formula, &mut reads);
+    let mut records: Vec<(TableId, &[SymExpr])> = Vec::new();
+    for (table, _, key) in reads {
+        if !records.contains(&(table, key)) {
+            records.push((table, key));
+        }
+    }
+    let assumed = records
+        .iter()
+        .flat_map(|&(table, key)| {
+            invariants
+                .iter()
+                .filter(move |invariant| invariant.table() == table)
+                .map(move |invariant| invariant.at(key))
+        })
+        .collect::<Vec<_>>();
+    assumed.into_iter().fold(formula, |all, invariant| {
+        SymExpr::binary(BinaryOp::And, invariant, all)
+    })
+}
+
+/// Whether a hop run from records satisfying `kept` leaves `kept[index]` of
+/// every record it writes, as far as the solver can show.
+fn preserves(
+    cfg: &CfgProgram,
+    solver: &mut dyn SolverBackend,
+    encoding: Encoding,
+    function: FunctionId,
+    hop: HopId,
+    kept: &[Invariant],
+    index: usize,
+) -> bool {
+    let pair = HopPair {
+        function_a: function,
+        hop_a: hop,
+        function_b: function,
+        hop_b: hop,
+    };
+    let Some(paths) = pair.explore_one(cfg, Side::A) else {
+        return false;
+    };
+    let invariant = &kept[index];
+    let broken = paths
+        .iter()
+        .filter(|path| path.end_a != HopEnd::Abort)
+        .flat_map(|path| {
+            let mut keys: Vec<&[SymExpr]> = Vec::new();
+            for write in &path.writes {
+                if write.table == invariant.table() && !keys.contains(&write.key.as_slice()) {
+                    keys.push(&write.key);
+                }
+            }
+            keys.into_iter().map(move |key| {
+                let after = invariant.on(|field| read(&path.writes, invariant.table(), field, key));
+                path.condition.iter().cloned().fold(
+                    SymExpr::unary(crate::cfg::UnaryOp::Not, after),
+                    |all, condition| SymExpr::binary(BinaryOp::And, condition, all),
+                )
+            })
+        })
+        .fold(SymExpr::Const(Constant::Bool(false)), |any, broken| {
+            SymExpr::binary(BinaryOp::Or, any, broken)
+        });
+    if broken == SymExpr::Const(Constant::Bool(false)) {
+        return true;
+    }
+    let question = format!(
+        "Can {}:{} break {}?",
+        cfg.functions[function].name,
+        hop.index(),
+        invariant.format(cfg)
+    );
+    let script = pair.query_script(cfg, &question, &assume(kept, broken), encoding);
+    solver.solve_script(&script) == SolverAnswer::Unsat
+}
+
+/// The fields a hop may write.
+fn written_fields(
+    cfg: &CfgProgram,
+    function: FunctionId,
+    hop: HopId,
+) -> BTreeSet<(TableId, FieldId)> {
+    let func = &cfg.functions[function];
+    func.hops[hop]
+        .blocks
+        .iter()
+        .flat_map(|&block| &func.blocks[block].statements)
+        .filter_map(|statement| match statement {
+            Statement::TableAssign { table, field, .. } => Some((*table, *field)),
+            Statement::Assign { .. } => None,
+        })
+        .collect()
+}
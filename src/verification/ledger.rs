@@ -0,0 +1,190 @@
+//! The assumptions a verdict rests on.
+//!
+//! A verdict of a verify run holds only as far as what the run took for
+//! granted: the isolation level cycles were judged against, how conditions
+//! encoded integers and strings, what records start from and the properties
+//! trusted of them, the invariants inferred, the pairs of functions and the
+//! tables the exclusions leave out, the limits each condition was held to,
+//! the C-edges that could not be decided and the results taken from the cache.
+//! The ledger lists each with what it leaves out, so `chopping serializable`
+//! can be read for what it is.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{ConflictExclusions, SCGraph};
+//! use FMitF_rs::verification::ledger::ledger;
+//! use FMitF_rs::verification::solver::{IntEncoding, SolverLimits};
+//! use FMitF_rs::verification::{VerificationManager, VerificationResult};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let source = include_str!("../../examples/sc_graph_demos/crossed_writers.transact");
+//! let h = "void h(int id) { hop on A { T[id: id].n = 3; } }";
+//! let program = parse_and_analyze(&format!("{}{}", source, h)).unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let mut sc_graph = SCGraph::new(&cfg);
+//! let mut exclusions = ConflictExclusions::default();
+//! exclusions.add_pair("g,h").unwrap();
+//! sc_graph.exclude(&exclusions, &cfg);
+//!
+//! // As if the prover ran out of time on an edge
+//! let mut manager = VerificationManager::new();
+//! manager.encoding.ints = IntEncoding::Bv32;
+//! manager.limits = SolverLimits { timeout: Some(30), ..Default::default() };
+//! let edge = sc_graph.c_edges_by_difficulty(&cfg)[0].0.clone();
+//! manager.results.insert(edge, VerificationResult::Unknown("timed out".to_string()));
+//!
+//! let ledger = ledger(&manager, &cfg, &sc_graph);
+//! let kinds: Vec<_> = ledger.iter().map(|entry| entry.kind).collect();
+//! assert_eq!(
+//!     kinds,
+//!     ["isolation", "integers", "strings", "initial_state", "ignored_pair", "time_limit", "unknown"]
+//! );
+//! assert_eq!(ledger[1].assumption, "integers are encoded as bv32");
+//! assert_eq!(ledger[4].assumption, "g and h never run together");
+//! assert_eq!(ledger[4].consequence, "1 C-edge between them is dropped unchecked");
+//! ```
+
+use crate::cfg::CfgProgram;
+use crate::sc_graph::{Isolation, SCGraph};
+use crate::verification::invariants::InitialState;
+use crate::verification::{VerificationManager, VerificationResult};
+use serde::Serialize;
+
+/// Something a verdict takes for granted.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Assumption {
+    /// What it is about: `isolation`, `integers`, `strings`, `initial_state`,
+    /// `assumed_property`, `inferred_invariant`, `ignored_pair`,
+    /// `ignored_table`, `time_limit`, `memory_limit`, `unknown` or `cache`
+    pub kind: &'static str,
+    pub assumption: String,
+    /// What a verdict under it does not cover, or how it was used
+    pub consequence: String,
+}
+
+/// The assumptions of a manager that has run on the SC-graph given.
+pub fn ledger(
+    manager: &VerificationManager,
+    cfg: &CfgProgram,
+    sc_graph: &SCGraph,
+) -> Vec<Assumption> {
+    let mut ledger = Vec::new();
+    let mut push = |kind, assumption: String, consequence: String| {
+        ledger.push(Assumption {
+            kind,
+            assumption,
+            consequence,
+        })
+    };
+    let edges = |count: usize| match count {
+        1 => "1 C-edge".to_string(),
+        n => format!("{} C-edges", n),
+    };
+
+    push(
+        "isolation",
+        format!("the isolation level is {}", sc_graph.isolation.name()),
+        match sc_graph.isolation {
+            Isolation::Serializable => "cycles count if any run of the pieces may break them",
+            Isolation::Snapshot => "runs that only show write skew count as correct",
+        }
+        .to_string(),
+    );
+    let encoding = manager.encoding;
+    push(
+        "integers",
+        format!("integers are encoded as {}", encoding.ints.name()),
+        encoding.ints.soundness().to_string(),
+    );
+    push(
+        "strings",
+        format!("strings are encoded as {}", encoding.strings.name()),
+        encoding.strings.soundness().to_string(),
+    );
+    push(
+        "initial_state",
+        format!("the initial state is {}", manager.initial_state.name()),
+        manager.initial_state.soundness().to_string(),
+    );
+    if manager.initial_state == InitialState::Assumed {
+        for property in &manager.assumptions {
+            push(
+                "assumed_property",
+                property.format(cfg),
+                "trusted of every record, not checked".to_string(),
+            );
+        }
+    }
+    for invariant in &manager.invariants {
+        push(
+            "inferred_invariant",
+            invariant.format(cfg),
+            "proven of records that start with default values and change only by the functions"
+                .to_string(),
+        );
+    }
+
+    let function_name = |node| &cfg.functions[sc_graph.nodes[node].cfg_function_id].name;
+    let excluded = sc_graph.excluded_edges();
+    for (a, b) in &sc_graph.exclusions.ignore_pairs {
+        let dropped = excluded
+            .iter()
+            .filter(|edge| {
+                let names = (function_name(edge.source), function_name(edge.target));
+                names == (a, b) || names == (b, a)
+            })
+            .count();
+        let verb = if dropped == 1 { "is" } else { "are" };
+        push(
+            "ignored_pair",
+            format!("{} and {} never run together", a, b),
+            format!("{} between them {} dropped unchecked", edges(dropped), verb),
+        );
+    }
+    for table in &sc_graph.exclusions.ignore_tables {
+        push(
+            "ignored_table",
+            format!("conflicts through {} are harmless", table),
+            "they are dropped unchecked, with the C-edges that had no others".to_string(),
+        );
+    }
+
+    if let Some(timeout) = manager.limits.timeout {
+        push(
+            "time_limit",
+            format!("each condition is given {} seconds", timeout),
+            "edges the prover runs out of time on are unknown".to_string(),
+        );
+    }
+    if let Some(memory) = manager.limits.memory {
+        push(
+            "memory_limit",
+            format!("each condition is given {} MB", memory),
+            "edges the prover runs out of memory on are unknown".to_string(),
+        );
+    }
+    let unknown = manager
+        .results
+        .values()
+        .filter(|result| matches!(result, VerificationResult::Unknown(_)))
+        .count();
+    if unknown > 0 {
+        push(
+            "unknown",
+            format!("{} could not be decided", edges(unknown)),
+            "kept in the SC-graph as if they may not commute, so cycles through them may be spurious"
+                .to_string(),
+        );
+    }
+    if !manager.reused.is_empty() {
+        push(
+            "cache",
+            format!(
+                "{} took their result from the cache",
+                edges(manager.reused.len())
+            ),
+            "trusted as an earlier run of the same prover found it for the same functions"
+                .to_string(),
+        );
+    }
+    ledger
+}
@@ -1,104 +1,550 @@
 use crate::cfg::CfgProgram;
 use crate::sc_graph::{Edge, EdgeType, SCGraph};
-use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
+pub mod artifacts;
 pub mod boogie_file_manager;
+pub mod cache;
 pub mod code_generation;
 pub mod commutativity_check;
 pub mod execution;
 pub mod interleaving;
+pub mod invariants;
+pub mod ledger;
+pub mod movers;
+pub mod output_dir;
+pub mod postconditions;
+pub mod progress;
+pub mod report;
+pub mod schedules;
+pub mod smt;
+pub mod solver;
+pub mod symbolic;
+pub mod toolchain;
+#[cfg(feature = "z3")]
+pub mod z3;
 pub use execution::{VerificationExecution, VerificationResult};
 
+use artifacts::Discharge;
 use boogie_file_manager::{BoogieFile, BoogieFileManager};
+use cache::{cache_key, VerificationCache};
+use commutativity_check::VerificationUnit;
+use invariants::{InitialState, Invariant};
+use postconditions::Postcondition;
+use progress::{EdgeProgress, ProgressCallback, ProgressEvent};
+use report::hop_label;
+use smt::format_model;
+use solver::{
+    BoogieBackend, Encoding, SolverAnswer, SolverBackend, SolverLimits, StringEncoding,
+    VcGranularity,
+};
+use symbolic::{ConflictWitness, HopPair, PairCheck};
 
 /// The main verification interface - handles all verification operations
 pub struct VerificationManager {
     pub boogie_files: Vec<BoogieFile>, // Store generated Boogie files with their names
     pub results: HashMap<Edge, VerificationResult>, // Store results of verification
-    pub temp_file_paths: Vec<PathBuf>, // Track temporary files for cleanup
+    /// The prover verification conditions are submitted to
+    pub solver: Box<dyn SolverBackend>,
+    /// Answers from earlier runs, consulted before submitting a condition
+    pub cache: Option<VerificationCache>,
+    /// The C-edges whose results come from the cache rather than this run
+    pub reused: HashSet<Edge>,
+    /// How many conditions are submitted at once
+    pub jobs: usize,
+    /// What the prover may spend on each condition
+    pub limits: SolverLimits,
+    /// Whether conditions are submitted per C-edge or per pair of functions
+    pub granularity: VcGranularity,
+    /// How conditions encode integers and strings
+    pub encoding: Encoding,
+    /// Whether to infer invariants of the tables before submitting
+    pub infer_invariants: bool,
+    /// The invariants inferred, which every condition assumes
+    pub invariants: Vec<Invariant>,
+    /// What records start from
+    pub initial_state: InitialState,
+    /// The properties declared of the tables, which conditions assume too
+    /// with `InitialState::Assumed`
+    pub assumptions: Vec<Invariant>,
+    /// How each C-edge removed was discharged, kept when proof artifacts are
+    /// wanted, as proving costs the prover another run per edge
+    pub discharges: Option<Vec<Discharge>>,
+    /// Told of each step as C-edges are decided
+    pub progress: Option<ProgressCallback>,
+    /// What checking the properties functions ensure found
+    pub postconditions: Vec<Postcondition>,
+    /// A start state on which the final hops of each C-edge kept may end
+    /// differently in the two orders, where the prover or symbolic execution
+    /// gave one
+    pub witnesses: HashMap<Edge, (HopPair, ConflictWitness)>,
+    /// The condition each C-edge was submitted in; edges batched per pair of
+    /// functions share theirs
+    pub conditions: HashMap<Edge, BoogieFile>,
+    /// How each C-edge was decided, in the order decided
+    pub decisions: Vec<EdgeProgress>,
+}
+
+impl Default for VerificationManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VerificationManager {
+    /// A manager verifying with Boogie, without a time limit.
     pub fn new() -> Self {
+        Self::with_solver(Box::new(BoogieBackend::new(SolverLimits::default())))
+    }
+
+    /// A manager verifying with the given prover.
+    pub fn with_solver(solver: Box<dyn SolverBackend>) -> Self {
         Self {
             boogie_files: Vec::new(),
             results: HashMap::new(),
-            temp_file_paths: Vec::new(),
+            solver,
+            cache: None,
+            reused: HashSet::new(),
+            jobs: 1,
+            limits: SolverLimits::default(),
+            granularity: VcGranularity::Edge,
+            encoding: Encoding::default(),
+            infer_invariants: false,
+            invariants: Vec::new(),
+            initial_state: InitialState::default(),
+            assumptions: Vec::new(),
+            discharges: None,
+            progress: None,
+            postconditions: Vec::new(),
+            witnesses: HashMap::new(),
+            conditions: HashMap::new(),
+            decisions: Vec::new(),
         }
     }
 
     /// Run the verification pipeline and remove successful C-edges
     pub fn run_commutativity_pipeline(&mut self, cfg: &CfgProgram, sc_graph: &mut SCGraph) {
-        // Get all C-edges (commutativity edges) from the SC graph
+        // Get all C-edges (commutativity edges) from the SC graph, easiest to
+        // verify first, so hard cases are attempted last
         let c_edges: Vec<_> = sc_graph
-            .edges
-            .iter()
-            .filter(|edge| edge.edge_type == EdgeType::C)
-            .cloned()
+            .c_edges_by_difficulty(cfg)
+            .into_iter()
+            .map(|(edge, _)| edge)
             .collect();
+        let started = Instant::now();
+        let mut progress = self.progress.take();
+        let mut decisions = Vec::new();
+        let mut tell = |event: ProgressEvent| {
+            if let Some(progress) = &mut progress {
+                progress(&event);
+            }
+            if let ProgressEvent::Decided(edge) = event {
+                decisions.push(edge);
+            }
+        };
+        tell(ProgressEvent::Started {
+            edges: c_edges.len(),
+        });
+
+        if self.infer_invariants && !c_edges.is_empty() {
+            self.invariants = invariants::infer(cfg, self.solver.as_mut(), self.encoding);
+        }
+        // Answers assuming other invariants or integers may differ
+        let prover = std::iter::once(self.solver.fingerprint())
+            .chain(self.encoding.ints.bits().map(|bits| format!("bv{}", bits)))
+            .chain(
+                (self.encoding.strings == StringEncoding::Uninterpreted)
+                    .then(|| "uninterpreted strings".to_string()),
+            )
+            .chain(self.assumed().iter().map(|invariant| invariant.format(cfg)))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let hops = |edge: &Edge| {
+            [edge.source, edge.target].map(|node| hop_label(cfg, &sc_graph.nodes[node]))
+        };
+        let decided = |edge: &Edge, status, by: &str, elapsed| {
+            ProgressEvent::Decided(EdgeProgress {
+                source: edge.source.index(),
+                target: edge.target.index(),
+                hops: hops(edge),
+                status,
+                by: by.to_string(),
+                elapsed,
+            })
+        };
 
-        let execution = VerificationExecution;
         let mut successful_edges = Vec::new();
+        // The C-edges left for the prover, with what explains its answer
+        let mut pending = Vec::new();
 
         // Process each C-edge
         for edge in c_edges {
+            let since = Instant::now();
             // 1) Create a VerificationUnit for this C-edge
             let verification_unit =
                 commutativity_check::create_verification_unit(edge.clone(), cfg, sc_graph);
 
-            // 2) Generate Boogie code for this unit
-            let boogie_code =
-                code_generation::generate_boogie_for_unit_with_cfg(&verification_unit, cfg);
-
-            // 3) Generate filename using the unit (better naming)
-            let filename = BoogieFileManager::generate_filename(&verification_unit, cfg);
-
-            // 4) Create BoogieFile entry
-            let boogie_file = BoogieFile {
-                filename,
-                code: boogie_code,
+            // Final hops that commute from any state need no prover; a witness
+            // is kept to explain the prover's answer if it fails
+            let hop_pair = HopPair {
+                function_a: verification_unit.function_a,
+                hop_a: verification_unit.final_a,
+                function_b: verification_unit.function_b,
+                hop_b: verification_unit.final_b,
+            };
+            // A hop committing a transaction that ensures properties is left
+            // to the prover, which checks them too
+            let ensures = commits_ensuring(cfg, &hop_pair);
+            // Hops whose accesses provably touch different records commute
+            let conflict = match sc_graph.justifications.get(&edge) {
+                Some(justification) if !justification.conflicting && !ensures => {
+                    self.discharge(hops(&edge), "disjoint accesses", None);
+                    tell(decided(
+                        &edge,
+                        "verified",
+                        "disjoint accesses",
+                        since.elapsed(),
+                    ));
+                    successful_edges.push(edge.clone());
+                    self.results.insert(edge, VerificationResult::Success);
+                    continue;
+                }
+                justification => justification.map(|justification| justification.reason.clone()),
+            };
+            let check = match sc_graph.pair_checks.get(&edge) {
+                _ if ensures => PairCheck::Unknown,
+                Some(check) => check.clone(),
+                None => hop_pair.check(cfg),
+            };
+            let witness = match check {
+                PairCheck::Commutative => {
+                    self.discharge(hops(&edge), "symbolic execution", None);
+                    tell(decided(
+                        &edge,
+                        "verified",
+                        "symbolic execution",
+                        since.elapsed(),
+                    ));
+                    successful_edges.push(edge.clone());
+                    self.results.insert(edge, VerificationResult::Success);
+                    continue;
+                }
+                PairCheck::Conflict(witness) => {
+                    let msg = match hop_pair.narrate(cfg, &witness) {
+                        Some(story) => format!("Possible non-serializable execution {}", story),
+                        None => format!(
+                            "Possible conflict from: {}",
+                            hop_pair.format_witness(cfg, &witness)
+                        ),
+                    };
+                    self.witnesses.insert(edge.clone(), (hop_pair, witness));
+                    Some(msg)
+                }
+                PairCheck::Unknown => None,
             };
 
-            // Store the Boogie file for potential output
-            self.boogie_files.push(boogie_file.clone());
-
-            // 5) Write temporary file for verification
-            match BoogieFileManager::write_temp_file(&boogie_file) {
-                Ok(temp_path) => {
-                    // Track temporary file for cleanup
-                    self.temp_file_paths.push(temp_path.clone());
-
-                    // 6) Execute the Boogie verification
-                    let result = execution.execute_boogie(&temp_path);
+            // Look up the answer of an earlier run
+            let key = self
+                .cache
+                .as_ref()
+                .map(|_| cache_key(&verification_unit, cfg, &prover));
+            let cached = match (&mut self.cache, &key) {
+                (Some(cache), Some(key)) => cache.get(key).cloned(),
+                _ => None,
+            };
+            if let Some(answer) = &cached {
+                tell(decided(&edge, status(answer), "cache", since.elapsed()));
+            }
+            pending.push((
+                edge,
+                verification_unit,
+                hop_pair,
+                conflict,
+                witness,
+                key,
+                cached,
+            ));
+        }
 
-                    // If verification is successful, mark edge for removal
-                    if matches!(result, VerificationResult::Success) {
-                        successful_edges.push(edge.clone());
+        // 2) Write the verification conditions of the other edges for the
+        // prover, keeping them for potential output, and submit them
+        let to_submit: Vec<_> = pending
+            .iter()
+            .filter(|(.., cached)| cached.is_none())
+            .collect();
+        let name = self.solver.name();
+        let submitted = self.submit_all(
+            cfg,
+            to_submit
+                .iter()
+                .map(|(_, unit, pair, ..)| (unit, pair))
+                .collect(),
+            &mut |index, answer, elapsed| {
+                tell(decided(&to_submit[index].0, status(answer), name, elapsed))
+            },
+        );
+        let mut submitted = submitted.into_iter();
+        for (edge, unit, hop_pair, conflict, witness, key, cached) in pending {
+            let answer = match cached {
+                Some(answer) => {
+                    self.reused.insert(edge.clone());
+                    answer
+                }
+                None => {
+                    let Some((condition, answer)) = submitted.next() else {
+                        unreachable!("an answer for every condition submitted")
+                    };
+                    if let Some(condition) = condition {
+                        // A batch comes back with each of its edges
+                        if !self
+                            .boogie_files
+                            .iter()
+                            .any(|file| file.filename == condition.filename)
+                        {
+                            self.boogie_files.push(condition.clone());
+                        }
+                        self.conditions.insert(edge.clone(), condition);
                     }
-
-                    self.results.insert(edge.clone(), result);
+                    if let (Some(cache), Some(key)) = (&mut self.cache, key) {
+                        cache.insert(key, answer.clone());
+                    }
+                    answer
                 }
-                Err(e) => {
-                    let result = VerificationResult::Failure(e);
-                    self.results.insert(edge.clone(), result);
+            };
+            let result = match answer {
+                SolverAnswer::Unsat => {
+                    if self.discharges.is_some() {
+                        // The condition alone, as a batch holds others too
+                        let condition = self
+                            .solver
+                            .encode(&unit, &hop_pair, cfg, &self.assumed(), self.encoding)
+                            .ok();
+                        self.discharge(hops(&edge), "prover", condition);
+                    }
+                    self.witnesses.remove(&edge);
+                    successful_edges.push(edge.clone());
+                    VerificationResult::Success
                 }
-            }
+                SolverAnswer::Sat(model) => {
+                    // Replay the prover's start state to tell what each order does
+                    let replay = hop_pair.witness_from_model(cfg, &model);
+                    self.witnesses
+                        .insert(edge.clone(), (hop_pair, replay.clone()));
+                    let msg = match hop_pair.narrate(cfg, &replay) {
+                        Some(story) => format!(
+                            "{} found a non-serializable execution {}",
+                            self.solver.name(),
+                            story
+                        ),
+                        None => with_witness(
+                            format!(
+                                "{} found a start state on which the orders end differently{}: {}",
+                                self.solver.name(),
+                                if commits_ensuring(cfg, &hop_pair) {
+                                    " or a transaction breaks what it ensures"
+                                } else {
+                                    ""
+                                },
+                                format_model(&model)
+                            ),
+                            &witness,
+                        ),
+                    };
+                    VerificationResult::Failure(with_conflict(msg, &conflict))
+                }
+                SolverAnswer::Unknown(msg) => VerificationResult::Unknown(with_conflict(
+                    with_witness(msg, &witness),
+                    &conflict,
+                )),
+            };
+            self.results.insert(edge, result);
         }
+        tell(ProgressEvent::Finished {
+            elapsed: started.elapsed(),
+        });
+        self.progress = progress;
+        self.decisions = decisions;
 
         // Remove successful C-edges from the SC graph
         sc_graph
             .edges
             .retain(|edge| !(edge.edge_type == EdgeType::C && successful_edges.contains(edge)));
+    }
 
-        // 7) Clean up temporary files
-        self.cleanup_temp_files();
+    /// Check the properties the functions ensure against the interleavings of
+    /// the chop, assuming what every condition assumes
+    pub fn check_postconditions(
+        &mut self,
+        cfg: &CfgProgram,
+        sc_graph: &SCGraph,
+    ) -> Result<(), String> {
+        let assumed = self.assumed();
+        self.postconditions =
+            postconditions::check(cfg, sc_graph, self.solver.as_mut(), self.encoding, &assumed)?;
+        Ok(())
     }
 
-    /// Clean up temporary files
-    fn cleanup_temp_files(&mut self) {
-        BoogieFileManager::cleanup_files(&self.temp_file_paths);
-        self.temp_file_paths.clear();
+    /// The invariants every condition assumes: those inferred, and with
+    /// `InitialState::Assumed` those declared.
+    pub fn assumed(&self) -> Vec<Invariant> {
+        let mut assumed = self.invariants.clone();
+        if self.initial_state == InitialState::Assumed {
+            for invariant in &self.assumptions {
+                if !assumed.contains(invariant) {
+                    assumed.push(invariant.clone());
+                }
+            }
+        }
+        assumed
+    }
+
+    /// Keeps how a C-edge was discharged, with the prover's proof of its
+    /// condition, if proof artifacts are wanted.
+    fn discharge(&mut self, hops: [String; 2], by: &'static str, condition: Option<BoogieFile>) {
+        let Some(discharges) = &mut self.discharges else {
+            return;
+        };
+        let proof = condition
+            .as_ref()
+            .and_then(|condition| self.solver.prove(condition));
+        discharges.push(Discharge {
+            hops,
+            by,
+            condition,
+            proof,
+        });
+    }
+
+    /// Writes and submits the verification conditions of C-edges, `jobs` at
+    /// a time, each worker with a prover of its own. With `VcGranularity::Pair`
+    /// the conditions of the edges between two functions are submitted as one
+    /// file, returned with each of them. Returns the condition, if it could be
+    /// written, and the answer of each edge, in the order given. `arrived` is
+    /// told of each answer, by its edge's index, as it comes in.
+    fn submit_all(
+        &mut self,
+        cfg: &CfgProgram,
+        edges: Vec<(&VerificationUnit, &HopPair)>,
+        arrived: &mut dyn FnMut(usize, &SolverAnswer, Duration),
+    ) -> Vec<(Option<BoogieFile>, SolverAnswer)> {
+        fn solve(
+            solver: &mut dyn SolverBackend,
+            cfg: &CfgProgram,
+            invariants: &[Invariant],
+            encoding: Encoding,
+            edges: &[(&VerificationUnit, &HopPair)],
+        ) -> Vec<(Option<BoogieFile>, SolverAnswer)> {
+            let mut results: Vec<_> = edges.iter().map(|_| (None, None)).collect();
+            let mut conditions = Vec::new();
+            let mut encoded = Vec::new();
+            for (index, (unit, pair)) in edges.iter().enumerate() {
+                match solver.encode(unit, pair, cfg, invariants, encoding) {
+                    Ok(condition) => {
+                        conditions.push(condition);
+                        encoded.push(index);
+                    }
+                    Err(e) => results[index].1 = Some(SolverAnswer::Unknown(e)),
+                }
+            }
+            if let ([condition], [index]) = (&conditions[..], &encoded[..]) {
+                let answer = solver.submit(condition);
+                results[*index] = (Some(condition.clone()), Some(answer));
+            } else if let Some(&first) = encoded.first() {
+                let name = BoogieFileManager::generate_batch_name(edges[first].0, cfg);
+                let (batch, answers) = solver.submit_batch(&name, &conditions);
+                for (index, answer) in encoded.into_iter().zip(answers) {
+                    results[index] = (Some(batch.clone()), Some(answer));
+                }
+            }
+            results
+                .into_iter()
+                .map(|(condition, answer)| {
+                    let answer = answer.expect("an answer for every condition submitted");
+                    (condition, answer)
+                })
+                .collect()
+        }
+
+        // The edges submitted together, as indices in the order given
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for (index, (unit, _)) in edges.iter().enumerate() {
+            let functions = |unit: &VerificationUnit| {
+                let (a, b) = (unit.function_a, unit.function_b);
+                (a.min(b), a.max(b))
+            };
+            let group = match self.granularity {
+                VcGranularity::Edge => None,
+                VcGranularity::Pair => groups
+                    .iter_mut()
+                    .find(|group| functions(edges[group[0]].0) == functions(unit)),
+            };
+            match group {
+                Some(group) => group.push(index),
+                None => groups.push(vec![index]),
+            }
+        }
+        let group_edges = |group: &[usize]| -> Vec<_> { group.iter().map(|&i| edges[i]).collect() };
+
+        let assumed = self.assumed();
+        let (invariants, encoding) = (assumed.as_slice(), self.encoding);
+        let mut answers: Vec<_> = edges.iter().map(|_| None).collect();
+        let workers = self.jobs.min(groups.len());
+        if workers <= 1 {
+            for group in &groups {
+                let group_started = Instant::now();
+                let results = solve(
+                    self.solver.as_mut(),
+                    cfg,
+                    invariants,
+                    encoding,
+                    &group_edges(group),
+                );
+                for (&index, result) in group.iter().zip(results) {
+                    arrived(index, &result.1, group_started.elapsed());
+                    answers[index] = Some(result);
+                }
+            }
+            return answers.into_iter().flatten().collect();
+        }
+        let mut forks: Vec<_> = (1..workers).map(|_| self.solver.fork()).collect();
+        let solvers =
+            std::iter::once(self.solver.as_mut()).chain(forks.iter_mut().map(|f| f.as_mut()));
+
+        // Workers take the next group as they finish one, and answers are put
+        // back in order as they come in
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for solver in solvers {
+                let (sender, next, groups, group_edges) =
+                    (sender.clone(), &next, &groups, &group_edges);
+                scope.spawn(move || loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(group) = groups.get(index) else {
+                        break;
+                    };
+                    let group_started = Instant::now();
+                    let results = solve(solver, cfg, invariants, encoding, &group_edges(group));
+                    let elapsed = group_started.elapsed();
+                    if sender.send((group, results, elapsed)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+            for (group, results, elapsed) in receiver {
+                for (&index, result) in group.iter().zip(results) {
+                    arrived(index, &result.1, elapsed);
+                    answers[index] = Some(result);
+                }
+            }
+        });
+        answers.into_iter().flatten().collect()
     }
 
     /// Save Boogie files to a directory
@@ -108,9 +554,36 @@ impl VerificationManager {
     }
 }
 
-impl Drop for VerificationManager {
-    fn drop(&mut self) {
-        // Ensure cleanup happens even if the user doesn't call cleanup_temp_files explicitly
-        self.cleanup_temp_files();
+/// How progress names an answer, as the report does.
+fn status(answer: &SolverAnswer) -> &'static str {
+    match answer {
+        SolverAnswer::Unsat => "verified",
+        SolverAnswer::Sat(_) => "failed",
+        SolverAnswer::Unknown(_) => "unknown",
+    }
+}
+
+/// Whether a hop of the pair commits a transaction that ensures properties,
+/// which its condition checks besides the orders.
+fn commits_ensuring(cfg: &CfgProgram, pair: &HopPair) -> bool {
+    [(pair.function_a, pair.hop_a), (pair.function_b, pair.hop_b)]
+        .into_iter()
+        .any(|(function, hop)| !postconditions::ensured(cfg, function, hop).is_empty())
+}
+
+/// Appends the execution on which symbolic execution saw the final hops conflict.
+fn with_witness(msg: String, witness: &Option<String>) -> String {
+    match witness {
+        Some(witness) => format!("{}\n{}", msg, witness),
+        None => msg,
+    }
+}
+
+/// Appends why the SC-graph kept the edge: the accesses of the hops that may
+/// address the same record.
+fn with_conflict(msg: String, conflict: &Option<String>) -> String {
+    match conflict {
+        Some(conflict) => format!("{}\nConflicting accesses: {}", msg, conflict),
+        None => msg,
     }
 }
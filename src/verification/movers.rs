@@ -0,0 +1,106 @@
+//! Lipton's reduction of the prefix interleavings of a C-edge.
+//!
+//! A verification unit checks its final hops after every interleaving of the
+//! hops before them, and there are exponentially many. Before any condition is
+//! written, each pair of prefix hops of the two transactions is classified by
+//! simple syntactic rules: the hops are both-movers with respect to each
+//! other, moving left or right past each other without changing the state,
+//! when every pair of their accesses
+//!
+//! - is to tables on different nodes, or to different fields,
+//! - only reads, or
+//! - addresses records whose keys provably differ.
+//!
+//! Interleavings that differ only by swapping adjacent movers end in the same
+//! state, so by Lipton's reduction only one of each class needs a check: the
+//! one in which no hop of the second transaction is directly followed by a hop
+//! of the first it moves with.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::verification::commutativity_check::create_verification_unit;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B, C }
+//!     table T on A { primary int id; int n; }
+//!     table U on B { primary int id; int n; }
+//!     table V on C { primary int id; int n; }
+//!     void f(int id) {
+//!         hop on A { T[id: id].n = 1; }
+//!         hop on B { U[id: 1].n = 1; }
+//!         hop on C { V[id: id].n = V[id: id].n * 2; }
+//!     }
+//!     void g(int id) {
+//!         hop on A { int n = T[id: id].n; }
+//!         hop on B { U[id: 2].n = 2; }
+//!         hop on C { V[id: id].n = V[id: id].n + 1; }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let edge = sc_graph
+//!     .edges
+//!     .iter()
+//!     .find(|edge| sc_graph.nodes[edge.source].cfg_node_id.index() == 2)
+//!     .unwrap();
+//! let unit = create_verification_unit(edge.clone(), &cfg, &sc_graph);
+//! // Of the 6 interleavings of two prefixes of two hops, the hops on B write
+//! // different records and the hops on A only move past those on B, so the
+//! // write and the read on A are the only pair whose order matters
+//! assert_eq!(unit.merges.len(), 2);
+//! assert_eq!(unit.reduced, 4);
+//! ```
+
+use crate::cfg::{CfgProgram, FunctionId, HopId, NodeId};
+use crate::dataflow::{function_keyed_accesses, AccessType, KeyAlias, KeyedAccess};
+use crate::verification::interleaving::enumerate_reduced_interleavings;
+use std::collections::HashMap;
+
+/// The accesses of the hops of two transactions, to tell which hops move.
+pub struct Movers {
+    nodes: HashMap<HopId, NodeId>,
+    accesses: HashMap<HopId, Vec<KeyedAccess>>,
+}
+
+impl Movers {
+    /// The movers of the hops of two functions, which may be the same.
+    pub fn new(cfg: &CfgProgram, function_a: FunctionId, function_b: FunctionId) -> Self {
+        let mut nodes = HashMap::new();
+        let mut accesses = HashMap::new();
+        let second = (function_b != function_a).then_some(function_b);
+        for function in std::iter::once(function_a).chain(second) {
+            for (hop, hop_cfg) in cfg.functions[function].hops.iter() {
+                nodes.insert(hop, hop_cfg.node_id);
+            }
+            accesses.extend(function_keyed_accesses(cfg, function));
+        }
+        Self { nodes, accesses }
+    }
+
+    /// Whether hop `a` of one transaction and hop `b` of the other are
+    /// both-movers with respect to each other.
+    pub fn moves(&self, a: HopId, b: HopId) -> bool {
+        if self.nodes.get(&a) != self.nodes.get(&b) {
+            return true;
+        }
+        let accesses = |hop| self.accesses.get(&hop).into_iter().flatten();
+        accesses(a).all(|x| {
+            accesses(b).all(|y| {
+                x.table != y.table
+                    || x.field != y.field
+                    || (x.access_type == AccessType::Read && y.access_type == AccessType::Read)
+                    || x.alias(y) == KeyAlias::MustNot
+            })
+        })
+    }
+
+    /// The interleavings of the prefixes of the two transactions that need a
+    /// check, one of each class equal up to swapping movers.
+    pub fn interleavings(&self, prefix_a: &[HopId], prefix_b: &[HopId]) -> Vec<Vec<HopId>> {
+        enumerate_reduced_interleavings(prefix_a, prefix_b, |a, b| self.moves(a, b))
+    }
+}
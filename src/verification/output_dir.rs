@@ -0,0 +1,230 @@
+//! The directory verify mode writes with `-o DIR` or `--output-dir DIR`, to
+//! inspect what was proven of each C-edge and how.
+//!
+//! `manifest.json` names the prover and the options the conditions were
+//! written and checked with, and lists every C-edge attempted. Each has a
+//! directory of its own under `edges/`, named after its hops, with:
+//!
+//! - the condition it was submitted in (`.bpl` or `.smt2`), unless it was
+//!   decided without the prover or from the cache; edges batched per pair of
+//!   functions each keep a copy of the batch
+//! - `solver.log`: the prover, what decided the edge, how long that took and
+//!   the answer, with the prover's message
+//! - `verdict.json`: the edge as the manifest lists it, with the message
+//! - `counterexample.txt`: the start state on which the two orders end
+//!   differently, where one was found
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::verification::output_dir::write_output_dir;
+//! use FMitF_rs::verification::VerificationManager;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void inc(int id) { hop on A { T[id: id].n = T[id: id].n + 1; } }
+//!     void set(int id) { hop on A { T[id: id].n = 0; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let mut sc_graph = SCGraph::new(&cfg);
+//! let mut manager = VerificationManager::new();
+//! manager.run_commutativity_pipeline(&cfg, &mut sc_graph);
+//!
+//! let dir = std::env::temp_dir().join(format!("fmitf-output-dir-{}", std::process::id()));
+//! let manifest = write_output_dir(&manager, &cfg, &sc_graph, &dir).unwrap();
+//! // Incrementing and resetting a counter do not commute
+//! let edge = &manifest.edges[0];
+//! assert_eq!(edge.dir, "edges/inc_H0-set_H0");
+//! assert_ne!(edge.result, "verified");
+//! assert_eq!(edge.counterexample.as_deref(), Some("counterexample.txt"));
+//! let edge_dir = dir.join(&edge.dir);
+//! assert!(edge_dir.join("solver.log").exists());
+//! assert!(edge_dir.join("verdict.json").exists());
+//! assert!(edge_dir.join("counterexample.txt").exists());
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::cfg::CfgProgram;
+use crate::sc_graph::{Edge, SCGraph};
+use crate::verification::boogie_file_manager::{BoogieFile, BoogieFileManager};
+use crate::verification::invariants::InitialState;
+use crate::verification::report::hop_label;
+use crate::verification::solver::{IntEncoding, SolverLimits, StringEncoding};
+use crate::verification::{VerificationManager, VerificationResult};
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::Path;
+
+/// The file in an output directory that lists its edges.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The directory under which each C-edge has its own.
+pub const EDGES_DIR: &str = "edges";
+
+/// What a verify run checked, and with what.
+#[derive(Serialize, Debug, Clone)]
+pub struct OutputManifest {
+    /// This tool and its version, which wrote the conditions
+    pub tool: String,
+    /// The prover, as messages name it
+    pub prover: String,
+    /// Its version, as it reports it
+    pub version: Option<String>,
+    /// What tells its answers apart, such as the command of an SMT solver
+    pub command: String,
+    pub limits: SolverLimits,
+    pub integers: IntEncoding,
+    pub strings: StringEncoding,
+    pub initial_state: InitialState,
+    /// The invariants every condition assumes
+    pub invariants: Vec<String>,
+    /// The C-edges attempted, by their nodes
+    pub edges: Vec<EdgeOutput>,
+}
+
+/// One C-edge of an output directory.
+#[derive(Serialize, Debug, Clone)]
+pub struct EdgeOutput {
+    /// Its directory, relative to the output directory
+    pub dir: String,
+    pub source: usize,
+    pub target: usize,
+    /// The hops at either end, as `f:H0`
+    pub hops: [String; 2],
+    /// `verified`, `failed` or `unknown`
+    pub result: &'static str,
+    /// The prover, `cache`, or what decided the edge without one
+    pub decided_by: Option<String>,
+    /// Whether it was removed from the SC-graph
+    pub removed: bool,
+    /// The file of the condition it was submitted in, in its directory
+    pub condition: Option<String>,
+    /// The file of its counterexample, in its directory
+    pub counterexample: Option<String>,
+}
+
+/// The `verdict.json` of an edge.
+#[derive(Serialize)]
+struct Verdict<'a> {
+    #[serde(flatten)]
+    edge: &'a EdgeOutput,
+    /// Why it failed or could not be decided
+    message: Option<&'a str>,
+}
+
+/// Writes the conditions and outcome of every C-edge the manager attempted to
+/// `dir`, each in a directory of its own, and returns the manifest.
+pub fn write_output_dir(
+    manager: &VerificationManager,
+    cfg: &CfgProgram,
+    sc_graph: &SCGraph,
+    dir: &Path,
+) -> Result<OutputManifest, String> {
+    let mut results: Vec<(&Edge, &VerificationResult)> = manager.results.iter().collect();
+    results.sort_by_key(|(edge, _)| (edge.source, edge.target));
+
+    let mut edges = Vec::new();
+    for (edge, result) in results {
+        let hops = [edge.source, edge.target].map(|node| hop_label(cfg, &sc_graph.nodes[node]));
+        let decision = manager.decisions.iter().find(|decision| {
+            (decision.source, decision.target) == (edge.source.index(), edge.target.index())
+        });
+        let (result, message) = match result {
+            VerificationResult::Success => ("verified", None),
+            VerificationResult::Failure(message) => ("failed", Some(message.as_str())),
+            VerificationResult::Unknown(message) => ("unknown", Some(message.as_str())),
+        };
+        let condition = manager.conditions.get(edge);
+        let counterexample =
+            manager
+                .witnesses
+                .get(edge)
+                .map(|(pair, witness)| match pair.narrate(cfg, witness) {
+                    Some(story) => format!("A non-serializable execution {}\n", story),
+                    None => format!("{}\n", pair.format_witness(cfg, witness)),
+                });
+        let output = EdgeOutput {
+            dir: format!("{}/{}", EDGES_DIR, hops.join("-").replace(':', "_")),
+            source: edge.source.index(),
+            target: edge.target.index(),
+            hops,
+            result,
+            decided_by: decision.map(|decision| decision.by.clone()),
+            removed: !sc_graph.edges.contains(edge),
+            condition: condition.map(|condition| condition.filename.clone()),
+            counterexample: counterexample
+                .as_ref()
+                .map(|_| "counterexample.txt".to_string()),
+        };
+
+        let mut log = format!("prover: {}\n", manager.solver.fingerprint());
+        if let Some(version) = manager.solver.version() {
+            let _ = writeln!(log, "version: {}", version);
+        }
+        let _ = match decision {
+            Some(decision) => writeln!(
+                log,
+                "decided by {} in {:.3}s: {}",
+                decision.by,
+                decision.elapsed.as_secs_f64(),
+                result
+            ),
+            None => writeln!(log, "{}", result),
+        };
+        if condition.is_none() {
+            log.push_str("no condition was submitted\n");
+        }
+        if let Some(message) = message {
+            let _ = writeln!(log, "{}", message);
+        }
+        let verdict = Verdict {
+            edge: &output,
+            message,
+        };
+
+        let mut files: Vec<BoogieFile> = condition.into_iter().cloned().collect();
+        files.push(BoogieFile {
+            filename: "solver.log".to_string(),
+            code: log,
+        });
+        files.push(BoogieFile {
+            filename: "verdict.json".to_string(),
+            code: serde_json::to_string_pretty(&verdict).expect("verdicts serialize"),
+        });
+        if let Some(counterexample) = counterexample {
+            files.push(BoogieFile {
+                filename: "counterexample.txt".to_string(),
+                code: counterexample,
+            });
+        }
+        BoogieFileManager::write_files(&files, &dir.join(&output.dir))?;
+        edges.push(output);
+    }
+
+    let manifest = OutputManifest {
+        tool: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        prover: manager.solver.name().to_string(),
+        version: manager.solver.version(),
+        command: manager.solver.fingerprint(),
+        limits: manager.limits,
+        integers: manager.encoding.ints,
+        strings: manager.encoding.strings,
+        initial_state: manager.initial_state,
+        invariants: manager
+            .assumed()
+            .iter()
+            .map(|invariant| invariant.format(cfg))
+            .collect(),
+        edges,
+    };
+    let file = BoogieFile {
+        filename: MANIFEST_FILE.to_string(),
+        code: serde_json::to_string_pretty(&manifest).expect("manifests serialize"),
+    };
+    BoogieFileManager::write_files(&[file], dir)?;
+    Ok(manifest)
+}
@@ -0,0 +1,463 @@
+//! Properties a transaction ensures, checked against the chop.
+//!
+//! A function may state in `// ensures(...)` comments properties of the
+//! records it writes that must hold when it commits, such as a balance that is
+//! never negative, in the forms `// assume(...)` takes. Serializability alone
+//! does not keep them: a chopped transaction may see the hops of another run
+//! between its own. So each property is checked after every interleaving of
+//! the function's hops with the first hops of another transaction, one at a
+//! time, which may still be running when it commits, and after the function
+//! run alone. Every record starts with the property and the properties every
+//! condition assumes, and a run in which the function aborts is left out.
+//!
+//! These are the only contracts the Boogie and SMT-LIB conditions check. The
+//! language has no `requires`, `assert` or loop invariants, so none are
+//! translated: parameters are unconstrained and loops keep their encoding.
+//!
+//! Interleavings that differ only by swapping hops that move past each other,
+//! as `movers` has it, end in the same state, so one of each class is checked.
+//! The first interleaving the solver finds to break a property is reported,
+//! with the start state it breaks it from. The hops of the other run are
+//! named after its function, primed when that is the function itself.
+//!
+//! ```
+//! use FMitF_rs::verification::postconditions::interleavings;
+//! use FMitF_rs::verification::symbolic::Side;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A, B }
+//!     table Account on A { primary int id; int balance; }
+//!     table Log on B { primary int id; int amount; }
+//!     // ensures(Account.balance >= 0)
+//!     void withdraw(int id, int amount) {
+//!         hop on A { if (Account[id: id].balance < amount) { abort; } }
+//!         hop on B { Log[id: id].amount = amount; }
+//!         hop on A { Account[id: id].balance = Account[id: id].balance - amount; }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let withdraw = cfg.root_functions[0];
+//! assert_eq!(cfg.functions[withdraw].ensures, ["Account.balance >= 0"]);
+//!
+//! // Of the 6 interleavings with the first two hops of another withdraw, only
+//! // the order of the log writes tells them apart: the checks of the balance
+//! // only read, and hops on different nodes move past each other. With the
+//! // other run's log write last, it could as well run after the withdraw, as
+//! // with its first hop alone
+//! let orders = interleavings(&cfg, withdraw, withdraw, 2).unwrap();
+//! let hops = &cfg.functions[withdraw].hop_order;
+//! let (a, b) = (|i: usize| (Side::A, hops[i]), |i: usize| (Side::B, hops[i]));
+//! assert_eq!(orders, [vec![a(0), b(0), b(1), a(1), a(2)]]);
+//! ```
+
+use crate::cfg::{BinaryOp, CfgProgram, Constant, FunctionId, HopId, TableId, UnaryOp};
+use crate::pretty::format_constant;
+use crate::sc_graph::SCGraph;
+use crate::verification::invariants::{assume, Invariant};
+use crate::verification::movers::Movers;
+use crate::verification::solver::{Encoding, SolverAnswer, SolverBackend};
+use crate::verification::symbolic::{read, HopEnd, HopPair, PairPath, Side, SymExpr};
+use serde::Serialize;
+
+/// Interleavings checked of a function with the first hops of another
+const MAX_INTERLEAVINGS: usize = 4096;
+
+/// What checking a property a function ensures found.
+#[derive(Serialize, Debug, Clone)]
+pub struct Postcondition {
+    pub function: String,
+    /// The property as written
+    pub property: String,
+    /// `holds`, `broken` or `unknown`
+    pub result: &'static str,
+    /// The hops of an interleaving that breaks it, in the order they run, as
+    /// `f:H0`
+    pub interleaving: Vec<String>,
+    /// The start state the interleaving breaks it from, or why it could not
+    /// be checked
+    pub message: Option<String>,
+}
+
+/// Checks the properties every function ensures against the interleavings
+/// of its hops with those of the other functions, and alone. Pairs of
+/// functions the exclusions ignore never run together, so are left out.
+/// Fails on a property that does not parse.
+pub fn check(
+    cfg: &CfgProgram,
+    sc_graph: &SCGraph,
+    solver: &mut dyn SolverBackend,
+    encoding: Encoding,
+    assumed: &[Invariant],
+) -> Result<Vec<Postcondition>, String> {
+    let mut checked = Vec::new();
+    for &function in &cfg.root_functions {
+        let func = &cfg.functions[function];
+        for text in &func.ensures {
+            let property = Invariant::parse(cfg, text, None)
+                .map_err(|e| format!("Invalid property '{}' of {}: {}", text, func.name, e))?;
+            let mut invariants = assumed.to_vec();
+            invariants.extend(property.iter().cloned());
+
+            // The run alone, then with the first hops of each other run
+            let others = cfg.root_functions.iter().filter(|&&other| {
+                !sc_graph
+                    .exclusions
+                    .ignores_pair(&func.name, &cfg.functions[other].name)
+            });
+            let runs = std::iter::once((function, 0)).chain(others.flat_map(|&other| {
+                (1..=cfg.functions[other].hop_order.len()).map(move |prefix| (other, prefix))
+            }));
+            let mut orders = Vec::new();
+            for (other, prefix) in runs {
+                match interleavings(cfg, function, other, prefix) {
+                    Some(found) => {
+                        orders.extend(found.into_iter().map(|order| (other, Some(order))))
+                    }
+                    None => orders.push((other, None)),
+                }
+            }
+
+            let mut outcome = Postcondition {
+                function: func.name.clone(),
+                property: text.clone(),
+                result: "holds",
+                interleaving: Vec::new(),
+                message: None,
+            };
+            for (other, order) in orders {
+                let pair = HopPair {
+                    function_a: function,
+                    hop_a: func.hop_order[0],
+                    function_b: other,
+                    hop_b: cfg.functions[other].hop_order[0],
+                };
+                let label = |(side, hop): &(Side, HopId)| hop_label(cfg, &pair, *side, *hop);
+                let Some(order) = order else {
+                    outcome.result = "unknown";
+                    outcome.message = Some(format!(
+                        "too many interleavings with {}",
+                        cfg.functions[other].name
+                    ));
+                    continue;
+                };
+                let interleaving: Vec<String> = order.iter().map(label).collect();
+                let Some(broken) = broken(cfg, &pair, &order, &property) else {
+                    outcome.result = "unknown";
+                    outcome.message = Some(format!(
+                        "too many paths through {}",
+                        interleaving.join(", ")
+                    ));
+                    continue;
+                };
+                if broken == SymExpr::Const(Constant::Bool(false)) {
+                    continue;
+                }
+                let question = format!("Can {} break {}?", interleaving.join(", "), text);
+                let script =
+                    pair.query_script(cfg, &question, &assume(&invariants, broken), encoding);
+                match solver.solve_script(&script) {
+                    SolverAnswer::Unsat => {}
+                    SolverAnswer::Sat(model) => {
+                        outcome.result = "broken";
+                        outcome.message = Some(start_state(cfg, &pair, &model));
+                        outcome.interleaving = interleaving;
+                        break;
+                    }
+                    SolverAnswer::Unknown(msg) => {
+                        outcome.result = "unknown";
+                        outcome.message = Some(msg);
+                    }
+                }
+            }
+            checked.push(outcome);
+        }
+    }
+    Ok(checked)
+}
+
+/// The interleavings of the hops of `function`, on side A, with the first
+/// `prefix` hops of `other`, on side B, that end with the last hop of
+/// `function`: one of each class equal up to swapping hops that move past each
+/// other. `None` when there are too many.
+pub fn interleavings(
+    cfg: &CfgProgram,
+    function: FunctionId,
+    other: FunctionId,
+    prefix: usize,
+) -> Option<Vec<Vec<(Side, HopId)>>> {
+    let hops = &cfg.functions[function].hop_order;
+    let Some((&last, hops)) = hops.split_last() else {
+        return Some(Vec::new());
+    };
+    let movers = Movers::new(cfg, function, other);
+    let mut results = Vec::new();
+    let mut current = Vec::new();
+    interleave(
+        hops,
+        &cfg.functions[other].hop_order[..prefix],
+        None,
+        &movers,
+        &mut current,
+        &mut results,
+    )?;
+    for order in &mut results {
+        order.push((Side::A, last));
+    }
+    // A hop of the other run left just before the last hop moves past it
+    results.retain(|order| {
+        order.len() < 2 || {
+            let (side, hop) = order[order.len() - 2];
+            side == Side::A || !movers.moves(last, hop)
+        }
+    });
+    Some(results)
+}
+
+/// Extends `current` with the interleavings of `a` and `b` in which no hop
+/// of `b` is directly followed by a hop of `a` that moves with it, as
+/// `interleaving::enumerate_reduced_interleavings` does, keeping the side of
+/// each hop, as the two may be runs of the same function.
+fn interleave(
+    a: &[HopId],
+    b: &[HopId],
+    last_b: Option<HopId>,
+    movers: &Movers,
+    current: &mut Vec<(Side, HopId)>,
+    results: &mut Vec<Vec<(Side, HopId)>>,
+) -> Option<()> {
+    if results.len() > MAX_INTERLEAVINGS {
+        return None;
+    }
+    if a.is_empty() && b.is_empty() {
+        results.push(current.clone());
+        return Some(());
+    }
+    if !a.is_empty() && !last_b.is_some_and(|hop| movers.moves(a[0], hop)) {
+        current.push((Side::A, a[0]));
+        interleave(&a[1..], b, None, movers, current, results)?;
+        current.pop();
+    }
+    if !b.is_empty() {
+        current.push((Side::B, b[0]));
+        interleave(a, &b[1..], Some(b[0]), movers, current, results)?;
+        current.pop();
+    }
+    Some(())
+}
+
+/// The start states on which the hops run in `order` commit side A with a
+/// record it wrote breaking `property`, as a condition; `None` when there are
+/// too many paths.
+fn broken(
+    cfg: &CfgProgram,
+    pair: &HopPair,
+    order: &[(Side, HopId)],
+    property: &[Invariant],
+) -> Option<SymExpr> {
+    let paths = pair.explore_order(cfg, order)?;
+    Some(broken_on(&paths, Side::A, property, Some(Side::A)))
+}
+
+/// The start states on which one of `paths` commits `side` with a record
+/// breaking an invariant of `property`, as a condition. Only the records
+/// `writer` wrote count when given, and every record written otherwise.
+pub(super) fn broken_on(
+    paths: &[PairPath],
+    side: Side,
+    property: &[Invariant],
+    writer: Option<Side>,
+) -> SymExpr {
+    paths
+        .iter()
+        .filter(|path| match side {
+            Side::A => path.end_a != HopEnd::Abort,
+            Side::B => path.end_b != HopEnd::Abort,
+        })
+        .flat_map(|path| {
+            let mut records: Vec<(TableId, &[SymExpr])> = Vec::new();
+            for write in &path.writes {
+                let record = (write.table, write.key.as_slice());
+                if writer.is_none_or(|writer| write.side == writer)
+                    && property
+                        .iter()
+                        .any(|invariant| invariant.table() == write.table)
+                    && !records.contains(&record)
+                {
+                    records.push(record);
+                }
+            }
+            records.into_iter().map(move |(table, key)| {
+                let after = property
+                    .iter()
+                    .filter(|invariant| invariant.table() == table)
+                    .map(|invariant| invariant.on(|field| read(&path.writes, table, field, key)))
+                    .fold(SymExpr::Const(Constant::Bool(true)), |all, holds| {
+                        SymExpr::binary(BinaryOp::And, all, holds)
+                    });
+                path.condition
+                    .iter()
+                    .cloned()
+                    .fold(SymExpr::unary(UnaryOp::Not, after), |all, condition| {
+                        SymExpr::binary(BinaryOp::And, condition, all)
+                    })
+            })
+        })
+        .fold(SymExpr::Const(Constant::Bool(false)), |any, broken| {
+            SymExpr::binary(BinaryOp::Or, any, broken)
+        })
+}
+
+/// What `function` ensures, when `hop` is its last, the hop it commits with.
+/// Properties that do not parse are left for `check` to report.
+///
+/// The conditions of the C-edges of such a hop check the properties, so an
+/// edge whose orders agree still stays when the hop breaks one:
+///
+/// ```
+/// use FMitF_rs::verification::code_generation::generate_boogie_assuming;
+/// use FMitF_rs::verification::commutativity_check::create_verification_unit;
+/// use FMitF_rs::verification::postconditions::ensured;
+/// use FMitF_rs::verification::solver::Encoding;
+/// use FMitF_rs::verification::symbolic::HopPair;
+/// use FMitF_rs::Pipeline;
+///
+/// let source = r#"
+///     nodes { A, B }
+///     table Account on A { primary int id; int balance; }
+///     table Log on B { primary int id; int n; }
+///     // ensures(Account.balance >= 0)
+///     void withdraw(int id, int amount) {
+///         hop on B { Log[id: id].n = 1; }
+///         hop on A {
+///             int before = Account[id: id].balance;
+///             Account[id: id].balance = before - amount;
+///         }
+///     }
+///     void deposit(int id, int amount) {
+///         hop on B { Log[id: id].n = 2; }
+///         hop on A {
+///             int before = Account[id: id].balance;
+///             Account[id: id].balance = before + amount;
+///         }
+///     }
+/// "#;
+/// // Without the property the debit and the deposit commute
+/// let unchecked = source.replace("// ensures(Account.balance >= 0)", "");
+/// let chopped = Pipeline::from_source(&unchecked).unwrap().analyze().unwrap().scgraph().unwrap();
+/// assert!(chopped.mixed_cycles().is_empty());
+///
+/// let chopped = Pipeline::from_source(source).unwrap().analyze().unwrap().scgraph().unwrap();
+/// assert!(!chopped.mixed_cycles().is_empty());
+/// let (cfg, sc_graph) = (&chopped.cfg, &chopped.sc_graph);
+/// let withdraw = cfg.root_functions[0];
+/// let debit = cfg.functions[withdraw].hop_order[1];
+/// assert_eq!(ensured(cfg, withdraw, debit).len(), 1);
+/// assert!(ensured(cfg, withdraw, cfg.functions[withdraw].hop_order[0]).is_empty());
+///
+/// let edge = sc_graph
+///     .edges
+///     .iter()
+///     .find(|edge| sc_graph.nodes[edge.source].cfg_hop_id == debit)
+///     .unwrap();
+/// let unit = create_verification_unit(edge.clone(), cfg, sc_graph);
+/// let boogie = generate_boogie_assuming(&unit, cfg, &[], Encoding::default());
+/// assert!(boogie.contains(
+///     "    // withdraw commits: check what it ensures\n    assert (forall k0: int :: Account_balance[k0] >= 0);"
+/// ));
+///
+/// let pair = HopPair {
+///     function_a: unit.function_a,
+///     hop_a: unit.final_a,
+///     function_b: unit.function_b,
+///     hop_b: unit.final_b,
+/// };
+/// let script = pair.smt_script_ensuring(cfg, &[], Encoding::default()).unwrap();
+/// assert!(script.contains("Or does a hop break what its transaction ensures?"));
+///
+/// // A prover finds the debit may leave a negative balance
+/// # #[cfg(feature = "z3")]
+/// # {
+/// use FMitF_rs::pipeline::VerifyOptions;
+/// use FMitF_rs::verification::solver::SolverKind;
+///
+/// let options = VerifyOptions { solver: SolverKind::Z3, ..VerifyOptions::default() };
+/// let verified = chopped.verify(&options).unwrap();
+/// assert!(!verified.report.cycles.is_empty());
+/// # }
+/// ```
+pub fn ensured(cfg: &CfgProgram, function: FunctionId, hop: HopId) -> Vec<Invariant> {
+    let func = &cfg.functions[function];
+    if func.hop_order.last() != Some(&hop) {
+        return Vec::new();
+    }
+    func.ensures
+        .iter()
+        .filter_map(|text| Invariant::parse(cfg, text, None).ok())
+        .flatten()
+        .collect()
+}
+
+/// A hop as `f:H0`, primed as `f':H0` when run by side B of a function
+/// paired with itself.
+fn hop_label(cfg: &CfgProgram, pair: &HopPair, side: Side, hop: HopId) -> String {
+    let function = match side {
+        Side::A => pair.function_a,
+        Side::B => pair.function_b,
+    };
+    let func = &cfg.functions[function];
+    let position = func
+        .hop_order
+        .iter()
+        .position(|&h| h == hop)
+        .unwrap_or_default();
+    format!("{}{}:H{}", func.name, prime(pair, side), position)
+}
+
+fn prime(pair: &HopPair, side: Side) -> &'static str {
+    if side == Side::B && pair.function_a == pair.function_b {
+        "'"
+    } else {
+        ""
+    }
+}
+
+/// The variable and record values a model starts from, named as `f.x` and
+/// `T[id: 1].n`.
+fn start_state(cfg: &CfgProgram, pair: &HopPair, model: &[(String, String)]) -> String {
+    let witness = pair.witness_from_model(cfg, model);
+    let inputs = witness.inputs.iter().map(|(side, var, value)| {
+        let function = match side {
+            Side::A => pair.function_a,
+            Side::B => pair.function_b,
+        };
+        let func = &cfg.functions[function];
+        format!(
+            "{}{}.{} = {}",
+            func.name,
+            prime(pair, *side),
+            func.variables[*var].name,
+            format_constant(value)
+        )
+    });
+    let initial = witness.initial.iter().map(|(table, field, key, value)| {
+        let table = &cfg.tables[*table];
+        let key: Vec<String> = table
+            .primary_keys
+            .iter()
+            .zip(key)
+            .map(|(pk, value)| format!("{}: {}", cfg.fields[*pk].name, format_constant(value)))
+            .collect();
+        format!(
+            "{}[{}].{} = {}",
+            table.name,
+            key.join(", "),
+            cfg.fields[*field].name,
+            format_constant(value)
+        )
+    });
+    inputs.chain(initial).collect::<Vec<_>>().join(", ")
+}
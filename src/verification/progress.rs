@@ -0,0 +1,95 @@
+//! Progress of a verify run, as C-edges are decided.
+//!
+//! A manager with a `progress` callback calls it once verification of the
+//! C-edges starts, again as each edge is decided, whether by the prover, the
+//! cache or without a prover, and once all are. Answers of the prover come in
+//! as they arrive, so with several jobs out of order; with conditions batched
+//! per pair of functions, the edges of a batch arrive together, each with the
+//! time the batch took.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::verification::progress::ProgressEvent;
+//! use FMitF_rs::verification::VerificationManager;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//! use std::sync::{Arc, Mutex};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     void inc(int id) { hop on A { T[id: id].n = T[id: id].n + 1; } }
+//!     void add(int id) { hop on A { T[id: id].n = T[id: id].n + 2; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let mut sc_graph = SCGraph::new(&cfg);
+//! let mut manager = VerificationManager::new();
+//! let events = Arc::new(Mutex::new(Vec::new()));
+//! let seen = events.clone();
+//! manager.progress = Some(Box::new(move |event| seen.lock().unwrap().push(event.to_json())));
+//! manager.run_commutativity_pipeline(&cfg, &mut sc_graph);
+//!
+//! let events = events.lock().unwrap();
+//! assert_eq!(events[0]["event"], "started");
+//! assert_eq!(events[1]["event"], "decided");
+//! assert_eq!(events[1]["status"], "verified");
+//! assert_eq!(events[1]["by"], "symbolic execution");
+//! assert_eq!(events.last().unwrap()["event"], "finished");
+//! ```
+
+use serde_json::json;
+use std::time::Duration;
+
+/// What a manager tells of each step; `Send` so the manager can run on another
+/// thread.
+pub type ProgressCallback = Box<dyn FnMut(&ProgressEvent) + Send>;
+
+/// A step of a verify run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// Verification of this many C-edges started
+    Started { edges: usize },
+    /// A C-edge was decided
+    Decided(EdgeProgress),
+    /// Every C-edge was decided, after this long
+    Finished { elapsed: Duration },
+}
+
+/// How one C-edge was decided.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeProgress {
+    pub source: usize,
+    pub target: usize,
+    /// The hops at either end, as `f:H0`
+    pub hops: [String; 2],
+    /// `verified`, `failed` or `unknown`
+    pub status: &'static str,
+    /// The prover, `cache`, or what removed the edge without one
+    pub by: String,
+    /// How long deciding it took
+    pub elapsed: Duration,
+}
+
+impl ProgressEvent {
+    /// The event as one JSON object, with its kind as `event` and times in
+    /// seconds.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            ProgressEvent::Started { edges } => json!({ "event": "started", "edges": edges }),
+            ProgressEvent::Decided(edge) => json!({
+                "event": "decided",
+                "source": edge.source,
+                "target": edge.target,
+                "hops": edge.hops,
+                "status": edge.status,
+                "by": edge.by,
+                "elapsed": edge.elapsed.as_secs_f64(),
+            }),
+            ProgressEvent::Finished { elapsed } => {
+                json!({ "event": "finished", "elapsed": elapsed.as_secs_f64() })
+            }
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! What a verify run found, for people and for CI pipelines.
+//!
+//! The report lists every C-edge verification was attempted on, with its
+//! result and whether it was removed from the SC-graph, the mixed cycles left
+//! in the pruned graph, as vertex ids, and a final verdict: the chopping is
+//! serializable when no cycle is left, or correct under snapshot isolation
+//! when the graph was checked against it. The verdict is only as sound as the
+//! encodings of integers and strings, which the report names along with what
+//! they leave out: unbounded integers miss overflow, bitvectors hold only at
+//! their width, and uninterpreted strings may keep edges that commute. It is
+//! only as sound as the states records start from, too: any values, or with
+//! `InitialState::Assumed` values with the properties declared of the tables,
+//! which the report lists as they were trusted rather than checked. Under
+//! `ensures` it lists what checking the properties functions ensure found,
+//! under `schedules` the tests that may reproduce the C-edges kept, and under
+//! `functions` each transaction: whether every C-edge at its hops was
+//! removed, the ones left and the transactions they still conflict with.
+//! Under `ledger` it lists every assumption the verdict rests on, as
+//! `ledger::ledger` finds them. The
+//! C-edges that failed or could not be decided can also be written as a SARIF
+//! log, for code scanning in CI to annotate the accesses they conflict
+//! through.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::{CycleLimits, SCGraph};
+//! use FMitF_rs::verification::report::VerificationReport;
+//! use FMitF_rs::verification::{VerificationManager, VerificationResult};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program =
+//!     parse_and_analyze(include_str!("../../examples/sc_graph_demos/crossed_writers.transact"))
+//!         .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let mut sc_graph = SCGraph::new(&cfg);
+//! let mut manager = VerificationManager::new();
+//!
+//! // As if the prover failed on the edge between the hops on A
+//! let edge = sc_graph.c_edges_by_difficulty(&cfg)[0].0.clone();
+//! manager.results.insert(edge, VerificationResult::Failure("differs".to_string()));
+//! let report = VerificationReport::new(&manager, &cfg, &sc_graph, &CycleLimits::default());
+//! assert_eq!(report.verdict(), "1 cycle remains");
+//! assert!(!report.edges[0].removed);
+//!
+//! let json = report.to_json();
+//! assert_eq!(json["edges"][0]["result"], "failed");
+//! assert_eq!(json["cycles"].as_array().unwrap().len(), 1);
+//! assert_eq!(json["serializable"], false);
+//! assert_eq!(json["integers"], "int");
+//! assert_eq!(json["initial_state"], "havoc");
+//! assert_eq!(json["isolation"], "serializable");
+//! assert_eq!(json["ledger"][0]["kind"], "isolation");
+//! assert_eq!(json["functions"][0]["function"], "f");
+//! assert_eq!(json["functions"][0]["discharged"], false);
+//! assert_eq!(json["functions"][0]["conflicts_with"][0], "g");
+//!
+//! let sarif = report.to_sarif(&cfg, &sc_graph);
+//! let result = &sarif["runs"][0]["results"][0];
+//! assert_eq!(result["level"], "error");
+//! // The writes to T of f and of g
+//! assert_eq!(result["locations"][0]["physicalLocation"]["region"]["startLine"], 24);
+//! assert_eq!(result["relatedLocations"][0]["physicalLocation"]["region"]["startLine"], 34);
+//!
+//! // Once both C-edges are removed, nothing is left
+//! sc_graph.edges.retain(|edge| edge.edge_type != FMitF_rs::sc_graph::EdgeType::C);
+//! let report = VerificationReport::new(&manager, &cfg, &sc_graph, &CycleLimits::default());
+//! assert_eq!(report.verdict(), "chopping serializable");
+//! assert!(report.functions.iter().all(|function| function.discharged));
+//! ```
+
+use crate::ast::Span;
+use crate::cfg::CfgProgram;
+use crate::pretty::sarif;
+use crate::sc_graph::{CycleLimits, Edge, EdgeType, Isolation, MixedCycles, SCGraph, SCGraphNode};
+use crate::verification::invariants::InitialState;
+use crate::verification::ledger::{ledger, Assumption};
+use crate::verification::postconditions::Postcondition;
+use crate::verification::schedules::{test_schedules, TestSchedule};
+use crate::verification::solver::{IntEncoding, StringEncoding};
+use crate::verification::{VerificationManager, VerificationResult};
+use serde::Serialize;
+use serde_json::json;
+
+/// The SARIF rules of C-edges that failed and that could not be decided.
+const FAILED_RULE: &str = "non-commuting-hops";
+const UNKNOWN_RULE: &str = "unverified-hops";
+
+/// The verdict on a pruned SC-graph with `cycles` mixed cycles left.
+pub fn verdict(cycles: usize, isolation: Isolation) -> String {
+    match (cycles, isolation) {
+        (0, Isolation::Serializable) => "chopping serializable".to_string(),
+        (0, Isolation::Snapshot) => "chopping correct under snapshot isolation".to_string(),
+        (1, _) => "1 cycle remains".to_string(),
+        (n, _) => format!("{} cycles remain", n),
+    }
+}
+
+/// The outcome of a verify run.
+#[derive(Serialize, Debug, Clone)]
+pub struct VerificationReport {
+    /// The C-edges attempted, by vertex ids
+    pub edges: Vec<EdgeOutcome>,
+    /// The mixed cycles left, as vertex ids
+    pub cycles: Vec<Vec<usize>>,
+    /// Whether the cycle search stopped at its limit, so more cycles may exist
+    pub cycles_truncated: bool,
+    /// What the cycles were judged against
+    pub isolation: Isolation,
+    /// How the conditions encoded integers
+    pub integers: IntEncoding,
+    /// How they encoded strings
+    pub strings: StringEncoding,
+    /// What records started from
+    pub initial_state: InitialState,
+    /// The properties declared of the tables that conditions assumed
+    pub assumptions: Vec<String>,
+    /// What checking the properties functions ensure found
+    pub ensures: Vec<Postcondition>,
+    /// Tests that may reproduce the C-edges kept on the cycles left
+    pub schedules: Vec<TestSchedule>,
+    /// What is left of the C-edges of each transaction
+    pub functions: Vec<FunctionSummary>,
+    /// What a proof under those encodings does not cover
+    pub soundness: String,
+    /// Every assumption the verdict rests on
+    pub ledger: Vec<Assumption>,
+}
+
+/// What verification made of one C-edge.
+#[derive(Serialize, Debug, Clone)]
+pub struct EdgeOutcome {
+    #[serde(skip)]
+    pub edge: Edge,
+    pub source: usize,
+    pub target: usize,
+    /// The hops at either end, as `f:H0`
+    pub hops: [String; 2],
+    /// `verified`, `failed` or `unknown`
+    pub result: &'static str,
+    /// Whether the edge was proven safe and removed from the SC-graph
+    pub removed: bool,
+    /// Whether the result was taken from the cache rather than this run
+    pub reused: bool,
+    /// Why the edge was kept, if it was
+    pub message: Option<String>,
+}
+
+/// What is left of the C-edges at the hops of one transaction.
+#[derive(Serialize, Debug, Clone)]
+pub struct FunctionSummary {
+    pub function: String,
+    /// Whether no C-edge at its hops is left in the SC-graph
+    pub discharged: bool,
+    /// How many C-edges at its hops verification removed
+    pub removed: usize,
+    /// The C-edges at its hops left, by the hops at either end
+    pub remaining: Vec<[String; 2]>,
+    /// The transactions at the other ends of those, in the order the program
+    /// declares them
+    pub conflicts_with: Vec<String>,
+}
+
+/// What is left of the C-edges of each transaction after verification, in
+/// the order the program declares them.
+pub fn function_summaries(
+    manager: &VerificationManager,
+    cfg: &CfgProgram,
+    sc_graph: &SCGraph,
+) -> Vec<FunctionSummary> {
+    let function = |node| sc_graph.nodes[node].cfg_function_id;
+    let mut c_edges: Vec<&Edge> = sc_graph
+        .edges
+        .iter()
+        .filter(|edge| edge.edge_type == EdgeType::C)
+        .collect();
+    c_edges.sort_by_key(|edge| (edge.source, edge.target));
+
+    cfg.root_functions
+        .iter()
+        .map(|&id| {
+            let at = |edge: &Edge| function(edge.source) == id || function(edge.target) == id;
+            let remaining: Vec<&Edge> = c_edges.iter().copied().filter(|edge| at(edge)).collect();
+            let others: Vec<_> = remaining
+                .iter()
+                .map(|edge| {
+                    if function(edge.source) == id {
+                        function(edge.target)
+                    } else {
+                        function(edge.source)
+                    }
+                })
+                .collect();
+            FunctionSummary {
+                function: cfg.functions[id].name.clone(),
+                discharged: remaining.is_empty(),
+                removed: manager
+                    .results
+                    .keys()
+                    .filter(|edge| at(edge) && !sc_graph.edges.contains(edge))
+                    .count(),
+                remaining: remaining
+                    .iter()
+                    .map(|edge| {
+                        [edge.source, edge.target].map(|node| hop_label(cfg, &sc_graph.nodes[node]))
+                    })
+                    .collect(),
+                conflicts_with: cfg
+                    .root_functions
+                    .iter()
+                    .filter(|other| others.contains(other))
+                    .map(|&other| cfg.functions[other].name.clone())
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+impl VerificationReport {
+    /// The report of a manager that has run on the SC-graph given, which is
+    /// searched for the mixed cycles left within the limits.
+    pub fn new(
+        manager: &VerificationManager,
+        cfg: &CfgProgram,
+        sc_graph: &SCGraph,
+        limits: &CycleLimits,
+    ) -> Self {
+        let hop = |node: &SCGraphNode| hop_label(cfg, node);
+        let mut edges: Vec<EdgeOutcome> = manager
+            .results
+            .iter()
+            .map(|(edge, result)| {
+                let (result, message) = match result {
+                    VerificationResult::Success => ("verified", None),
+                    VerificationResult::Failure(msg) => ("failed", Some(msg.clone())),
+                    VerificationResult::Unknown(msg) => ("unknown", Some(msg.clone())),
+                };
+                EdgeOutcome {
+                    edge: edge.clone(),
+                    source: edge.source.index(),
+                    target: edge.target.index(),
+                    hops: [
+                        hop(&sc_graph.nodes[edge.source]),
+                        hop(&sc_graph.nodes[edge.target]),
+                    ],
+                    result,
+                    removed: !sc_graph.edges.contains(edge),
+                    reused: manager.reused.contains(edge),
+                    message,
+                }
+            })
+            .collect();
+        edges.sort_by_key(|edge| (edge.source, edge.target));
+
+        let MixedCycles { cycles, truncated } = sc_graph.find_mixed_cycles_within(limits);
+        Self {
+            edges,
+            cycles: cycles
+                .iter()
+                .map(|cycle| cycle.iter().map(|node| node.index()).collect())
+                .collect(),
+            cycles_truncated: truncated,
+            isolation: sc_graph.isolation,
+            integers: manager.encoding.ints,
+            strings: manager.encoding.strings,
+            initial_state: manager.initial_state,
+            assumptions: match manager.initial_state {
+                InitialState::Havoc => Vec::new(),
+                InitialState::Assumed => manager
+                    .assumptions
+                    .iter()
+                    .map(|invariant| invariant.format(cfg))
+                    .collect(),
+            },
+            ensures: manager.postconditions.clone(),
+            schedules: test_schedules(manager, cfg, sc_graph, &cycles),
+            functions: function_summaries(manager, cfg, sc_graph),
+            soundness: format!(
+                "{}; {}; {}",
+                manager.encoding.ints.soundness(),
+                manager.encoding.strings.soundness(),
+                manager.initial_state.soundness()
+            ),
+            ledger: ledger(manager, cfg, sc_graph),
+        }
+    }
+
+    /// Whether no mixed cycle is left.
+    pub fn is_serializable(&self) -> bool {
+        self.cycles.is_empty()
+    }
+
+    /// `chopping serializable`, or how many cycles remain.
+    pub fn verdict(&self) -> String {
+        verdict(self.cycles.len(), self.isolation)
+    }
+
+    /// The report as JSON, with the verdict.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::to_value(self).expect("reports serialize");
+        json["verdict"] = self.verdict().into();
+        json["serializable"] = self.is_serializable().into();
+        json
+    }
+
+    /// The C-edges that failed or could not be decided as a SARIF 2.1.0 log,
+    /// each located at the first access its hops conflict through, with the
+    /// other accesses as related locations.
+    pub fn to_sarif(&self, cfg: &CfgProgram, sc_graph: &SCGraph) -> serde_json::Value {
+        let results: Vec<_> = self
+            .edges
+            .iter()
+            .filter_map(|outcome| {
+                let (rule, level, summary) = match outcome.result {
+                    "failed" => (FAILED_RULE, "error", "may not commute"),
+                    "unknown" => (UNKNOWN_RULE, "warning", "could not be verified to commute"),
+                    _ => return None,
+                };
+                let mut spans: Vec<&Span> = Vec::new();
+                let conflicts = sc_graph
+                    .justifications
+                    .get(&outcome.edge)
+                    .map(|justification| justification.conflicts.as_slice())
+                    .unwrap_or_default();
+                for conflict in conflicts {
+                    for site in [conflict.source, conflict.target] {
+                        let span = cfg.site_span(site);
+                        if !spans.contains(&span) {
+                            spans.push(span);
+                        }
+                    }
+                }
+                let mut text = format!("{} and {} {}", outcome.hops[0], outcome.hops[1], summary);
+                if let Some(message) = &outcome.message {
+                    text = format!("{}: {}", text, message);
+                }
+                let related: Vec<_> = spans
+                    .iter()
+                    .enumerate()
+                    .skip(1)
+                    .map(|(id, span)| {
+                        let mut location = sarif::location(span);
+                        location["id"] = id.into();
+                        location
+                    })
+                    .collect();
+                let locations: Vec<_> = spans
+                    .first()
+                    .map(|span| sarif::location(span))
+                    .into_iter()
+                    .collect();
+                Some(json!({
+                    "ruleId": rule,
+                    "level": level,
+                    "message": { "text": text },
+                    "locations": locations,
+                    "relatedLocations": related,
+                }))
+            })
+            .collect();
+
+        sarif::log(
+            vec![
+                sarif::rule(FAILED_RULE, "Hops of a C-edge that may not commute"),
+                sarif::rule(
+                    UNKNOWN_RULE,
+                    "Hops of a C-edge not verified to commute in time",
+                ),
+            ],
+            results,
+            json!({
+                "integers": self.integers,
+                "strings": self.strings,
+                "isolation": self.isolation,
+                "initialState": self.initial_state,
+                "assumptions": self.assumptions,
+                "soundness": self.soundness,
+            }),
+        )
+    }
+}
+
+/// A hop as `f:H0`: its function and its position there.
+pub fn hop_label(cfg: &CfgProgram, node: &SCGraphNode) -> String {
+    let function = &cfg.functions[node.cfg_function_id];
+    let position = function
+        .hop_order
+        .iter()
+        .position(|&hop| hop == node.cfg_hop_id)
+        .unwrap_or_default();
+    format!("{}:H{}", function.name, position)
+}
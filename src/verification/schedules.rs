@@ -0,0 +1,214 @@
+//! Test schedules that replay the C-edges verification kept.
+//!
+//! A C-edge the prover could not discharge comes with a start state on which
+//! its final hops may end differently in the two orders, from the prover's
+//! model or from symbolic execution. On a mixed cycle left in the pruned graph
+//! that passes it, the edge can make a run non-serializable, so the cycle's
+//! schedule, the calls of its functions with their parameters as the start
+//! state has them and the records it reads make a test that simulate mode
+//! runs with `--test`. Parameters the start state leaves out take their
+//! type's default. The records are set before any call runs, so a hop before
+//! the final ones that writes them may hide the anomaly.
+//!
+//! The runtime runs each function once in a schedule, so edges between two
+//! runs of the same function, and cycles through one, get no test.
+//!
+//! ```
+//! use FMitF_rs::sc_graph::SCGraph;
+//! use FMitF_rs::verification::schedules::test_schedules;
+//! use FMitF_rs::verification::symbolic::{ConflictWitness, HopPair, Side};
+//! use FMitF_rs::verification::{VerificationManager, VerificationResult};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//! use FMitF_rs::cfg::Constant;
+//!
+//! let program =
+//!     parse_and_analyze(include_str!("../../examples/sc_graph_demos/crossed_writers.transact"))
+//!         .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let sc_graph = SCGraph::new(&cfg);
+//! let (f, g) = (cfg.root_functions[0], cfg.root_functions[1]);
+//!
+//! // As if the prover failed on the edge between the hops on A from id 7
+//! let edge = sc_graph.c_edges_by_difficulty(&cfg)[0].0.clone();
+//! let id = |function| cfg.functions[function].parameters[0];
+//! let pair = HopPair {
+//!     function_a: f,
+//!     hop_a: cfg.functions[f].hop_order[0],
+//!     function_b: g,
+//!     hop_b: cfg.functions[g].hop_order[0],
+//! };
+//! let witness = ConflictWitness {
+//!     inputs: vec![(Side::A, id(f), Constant::Int(7)), (Side::B, id(g), Constant::Int(7))],
+//!     initial: vec![],
+//! };
+//! let mut manager = VerificationManager::new();
+//! manager.results.insert(edge.clone(), VerificationResult::Failure("differs".to_string()));
+//! manager.witnesses.insert(edge, (pair, witness));
+//!
+//! let cycles = sc_graph.find_mixed_cycles();
+//! let tests = test_schedules(&manager, &cfg, &sc_graph, &cycles);
+//! assert_eq!(tests.len(), 1);
+//! assert_eq!(tests[0].hops, ["f:H0", "g:H0"]);
+//! assert_eq!(tests[0].schedule, "g:1 -> f:1 -> f:2 -> g:2");
+//! let calls: Vec<_> = tests[0].calls.iter().map(|call| (&call.function[..], &call.args[..])).collect();
+//! assert_eq!(calls, [("g", &["7".to_string()][..]), ("f", &["7".to_string()][..])]);
+//! ```
+
+use crate::cfg::{CfgProgram, Constant, HopId};
+use crate::sc_graph::SCGraph;
+use crate::verification::report::hop_label;
+use crate::verification::symbolic::{default_value, Side};
+use crate::verification::{VerificationManager, VerificationResult};
+use serde::{Deserialize, Serialize};
+
+/// A run of the hops of transactions that may show a C-edge's anomaly.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TestSchedule {
+    /// The hops of the C-edge, as `f:H0`
+    pub hops: [String; 2],
+    /// The transactions to run
+    pub calls: Vec<TestCall>,
+    /// The order of their hops, as `--schedule` takes it
+    pub schedule: String,
+    /// Record fields to set before the calls run
+    pub records: Vec<TestRecord>,
+}
+
+/// A transaction of a test, with its arguments as `--call` takes them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TestCall {
+    pub function: String,
+    pub args: Vec<String>,
+}
+
+/// A field of a record a test starts from, its key in the order the table
+/// declares its primary key fields.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TestRecord {
+    pub table: String,
+    pub key: Vec<String>,
+    pub field: String,
+    pub value: String,
+}
+
+impl TestSchedule {
+    /// A name for the test's file, after the hops of its C-edge.
+    pub fn file_name(&self) -> String {
+        format!("{}--{}.json", self.hops[0], self.hops[1]).replace(':', "_")
+    }
+}
+
+/// A test for each C-edge kept with a start state, on the first of the mixed
+/// `cycles` left through it that has a schedule, in the order of the
+/// SC-graph's nodes.
+pub fn test_schedules(
+    manager: &VerificationManager,
+    cfg: &CfgProgram,
+    sc_graph: &SCGraph,
+    cycles: &[Vec<HopId>],
+) -> Vec<TestSchedule> {
+    let mut edges: Vec<_> = manager
+        .results
+        .iter()
+        .filter(|(edge, result)| {
+            !matches!(result, VerificationResult::Success) && sc_graph.edges.contains(edge)
+        })
+        .filter_map(|(edge, _)| Some((edge, manager.witnesses.get(edge)?)))
+        .collect();
+    edges.sort_by_key(|(edge, _)| (edge.source, edge.target));
+
+    let mut tests = Vec::new();
+    for (edge, (pair, witness)) in edges {
+        if pair.function_a == pair.function_b {
+            continue;
+        }
+        let ends = [edge.source, edge.target].map(|node| sc_graph.nodes[node].cfg_hop_id);
+        let Some(schedule) = cycles
+            .iter()
+            .filter(|cycle| passes(cycle, ends) && runs_once(sc_graph, cycle))
+            .find_map(|cycle| sc_graph.cycle_schedule(cycle, cfg))
+        else {
+            continue;
+        };
+
+        let mut calls: Vec<TestCall> = Vec::new();
+        for step in &schedule.steps {
+            if calls.iter().any(|call| call.function == step.function) {
+                continue;
+            }
+            let function = sc_graph.nodes[step.node].cfg_function_id;
+            let side = [(pair.function_a, Side::A), (pair.function_b, Side::B)]
+                .into_iter()
+                .find(|&(f, _)| f == function)
+                .map(|(_, side)| side);
+            let func = &cfg.functions[function];
+            let args = func
+                .parameters
+                .iter()
+                .map(|&param| {
+                    let given = witness
+                        .inputs
+                        .iter()
+                        .find(|(s, var, _)| Some(*s) == side && *var == param)
+                        .map(|(_, _, value)| value.clone());
+                    argument(&given.unwrap_or_else(|| default_value(&func.variables[param].ty)))
+                })
+                .collect();
+            calls.push(TestCall {
+                function: step.function.clone(),
+                args,
+            });
+        }
+
+        let records = witness
+            .initial
+            .iter()
+            .map(|(table, field, key, value)| TestRecord {
+                table: cfg.tables[*table].name.clone(),
+                key: key.iter().map(argument).collect(),
+                field: cfg.fields[*field].name.clone(),
+                value: argument(value),
+            })
+            .collect();
+        tests.push(TestSchedule {
+            hops: [edge.source, edge.target].map(|node| hop_label(cfg, &sc_graph.nodes[node])),
+            calls,
+            schedule: schedule.to_string(),
+            records,
+        });
+    }
+    tests
+}
+
+/// Whether a cycle steps between the two hops.
+fn passes(cycle: &[HopId], [a, b]: [HopId; 2]) -> bool {
+    (0..cycle.len()).any(|index| {
+        let next = cycle[(index + 1) % cycle.len()];
+        (cycle[index], next) == (a, b) || (cycle[index], next) == (b, a)
+    })
+}
+
+/// Whether a cycle passes the hops of each of its functions in one stretch,
+/// so a single run of each realizes it.
+fn runs_once(sc_graph: &SCGraph, cycle: &[HopId]) -> bool {
+    let function = |hop: HopId| {
+        sc_graph
+            .get_sc_node_id(hop)
+            .map(|node| sc_graph.nodes[node].cfg_function_id)
+    };
+    let entered: Vec<_> = (0..cycle.len())
+        .filter(|&index| {
+            function(cycle[index]) != function(cycle[(index + cycle.len() - 1) % cycle.len()])
+        })
+        .filter_map(|index| function(cycle[index]))
+        .collect();
+    (0..entered.len()).all(|index| !entered[index + 1..].contains(&entered[index]))
+}
+
+/// A value as `--call` takes it: strings without quotes.
+fn argument(value: &Constant) -> String {
+    match value {
+        Constant::String(s) => s.clone(),
+        other => crate::pretty::format_constant(other),
+    }
+}
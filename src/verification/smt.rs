@@ -0,0 +1,1276 @@
+//! Checking hop pairs with an SMT solver, without Boogie.
+//!
+//! The paths symbolic execution finds through both orders of a pair of final
+//! hops are written out as an SMT-LIB2 script asserting that some start state
+//! takes a path in each order and ends them differently: in whether a hop
+//! aborts, how it ends or what it returns, in a variable later hops read, or
+//! in a field either order writes. Where both orders abort the same hops,
+//! only how they end is compared. The script of a C-edge's condition also
+//! asks whether a hop that commits a transaction which ensures properties
+//! leaves a record without them in either order, from records that have
+//! them. The script is piped to any solver that reads SMT-LIB2 on stdin,
+//! such as `z3 -in`. When it answers `unsat` the hops commute; when it
+//! answers `sat`, its model is a start state on which they do not.
+//!
+//! Like symbolic execution, the script starts the hops from any state rather
+//! than the states the hops before them leave, so it proves no more than
+//! Boogie does, and a model may be a state the earlier hops never produce.
+//! Integers are unbounded unless `IntEncoding` makes them bitvectors, which
+//! wrap on overflow and are compared and divided as signed. Floats are reals,
+//! and unbounded integer division and the case folding of `nocase`
+//! comparisons are uninterpreted functions, which agree with themselves but
+//! nothing more. Strings use the solver's theory of strings, or with
+//! `StringEncoding::Uninterpreted` a sort of their own, on which literals are
+//! distinct and the order is only known to be total.
+//!
+//! ```
+//! use FMitF_rs::verification::solver::{Encoding, IntEncoding, StringEncoding};
+//! use FMitF_rs::verification::symbolic::HopPair;
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; }
+//!     table U on A { primary string name; string status; }
+//!     void inc(int id) { hop on A { T[id: id].n = T[id: id].n + 1; } }
+//!     void set(int id) { hop on A { T[id: id].n = 0; } }
+//!     void open(string name) { hop on A { U[name: name].status = "open"; } }
+//!     void shut(string name) { hop on A { U[name: name].status = "shut"; } }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let (inc, set) = (cfg.root_functions[0], cfg.root_functions[1]);
+//! let pair = HopPair {
+//!     function_a: inc,
+//!     hop_a: cfg.functions[inc].hop_order[0],
+//!     function_b: set,
+//!     hop_b: cfg.functions[set].hop_order[0],
+//! };
+//!
+//! let script = pair.smt_script(&cfg).unwrap();
+//! assert!(script.contains("(declare-const |inc.id@A| Int)"));
+//! assert!(script.contains("(declare-fun |T.n| (Int) Int)"));
+//! assert!(script.ends_with("(check-sat)\n"));
+//!
+//! // As 32-bit bitvectors, the increment may wrap
+//! let ints = Encoding { ints: IntEncoding::Bv32, ..Encoding::default() };
+//! let script = pair.smt_script_assuming(&cfg, &[], ints).unwrap();
+//! assert!(script.contains("(declare-fun |T.n| ((_ BitVec 32)) (_ BitVec 32))"));
+//! assert!(script.contains("(bvadd (|T.n| |inc.id@A|) (_ bv1 32))"));
+//!
+//! // Uninterpreted strings are a sort of their own
+//! let (open, shut) = (cfg.root_functions[2], cfg.root_functions[3]);
+//! let pair = HopPair {
+//!     function_a: open,
+//!     hop_a: cfg.functions[open].hop_order[0],
+//!     function_b: shut,
+//!     hop_b: cfg.functions[shut].hop_order[0],
+//! };
+//! let strings = Encoding { strings: StringEncoding::Uninterpreted, ..Encoding::default() };
+//! let script = pair.smt_script_assuming(&cfg, &[], strings).unwrap();
+//! assert!(script.contains("(declare-sort Str 0)"));
+//! assert!(script.contains("(declare-const |open.name@A| Str)"));
+//! ```
+
+use crate::ast::TypeName;
+use crate::cfg::{BinaryOp, CfgProgram, Constant, FieldId, TableId, UnaryOp, VarId};
+use crate::verification::code_generation::{string_literal, string_literal_name};
+use crate::verification::invariants::{self, Invariant};
+use crate::verification::postconditions::{broken_on, ensured};
+use crate::verification::solver::{
+    limit_memory, Encoding, SolverAnswer, SolverLimits, StringEncoding,
+};
+use crate::verification::symbolic::{
+    collect_initial, read, ConflictWitness, HopEnd, HopPair, Side, SymExpr,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Pairs of paths, one from each order, a script may compare
+const MAX_PATH_PAIRS: usize = 4096;
+
+/// The option every script sets, which a batch sets once for all
+const PRODUCE_MODELS: &str = "(set-option :produce-models true)\n";
+
+/// Uninterpreted strings are ordered by some strict total order, as
+/// `STRING_PRELUDE` orders them for Boogie
+const STRING_ORDER: &str = "(declare-fun str_lt (Str Str) Bool)
+(assert (forall ((a Str)) (not (str_lt a a))))
+(assert (forall ((a Str) (b Str) (c Str)) (=> (and (str_lt a b) (str_lt b c)) (str_lt a c))))
+(assert (forall ((a Str) (b Str)) (or (= a b) (str_lt a b) (str_lt b a))))
+";
+
+/// A solver run as a command that reads SMT-LIB2 on stdin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmtSolver {
+    /// The program and its arguments
+    pub command: Vec<String>,
+    /// What the solver may spend on each script
+    pub limits: SolverLimits,
+}
+
+impl SmtSolver {
+    /// A solver from a command line such as `z3 -in`, split at whitespace.
+    pub fn from_command(command: &str) -> Result<Self, String> {
+        let command: Vec<String> = command.split_whitespace().map(String::from).collect();
+        if command.is_empty() {
+            return Err("The SMT solver command is empty".to_string());
+        }
+        Ok(Self {
+            command,
+            limits: SolverLimits::default(),
+        })
+    }
+
+    /// Sends the script to a fresh solver process, then asks for a model if
+    /// it answers `sat`. The seed is set with the standard `:random-seed`
+    /// option.
+    pub fn solve(&self, script: &str) -> SolverAnswer {
+        let mut command = Command::new(&self.command[0]);
+        command
+            .args(&self.command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        limit_memory(&mut command, self.limits.memory);
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                return SolverAnswer::Unknown(format!(
+                    "Failed to run SMT solver '{}': {}",
+                    self.command[0], e
+                ))
+            }
+        };
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        // The answer is read on another thread, so a timeout can stop waiting
+        let (sender, receiver) = mpsc::channel();
+        let reader = thread::spawn(move || {
+            let mut stdout = BufReader::new(stdout);
+            let mut answer = String::new();
+            let read = stdout.read_line(&mut answer).map(|_| answer);
+            let _ = sender.send(read);
+            let mut rest = String::new();
+            let _ = stdout.read_to_string(&mut rest);
+            rest
+        });
+
+        let seed = match self.limits.seed {
+            Some(seed) => format!("(set-option :random-seed {})\n", seed),
+            None => String::new(),
+        };
+        if let Err(e) = stdin
+            .write_all(seed.as_bytes())
+            .and_then(|_| stdin.write_all(script.as_bytes()))
+            .and_then(|_| stdin.flush())
+        {
+            let _ = child.kill();
+            let _ = child.wait();
+            return SolverAnswer::Unknown(format!(
+                "Failed to send the script to the solver: {}",
+                e
+            ));
+        }
+        let answer = match self.limits.timeout {
+            Some(seconds) => receiver
+                .recv_timeout(Duration::from_secs(seconds.into()))
+                .map_err(|_| format!("The solver timed out after {} seconds", seconds)),
+            None => receiver
+                .recv()
+                .map_err(|_| "The solver stopped without answering".to_string()),
+        };
+        let answer = match answer {
+            Ok(Ok(answer)) => answer.trim().to_string(),
+            Ok(Err(e)) => format!("Failed to read the solver's answer: {}", e),
+            Err(e) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return SolverAnswer::Unknown(e);
+            }
+        };
+
+        let followup = if answer == "sat" {
+            "(get-model)\n(exit)\n"
+        } else {
+            "(exit)\n"
+        };
+        let _ = stdin.write_all(followup.as_bytes());
+        drop(stdin);
+        let rest = reader.join().unwrap_or_default();
+        let mut stderr = String::new();
+        if let Some(mut pipe) = child.stderr.take() {
+            let _ = pipe.read_to_string(&mut stderr);
+        }
+        let _ = child.wait();
+
+        match answer.as_str() {
+            "unsat" => SolverAnswer::Unsat,
+            "sat" => SolverAnswer::Sat(parse_model(&rest)),
+            "" => SolverAnswer::Unknown(format!(
+                "The solver gave no answer{}",
+                if stderr.trim().is_empty() {
+                    String::new()
+                } else {
+                    format!(": {}", stderr.trim())
+                }
+            )),
+            _ => SolverAnswer::Unknown(answer),
+        }
+    }
+
+    /// Sends the script to a fresh solver process that keeps proofs, and
+    /// returns its proof if it answers `unsat` and has one, as it prints it.
+    pub fn proof(&self, script: &str) -> Option<String> {
+        let mut command = Command::new(&self.command[0]);
+        command
+            .args(&self.command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        limit_memory(&mut command, self.limits.memory);
+        let mut child = command.spawn().ok()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut output = String::new();
+            let _ = stdout.read_to_string(&mut output);
+            let _ = sender.send(output);
+        });
+        let seed = match self.limits.seed {
+            Some(seed) => format!("(set-option :random-seed {})\n", seed),
+            None => String::new(),
+        };
+        let written = stdin.write_all(
+            format!(
+                "(set-option :produce-proofs true)\n{}{}(get-proof)\n(exit)\n",
+                seed, script
+            )
+            .as_bytes(),
+        );
+        drop(stdin);
+        let output = match self.limits.timeout {
+            Some(seconds) => receiver
+                .recv_timeout(Duration::from_secs(seconds.into()))
+                .ok(),
+            None => receiver.recv().ok(),
+        };
+        if output.is_none() {
+            let _ = child.kill();
+        }
+        let _ = child.wait();
+        written.ok()?;
+        let output = output?;
+        let (answer, proof) = output.trim().split_once('\n')?;
+        let proof = proof.trim();
+        (answer.trim() == "unsat" && !proof.starts_with("(error")).then(|| proof.to_string())
+    }
+}
+
+impl SmtSolver {
+    /// Sends several scripts to one solver process, each in a scope of its
+    /// own, and answers each, asking for a model after `sat`. Each script
+    /// has the time limit; a solver that runs out of it is stopped, and the
+    /// scripts after it go to a fresh one.
+    pub fn solve_all(&self, scripts: &[&str]) -> Vec<SolverAnswer> {
+        let mut answers = Vec::new();
+        while answers.len() < scripts.len() {
+            let rest = &scripts[answers.len()..];
+            if let Err(e) = self.solve_some(rest, &mut answers) {
+                // The script being solved when the solver failed
+                answers.push(SolverAnswer::Unknown(e));
+            }
+        }
+        answers
+    }
+
+    /// Solves scripts in one process until they are done or the solver
+    /// fails, pushing each answer, and returns why it failed.
+    fn solve_some(&self, scripts: &[&str], answers: &mut Vec<SolverAnswer>) -> Result<(), String> {
+        let mut process = SolverProcess::spawn(self)?;
+        for script in scripts {
+            answers.push(process.answer(script)?);
+        }
+        Ok(())
+    }
+}
+
+/// A solver process that answers scripts one after another, each in a scope
+/// of its own, with models on and the seed set once.
+#[derive(Debug)]
+pub(crate) struct SolverProcess {
+    child: Child,
+    /// Taken to close it, when the solver is done with
+    stdin: Option<ChildStdin>,
+    /// The lines the solver prints, read on another thread, so a timeout can
+    /// stop waiting
+    lines: mpsc::Receiver<String>,
+    timeout: Option<u32>,
+    /// Whether the last script was answered, so the solver waits for more
+    ready: bool,
+}
+
+impl SolverProcess {
+    /// Starts the solver.
+    pub(crate) fn spawn(solver: &SmtSolver) -> Result<Self, String> {
+        let mut command = Command::new(&solver.command[0]);
+        command
+            .args(&solver.command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        limit_memory(&mut command, solver.limits.memory);
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to run SMT solver '{}': {}", solver.command[0], e))?;
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                if line.is_err() || sender.send(line.unwrap_or_default()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut process = Self {
+            child,
+            stdin,
+            lines,
+            timeout: solver.limits.timeout,
+            ready: false,
+        };
+        let seed = match solver.limits.seed {
+            Some(seed) => format!("(set-option :random-seed {})\n", seed),
+            None => String::new(),
+        };
+        process.send(&format!("{}{}", PRODUCE_MODELS, seed))?;
+        process.ready = true;
+        Ok(process)
+    }
+
+    fn send(&mut self, text: &str) -> Result<(), String> {
+        let stdin = self.stdin.as_mut().expect("stdin is piped");
+        stdin
+            .write_all(text.as_bytes())
+            .and_then(|_| stdin.flush())
+            .map_err(|e| format!("Failed to send the script to the solver: {}", e))
+    }
+
+    fn next_line(&self) -> Result<String, String> {
+        match self.timeout {
+            Some(seconds) => self
+                .lines
+                .recv_timeout(Duration::from_secs(seconds.into()))
+                .map_err(|_| format!("The solver timed out after {} seconds", seconds)),
+            None => self
+                .lines
+                .recv()
+                .map_err(|_| "The solver stopped without answering".to_string()),
+        }
+    }
+
+    /// Answers a script, asking for a model after `sat`, or says why the
+    /// solver failed, after which it answers no more.
+    pub(crate) fn answer(&mut self, script: &str) -> Result<SolverAnswer, String> {
+        if !self.ready {
+            return Err("The solver failed on an earlier script".to_string());
+        }
+        self.ready = false;
+        self.send(&format!(
+            "(push 1)\n{}",
+            script.replacen(PRODUCE_MODELS, "", 1)
+        ))?;
+        // Errors in the script come before the answer to `check-sat`
+        let mut errors = Vec::new();
+        let answer = loop {
+            let line = self.next_line()?;
+            match line.trim() {
+                "" => {}
+                line if line.starts_with("(error") => errors.push(line.to_string()),
+                line => break line.to_string(),
+            }
+        };
+        let answer = match answer.as_str() {
+            _ if !errors.is_empty() => {
+                SolverAnswer::Unknown(format!("{} {}", errors.join(" "), answer))
+            }
+            "unsat" => SolverAnswer::Unsat,
+            "sat" => {
+                // The model ends where its parentheses balance
+                self.send("(get-model)\n")?;
+                let mut model = String::new();
+                let mut depth = 0;
+                while depth > 0 || !model.contains('(') {
+                    let line = self.next_line()?;
+                    depth += line.matches('(').count();
+                    depth = depth.saturating_sub(line.matches(')').count());
+                    model.push_str(&line);
+                    model.push('\n');
+                }
+                SolverAnswer::Sat(parse_model(&model))
+            }
+            _ => SolverAnswer::Unknown(answer),
+        };
+        self.send("(pop 1)\n")?;
+        self.ready = true;
+        Ok(answer)
+    }
+}
+
+impl Drop for SolverProcess {
+    /// Lets a solver waiting for scripts exit, and stops one that is not.
+    fn drop(&mut self) {
+        if self.ready {
+            let _ = self.send("(exit)\n");
+        } else {
+            let _ = self.child.kill();
+        }
+        self.stdin.take();
+        let _ = self.child.wait();
+    }
+}
+
+/// The scripts of several C-edges as one, each in a scope of its own, as
+/// `SmtSolver::solve_all` sends them.
+pub fn batch_script(scripts: &[&str]) -> String {
+    let mut batch = PRODUCE_MODELS.to_string();
+    for script in scripts {
+        batch.push_str("(push 1)\n");
+        batch.push_str(&script.replacen(PRODUCE_MODELS, "", 1));
+        batch.push_str("(pop 1)\n");
+    }
+    batch
+}
+
+/// An S-expression, as solvers print them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Sexp {
+    Atom(String),
+    List(Vec<Sexp>),
+}
+
+impl Sexp {
+    /// Parses the S-expressions in `text`, or as many as are complete.
+    fn parse_all(text: &str) -> Vec<Sexp> {
+        let mut stack: Vec<Vec<Sexp>> = vec![Vec::new()];
+        let mut chars = text.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '(' => stack.push(Vec::new()),
+                ')' if stack.len() > 1 => {
+                    let list = stack.pop().unwrap_or_default();
+                    if let Some(parent) = stack.last_mut() {
+                        parent.push(Sexp::List(list));
+                    }
+                }
+                ';' => while chars.next_if(|&ch| ch != '\n').is_some() {},
+                ch if ch.is_whitespace() || ch == ')' => {}
+                _ => {
+                    let mut atom = String::from(ch);
+                    let close = match ch {
+                        '|' => Some('|'),
+                        '"' => Some('"'),
+                        _ => None,
+                    };
+                    match close {
+                        Some(close) => {
+                            while let Some(next) = chars.next() {
+                                atom.push(next);
+                                if next == close {
+                                    // A doubled quote stands for one inside a string
+                                    match chars.next_if(|&ch| close == '"' && ch == '"') {
+                                        Some(quote) => atom.push(quote),
+                                        None => break,
+                                    }
+                                }
+                            }
+                        }
+                        None => {
+                            while let Some(next) =
+                                chars.next_if(|&ch| !ch.is_whitespace() && ch != '(' && ch != ')')
+                            {
+                                atom.push(next);
+                            }
+                        }
+                    }
+                    if let Some(list) = stack.last_mut() {
+                        list.push(Sexp::Atom(atom));
+                    }
+                }
+            }
+        }
+        stack.swap_remove(0)
+    }
+
+    fn format(&self) -> String {
+        match self {
+            Sexp::Atom(atom) => atom.clone(),
+            Sexp::List(items) => {
+                let items: Vec<String> = items.iter().map(Sexp::format).collect();
+                format!("({})", items.join(" "))
+            }
+        }
+    }
+}
+
+/// The values of the constants in a model as `(get-model)` prints it, by
+/// symbol. Functions, like record fields, are left out.
+fn parse_model(text: &str) -> Vec<(String, String)> {
+    let mut values = Vec::new();
+    for sexp in Sexp::parse_all(text) {
+        let Sexp::List(definitions) = sexp else {
+            continue;
+        };
+        for definition in definitions {
+            if let Sexp::List(parts) = definition {
+                if let [Sexp::Atom(define), Sexp::Atom(name), Sexp::List(args), _, value] =
+                    parts.as_slice()
+                {
+                    if define == "define-fun" && args.is_empty() {
+                        values.push((name.trim_matches('|').to_string(), value.format()));
+                    }
+                }
+            }
+        }
+    }
+    values
+}
+
+/// Formats a model as the values it gives each constant.
+pub fn format_model(values: &[(String, String)]) -> String {
+    values
+        .iter()
+        .map(|(name, value)| {
+            // Only integers are encoded as bitvectors
+            let value = match Sexp::parse_all(value).pop().as_ref().and_then(bitvector) {
+                Some(number) => number.to_string(),
+                None => value.clone(),
+            };
+            format!("{} = {}", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl HopPair {
+    /// An SMT-LIB2 script that is satisfiable exactly when some start state
+    /// ends the two orders of the hops differently, or `None` when the hops
+    /// have too many paths to compare.
+    pub fn smt_script(&self, cfg: &CfgProgram) -> Option<String> {
+        self.smt_script_assuming(cfg, &[], Encoding::default())
+    }
+
+    /// `smt_script`, assuming the invariants of every record the hops read,
+    /// with values encoded as `encoding` says.
+    pub fn smt_script_assuming(
+        &self,
+        cfg: &CfgProgram,
+        invariants: &[Invariant],
+        encoding: Encoding,
+    ) -> Option<String> {
+        let differ = invariants::assume(invariants, self.differ(cfg)?);
+        Some(self.query_script(cfg, &self.question(cfg), &differ, encoding))
+    }
+
+    /// `smt_script_assuming`, also satisfiable when a hop that commits its
+    /// transaction, in either order, leaves a record of a table it ensures a
+    /// property of breaking it. Every record starts with the property.
+    pub fn smt_script_ensuring(
+        &self,
+        cfg: &CfgProgram,
+        invariants: &[Invariant],
+        encoding: Encoding,
+    ) -> Option<String> {
+        let mut assumed = invariants.to_vec();
+        let mut formula = self.differ(cfg)?;
+        for (side, other) in [(Side::A, Side::B), (Side::B, Side::A)] {
+            let (function, hop) = self.hop(side);
+            let property = ensured(cfg, function, hop);
+            if property.is_empty() {
+                continue;
+            }
+            // The hop commits before the other's hop runs, or after it
+            for order in [
+                vec![(side, hop)],
+                vec![(other, self.hop(other).1), (side, hop)],
+            ] {
+                let paths = self.explore_order(cfg, &order)?;
+                let broken = broken_on(&paths, side, &property, None);
+                formula = SymExpr::binary(BinaryOp::Or, formula, broken);
+            }
+            assumed.extend(property);
+        }
+        let question = format!(
+            "{} Or does a hop break what its transaction ensures?",
+            self.question(cfg)
+        );
+        let formula = invariants::assume(&assumed, formula);
+        Some(self.query_script(cfg, &question, &formula, encoding))
+    }
+
+    fn question(&self, cfg: &CfgProgram) -> String {
+        format!(
+            "Do {}:{} and {}:{} end differently in some order?",
+            cfg.functions[self.function_a].name,
+            self.hop_a.index(),
+            cfg.functions[self.function_b].name,
+            self.hop_b.index()
+        )
+    }
+
+    /// The start states on which the two orders end differently, as a
+    /// condition; `None` when the hops have too many paths to compare.
+    fn differ(&self, cfg: &CfgProgram) -> Option<SymExpr> {
+        let ab = self.explore(cfg, Side::A)?;
+        let ba = self.explore(cfg, Side::B)?;
+        if ab.len().saturating_mul(ba.len()) > MAX_PATH_PAIRS {
+            return None;
+        }
+        let observed = self.observed_vars(cfg);
+        let mut observed: Vec<_> = observed.into_iter().collect();
+        observed.sort();
+
+        // Orders that abort the same hops leave nothing to compare
+        let differ = ab
+            .iter()
+            .flat_map(|path| ba.iter().map(move |other| (path, other)))
+            .filter(|(path, other)| path.commits() || path.aborts() != other.aborts())
+            .map(|(path, other)| {
+                let difference = [(&path.end_a, &other.end_a), (&path.end_b, &other.end_b)]
+                    .into_iter()
+                    .map(|(end, other_end)| ends_differ(end, other_end))
+                    .chain(observed.iter().map(|&(side, var)| {
+                        SymExpr::binary(BinaryOp::Neq, path.var(side, var), other.var(side, var))
+                    }))
+                    .chain(path.writes.iter().chain(&other.writes).map(|write| {
+                        SymExpr::binary(
+                            BinaryOp::Neq,
+                            read(&path.writes, write.table, write.field, &write.key),
+                            read(&other.writes, write.table, write.field, &write.key),
+                        )
+                    }))
+                    .fold(SymExpr::Const(Constant::Bool(false)), |any, differs| {
+                        SymExpr::binary(BinaryOp::Or, any, differs)
+                    });
+                path.condition
+                    .iter()
+                    .chain(&other.condition)
+                    .cloned()
+                    .fold(difference, |all, condition| {
+                        SymExpr::binary(BinaryOp::And, condition, all)
+                    })
+            })
+            .fold(SymExpr::Const(Constant::Bool(false)), |any, pair| {
+                SymExpr::binary(BinaryOp::Or, any, pair)
+            });
+        Some(differ)
+    }
+
+    /// A script asserting `formula`, an expression over the hops' start
+    /// state, under a comment asking `question`.
+    pub(super) fn query_script(
+        &self,
+        cfg: &CfgProgram,
+        question: &str,
+        formula: &SymExpr,
+        encoding: Encoding,
+    ) -> String {
+        ScriptWriter {
+            pair: self,
+            cfg,
+            encoding,
+        }
+        .script(question, formula)
+    }
+}
+
+impl HopPair {
+    /// The start state a model of `smt_script` gives: the values of the
+    /// hops' variables and of the records they read. Values the model leaves
+    /// out are left out.
+    pub fn witness_from_model(
+        &self,
+        cfg: &CfgProgram,
+        model: &[(String, String)],
+    ) -> ConflictWitness {
+        // Uninterpreted strings equal to a literal are shown as the literal
+        let literals: BTreeMap<&str, String> = model
+            .iter()
+            .filter_map(|(name, text)| Some((text.as_str(), string_literal(name)?)))
+            .collect();
+        let parse_value = |text: &str, ty: &TypeName| match literals.get(text) {
+            Some(literal) if *ty == TypeName::String => Some(Constant::String(literal.clone())),
+            _ => parse_value(text, ty),
+        };
+        let mut inputs = Vec::new();
+        // The record reads by index, each with its value and key parts
+        let mut reads = BTreeMap::new();
+        for (name, text) in model {
+            if let Some((read, key)) = name.split_once('#') {
+                // A record read, `T.n#0`, or a part of its key, `T.n#0.id`
+                let (index, part) = key.split_once('.').unwrap_or((key, ""));
+                let Ok(index) = index.parse::<usize>() else {
+                    continue;
+                };
+                let (_, parts) = reads.entry(index).or_insert((read, Vec::new()));
+                parts.push((part, text));
+            } else if let Some((var, side)) = name.rsplit_once('@') {
+                let (side, function) = match side {
+                    "A" => (Side::A, self.function_a),
+                    "B" => (Side::B, self.function_b),
+                    _ => continue,
+                };
+                let func = &cfg.functions[function];
+                let Some(var) = var.strip_prefix(&format!("{}.", func.name)) else {
+                    continue;
+                };
+                let Some((var_id, info)) = func.variables.iter().find(|(_, v)| v.name == var)
+                else {
+                    continue;
+                };
+                if let Some(value) = parse_value(text, &info.ty) {
+                    inputs.push((side, var_id, value));
+                }
+            }
+        }
+        inputs.sort_by_key(|&(side, var, _)| (side, var));
+
+        let mut initial: Vec<(TableId, FieldId, Vec<Constant>, Constant)> = Vec::new();
+        for (read, parts) in reads.into_values() {
+            let Some((table, field)) = read.split_once('.') else {
+                continue;
+            };
+            let Some((table_id, info)) = cfg.tables.iter().find(|(_, t)| t.name == table) else {
+                continue;
+            };
+            let Some(&field_id) = info.fields.iter().find(|&&f| cfg.fields[f].name == field) else {
+                continue;
+            };
+            let part = |name: &str, field: FieldId| {
+                let (_, text) = parts.iter().find(|(part, _)| *part == name)?;
+                parse_value(text, &cfg.fields[field].ty)
+            };
+            // Reads whose key or value the model leaves out are left out
+            let key: Option<Vec<Constant>> = info
+                .primary_keys
+                .iter()
+                .map(|&pk| part(&cfg.fields[pk].name, pk))
+                .collect();
+            if let (Some(key), Some(value)) = (key, part("", field_id)) {
+                if !initial
+                    .iter()
+                    .any(|(t, f, k, _)| (*t, *f, k) == (table_id, field_id, &key))
+                {
+                    initial.push((table_id, field_id, key, value));
+                }
+            }
+        }
+        ConflictWitness { inputs, initial }
+    }
+}
+
+/// A value as a model prints it, of the given type.
+fn parse_value(text: &str, ty: &TypeName) -> Option<Constant> {
+    fn number(sexp: &Sexp) -> Option<f64> {
+        match sexp {
+            Sexp::Atom(atom) => atom.parse().ok(),
+            Sexp::List(items) => match items.as_slice() {
+                [Sexp::Atom(op), operand] if op == "-" => Some(-number(operand)?),
+                [Sexp::Atom(op), left, right] if op == "/" => Some(number(left)? / number(right)?),
+                _ => None,
+            },
+        }
+    }
+    let sexp = Sexp::parse_all(text).pop()?;
+    match ty {
+        TypeName::Int => {
+            if let Some(value) = bitvector(&sexp) {
+                return Some(Constant::Int(value));
+            }
+            let value = number(&sexp)?;
+            (value.fract() == 0.0).then_some(Constant::Int(value as i64))
+        }
+        TypeName::Float => Some(Constant::Float(number(&sexp)?.into())),
+        TypeName::Bool => match &sexp {
+            Sexp::Atom(atom) => atom.parse().ok().map(Constant::Bool),
+            Sexp::List(_) => None,
+        },
+        TypeName::String => match &sexp {
+            Sexp::Atom(atom) if atom.len() >= 2 && atom.starts_with('"') => {
+                let inner = &atom[1..atom.len() - 1];
+                Some(Constant::String(unescape_string(
+                    &inner.replace("\"\"", "\""),
+                )))
+            }
+            // A value of the uninterpreted sort, such as `Str!val!0`
+            Sexp::Atom(atom) => Some(Constant::String(atom.clone())),
+            Sexp::List(_) => None,
+        },
+    }
+}
+
+/// A bitvector, `#x..`, `#b..` or `(_ bvN w)`, as the signed integer it
+/// encodes.
+fn bitvector(sexp: &Sexp) -> Option<i64> {
+    let (digits, radix, bits) = match sexp {
+        Sexp::Atom(atom) if atom.starts_with("#x") => (&atom[2..], 16, 4 * (atom.len() - 2)),
+        Sexp::Atom(atom) if atom.starts_with("#b") => (&atom[2..], 2, atom.len() - 2),
+        Sexp::List(items) => match items.as_slice() {
+            [Sexp::Atom(under), Sexp::Atom(value), Sexp::Atom(width)] if under == "_" => {
+                (value.strip_prefix("bv")?, 10, width.parse().ok()?)
+            }
+            _ => return None,
+        },
+        Sexp::Atom(_) => return None,
+    };
+    let value = u64::from_str_radix(digits, radix).ok()?;
+    // The top bit is the sign, extended by shifting it to the top and back
+    if !(1..=64).contains(&bits) {
+        return None;
+    }
+    let shift = 64 - bits;
+    Some(((value << shift) as i64) >> shift)
+}
+
+/// Undoes the `\u{..}` escapes of an SMT-LIB2 string literal.
+fn unescape_string(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("\\u{") {
+        result.push_str(&rest[..start]);
+        let escape = &rest[start + 3..];
+        match escape.find('}').and_then(|end| {
+            let ch = u32::from_str_radix(&escape[..end], 16)
+                .ok()
+                .and_then(char::from_u32)?;
+            Some((ch, end))
+        }) {
+            Some((ch, end)) => {
+                result.push(ch);
+                rest = &escape[end + 1..];
+            }
+            None => {
+                result.push_str(&rest[start..start + 3]);
+                rest = escape;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Whether two ways of ending a hop differ, as a condition.
+fn ends_differ(end: &HopEnd, other: &HopEnd) -> SymExpr {
+    match (end, other) {
+        (HopEnd::Return(Some(value)), HopEnd::Return(Some(other))) => {
+            SymExpr::binary(BinaryOp::Neq, value.clone(), other.clone())
+        }
+        _ => SymExpr::Const(Constant::Bool(end != other)),
+    }
+}
+
+/// Writes the symbolic values of a hop pair in SMT-LIB2.
+struct ScriptWriter<'a> {
+    pair: &'a HopPair,
+    cfg: &'a CfgProgram,
+    encoding: Encoding,
+}
+
+impl ScriptWriter<'_> {
+    /// A script asserting `differ`, declaring the unknowns it uses.
+    fn script(&self, question: &str, differ: &SymExpr) -> String {
+        let mut inputs = BTreeSet::new();
+        let mut fields = BTreeSet::new();
+        let mut folds = false;
+        collect(differ, &mut inputs, &mut fields, &mut folds);
+
+        let mut script = format!("; {}\n", question);
+        script.push_str(PRODUCE_MODELS);
+        if self.encoding.strings == StringEncoding::Uninterpreted {
+            self.declare_strings(differ, &mut script);
+        }
+        for (side, var) in inputs {
+            script.push_str(&format!(
+                "(declare-const {} {})\n",
+                self.var_name(side, var),
+                self.sort(&self.var_type(side, var))
+            ));
+        }
+        for (table, field) in fields {
+            let keys: Vec<String> = self.cfg.tables[table]
+                .primary_keys
+                .iter()
+                .map(|&pk| self.sort(&self.cfg.fields[pk].ty))
+                .collect();
+            script.push_str(&format!(
+                "(declare-fun {} ({}) {})\n",
+                self.field_name(table, field),
+                keys.join(" "),
+                self.sort(&self.cfg.fields[field].ty)
+            ));
+        }
+        if self.uses_int_division(differ) {
+            script.push_str("(declare-fun int_div (Int Int) Int)\n");
+        }
+        if folds {
+            script.push_str(&format!(
+                "(declare-fun str_fold ({0}) {0})\n",
+                self.sort(&TypeName::String)
+            ));
+        }
+        // Each record read is named, with its key, so that a model gives the
+        // contents the tables start with
+        let mut reads = Vec::new();
+        collect_initial(differ, &mut reads);
+        for (index, (table, field, key)) in reads.into_iter().enumerate() {
+            let read = format!(
+                "{}.{}#{}",
+                self.cfg.tables[table].name, self.cfg.fields[field].name, index
+            );
+            let value = SymExpr::Initial {
+                table,
+                field,
+                key: key.to_vec(),
+            };
+            let parts = self.cfg.tables[table].primary_keys.iter().zip(key);
+            for (name, ty, term) in
+                [(read.clone(), field, &value)]
+                    .into_iter()
+                    .chain(parts.map(|(&pk, part)| {
+                        (format!("{}.{}", read, self.cfg.fields[pk].name), pk, part)
+                    }))
+            {
+                let name = quote_symbol(&name);
+                script.push_str(&format!(
+                    "(declare-const {} {})\n(assert (= {} {}))\n",
+                    name,
+                    self.sort(&self.cfg.fields[ty].ty),
+                    name,
+                    self.expr(term)
+                ));
+            }
+        }
+        script.push_str(&format!("(assert {})\n", self.expr(differ)));
+        script.push_str("(check-sat)\n");
+        script
+    }
+
+    /// Declares the sort of uninterpreted strings, if a value uses strings,
+    /// with the literals as distinct constants and an order when strings are
+    /// compared by one.
+    fn declare_strings(&self, differ: &SymExpr, script: &mut String) {
+        let mut literals = BTreeSet::new();
+        let (mut strings, mut ordered) = (false, false);
+        differ.visit(&mut |expr| {
+            strings |= self.type_of(expr) == TypeName::String;
+            match expr {
+                SymExpr::Const(Constant::String(s)) => {
+                    literals.insert(s.clone());
+                }
+                SymExpr::Binary {
+                    op: BinaryOp::Lt | BinaryOp::Lte | BinaryOp::Gt | BinaryOp::Gte,
+                    left,
+                    ..
+                } => ordered |= self.type_of(left) == TypeName::String,
+                _ => {}
+            }
+        });
+        if !strings {
+            return;
+        }
+        script.push_str("(declare-sort Str 0)\n");
+        let names: Vec<String> = literals.iter().map(|s| string_literal_name(s)).collect();
+        for name in &names {
+            script.push_str(&format!("(declare-const {} Str)\n", name));
+        }
+        if names.len() > 1 {
+            script.push_str(&format!("(assert (distinct {}))\n", names.join(" ")));
+        }
+        if ordered {
+            script.push_str(STRING_ORDER);
+        }
+    }
+
+    fn var_type(&self, side: Side, var: VarId) -> TypeName {
+        let function = match side {
+            Side::A => self.pair.function_a,
+            Side::B => self.pair.function_b,
+        };
+        self.cfg.functions[function].variables[var].ty.clone()
+    }
+
+    /// A variable's symbol, marked with its side, since both hops may be of
+    /// the same function.
+    fn var_name(&self, side: Side, var: VarId) -> String {
+        let function = match side {
+            Side::A => self.pair.function_a,
+            Side::B => self.pair.function_b,
+        };
+        let func = &self.cfg.functions[function];
+        quote_symbol(&format!(
+            "{}.{}@{:?}",
+            func.name, func.variables[var].name, side
+        ))
+    }
+
+    fn field_name(&self, table: TableId, field: FieldId) -> String {
+        quote_symbol(&format!(
+            "{}.{}",
+            self.cfg.tables[table].name, self.cfg.fields[field].name
+        ))
+    }
+
+    /// The type of a value.
+    fn type_of(&self, expr: &SymExpr) -> TypeName {
+        match expr {
+            SymExpr::Const(c) => match c {
+                Constant::Int(_) => TypeName::Int,
+                Constant::Float(_) => TypeName::Float,
+                Constant::Bool(_) => TypeName::Bool,
+                Constant::String(_) => TypeName::String,
+            },
+            SymExpr::Input { side, var } => self.var_type(*side, *var),
+            SymExpr::Initial { field, .. } => self.cfg.fields[*field].ty.clone(),
+            SymExpr::Unary { op, operand } => match op {
+                UnaryOp::Not => TypeName::Bool,
+                UnaryOp::Neg => self.type_of(operand),
+                UnaryOp::FoldCase => TypeName::String,
+            },
+            SymExpr::Binary { op, left, .. } => match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => self.type_of(left),
+                _ => TypeName::Bool,
+            },
+            SymExpr::Ite { then, .. } => self.type_of(then),
+        }
+    }
+
+    fn uses_int_division(&self, expr: &SymExpr) -> bool {
+        match expr {
+            SymExpr::Const(_) | SymExpr::Input { .. } => false,
+            SymExpr::Initial { key, .. } => key.iter().any(|part| self.uses_int_division(part)),
+            SymExpr::Unary { operand, .. } => self.uses_int_division(operand),
+            SymExpr::Binary { op, left, right } => {
+                (*op == BinaryOp::Div
+                    && self.type_of(left) == TypeName::Int
+                    && self.encoding.ints.bits().is_none())
+                    || self.uses_int_division(left)
+                    || self.uses_int_division(right)
+            }
+            SymExpr::Ite {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.uses_int_division(condition)
+                    || self.uses_int_division(then)
+                    || self.uses_int_division(otherwise)
+            }
+        }
+    }
+
+    /// Whether a value is an integer encoded as a bitvector.
+    fn bitvector(&self, expr: &SymExpr) -> bool {
+        self.encoding.ints.bits().is_some() && self.type_of(expr) == TypeName::Int
+    }
+
+    fn sort(&self, ty: &TypeName) -> String {
+        match (ty, self.encoding.ints.bits()) {
+            (TypeName::Int, Some(bits)) => format!("(_ BitVec {})", bits),
+            (TypeName::String, _) if self.uninterpreted_strings() => "Str".to_string(),
+            (ty, _) => sort(ty).to_string(),
+        }
+    }
+
+    fn uninterpreted_strings(&self) -> bool {
+        self.encoding.strings == StringEncoding::Uninterpreted
+    }
+
+    /// A constant as an SMT-LIB2 term; integers too wide for bitvectors
+    /// wrap, as they would in the program.
+    fn constant(&self, c: &Constant) -> String {
+        match (c, self.encoding.ints.bits()) {
+            (Constant::Int(i), Some(bits)) => {
+                let value = (*i as u64) & (u64::MAX >> (64 - bits));
+                format!("(_ bv{} {})", value, bits)
+            }
+            (Constant::String(s), _) if self.uninterpreted_strings() => string_literal_name(s),
+            (c, _) => constant(c),
+        }
+    }
+
+    /// A value as an SMT-LIB2 term.
+    fn expr(&self, expr: &SymExpr) -> String {
+        match expr {
+            SymExpr::Const(c) => self.constant(c),
+            SymExpr::Input { side, var } => self.var_name(*side, *var),
+            SymExpr::Initial { table, field, key } => {
+                let name = self.field_name(*table, *field);
+                if key.is_empty() {
+                    name
+                } else {
+                    let key: Vec<String> = key.iter().map(|part| self.expr(part)).collect();
+                    format!("({} {})", name, key.join(" "))
+                }
+            }
+            SymExpr::Unary { op, operand } => {
+                let op = match op {
+                    UnaryOp::Not => "not",
+                    UnaryOp::Neg if self.bitvector(operand) => "bvneg",
+                    UnaryOp::Neg => "-",
+                    UnaryOp::FoldCase => "str_fold",
+                };
+                format!("({} {})", op, self.expr(operand))
+            }
+            SymExpr::Binary { op, left, right } => {
+                let (l, r) = (self.expr(left), self.expr(right));
+                let strings = self.type_of(left) == TypeName::String;
+                let uninterpreted = strings && self.uninterpreted_strings();
+                let bitvectors = self.bitvector(left);
+                match op {
+                    BinaryOp::Neq => format!("(not (= {} {}))", l, r),
+                    BinaryOp::Lt if uninterpreted => format!("(str_lt {} {})", l, r),
+                    BinaryOp::Lte if uninterpreted => format!("(not (str_lt {} {}))", r, l),
+                    BinaryOp::Gt if uninterpreted => format!("(str_lt {} {})", r, l),
+                    BinaryOp::Gte if uninterpreted => format!("(not (str_lt {} {}))", l, r),
+                    BinaryOp::Lt if strings => format!("(str.< {} {})", l, r),
+                    BinaryOp::Lte if strings => format!("(str.<= {} {})", l, r),
+                    BinaryOp::Gt if strings => format!("(str.< {} {})", r, l),
+                    BinaryOp::Gte if strings => format!("(str.<= {} {})", r, l),
+                    // Bitvectors are signed, so compare and divide as such
+                    BinaryOp::Add if bitvectors => format!("(bvadd {} {})", l, r),
+                    BinaryOp::Sub if bitvectors => format!("(bvsub {} {})", l, r),
+                    BinaryOp::Mul if bitvectors => format!("(bvmul {} {})", l, r),
+                    BinaryOp::Div if bitvectors => format!("(bvsdiv {} {})", l, r),
+                    BinaryOp::Lt if bitvectors => format!("(bvslt {} {})", l, r),
+                    BinaryOp::Lte if bitvectors => format!("(bvsle {} {})", l, r),
+                    BinaryOp::Gt if bitvectors => format!("(bvsgt {} {})", l, r),
+                    BinaryOp::Gte if bitvectors => format!("(bvsge {} {})", l, r),
+                    BinaryOp::Div if self.type_of(left) == TypeName::Int => {
+                        format!("(int_div {} {})", l, r)
+                    }
+                    _ => {
+                        let op = match op {
+                            BinaryOp::Add => "+",
+                            BinaryOp::Sub => "-",
+                            BinaryOp::Mul => "*",
+                            BinaryOp::Div => "/",
+                            BinaryOp::Lt => "<",
+                            BinaryOp::Lte => "<=",
+                            BinaryOp::Gt => ">",
+                            BinaryOp::Gte => ">=",
+                            BinaryOp::Eq | BinaryOp::Neq => "=",
+                            BinaryOp::And => "and",
+                            BinaryOp::Or => "or",
+                        };
+                        format!("({} {} {})", op, l, r)
+                    }
+                }
+            }
+            SymExpr::Ite {
+                condition,
+                then,
+                otherwise,
+            } => format!(
+                "(ite {} {} {})",
+                self.expr(condition),
+                self.expr(then),
+                self.expr(otherwise)
+            ),
+        }
+    }
+}
+
+/// Adds the variables, record fields and case folds a value uses.
+fn collect(
+    expr: &SymExpr,
+    inputs: &mut BTreeSet<(Side, VarId)>,
+    fields: &mut BTreeSet<(TableId, FieldId)>,
+    folds: &mut bool,
+) {
+    match expr {
+        SymExpr::Const(_) => {}
+        SymExpr::Input { side, var } => {
+            inputs.insert((*side, *var));
+        }
+        SymExpr::Initial { table, field, key } => {
+            fields.insert((*table, *field));
+            key.iter()
+                .for_each(|part| collect(part, inputs, fields, folds));
+        }
+        SymExpr::Unary { op, operand } => {
+            *folds |= *op == UnaryOp::FoldCase;
+            collect(operand, inputs, fields, folds);
+        }
+        SymExpr::Binary { left, right, .. } => {
+            collect(left, inputs, fields, folds);
+            collect(right, inputs, fields, folds);
+        }
+        SymExpr::Ite {
+            condition,
+            then,
+            otherwise,
+        } => {
+            collect(condition, inputs, fields, folds);
+            collect(then, inputs, fields, folds);
+            collect(otherwise, inputs, fields, folds);
+        }
+    }
+}
+
+fn sort(ty: &TypeName) -> &'static str {
+    match ty {
+        TypeName::Int => "Int",
+        TypeName::Float => "Real",
+        TypeName::Bool => "Bool",
+        TypeName::String => "String",
+    }
+}
+
+fn constant(c: &Constant) -> String {
+    match c {
+        Constant::Int(i) if *i < 0 => format!("(- {})", i.unsigned_abs()),
+        Constant::Int(i) => i.to_string(),
+        Constant::Float(f) => {
+            let magnitude = f.0.abs().to_string();
+            let magnitude = if magnitude.contains('.') {
+                magnitude
+            } else {
+                format!("{}.0", magnitude)
+            };
+            if f.0 < 0.0 {
+                format!("(- {})", magnitude)
+            } else {
+                magnitude
+            }
+        }
+        Constant::Bool(b) => b.to_string(),
+        Constant::String(s) => {
+            let mut literal = String::from("\"");
+            for ch in s.chars() {
+                match ch {
+                    '"' => literal.push_str("\"\""),
+                    ' '..='~' if ch != '\\' => literal.push(ch),
+                    _ => literal.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+                }
+            }
+            literal.push('"');
+            literal
+        }
+    }
+}
+
+/// A symbol between bars, which may hold any character but a bar or backslash.
+fn quote_symbol(name: &str) -> String {
+    format!("|{}|", name.replace(['|', '\\'], "_"))
+}
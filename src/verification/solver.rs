@@ -0,0 +1,604 @@
+//! The provers verification conditions can be submitted to.
+//!
+//! A `SolverBackend` writes the verification condition of a C-edge in its own
+//! input language and answers whether the two orders of the edge's hops can
+//! end differently: `Unsat` when they cannot, `Sat` with the values of a start
+//! state on which they do, or `Unknown` with why it could not tell. Boogie
+//! checks the interleavings of the whole functions; the SMT backends check the
+//! final hops from any start state, as `HopPair::smt_script` writes them.
+//!
+//! Every backend holds each condition to the same `SolverLimits`: a prover
+//! still running when the time is up is stopped and the edge is left unknown.
+//!
+//! With `VcGranularity::Pair`, the conditions of the C-edges between two
+//! functions are submitted as one file, `f_g.bpl` or `f_g.smt2`, to one prover
+//! run: Boogie checks one procedure per edge and its messages are told apart
+//! by line, and an SMT solver checks one scope per edge.
+//!
+//! ```
+//! use FMitF_rs::verification::solver::{SmtBackend, SolverBackend, SolverKind, SolverLimits};
+//!
+//! let limits = SolverLimits {
+//!     timeout: Some(30),
+//!     memory: Some(2048),
+//!     seed: Some(7),
+//! };
+//! assert_eq!(SolverKind::default().backend(limits).name(), "Boogie");
+//! assert_eq!(SolverKind::Z3.backend(limits).name(), "Z3");
+//! let cvc5 = SmtBackend::cvc5(limits);
+//! assert_eq!(cvc5.name(), "CVC5");
+//! assert_eq!(cvc5.solver.command[0], "cvc5");
+//! assert_eq!(cvc5.solver.limits.seed, Some(7));
+//! ```
+
+use crate::cfg::CfgProgram;
+use crate::verification::boogie_file_manager::{BoogieFile, BoogieFileManager};
+use crate::verification::code_generation;
+use crate::verification::commutativity_check::VerificationUnit;
+use crate::verification::execution::{VerificationExecution, VerificationResult};
+use crate::verification::invariants::Invariant;
+use crate::verification::smt::{batch_script, SmtSolver};
+use crate::verification::symbolic::HopPair;
+use crate::verification::toolchain::{SolverPool, Toolchain};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The provers `--solver` can choose.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SolverKind {
+    #[default]
+    Boogie,
+    /// Z3, in this process when built with the `z3` feature, or else `z3 -in`
+    Z3,
+    Cvc5,
+}
+
+impl SolverKind {
+    /// The backend of this prover, held to the limits on each condition.
+    pub fn backend(self, limits: SolverLimits) -> Box<dyn SolverBackend> {
+        match self {
+            SolverKind::Boogie => Box::new(BoogieBackend::new(limits)),
+            #[cfg(feature = "z3")]
+            SolverKind::Z3 => Box::new(crate::verification::z3::InProcessZ3::new(limits)),
+            #[cfg(not(feature = "z3"))]
+            SolverKind::Z3 => Box::new(SmtBackend::z3(limits)),
+            SolverKind::Cvc5 => Box::new(SmtBackend::cvc5(limits)),
+        }
+    }
+
+    /// Like `backend`, but with the executables of Boogie and Z3 found, at
+    /// `boogie_path` or on `PATH`, and their versions checked, or why not.
+    pub fn discover(
+        self,
+        limits: SolverLimits,
+        boogie_path: Option<&Path>,
+    ) -> Result<Box<dyn SolverBackend>, String> {
+        Ok(match self {
+            SolverKind::Boogie => Box::new(BoogieBackend::with_toolchain(
+                Toolchain::discover(boogie_path)?,
+                limits,
+            )),
+            #[cfg(not(feature = "z3"))]
+            SolverKind::Z3 => {
+                let z3 = crate::verification::toolchain::find_z3()?.ok_or_else(|| {
+                    "Z3 was not found on PATH. Install it, build with --features z3 to link \
+                     libz3, or choose another prover with --solver"
+                        .to_string()
+                })?;
+                Box::new(SmtBackend::z3_at(&z3, limits))
+            }
+            _ => self.backend(limits),
+        })
+    }
+}
+
+/// How the verification conditions of C-edges are put into files.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VcGranularity {
+    /// A file per C-edge, each submitted on its own: as parallel as the jobs
+    /// allow, and each easy to debug
+    #[default]
+    Edge,
+    /// A file per pair of functions, with the conditions of all their
+    /// C-edges, so the prover starts once per pair
+    Pair,
+}
+
+/// How verification conditions encode the program's integers.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IntEncoding {
+    /// Unbounded mathematical integers, which provers reason about fastest
+    #[default]
+    Int,
+    /// 32-bit two's complement bitvectors, wrapping on overflow
+    Bv32,
+    /// 64-bit two's complement bitvectors, wrapping on overflow
+    Bv64,
+}
+
+impl IntEncoding {
+    /// The encoding as `--int-encoding` names it.
+    pub fn name(self) -> &'static str {
+        match self {
+            IntEncoding::Int => "int",
+            IntEncoding::Bv32 => "bv32",
+            IntEncoding::Bv64 => "bv64",
+        }
+    }
+
+    /// The width of the bitvectors integers are, if they are.
+    pub fn bits(self) -> Option<u32> {
+        match self {
+            IntEncoding::Int => None,
+            IntEncoding::Bv32 => Some(32),
+            IntEncoding::Bv64 => Some(64),
+        }
+    }
+
+    /// What a proof under this encoding does and does not cover.
+    pub fn soundness(self) -> &'static str {
+        match self {
+            IntEncoding::Int => "integers are unbounded, so runs that overflow are not covered",
+            IntEncoding::Bv32 => {
+                "integers wrap at 32 bits, so proofs hold only for 32-bit integers"
+            }
+            IntEncoding::Bv64 => {
+                "integers wrap at 64 bits, so proofs hold only for 64-bit integers"
+            }
+        }
+    }
+}
+
+/// How verification conditions encode the program's strings.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StringEncoding {
+    /// The solver's theory of strings, which knows their contents and order
+    #[default]
+    Theory,
+    /// An uninterpreted sort, with distinct literals and an unknown total
+    /// order, which any prover handles
+    Uninterpreted,
+}
+
+impl StringEncoding {
+    /// The encoding as `--string-encoding` names it.
+    pub fn name(self) -> &'static str {
+        match self {
+            StringEncoding::Theory => "theory",
+            StringEncoding::Uninterpreted => "uninterpreted",
+        }
+    }
+
+    /// What a proof under this encoding does and does not cover.
+    pub fn soundness(self) -> &'static str {
+        match self {
+            StringEncoding::Theory => "strings are exact",
+            StringEncoding::Uninterpreted => {
+                "only equality and some order of strings are known, so edges may fail that commute"
+            }
+        }
+    }
+}
+
+/// How verification conditions encode the program's values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Encoding {
+    pub ints: IntEncoding,
+    pub strings: StringEncoding,
+}
+
+/// Wall-clock seconds a prover may spend on each condition unless set.
+pub const DEFAULT_TIMEOUT: u32 = 30;
+
+/// What a prover may spend on each verification condition, set on the
+/// command line or in the `[verification]` section of a config file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SolverLimits {
+    /// Wall-clock seconds, or no limit
+    pub timeout: Option<u32>,
+    /// Megabytes of memory, or no limit
+    pub memory: Option<u32>,
+    /// The seed of the prover's random choices, to repeat a run exactly
+    pub seed: Option<u32>,
+}
+
+/// What a prover answered about a verification condition.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SolverAnswer {
+    /// No start state ends the two orders differently
+    Unsat,
+    /// One does; the values the prover's model gives the condition's constants
+    Sat(Vec<(String, String)>),
+    /// The prover could not tell, timed out or failed, and why
+    Unknown(String),
+}
+
+/// A prover that verification conditions can be submitted to.
+pub trait SolverBackend: Send {
+    /// The prover's name, for messages.
+    fn name(&self) -> &'static str;
+
+    /// What tells this prover's answers apart from another's, for the cache.
+    fn fingerprint(&self) -> String {
+        self.name().to_string()
+    }
+
+    /// The prover's version, as it reports it, if it does.
+    fn version(&self) -> Option<String> {
+        None
+    }
+
+    /// The extension of the files `encode` writes.
+    fn extension(&self) -> &'static str {
+        "smt2"
+    }
+
+    /// The verification condition of a C-edge, assuming the invariants of
+    /// the records it reads and with values encoded as `encoding` says, as a
+    /// file named after the edge, or why it cannot be written.
+    fn encode(
+        &self,
+        unit: &VerificationUnit,
+        pair: &HopPair,
+        cfg: &CfgProgram,
+        invariants: &[Invariant],
+        encoding: Encoding,
+    ) -> Result<BoogieFile, String>;
+
+    /// Submits a verification condition `encode` wrote.
+    fn submit(&mut self, condition: &BoogieFile) -> SolverAnswer;
+
+    /// Submits the conditions `encode` wrote for several C-edges as one file,
+    /// named `name` with this prover's extension, and returns the file with
+    /// an answer for each condition.
+    fn submit_batch(
+        &mut self,
+        name: &str,
+        conditions: &[BoogieFile],
+    ) -> (BoogieFile, Vec<SolverAnswer>);
+
+    /// Checks an SMT-LIB2 script, such as a query of invariant inference.
+    fn solve_script(&mut self, script: &str) -> SolverAnswer;
+
+    /// The prover's proof that a condition `submit` answered `Unsat` holds,
+    /// if it writes proofs.
+    fn prove(&mut self, _condition: &BoogieFile) -> Option<String> {
+        None
+    }
+
+    /// Another prover like this one, for another thread to submit to.
+    fn fork(&self) -> Box<dyn SolverBackend>;
+}
+
+/// Boogie, run on a temporary file for each condition, with scripts going to
+/// a pool of the Z3 it proves with.
+#[derive(Debug)]
+pub struct BoogieBackend {
+    pub execution: VerificationExecution,
+    pub toolchain: Toolchain,
+    z3: Option<SolverPool>,
+}
+
+impl BoogieBackend {
+    /// Boogie, held to the limits on each file, as `PATH` resolves it.
+    pub fn new(limits: SolverLimits) -> Self {
+        Self::with_toolchain(Toolchain::default(), limits)
+    }
+
+    /// The Boogie and Z3 of a toolchain, held to the limits on each file.
+    pub fn with_toolchain(toolchain: Toolchain, limits: SolverLimits) -> Self {
+        let z3 = toolchain
+            .z3
+            .as_ref()
+            .and_then(|z3| SmtBackend::z3_at(z3, limits).pool);
+        Self {
+            execution: VerificationExecution {
+                boogie: toolchain.boogie.clone(),
+                limits,
+            },
+            toolchain,
+            z3,
+        }
+    }
+}
+
+impl SolverBackend for BoogieBackend {
+    fn name(&self) -> &'static str {
+        "Boogie"
+    }
+
+    fn version(&self) -> Option<String> {
+        self.toolchain
+            .boogie_version
+            .clone()
+            .or_else(|| first_line_of(Command::new(&self.toolchain.boogie).arg("/version")))
+    }
+
+    fn extension(&self) -> &'static str {
+        "bpl"
+    }
+
+    fn encode(
+        &self,
+        unit: &VerificationUnit,
+        _pair: &HopPair,
+        cfg: &CfgProgram,
+        invariants: &[Invariant],
+        encoding: Encoding,
+    ) -> Result<BoogieFile, String> {
+        Ok(BoogieFile {
+            filename: BoogieFileManager::generate_filename(unit, cfg),
+            code: code_generation::generate_boogie_assuming(unit, cfg, invariants, encoding),
+        })
+    }
+
+    /// Boogie reports a failed proof without a model, so its output is
+    /// passed on as unknown.
+    fn submit(&mut self, condition: &BoogieFile) -> SolverAnswer {
+        let path = match BoogieFileManager::write_temp_file(condition) {
+            Ok(path) => path,
+            Err(e) => return SolverAnswer::Unknown(e),
+        };
+        let result = self.execution.execute_boogie(&path);
+        BoogieFileManager::cleanup_files(&[path]);
+        match result {
+            VerificationResult::Success => SolverAnswer::Unsat,
+            VerificationResult::Failure(msg) | VerificationResult::Unknown(msg) => {
+                SolverAnswer::Unknown(msg)
+            }
+        }
+    }
+
+    /// Boogie runs once on the procedures of all conditions, and each answer
+    /// comes from the messages on the lines of its procedure.
+    fn submit_batch(
+        &mut self,
+        name: &str,
+        conditions: &[BoogieFile],
+    ) -> (BoogieFile, Vec<SolverAnswer>) {
+        let codes: Vec<&str> = conditions.iter().map(|c| c.code.as_str()).collect();
+        let (code, lines) = code_generation::batch_boogie(&codes);
+        let batch = BoogieFile {
+            filename: format!("{}.bpl", name),
+            code,
+        };
+        let path = match BoogieFileManager::write_temp_file(&batch) {
+            Ok(path) => path,
+            Err(e) => return (batch, vec![SolverAnswer::Unknown(e); conditions.len()]),
+        };
+        let result = self
+            .execution
+            .execute_boogie_procedures(&path, conditions.len() as u32);
+        BoogieFileManager::cleanup_files(&[path]);
+        let answers = match result {
+            VerificationResult::Success => vec![SolverAnswer::Unsat; conditions.len()],
+            VerificationResult::Failure(msg) | VerificationResult::Unknown(msg) => {
+                split_boogie_messages(&msg, &lines)
+            }
+        };
+        (batch, answers)
+    }
+
+    /// Boogie proves with Z3, so scripts go to Z3 directly.
+    fn solve_script(&mut self, script: &str) -> SolverAnswer {
+        match &self.z3 {
+            Some(pool) => pool.solve(script),
+            None => SolverAnswer::Unknown(
+                "Z3 was not found on PATH, and scripts such as inference queries go to it \
+                 when Boogie checks the C-edges"
+                    .to_string(),
+            ),
+        }
+    }
+
+    /// The fork shares the Z3 processes.
+    fn fork(&self) -> Box<dyn SolverBackend> {
+        Box::new(Self {
+            execution: VerificationExecution {
+                boogie: self.execution.boogie.clone(),
+                limits: self.execution.limits,
+            },
+            toolchain: self.toolchain.clone(),
+            z3: self.z3.clone(),
+        })
+    }
+}
+
+/// An SMT solver reading SMT-LIB2 on stdin.
+#[derive(Debug, Clone)]
+pub struct SmtBackend {
+    pub name: &'static str,
+    pub solver: SmtSolver,
+    /// Processes of the solver kept running between conditions, if it takes
+    /// one script after another
+    pub pool: Option<SolverPool>,
+}
+
+impl SmtBackend {
+    /// The `z3` executable.
+    pub fn z3(limits: SolverLimits) -> Self {
+        Self::z3_at(Path::new("z3"), limits)
+    }
+
+    /// Z3 at `path`, kept running between conditions.
+    pub fn z3_at(path: &Path, limits: SolverLimits) -> Self {
+        let mut backend = Self::with_command("Z3", &[&path.display().to_string(), "-in"], limits);
+        backend.pool = Some(SolverPool::new(backend.solver.clone()));
+        backend
+    }
+
+    /// The `cvc5` executable.
+    pub fn cvc5(limits: SolverLimits) -> Self {
+        Self::with_command("CVC5", &["cvc5", "--lang=smt2", "--incremental"], limits)
+    }
+
+    /// Any solver, run as `command`, such as `yices-smt2`.
+    pub fn custom(command: &str, limits: SolverLimits) -> Result<Self, String> {
+        let mut solver = SmtSolver::from_command(command)?;
+        solver.limits = limits;
+        Ok(Self {
+            name: "SMT solver",
+            solver,
+            pool: None,
+        })
+    }
+
+    fn with_command(name: &'static str, command: &[&str], limits: SolverLimits) -> Self {
+        Self {
+            name,
+            solver: SmtSolver {
+                command: command.iter().map(|part| part.to_string()).collect(),
+                limits,
+            },
+            pool: None,
+        }
+    }
+}
+
+impl SolverBackend for SmtBackend {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn fingerprint(&self) -> String {
+        format!("{} {}", self.name, self.solver.command.join(" "))
+    }
+
+    fn version(&self) -> Option<String> {
+        first_line_of(Command::new(&self.solver.command[0]).arg("--version"))
+    }
+
+    fn encode(
+        &self,
+        unit: &VerificationUnit,
+        pair: &HopPair,
+        cfg: &CfgProgram,
+        invariants: &[Invariant],
+        encoding: Encoding,
+    ) -> Result<BoogieFile, String> {
+        smt_condition(unit, pair, cfg, invariants, encoding)
+    }
+
+    fn submit(&mut self, condition: &BoogieFile) -> SolverAnswer {
+        self.solve_script(&condition.code)
+    }
+
+    fn submit_batch(
+        &mut self,
+        name: &str,
+        conditions: &[BoogieFile],
+    ) -> (BoogieFile, Vec<SolverAnswer>) {
+        let scripts: Vec<&str> = conditions.iter().map(|c| c.code.as_str()).collect();
+        let batch = BoogieFile {
+            filename: format!("{}.smt2", name),
+            code: batch_script(&scripts),
+        };
+        (batch, self.solver.solve_all(&scripts))
+    }
+
+    fn solve_script(&mut self, script: &str) -> SolverAnswer {
+        match &self.pool {
+            Some(pool) => pool.solve(script),
+            None => self.solver.solve(script),
+        }
+    }
+
+    fn prove(&mut self, condition: &BoogieFile) -> Option<String> {
+        self.solver.proof(&condition.code)
+    }
+
+    fn fork(&self) -> Box<dyn SolverBackend> {
+        Box::new(self.clone())
+    }
+}
+
+/// The SMT-LIB2 script of a C-edge, named like its Boogie file.
+pub(crate) fn smt_condition(
+    unit: &VerificationUnit,
+    pair: &HopPair,
+    cfg: &CfgProgram,
+    invariants: &[Invariant],
+    encoding: Encoding,
+) -> Result<BoogieFile, String> {
+    let code = pair
+        .smt_script_ensuring(cfg, invariants, encoding)
+        .ok_or_else(|| "The hops have too many paths for an SMT-LIB2 script".to_string())?;
+    let filename = BoogieFileManager::generate_filename(unit, cfg).replace(".bpl", ".smt2");
+    Ok(BoogieFile { filename, code })
+}
+
+/// The answers of a failed Boogie run on a batch, whose conditions take the
+/// lines given: a condition with messages located on its lines is unknown
+/// with them; the others are proven, unless a message is located nowhere,
+/// which leaves them unknown with it.
+fn split_boogie_messages(output: &str, lines: &[RangeInclusive<usize>]) -> Vec<SolverAnswer> {
+    let mut messages = vec![Vec::new(); lines.len()];
+    let mut unplaced = Vec::new();
+    // The closing summary counts the errors placed, so adds nothing
+    let reported = output.lines().filter(|line| {
+        !line.trim().is_empty() && !line.starts_with("Boogie program verifier finished")
+    });
+    for message in reported {
+        // Messages are located as `file.bpl(line,column): ...`
+        let line = message
+            .split_once(".bpl(")
+            .and_then(|(_, rest)| rest.split_once(','))
+            .and_then(|(line, _)| line.parse::<usize>().ok());
+        match line.and_then(|line| lines.iter().position(|range| range.contains(&line))) {
+            Some(condition) => messages[condition].push(message),
+            None => unplaced.push(message),
+        }
+    }
+    messages
+        .into_iter()
+        .map(|messages| {
+            if !messages.is_empty() {
+                SolverAnswer::Unknown(messages.join("\n"))
+            } else if !unplaced.is_empty() {
+                SolverAnswer::Unknown(unplaced.join("\n"))
+            } else {
+                SolverAnswer::Unsat
+            }
+        })
+        .collect()
+}
+
+/// The first line a command prints, such as its version, if it runs.
+fn first_line_of(command: &mut Command) -> Option<String> {
+    let output = command.stderr(Stdio::null()).output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    Some(line.to_string())
+}
+
+/// Caps the address space of a prover process at `megabytes`, where the
+/// platform allows it.
+pub(crate) fn limit_memory(command: &mut Command, megabytes: Option<u32>) {
+    #[cfg(unix)]
+    if let Some(megabytes) = megabytes {
+        use std::os::unix::process::CommandExt;
+        let bytes = libc::rlim_t::from(megabytes) * 1024 * 1024;
+        // SAFETY: `setrlimit` is async-signal-safe, as code between fork and
+        // exec must be
+        unsafe {
+            command.pre_exec(move || {
+                let limit = libc::rlimit {
+                    rlim_cur: bytes,
+                    rlim_max: bytes,
+                };
+                if libc::setrlimit(libc::RLIMIT_AS, &limit) == 0 {
+                    Ok(())
+                } else {
+                    Err(std::io::Error::last_os_error())
+                }
+            });
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (command, megabytes);
+}
@@ -0,0 +1,1392 @@
+//! Symbolic execution of a pair of hops, in both orders.
+//!
+//! Every path through the two hops run one after the other is explored from
+//! symbolic values: the variables of each hop start from unknown inputs, and
+//! each record field from its unknown value before either hop runs. A path
+//! carries the branch conditions that select it, the table writes along it and
+//! the final values of the hops' variables.
+//!
+//! When, for every choice of branches, both orders have the same path
+//! condition and end with the same tables, variables and results, the hops
+//! commute whatever state they start from, and their C-edge needs no prover.
+//! A hop that commits in one order and aborts in the other makes the orders
+//! differ. When both orders abort the same hops, the aborted transactions
+//! leave no effects, so only how the hops end is compared.
+//! Otherwise small concrete inputs are tried in search of a conflict witness:
+//! a start state on which the two orders end differently. The hops start from
+//! any state here, so a witness may be one the hops before them never produce.
+//!
+//! ```
+//! use FMitF_rs::verification::symbolic::{HopPair, PairCheck};
+//! use FMitF_rs::{parse_and_analyze, CfgBuilder};
+//!
+//! let program = parse_and_analyze(
+//!     r#"
+//!     nodes { A }
+//!     table T on A { primary int id; int n; int m; }
+//!     void inc(int id) {
+//!         hop on A {
+//!             T[id: id].n = T[id: id].n + 1;
+//!         }
+//!     }
+//!     void set(int id) {
+//!         hop on A {
+//!             T[id: id].n = 0;
+//!         }
+//!     }
+//!     void mark(int id) {
+//!         hop on A {
+//!             T[id: id].m = 1;
+//!         }
+//!     }
+//!     "#,
+//! )
+//! .unwrap();
+//! let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+//! let pair = |a: usize, b: usize| {
+//!     let (function_a, function_b) = (cfg.root_functions[a], cfg.root_functions[b]);
+//!     HopPair {
+//!         function_a,
+//!         hop_a: cfg.functions[function_a].hop_order[0],
+//!         function_b,
+//!         hop_b: cfg.functions[function_b].hop_order[0],
+//!     }
+//! };
+//!
+//! // Writes to different fields commute
+//! assert!(matches!(pair(0, 2).check(&cfg), PairCheck::Commutative));
+//!
+//! // An increment and a reset of the same record do not
+//! let PairCheck::Conflict(witness) = pair(0, 1).check(&cfg) else {
+//!     panic!("expected a conflict");
+//! };
+//! assert_eq!(
+//!     pair(0, 1).format_witness(&cfg, &witness),
+//!     "inc.id = 0, set.id = 0, T[id: 0].n = 0"
+//! );
+//! assert_eq!(
+//!     pair(0, 1).narrate(&cfg, &witness).unwrap(),
+//!     "from inc.id = 0, set.id = 0, T[id: 0].n = 0:\n  \
+//!      inc:1 then set:1: T[id: 0].n = 0\n  \
+//!      set:1 then inc:1: T[id: 0].n = 1"
+//! );
+//! ```
+
+use crate::ast::TypeName;
+use crate::cfg::serialize::IdRef;
+use crate::cfg::{
+    BasicBlockId, BinaryOp, BoundCheck, CfgProgram, Constant, FieldId, FieldInfo, FunctionId,
+    HopId, Operand, Rvalue, Statement, TableId, TableInfo, Terminator, UnaryOp, VarId, Variable,
+};
+use crate::dataflow::LiveVariables;
+use crate::optimization::ConstantPropagationPass;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+/// Blocks one path may run through in a single hop, bounding loops
+const MAX_BLOCKS_PER_HOP: usize = 256;
+
+/// Paths explored for one order of the hops
+const MAX_PATHS: usize = 1024;
+
+/// Concrete start states tried in search of a witness
+const MAX_CANDIDATES: usize = 4096;
+
+/// Which hop of the pair a value belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Side {
+    A,
+    B,
+}
+
+/// A value as an expression over the state the hops start from. Built through
+/// its constructors, which simplify it so that values equal for simple reasons
+/// are usually equal as expressions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SymExpr {
+    Const(Constant),
+    /// A variable's value when its hop starts
+    Input {
+        side: Side,
+        var: VarId,
+    },
+    /// A field of a record before either hop runs, keyed in the order the
+    /// table declares its primary key
+    Initial {
+        table: TableId,
+        field: FieldId,
+        key: Vec<SymExpr>,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<SymExpr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<SymExpr>,
+        right: Box<SymExpr>,
+    },
+    /// `then` where `condition` holds, `otherwise` elsewhere
+    Ite {
+        condition: Box<SymExpr>,
+        then: Box<SymExpr>,
+        otherwise: Box<SymExpr>,
+    },
+}
+
+impl SymExpr {
+    fn bool(value: bool) -> Self {
+        SymExpr::Const(Constant::Bool(value))
+    }
+
+    /// A unary operation, folded where its operand is known.
+    pub fn unary(op: UnaryOp, operand: SymExpr) -> Self {
+        if let SymExpr::Const(c) = &operand {
+            if let Some(c) = ConstantPropagationPass.evaluate_unary_op(&op, c) {
+                return SymExpr::Const(c);
+            }
+        }
+        match (&op, operand) {
+            (
+                UnaryOp::Not,
+                SymExpr::Unary {
+                    op: UnaryOp::Not,
+                    operand,
+                },
+            ) => *operand,
+            (_, operand) => SymExpr::Unary {
+                op,
+                operand: Box::new(operand),
+            },
+        }
+    }
+
+    /// A binary operation, folded where its operands are known or equal.
+    pub fn binary(op: BinaryOp, left: SymExpr, right: SymExpr) -> Self {
+        if let (SymExpr::Const(l), SymExpr::Const(r)) = (&left, &right) {
+            if let Some(c) = ConstantPropagationPass.evaluate_binary_op(&op, l, r) {
+                return SymExpr::Const(c);
+            }
+        }
+        // Operands of commutative operations in a fixed order, constants last
+        let commutative = matches!(
+            op,
+            BinaryOp::Add
+                | BinaryOp::Mul
+                | BinaryOp::Eq
+                | BinaryOp::Neq
+                | BinaryOp::And
+                | BinaryOp::Or
+        );
+        let (left, right) = match (&left, &right) {
+            (SymExpr::Const(_), SymExpr::Const(_)) => (left, right),
+            (SymExpr::Const(_), _) if commutative => (right, left),
+            (_, SymExpr::Const(_)) => (left, right),
+            _ if commutative && right < left => (right, left),
+            _ => (left, right),
+        };
+
+        // An operation on a choice is a choice of operations
+        match (&left, &right) {
+            (SymExpr::Ite { .. }, SymExpr::Ite { .. }) => {}
+            (
+                SymExpr::Ite {
+                    condition,
+                    then,
+                    otherwise,
+                },
+                _,
+            ) => {
+                return Self::ite(
+                    (**condition).clone(),
+                    Self::binary(op.clone(), (**then).clone(), right.clone()),
+                    Self::binary(op, (**otherwise).clone(), right),
+                );
+            }
+            (
+                _,
+                SymExpr::Ite {
+                    condition,
+                    then,
+                    otherwise,
+                },
+            ) => {
+                return Self::ite(
+                    (**condition).clone(),
+                    Self::binary(op.clone(), left.clone(), (**then).clone()),
+                    Self::binary(op, left, (**otherwise).clone()),
+                );
+            }
+            _ => {}
+        }
+
+        // Constant offsets are gathered up, so adding them in another order gives
+        // the same expression. Offsets of one sign overflow exactly when their
+        // sum does.
+        match (&op, &left, &right) {
+            (BinaryOp::Sub, _, SymExpr::Const(Constant::Int(c))) if *c != i64::MIN => {
+                return Self::binary(BinaryOp::Add, left, SymExpr::Const(Constant::Int(-c)));
+            }
+            (
+                BinaryOp::Add,
+                SymExpr::Binary {
+                    op: BinaryOp::Add,
+                    left: inner,
+                    right: inner_offset,
+                },
+                SymExpr::Const(Constant::Int(c2)),
+            ) => {
+                if let SymExpr::Const(Constant::Int(c1)) = **inner_offset {
+                    if let Some(sum) = c1.checked_add(*c2).filter(|_| (c1 < 0) == (*c2 < 0)) {
+                        return Self::binary(
+                            BinaryOp::Add,
+                            (**inner).clone(),
+                            SymExpr::Const(Constant::Int(sum)),
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        match (&op, &left, &right) {
+            (BinaryOp::Eq, l, r) if l == r => SymExpr::bool(true),
+            (BinaryOp::Neq, l, r) if l == r => SymExpr::bool(false),
+            (BinaryOp::And, SymExpr::Const(Constant::Bool(true)), _) => right,
+            (BinaryOp::And, _, SymExpr::Const(Constant::Bool(true))) => left,
+            (BinaryOp::And, SymExpr::Const(Constant::Bool(false)), _)
+            | (BinaryOp::And, _, SymExpr::Const(Constant::Bool(false))) => SymExpr::bool(false),
+            (BinaryOp::Or, SymExpr::Const(Constant::Bool(false)), _) => right,
+            (BinaryOp::Or, _, SymExpr::Const(Constant::Bool(false))) => left,
+            (BinaryOp::Or, SymExpr::Const(Constant::Bool(true)), _)
+            | (BinaryOp::Or, _, SymExpr::Const(Constant::Bool(true))) => SymExpr::bool(true),
+            _ => SymExpr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+        }
+    }
+
+    /// A choice between two values, folded where the condition is known or
+    /// both values are the same. Each value is simplified knowing whether the
+    /// condition holds.
+    pub fn ite(condition: SymExpr, then: SymExpr, otherwise: SymExpr) -> Self {
+        match condition {
+            SymExpr::Const(Constant::Bool(true)) => then,
+            SymExpr::Const(Constant::Bool(false)) => otherwise,
+            condition => {
+                let then = then.assume(&condition, true);
+                let otherwise = otherwise.assume(&condition, false);
+                if then == otherwise {
+                    return then;
+                }
+                SymExpr::Ite {
+                    condition: Box::new(condition),
+                    then: Box::new(then),
+                    otherwise: Box::new(otherwise),
+                }
+            }
+        }
+    }
+
+    /// This expression where `condition` is known to be `holds`. Where an
+    /// equality holds, its sides are interchangeable.
+    fn assume(&self, condition: &SymExpr, holds: bool) -> SymExpr {
+        let result = self.substitute(condition, &SymExpr::bool(holds));
+        match condition {
+            SymExpr::Binary {
+                op: BinaryOp::Eq,
+                left,
+                right,
+            } if holds => result.substitute(right, left),
+            _ => result,
+        }
+    }
+
+    /// This expression with `from` replaced by `to`, simplified again.
+    fn substitute(&self, from: &SymExpr, to: &SymExpr) -> SymExpr {
+        if self == from {
+            return to.clone();
+        }
+        match self {
+            SymExpr::Const(_) | SymExpr::Input { .. } => self.clone(),
+            SymExpr::Initial { table, field, key } => SymExpr::Initial {
+                table: *table,
+                field: *field,
+                key: key.iter().map(|part| part.substitute(from, to)).collect(),
+            },
+            SymExpr::Unary { op, operand } => Self::unary(op.clone(), operand.substitute(from, to)),
+            SymExpr::Binary { op, left, right } => Self::binary(
+                op.clone(),
+                left.substitute(from, to),
+                right.substitute(from, to),
+            ),
+            SymExpr::Ite {
+                condition,
+                then,
+                otherwise,
+            } => Self::ite(
+                condition.substitute(from, to),
+                then.substitute(from, to),
+                otherwise.substitute(from, to),
+            ),
+        }
+    }
+
+    /// Calls `f` on this expression and everything inside it, outermost first.
+    pub(crate) fn visit(&self, f: &mut impl FnMut(&SymExpr)) {
+        f(self);
+        match self {
+            SymExpr::Const(_) | SymExpr::Input { .. } => {}
+            SymExpr::Initial { key, .. } => key.iter().for_each(|part| part.visit(f)),
+            SymExpr::Unary { operand, .. } => operand.visit(f),
+            SymExpr::Binary { left, right, .. } => {
+                left.visit(f);
+                right.visit(f);
+            }
+            SymExpr::Ite {
+                condition,
+                then,
+                otherwise,
+            } => {
+                condition.visit(f);
+                then.visit(f);
+                otherwise.visit(f);
+            }
+        }
+    }
+}
+
+/// A write of `value` to `field` of the record with primary key `key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymWrite {
+    /// The hop that wrote it
+    pub side: Side,
+    pub table: TableId,
+    pub field: FieldId,
+    pub key: Vec<SymExpr>,
+    pub value: SymExpr,
+}
+
+/// A branch taken on a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Decision {
+    pub side: Side,
+    /// The block ending in the branch
+    pub block: BasicBlockId,
+    /// Whether the `then` block was taken
+    pub taken: bool,
+}
+
+/// How a hop ended on a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HopEnd {
+    /// Left the hop for the next one, or the function
+    Exit,
+    Return(Option<SymExpr>),
+    /// Aborted, dropping the hop's writes
+    Abort,
+}
+
+/// One path through both hops, run in some order.
+#[derive(Debug, Clone)]
+pub struct PairPath {
+    /// The branches taken, in the order they ran
+    pub decisions: Vec<Decision>,
+    /// Conditions that together hold for exactly the start states taking this path
+    pub condition: Vec<SymExpr>,
+    /// The table writes that stay, oldest first
+    pub writes: Vec<SymWrite>,
+    /// The final values of the variables the hops assign
+    pub vars: HashMap<(Side, VarId), SymExpr>,
+    pub end_a: HopEnd,
+    pub end_b: HopEnd,
+}
+
+impl PairPath {
+    /// Whether both hops commit.
+    pub fn commits(&self) -> bool {
+        self.end_a != HopEnd::Abort && self.end_b != HopEnd::Abort
+    }
+
+    /// Whether each hop aborts, A first.
+    pub fn aborts(&self) -> [bool; 2] {
+        [self.end_a == HopEnd::Abort, self.end_b == HopEnd::Abort]
+    }
+
+    /// The branches of each hop, which pick the same path in either order.
+    fn branches(&self) -> [Vec<(BasicBlockId, bool)>; 2] {
+        let of = |side| {
+            self.decisions
+                .iter()
+                .filter(|decision| decision.side == side)
+                .map(|decision| (decision.block, decision.taken))
+                .collect()
+        };
+        [of(Side::A), of(Side::B)]
+    }
+
+    /// The value of a variable at the end of the path.
+    pub(super) fn var(&self, side: Side, var: VarId) -> SymExpr {
+        self.vars
+            .get(&(side, var))
+            .cloned()
+            .unwrap_or(SymExpr::Input { side, var })
+    }
+
+    fn exprs(&self) -> impl Iterator<Item = &SymExpr> {
+        let ends = [&self.end_a, &self.end_b]
+            .into_iter()
+            .filter_map(|end| match end {
+                HopEnd::Return(Some(value)) => Some(value),
+                _ => None,
+            });
+        self.condition
+            .iter()
+            .chain(
+                self.writes
+                    .iter()
+                    .flat_map(|write| write.key.iter().chain([&write.value])),
+            )
+            .chain(self.vars.values())
+            .chain(ends)
+    }
+}
+
+/// What symbolic execution found out about a hop pair.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PairCheck {
+    /// Both orders end the same from every start state
+    Commutative,
+    /// A start state on which the two orders end differently
+    Conflict(ConflictWitness),
+    /// Neither could be shown, or the hops have too many paths to explore
+    Unknown,
+}
+
+/// A concrete start state on which a hop pair does not commute.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "WitnessIds", from = "WitnessIds")]
+pub struct ConflictWitness {
+    /// The values the hops' variables start with
+    pub inputs: Vec<(Side, VarId, Constant)>,
+    /// Record fields as they are before either hop runs; every other field
+    /// starts with its type's default
+    pub initial: Vec<(TableId, FieldId, Vec<Constant>, Constant)>,
+}
+
+/// A record field of a `ConflictWitness` as serialized.
+type FieldIds = (IdRef<TableInfo>, IdRef<FieldInfo>, Vec<Constant>, Constant);
+
+/// A `ConflictWitness` as serialized, its ids inside tuples.
+#[derive(Serialize, Deserialize)]
+struct WitnessIds {
+    inputs: Vec<(Side, IdRef<Variable>, Constant)>,
+    initial: Vec<FieldIds>,
+}
+
+impl From<ConflictWitness> for WitnessIds {
+    fn from(witness: ConflictWitness) -> Self {
+        WitnessIds {
+            inputs: witness
+                .inputs
+                .into_iter()
+                .map(|(side, var, value)| (side, IdRef(var), value))
+                .collect(),
+            initial: witness
+                .initial
+                .into_iter()
+                .map(|(table, field, key, value)| (IdRef(table), IdRef(field), key, value))
+                .collect(),
+        }
+    }
+}
+
+impl From<WitnessIds> for ConflictWitness {
+    fn from(witness: WitnessIds) -> Self {
+        ConflictWitness {
+            inputs: witness
+                .inputs
+                .into_iter()
+                .map(|(side, var, value)| (side, var.0, value))
+                .collect(),
+            initial: witness
+                .initial
+                .into_iter()
+                .map(|(table, field, key, value)| (table.0, field.0, key, value))
+                .collect(),
+        }
+    }
+}
+
+/// Two hops, each from a function, that may run in either order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HopPair {
+    pub function_a: FunctionId,
+    pub hop_a: HopId,
+    pub function_b: FunctionId,
+    pub hop_b: HopId,
+}
+
+impl HopPair {
+    pub(super) fn hop(&self, side: Side) -> (FunctionId, HopId) {
+        match side {
+            Side::A => (self.function_a, self.hop_a),
+            Side::B => (self.function_b, self.hop_b),
+        }
+    }
+
+    /// All paths through the hops run in the given order, or `None` when a
+    /// path runs through too many blocks or there are too many paths.
+    pub fn explore(&self, cfg: &CfgProgram, first: Side) -> Option<Vec<PairPath>> {
+        let second = match first {
+            Side::A => Side::B,
+            Side::B => Side::A,
+        };
+        let mut paths = Vec::new();
+        for state in self.explore_hop(cfg, first, PathState::default())? {
+            paths.extend(self.explore_hop(cfg, second, state)?);
+            if paths.len() > MAX_PATHS {
+                return None;
+            }
+        }
+        Some(paths.into_iter().map(PathState::finish).collect())
+    }
+
+    /// All paths through one of the hops run alone.
+    pub(super) fn explore_one(&self, cfg: &CfgProgram, side: Side) -> Option<Vec<PairPath>> {
+        let paths = self.explore_hop(cfg, side, PathState::default())?;
+        Some(paths.into_iter().map(PathState::finish).collect())
+    }
+
+    /// All paths through hops of the two functions run in the given order,
+    /// each from where the hops of its side before it left off. A side's hops
+    /// after one that returns or aborts are skipped. `None` as for `explore`.
+    pub fn explore_order(
+        &self,
+        cfg: &CfgProgram,
+        order: &[(Side, HopId)],
+    ) -> Option<Vec<PairPath>> {
+        let mut states = vec![PathState::default()];
+        for &(side, hop) in order {
+            let mut pair = *self;
+            match side {
+                Side::A => pair.hop_a = hop,
+                Side::B => pair.hop_b = hop,
+            }
+            let mut next = Vec::new();
+            for state in states {
+                let end = match side {
+                    Side::A => &state.end_a,
+                    Side::B => &state.end_b,
+                };
+                if matches!(end, Some(HopEnd::Return(_) | HopEnd::Abort)) {
+                    next.push(state);
+                } else {
+                    next.extend(pair.explore_hop(cfg, side, state)?);
+                }
+                if next.len() > MAX_PATHS {
+                    return None;
+                }
+            }
+            states = next;
+        }
+        Some(states.into_iter().map(PathState::finish).collect())
+    }
+
+    /// Runs one hop from `state` down every path.
+    fn explore_hop(
+        &self,
+        cfg: &CfgProgram,
+        side: Side,
+        state: PathState,
+    ) -> Option<Vec<PathState>> {
+        let (function, hop) = self.hop(side);
+        let func = &cfg.functions[function];
+        let Some(entry) = func.hops[hop].entry_block else {
+            return Some(vec![state.ended(side, HopEnd::Exit)]);
+        };
+
+        let kept_writes = state.writes.len();
+        let mut stack = vec![(state, entry, 0)];
+        let mut done = Vec::new();
+        while let Some((mut state, block_id, steps)) = stack.pop() {
+            if steps >= MAX_BLOCKS_PER_HOP || done.len() + stack.len() > MAX_PATHS {
+                return None;
+            }
+            let block = &func.blocks[block_id];
+            for stmt in &block.statements {
+                state.execute(cfg, side, stmt);
+            }
+            match &block.terminator {
+                Terminator::Goto(target) => stack.push((state, *target, steps + 1)),
+                Terminator::Branch {
+                    condition,
+                    then_block,
+                    else_block,
+                } => {
+                    let condition = state.operand(side, condition);
+                    for (taken, target) in [(false, *else_block), (true, *then_block)] {
+                        let guard = if taken {
+                            condition.clone()
+                        } else {
+                            SymExpr::unary(UnaryOp::Not, condition.clone())
+                        };
+                        if guard == SymExpr::bool(false) {
+                            continue;
+                        }
+                        let mut next = state.clone();
+                        if guard != SymExpr::bool(true) {
+                            next.condition.push(guard);
+                        }
+                        next.decisions.push(Decision {
+                            side,
+                            block: block_id,
+                            taken,
+                        });
+                        stack.push((next, target, steps + 1));
+                    }
+                }
+                Terminator::Return(value) => {
+                    let value = value.as_ref().map(|value| state.operand(side, value));
+                    done.push(state.ended(side, HopEnd::Return(value)));
+                }
+                Terminator::Abort => {
+                    state.writes.truncate(kept_writes);
+                    done.push(state.ended(side, HopEnd::Abort));
+                }
+                Terminator::HopExit { .. } => done.push(state.ended(side, HopEnd::Exit)),
+                // Runs beyond an unroll bound are out of scope, unless the bound
+                // itself has to be proved
+                Terminator::BoundExceeded(BoundCheck::Assume) => {}
+                Terminator::BoundExceeded(BoundCheck::Assert) => return None,
+            }
+        }
+        Some(done)
+    }
+
+    /// Compares the two orders of the hops, looking for a conflict witness
+    /// when they cannot be shown to commute.
+    ///
+    /// ```
+    /// use FMitF_rs::verification::symbolic::{HopPair, PairCheck};
+    /// use FMitF_rs::{parse_and_analyze, CfgBuilder};
+    ///
+    /// // From a balance of 15, guarded commits only if it runs first
+    /// let program = parse_and_analyze(
+    ///     r#"
+    ///     nodes { A, B }
+    ///     table Acc on A { primary int id; int bal; }
+    ///     table Log on B { primary int id; int v; }
+    ///     void guarded() {
+    ///         hop on A {
+    ///             if (Acc[id: 1].bal < 10) { abort; }
+    ///             Acc[id: 1].bal = Acc[id: 1].bal - 10;
+    ///         }
+    ///         hop on B { Log[id: 1].v = 1; }
+    ///     }
+    ///     void blind() {
+    ///         hop on A { Acc[id: 1].bal = Acc[id: 1].bal - 10; }
+    ///         hop on B { Log[id: 1].v = 2; }
+    ///     }
+    ///     "#,
+    /// )
+    /// .unwrap();
+    /// let cfg = CfgBuilder::build_from_program(&program).unwrap().program;
+    /// let (guarded, blind) = (cfg.root_functions[0], cfg.root_functions[1]);
+    /// let pair = HopPair {
+    ///     function_a: guarded,
+    ///     hop_a: cfg.functions[guarded].hop_order[0],
+    ///     function_b: blind,
+    ///     hop_b: cfg.functions[blind].hop_order[0],
+    /// };
+    /// assert!(matches!(pair.check(&cfg), PairCheck::Conflict(_)));
+    /// ```
+    pub fn check(&self, cfg: &CfgProgram) -> PairCheck {
+        let (Some(ab), Some(ba)) = (self.explore(cfg, Side::A), self.explore(cfg, Side::B)) else {
+            return PairCheck::Unknown;
+        };
+        let observed = self.observed_vars(cfg);
+        if self.same_paths(&ab, &ba, &observed) {
+            return PairCheck::Commutative;
+        }
+        match self.find_witness(cfg, &ab, &ba, &observed) {
+            Some(witness) => PairCheck::Conflict(witness),
+            None => PairCheck::Unknown,
+        }
+    }
+
+    /// The variables later hops may read after the pair ran. The values of the
+    /// other variables make no difference.
+    pub(super) fn observed_vars(&self, cfg: &CfgProgram) -> HashSet<(Side, VarId)> {
+        let mut observed = HashSet::new();
+        for side in [Side::A, Side::B] {
+            let (function, hop) = self.hop(side);
+            let func = &cfg.functions[function];
+            let live = LiveVariables::new(func);
+            for &block in &func.hops[hop].blocks {
+                if matches!(func.blocks[block].terminator, Terminator::HopExit { .. }) {
+                    observed.extend(live.live_out(block).iter().map(|&var| (side, var)));
+                }
+            }
+        }
+        observed
+    }
+
+    /// Whether each path of one order has a path with the same branches, path
+    /// condition and results in the other.
+    fn same_paths(
+        &self,
+        ab: &[PairPath],
+        ba: &[PairPath],
+        observed: &HashSet<(Side, VarId)>,
+    ) -> bool {
+        let ba: HashMap<_, _> = ba.iter().map(|path| (path.branches(), path)).collect();
+        if ab.len() != ba.len() {
+            return false;
+        }
+        ab.iter().all(|path| {
+            let Some(other) = ba.get(&path.branches()) else {
+                return false;
+            };
+            let condition: HashSet<_> = path.condition.iter().collect();
+            let other_condition: HashSet<_> = other.condition.iter().collect();
+            condition == other_condition
+                && path.end_a == other.end_a
+                && path.end_b == other.end_b
+                // Both orders abort the same hops, leaving nothing to compare
+                && (!path.commits()
+                    || observed
+                        .iter()
+                        .all(|&(side, var)| path.var(side, var) == other.var(side, var))
+                        && path.writes.iter().chain(&other.writes).all(|write| {
+                            read(&path.writes, write.table, write.field, &write.key)
+                                == read(&other.writes, write.table, write.field, &write.key)
+                        }))
+        })
+    }
+
+    /// Tries small start states built from the constants of the hops until the
+    /// two orders end differently on one.
+    fn find_witness(
+        &self,
+        cfg: &CfgProgram,
+        ab: &[PairPath],
+        ba: &[PairPath],
+        observed: &HashSet<(Side, VarId)>,
+    ) -> Option<ConflictWitness> {
+        // The unknowns, in the order they first appear
+        let mut inputs: Vec<(Side, VarId)> = Vec::new();
+        let mut initial: Vec<InitialField> = Vec::new();
+        let mut constants: Vec<Constant> = Vec::new();
+        for expr in ab.iter().chain(ba).flat_map(PairPath::exprs) {
+            expr.visit(&mut |expr| match expr {
+                SymExpr::Input { side, var } if !inputs.contains(&(*side, *var)) => {
+                    inputs.push((*side, *var));
+                }
+                SymExpr::Const(c) if !constants.contains(c) => constants.push(c.clone()),
+                _ => {}
+            });
+        }
+        for expr in ab.iter().chain(ba).flat_map(PairPath::exprs) {
+            collect_initial(expr, &mut initial);
+        }
+
+        let domains: Vec<Vec<Constant>> = inputs
+            .iter()
+            .map(|&(side, var)| {
+                let (function, _) = self.hop(side);
+                candidates(&cfg.functions[function].variables[var].ty, &constants)
+            })
+            .chain(
+                initial
+                    .iter()
+                    .map(|&(_, field, _)| candidates(&cfg.fields[field].ty, &constants)),
+            )
+            .collect();
+
+        let total = domains
+            .iter()
+            .try_fold(1usize, |total, domain| total.checked_mul(domain.len()))
+            .unwrap_or(usize::MAX);
+        for mut index in 0..total.min(MAX_CANDIDATES) {
+            let choice: Vec<Constant> = domains
+                .iter()
+                .map(|domain| {
+                    let value = domain[index % domain.len()].clone();
+                    index /= domain.len();
+                    value
+                })
+                .collect();
+
+            let mut env = Env {
+                cfg,
+                inputs: inputs.iter().copied().zip(choice.iter().cloned()).collect(),
+                initial: HashMap::new(),
+            };
+            // Seed the records the hops read; the first read of a record decides it
+            let mut seeded = Vec::new();
+            for (&(table, field, key), value) in initial.iter().zip(&choice[inputs.len()..]) {
+                let Some(key) = key
+                    .iter()
+                    .map(|part| env.evaluate(part))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    continue;
+                };
+                if let Entry::Vacant(entry) = env.initial.entry((table, field, key.clone())) {
+                    entry.insert(value.clone());
+                    seeded.push((table, field, key, value.clone()));
+                }
+            }
+
+            let (Some(end_ab), Some(end_ba)) = (env.run(ab), env.run(ba)) else {
+                continue;
+            };
+            if env.differ(&end_ab, &end_ba, observed) {
+                return Some(ConflictWitness {
+                    inputs: inputs
+                        .iter()
+                        .zip(choice)
+                        .map(|(&(side, var), value)| (side, var, value))
+                        .collect(),
+                    initial: seeded,
+                });
+            }
+        }
+        None
+    }
+
+    /// Formats a symbolic value, naming variables after their function.
+    pub fn format_expr(&self, cfg: &CfgProgram, expr: &SymExpr) -> String {
+        match expr {
+            SymExpr::Const(c) => format_constant(c),
+            SymExpr::Input { side, var } => self.format_var(cfg, *side, *var),
+            SymExpr::Initial { table, field, key } => {
+                let key: Vec<String> = key.iter().map(|part| self.format_expr(cfg, part)).collect();
+                format_record(cfg, *table, *field, &key)
+            }
+            SymExpr::Unary { op, operand } => {
+                format!("({:?} {})", op, self.format_expr(cfg, operand))
+            }
+            SymExpr::Binary { op, left, right } => format!(
+                "({} {:?} {})",
+                self.format_expr(cfg, left),
+                op,
+                self.format_expr(cfg, right)
+            ),
+            SymExpr::Ite {
+                condition,
+                then,
+                otherwise,
+            } => format!(
+                "(if {} then {} else {})",
+                self.format_expr(cfg, condition),
+                self.format_expr(cfg, then),
+                self.format_expr(cfg, otherwise)
+            ),
+        }
+    }
+
+    /// Formats a witness as the variable and record values it starts from.
+    pub fn format_witness(&self, cfg: &CfgProgram, witness: &ConflictWitness) -> String {
+        let inputs = witness.inputs.iter().map(|(side, var, value)| {
+            format!(
+                "{} = {}",
+                self.format_var(cfg, *side, *var),
+                format_constant(value)
+            )
+        });
+        let initial = witness.initial.iter().map(|(table, field, key, value)| {
+            let key: Vec<String> = key.iter().map(format_constant).collect();
+            format!(
+                "{} = {}",
+                format_record(cfg, *table, *field, &key),
+                format_constant(value)
+            )
+        });
+        inputs.chain(initial).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Replays both orders of the hops from a witness's start state and tells
+    /// where they part: the start state, then for each order the results that
+    /// differ. `None` when the orders end the same from it. Variables the
+    /// witness leaves out start with their type's default.
+    pub fn narrate(&self, cfg: &CfgProgram, witness: &ConflictWitness) -> Option<String> {
+        let ab = self.explore(cfg, Side::A)?;
+        let ba = self.explore(cfg, Side::B)?;
+        let mut env = Env {
+            cfg,
+            inputs: witness
+                .inputs
+                .iter()
+                .map(|(side, var, value)| ((*side, *var), value.clone()))
+                .collect(),
+            initial: witness
+                .initial
+                .iter()
+                .map(|(table, field, key, value)| ((*table, *field, key.clone()), value.clone()))
+                .collect(),
+        };
+        for expr in ab.iter().chain(&ba).flat_map(PairPath::exprs) {
+            expr.visit(&mut |expr| {
+                if let SymExpr::Input { side, var } = expr {
+                    let (function, _) = self.hop(*side);
+                    let ty = &cfg.functions[function].variables[*var].ty;
+                    env.inputs
+                        .entry((*side, *var))
+                        .or_insert_with(|| default_value(ty));
+                }
+            });
+        }
+
+        let (end_ab, end_ba) = (env.run(&ab)?, env.run(&ba)?);
+        let observed = self.observed_vars(cfg);
+        let mut vars: Vec<_> = observed
+            .into_iter()
+            .filter(|key| env.var(&end_ab, key) != env.var(&end_ba, key))
+            .collect();
+        vars.sort();
+        let mut fields: Vec<_> = end_ab
+            .fields
+            .keys()
+            .chain(end_ba.fields.keys())
+            .filter(|key| env.field(&end_ab, key) != env.field(&end_ba, key))
+            .cloned()
+            .collect();
+        fields.sort();
+        fields.dedup();
+        let ends: Vec<usize> = (0..2)
+            .filter(|&i| end_ab.ends[i] != end_ba.ends[i])
+            .collect();
+        if ends.is_empty() && vars.is_empty() && fields.is_empty() {
+            return None;
+        }
+
+        let outcome = |end: &ConcreteEnd| {
+            let ends = ends.iter().map(|&i| {
+                let side = [Side::A, Side::B][i];
+                let name = &cfg.functions[self.hop(side).0].name;
+                match &end.ends[i] {
+                    (_, Some(value)) => format!("{} returns {}", name, format_constant(value)),
+                    (kind, None) if *kind == std::mem::discriminant(&HopEnd::Abort) => {
+                        format!("{} aborts", name)
+                    }
+                    (kind, None) if *kind == std::mem::discriminant(&HopEnd::Exit) => {
+                        format!("{} goes on", name)
+                    }
+                    _ => format!("{} returns", name),
+                }
+            });
+            let vars = vars.iter().map(|key| {
+                let value = env
+                    .var(end, key)
+                    .map_or("?".to_string(), |v| format_constant(&v));
+                format!("{} = {}", self.format_var(cfg, key.0, key.1), value)
+            });
+            let fields = fields.iter().map(|key| {
+                let record: Vec<String> = key.2.iter().map(format_constant).collect();
+                format!(
+                    "{} = {}",
+                    format_record(cfg, key.0, key.1, &record),
+                    format_constant(&env.field(end, key))
+                )
+            });
+            ends.chain(vars)
+                .chain(fields)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let [a, b] = [Side::A, Side::B].map(|side| self.format_hop(cfg, side));
+        Some(format!(
+            "from {}:\n  {} then {}: {}\n  {} then {}: {}",
+            self.format_witness(cfg, witness),
+            a,
+            b,
+            outcome(&end_ab),
+            b,
+            a,
+            outcome(&end_ba)
+        ))
+    }
+
+    /// A hop as `f:2`, its function and its place in the function.
+    fn format_hop(&self, cfg: &CfgProgram, side: Side) -> String {
+        let (function, hop) = self.hop(side);
+        let func = &cfg.functions[function];
+        let position = func
+            .hop_order
+            .iter()
+            .position(|&h| h == hop)
+            .unwrap_or_default();
+        format!("{}:{}", func.name, position + 1)
+    }
+
+    fn format_var(&self, cfg: &CfgProgram, side: Side, var: VarId) -> String {
+        let (function, _) = self.hop(side);
+        let func = &cfg.functions[function];
+        format!("{}.{}", func.name, func.variables[var].name)
+    }
+}
+
+/// The symbolic state of a path being explored.
+#[derive(Debug, Clone, Default)]
+struct PathState {
+    vars: HashMap<(Side, VarId), SymExpr>,
+    writes: Vec<SymWrite>,
+    condition: Vec<SymExpr>,
+    decisions: Vec<Decision>,
+    end_a: Option<HopEnd>,
+    end_b: Option<HopEnd>,
+}
+
+impl PathState {
+    fn finish(self) -> PairPath {
+        PairPath {
+            decisions: self.decisions,
+            condition: self.condition,
+            writes: self.writes,
+            vars: self.vars,
+            end_a: self.end_a.unwrap_or(HopEnd::Exit),
+            end_b: self.end_b.unwrap_or(HopEnd::Exit),
+        }
+    }
+
+    fn ended(mut self, side: Side, end: HopEnd) -> Self {
+        match side {
+            Side::A => self.end_a = Some(end),
+            Side::B => self.end_b = Some(end),
+        }
+        self
+    }
+
+    fn execute(&mut self, cfg: &CfgProgram, side: Side, stmt: &Statement) {
+        match stmt {
+            Statement::Assign { var, rvalue, .. } => {
+                let value = self.rvalue(cfg, side, rvalue);
+                self.vars.insert((side, *var), value);
+            }
+            Statement::TableAssign {
+                table,
+                pk_fields,
+                pk_values,
+                field,
+                value,
+                ..
+            } => {
+                let key = self.key(cfg, side, *table, pk_fields, pk_values);
+                let value = self.operand(side, value);
+                self.writes.push(SymWrite {
+                    side,
+                    table: *table,
+                    field: *field,
+                    key,
+                    value,
+                });
+            }
+        }
+    }
+
+    fn rvalue(&self, cfg: &CfgProgram, side: Side, rvalue: &Rvalue) -> SymExpr {
+        match rvalue {
+            Rvalue::Use(operand) => self.operand(side, operand),
+            Rvalue::TableAccess {
+                table,
+                pk_fields,
+                pk_values,
+                field,
+            } => {
+                let key = self.key(cfg, side, *table, pk_fields, pk_values);
+                read(&self.writes, *table, *field, &key)
+            }
+            Rvalue::UnaryOp { op, operand } => {
+                SymExpr::unary(op.clone(), self.operand(side, operand))
+            }
+            Rvalue::BinaryOp { op, left, right } => SymExpr::binary(
+                op.clone(),
+                self.operand(side, left),
+                self.operand(side, right),
+            ),
+        }
+    }
+
+    fn operand(&self, side: Side, operand: &Operand) -> SymExpr {
+        match operand {
+            Operand::Const(c) => SymExpr::Const(c.clone()),
+            Operand::Var(var) => self
+                .vars
+                .get(&(side, *var))
+                .cloned()
+                .unwrap_or(SymExpr::Input { side, var: *var }),
+        }
+    }
+
+    /// A primary key in the order the table declares its fields.
+    fn key(
+        &self,
+        cfg: &CfgProgram,
+        side: Side,
+        table: TableId,
+        pk_fields: &[FieldId],
+        pk_values: &[Operand],
+    ) -> Vec<SymExpr> {
+        let mut key: Vec<_> = pk_fields.iter().zip(pk_values).collect();
+        key.sort_by_key(|(field, _)| {
+            cfg.tables[table]
+                .primary_keys
+                .iter()
+                .position(|pk| pk == *field)
+        });
+        key.into_iter()
+            .map(|(_, value)| self.operand(side, value))
+            .collect()
+    }
+}
+
+/// The value of a field after `writes`: the latest write whose key matches, or
+/// the value before the hops.
+pub(super) fn read(
+    writes: &[SymWrite],
+    table: TableId,
+    field: FieldId,
+    key: &[SymExpr],
+) -> SymExpr {
+    let initial = SymExpr::Initial {
+        table,
+        field,
+        key: key.to_vec(),
+    };
+    writes
+        .iter()
+        .filter(|write| write.table == table && write.field == field)
+        .fold(initial, |value, write| {
+            let same_key = write
+                .key
+                .iter()
+                .zip(key)
+                .map(|(a, b)| SymExpr::binary(BinaryOp::Eq, a.clone(), b.clone()))
+                .fold(SymExpr::bool(true), |all, eq| {
+                    SymExpr::binary(BinaryOp::And, all, eq)
+                });
+            SymExpr::ite(same_key, write.value.clone(), value)
+        })
+}
+
+/// A field read before either hop runs, with its symbolic key
+pub(super) type InitialField<'a> = (TableId, FieldId, &'a [SymExpr]);
+
+/// Adds the fields `expr` reads before either hop runs to `initial`, those its
+/// keys read first.
+pub(super) fn collect_initial<'a>(expr: &'a SymExpr, initial: &mut Vec<InitialField<'a>>) {
+    match expr {
+        SymExpr::Const(_) | SymExpr::Input { .. } => {}
+        SymExpr::Initial { table, field, key } => {
+            key.iter().for_each(|part| collect_initial(part, initial));
+            if !initial.contains(&(*table, *field, key.as_slice())) {
+                initial.push((*table, *field, key));
+            }
+        }
+        SymExpr::Unary { operand, .. } => collect_initial(operand, initial),
+        SymExpr::Binary { left, right, .. } => {
+            collect_initial(left, initial);
+            collect_initial(right, initial);
+        }
+        SymExpr::Ite {
+            condition,
+            then,
+            otherwise,
+        } => {
+            collect_initial(condition, initial);
+            collect_initial(then, initial);
+            collect_initial(otherwise, initial);
+        }
+    }
+}
+
+/// A few values of a type to try: small ones, then the constants of the hops.
+fn candidates(ty: &TypeName, constants: &[Constant]) -> Vec<Constant> {
+    let (mut values, limit) = match ty {
+        TypeName::Bool => return vec![Constant::Bool(false), Constant::Bool(true)],
+        TypeName::Int => (vec![Constant::Int(0), Constant::Int(1)], 4),
+        TypeName::Float => (
+            vec![Constant::Float(0.0.into()), Constant::Float(1.0.into())],
+            3,
+        ),
+        TypeName::String => (vec![Constant::String(String::new())], 3),
+    };
+    for c in constants {
+        let same_type = matches!(
+            (ty, c),
+            (TypeName::Int, Constant::Int(_))
+                | (TypeName::Float, Constant::Float(_))
+                | (TypeName::String, Constant::String(_))
+        );
+        if same_type && values.len() < limit && !values.contains(c) {
+            values.push(c.clone());
+        }
+    }
+    values
+}
+
+pub(super) fn default_value(ty: &TypeName) -> Constant {
+    match ty {
+        TypeName::Int => Constant::Int(0),
+        TypeName::Float => Constant::Float(0.0.into()),
+        TypeName::Bool => Constant::Bool(false),
+        TypeName::String => Constant::String(String::new()),
+    }
+}
+
+/// What one order of the hops ends with from a concrete start state.
+#[derive(Debug, PartialEq)]
+struct ConcreteEnd {
+    /// How each hop ended, with the value it returned
+    ends: Vec<(std::mem::Discriminant<HopEnd>, Option<Constant>)>,
+    vars: HashMap<(Side, VarId), Constant>,
+    /// The written fields
+    fields: HashMap<(TableId, FieldId, Vec<Constant>), Constant>,
+}
+
+/// A concrete start state.
+struct Env<'a> {
+    cfg: &'a CfgProgram,
+    inputs: HashMap<(Side, VarId), Constant>,
+    initial: HashMap<(TableId, FieldId, Vec<Constant>), Constant>,
+}
+
+impl Env<'_> {
+    /// The value of an expression, `None` where it fails, like on overflow.
+    fn evaluate(&self, expr: &SymExpr) -> Option<Constant> {
+        match expr {
+            SymExpr::Const(c) => Some(c.clone()),
+            SymExpr::Input { side, var } => self.inputs.get(&(*side, *var)).cloned(),
+            SymExpr::Initial { table, field, key } => {
+                let key = key
+                    .iter()
+                    .map(|part| self.evaluate(part))
+                    .collect::<Option<Vec<_>>>()?;
+                Some(
+                    self.initial
+                        .get(&(*table, *field, key))
+                        .cloned()
+                        .unwrap_or_else(|| default_value(&self.cfg.fields[*field].ty)),
+                )
+            }
+            SymExpr::Unary { op, operand } => {
+                ConstantPropagationPass.evaluate_unary_op(op, &self.evaluate(operand)?)
+            }
+            SymExpr::Binary { op, left, right } => ConstantPropagationPass.evaluate_binary_op(
+                op,
+                &self.evaluate(left)?,
+                &self.evaluate(right)?,
+            ),
+            SymExpr::Ite {
+                condition,
+                then,
+                otherwise,
+            } => match self.evaluate(condition)? {
+                Constant::Bool(true) => self.evaluate(then),
+                Constant::Bool(false) => self.evaluate(otherwise),
+                _ => None,
+            },
+        }
+    }
+
+    /// Runs the path of one order that this start state takes.
+    fn run(&self, paths: &[PairPath]) -> Option<ConcreteEnd> {
+        let path = paths.iter().find(|path| {
+            path.condition
+                .iter()
+                .all(|condition| self.evaluate(condition) == Some(Constant::Bool(true)))
+        })?;
+
+        let mut ends = Vec::new();
+        for end in [&path.end_a, &path.end_b] {
+            let value = match end {
+                HopEnd::Return(Some(value)) => Some(self.evaluate(value)?),
+                _ => None,
+            };
+            ends.push((std::mem::discriminant(end), value));
+        }
+        let mut vars = HashMap::new();
+        for (&var, value) in &path.vars {
+            vars.insert(var, self.evaluate(value)?);
+        }
+        let mut fields = HashMap::new();
+        for write in &path.writes {
+            let key = write
+                .key
+                .iter()
+                .map(|part| self.evaluate(part))
+                .collect::<Option<Vec<_>>>()?;
+            fields.insert(
+                (write.table, write.field, key),
+                self.evaluate(&write.value)?,
+            );
+        }
+        Some(ConcreteEnd { ends, vars, fields })
+    }
+
+    /// A variable's value at the end of an order, which is its start value
+    /// where the order leaves it alone.
+    fn var(&self, end: &ConcreteEnd, key: &(Side, VarId)) -> Option<Constant> {
+        end.vars.get(key).or_else(|| self.inputs.get(key)).cloned()
+    }
+
+    /// A field's value at the end of an order, which is its start value where
+    /// the order leaves it alone.
+    fn field(&self, end: &ConcreteEnd, key: &(TableId, FieldId, Vec<Constant>)) -> Constant {
+        end.fields.get(key).cloned().unwrap_or_else(|| {
+            self.initial
+                .get(key)
+                .cloned()
+                .unwrap_or_else(|| default_value(&self.cfg.fields[key.1].ty))
+        })
+    }
+
+    /// Whether two orders end differently in what later hops can see.
+    fn differ(&self, a: &ConcreteEnd, b: &ConcreteEnd, observed: &HashSet<(Side, VarId)>) -> bool {
+        if a.ends != b.ends {
+            return true;
+        }
+        // Both orders abort the same hops, leaving nothing to compare
+        let abort = std::mem::discriminant(&HopEnd::Abort);
+        if a.ends.iter().any(|(end, _)| *end == abort) {
+            return false;
+        }
+        observed
+            .iter()
+            .any(|key| self.var(a, key) != self.var(b, key))
+            || a.fields
+                .keys()
+                .chain(b.fields.keys())
+                .any(|key| self.field(a, key) != self.field(b, key))
+    }
+}
+
+fn format_constant(c: &Constant) -> String {
+    match c {
+        Constant::Int(i) => i.to_string(),
+        Constant::Float(f) => f.to_string(),
+        Constant::Bool(b) => b.to_string(),
+        Constant::String(s) => format!("{:?}", s),
+    }
+}
+
+fn format_record(cfg: &CfgProgram, table: TableId, field: FieldId, key: &[String]) -> String {
+    let table_info = &cfg.tables[table];
+    let key: Vec<String> = table_info
+        .primary_keys
+        .iter()
+        .zip(key)
+        .map(|(pk, value)| format!("{}: {}", cfg.fields[*pk].name, value))
+        .collect();
+    format!(
+        "{}[{}].{}",
+        table_info.name,
+        key.join(", "),
+        cfg.fields[field].name
+    )
+}
@@ -0,0 +1,211 @@
+//! Finding and running the external provers: Boogie and the Z3 it proves with.
+//!
+//! Boogie is taken from `--boogie-path` or found on `PATH`, and Z3 is found on
+//! `PATH`, where Boogie looks for it too. Each is asked its version once, up
+//! front, and refused when older than the oldest this tool's conditions are
+//! written for, so a missing or outdated prover fails the run with what to do
+//! about it rather than every C-edge with the same spawn error. A version that
+//! cannot be read is let through.
+//!
+//! Boogie reads a file per run, so it is started for each; SMT-LIB2 scripts
+//! go to a `SolverPool` of solver processes that are kept running and answer
+//! one script after another, each in a scope of its own.
+//!
+//! ```
+//! use FMitF_rs::verification::toolchain::{parse_version, MIN_BOOGIE, MIN_Z3};
+//!
+//! let boogie = "Boogie program verifier version 3.2.4, Copyright (c) 2003-2014, Microsoft.";
+//! assert_eq!(parse_version(boogie), Some(vec![3, 2, 4]));
+//! assert!(parse_version(boogie).unwrap().as_slice() >= MIN_BOOGIE);
+//! let z3 = parse_version("Z3 version 4.4.1 - 64 bit").unwrap();
+//! assert!(z3.as_slice() < MIN_Z3);
+//! assert_eq!(parse_version("boogie: unknown option"), None);
+//! ```
+
+use crate::verification::execution::run;
+use crate::verification::smt::{SmtSolver, SolverProcess};
+use crate::verification::solver::SolverAnswer;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+/// The oldest Boogie the conditions are written for
+pub const MIN_BOOGIE: &[u32] = &[2, 4];
+
+/// The oldest Z3 the conditions are written for
+pub const MIN_Z3: &[u32] = &[4, 8];
+
+/// Seconds a prover may take to report its version
+const VERSION_TIMEOUT: u32 = 30;
+
+/// The Boogie and Z3 executables verification runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    /// Boogie, as `--boogie-path` gives it or as found on `PATH`
+    pub boogie: PathBuf,
+    /// Boogie's version, as it reports it, if known
+    pub boogie_version: Option<String>,
+    /// Z3, which scripts go to when Boogie checks the C-edges, if found
+    pub z3: Option<PathBuf>,
+}
+
+impl Default for Toolchain {
+    /// The executables by name, unchecked, for `PATH` to resolve as they run.
+    fn default() -> Self {
+        Self {
+            boogie: PathBuf::from("boogie"),
+            boogie_version: None,
+            z3: Some(PathBuf::from("z3")),
+        }
+    }
+}
+
+impl Toolchain {
+    /// Finds Boogie at `boogie_path`, or else on `PATH`, and Z3 on `PATH`,
+    /// and checks their versions. Fails when Boogie is missing or either is
+    /// too old; a missing Z3 is left for Boogie to find on its own.
+    pub fn discover(boogie_path: Option<&Path>) -> Result<Self, String> {
+        let boogie = match boogie_path {
+            Some(path) if path.is_file() => path.to_path_buf(),
+            Some(path) => {
+                return Err(format!(
+                    "--boogie-path {}: no such executable",
+                    path.display()
+                ))
+            }
+            None => find_on_path("boogie").ok_or_else(|| {
+                "Boogie was not found on PATH. Install it with `dotnet tool install --global \
+                 Boogie`, give its path with --boogie-path, or check C-edges with an SMT solver \
+                 instead, e.g. --solver z3"
+                    .to_string()
+            })?,
+        };
+        let boogie_version = checked_version("Boogie", &boogie, "/version", MIN_BOOGIE)?;
+        Ok(Self {
+            boogie,
+            boogie_version,
+            z3: find_z3()?,
+        })
+    }
+}
+
+/// Z3 on `PATH`, if it is there, checking its version.
+pub fn find_z3() -> Result<Option<PathBuf>, String> {
+    let Some(z3) = find_on_path("z3") else {
+        return Ok(None);
+    };
+    checked_version("Z3", &z3, "--version", MIN_Z3)?;
+    Ok(Some(z3))
+}
+
+/// The first executable named `name` in a directory of `PATH`.
+pub fn find_on_path(name: &str) -> Option<PathBuf> {
+    let file = format!("{}{}", name, env::consts::EXE_SUFFIX);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&file))
+        .find(|path| is_executable(path))
+}
+
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// The numbers of the first version in a prover's banner, such as `[4, 8, 12]`
+/// from `Z3 version 4.8.12 - 64 bit`.
+pub fn parse_version(text: &str) -> Option<Vec<u32>> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .map(|word| word.trim_start_matches('v'))
+        .map(|word| {
+            let end = word
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(word.len());
+            word[..end].trim_end_matches('.')
+        })
+        .filter(|word| word.contains('.'))
+        .find_map(|word| word.split('.').map(|part| part.parse().ok()).collect())
+}
+
+/// The version a prover reports with `flag`, failing if it does not run or
+/// is older than `min`.
+fn checked_version(
+    name: &str,
+    path: &Path,
+    flag: &str,
+    min: &[u32],
+) -> Result<Option<String>, String> {
+    let output = run(Command::new(path).arg(flag), Some(VERSION_TIMEOUT))
+        .map_err(|e| format!("Failed to run {} at {}: {}", name, path.display(), e))?
+        .ok_or_else(|| {
+            format!(
+                "{} at {} did not report its version within {} seconds",
+                name,
+                path.display(),
+                VERSION_TIMEOUT
+            )
+        })?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let Some(banner) = text.lines().map(str::trim).find(|line| !line.is_empty()) else {
+        return Ok(None);
+    };
+    if let Some(version) = parse_version(banner).filter(|version| version.as_slice() < min) {
+        let join = |numbers: &[u32]| {
+            let parts: Vec<String> = numbers.iter().map(u32::to_string).collect();
+            parts.join(".")
+        };
+        return Err(format!(
+            "{} {} at {} is older than {}, the oldest supported",
+            name,
+            join(&version),
+            path.display(),
+            join(min)
+        ));
+    }
+    Ok(Some(banner.to_string()))
+}
+
+/// Solver processes kept running between scripts, so a script does not wait
+/// for one to start. Clones share the processes, so the forks of a backend
+/// do; a process that times out or fails is stopped rather than kept.
+#[derive(Debug, Clone)]
+pub struct SolverPool {
+    pub solver: SmtSolver,
+    idle: Arc<Mutex<Vec<SolverProcess>>>,
+}
+
+impl SolverPool {
+    /// A pool of processes of `solver`, started as scripts need them.
+    pub fn new(solver: SmtSolver) -> Self {
+        Self {
+            solver,
+            idle: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Answers a script with an idle process, or a new one if none is.
+    pub fn solve(&self, script: &str) -> SolverAnswer {
+        let idle = self.idle.lock().ok().and_then(|mut idle| idle.pop());
+        let mut process = match idle.map_or_else(|| SolverProcess::spawn(&self.solver), Ok) {
+            Ok(process) => process,
+            Err(e) => return SolverAnswer::Unknown(e),
+        };
+        match process.answer(script) {
+            Ok(answer) => {
+                if let Ok(mut idle) = self.idle.lock() {
+                    idle.push(process);
+                }
+                answer
+            }
+            Err(e) => SolverAnswer::Unknown(e),
+        }
+    }
+}
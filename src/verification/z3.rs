@@ -0,0 +1,296 @@
+//! Solving SMT-LIB2 scripts in-process with the Z3 library.
+//!
+//! Built with the `z3` feature, which links `libz3` through its C API. One
+//! solver checks the scripts of every C-edge, each in its own scope, so no
+//! process is started per edge, what Z3 learns about the theories carries over
+//! from one edge to the next, and the model comes back as values rather than
+//! text to parse.
+
+use crate::cfg::CfgProgram;
+use crate::verification::boogie_file_manager::BoogieFile;
+use crate::verification::commutativity_check::VerificationUnit;
+use crate::verification::invariants::Invariant;
+use crate::verification::smt::batch_script;
+use crate::verification::solver::{
+    smt_condition, Encoding, SolverAnswer, SolverBackend, SolverLimits,
+};
+use crate::verification::symbolic::HopPair;
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString};
+
+type Z3Config = *mut c_void;
+type Z3Context = *mut c_void;
+type Z3Solver = *mut c_void;
+type Z3Model = *mut c_void;
+type Z3Params = *mut c_void;
+type Z3Ast = *mut c_void;
+type Z3FuncDecl = *mut c_void;
+type Z3Symbol = *mut c_void;
+
+/// `Z3_L_TRUE` and `Z3_L_FALSE` of `Z3_lbool`; anything else is unknown
+const Z3_L_TRUE: c_int = 1;
+const Z3_L_FALSE: c_int = -1;
+
+#[link(name = "z3")]
+extern "C" {
+    fn Z3_mk_config() -> Z3Config;
+    fn Z3_del_config(config: Z3Config);
+    fn Z3_set_param_value(config: Z3Config, id: *const c_char, value: *const c_char);
+    fn Z3_get_full_version() -> *const c_char;
+    fn Z3_mk_context(config: Z3Config) -> Z3Context;
+    fn Z3_del_context(context: Z3Context);
+    fn Z3_set_error_handler(context: Z3Context, handler: Option<extern "C" fn(Z3Context, c_int)>);
+    fn Z3_get_error_code(context: Z3Context) -> c_int;
+    fn Z3_get_error_msg(context: Z3Context, code: c_int) -> *const c_char;
+    fn Z3_mk_string_symbol(context: Z3Context, name: *const c_char) -> Z3Symbol;
+    fn Z3_get_symbol_string(context: Z3Context, symbol: Z3Symbol) -> *const c_char;
+    fn Z3_mk_params(context: Z3Context) -> Z3Params;
+    fn Z3_params_inc_ref(context: Z3Context, params: Z3Params);
+    fn Z3_params_dec_ref(context: Z3Context, params: Z3Params);
+    fn Z3_global_param_set(id: *const c_char, value: *const c_char);
+    fn Z3_params_set_uint(context: Z3Context, params: Z3Params, key: Z3Symbol, value: c_uint);
+    fn Z3_mk_solver(context: Z3Context) -> Z3Solver;
+    fn Z3_solver_inc_ref(context: Z3Context, solver: Z3Solver);
+    fn Z3_solver_dec_ref(context: Z3Context, solver: Z3Solver);
+    fn Z3_solver_set_params(context: Z3Context, solver: Z3Solver, params: Z3Params);
+    fn Z3_solver_push(context: Z3Context, solver: Z3Solver);
+    fn Z3_solver_pop(context: Z3Context, solver: Z3Solver, scopes: c_uint);
+    fn Z3_solver_from_string(context: Z3Context, solver: Z3Solver, script: *const c_char);
+    fn Z3_solver_check(context: Z3Context, solver: Z3Solver) -> c_int;
+    fn Z3_solver_get_reason_unknown(context: Z3Context, solver: Z3Solver) -> *const c_char;
+    fn Z3_solver_get_proof(context: Z3Context, solver: Z3Solver) -> Z3Ast;
+    fn Z3_solver_get_model(context: Z3Context, solver: Z3Solver) -> Z3Model;
+    fn Z3_model_inc_ref(context: Z3Context, model: Z3Model);
+    fn Z3_model_dec_ref(context: Z3Context, model: Z3Model);
+    fn Z3_model_get_num_consts(context: Z3Context, model: Z3Model) -> c_uint;
+    fn Z3_model_get_const_decl(context: Z3Context, model: Z3Model, index: c_uint) -> Z3FuncDecl;
+    fn Z3_model_get_const_interp(context: Z3Context, model: Z3Model, decl: Z3FuncDecl) -> Z3Ast;
+    fn Z3_get_decl_name(context: Z3Context, decl: Z3FuncDecl) -> Z3Symbol;
+    fn Z3_ast_to_string(context: Z3Context, ast: Z3Ast) -> *const c_char;
+}
+
+/// A Z3 solver in this process, kept across scripts.
+pub struct InProcessZ3 {
+    context: Z3Context,
+    solver: Z3Solver,
+    limits: SolverLimits,
+}
+
+// SAFETY: a context may move to another thread; it is only ever used by the
+// thread owning this value
+unsafe impl Send for InProcessZ3 {}
+
+impl InProcessZ3 {
+    /// A solver held to the limits on each script. The memory limit is a
+    /// global parameter of the library, so it holds for every solver.
+    pub fn new(limits: SolverLimits) -> Self {
+        // SAFETY: see `open`
+        let (context, solver) = unsafe { open(limits, false) };
+        Self {
+            context,
+            solver,
+            limits,
+        }
+    }
+
+    /// Checks a script, as `HopPair::smt_script` writes it, in a scope of its
+    /// own.
+    pub fn solve(&mut self, script: &str) -> SolverAnswer {
+        let Ok(script) = CString::new(script) else {
+            return SolverAnswer::Unknown("The script holds a NUL character".to_string());
+        };
+        // SAFETY: the solver belongs to the context, and the strings Z3
+        // returns are copied before the next call can invalidate them
+        unsafe {
+            Z3_solver_push(self.context, self.solver);
+            Z3_solver_from_string(self.context, self.solver, script.as_ptr());
+            let answer = match self.error() {
+                Some(error) => SolverAnswer::Unknown(format!("Z3 rejected the script: {}", error)),
+                None => match Z3_solver_check(self.context, self.solver) {
+                    Z3_L_FALSE => SolverAnswer::Unsat,
+                    Z3_L_TRUE => SolverAnswer::Sat(self.model()),
+                    _ => SolverAnswer::Unknown(format!(
+                        "Z3 gave up: {}",
+                        string(Z3_solver_get_reason_unknown(self.context, self.solver))
+                    )),
+                },
+            };
+            Z3_solver_pop(self.context, self.solver, 1);
+            answer
+        }
+    }
+
+    /// Checks a script in a context of its own that keeps proofs, and returns
+    /// the proof that it is unsatisfiable, if it is.
+    pub fn proof(&self, script: &str) -> Option<String> {
+        let script = CString::new(script).ok()?;
+        // SAFETY: as in `solve`, and the context and solver are released
+        // before returning
+        unsafe {
+            let (context, solver) = open(self.limits, true);
+            Z3_solver_from_string(context, solver, script.as_ptr());
+            let proof = if Z3_get_error_code(context) == 0
+                && Z3_solver_check(context, solver) == Z3_L_FALSE
+            {
+                let proof = Z3_solver_get_proof(context, solver);
+                (!proof.is_null()).then(|| string(Z3_ast_to_string(context, proof)))
+            } else {
+                None
+            };
+            Z3_solver_dec_ref(context, solver);
+            Z3_del_context(context);
+            proof
+        }
+    }
+
+    /// The message of the last error, if the last call failed.
+    unsafe fn error(&self) -> Option<String> {
+        match Z3_get_error_code(self.context) {
+            0 => None,
+            code => Some(string(Z3_get_error_msg(self.context, code))),
+        }
+    }
+
+    /// The values the solver's model gives the constants, by symbol.
+    unsafe fn model(&self) -> Vec<(String, String)> {
+        let model = Z3_solver_get_model(self.context, self.solver);
+        if model.is_null() {
+            return Vec::new();
+        }
+        Z3_model_inc_ref(self.context, model);
+        let values = (0..Z3_model_get_num_consts(self.context, model))
+            .filter_map(|index| {
+                let decl = Z3_model_get_const_decl(self.context, model, index);
+                let value = Z3_model_get_const_interp(self.context, model, decl);
+                if value.is_null() {
+                    return None;
+                }
+                let name = string(Z3_get_symbol_string(
+                    self.context,
+                    Z3_get_decl_name(self.context, decl),
+                ));
+                Some((name, string(Z3_ast_to_string(self.context, value))))
+            })
+            .collect();
+        Z3_model_dec_ref(self.context, model);
+        values
+    }
+}
+
+impl SolverBackend for InProcessZ3 {
+    fn name(&self) -> &'static str {
+        "Z3"
+    }
+
+    fn version(&self) -> Option<String> {
+        // SAFETY: the version is a static string of the library
+        Some(unsafe { string(Z3_get_full_version()) })
+    }
+
+    fn encode(
+        &self,
+        unit: &VerificationUnit,
+        pair: &HopPair,
+        cfg: &CfgProgram,
+        invariants: &[Invariant],
+        encoding: Encoding,
+    ) -> Result<BoogieFile, String> {
+        smt_condition(unit, pair, cfg, invariants, encoding)
+    }
+
+    fn submit(&mut self, condition: &BoogieFile) -> SolverAnswer {
+        self.solve(&condition.code)
+    }
+
+    /// The context checks each condition in a scope of its own anyway.
+    fn submit_batch(
+        &mut self,
+        name: &str,
+        conditions: &[BoogieFile],
+    ) -> (BoogieFile, Vec<SolverAnswer>) {
+        let scripts: Vec<&str> = conditions.iter().map(|c| c.code.as_str()).collect();
+        let answers = scripts.iter().map(|script| self.solve(script)).collect();
+        let batch = BoogieFile {
+            filename: format!("{}.smt2", name),
+            code: batch_script(&scripts),
+        };
+        (batch, answers)
+    }
+
+    fn solve_script(&mut self, script: &str) -> SolverAnswer {
+        self.solve(script)
+    }
+
+    fn prove(&mut self, condition: &BoogieFile) -> Option<String> {
+        self.proof(&condition.code)
+    }
+
+    /// A solver with a context of its own, as contexts are not shared
+    /// between threads.
+    fn fork(&self) -> Box<dyn SolverBackend> {
+        Box::new(Self::new(self.limits))
+    }
+}
+
+impl Drop for InProcessZ3 {
+    fn drop(&mut self) {
+        // SAFETY: the solver is released before the context that owns it
+        unsafe {
+            Z3_solver_dec_ref(self.context, self.solver);
+            Z3_del_context(self.context);
+        }
+    }
+}
+
+/// A context and a solver of it held to the limits, keeping proofs if asked.
+///
+/// # Safety
+///
+/// The context outlives the solver and params made from it, and errors are
+/// reported through `Z3_get_error_code` instead of aborting; the caller
+/// releases both.
+unsafe fn open(limits: SolverLimits, proofs: bool) -> (Z3Context, Z3Solver) {
+    if let Some(megabytes) = limits.memory {
+        let key = CString::new("memory_max_size").expect("no NUL in a literal");
+        let value = CString::new(megabytes.to_string()).expect("no NUL in a number");
+        Z3_global_param_set(key.as_ptr(), value.as_ptr());
+    }
+    let config = Z3_mk_config();
+    if proofs {
+        let key = CString::new("proof").expect("no NUL in a literal");
+        let value = CString::new("true").expect("no NUL in a literal");
+        Z3_set_param_value(config, key.as_ptr(), value.as_ptr());
+    }
+    let context = Z3_mk_context(config);
+    Z3_del_config(config);
+    Z3_set_error_handler(context, None);
+    let solver = Z3_mk_solver(context);
+    Z3_solver_inc_ref(context, solver);
+    let params = Z3_mk_params(context);
+    Z3_params_inc_ref(context, params);
+    let settings = [
+        ("timeout", limits.timeout.map(|s| s.saturating_mul(1000))),
+        ("random_seed", limits.seed),
+    ];
+    for (name, value) in settings {
+        if let Some(value) = value {
+            let key = CString::new(name).expect("no NUL in a literal");
+            Z3_params_set_uint(
+                context,
+                params,
+                Z3_mk_string_symbol(context, key.as_ptr()),
+                value,
+            );
+        }
+    }
+    Z3_solver_set_params(context, solver, params);
+    Z3_params_dec_ref(context, params);
+    (context, solver)
+}
+
+/// Copies a string Z3 owns.
+unsafe fn string(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}